@@ -7,7 +7,9 @@ starting with a line like this:
     # `foo` Command
 
 The command name is taken from this header line, and the remainder of the doc
-comment is included as the command description.
+comment is included as the command description.  The usage line and argument
+list are generated from the command's argument spec, which
+`c2rust-refactor --dump-command-specs` prints.
 '''
 import json
 import os
@@ -54,6 +56,29 @@ def load_analysis():
             return j
 
 
+def load_specs():
+    '''Get the argument specs of all commands.'''
+    with local.cwd(os.path.join(os.path.dirname(__file__), '../..')):
+        eprint('loading command argument specs...')
+        out = cargo['run', '--bin', 'c2rust-refactor', '--',
+                '--dump-command-specs']()
+        return json.loads(out)
+
+
+def format_spec(spec):
+    '''Format the usage line and argument list of a command.'''
+    out = 'Usage: `%s`\n\n' % spec['usage']
+    if spec['args']:
+        out += 'Arguments:\n\n'
+        for arg in spec['args']:
+            out += ' * `%s`: %s' % (arg['label'], arg['help'])
+            if arg['default'] is not None:
+                out += ' (default: `%s`)' % arg['default']
+            out += '\n'
+        out += '\n'
+    return out
+
+
 # Expect to see a header line of the form:  # `cmd_name` Command
 HEADER_RE = re.compile(r'# `(.*)` Command')
 
@@ -62,6 +87,7 @@ def generate_commands():
     out = ''
 
     ana = load_analysis()
+    specs = load_specs()
 
     # Find all documented commands
 
@@ -79,6 +105,8 @@ def generate_commands():
 
         name = m.group(1)
         content = rest
+        if name in specs:
+            content = format_spec(specs[name]) + content.lstrip('\n')
         cmds.append((name, content))
 
     eprint('found %d commands' % len(cmds))
@@ -27,8 +27,16 @@ extern crate syntax_pos;
 pub use c2rust_refactor::*;
 
 
-fn mk<T: transform::Transform + 'static>(t: T) -> Box<command::Command> {
-    Box::new(transform::TransformCommand(t))
+/// Builds the command for a transform, for the builders that plugins pass to
+/// `Registry::register` along with each command's spec:
+///
+/// ```ignore
+/// let spec = command_args::CommandSpec::new("Do the thing.")
+///     .flag("--all", "do it everywhere");
+/// reg.register("do_thing", spec, |args| mk(DoThing { all: args.flag("--all") }));
+/// ```
+fn mk<T: transform::Transform + 'static>(t: T) -> Result<Box<dyn command::Command>, String> {
+    Ok(Box::new(transform::TransformCommand(t)))
 }
 
 // Adjust these lines to control what part of `c2rust-refactor` gets built.
@@ -6,6 +6,7 @@
 #[macro_use] extern crate c2rust_refactor;
 
 use c2rust_refactor::command::{Registry, FuncCommand, RefactorState};
+use c2rust_refactor::command_args::CommandSpec;
 
 fn mark_fields(state: &mut RefactorState) {
     state.run("select", &["dummy",
@@ -16,31 +17,31 @@ fn mark_fields(state: &mut RefactorState) {
             reset;
         "]);
 
-    state.run("print_marks", &[""]);
+    state.run("print_marks", &[] as &[&str]);
     let fields = ["collisions", "resizes", "lookups", "inserts", "deletes"];
     for (i, &field) in fields.iter().enumerate() {
         state.run("mark_field_uses", &[field, &format!("target{}", i)]);
         state.run("rename_marks", &[&format!("target{}", i) as &str, "target"]);
         eprintln!(" -- after {}", field);
-        state.run("print_marks", &[""]);
+        state.run("print_marks", &[] as &[&str]);
     }
 }
 
 #[no_mangle]
 pub fn register_commands(reg: &mut Registry) {
-    reg.register("lh_table_counter_cell_1", |args| {
-        let args = args.clone();
-        Box::new(FuncCommand(move |state: &mut RefactorState| {
+    let spec = CommandSpec::new("Make the `lh_table` counters `Cell`s, reading through `as_ptr`.");
+    reg.register("lh_table_counter_cell_1", spec, |_args| {
+        Ok(Box::new(FuncCommand(move |state: &mut RefactorState| {
 
             mark_fields(state);
             state.run("rewrite_expr", &["marked!(__e)", "*__e.as_ptr()"]);
 
-        }))
+        })))
     });
 
-    reg.register("lh_table_counter_cell_2", |args| {
-        let args = args.clone();
-        Box::new(FuncCommand(move |state: &mut RefactorState| {
+    let spec = CommandSpec::new("Make the `lh_table` counters `Cell`s, using `get` and `set`.");
+    reg.register("lh_table_counter_cell_2", spec, |_args| {
+        Ok(Box::new(FuncCommand(move |state: &mut RefactorState| {
 
             mark_fields(state);
             state.run("rewrite_expr", &["*marked!(__e).as_ptr() = __f", "__e.set(__f)"]);
@@ -48,6 +49,6 @@ pub fn register_commands(reg: &mut Registry) {
             mark_fields(state);
             state.run("rewrite_expr", &["*marked!(__e).as_ptr()", "__e.get()"]);
 
-        }))
+        })))
     });
 }
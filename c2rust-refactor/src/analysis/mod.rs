@@ -3,6 +3,7 @@
 use std::collections::HashSet;
 
 use crate::command::{DriverCommand, Registry};
+use crate::command_args::{ArgType, CommandSpec};
 use crate::driver::Phase;
 use arena::SyncDroplessArena;
 use c2rust_ast_builder::IntoSymbol;
@@ -15,15 +16,14 @@ pub mod type_eq;
 ///
 /// Test command - not intended for general use.
 ///
-/// Usage: `test_analysis_type_eq`
-///
 /// Runs the `type_eq` analysis and logs the result (at level `info`).
 fn register_test_analysis_type_eq(reg: &mut Registry) {
-    reg.register("test_analysis_type_eq", |_args| {
-        Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+    let spec = CommandSpec::new("Run the `type_eq` analysis and log the result.");
+    reg.register("test_analysis_type_eq", spec, |_args| {
+        Ok(Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
             let result = type_eq::analyze(&cx, &st.krate());
             info!("{:?}", result);
-        }))
+        })))
     });
 }
 
@@ -31,23 +31,20 @@ fn register_test_analysis_type_eq(reg: &mut Registry) {
 ///
 /// Test command - not intended for general use.
 ///
-/// Usage: `test_analysis_ownership`
-///
 /// Runs the `ownership` analysis and dumps the results to stderr.
 fn register_test_analysis_ownership(reg: &mut Registry) {
-    reg.register("test_analysis_ownership", |_args| {
-        Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+    let spec = CommandSpec::new("Run the ownership analysis and dump the results.");
+    reg.register("test_analysis_ownership", spec, |_args| {
+        Ok(Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
             let arena = SyncDroplessArena::default();
             let results = ownership::analyze(&st, &cx, &arena);
             ownership::dump_results(&cx, &results);
-        }))
+        })))
     });
 }
 
 /// # `mark_related_types` Command
 ///
-/// Usage: `mark_related_types [MARK]`
-///
 /// Marks: `MARK`/`target`
 ///
 /// For each type annotation bearing `MARK` (default: `target`),
@@ -67,9 +64,11 @@ fn register_test_analysis_ownership(reg: &mut Registry) {
 /// would produce a type error.  But the `i32` annotation on `y` is
 /// unrelated, and can be changed independently of the other two.
 fn register_mark_related_types(reg: &mut Registry) {
-    reg.register("mark_related_types", |args| {
-        let label = args.get(0).map_or("target", |x| x).into_symbol();
-        Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+    let spec = CommandSpec::new("Mark the type annotations that must match one marked MARK.")
+        .optional_arg("MARK", ArgType::Str, Some("target"), "the mark of the type annotations");
+    reg.register("mark_related_types", spec, |args| {
+        let label = args.str("MARK").into_symbol();
+        Ok(Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
             let ty_class = type_eq::analyze(&cx, &st.krate());
 
             let mut related_classes = HashSet::new();
@@ -87,7 +86,7 @@ fn register_mark_related_types(reg: &mut Registry) {
                     st.add_mark(cx.hir_map().hir_to_node_id(id), label);
                 }
             }
-        }))
+        })))
     });
 }
 
@@ -1,4 +1,5 @@
 use std::cell::Cell;
+use std::collections::HashSet;
 use syntax::ast::{Mac, NodeId, DUMMY_NODE_ID};
 use syntax::mut_visit::{self, MutVisitor};
 
@@ -44,6 +45,34 @@ pub fn number_nodes_with<T: MutVisit>(x: &mut T, counter: &NodeIdCounter) {
     x.visit(&mut NumberNodes { counter })
 }
 
+struct NumberNewNodes<'a> {
+    counter: &'a NodeIdCounter,
+    seen: HashSet<NodeId>,
+}
+
+impl<'a> MutVisitor for NumberNewNodes<'a> {
+    fn visit_id(&mut self, i: &mut NodeId) {
+        if *i == DUMMY_NODE_ID || !self.seen.insert(*i) {
+            *i = self.counter.next();
+            self.seen.insert(*i);
+        }
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+/// Assign new `NodeId`s to the nodes in `x` that don't have a unique one yet, i.e., nodes with
+/// `DUMMY_NODE_ID` and all but the first copy of nodes that appear more than once.  The other
+/// nodes keep their `NodeId`s, so they can still be looked up in the typeck tables.
+pub fn number_new_nodes_with<T: MutVisit>(x: &mut T, counter: &NodeIdCounter) {
+    x.visit(&mut NumberNewNodes {
+        counter,
+        seen: HashSet::new(),
+    })
+}
+
 struct ResetNodeIds;
 impl MutVisitor for ResetNodeIds {
     fn visit_id(&mut self, i: &mut NodeId) {
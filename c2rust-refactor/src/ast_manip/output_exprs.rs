@@ -104,21 +104,14 @@ impl<F: FnMut(&mut P<Expr>)> MutVisitor for OutputFolder<F> {
                 self.visit_block(b);
             }
 
-            ExprKind::Try(_) => {
-                // Explicitly unimplemented.  Depending on whether `try` winds up
-                // auto-wrapping its "return" value in `Ok`, we may need to treat the trailing
-                // expr of a `catch` specially.
-                panic!("output_exprs: ExprKind::Try is not supported")
-            }
-
             ExprKind::Ret(ret) => {
-                visit_opt(ret, |ret| self.with_trailing(false, |f| f.visit_expr(ret)));
+                visit_opt(ret, |ret| self.with_trailing(true, |f| f.visit_expr(ret)));
             }
 
             //ExprKind::Break(Some(label), Some(expr)) => { TODO },
 
-            // Not sure what to do with ExprKind::Try.  It can return (on error), but doesn't
-            // have an actual output expression.
+            // `ExprKind::Try` can return on error, but has no output expression of its own, so
+            // it's treated like any other expression.
             _ => {
                 self.with_trailing(false, |f| mut_visit::noop_visit_expr(e, f));
                 if self.trailing {
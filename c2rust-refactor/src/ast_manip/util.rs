@@ -124,6 +124,28 @@ impl PatternSymbol for TraitItem {
     }
 }
 
+impl PatternSymbol for Attribute {
+    fn pattern_symbol(&self) -> Option<Symbol> {
+        match self.kind {
+            AttrKind::Normal(ref item) => match item.args {
+                MacArgs::Empty => item.path.pattern_symbol(),
+                _ => None,
+            },
+            AttrKind::DocComment(_) => None,
+        }
+    }
+}
+
+impl PatternSymbol for FnHeader {
+    /// `$q:FnQual` bindings are parsed as an ABI string, as in `extern "$q" fn`.
+    fn pattern_symbol(&self) -> Option<Symbol> {
+        match self.ext {
+            Extern::Explicit(ref abi) if abi.symbol.as_str().starts_with('$') => Some(abi.symbol),
+            _ => None,
+        }
+    }
+}
+
 pub fn is_c2rust_attr(attr: &Attribute, name: &str) -> bool {
     if let AttrKind::Normal(item) = &attr.kind {
         item.path.segments.len() == 2
@@ -108,6 +108,22 @@ gen_visit_node_impl! {
     walk = visit::walk_path(self, p);
 }
 
+gen_visit_node_impl! {
+    node = ImplItem;
+    visitor = ImplItemNodeVisitor;
+    visitor_post = ImplItemNodeVisitorPost;
+    fn visit_impl_item(&mut self, i: &'ast ImplItem);
+    walk = visit::walk_impl_item(self, i);
+}
+
+gen_visit_node_impl! {
+    node = Pat;
+    visitor = PatNodeVisitor;
+    visitor_post = PatNodeVisitorPost;
+    fn visit_pat(&mut self, p: &'ast Pat);
+    walk = visit::walk_pat(self, p);
+}
+
 gen_visit_node_impl! {
     node = Block;
     visitor = BlockNodeVisitor;
@@ -17,7 +17,7 @@ use syntax::visit::Visitor;
 use syntax_pos::Symbol;
 
 use crate::ast_manip::Visit;
-use crate::ast_manip::{GetNodeId, GetSpan};
+use crate::ast_manip::{GetNodeId, GetSpan, ListNodeIds};
 use crate::ast_manip::util::path_eq;
 
 use super::root_callsite_span;
@@ -49,6 +49,33 @@ impl<'a> MacNodeRef<'a> {
             MacNodeRef::Stmt(x) => x.id,
         }
     }
+
+    pub fn span(&self) -> Span {
+        match *self {
+            MacNodeRef::Expr(x) => x.span,
+            MacNodeRef::Pat(x) => x.span,
+            MacNodeRef::Ty(x) => x.span,
+            MacNodeRef::Item(x) => x.span,
+            MacNodeRef::ImplItem(x) => x.span,
+            MacNodeRef::TraitItem(x) => x.span,
+            MacNodeRef::ForeignItem(x) => x.span,
+            MacNodeRef::Stmt(x) => x.span,
+        }
+    }
+
+    /// List the IDs of this node and all of its descendants.
+    pub fn list_node_ids(&self) -> Vec<NodeId> {
+        match *self {
+            MacNodeRef::Expr(x) => x.list_node_ids(),
+            MacNodeRef::Pat(x) => x.list_node_ids(),
+            MacNodeRef::Ty(x) => x.list_node_ids(),
+            MacNodeRef::Item(x) => x.list_node_ids(),
+            MacNodeRef::ImplItem(x) => x.list_node_ids(),
+            MacNodeRef::TraitItem(x) => x.list_node_ids(),
+            MacNodeRef::ForeignItem(x) => x.list_node_ids(),
+            MacNodeRef::Stmt(x) => x.list_node_ids(),
+        }
+    }
 }
 
 macro_rules! mac_node_ref_getters {
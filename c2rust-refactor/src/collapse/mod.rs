@@ -14,10 +14,15 @@
 //! Though most of the code and comments talk about "macros", we really mean everything that gets
 //! processed during macro expansion, which includes regular macros, proc macros (`format!`, etc.),
 //! certain attributes (`#[derive]`, `#[cfg]`), and `std`/prelude injection.
+use std::cmp::Reverse;
 use std::collections::HashMap;
+use std::rc::Rc;
 use syntax::ast::*;
 use syntax::attr;
-use syntax::source_map::Span;
+use syntax::source_map::{SourceMap, Span};
+use syntax::token::TokenKind;
+use syntax::tokenstream::TokenTree;
+use syntax_pos::hygiene::ExpnKind;
 use syntax_pos::sym;
 
 mod cfg_attr;
@@ -29,7 +34,7 @@ mod nt_match;
 
 pub use self::cfg_attr::{collect_cfg_attrs, restore_cfg_attrs};
 pub use self::deleted::{collect_deleted_nodes, restore_deleted_nodes};
-pub use self::mac_table::{collect_macro_invocations, MacInfo, MacTable};
+pub use self::mac_table::{collect_macro_invocations, InvocKind, MacInfo, MacNodeRef, MacTable};
 pub use self::macros::collapse_macros;
 pub use self::node_map::match_nonterminal_ids;
 
@@ -92,6 +97,71 @@ impl<'ast> CollapseInfo<'ast> {
         node_map.transfer_marks(&mut cs.marks_mut());
         node_map.commit();
     }
+
+    /// Map each node in the expansion of a macro invocation to the invocation it came from.
+    /// Attribute macros and derives aren't included.  For nested invocations, nodes map to the
+    /// innermost one.
+    pub fn macro_expansions(&self, source_map: &SourceMap) -> HashMap<NodeId, Rc<MacroExpansion>> {
+        let mut invocs = self
+            .mac_table
+            .invocations()
+            .filter_map(|info| match info.invoc {
+                InvocKind::Mac(mac) => Some((mac, info.expanded, info.expanded.list_node_ids())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // Visit outer invocations first, so inner ones overwrite them.
+        invocs.sort_by_key(|&(_, _, ref ids)| Reverse(ids.len()));
+
+        let mut result = HashMap::new();
+        for (mac, expanded, ids) in invocs {
+            let exp = Rc::new(MacroExpansion::new(mac, expanded.span(), source_map));
+            for id in ids {
+                result.insert(id, exp.clone());
+            }
+        }
+        result
+    }
+}
+
+/// A macro invocation, as seen from the nodes of its expansion.
+#[derive(Clone, Debug)]
+pub struct MacroExpansion {
+    /// Whether the macro is defined in the crate being refactored, as opposed to a dependency or
+    /// the compiler.
+    pub local: bool,
+    /// The spans of the comma- or semicolon-separated arguments at the call site.
+    pub arg_spans: Vec<Span>,
+}
+
+impl MacroExpansion {
+    fn new(mac: &Mac, expanded_span: Span, source_map: &SourceMap) -> MacroExpansion {
+        let expn_data = expanded_span.ctxt().outer_expn_data();
+        let local = match expn_data.kind {
+            ExpnKind::Macro(..) => {
+                !expn_data.def_site.is_dummy()
+                    && !source_map.lookup_char_pos(expn_data.def_site.lo()).file.is_imported()
+            }
+            _ => false,
+        };
+
+        let mut arg_spans = Vec::new();
+        let mut cur: Option<Span> = None;
+        for tt in mac.args.inner_tokens().trees() {
+            match tt {
+                TokenTree::Token(ref t) if t.kind == TokenKind::Comma || t.kind == TokenKind::Semi => {
+                    arg_spans.extend(cur.take());
+                }
+                _ => {
+                    let sp = tt.span();
+                    cur = Some(cur.map_or(sp, |cur| cur.to(sp)));
+                }
+            }
+        }
+        arg_spans.extend(cur);
+
+        MacroExpansion { local, arg_spans }
+    }
 }
 
 /// Returns a list of injected crate names, plus a flag indicating whether a prelude import was
@@ -9,19 +9,27 @@ use rustc_interface::interface;
 use rustc_interface::util;
 use std::cell::{self, Cell, RefCell};
 use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::iter;
 use std::io::Write;
 use std::mem;
 use std::ops::Deref;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::rc::Rc;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use smallvec::SmallVec;
 use syntax::ast::{Crate, NodeId, CRATE_NODE_ID};
-use syntax::ast::{Expr, Item, Pat, Stmt, Ty};
+use syntax::ast::{Attribute, Expr, ForeignItem, ImplItem, Item, ItemKind, Mac, Mod, Pat, Stmt};
+use syntax::ast::{TraitItem, Ty};
+use syntax::mut_visit::{self, MutVisitor};
 use syntax::ptr::P;
-use syntax::source_map::SourceMap;
+use syntax::source_map::{SourceMap, Span};
 use syntax::symbol::Symbol;
-use syntax::visit::Visitor;
+use syntax::print::pprust;
+use syntax::visit::{self, Visitor};
+use syntax_pos::FileName;
 
 use crate::ast_manip::map_ast_into;
 use crate::ast_manip::number_nodes::{
@@ -29,15 +37,19 @@ use crate::ast_manip::number_nodes::{
 };
 use crate::ast_manip::{remove_paren, ListNodeIds, MutVisit, Visit};
 use crate::ast_manip::{collect_comments, gather_comments, Comment, CommentMap};
-use crate::collapse::CollapseInfo;
+use crate::collapse::{CollapseInfo, MacroExpansion};
+use crate::command_args::{ArgType, Args, CommandSpec};
 use crate::driver::{self, Phase};
 use crate::file_io::FileIO;
+use crate::matcher::{MatchCtxt, MatchExplanation, MatchScope, MatchTrace};
 use crate::node_map::NodeMap;
+use crate::report::Report;
 use crate::rewrite;
 use crate::rewrite::files;
 use crate::span_fix;
 use crate::RefactorCtxt;
 use c2rust_ast_builder::IntoSymbol;
+use json::JsonValue;
 
 /// Extra nodes that were parsed from strings while running a transformation pass.  During
 /// rewriting, we'd like to reuse the original strings for these, rather than pretty-printing them.
@@ -98,8 +110,42 @@ struct DiskState {
 
     /// Original comments from the parsed crate
     comment_map: CommentMap,
+
+    /// Nodes of `orig_krate` that must be pretty-printed when rewriting, even if unchanged
+    reprint_nodes: HashSet<NodeId>,
 }
 
+/// The in-memory state of the refactoring process at some point, which `revert` and `undo` can
+/// go back to.
+///
+/// The crate, `node_map`, and marks are saved and restored together, so restored marks refer to
+/// the `NodeId`s of the restored crate.  The `NodeId` counter is deliberately left alone: IDs
+/// handed out after the snapshot are never reused, so stale references to them (e.g. in the
+/// comment map) can't pick up unrelated nodes.
+///
+/// Everything is deep-cloned, so each snapshot takes about as much time and memory as a copy of
+/// the crate's AST.  With undo enabled, one is taken before every command, and up to the undo
+/// limit of them are kept at once.
+#[derive(Clone)]
+struct Snapshot {
+    krate: Option<Crate>,
+    marks: HashSet<(NodeId, Symbol)>,
+    parsed_nodes: ParsedNodes,
+    /// `node_map` and `disk_state.reprint_nodes`, or `None` if the crate hadn't been parsed yet
+    disk: Option<(NodeMap, HashSet<NodeId>)>,
+    edit_log: Option<HashMap<NodeId, Vec<String>>>,
+    commands: Vec<String>,
+    /// `RefactorState::disk_generation` at the time of the snapshot
+    generation: usize,
+}
+
+/// Commands that manage snapshots, rather than changing the crate themselves
+const HISTORY_COMMANDS: &[&str] = &["checkpoint", "revert", "undo"];
+
+/// How many commands `undo` can revert in a row, unless `RefactorState::set_undo_limit` says
+/// otherwise
+pub const DEFAULT_UNDO_LIMIT: usize = 10;
+
 /// Stores the overall state of the refactoring process, which can be read and updated by
 /// `Command`s.
 pub struct RefactorState {
@@ -137,6 +183,60 @@ pub struct RefactorState {
 
     /// Generation number for TyCtxt references
     tcx_gen: TyCtxtGeneration,
+
+    /// Report collected by the currently running command, if it asked for one
+    report: Option<Report>,
+
+    /// Items that pattern matching in the currently running command is restricted to
+    match_scope: Option<MatchScope>,
+
+    /// Whether the currently running command may match inside local macro expansions
+    match_in_macros: bool,
+
+    /// Near-misses collected by the currently running command, with `--explain-matches`
+    explanation: Option<MatchExplanation>,
+
+    /// The commands that changed each item of `disk_state.orig_krate`, keyed by its original
+    /// `NodeId`.  Only tracked if the `FileIO` records edits.
+    edit_log: Option<HashMap<NodeId, Vec<String>>>,
+
+    /// The `item_fingerprints` of `krate` as of the end of the last command, for `edit_log`,
+    /// along with the `krate_version` they were taken at.  Kept so the crate only gets
+    /// fingerprinted again after commands that changed it.
+    fingerprints: Option<(usize, HashMap<NodeId, String>)>,
+
+    /// Incremented whenever `krate` changes
+    krate_version: usize,
+
+    /// How many times the crate has been loaded from disk
+    load_count: usize,
+
+    /// Snapshots saved by the `checkpoint` command
+    checkpoints: HashMap<String, Snapshot>,
+
+    /// Snapshots from before each of the latest commands that changed the crate or its marks
+    undo_stack: Vec<Snapshot>,
+
+    /// Whether to fill `undo_stack`, which copies the whole crate before each command
+    undo_enabled: bool,
+
+    /// The most snapshots `undo_stack` keeps, dropping the oldest first
+    undo_limit: usize,
+
+    /// Whether the crate or its marks may have changed since the current command started
+    state_changed: bool,
+
+    /// Incremented each time the crate is loaded from disk, which invalidates all snapshots
+    disk_generation: usize,
+
+    /// The text last written to each file since the crate was loaded
+    disk_texts: HashMap<PathBuf, String>,
+
+    /// Error reported by the currently running command
+    command_error: Option<String>,
+
+    /// Pipelines currently running, innermost last
+    pipeline_stack: Vec<String>,
 }
 
 // #[cfg_attr(feature = "profile", flame)]
@@ -192,6 +292,7 @@ impl DiskState {
         DiskState {
             orig_krate: krate,
             comment_map,
+            reprint_nodes: HashSet::new(),
         }
     }
 }
@@ -205,6 +306,11 @@ impl RefactorState {
         marks: HashSet<(NodeId, Symbol)>,
     ) -> RefactorState {
         let compiler = driver::make_compiler(&config, file_io.clone());
+        let edit_log = if file_io.records_edits() {
+            Some(HashMap::new())
+        } else {
+            None
+        };
         RefactorState {
             config,
             compiler,
@@ -225,6 +331,40 @@ impl RefactorState {
             node_id_counter: NodeIdCounter::new(FRESH_NODE_ID_START),
 
             tcx_gen: Arc::new(AtomicUsize::new(1)),
+
+            report: None,
+
+            match_scope: None,
+
+            match_in_macros: false,
+
+            explanation: None,
+
+            edit_log,
+
+            fingerprints: None,
+
+            krate_version: 0,
+
+            load_count: 0,
+
+            checkpoints: HashMap::new(),
+
+            undo_stack: Vec::new(),
+
+            undo_enabled: false,
+
+            undo_limit: DEFAULT_UNDO_LIMIT,
+
+            state_changed: false,
+
+            disk_generation: 0,
+
+            disk_texts: HashMap::new(),
+
+            command_error: None,
+
+            pipeline_stack: Vec::new(),
         }
     }
 
@@ -247,10 +387,49 @@ impl RefactorState {
         self.compiler = driver::make_compiler(&self.config, self.file_io.clone());
         self.disk_state = None;
         self.krate = None;
+        self.krate_version += 1;
         self.marks.clear();
         self.node_map = NodeMap::new();
         self.parsed_nodes = ParsedNodes::default();
         self.node_id_counter = NodeIdCounter::new(FRESH_NODE_ID_START);
+        if let Some(ref mut log) = self.edit_log {
+            log.clear();
+        }
+        self.load_count += 1;
+        self.disk_texts.clear();
+        self.invalidate_snapshots();
+    }
+
+    /// Load the crate from disk, unless its files still hold the text that was last read from
+    /// or written to them.  Otherwise the current state, including marks and snapshots, is kept,
+    /// and later `save_crate`s keep rewriting the text it was loaded from.
+    pub fn reload_crate_if_changed(&mut self) {
+        if self.disk_state.is_some() && !self.files_changed() {
+            info!("crate files are unchanged, keeping the loaded crate");
+            return;
+        }
+        self.load_crate();
+    }
+
+    /// Whether any file of the loaded crate no longer holds the text last read from or written
+    /// to it.
+    fn files_changed(&self) -> bool {
+        for sf in self.source_map().files().iter() {
+            let path = match sf.name {
+                FileName::Real(ref path) => path,
+                _ => continue,
+            };
+            let expected = match (self.disk_texts.get(path), &sf.src) {
+                (Some(text), _) => text.as_str(),
+                (None, Some(src)) => src.as_str(),
+                (None, None) => continue,
+            };
+            match self.file_io.read_file(path) {
+                Ok(ref text) if text == expected => {}
+                _ => return true,
+            }
+        }
+        false
     }
 
     /// Save the crate to disk, by writing out the new source text produced by rewriting.
@@ -260,13 +439,13 @@ impl RefactorState {
     /// matches the text on disk) as the basis for rewriting.
     #[cfg_attr(feature = "profile", flame)]
     pub fn save_crate(&mut self) {
-        if let None = self.krate {
-            return;
-        }
-
-        let disk_state = self.disk_state.as_ref().unwrap();
+        let disk_state = match self.disk_state {
+            Some(ref disk_state) => disk_state,
+            None => return,
+        };
         let old = &disk_state.orig_krate;
-        let new = self.krate.as_ref().unwrap();
+        // The crate may still be unchanged after `undo` or `revert` went back past its parsing.
+        let new = self.krate.as_ref().unwrap_or(old);
         let node_id_map = self.node_map.clone().into_inner();
 
         self.file_io
@@ -278,12 +457,72 @@ impl RefactorState {
             )
             .unwrap();
 
-        let rw = rewrite::rewrite(self.session(), old, new, &disk_state.comment_map, node_id_map, |map| {
-            map_ast_into(&self.parsed_nodes, map);
-        });
-        // Note that `rewrite_files_with` does not read any files from disk - it uses the
-        // `SourceMap` to get files' original source text.
-        files::rewrite_files_with(self.source_map(), &rw, &*self.file_io).unwrap();
+        let rw = rewrite::rewrite(
+            self.session(),
+            old,
+            new,
+            &disk_state.comment_map,
+            &disk_state.reprint_nodes,
+            node_id_map.clone(),
+            |map| map_ast_into(&self.parsed_nodes, map),
+        );
+        // Note that `file_edits` does not read any files from disk - it uses the `SourceMap` to
+        // get files' original source text.
+        let mut file_edits = files::file_edits(self.source_map(), &rw, &*self.file_io);
+        // Files that an earlier save changed, but that are back to their original text after
+        // `undo` or `revert`, have no edits left, yet still need to be written.
+        for sf in self.source_map().files().iter() {
+            let path = match sf.name {
+                FileName::Real(ref path) => path,
+                _ => continue,
+            };
+            let reverted = match (self.disk_texts.get(path), &sf.src) {
+                (Some(text), Some(src)) => text.as_str() != src.as_str(),
+                _ => false,
+            };
+            if reverted && !file_edits.iter().any(|f| &f.path == path) {
+                file_edits.push(files::FileEdits {
+                    sf: sf.clone(),
+                    path: path.clone(),
+                    created: false,
+                    rewrites: Vec::new(),
+                    nodes: Vec::new(),
+                    edits: Vec::new(),
+                });
+            }
+        }
+        file_edits.sort_by(|a, b| a.path.cmp(&b.path));
+        if let Some(ref log) = self.edit_log {
+            let items = item_spans(old);
+            for e in file_edits.iter_mut().flat_map(|f| f.edits.iter_mut()) {
+                // Attribute the edit to the innermost original item around it.
+                let item = items
+                    .iter()
+                    .filter(|&&(sp, _)| sp.contains(e.old_span))
+                    .min_by_key(|&&(sp, _)| sp.hi() - sp.lo())
+                    .map_or(CRATE_NODE_ID, |&(_, id)| id);
+                e.commands = log.get(&item).cloned().unwrap_or_default();
+                let mut labels = self
+                    .marks
+                    .iter()
+                    .filter(|&&(id, _)| {
+                        node_id_map.get(&id) == Some(&item) || e.nodes.contains(&id)
+                    })
+                    .map(|&(_, label)| label.to_string())
+                    .collect::<Vec<_>>();
+                labels.sort();
+                labels.dedup();
+                e.labels = labels;
+            }
+            self.file_io.save_edits(self.load_count, &file_edits).unwrap();
+        }
+        files::write_file_edits(self.source_map(), &file_edits, &*self.file_io).unwrap();
+        for f in &file_edits {
+            if let Some(ref src) = f.sf.src {
+                let text = files::apply_edits(src, &f.edits);
+                self.disk_texts.insert(f.path.clone(), text);
+            }
+        }
     }
 
     #[cfg_attr(feature = "profile", flame)]
@@ -302,6 +541,13 @@ impl RefactorState {
         let tcx_gen = &self.tcx_gen;
         let krate = &mut self.krate;
         let node_id_counter = &mut self.node_id_counter;
+        let report = &mut self.report;
+        let match_scope = &self.match_scope;
+        let match_in_macros = self.match_in_macros;
+        let explanation = &mut self.explanation;
+        let state_changed = &mut self.state_changed;
+        let krate_version = &mut self.krate_version;
+        let guards = self.cmd_reg.guards();
 
         self.compiler.enter(|queries| {
             // Replace current parse query results
@@ -332,6 +578,10 @@ impl RefactorState {
                 ParsedNodes::default(),
                 node_id_counter.clone(),
             );
+            cs.match_scope = match_scope.clone();
+            cs.guards = guards.clone();
+            cs.match_in_macros = match_in_macros;
+            *cs.explanation.get_mut() = explanation.take();
 
             let unexpanded = cs.krate().clone();
             if phase != Phase::Phase1 {
@@ -376,6 +626,9 @@ impl RefactorState {
                     Some(CollapseInfo::collect(&unexpanded, &expanded, node_map, &cs))
                 }
             };
+            if let Some(ref collapse_info) = collapse_info {
+                cs.macro_expansions = collapse_info.macro_expansions(source_map);
+            }
 
             // Run the transform
             let r = match phase {
@@ -434,6 +687,14 @@ impl RefactorState {
                 }
             };
 
+            cs.apply_attr_edits();
+            if cs.krate_changed() || cs.marks_changed() {
+                *state_changed = true;
+            }
+            if cs.krate_changed() {
+                *krate_version += 1;
+            }
+
             node_map.init(cs.new_parsed_node_ids.get_mut().drain(..));
 
             if let Some(collapse_info) = collapse_info {
@@ -448,6 +709,20 @@ impl RefactorState {
                 }
             }
 
+            for node in cs.reprint_nodes.get_mut().drain(..) {
+                if let Some(&node) = node_map.get(&node) {
+                    disk_state.reprint_nodes.insert(node);
+                }
+            }
+
+            if let Some(new_report) = cs.report.into_inner() {
+                match *report {
+                    Some(ref mut report) => report.merge(new_report),
+                    None => *report = Some(new_report),
+                }
+            }
+
+            *explanation = cs.explanation.into_inner();
             *marks = cs.marks.into_inner();
             parsed_nodes.append(cs.parsed_nodes.into_inner());
             *krate = Some(cs.krate.into_inner());
@@ -518,28 +793,300 @@ impl RefactorState {
         result.unwrap()
     }
 
+    /// Make all existing snapshots unusable, as the crate was loaded again and their `NodeId`s no
+    /// longer match it.  Checkpoints are kept around so `revert` can explain why it fails.
+    fn invalidate_snapshots(&mut self) {
+        self.disk_generation += 1;
+        self.undo_stack.clear();
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            krate: self.krate.clone(),
+            marks: self.marks.clone(),
+            parsed_nodes: self.parsed_nodes.clone(),
+            disk: self
+                .disk_state
+                .as_ref()
+                .map(|ds| (self.node_map.clone(), ds.reprint_nodes.clone())),
+            edit_log: self.edit_log.clone(),
+            commands: self.commands.clone(),
+            generation: self.disk_generation,
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.krate = snapshot.krate;
+        self.krate_version += 1;
+        self.marks = snapshot.marks;
+        self.parsed_nodes = snapshot.parsed_nodes;
+        self.edit_log = snapshot.edit_log;
+        self.commands = snapshot.commands;
+        if let Some(ref mut disk_state) = self.disk_state {
+            match snapshot.disk {
+                Some((node_map, reprint_nodes)) => {
+                    self.node_map = node_map;
+                    disk_state.reprint_nodes = reprint_nodes;
+                }
+                None => {
+                    // The crate was parsed after the snapshot, so go back to the freshly parsed
+                    // state, as `DiskState::new` left it.
+                    self.node_map = NodeMap::new();
+                    self.node_map
+                        .init(disk_state.orig_krate.list_node_ids().into_iter());
+                    self.node_map.init(iter::once(CRATE_NODE_ID));
+                    disk_state.reprint_nodes.clear();
+                }
+            }
+        }
+    }
+
+    /// Save the current state under `name`, replacing any earlier checkpoint with that name.
+    pub fn checkpoint(&mut self, name: &str) {
+        let snapshot = self.snapshot();
+        self.checkpoints.insert(name.to_owned(), snapshot);
+    }
+
+    /// Go back to the state saved by `checkpoint(name)`.  The checkpoint stays available, so
+    /// the same state can be restored again later.
+    pub fn revert(&mut self, name: &str) -> Result<(), String> {
+        let snapshot = match self.checkpoints.get(name) {
+            Some(snapshot) => snapshot,
+            None => return Err(format!("no checkpoint named `{}`", name)),
+        };
+        if snapshot.generation != self.disk_generation {
+            return Err(format!(
+                "checkpoint `{}` is from before the crate was last loaded from disk",
+                name
+            ));
+        }
+        let snapshot = snapshot.clone();
+        self.restore(snapshot);
+        Ok(())
+    }
+
+    /// Go back to the state before the most recent command that changed the crate or its marks.
+    pub fn undo(&mut self) -> Result<(), String> {
+        if !self.undo_enabled {
+            return Err(
+                "undo is not enabled; Lua scripts must call `refactor:enable_undo()`".to_owned()
+            );
+        }
+        match self.undo_stack.pop() {
+            Some(snapshot) => {
+                self.restore(snapshot);
+                Ok(())
+            }
+            None => Err(
+                "nothing to undo since the crate was last loaded from disk".to_owned()
+            ),
+        }
+    }
+
+    /// Keep a snapshot from before each command that changes the crate or its marks, so `undo`
+    /// can go back to it.  This is off by default, since each snapshot is a copy of the crate.
+    pub fn enable_undo(&mut self) {
+        self.undo_enabled = true;
+    }
+
+    /// Set how many commands in a row `undo` can revert.  Lower limits keep fewer copies of the
+    /// crate around.
+    pub fn set_undo_limit(&mut self, limit: usize) {
+        self.undo_limit = limit;
+        if self.undo_stack.len() > limit {
+            let excess = self.undo_stack.len() - limit;
+            self.undo_stack.drain(..excess);
+        }
+    }
+
+    /// Make the currently running command fail with `msg`, once it returns.
+    pub fn fail(&mut self, msg: String) {
+        self.command_error = Some(msg);
+    }
+
     pub fn clear_marks(&mut self) {
+        self.state_changed = true;
         self.marks.clear();
     }
 
     /// Invoke a registered command with the given command name and arguments.
+    ///
+    /// Every command accepts the `--scope PATH` and `--scope-mark LABEL` options, which restrict
+    /// the pattern matching done by the command to the items whose paths match the `PATH` glob
+    /// and to the items marked `LABEL`, respectively.  See `matcher::MatchScope` for details.
+    ///
+    /// Commands also accept the `--match-in-macros` flag, which lets pattern matching look inside
+    /// the expansions of macros defined in the crate.  Matches there must lie entirely within a
+    /// single macro argument, and are rewritten at the call site.
+    ///
+    /// With the `--explain-matches N` option, failed match attempts are recorded, and the `N`
+    /// that got furthest are printed after the command.  See `matcher::MatchExplanation`.
+    ///
+    /// With undo enabled, the state from before the command is saved for `undo`, if the command
+    /// changed the crate or its marks.  A pipeline is saved for as a whole, not per member.
     #[cfg_attr(feature = "profile", flame)]
     pub fn run<S: AsRef<str>>(&mut self, cmd_name: &str, args: &[S]) -> Result<(), String> {
-        let args = args
+        if !self.pipeline_stack.is_empty() {
+            return self.run_command(cmd_name, args);
+        }
+        let undo_snapshot = if self.undo_enabled && !HISTORY_COMMANDS.contains(&cmd_name) {
+            Some(self.snapshot())
+        } else {
+            None
+        };
+        self.state_changed = false;
+        let result = self.run_command(cmd_name, args);
+        if let Some(snapshot) = undo_snapshot {
+            if snapshot.generation == self.disk_generation && self.state_changed {
+                self.undo_stack.push(snapshot);
+                self.set_undo_limit(self.undo_limit);
+            }
+        }
+        result
+    }
+
+    fn run_command<S: AsRef<str>>(&mut self, cmd_name: &str, args: &[S]) -> Result<(), String> {
+        let mut args = args
             .iter()
             .map(|s| s.as_ref().to_owned())
             .collect::<Vec<_>>();
+        if !self.cmd_reg.commands.contains_key(cmd_name)
+            && self.cmd_reg.pipelines.contains_key(cmd_name)
+        {
+            return self.run_pipeline(cmd_name, &args);
+        }
+        let scope_path = take_option(&mut args, "--scope")?;
+        let scope_mark = take_option(&mut args, "--scope-mark")?;
+        self.match_scope = if scope_path.is_some() || scope_mark.is_some() {
+            Some(MatchScope::new(scope_path.as_ref().map(|s| &s[..]),
+                                 scope_mark.map(|s| s.into_symbol())))
+        } else {
+            None
+        };
+        self.match_in_macros = take_flag(&mut args, "--match-in-macros");
+        let explain_limit = take_option(&mut args, "--explain-matches")?
+            .map(|n| {
+                n.parse::<usize>()
+                    .map_err(|_| format!("--explain-matches requires a number, got {}", n))
+            })
+            .transpose()?;
+        self.explanation = explain_limit.map(|n| MatchExplanation::new(cmd_name, n));
         info!("running command: {} {:?}", cmd_name, args);
+        let mut cmd = self.cmd_reg.get_command(cmd_name, &args)?;
         self.commands.push(args.iter().fold(cmd_name.to_string(), |mut s, arg| {
             s.push_str(arg);
             s
         }));
-
-        let mut cmd = self.cmd_reg.get_command(cmd_name, &args)?;
+        let before = match (&self.edit_log, &self.krate) {
+            (Some(_), Some(krate)) => Some(match self.fingerprints.take() {
+                Some((version, fingerprints)) if version == self.krate_version => fingerprints,
+                _ => item_fingerprints(krate, Some(&self.node_map)),
+            }),
+            _ => None,
+        };
+        let before_version = self.krate_version;
         profile_start!(format!("Command {}", cmd_name));
         cmd.run(self);
         profile_end!(format!("Command {}", cmd_name));
-        Ok(())
+        if self.edit_log.is_some() {
+            let desc = iter::once(cmd_name.to_owned())
+                .chain(args.iter().cloned())
+                .collect::<Vec<_>>()
+                .join(" ");
+            self.log_edits(&desc, before, before_version);
+        }
+        if let Some(report) = self.report.take() {
+            report.emit();
+        }
+        if let Some(explanation) = self.explanation.take() {
+            explanation.emit();
+        }
+        self.match_scope = None;
+        self.match_in_macros = false;
+        match self.command_error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Run the commands of the pipeline `name`.  Errors name the pipeline and the position of the
+    /// member command that failed.
+    fn run_pipeline(&mut self, name: &str, args: &[String]) -> Result<(), String> {
+        if self.pipeline_stack.iter().any(|n| n == name) {
+            return Err(format!("pipeline `{}` runs itself", name));
+        }
+        let commands = self.cmd_reg.pipelines[name]
+            .expand(args, false)
+            .map_err(|e| format!("pipeline `{}`: {}", name, e))?;
+        let member_error = |i: usize, e: String| {
+            format!(
+                "pipeline `{}`, command {} of {} (`{}`): {}",
+                name,
+                i + 1,
+                commands.len(),
+                commands[i].join(" "),
+                e
+            )
+        };
+
+        // Check the whole pipeline before running any of it.
+        for (i, cmd) in commands.iter().enumerate() {
+            match cmd.get(0) {
+                Some(cmd_name)
+                    if self.cmd_reg.commands.contains_key(cmd_name)
+                        || self.cmd_reg.pipelines.contains_key(cmd_name) => {}
+                Some(_) => return Err(member_error(i, "no such command".to_owned())),
+                None => return Err(member_error(i, "empty command".to_owned())),
+            }
+        }
+
+        self.pipeline_stack.push(name.to_owned());
+        let mut result = Ok(());
+        for (i, cmd) in commands.iter().enumerate() {
+            if let Err(e) = self.run(&cmd[0], &cmd[1..]) {
+                result = Err(member_error(i, e));
+                break;
+            }
+        }
+        self.pipeline_stack.pop();
+        result
+    }
+
+    /// Record `cmd` in `edit_log` for each original item it changed or removed.  `before` holds
+    /// the fingerprints of the items before the command, taken at `krate_version`
+    /// `before_version`, or is `None` if the crate hadn't been transformed yet.
+    fn log_edits(
+        &mut self,
+        cmd: &str,
+        before: Option<HashMap<NodeId, String>>,
+        before_version: usize,
+    ) {
+        let krate = match self.krate {
+            Some(ref krate) => krate,
+            None => return,
+        };
+        let before = match (before, &self.disk_state) {
+            // Nothing changed, so the fingerprints still hold for the next command
+            (Some(before), _) if before_version == self.krate_version => {
+                self.fingerprints = Some((before_version, before));
+                return;
+            }
+            (Some(before), _) => before,
+            (None, Some(disk_state)) => item_fingerprints(&disk_state.orig_krate, None),
+            (None, None) => return,
+        };
+        let after = item_fingerprints(krate, Some(&self.node_map));
+        let log = self.edit_log.as_mut().unwrap();
+        for (id, fingerprint) in before {
+            if after.get(&id) == Some(&fingerprint) {
+                continue;
+            }
+            let cmds = log.entry(id).or_insert_with(Vec::new);
+            if cmds.last().map(|s| &s[..]) != Some(cmd) {
+                cmds.push(cmd.to_owned());
+            }
+        }
+        self.fingerprints = Some((self.krate_version, after));
     }
 
     pub fn marks(&self) -> &HashSet<(NodeId, Symbol)> {
@@ -547,10 +1094,117 @@ impl RefactorState {
     }
 
     pub fn marks_mut(&mut self) -> &mut HashSet<(NodeId, Symbol)> {
+        self.state_changed = true;
         &mut self.marks
     }
 }
 
+/// Summarize each item of `krate` as a string that changes whenever the item does, keyed by its
+/// original `NodeId` according to `node_map`, or by its own if `node_map` is `None`.  Modules
+/// (including the crate itself, under `CRATE_NODE_ID`) are summarized by the names of their
+/// items, as their contents are covered by the items themselves.
+fn item_fingerprints(krate: &Crate, node_map: Option<&NodeMap>) -> HashMap<NodeId, String> {
+    struct FingerprintVisitor<'a> {
+        node_map: Option<&'a NodeMap>,
+        fingerprints: HashMap<NodeId, String>,
+    }
+
+    impl<'a> FingerprintVisitor<'a> {
+        fn add(&mut self, id: NodeId, fingerprint: String) {
+            let id = match self.node_map {
+                Some(node_map) => match node_map.save_origin(id) {
+                    Some(id) => id,
+                    None => return,
+                },
+                None => id,
+            };
+            self.fingerprints.insert(id, fingerprint);
+        }
+    }
+
+    impl<'a, 'ast> Visitor<'ast> for FingerprintVisitor<'a> {
+        fn visit_item(&mut self, i: &'ast Item) {
+            let fingerprint = match i.kind {
+                ItemKind::Mod(ref m) => module_fingerprint(m),
+                _ => pprust::item_to_string(i),
+            };
+            self.add(i.id, fingerprint);
+            visit::walk_item(self, i);
+        }
+
+        fn visit_mac(&mut self, mac: &'ast Mac) {
+            visit::walk_mac(self, mac);
+        }
+    }
+
+    fn module_fingerprint(m: &Mod) -> String {
+        m.items
+            .iter()
+            .map(|i| i.ident.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    let mut v = FingerprintVisitor {
+        node_map,
+        fingerprints: HashMap::new(),
+    };
+    v.add(CRATE_NODE_ID, module_fingerprint(&krate.module));
+    krate.visit(&mut v);
+    v.fingerprints
+}
+
+/// Collect the span of each item of `krate`, along with the span of each module's contents,
+/// which for a module in another file lies outside the module item's own span.
+fn item_spans(krate: &Crate) -> Vec<(Span, NodeId)> {
+    struct SpanVisitor {
+        spans: Vec<(Span, NodeId)>,
+    }
+
+    impl<'ast> Visitor<'ast> for SpanVisitor {
+        fn visit_item(&mut self, i: &'ast Item) {
+            self.spans.push((i.span, i.id));
+            if let ItemKind::Mod(ref m) = i.kind {
+                self.spans.push((m.inner, i.id));
+            }
+            visit::walk_item(self, i);
+        }
+
+        fn visit_mac(&mut self, mac: &'ast Mac) {
+            visit::walk_mac(self, mac);
+        }
+    }
+
+    let mut v = SpanVisitor { spans: Vec::new() };
+    krate.visit(&mut v);
+    v.spans
+}
+
+/// Remove the option `name` and its value from `args`, returning the value.
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    let i = match args.iter().position(|arg| arg == name) {
+        Some(i) => i,
+        None => return Ok(None),
+    };
+    if i + 1 >= args.len() {
+        return Err(format!("{} requires an argument", name));
+    }
+    let value = args.remove(i + 1);
+    args.remove(i);
+    Ok(Some(value))
+}
+
+/// Remove the flag `name` from `args`, returning whether it was present.
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    match args.iter().position(|arg| arg == name) {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    }
+}
+
 pub enum TypeckLoopResult {
     Iterate,
     Err(&'static str),
@@ -584,6 +1238,30 @@ pub struct CommandState {
 
     new_comments: RefCell<Vec<(NodeId, Comment)>>,
 
+    /// Unchanged nodes whose source text should be pretty-printed anew
+    reprint_nodes: RefCell<Vec<NodeId>>,
+
+    /// Attributes to add to or remove from items once the transform finishes
+    attr_edits: RefCell<Vec<(NodeId, AttrEdit)>>,
+
+    /// Summary of the rewrites made so far, if the command asked for one
+    report: RefCell<Option<Report>>,
+
+    /// Items that pattern matching is restricted to, if any
+    match_scope: Option<MatchScope>,
+
+    /// Guards that `guard!` patterns can call, from the command `Registry`
+    guards: Arc<GuardMap>,
+
+    /// The macro invocation each node of the expanded crate came from, if any
+    macro_expansions: HashMap<NodeId, Rc<MacroExpansion>>,
+
+    /// Whether pattern matching may look inside local macro expansions
+    match_in_macros: bool,
+
+    /// Failed match attempts, if the command was run with `--explain-matches`
+    explanation: RefCell<Option<MatchExplanation>>,
+
     krate_changed: Cell<bool>,
     marks_changed: Cell<bool>,
 }
@@ -603,6 +1281,14 @@ impl CommandState {
             parsed_nodes: RefCell::new(parsed_nodes),
             new_parsed_node_ids: RefCell::new(Vec::new()),
             new_comments: RefCell::new(Vec::new()),
+            reprint_nodes: RefCell::new(Vec::new()),
+            attr_edits: RefCell::new(Vec::new()),
+            report: RefCell::new(None),
+            match_scope: None,
+            guards: Arc::new(GuardMap::new()),
+            macro_expansions: HashMap::new(),
+            match_in_macros: false,
+            explanation: RefCell::new(None),
 
             krate_changed: Cell::new(false),
             marks_changed: Cell::new(false),
@@ -632,6 +1318,95 @@ impl CommandState {
         self.new_comments.borrow_mut().push((node, comment));
     }
 
+    /// Make the rewriter pretty-print the expression, statement or item `id` when the crate is
+    /// saved, even if it's unchanged.  The text of its children is still reused, and only
+    /// parenthesized where their precedence requires it.
+    pub fn reprint(&self, id: NodeId) {
+        self.krate_changed.set(true);
+        self.reprint_nodes.borrow_mut().push(id);
+    }
+
+    /// Add `attr` to the item, impl item, trait item, or foreign item `id`.  The attribute is
+    /// added once the current transform finishes, so this can be called while the crate is
+    /// borrowed.
+    pub fn add_attr(&self, id: NodeId, attr: Attribute) {
+        self.attr_edits.borrow_mut().push((id, AttrEdit::Add(attr)));
+    }
+
+    /// Remove all attributes named `name` from the item-like node `id`, once the current
+    /// transform finishes.
+    pub fn remove_attr<S: IntoSymbol>(&self, id: NodeId, name: S) {
+        self.attr_edits
+            .borrow_mut()
+            .push((id, AttrEdit::Remove(name.into_symbol())));
+    }
+
+    fn apply_attr_edits(&mut self) {
+        let edits = mem::replace(self.attr_edits.get_mut(), Vec::new());
+        if edits.is_empty() {
+            return;
+        }
+        self.krate_changed.set(true);
+        let mut editor = AttrEditor { edits };
+        editor.visit_crate(self.krate.get_mut());
+    }
+
+    /// Start collecting a `Report` of this command's rewrites.  The report gets printed, and
+    /// written to `output` as JSON if that's set, once the command finishes.
+    pub fn start_report(&self, title: &str, output: Option<String>) {
+        *self.report.borrow_mut() = Some(Report::new(title, output));
+    }
+
+    /// Record a rewrite of kind `category` at `span` in the current report, if there is one.
+    pub fn report(&self, cx: &RefactorCtxt, category: &str, span: Span) {
+        if let Some(ref mut report) = *self.report.borrow_mut() {
+            report.record(category, cx.session().source_map().span_to_string(span));
+        }
+    }
+
+    /// Record an entry of kind `category` at `span` in the current report, if there is one,
+    /// along with the location of a `related` span, such as a conflicting definition.
+    pub fn report_related(&self, cx: &RefactorCtxt, category: &str, span: Span, related: Span) {
+        if let Some(ref mut report) = *self.report.borrow_mut() {
+            let source_map = cx.session().source_map();
+            report.record(category, format!("{} (see {})",
+                                            source_map.span_to_string(span),
+                                            source_map.span_to_string(related)));
+        }
+    }
+
+    /// The items that pattern matching in the current command is restricted to, if any.
+    pub fn match_scope(&self) -> Option<&MatchScope> {
+        self.match_scope.as_ref()
+    }
+
+    /// Look up a guard registered under `name`, for use by a `guard!` pattern.
+    pub fn guard(&self, name: &str) -> Option<&Guard> {
+        self.guards.get(name).map(|g| &**g)
+    }
+
+    /// The macro invocation whose expansion produced node `id`, if any.
+    pub fn macro_expansion(&self, id: NodeId) -> Option<&MacroExpansion> {
+        self.macro_expansions.get(&id).map(|e| &**e)
+    }
+
+    /// Whether pattern matching may look inside the expansions of local macros.
+    pub fn match_in_macros(&self) -> bool {
+        self.match_in_macros
+    }
+
+    /// Whether failed match attempts should be recorded for `--explain-matches`.
+    pub fn explain_matches(&self) -> bool {
+        self.explanation.borrow().is_some()
+    }
+
+    /// Record a failed match attempt, if the command was run with `--explain-matches`.
+    pub fn record_near_miss(&self, cx: &RefactorCtxt, trace: MatchTrace) {
+        if let Some(ref mut explanation) = *self.explanation.borrow_mut() {
+            explanation.record(cx.session().source_map(), trace);
+        }
+    }
+
     pub fn marks(&self) -> cell::Ref<HashSet<(NodeId, Symbol)>> {
         self.marks.borrow()
     }
@@ -719,39 +1494,365 @@ impl CommandState {
     }
 }
 
+enum AttrEdit {
+    Add(Attribute),
+    Remove(Symbol),
+}
+
+/// Applies queued `AttrEdit`s to the attributes of item-like nodes.
+struct AttrEditor {
+    edits: Vec<(NodeId, AttrEdit)>,
+}
+
+impl AttrEditor {
+    fn edit(&self, id: NodeId, attrs: &mut Vec<Attribute>) {
+        for (edit_id, edit) in &self.edits {
+            if *edit_id != id {
+                continue;
+            }
+            match edit {
+                AttrEdit::Add(attr) => attrs.push(attr.clone()),
+                AttrEdit::Remove(name) => attrs.retain(|attr| !attr.check_name(*name)),
+            }
+        }
+    }
+}
+
+impl MutVisitor for AttrEditor {
+    fn flat_map_item(&mut self, mut i: P<Item>) -> SmallVec<[P<Item>; 1]> {
+        self.edit(i.id, &mut i.attrs);
+        mut_visit::noop_flat_map_item(i, self)
+    }
+
+    fn flat_map_impl_item(&mut self, mut i: ImplItem) -> SmallVec<[ImplItem; 1]> {
+        self.edit(i.id, &mut i.attrs);
+        mut_visit::noop_flat_map_impl_item(i, self)
+    }
+
+    fn flat_map_trait_item(&mut self, mut i: TraitItem) -> SmallVec<[TraitItem; 1]> {
+        self.edit(i.id, &mut i.attrs);
+        mut_visit::noop_flat_map_trait_item(i, self)
+    }
+
+    fn flat_map_foreign_item(&mut self, mut i: ForeignItem) -> SmallVec<[ForeignItem; 1]> {
+        self.edit(i.id, &mut i.attrs);
+        mut_visit::noop_flat_map_foreign_item(i, self)
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
 /// Implementation of a refactoring command.
 pub trait Command {
     fn run(&mut self, state: &mut RefactorState);
 }
 
-/// A command builder is a function that takes some string arguments and produces a `Command`.
-pub type Builder = dyn FnMut(&[String]) -> Box<dyn Command> + Send;
+/// A command builder is a function that takes the arguments of a command, already checked
+/// against its `CommandSpec`, and produces a `Command`.  It can still reject them, e.g. if some
+/// combination of them makes no sense.
+pub type Builder = dyn FnMut(&Args) -> Result<Box<dyn Command>, String> + Send;
+
+struct CommandBuilder {
+    spec: CommandSpec,
+    builder: Box<Builder>,
+}
+
+/// A named predicate on expressions, which `guard!(name, ...)` patterns call during matching.
+/// It gets the target expression, along with the match context holding the bindings captured by
+/// the guard's inner pattern.
+pub type Guard = dyn Fn(&Expr, &MatchCtxt, &RefactorCtxt) -> bool + Send + Sync;
+
+type GuardMap = HashMap<String, Arc<Guard>>;
+
+/// A named sequence of commands, which runs as a single command.
+///
+/// The arguments of the member commands can refer to parameters of the pipeline as `${NAME}`.
+/// Invoking the pipeline as `PIPELINE NAME=VALUE ...` sets them.  A member command may itself be
+/// a pipeline.
+#[derive(Clone, Debug, Default)]
+pub struct Pipeline {
+    /// Each command's name followed by its arguments
+    pub commands: Vec<Vec<String>>,
+    /// Values of the parameters that don't need to be set on invocation
+    pub defaults: HashMap<String, String>,
+}
+
+impl Pipeline {
+    pub fn new<S: AsRef<str>>(commands: &[&[S]]) -> Pipeline {
+        Pipeline {
+            commands: commands
+                .iter()
+                .map(|c| c.iter().map(|s| s.as_ref().to_owned()).collect())
+                .collect(),
+            defaults: HashMap::new(),
+        }
+    }
+
+    pub fn with_default(mut self, param: &str, value: &str) -> Pipeline {
+        self.defaults.insert(param.to_owned(), value.to_owned());
+        self
+    }
+
+    /// Parse the definition of one pipeline from `pipelines.toml`:
+    ///
+    /// ```toml
+    /// [cleanup_basic]
+    /// params = { target_mod = "*" }
+    /// commands = [
+    ///     "reorganize_definitions",
+    ///     ["rename_unnamed", "--scope", "${target_mod}"],
+    /// ]
+    /// ```
+    ///
+    /// A command is either an array holding its name and arguments, or a string, which is split
+    /// on whitespace.
+    fn from_toml(def: &toml::Value) -> Result<Pipeline, String> {
+        let mut pipeline = Pipeline::default();
+        let commands = def
+            .get("commands")
+            .and_then(|v| v.as_array())
+            .ok_or("missing `commands` array")?;
+        for (i, cmd) in commands.iter().enumerate() {
+            let words = match cmd {
+                toml::Value::String(s) => s.split_whitespace().map(|s| s.to_owned()).collect(),
+                toml::Value::Array(words) => words
+                    .iter()
+                    .map(|w| w.as_str().map(|s| s.to_owned()))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| format!("command {}: arguments must be strings", i + 1))?,
+                _ => return Err(format!("command {}: expected a string or an array", i + 1)),
+            };
+            pipeline.commands.push(words);
+        }
+        if let Some(params) = def.get("params") {
+            let params = params.as_table().ok_or("`params` must be a table")?;
+            for (param, value) in params {
+                let value = value
+                    .as_str()
+                    .ok_or_else(|| format!("parameter `{}` must be a string", param))?;
+                pipeline.defaults.insert(param.clone(), value.to_owned());
+            }
+        }
+        Ok(pipeline)
+    }
+
+    /// Substitute the parameters in the pipeline's commands.  `args` are the `NAME=VALUE`
+    /// arguments of the invocation.  With `keep_unset`, references to parameters without a value
+    /// are left as they are, rather than being an error.
+    fn expand(&self, args: &[String], keep_unset: bool) -> Result<Vec<Vec<String>>, String> {
+        let mut params = self.defaults.clone();
+        for arg in args {
+            let mut parts = arg.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(param), Some(value)) => {
+                    params.insert(param.to_owned(), value.to_owned());
+                }
+                _ => return Err(format!("expected NAME=VALUE, got `{}`", arg)),
+            }
+        }
 
-/// Tracks known refactoring command builders, and allows invoking them by name.
+        let subst = |arg: &str| -> Result<String, String> {
+            let mut out = String::new();
+            let mut rest = arg;
+            while let Some(start) = rest.find("${") {
+                let end = match rest[start..].find('}') {
+                    Some(end) => start + end,
+                    None => break,
+                };
+                out.push_str(&rest[..start]);
+                let param = &rest[start + 2..end];
+                match params.get(param) {
+                    Some(value) => out.push_str(value),
+                    None if keep_unset => out.push_str(&rest[start..=end]),
+                    None => return Err(format!("parameter `{}` is not set", param)),
+                }
+                rest = &rest[end + 1..];
+            }
+            out.push_str(rest);
+            Ok(out)
+        };
+
+        self.commands
+            .iter()
+            .map(|cmd| cmd.iter().map(|arg| subst(arg)).collect::<Result<Vec<_>, _>>())
+            .collect()
+    }
+}
+
+/// Tracks known refactoring command builders, and allows invoking them by name.  Also tracks the
+/// guards available to `guard!` patterns, and named pipelines of commands.
 pub struct Registry {
-    commands: HashMap<String, Box<Builder>>,
+    commands: HashMap<String, CommandBuilder>,
+    guards: Arc<GuardMap>,
+    pipelines: HashMap<String, Pipeline>,
 }
 
 impl Registry {
     pub fn new() -> Registry {
         Registry {
             commands: HashMap::new(),
+            guards: Arc::new(HashMap::new()),
+            pipelines: HashMap::new(),
         }
     }
 
-    pub fn register<B>(&mut self, name: &str, builder: B)
+    /// Register a pipeline, which can then be run like a command named `name`.  Commands take
+    /// precedence over pipelines of the same name.
+    pub fn register_pipeline(&mut self, name: &str, pipeline: Pipeline) {
+        self.pipelines.insert(name.to_owned(), pipeline);
+    }
+
+    /// Register each pipeline defined in the TOML file at `path`.  See `Pipeline::from_toml` for
+    /// the format.
+    pub fn load_pipelines(&mut self, path: &Path) -> Result<(), String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let defs = text
+            .parse::<toml::Value>()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let defs = defs
+            .as_table()
+            .ok_or_else(|| format!("{}: expected a table of pipelines", path.display()))?;
+        for (name, def) in defs {
+            if self.commands.contains_key(name) {
+                return Err(format!(
+                    "{}: pipeline `{}` has the same name as a command",
+                    path.display(),
+                    name
+                ));
+            }
+            let pipeline = Pipeline::from_toml(def)
+                .map_err(|e| format!("{}: pipeline `{}`: {}", path.display(), name, e))?;
+            self.register_pipeline(name, pipeline);
+        }
+        Ok(())
+    }
+
+    /// Print every pipeline, along with the commands it expands to.  The members of nested
+    /// pipelines are listed below them.
+    pub fn print_pipelines(&self) {
+        fn print_commands(
+            reg: &Registry,
+            commands: &[Vec<String>],
+            indent: usize,
+            stack: &mut Vec<String>,
+        ) {
+            for (i, cmd) in commands.iter().enumerate() {
+                println!("{:indent$}{}. {}", "", i + 1, cmd.join(" "), indent = indent);
+                let name = &cmd[0];
+                if reg.commands.contains_key(name) || stack.contains(name) {
+                    continue;
+                }
+                if let Some(pipeline) = reg.pipelines.get(name) {
+                    if let Ok(inner) = pipeline.expand(&cmd[1..], true) {
+                        stack.push(name.clone());
+                        print_commands(reg, &inner, indent + 3, stack);
+                        stack.pop();
+                    }
+                }
+            }
+        }
+
+        let mut names = self.pipelines.keys().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            let pipeline = &self.pipelines[name];
+            let mut defaults = pipeline
+                .defaults
+                .iter()
+                .map(|(param, value)| format!("{}={}", param, value))
+                .collect::<Vec<_>>();
+            defaults.sort();
+            if defaults.is_empty() {
+                println!("{}", name);
+            } else {
+                println!("{} ({})", name, defaults.join(", "));
+            }
+            let commands = pipeline.expand(&[], true).unwrap();
+            print_commands(self, &commands, 2, &mut vec![name.clone()]);
+        }
+    }
+
+    /// Register a guard that patterns can invoke as `guard!(name, ...)`.
+    pub fn register_guard<G>(&mut self, name: &str, guard: G)
     where
-        B: FnMut(&[String]) -> Box<dyn Command> + 'static + Send,
+        G: Fn(&Expr, &MatchCtxt, &RefactorCtxt) -> bool + 'static + Send + Sync,
     {
-        self.commands.insert(name.to_owned(), Box::new(builder));
+        Arc::make_mut(&mut self.guards).insert(name.to_owned(), Arc::new(guard));
+    }
+
+    fn guards(&self) -> Arc<GuardMap> {
+        self.guards.clone()
     }
 
+    /// Register a command whose arguments are described by `spec`.  `get_command` checks the
+    /// arguments against it, so `builder` only gets valid ones.
+    pub fn register<B>(&mut self, name: &str, spec: CommandSpec, builder: B)
+    where
+        B: FnMut(&Args) -> Result<Box<dyn Command>, String> + 'static + Send,
+    {
+        let builder = Box::new(builder);
+        self.commands.insert(name.to_owned(), CommandBuilder { spec, builder });
+    }
+
+    /// Build the command `name` from `args`.  Invalid arguments are reported along with the
+    /// command's usage.
     pub fn get_command(&mut self, name: &str, args: &[String]) -> Result<Box<dyn Command>, String> {
-        let builder = match self.commands.get_mut(name) {
-            Some(command) => command,
+        let CommandBuilder { spec, builder } = match self.commands.get_mut(name) {
+            Some(cmd) => cmd,
             None => return Err(format!("Invalid command: {:#?}", name)),
         };
-        Ok(builder(args))
+        let args = spec.parse(args).map_err(|e| spec.error(name, &e))?;
+        builder(&args).map_err(|e| spec.error(name, &e))
+    }
+
+    /// Whether running the command or pipeline `name` can run `undo`, possibly through other
+    /// pipelines.
+    pub fn runs_undo(&self, name: &str) -> bool {
+        let mut pending = vec![name];
+        let mut seen = HashSet::new();
+        while let Some(name) = pending.pop() {
+            if name == "undo" {
+                return true;
+            }
+            if !seen.insert(name) || self.commands.contains_key(name) {
+                continue;
+            }
+            if let Some(pipeline) = self.pipelines.get(name) {
+                let names = pipeline.commands.iter().filter_map(|cmd| cmd.get(0));
+                pending.extend(names.map(|s| &s[..]));
+            }
+        }
+        false
+    }
+
+    /// The `--help NAME` text for a command or pipeline.
+    pub fn help(&self, name: &str) -> Result<String, String> {
+        if let Some(cmd) = self.commands.get(name) {
+            return Ok(cmd.spec.help(name));
+        }
+        match self.pipelines.get(name) {
+            Some(pipeline) => {
+                let mut help = format!("Usage: {} [NAME=VALUE...]\n\nPipeline of:\n", name);
+                for cmd in pipeline.expand(&[], true)? {
+                    help.push_str(&format!("  {}\n", cmd.join(" ")));
+                }
+                Ok(help)
+            }
+            None => Err(format!("no command or pipeline named `{}`", name)),
+        }
+    }
+
+    /// The argument specs of all commands, as JSON, for generating the command documentation.
+    pub fn specs_json(&self) -> JsonValue {
+        let mut specs = JsonValue::new_object();
+        let mut names = self.commands.keys().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            specs[&name[..]] = self.commands[name].spec.to_json(name);
+        }
+        specs
     }
 }
 
@@ -799,8 +1900,6 @@ where
 
 /// # `commit` Command
 ///
-/// Usage: `commit`
-///
 /// Write the current crate to disk (by rewriting the original source files), then
 /// read it back in, clearing all mark.  This can be useful as a "checkpoint"
 /// between two sets of transformations, if applying both sets of changes at once
@@ -809,13 +1908,15 @@ where
 /// This is only useful when the rewrite mode is `inplace`.  Otherwise the "write"
 /// part of the operation won't actually change the original source files, and the
 /// "read" part will revert the crate to its original form.
+///
+/// With `--git`, the rewritten files are also committed to git, with the commands run so far as
+/// the commit message, as long as the working tree was clean beforehand.
 fn register_commit(reg: &mut Registry) {
-    reg.register("commit", |args| {
-        let git_commit = match args.get(0) {
-            Some(arg) if arg == "git" => true,
-            _ => false,
-        };
-        Box::new(FuncCommand(move |rs: &mut RefactorState| {
+    let spec = CommandSpec::new("Write the crate to disk and read it back in.")
+        .flag("--git", "also commit the changes to git");
+    reg.register("commit", spec, |args| {
+        let git_commit = args.flag("--git");
+        Ok(Box::new(FuncCommand(move |rs: &mut RefactorState| {
             let clean = if git_commit {
                 let result = process::Command::new("git")
                     .arg("status")
@@ -863,31 +1964,90 @@ fn register_commit(reg: &mut Registry) {
 
             rs.load_crate();
             rs.clear_marks();
-        }))
+        })))
     });
 
-    reg.register("write", |_args| {
-        Box::new(FuncCommand(|rs: &mut RefactorState| {
+    let spec = CommandSpec::new("Write the crate to disk.");
+    reg.register("write", spec, |_args| {
+        Ok(Box::new(FuncCommand(|rs: &mut RefactorState| {
             rs.save_crate();
-        }))
+        })))
     });
 
-    reg.register("dump_crate", |_args| {
-        Box::new(FuncCommand(|rs: &mut RefactorState| {
+    let spec = CommandSpec::new("Print the AST of the crate, for debugging.");
+    reg.register("dump_crate", spec, |_args| {
+        Ok(Box::new(FuncCommand(|rs: &mut RefactorState| {
             rs.transform_crate(Phase::Phase2, |st, _cx| {
                 eprintln!("{:#?}", st.krate());
             }).unwrap();
-        }))
+        })))
     });
 
-    reg.register("noop", |_args| {
-        Box::new(FuncCommand(|rs: &mut RefactorState| {
+    let spec = CommandSpec::new("Run the compiler on the crate without changing it.");
+    reg.register("noop", spec, |_args| {
+        Ok(Box::new(FuncCommand(|rs: &mut RefactorState| {
             rs.transform_crate(Phase::Phase2, |_st, _cx| {
             }).unwrap();
-        }))
+        })))
+    });
+}
+
+/// # `checkpoint` Command
+///
+/// Save the current state of the crate, including its marks and all changes not yet written to
+/// disk, under `NAME`.  Use `revert NAME` to go back to it.
+///
+/// Reading the crate back in from disk (e.g. with `commit`) invalidates all checkpoints, and
+/// reverting to one afterward is an error.  Reverting past a `write` is fine: the next write
+/// rewrites the files with the restored state.
+///
+/// # `revert` Command
+///
+/// Restore the state saved by `checkpoint NAME`, undoing all commands run since then.  The
+/// checkpoint is kept, so it can be reverted to again.
+///
+/// # `undo` Command
+///
+/// Revert the most recent command that may have changed the crate or its marks, which includes
+/// every transform, even one that found nothing to rewrite.  Repeating `undo` goes further back,
+/// up to the last ten such commands (or as many as `--undo-limit` says), but never past the last
+/// time the crate was read from disk.  A pipeline is undone as a whole.
+///
+/// Saving the state for `undo` copies the whole crate before each command, so it's only done
+/// when one of the commands on the command line runs `undo`, directly or through a pipeline, in
+/// interactive mode, or after a Lua script calls `refactor:enable_undo()`.
+fn register_history(reg: &mut Registry) {
+    let spec = CommandSpec::new("Save the current state of the crate and its marks.")
+        .arg("NAME", ArgType::Str, "the name to save the state under");
+    reg.register("checkpoint", spec, |args| {
+        let name = args.str("NAME").to_owned();
+        Ok(Box::new(FuncCommand(move |rs: &mut RefactorState| {
+            rs.checkpoint(&name);
+        })))
+    });
+
+    let spec = CommandSpec::new("Restore the state saved by `checkpoint NAME`.")
+        .arg("NAME", ArgType::Str, "the name of the checkpoint");
+    reg.register("revert", spec, |args| {
+        let name = args.str("NAME").to_owned();
+        Ok(Box::new(FuncCommand(move |rs: &mut RefactorState| {
+            if let Err(e) = rs.revert(&name) {
+                rs.fail(e);
+            }
+        })))
+    });
+
+    let spec = CommandSpec::new("Revert the most recent command that changed the crate.");
+    reg.register("undo", spec, |_args| {
+        Ok(Box::new(FuncCommand(|rs: &mut RefactorState| {
+            if let Err(e) = rs.undo() {
+                rs.fail(e);
+            }
+        })))
     });
 }
 
 pub fn register_commands(reg: &mut Registry) {
     register_commit(reg);
+    register_history(reg);
 }
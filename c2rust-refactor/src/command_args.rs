@@ -0,0 +1,423 @@
+//! Declarative specs of the arguments a command takes.  Every command is registered with a spec,
+//! which checks and parses its arguments into an `Args` map before it's built, and produces its
+//! `--help COMMAND` text and its usage in the documentation.
+use json::JsonValue;
+use std::collections::HashMap;
+
+/// The type of an argument's value
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ArgType {
+    Str,
+    Int,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ParamKind {
+    /// Matched by position among the arguments that aren't flags or options
+    Positional,
+    /// A word that's either present or not, like `--strip-repr-c`
+    Flag,
+    /// A word followed by a value, like `-o FILE`
+    Opt,
+    /// All the positional arguments after the others
+    Rest,
+}
+
+#[derive(Clone, Debug)]
+struct Param {
+    name: String,
+    kind: ParamKind,
+    ty: ArgType,
+    /// The placeholder for an option's value in the usage line
+    value_name: String,
+    required: bool,
+    default: Option<String>,
+    help: String,
+}
+
+/// The value of one argument after parsing
+#[derive(Clone, Debug)]
+pub enum ArgValue {
+    Str(String),
+    Int(i64),
+    Flag(bool),
+    List(Vec<String>),
+}
+
+/// The arguments of one invocation of a command, keyed by the names in its `CommandSpec`.
+/// Arguments that were neither given nor have a default are absent.
+#[derive(Clone, Debug, Default)]
+pub struct Args {
+    values: HashMap<String, ArgValue>,
+}
+
+impl Args {
+    pub fn get(&self, name: &str) -> Option<&ArgValue> {
+        self.values.get(name)
+    }
+
+    pub fn opt_str(&self, name: &str) -> Option<&str> {
+        match self.values.get(name) {
+            Some(ArgValue::Str(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Get a string argument that's required or has a default.
+    pub fn str(&self, name: &str) -> &str {
+        self.opt_str(name)
+            .unwrap_or_else(|| panic!("no string argument `{}`", name))
+    }
+
+    pub fn opt_int(&self, name: &str) -> Option<i64> {
+        match self.values.get(name) {
+            Some(&ArgValue::Int(i)) => Some(i),
+            _ => None,
+        }
+    }
+
+    /// Get an integer argument that's required or has a default.
+    pub fn int(&self, name: &str) -> i64 {
+        self.opt_int(name)
+            .unwrap_or_else(|| panic!("no integer argument `{}`", name))
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        match self.values.get(name) {
+            Some(&ArgValue::Flag(b)) => b,
+            _ => false,
+        }
+    }
+
+    pub fn list(&self, name: &str) -> &[String] {
+        match self.values.get(name) {
+            Some(ArgValue::List(l)) => l,
+            _ => &[],
+        }
+    }
+}
+
+/// Describes the arguments of a command:
+///
+/// ```ignore
+/// CommandSpec::new("Rename the struct marked `target`")
+///     .arg("NAME", ArgType::Str, "the new name")
+///     .option("-o", "FILE", ArgType::Str, None, "where to write a report")
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CommandSpec {
+    summary: String,
+    params: Vec<Param>,
+}
+
+impl CommandSpec {
+    pub fn new(summary: &str) -> CommandSpec {
+        CommandSpec {
+            summary: summary.to_owned(),
+            params: Vec::new(),
+        }
+    }
+
+    fn param(mut self, name: &str, kind: ParamKind, ty: ArgType, help: &str) -> CommandSpec {
+        self.params.push(Param {
+            name: name.to_owned(),
+            kind,
+            ty,
+            value_name: String::new(),
+            required: false,
+            default: None,
+            help: help.to_owned(),
+        });
+        self
+    }
+
+    /// Add a required positional argument.
+    pub fn arg(self, name: &str, ty: ArgType, help: &str) -> CommandSpec {
+        let mut spec = self.param(name, ParamKind::Positional, ty, help);
+        spec.params.last_mut().unwrap().required = true;
+        spec
+    }
+
+    /// Add an optional positional argument.  It must come after the required ones.
+    pub fn optional_arg(
+        self,
+        name: &str,
+        ty: ArgType,
+        default: Option<&str>,
+        help: &str,
+    ) -> CommandSpec {
+        let mut spec = self.param(name, ParamKind::Positional, ty, help);
+        spec.params.last_mut().unwrap().default = default.map(|s| s.to_owned());
+        spec
+    }
+
+    /// Add a flag, which is set by passing the word `name` itself.  Its name must start with
+    /// `--`, so it can't be mistaken for a positional argument.
+    ///
+    /// Flags used to be bare words, like `fold_char_lits` for `--fold-char-lits`.  That spelling
+    /// still sets the flag, with a warning, where it can't be a positional argument instead.
+    pub fn flag(self, name: &str, help: &str) -> CommandSpec {
+        assert!(is_long_option(name), "flag `{}` must look like `--name`", name);
+        self.param(name, ParamKind::Flag, ArgType::Str, help)
+    }
+
+    /// Add an option, which is set by passing the word `name` followed by its value.
+    pub fn option(
+        self,
+        name: &str,
+        value_name: &str,
+        ty: ArgType,
+        default: Option<&str>,
+        help: &str,
+    ) -> CommandSpec {
+        let mut spec = self.param(name, ParamKind::Opt, ty, help);
+        let p = spec.params.last_mut().unwrap();
+        p.value_name = value_name.to_owned();
+        p.default = default.map(|s| s.to_owned());
+        spec
+    }
+
+    /// Collect all remaining positional arguments into a list named `name`.
+    pub fn rest(self, name: &str, help: &str) -> CommandSpec {
+        self.param(name, ParamKind::Rest, ArgType::Str, help)
+    }
+
+    /// Check `args` against the spec, and parse them.
+    pub fn parse(&self, args: &[String]) -> Result<Args, String> {
+        let mut values = HashMap::new();
+        let mut positionals = self
+            .params
+            .iter()
+            .filter(|p| p.kind == ParamKind::Positional)
+            .peekable();
+        let rest = self.params.iter().find(|p| p.kind == ParamKind::Rest);
+        let mut rest_values = Vec::new();
+
+        let mut i = 0;
+        while i < args.len() {
+            let arg = &args[i];
+            i += 1;
+            match self.params.iter().find(|p| p.name == *arg) {
+                Some(p) if p.kind == ParamKind::Flag => {
+                    values.insert(p.name.clone(), ArgValue::Flag(true));
+                    continue;
+                }
+                Some(p) if p.kind == ParamKind::Opt => {
+                    let value = args
+                        .get(i)
+                        .ok_or_else(|| format!("`{}` requires a {}", p.name, p.value_name))?;
+                    i += 1;
+                    values.insert(p.name.clone(), typed_value(p, value)?);
+                    continue;
+                }
+                _ => {}
+            }
+
+            // Positional arguments can start with `-` too, e.g. the pattern `-$e`, so only words
+            // like `--foo` are taken for misspelled options
+            if is_long_option(arg) {
+                return Err(format!("unknown option `{}`", arg));
+            }
+            let old_flag = self
+                .params
+                .iter()
+                .find(|p| p.kind == ParamKind::Flag && old_flag_spelling(&p.name) == *arg);
+            if let Some(flag) = old_flag {
+                match positionals.peek().cloned().or(rest) {
+                    Some(p) => warn!(
+                        "`{}` is taken as {}; pass `{}` to set the flag",
+                        arg, p.name, flag.name
+                    ),
+                    None => {
+                        warn!("`{}` is deprecated, pass `{}` instead", arg, flag.name);
+                        values.insert(flag.name.clone(), ArgValue::Flag(true));
+                        continue;
+                    }
+                }
+            }
+            if let Some(p) = positionals.next() {
+                values.insert(p.name.clone(), typed_value(p, arg)?);
+            } else if rest.is_some() {
+                rest_values.push(arg.clone());
+            } else {
+                return Err(format!("unexpected argument `{}`", arg));
+            }
+        }
+
+        for p in &self.params {
+            if values.contains_key(&p.name) {
+                continue;
+            }
+            if p.required {
+                return Err(format!("missing argument {}", p.name));
+            }
+            if let Some(ref default) = p.default {
+                values.insert(p.name.clone(), typed_value(p, default)?);
+            }
+        }
+        if let Some(p) = rest {
+            values.insert(p.name.clone(), ArgValue::List(rest_values));
+        }
+
+        Ok(Args { values })
+    }
+
+    /// The usage line of the command `name`, like `cmd NAME [FILTER] [-o FILE]`.
+    pub fn usage(&self, name: &str) -> String {
+        let mut usage = name.to_owned();
+        for p in &self.params {
+            usage.push(' ');
+            let word = match p.kind {
+                ParamKind::Positional | ParamKind::Flag => p.name.clone(),
+                ParamKind::Opt => format!("{} {}", p.name, p.value_name),
+                ParamKind::Rest => format!("{}...", p.name),
+            };
+            if p.required {
+                usage.push_str(&word);
+            } else {
+                usage.push_str(&format!("[{}]", word));
+            }
+        }
+        usage
+    }
+
+    /// The full help text of the command `name`: its usage, summary, and a description of each
+    /// argument.
+    pub fn help(&self, name: &str) -> String {
+        let mut help = format!("Usage: {}\n\n{}\n", self.usage(name), self.summary);
+        if !self.params.is_empty() {
+            help.push_str("\nArguments:\n");
+        }
+        for p in &self.params {
+            let mut line = format!("  {:<24} {}", param_label(p), p.help);
+            if let Some(ref default) = p.default {
+                line.push_str(&format!(" (default: {})", default));
+            }
+            help.push_str(line.trim_end());
+            help.push('\n');
+        }
+        help
+    }
+
+    /// Wrap `msg`, an error with the arguments given to the command `name`, with the command's
+    /// name and expected usage.
+    pub fn error(&self, name: &str, msg: &str) -> String {
+        format!("{}: {}\nusage: {}", name, msg, self.usage(name))
+    }
+
+    /// Describe the spec as JSON, for generating the command documentation.
+    pub fn to_json(&self, name: &str) -> JsonValue {
+        let params = self
+            .params
+            .iter()
+            .map(|p| {
+                object! {
+                    "label" => param_label(p),
+                    "required" => p.required,
+                    "default" => p.default.clone(),
+                    "help" => p.help.clone(),
+                }
+            })
+            .collect();
+        object! {
+            "usage" => self.usage(name),
+            "summary" => self.summary.clone(),
+            "args" => JsonValue::Array(params),
+        }
+    }
+}
+
+fn param_label(p: &Param) -> String {
+    match p.kind {
+        ParamKind::Opt => format!("{} {}", p.name, p.value_name),
+        ParamKind::Rest => format!("{}...", p.name),
+        _ => p.name.clone(),
+    }
+}
+
+fn is_long_option(arg: &str) -> bool {
+    arg.starts_with("--")
+        && arg[2..].starts_with(|c: char| c.is_ascii_alphabetic())
+        && arg[2..].chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// The bare word that used to set the flag `name`, e.g. `fold_char_lits` for `--fold-char-lits`
+fn old_flag_spelling(name: &str) -> String {
+    name[2..].replace('-', "_")
+}
+
+fn typed_value(p: &Param, value: &str) -> Result<ArgValue, String> {
+    match p.ty {
+        ArgType::Str => Ok(ArgValue::Str(value.to_owned())),
+        ArgType::Int => value.parse().map(ArgValue::Int).map_err(|_| {
+            format!("{} must be an integer, got `{}`", param_label(p), value)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec() -> CommandSpec {
+        CommandSpec::new("Do something")
+            .arg("NAME", ArgType::Str, "a name")
+            .optional_arg("COUNT", ArgType::Int, Some("1"), "how many")
+            .flag("--keep", "keep things")
+            .option("-o", "FILE", ArgType::Str, None, "a report")
+    }
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_args() {
+        let args = spec().parse(&strings(&["--keep", "foo", "-o", "out.json"])).unwrap();
+        assert_eq!(args.str("NAME"), "foo");
+        assert_eq!(args.int("COUNT"), 1);
+        assert!(args.flag("--keep"));
+        assert_eq!(args.opt_str("-o"), Some("out.json"));
+
+        let args = spec().parse(&strings(&["foo", "-3"])).unwrap();
+        assert_eq!(args.int("COUNT"), -3);
+        assert!(!args.flag("--keep"));
+        assert_eq!(args.opt_str("-o"), None);
+
+        let args = spec().parse(&strings(&["-$e"])).unwrap();
+        assert_eq!(args.str("NAME"), "-$e");
+    }
+
+    #[test]
+    fn parse_old_flags() {
+        // The bare word only sets the flag once there's no positional argument left to take it
+        let args = spec().parse(&strings(&["keep", "2"])).unwrap();
+        assert_eq!(args.str("NAME"), "keep");
+        assert_eq!(args.int("COUNT"), 2);
+        assert!(!args.flag("--keep"));
+
+        let args = spec().parse(&strings(&["foo", "2", "keep"])).unwrap();
+        assert!(args.flag("--keep"));
+
+        let spec = CommandSpec::new("").flag("--only-marked", "");
+        let args = spec.parse(&strings(&["only_marked"])).unwrap();
+        assert!(args.flag("--only-marked"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let parse = |args: &[&str]| spec().parse(&strings(args)).unwrap_err();
+        assert_eq!(parse(&[]), "missing argument NAME");
+        assert_eq!(parse(&["foo", "bar"]), "COUNT must be an integer, got `bar`");
+        assert_eq!(parse(&["foo", "1", "2"]), "unexpected argument `2`");
+        assert_eq!(parse(&["foo", "--kep"]), "unknown option `--kep`");
+        assert_eq!(parse(&["foo", "-o"]), "`-o` requires a FILE");
+    }
+
+    #[test]
+    fn usage() {
+        assert_eq!(spec().usage("cmd"), "cmd NAME [COUNT] [--keep] [-o FILE]");
+        let spec = CommandSpec::new("").rest("TYPE OLD NEW", "");
+        assert_eq!(spec.usage("cmd"), "cmd [TYPE OLD NEW...]");
+    }
+}
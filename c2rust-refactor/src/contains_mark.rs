@@ -1,4 +1,5 @@
-//! Helper for checking if a node or one of its descendants has a particular mark.
+//! Helpers for checking if a node or one of its descendants has a particular mark.
+use std::collections::HashSet;
 use syntax::ast::*;
 use syntax::symbol::Symbol;
 use syntax::visit::{self, Visitor};
@@ -53,3 +54,55 @@ where
     target.visit(&mut v);
     v.found
 }
+
+struct MarkedExprsVisitor<'a> {
+    st: &'a CommandState,
+    label: Symbol,
+    inside: bool,
+    ids: HashSet<NodeId>,
+}
+
+macro_rules! gen_scope_method {
+    ($name:ident (& $lt:tt $ArgTy:ty) -> $walk:ident) => {
+        fn $name(&mut self, x: & $lt $ArgTy) {
+            let was_inside = self.inside;
+            self.inside |= self.st.marked(x.id, self.label);
+            visit::$walk(self, x);
+            self.inside = was_inside;
+        }
+    };
+}
+
+impl<'a, 'ast> Visitor<'ast> for MarkedExprsVisitor<'a> {
+    fn visit_expr(&mut self, x: &'ast Expr) {
+        let was_inside = self.inside;
+        self.inside |= self.st.marked(x.id, self.label);
+        if self.inside {
+            self.ids.insert(x.id);
+        }
+        visit::walk_expr(self, x);
+        self.inside = was_inside;
+    }
+
+    gen_scope_method!(visit_stmt(&'ast Stmt) -> walk_stmt);
+    gen_scope_method!(visit_item(&'ast Item) -> walk_item);
+    gen_scope_method!(visit_impl_item(&'ast ImplItem) -> walk_impl_item);
+    gen_scope_method!(visit_trait_item(&'ast TraitItem) -> walk_trait_item);
+}
+
+/// Collect the IDs of all expressions that have a particular mark or are lexically inside an
+/// item, statement or expression that has it.
+pub fn marked_exprs<T, S>(target: &T, label: S, st: &CommandState) -> HashSet<NodeId>
+where
+    T: Visit,
+    S: IntoSymbol,
+{
+    let mut v = MarkedExprsVisitor {
+        st,
+        label: label.into_symbol(),
+        inside: false,
+        ids: HashSet::new(),
+    };
+    target.visit(&mut v);
+    v.ids
+}
@@ -638,6 +638,15 @@ pub fn parse_block(sess: &Session, src: &str) -> P<Block> {
     }
 }
 
+#[cfg_attr(feature = "profile", flame)]
+pub fn parse_attr(sess: &Session, src: &str) -> ast::Attribute {
+    let mut p = make_parser(sess, src);
+    match p.parse_attribute(false) {
+        Ok(attr) => attr,
+        Err(db) => emit_and_panic(db, "attr"),
+    }
+}
+
 fn parse_arg_inner<'a>(p: &mut Parser<'a>) -> PResult<'a, Param> {
     // `parse_arg` is private, so we make do with `parse_attribute`,
     // `parse_pat`, & `parse_ty`.
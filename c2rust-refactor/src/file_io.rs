@@ -1,6 +1,7 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
 use std::fs;
-use std::io;
+use std::io::{self, Write};
 use std::mem;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
@@ -12,6 +13,7 @@ use syntax::source_map::{Span, DUMMY_SP};
 use syntax::symbol::Symbol;
 use syntax_pos::hygiene::SyntaxContext;
 
+use crate::rewrite::files::FileEdits;
 use crate::rewrite::{self, TextRewrite};
 
 #[allow(unused_variables)]
@@ -31,6 +33,11 @@ pub trait FileIO {
         fs::canonicalize(path)
     }
 
+    /// Called once all rewriting is done.  Returns whether the rewrites changed any file.
+    fn finish(&self) -> io::Result<bool> {
+        Ok(false)
+    }
+
     fn read_file(&self, path: &Path) -> io::Result<String>;
     fn write_file(&self, path: &Path, s: &str) -> io::Result<()>;
     fn save_rewrites(
@@ -51,6 +58,19 @@ pub trait FileIO {
     ) -> io::Result<()> {
         Ok(())
     }
+
+    /// Whether `save_edits` should be called.  The commands and mark labels behind each edit
+    /// are only tracked if it is.
+    fn records_edits(&self) -> bool {
+        false
+    }
+
+    /// Called with the edits of each rewriting operation, before any `write_file`.  `base`
+    /// counts the times the crate has been loaded, as the edits apply to the text it was loaded
+    /// from.
+    fn save_edits(&self, base: usize, files: &[FileEdits]) -> io::Result<()> {
+        Ok(())
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -58,6 +78,8 @@ pub enum OutputMode {
     InPlace,
     Alongside,
     Print,
+    /// Collect the changes to every file, and write them as a single unified diff, from the
+    /// original text to the final one, once rewriting is done.
     PrintDiff,
     Json,
     Marks,
@@ -85,10 +107,22 @@ impl OutputMode {
     }
 }
 
+/// The text of a file before and after rewriting, for `OutputMode::PrintDiff`.
+struct FileDiff {
+    /// The path to show in the diff header.
+    path: PathBuf,
+    /// The text on disk before the first rewrite, or `None` if rewriting created the file.
+    old: Option<String>,
+    new: String,
+}
+
 struct RealState {
     rewrite_counter: usize,
     rewrites_json: Vec<JsonValue>,
     file_state: HashMap<PathBuf, String>,
+    /// Keyed on absolute paths.  A `BTreeMap` keeps the files in the diff in a stable order.
+    diffs: BTreeMap<PathBuf, FileDiff>,
+    workspace_edits: Vec<JsonValue>,
 }
 
 impl RealState {
@@ -97,12 +131,18 @@ impl RealState {
             rewrite_counter: 0,
             rewrites_json: Vec::new(),
             file_state: HashMap::new(),
+            diffs: BTreeMap::new(),
+            workspace_edits: Vec::new(),
         }
     }
 }
 
 pub struct RealFileIO {
     output_modes: Vec<OutputMode>,
+    /// Where `OutputMode::PrintDiff` writes the diff, instead of stdout.
+    diff_out: Option<PathBuf>,
+    /// Where to write the edits of every rewrite as JSON.
+    json_out: Option<PathBuf>,
     state: Mutex<RealState>,
 }
 
@@ -110,9 +150,34 @@ impl RealFileIO {
     pub fn new(modes: Vec<OutputMode>) -> RealFileIO {
         RealFileIO {
             output_modes: modes,
+            diff_out: None,
+            json_out: None,
             state: Mutex::new(RealState::new()),
         }
     }
+
+    pub fn with_diff_out(mut self, path: Option<PathBuf>) -> RealFileIO {
+        self.diff_out = path;
+        self
+    }
+
+    pub fn with_json_out(mut self, path: Option<PathBuf>) -> RealFileIO {
+        self.json_out = path;
+        self
+    }
+}
+
+/// Get the absolute path of `path`, which may name a file that doesn't exist yet, such as a new
+/// module.  In that case, only its directory is canonicalized.
+fn abs_path_of(path: &Path) -> io::Result<PathBuf> {
+    if path.is_relative() {
+        let parent_dir = Path::new(".").join(path.parent().unwrap());
+        let mut abs_path = fs::canonicalize(parent_dir)?;
+        abs_path.push(path.file_name().unwrap());
+        Ok(abs_path)
+    } else {
+        Ok(path.to_owned())
+    }
 }
 
 impl FileIO for RealFileIO {
@@ -134,6 +199,42 @@ impl FileIO for RealFileIO {
         Ok(())
     }
 
+    fn finish(&self) -> io::Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(ref path) = self.json_out {
+            // Earlier targets of the same run may have written their edits already.
+            let mut edits = match fs::read_to_string(path).map(|s| json::parse(&s)) {
+                Ok(Ok(JsonValue::Array(edits))) => edits,
+                _ => Vec::new(),
+            };
+            edits.extend(state.workspace_edits.drain(..));
+            fs::write(path, json::stringify_pretty(JsonValue::Array(edits), 2))?;
+        }
+
+        let changed = state.diffs.values()
+            .filter(|d| d.old.as_ref() != Some(&d.new))
+            .collect::<Vec<_>>();
+        if self.output_modes.contains(&OutputMode::PrintDiff) {
+            let mut out: Box<dyn Write> = match self.diff_out {
+                Some(ref path) => {
+                    Box::new(fs::OpenOptions::new().create(true).append(true).open(path)?)
+                }
+                None => Box::new(io::stdout()),
+            };
+            for d in &changed {
+                match d.old {
+                    Some(_) => writeln!(out, "--- a/{}", d.path.display())?,
+                    None => writeln!(out, "--- /dev/null")?,
+                }
+                writeln!(out, "+++ b/{}", d.path.display())?;
+                let old = d.old.as_ref().map_or("", |s| s);
+                rewrite::files::write_diff(&mut out, old, &d.new)?;
+            }
+            out.flush()?;
+        }
+        Ok(!changed.is_empty())
+    }
+
     fn read_file(&self, path: &Path) -> io::Result<String> {
         let state = self.state.lock().unwrap();
         let path = fs::canonicalize(path)?;
@@ -153,13 +254,7 @@ impl FileIO for RealFileIO {
                 OutputMode::Print => {
                     println!(" ==== {:?} ====\n{}\n =========", path, s);
                 }
-                OutputMode::PrintDiff => {
-                    let old_s = self.read_file(path)?;
-                    println!();
-                    println!("--- old/{}", path.display());
-                    println!("+++ new/{}", path.display());
-                    rewrite::files::print_diff(&old_s, s);
-                }
+                OutputMode::PrintDiff => {} // Handled in finish
                 OutputMode::Json => {}  // Handled in end_rewrite
                 OutputMode::Marks => {} // Handled in save_marks
             }
@@ -168,6 +263,19 @@ impl FileIO for RealFileIO {
         {
             let mut state = self.state.lock().unwrap();
 
+            if self.output_modes.contains(&OutputMode::PrintDiff) {
+                // Remember the text on disk the first time a file is written, which is before
+                // any `InPlace` write below.
+                let abs_path = abs_path_of(path)?;
+                let cwd = env::current_dir()?;
+                let diff = state.diffs.entry(abs_path.clone()).or_insert_with(|| FileDiff {
+                    path: abs_path.strip_prefix(&cwd).unwrap_or(&abs_path).to_owned(),
+                    old: fs::read_to_string(&abs_path).ok(),
+                    new: String::new(),
+                });
+                diff.new = s.to_owned();
+            }
+
             // Common handling
             for &mode in &self.output_modes {
                 if let Some(dest) = mode.write_dest(path) {
@@ -178,18 +286,8 @@ impl FileIO for RealFileIO {
 
             if !self.output_modes.iter().any(|&mode| mode.overwrites()) {
                 // None of the modes actually updated the original file, so we
-                // need to record the new content internally. If we're creating
-                // a new module, we can't canonicalize the filename itself
-                // (since it doesn't exist), so canonicalize its path and append
-                // the filename.
-                let abs_path = if path.is_relative() {
-                    let parent_dir = Path::new(".").join(path.parent().unwrap());
-                    let mut abs_path = fs::canonicalize(parent_dir)?;
-                    abs_path.push(path.file_name().unwrap());
-                    abs_path
-                } else {
-                    path.to_owned()
-                };
+                // need to record the new content internally.
+                let abs_path = abs_path_of(path)?;
                 state.file_state.insert(abs_path, s.to_owned());
             }
         }
@@ -232,6 +330,18 @@ impl FileIO for RealFileIO {
         Ok(())
     }
 
+    fn records_edits(&self) -> bool {
+        self.json_out.is_some()
+    }
+
+    fn save_edits(&self, base: usize, files: &[FileEdits]) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state
+            .workspace_edits
+            .push(rewrite::json::encode_file_edits(base, files));
+        Ok(())
+    }
+
     fn save_marks(
         &self,
         krate: &Crate,
@@ -189,15 +189,15 @@ impl InteractState {
 
             RunCommand { name, args } => {
                 info!("running command {} with args {:?}", name, args);
-                self.state.load_crate();
-                match self.state.run(&name, &args) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        eprintln!("{:?}", e);
-                        panic!("Invalid command.");
-                    }
-                }
+                // Reloading only after the buffers change keeps marks and `undo` snapshots
+                // around from one command to the next.
+                self.state.reload_crate_if_changed();
+                let result = self.state.run(&name, &args);
+                // Save even after an error, so the buffers show the state later commands see.
                 self.state.save_crate();
+                if let Err(text) = result {
+                    self.to_client.send(Error { text }).unwrap();
+                }
             }
 
             // Other messages are handled by the worker thread
@@ -252,7 +252,12 @@ fn collect_mark_infos(
     infos_vec
 }
 
-pub fn interact_command(args: &[String], config: Config, registry: command::Registry) {
+pub fn interact_command(
+    args: &[String],
+    config: Config,
+    registry: command::Registry,
+    undo_limit: usize,
+) {
     let (to_main, main_recv) = mpsc::channel();
     let (to_worker, worker_recv) = mpsc::sync_channel(1);
 
@@ -276,7 +281,9 @@ pub fn interact_command(args: &[String], config: Config, registry: command::Regi
         to_client: to_client.clone(),
     });
 
-    driver::run_refactoring(config, registry, file_io, HashSet::new(), |state| {
+    driver::run_refactoring(config, registry, file_io, HashSet::new(), |mut state| {
+        state.enable_undo();
+        state.set_undo_limit(undo_limit);
         InteractState::new(state, buffers_available, to_worker, to_client).run_loop(main_recv);
     });
 }
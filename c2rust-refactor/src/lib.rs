@@ -74,12 +74,14 @@ pub mod driver;
 pub mod node_map;
 
 pub mod command;
+pub mod command_args;
 pub mod file_io;
 pub mod interact;
 pub mod plugin;
 
 pub mod mark_adjust;
 pub mod print_spans;
+pub mod report;
 pub mod select;
 pub mod transform;
 
@@ -97,6 +99,7 @@ use std::sync::Arc;
 use syntax::ast::NodeId;
 
 use c2rust_ast_builder::IntoSymbol;
+use crate::file_io::FileIO;
 
 pub use crate::context::RefactorCtxt;
 
@@ -168,6 +171,24 @@ struct RustcArgs {
 
 pub struct Options {
     pub rewrite_modes: Vec<file_io::OutputMode>,
+    /// Only show the changes, and exit with status 2 if there are any.
+    pub dry_run: bool,
+    /// Where `OutputMode::PrintDiff` writes the diff, instead of stdout.
+    pub diff_out: Option<PathBuf>,
+    /// Where to write the edits of every rewrite as JSON, along with the commands and marks
+    /// behind each one.
+    pub json_out: Option<PathBuf>,
+    /// File to load named pipelines from.  `pipelines.toml` in the current directory is used if
+    /// it exists and this is `None`.
+    pub pipelines: Option<PathBuf>,
+    /// Print the available pipelines and their commands instead of refactoring.
+    pub list_pipelines: bool,
+    /// Print the help for this command instead of refactoring.
+    pub help_command: Option<String>,
+    /// Print the argument specs of all commands as JSON instead of refactoring.
+    pub dump_command_specs: bool,
+    /// How many commands in a row `undo` can revert.
+    pub undo_limit: usize,
     pub commands: Vec<Command>,
     pub rustc_args: RustcArgSource,
     pub cursors: Vec<Cursor>,
@@ -396,7 +417,58 @@ pub fn lib_main(opts: Options) -> interface::Result<()> {
     rustc_driver::catch_fatal_errors(move || main_impl(opts)).and_then(|x| x)
 }
 
+/// Create the registry of all commands and pipelines, including those from plugins and from the
+/// pipelines file at `pipelines`.
+fn make_registry(opts: &Options, pipelines: Option<&Path>) -> command::Registry {
+    let mut cmd_reg = command::Registry::new();
+    transform::register_commands(&mut cmd_reg);
+    mark_adjust::register_commands(&mut cmd_reg);
+    pick_node::register_commands(&mut cmd_reg);
+    print_spans::register_commands(&mut cmd_reg);
+    select::register_commands(&mut cmd_reg);
+    analysis::register_commands(&mut cmd_reg);
+    reflect::register_commands(&mut cmd_reg);
+    command::register_commands(&mut cmd_reg);
+
+    plugin::load_plugins(&opts.plugin_dirs, &opts.plugins, &mut cmd_reg);
+
+    if let Some(path) = pipelines {
+        if let Err(e) = cmd_reg.load_pipelines(path) {
+            eprintln!("Error loading pipelines: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    cmd_reg
+}
+
 fn main_impl(opts: Options) -> interface::Result<()> {
+    // Resolve the pipelines file before changing to each target's directory.
+    let pipelines = match opts.pipelines {
+        Some(ref path) => Some(path.clone()),
+        None => Some(PathBuf::from("pipelines.toml")).filter(|path| path.exists()),
+    };
+    let pipelines = pipelines.map(|path| env::current_dir().unwrap().join(path));
+    if opts.list_pipelines {
+        make_registry(&opts, pipelines.as_ref().map(|p| &**p)).print_pipelines();
+        return Ok(());
+    }
+    if let Some(ref name) = opts.help_command {
+        match make_registry(&opts, pipelines.as_ref().map(|p| &**p)).help(name) {
+            Ok(help) => print!("{}", help),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+    if opts.dump_command_specs {
+        let specs = make_registry(&opts, pipelines.as_ref().map(|p| &**p)).specs_json();
+        println!("{}", json::stringify_pretty(specs, 2));
+        return Ok(());
+    }
+
     if opts.commands.len() == 1 && opts.commands[0].name == "script" {
         // Validate script command ASAP to avoid running the compiler if the
         // script path is invalid.
@@ -410,6 +482,17 @@ fn main_impl(opts: Options) -> interface::Result<()> {
         warn!("Could not derive any rustc invocations for refactoring");
     }
     let multiple_refactorings = target_args.len() > 1;
+    if let Some(ref path) = opts.diff_out {
+        // Each target appends its own changes.
+        std::fs::File::create(path).expect("Error creating diff output file");
+    }
+    if let Some(ref path) = opts.json_out {
+        // Each target adds its edits to the ones already in the file.
+        if path.exists() {
+            std::fs::remove_file(path).expect("Error removing old JSON output file");
+        }
+    }
+    let mut changed = false;
     for rustc_args in target_args {
         let mut marks = HashSet::new();
         for m in &opts.marks {
@@ -471,32 +554,32 @@ fn main_impl(opts: Options) -> interface::Result<()> {
             });
         }
 
-        let mut cmd_reg = command::Registry::new();
-        transform::register_commands(&mut cmd_reg);
-        mark_adjust::register_commands(&mut cmd_reg);
-        pick_node::register_commands(&mut cmd_reg);
-        print_spans::register_commands(&mut cmd_reg);
-        select::register_commands(&mut cmd_reg);
-        analysis::register_commands(&mut cmd_reg);
-        reflect::register_commands(&mut cmd_reg);
-        command::register_commands(&mut cmd_reg);
-
-        plugin::load_plugins(&opts.plugin_dirs, &opts.plugins, &mut cmd_reg);
+        let cmd_reg = make_registry(&opts, pipelines.as_ref().map(|p| &**p));
 
         let config = driver::create_config(&rustc_args.args);
+        let file_io = Arc::new(
+            file_io::RealFileIO::new(opts.rewrite_modes.clone())
+                .with_diff_out(opts.diff_out.clone())
+                .with_json_out(opts.json_out.clone()),
+        );
 
         if opts.commands.len() == 1 && opts.commands[0].name == "interact" {
-            interact::interact_command(&opts.commands[0].args, config, cmd_reg);
+            interact::interact_command(&opts.commands[0].args, config, cmd_reg, opts.undo_limit);
         } else if opts.commands.len() == 1 && opts.commands[0].name == "script" {
             scripting::run_lua_file(
                 Path::new(&opts.commands[0].args[0]),
                 config,
                 cmd_reg,
-                opts.rewrite_modes.clone(),
+                file_io.clone(),
+                opts.undo_limit,
             ).expect("Error loading user script");
         } else {
-            let file_io = Arc::new(file_io::RealFileIO::new(opts.rewrite_modes.clone()));
-            driver::run_refactoring(config, cmd_reg, file_io, marks, |mut state| {
+            let uses_undo = opts.commands.iter().any(|cmd| cmd_reg.runs_undo(&cmd.name));
+            driver::run_refactoring(config, cmd_reg, file_io.clone(), marks, |mut state| {
+                if uses_undo {
+                    state.enable_undo();
+                    state.set_undo_limit(opts.undo_limit);
+                }
                 for cmd in opts.commands.clone() {
                     if &cmd.name == "interact" {
                         panic!("`interact` must be the only command");
@@ -504,7 +587,7 @@ fn main_impl(opts: Options) -> interface::Result<()> {
                         match state.run(&cmd.name, &cmd.args) {
                             Ok(_) => {}
                             Err(e) => {
-                                eprintln!("{:?}", e);
+                                eprintln!("{}", e);
                                 std::process::exit(1);
                             }
                         }
@@ -514,6 +597,7 @@ fn main_impl(opts: Options) -> interface::Result<()> {
                 state.save_crate();
             });
         }
+        changed |= file_io.finish().expect("Error writing diff");
 
         // We need to rebuild the crate metadata if this was a library and we
         // are refactoring binaries that may depend on it.
@@ -526,6 +610,10 @@ fn main_impl(opts: Options) -> interface::Result<()> {
 
     dump_profile();
 
+    if opts.dry_run && changed {
+        std::process::exit(2);
+    }
+
     Ok(())
 }
 
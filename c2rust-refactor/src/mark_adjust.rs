@@ -2,7 +2,6 @@
 use rustc::hir;
 use rustc::hir::def::{DefKind, Res};
 use rustc::ty::TyKind;
-use std::str::FromStr;
 use syntax::ast;
 use syntax::ast::*;
 use syntax::symbol::Symbol;
@@ -11,6 +10,7 @@ use syntax::visit::{self, Visitor};
 use crate::ast_manip::{visit_nodes, Visit};
 use crate::command::CommandState;
 use crate::command::{DriverCommand, FuncCommand, RefactorState, Registry};
+use crate::command_args::{ArgType, CommandSpec};
 use crate::driver::Phase;
 use crate::RefactorCtxt;
 use c2rust_ast_builder::IntoSymbol;
@@ -163,8 +163,6 @@ pub fn find_mark_uses<T: Visit>(target: &T, st: &CommandState, cx: &RefactorCtxt
 
 /// # `mark_uses` Command
 ///
-/// Usage: `mark_uses MARK`
-///
 /// Marks: reads `MARK`; sets/clears `MARK`
 ///
 /// For every top-level definition bearing `MARK`, apply `MARK` to uses of that
@@ -225,8 +223,6 @@ pub fn find_field_uses<T: Visit>(
 ///
 /// Obsolete - use `select` with `match_expr!(typed!(::TheStruct).field)` instead
 ///
-/// Usage: `mark_field_uses FIELD MARK`
-///
 /// Marks: reads `MARK`; sets/clears `MARK`
 ///
 /// For every struct definition bearing `MARK`, apply `MARK` to expressions
@@ -273,8 +269,6 @@ pub fn find_arg_uses<T: Visit>(
 
 /// # `mark_arg_uses` Command
 ///
-/// Usage: `mark_arg_uses ARG_IDX MARK`
-///
 /// Marks: reads `MARK`; sets/clears `MARK`
 ///
 /// For every `fn` definition bearing `MARK`, apply `MARK` to expressions
@@ -311,8 +305,6 @@ pub fn find_callers<T: Visit>(target: &T, st: &CommandState, cx: &RefactorCtxt,
 
 /// # `mark_callers` Command
 ///
-/// Usage: `mark_callers MARK`
-///
 /// Marks: reads `MARK`; sets/clears `MARK`
 ///
 /// For every `fn` definition bearing `MARK`, apply `MARK` to call
@@ -324,8 +316,6 @@ pub fn find_callers_command(st: &CommandState, cx: &RefactorCtxt, label: &str) {
 
 /// # `copy_marks` Command
 ///
-/// Usage: `copy_marks OLD_MARK NEW_MARK`
-///
 /// Marks: reads `OLD_MARK`; sets `NEW_MARK`
 ///
 /// For every node bearing `OLD_MARK`, also apply `NEW_MARK`.
@@ -343,8 +333,6 @@ pub fn copy_marks(st: &CommandState, old: Symbol, new: Symbol) {
 
 /// # `delete_marks` Command
 ///
-/// Usage: `delete_marks MARK`
-///
 /// Marks: clears `MARK`
 ///
 /// Remove `MARK` from every node where it appears.
@@ -355,8 +343,6 @@ pub fn delete_marks(st: &CommandState, old: Symbol) {
 
 /// # `rename_marks` Command
 ///
-/// Usage: `rename_marks OLD_MARK NEW_MARK`
-///
 /// Marks: reads/clears `OLD_MARK`; sets `NEW_MARK`
 ///
 /// For every node bearing `OLD_MARK`, remove `OLD_MARK` and apply `NEW_MARK`.
@@ -369,8 +355,6 @@ pub fn rename_marks(st: &CommandState, old: Symbol, new: Symbol) {
 ///
 /// Obsolete - use `select` instead.
 ///
-/// Usage: `mark_pub_in_mod MARK`
-///
 /// Marks: reads `MARK`; sets `MARK`
 ///
 /// In each `mod` bearing `MARK`, apply `MARK` to every public item in the module.
@@ -404,8 +388,6 @@ pub fn mark_pub_in_mod(st: &CommandState, label: &str) {
 ///
 /// Test command - not intended for general use.
 ///
-/// Usage: `print_marks`
-///
 /// Marks: reads all
 ///
 /// Logs the ID and label of every mark, at level `info`.
@@ -420,84 +402,108 @@ fn print_marks(st: &CommandState) {
 
 /// # `clear_marks` Command
 ///
-/// Usage: `clear_marks`
-///
 /// Marks: clears all marks
 ///
 /// Remove all marks from all nodes.
 fn register_clear_marks(reg: &mut Registry) {
-    reg.register("clear_marks", |_args| {
-        Box::new(FuncCommand(|rs: &mut RefactorState| {
+    let spec = CommandSpec::new("Remove all marks from all nodes.");
+    reg.register("clear_marks", spec, |_args| {
+        Ok(Box::new(FuncCommand(|rs: &mut RefactorState| {
             rs.clear_marks();
-        }))
+        })))
     });
 }
 
 pub fn register_commands(reg: &mut Registry) {
-    reg.register("print_marks", |_| {
-        Box::new(DriverCommand::new(Phase::Phase2, move |st, _cx| {
+    let spec = CommandSpec::new("Log the ID and label of every mark.");
+    reg.register("print_marks", spec, |_args| {
+        Ok(Box::new(DriverCommand::new(Phase::Phase2, move |st, _cx| {
             print_marks(st);
-        }))
+        })))
     });
 
-    reg.register("mark_uses", |args| {
-        let arg = args[0].clone();
-        Box::new(DriverCommand::new(Phase::Phase2, move |st, cx| {
+    let spec = CommandSpec::new("Mark the uses of each definition marked MARK.")
+        .arg("MARK", ArgType::Str, "the mark to move from definitions to their uses");
+    reg.register("mark_uses", spec, |args| {
+        let arg = args.str("MARK").to_owned();
+        Ok(Box::new(DriverCommand::new(Phase::Phase2, move |st, cx| {
             find_mark_uses_command(st, cx, &arg);
-        }))
+        })))
     });
 
-    reg.register("mark_field_uses", |args| {
-        let field = args[0].clone();
-        let label = args[1].clone();
-        Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+    let spec = CommandSpec::new("Mark the uses of FIELD of each struct marked MARK.")
+        .arg("FIELD", ArgType::Str, "the name of the field")
+        .arg("MARK", ArgType::Str, "the mark to move from structs to the field uses");
+    reg.register("mark_field_uses", spec, |args| {
+        let field = args.str("FIELD").to_owned();
+        let label = args.str("MARK").to_owned();
+        Ok(Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
             find_field_uses_command(st, cx, &field, &label);
-        }))
+        })))
     });
 
-    reg.register("mark_arg_uses", |args| {
-        let arg_idx = usize::from_str(&args[0]).unwrap();
-        let label = args[1].clone();
-        Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+    let spec = CommandSpec::new("Mark argument ARG_IDX of each call to a function marked MARK.")
+        .arg("ARG_IDX", ArgType::Int, "the index of the argument, starting at 0")
+        .arg("MARK", ArgType::Str, "the mark to move from functions to the arguments");
+    reg.register("mark_arg_uses", spec, |args| {
+        let arg_idx = args.int("ARG_IDX");
+        if arg_idx < 0 {
+            return Err(format!("ARG_IDX must not be negative, got {}", arg_idx));
+        }
+        let arg_idx = arg_idx as usize;
+        let label = args.str("MARK").to_owned();
+        Ok(Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
             find_arg_uses_command(st, cx, arg_idx, &label);
-        }))
+        })))
     });
 
-    reg.register("mark_callers", |args| {
-        let label = args[0].clone();
-        Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+    let spec = CommandSpec::new("Mark the calls to each function marked MARK.")
+        .arg("MARK", ArgType::Str, "the mark to move from functions to their calls");
+    reg.register("mark_callers", spec, |args| {
+        let label = args.str("MARK").to_owned();
+        Ok(Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
             find_callers_command(st, cx, &label);
-        }))
+        })))
     });
 
-    reg.register("copy_marks", |args| {
-        let old = (&args[0]).into_symbol();
-        let new = (&args[1]).into_symbol();
-        Box::new(DriverCommand::new(Phase::Phase2, move |st, _cx| {
+    let spec = CommandSpec::new("Also apply NEW_MARK to every node marked OLD_MARK.")
+        .arg("OLD_MARK", ArgType::Str, "the mark to copy")
+        .arg("NEW_MARK", ArgType::Str, "the mark to apply");
+    reg.register("copy_marks", spec, |args| {
+        let old = args.str("OLD_MARK").into_symbol();
+        let new = args.str("NEW_MARK").into_symbol();
+        Ok(Box::new(DriverCommand::new(Phase::Phase2, move |st, _cx| {
             copy_marks(st, old, new);
-        }))
+        })))
     });
 
-    reg.register("delete_marks", |args| {
-        let old = (&args[0]).into_symbol();
-        Box::new(DriverCommand::new(Phase::Phase2, move |st, _cx| {
+    let spec = CommandSpec::new("Remove MARK from every node.")
+        .arg("MARK", ArgType::Str, "the mark to remove");
+    reg.register("delete_marks", spec, |args| {
+        let old = args.str("MARK").into_symbol();
+        Ok(Box::new(DriverCommand::new(Phase::Phase2, move |st, _cx| {
             delete_marks(st, old);
-        }))
+        })))
     });
 
-    reg.register("rename_marks", |args| {
-        let old = (&args[0]).into_symbol();
-        let new = (&args[1]).into_symbol();
-        Box::new(DriverCommand::new(Phase::Phase2, move |st, _cx| {
+    let spec = CommandSpec::new("Move the mark OLD_MARK of every node to NEW_MARK.")
+        .arg("OLD_MARK", ArgType::Str, "the mark to remove")
+        .arg("NEW_MARK", ArgType::Str, "the mark to apply in its place");
+    reg.register("rename_marks", spec, |args| {
+        let old = args.str("OLD_MARK").into_symbol();
+        let new = args.str("NEW_MARK").into_symbol();
+        Ok(Box::new(DriverCommand::new(Phase::Phase2, move |st, _cx| {
             rename_marks(st, old, new);
-        }))
+        })))
     });
 
-    reg.register("mark_pub_in_mod", |args| {
-        let label = args[0].clone();
-        Box::new(DriverCommand::new(Phase::Phase2, move |st, _cx| {
+    let spec = CommandSpec::new("Mark the public items of each module marked MARK.")
+        .arg("MARK", ArgType::Str, "the mark to apply");
+    reg.register("mark_pub_in_mod", spec, |args| {
+        let label = args.str("MARK").to_owned();
+        Ok(Box::new(DriverCommand::new(Phase::Phase2, move |st, _cx| {
             mark_pub_in_mod(st, &label);
-        }))
+        })))
     });
 
     register_clear_marks(reg);
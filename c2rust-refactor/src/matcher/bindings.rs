@@ -1,14 +1,17 @@
 //! The `Bindings` type, for mapping names to AST fragments.
 use std::collections::hash_map::{Entry, HashMap};
+use std::collections::HashSet;
+use std::cmp;
 use std::convert::{TryFrom, TryInto};
 
 use derive_more::{From, TryInto};
-use syntax::ast::{Expr, Ident, Item, Lit, Pat, Path, Stmt, Ty};
-use syntax::token::{Token, TokenKind, LitKind as TokenLitKind};
+use syntax::ast::{Attribute, Expr, ExprKind, FnHeader, Ident, Item, Lit, LitKind, Pat, Path, Stmt};
+use syntax::ast::{Ty, UnOp};
+use syntax::token::{BinOpToken, DelimToken, Token, TokenKind, LitKind as TokenLitKind};
 use syntax::ptr::P;
 use syntax::source_map::DUMMY_SP;
-use syntax::symbol::Symbol;
-use syntax::tokenstream::{Cursor, TokenStream, TokenStreamBuilder, TokenTree};
+use syntax::symbol::{kw, Symbol};
+use syntax::tokenstream::{Cursor, DelimSpan, TokenStream, TokenStreamBuilder, TokenTree};
 
 use crate::ast_manip::AstEquiv;
 use c2rust_ast_builder::IntoSymbol;
@@ -17,12 +20,18 @@ use c2rust_ast_builder::IntoSymbol;
 #[derive(Clone, Debug)]
 pub struct BindingTypes {
     types: HashMap<Symbol, Type>,
+    /// Repetition bindings that must match at least one node, like `$x:Expr+`.
+    nonempty: HashSet<Symbol>,
+    /// Constraints on the values of literal bindings, like `$n:LitInt(..=4096)`.
+    lit_constraints: HashMap<Symbol, LitConstraint>,
 }
 
 impl BindingTypes {
     pub fn new() -> BindingTypes {
         BindingTypes {
             types: HashMap::new(),
+            nonempty: HashSet::new(),
+            lit_constraints: HashMap::new(),
         }
     }
 
@@ -30,6 +39,26 @@ impl BindingTypes {
         self.types.get(name)
     }
 
+    /// Require the repetition binding `name` to match at least one node.
+    pub fn set_nonempty<S: IntoSymbol>(&mut self, name: S) {
+        self.nonempty.insert(name.into_symbol());
+    }
+
+    /// Check whether the repetition binding `name` must match at least one node.
+    pub fn is_nonempty(&self, name: &Symbol) -> bool {
+        self.nonempty.contains(name)
+    }
+
+    /// Restrict the binding `name` to literals satisfying `constraint`.
+    pub fn set_lit_constraint<S: IntoSymbol>(&mut self, name: S, constraint: LitConstraint) {
+        self.lit_constraints.insert(name.into_symbol(), constraint);
+    }
+
+    /// Get the literal constraint on the binding `name`, if it has one.
+    pub fn lit_constraint(&self, name: &Symbol) -> Option<&LitConstraint> {
+        self.lit_constraints.get(name)
+    }
+
     /// Set the type for a name, so that the name matches (and captures) only nodes of the
     /// appropriate typ.
     pub fn set_type<S: IntoSymbol>(&mut self, name: S, ty: Type) {
@@ -59,6 +88,61 @@ impl BindingTypes {
         for (name, ty) in other.types.into_iter() {
             self.set_type(name, ty);
         }
+        self.nonempty.extend(other.nonempty);
+        self.lit_constraints.extend(other.lit_constraints);
+    }
+}
+
+/// A constraint on the value of a literal binding, from binding types like `$n:LitInt(0..8)` or
+/// `$s:LitStr("fmt:"..)`.
+#[derive(Clone, Debug)]
+pub enum LitConstraint {
+    /// An integer literal, possibly negated, in the inclusive range `lo ..= hi`.  Either end may
+    /// be missing.
+    Int(Option<i128>, Option<i128>),
+    /// A string literal equal to the given string.
+    StrEq(String),
+    /// A string literal starting with the given string.
+    StrPrefix(String),
+}
+
+impl LitConstraint {
+    /// Check whether the expression `e` is a literal that satisfies the constraint.
+    pub fn matches(&self, e: &Expr) -> bool {
+        match *self {
+            LitConstraint::Int(lo, hi) => {
+                let v = match int_lit_value(e) {
+                    Some(v) => v,
+                    None => return false,
+                };
+                lo.map_or(true, |lo| lo <= v) && hi.map_or(true, |hi| v <= hi)
+            }
+            LitConstraint::StrEq(ref s) => {
+                str_lit_value(e).map_or(false, |v| &*v.as_str() == s.as_str())
+            }
+            LitConstraint::StrPrefix(ref s) => {
+                str_lit_value(e).map_or(false, |v| v.as_str().starts_with(s.as_str()))
+            }
+        }
+    }
+}
+
+/// Get the value of an integer literal expression, like `4096` or `-1i32`.  Values too large
+/// for an `i128` are clamped, so they only satisfy ranges with no upper bound.
+fn int_lit_value(e: &Expr) -> Option<i128> {
+    match e.kind {
+        ExprKind::Lit(Lit { kind: LitKind::Int(v, _), .. }) => {
+            Some(cmp::min(v, i128::max_value() as u128) as i128)
+        }
+        ExprKind::Unary(UnOp::Neg, ref inner) => int_lit_value(inner).map(|v| -v),
+        _ => None,
+    }
+}
+
+fn str_lit_value(e: &Expr) -> Option<Symbol> {
+    match e.kind {
+        ExprKind::Lit(Lit { kind: LitKind::Str(s, _), .. }) => Some(s),
+        _ => None,
     }
 }
 
@@ -66,12 +150,15 @@ impl BindingTypes {
 #[derive(Clone, Debug)]
 pub struct Bindings {
     map: HashMap<Symbol, Value>,
+    /// Names mentioned inside `not!` patterns, which never get bound.
+    negated: HashSet<Symbol>,
 }
 
 impl Bindings {
     pub fn new() -> Bindings {
         Bindings {
             map: HashMap::new(),
+            negated: HashSet::new(),
         }
     }
 
@@ -128,6 +215,37 @@ impl Bindings {
         self.map.get(&name).and_then(|v| v.try_into().ok())
     }
 
+    /// Check whether `name` is bound to a value, as opposed to being unbound or bound as
+    /// absent, e.g., because it only appears in an optional group that didn't match.
+    pub fn is_present<S: IntoSymbol>(&self, name: S) -> bool {
+        match self.map.get(&name.into_symbol()) {
+            Some(Value::Optional(None)) | None => false,
+            Some(_) => true,
+        }
+    }
+
+    /// Record that `name` appeared inside a `not!` pattern.  Has no effect if `name` is bound.
+    pub fn add_negated<S: IntoSymbol>(&mut self, name: S) {
+        let name = name.into_symbol();
+        if !self.map.contains_key(&name) {
+            self.negated.insert(name);
+        }
+    }
+
+    /// Check whether `name` is unbound because it only appeared inside `not!` patterns.
+    pub fn is_negated<S: IntoSymbol>(&self, name: S) -> bool {
+        let name = name.into_symbol();
+        self.negated.contains(&name) && !self.map.contains_key(&name)
+    }
+
+    /// Check whether `name` is bound as absent.
+    pub fn is_absent<S: IntoSymbol>(&self, name: S) -> bool {
+        match self.map.get(&name.into_symbol()) {
+            Some(Value::Optional(None)) => true,
+            _ => false,
+        }
+    }
+
     pub fn get_opt<'a, S, T>(&'a self, name: S) -> Option<Option<&'a T>>
     where
         S: IntoSymbol,
@@ -275,9 +393,12 @@ define_binding_values! {
     Expr(P<Expr>),
     Pat(P<Pat>),
     Ty(P<Ty>),
+    MultiExpr(Vec<P<Expr>>),
     Stmt(Stmt),
     MultiStmt(Vec<Stmt>),
-    Item(P<Item>)
+    Item(P<Item>),
+    Attr(Attribute),
+    FnQual(FnHeader)
 }
 
 impl Type {
@@ -319,17 +440,204 @@ fn maybe_get_type(c: &mut Cursor) -> Type {
     Type::Unknown
 }
 
+/// Parse a literal binding type, like the `:LitInt(0..8)` in `$k:LitInt(0..8)`.  The
+/// parenthesized constraint is optional.
+fn maybe_get_lit_constraint(c: &mut Cursor) -> Option<LitConstraint> {
+    match c.look_ahead(0) {
+        Some(TokenTree::Token(Token{kind: TokenKind::Colon, ..})) => {}
+        _ => return None,
+    }
+    let kind = match c.look_ahead(1) {
+        Some(TokenTree::Token(Token{kind: TokenKind::Ident(name, _), ..}))
+            if name.as_str() == "LitInt" || name.as_str() == "LitStr" => name,
+        _ => return None,
+    };
+    c.nth(1);
+    let tts = match c.look_ahead(0) {
+        Some(TokenTree::Delimited(_, DelimToken::Paren, tts)) => {
+            c.next();
+            tts
+        }
+        _ => TokenStream::default(),
+    };
+
+    let toks = tts
+        .into_trees()
+        .map(|tt| match tt {
+            TokenTree::Token(t) => t.kind,
+            TokenTree::Delimited(..) => panic!("bad literal constraint for {}", kind),
+        })
+        .collect::<Vec<_>>();
+    let bad = || -> ! { panic!("bad literal constraint for {}: {:?}", kind, toks) };
+
+    if kind.as_str() == "LitStr" {
+        let str_value = |tok: &TokenKind| match *tok {
+            TokenKind::Literal(lit) => match Lit::from_lit_token(lit, DUMMY_SP) {
+                Ok(Lit { kind: LitKind::Str(s, _), .. }) => s.to_string(),
+                _ => bad(),
+            },
+            _ => bad(),
+        };
+        return Some(match toks.as_slice() {
+            [] => LitConstraint::StrPrefix(String::new()),
+            [tok] => LitConstraint::StrEq(str_value(tok)),
+            [tok, TokenKind::DotDot] => LitConstraint::StrPrefix(str_value(tok)),
+            _ => bad(),
+        });
+    }
+
+    // Parse an optionally negated integer at `toks[*i]`.
+    let int_value = |i: &mut usize| -> Option<i128> {
+        let neg = match toks.get(*i) {
+            Some(TokenKind::BinOp(BinOpToken::Minus)) => {
+                *i += 1;
+                true
+            }
+            _ => false,
+        };
+        let v = match toks.get(*i) {
+            Some(&TokenKind::Literal(lit)) => match Lit::from_lit_token(lit, DUMMY_SP) {
+                Ok(Lit { kind: LitKind::Int(v, _), .. }) => v as i128,
+                _ => bad(),
+            },
+            _ if neg => bad(),
+            _ => return None,
+        };
+        *i += 1;
+        Some(if neg { -v } else { v })
+    };
+
+    if toks.is_empty() {
+        return Some(LitConstraint::Int(None, None));
+    }
+    let mut i = 0;
+    let lo = int_value(&mut i);
+    let (lo, hi) = match toks.get(i) {
+        None => (lo, lo),
+        Some(TokenKind::DotDot) => {
+            i += 1;
+            (lo, int_value(&mut i).map(|hi| hi - 1))
+        }
+        Some(TokenKind::DotDotEq) => {
+            i += 1;
+            (lo, Some(int_value(&mut i).unwrap_or_else(|| bad())))
+        }
+        _ => bad(),
+    };
+    if i != toks.len() {
+        bad();
+    }
+    Some(LitConstraint::Int(lo, hi))
+}
+
+/// Parse the `*` or `+` following a typed binding like `$args:Expr*`, returning `Some(true)` if
+/// the repetition needs to match at least one node.  The repetition has to end a list element,
+/// so `$x:Expr * 2` is still a multiplication.
+fn maybe_get_repetition(c: &mut Cursor) -> Option<bool> {
+    let nonempty = match c.look_ahead(0) {
+        Some(TokenTree::Token(Token{kind: TokenKind::BinOp(BinOpToken::Star), ..})) => false,
+        Some(TokenTree::Token(Token{kind: TokenKind::BinOp(BinOpToken::Plus), ..})) => true,
+        _ => return None,
+    };
+    match c.look_ahead(1) {
+        None |
+        Some(TokenTree::Token(Token{kind: TokenKind::Comma, ..})) |
+        Some(TokenTree::Token(Token{kind: TokenKind::Semi, ..})) => {}
+        // The start of an optional group, e.g., `$xs:Expr* $(, $last:Expr)?`
+        Some(TokenTree::Token(Token{kind: TokenKind::Dollar, ..})) => match c.look_ahead(2) {
+            Some(TokenTree::Delimited(_, DelimToken::Paren, _)) => {}
+            _ => return None,
+        },
+        _ => return None,
+    }
+    c.next();
+    Some(nonempty)
+}
+
+/// Check whether the cursor is at the `(...)?` part of an optional group like `$(, $x:Expr)?`.
+fn is_optional_group(c: &Cursor) -> bool {
+    match (c.look_ahead(0), c.look_ahead(1)) {
+        (Some(TokenTree::Delimited(_, DelimToken::Paren, _)),
+         Some(TokenTree::Token(Token{kind: TokenKind::Question, ..}))) => true,
+        _ => false,
+    }
+}
+
+/// Lower the optional group `$(tokens)?` into the list element `optional!(tokens)`, moving a
+/// leading or trailing comma out of the group so the result still parses as a list.
+fn rewrite_optional_group(tts: TokenStream, span: DelimSpan, tsb: &mut TokenStreamBuilder) {
+    let mut tts = tts.into_trees().collect::<Vec<_>>();
+    let is_comma = |tt: Option<&TokenTree>| match tt {
+        Some(TokenTree::Token(Token{kind: TokenKind::Comma, ..})) => true,
+        _ => false,
+    };
+    let leading_comma = if is_comma(tts.first()) {
+        Some(tts.remove(0))
+    } else {
+        None
+    };
+    let trailing_comma = if is_comma(tts.last()) {
+        tts.pop()
+    } else {
+        None
+    };
+
+    if let Some(comma) = leading_comma {
+        tsb.push(comma);
+    }
+    let name = Symbol::intern("optional");
+    tsb.push(TokenTree::Token(Token{kind: TokenKind::Ident(name, false), span: span.open}));
+    tsb.push(TokenTree::Token(Token{kind: TokenKind::Not, span: span.open}));
+    tsb.push(TokenTree::Delimited(span, DelimToken::Paren, tts.into_iter().collect()));
+    if let Some(comma) = trailing_comma {
+        tsb.push(comma);
+    }
+}
+
 /// Rewrite tokens like `$foo:ty` into `$foo` and extract the types
 fn rewrite_token_stream(ts: TokenStream, bt: &mut BindingTypes) -> TokenStream {
     let mut tsb = TokenStreamBuilder::new();
     let mut c = ts.into_trees();
     while let Some(tt) = c.next() {
         let new_tt = match tt {
+            TokenTree::Token(Token{kind: TokenKind::Dollar, ..}) if is_optional_group(&c) => {
+                if let Some(TokenTree::Delimited(sp, _, tts)) = c.next() {
+                    let dts = rewrite_token_stream(tts, bt);
+                    rewrite_optional_group(dts, sp, &mut tsb);
+                }
+                // Skip the `?`
+                c.next();
+                continue;
+            }
+
             TokenTree::Token(Token{kind: TokenKind::Dollar, ..}) => match c.look_ahead(0) {
                 Some(TokenTree::Token(Token{kind: TokenKind::Ident(ident, is_raw), span})) => {
                     c.next();
                     let dollar_sym = Symbol::intern(&format!("${}", ident));
-                    let ident_ty = maybe_get_type(&mut c);
+                    if let Some(constraint) = maybe_get_lit_constraint(&mut c) {
+                        // Literal bindings capture the whole `Expr`, so they can include a sign.
+                        bt.set_type(dollar_sym, Type::Expr);
+                        bt.set_lit_constraint(dollar_sym, constraint);
+                        tsb.push(TokenTree::Token(Token{
+                            kind: TokenKind::Ident(dollar_sym, is_raw),
+                            span,
+                        }));
+                        continue;
+                    }
+                    let mut ident_ty = maybe_get_type(&mut c);
+                    if ident_ty != Type::Unknown {
+                        if let Some(nonempty) = maybe_get_repetition(&mut c) {
+                            ident_ty = match ident_ty {
+                                Type::Expr | Type::MultiExpr => Type::MultiExpr,
+                                Type::Stmt | Type::MultiStmt => Type::MultiStmt,
+                                _ => panic!("repetition of {:?} bindings is not supported: {}",
+                                            ident_ty, dollar_sym),
+                            };
+                            if nonempty {
+                                bt.set_nonempty(dollar_sym);
+                            }
+                        }
+                    }
                     bt.set_type(dollar_sym, ident_ty);
 
                     let token_kind = match ident_ty {
@@ -338,6 +646,12 @@ fn rewrite_token_stream(ts: TokenStream, bt: &mut BindingTypes) -> TokenStream {
                             // inside a LitKind::Err
                             TokenKind::lit(TokenLitKind::Err, dollar_sym, None)
                         }
+                        Type::FnQual => {
+                            // Function qualifiers aren't a node, so we turn the binding into an
+                            // ABI string, which sits in the same place: `extern "$q" fn`.
+                            tsb.push(TokenTree::token(TokenKind::Ident(kw::Extern, false), span));
+                            TokenKind::lit(TokenLitKind::Str, dollar_sym, None)
+                        }
                         _ => TokenKind::Ident(dollar_sym, is_raw)
                     };
                     TokenTree::Token(Token{kind: token_kind, span})
@@ -0,0 +1,111 @@
+//! Trait bound queries for the `typed_bound!` matching form.
+use rustc::hir::def_id::{DefId, LOCAL_CRATE};
+use rustc::traits::{FulfillmentContext, Obligation, ObligationCause, TraitEngine};
+use rustc::ty::subst::{Subst, SubstsRef};
+use rustc::ty::{self, ParamEnv, ToPredicate, Ty, TyCtxt, TypeFoldable};
+use syntax::source_map::DUMMY_SP;
+use syntax::symbol::Symbol;
+
+/// All traits named `name`, in the local crate and in its dependencies.
+pub fn traits_named(tcx: TyCtxt, name: Symbol) -> Vec<DefId> {
+    tcx.all_traits(LOCAL_CRATE)
+        .iter()
+        .cloned()
+        .filter(|&did| tcx.item_name(did) == name)
+        .collect()
+}
+
+/// Find the ways `self_ty` implements the trait `trait_did` in `param_env`.  Returns the generic
+/// arguments of each bound `self_ty: Trait<...>` that holds, with `self_ty` as the first one.
+///
+/// The candidate arguments come from the where-clauses in `param_env`, and from the impls of the
+/// trait whose self type unifies with `self_ty`.  Impls that leave some of the trait's arguments
+/// undetermined (even after solving their own where-clauses) are skipped.
+pub fn bound_substs<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    param_env: ParamEnv<'tcx>,
+    self_ty: Ty<'tcx>,
+    trait_did: DefId,
+) -> Vec<SubstsRef<'tcx>> {
+    let self_ty = tcx.erase_regions(&self_ty);
+    let mut candidates = Vec::new();
+
+    if tcx.generics_of(trait_did).count() == 1 {
+        // The trait has no parameters besides `Self`, so there's only one bound to check.
+        candidates.push(tcx.mk_substs_trait(self_ty, &[]));
+    }
+
+    for pred in param_env.caller_bounds {
+        if let ty::Predicate::Trait(ref poly_trait_pred, ..) = *pred {
+            let trait_ref = poly_trait_pred.skip_binder().trait_ref;
+            if trait_ref.def_id != trait_did {
+                continue;
+            }
+            let substs = tcx.erase_regions(&trait_ref.substs);
+            if substs.type_at(0) == self_ty {
+                candidates.push(substs);
+            }
+        }
+    }
+
+    tcx.infer_ctxt().enter(|infcx| {
+        let cause = ObligationCause::dummy();
+        tcx.for_each_relevant_impl(trait_did, self_ty, |impl_did| {
+            let impl_trait_ref = match tcx.impl_trait_ref(impl_did) {
+                Some(x) => x,
+                None => return,
+            };
+            infcx.probe(|_| {
+                let impl_substs = infcx.fresh_substs_for_item(DUMMY_SP, impl_did);
+                let trait_ref = impl_trait_ref.subst(tcx, impl_substs);
+                if infcx.at(&cause, param_env).eq(trait_ref.self_ty(), self_ty).is_err() {
+                    return;
+                }
+
+                // Solve the obligation as far as possible, to infer any trait arguments that
+                // come from the impl's where-clauses, as in `impl<T: AsRef<U>, U> AsRef<U> for &T`.
+                let mut fulfill = FulfillmentContext::new();
+                fulfill.register_predicate_obligation(
+                    &infcx,
+                    Obligation::new(cause.clone(), param_env, trait_ref.to_predicate()),
+                );
+                let _ = fulfill.select_where_possible(&infcx);
+
+                let substs = infcx.resolve_vars_if_possible(&trait_ref.substs);
+                let substs = tcx.erase_regions(&substs);
+                if !substs.needs_infer() {
+                    candidates.push(substs);
+                }
+            });
+        });
+    });
+
+    candidates.dedup();
+    candidates.retain(|&substs| {
+        let trait_ref = ty::TraitRef::new(trait_did, substs);
+        let obligation = Obligation::new(
+            ObligationCause::dummy(),
+            param_env,
+            trait_ref.to_predicate(),
+        );
+        tcx.infer_ctxt()
+            .enter(|infcx| infcx.predicate_must_hold_modulo_regions(&obligation))
+    });
+    candidates
+}
+
+/// Get the associated type `name` of the trait bound `substs[0]: trait_did<substs[1..]>`,
+/// normalized in `param_env`.  Returns `None` if the trait has no such associated type.
+pub fn assoc_ty<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    param_env: ParamEnv<'tcx>,
+    trait_did: DefId,
+    substs: SubstsRef<'tcx>,
+    name: Symbol,
+) -> Option<Ty<'tcx>> {
+    let item = tcx
+        .associated_items(trait_did)
+        .find(|item| item.kind == ty::AssocKind::Type && item.ident.name == name)?;
+    let proj = tcx.mk_projection(item.def_id, substs);
+    Some(tcx.normalize_erasing_regions(param_env, proj))
+}
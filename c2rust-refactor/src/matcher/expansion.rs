@@ -0,0 +1,89 @@
+//! Matching inside macro expansions.
+//!
+//! Nodes that a macro invocation produced can only be rewritten if they came entirely from one of
+//! the invocation's arguments, since only those tokens exist at the call site.  Macro collapsing
+//! then carries the rewrite back into the argument.
+use syntax::ast::{Attribute, Expr, Item, Mac, NodeId, Pat, Stmt, Ty, DUMMY_NODE_ID};
+use syntax::mut_visit::{self, MutVisitor};
+use syntax::ptr::P;
+use syntax::source_map::Span;
+
+use crate::ast_manip::MutVisit;
+use crate::collapse::MacroExpansion;
+
+/// A node type that pattern matching can rewrite inside macro expansions.
+pub trait ExpansionTarget: MutVisit + Clone {
+    fn node_id(&self) -> NodeId;
+    fn set_node_id(&mut self, id: NodeId);
+    fn span(&self) -> Span;
+}
+
+macro_rules! expansion_target_impl {
+    ($($T:ty,)*) => {
+        $(
+            impl ExpansionTarget for $T {
+                fn node_id(&self) -> NodeId {
+                    self.id
+                }
+
+                fn set_node_id(&mut self, id: NodeId) {
+                    self.id = id;
+                }
+
+                fn span(&self) -> Span {
+                    self.span
+                }
+            }
+        )*
+    };
+}
+
+expansion_target_impl! {
+    P<Expr>,
+    P<Pat>,
+    P<Ty>,
+    P<Item>,
+    Stmt,
+}
+
+/// Attributes have no `NodeId`, so they're never treated as part of an expansion.
+impl ExpansionTarget for Attribute {
+    fn node_id(&self) -> NodeId {
+        DUMMY_NODE_ID
+    }
+
+    fn set_node_id(&mut self, _id: NodeId) {}
+
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// Check that `target`, a node inside the expansion of `exp`, came entirely from a single argument
+/// of the invocation.  This fails if any part of `target` came from the macro's own body.
+pub fn from_single_arg<T: ExpansionTarget>(target: &T, exp: &MacroExpansion) -> bool {
+    let span = target.span();
+    if span.from_expansion() || !exp.arg_spans.iter().any(|arg| arg.contains(span)) {
+        return false;
+    }
+
+    let mut finder = ExpansionSpanFinder { found: false };
+    target.clone().visit(&mut finder);
+    !finder.found
+}
+
+struct ExpansionSpanFinder {
+    found: bool,
+}
+
+impl MutVisitor for ExpansionSpanFinder {
+    fn visit_span(&mut self, sp: &mut Span) {
+        if sp.from_expansion() {
+            self.found = true;
+        }
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
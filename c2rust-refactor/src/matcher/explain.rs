@@ -0,0 +1,161 @@
+//! Near-miss reports for the `--explain-matches N` command option.
+//!
+//! While the option is set, each failed match attempt keeps a `MatchTrace`: how many sub-patterns
+//! matched before the failure, which error ended the attempt, and the span of the innermost target
+//! node where it happened.  Once the command finishes, the `N` attempts that got furthest are
+//! printed, which usually points straight at the part of the pattern that's wrong.
+use syntax::source_map::{SourceMap, Span};
+
+use super::{Error, Result};
+
+/// The progress of a single match attempt.
+#[derive(Clone, Debug, Default)]
+pub struct MatchTrace {
+    /// The number of target nodes that matched so far.  Only nodes with spans count, so
+    /// trivially matching parts like `NodeId`s don't make an attempt look closer than it is.
+    progress: usize,
+    /// The span of the outermost target node.
+    target: Option<Span>,
+    /// Spans of the target nodes currently being matched, innermost last.
+    spans: Vec<Span>,
+    /// The error that ended the attempt, and the innermost target span where it happened.
+    failure: Option<(Error, Option<Span>)>,
+}
+
+impl MatchTrace {
+    /// Start matching a target node with the given span.
+    pub fn enter(&mut self, span: Option<Span>) {
+        if let Some(span) = span {
+            if self.target.is_none() {
+                self.target = Some(span);
+            }
+            self.spans.push(span);
+        }
+    }
+
+    /// Finish matching the node passed to the corresponding `enter` call.
+    pub fn leave(&mut self, span: Option<Span>, result: &Result<()>) {
+        match *result {
+            Ok(()) => {
+                if span.is_some() {
+                    self.progress += 1;
+                }
+                // Anything that failed inside a successful match was backtracked over.
+                self.failure = None;
+            }
+            Err(ref e) => {
+                // Errors normally propagate unchanged, so the innermost node is the one to blame.
+                // A different error means a special form like `typed!` replaced it with its own.
+                let replaced = match self.failure {
+                    Some((ref old, _)) => old != e,
+                    None => true,
+                };
+                if replaced {
+                    self.failure = Some((e.clone(), self.spans.last().cloned()));
+                }
+            }
+        }
+        if span.is_some() {
+            self.spans.pop();
+        }
+    }
+}
+
+struct NearMiss {
+    progress: usize,
+    target: String,
+    reason: String,
+    location: String,
+}
+
+/// The failed match attempts that got furthest during a command.
+pub struct MatchExplanation {
+    title: String,
+    limit: usize,
+    attempts: usize,
+    misses: Vec<NearMiss>,
+}
+
+impl MatchExplanation {
+    /// Create an empty explanation, which keeps the `limit` closest near-misses.
+    pub fn new(title: &str, limit: usize) -> MatchExplanation {
+        MatchExplanation {
+            title: title.to_owned(),
+            limit,
+            attempts: 0,
+            misses: Vec::new(),
+        }
+    }
+
+    /// Record a failed match attempt.  Attempts that failed on the outermost node, before any
+    /// sub-pattern matched, aren't near-misses and only get counted.
+    pub fn record(&mut self, source_map: &SourceMap, trace: MatchTrace) {
+        self.attempts += 1;
+        if trace.progress == 0 {
+            return;
+        }
+        let (error, span) = match trace.failure {
+            Some(x) => x,
+            None => return,
+        };
+
+        let pos = self
+            .misses
+            .iter()
+            .position(|m| m.progress < trace.progress)
+            .unwrap_or(self.misses.len());
+        if pos >= self.limit {
+            return;
+        }
+
+        let span_str = |sp: Option<Span>| {
+            sp.map_or_else(|| "<unknown>".to_owned(), |sp| source_map.span_to_string(sp))
+        };
+        self.misses.insert(
+            pos,
+            NearMiss {
+                progress: trace.progress,
+                target: span_str(trace.target),
+                reason: describe_error(&error),
+                location: span_str(span),
+            },
+        );
+        self.misses.truncate(self.limit);
+    }
+
+    /// Print the near-misses to stderr, closest first.
+    pub fn emit(&self) {
+        eprintln!(
+            "{}: {} closest of {} failed match attempts:",
+            self.title,
+            self.misses.len(),
+            self.attempts
+        );
+        for m in &self.misses {
+            eprintln!("  {}: {} sub-patterns matched", m.target, m.progress);
+            eprintln!("    then: {} at {}", m.reason, m.location);
+        }
+        if self.misses.is_empty() {
+            eprintln!("  (no near misses)");
+        }
+    }
+}
+
+fn describe_error(e: &Error) -> String {
+    let s = match *e {
+        Error::VariantMismatch | Error::LengthMismatch | Error::SymbolMismatch => {
+            "structural mismatch"
+        }
+        Error::NegatedMatch => "`not!` pattern matched",
+        Error::InvalidParse => "invalid special pattern",
+        Error::NonlinearMismatch => "backreference mismatch",
+        Error::NotMarked => "`marked!` node not marked",
+        Error::DefMismatch => "`def!` resolution mismatch",
+        Error::LitMismatch => "literal constraint mismatch",
+        Error::WrongType => "type guard mismatch",
+        Error::GuardFailed => "`guard!` rejected",
+        Error::TypeUnavailable => "type unavailable",
+        Error::BadSpecialPattern(name) => return format!("unknown special pattern `{}!`", name),
+    };
+    format!("{} ({:?})", s, e)
+}
@@ -41,6 +41,10 @@ impl TryMatch for Ident {
             Err(matcher::Error::SymbolMismatch)
         }
     }
+
+    fn match_span(&self) -> Option<Span> {
+        Some(self.span)
+    }
 }
 
 impl TryMatch for Label {
@@ -65,6 +69,10 @@ impl TryMatch for Path {
 
         default_try_match_path(self, target, mcx)
     }
+
+    fn match_span(&self) -> Option<Span> {
+        Some(self.span)
+    }
 }
 
 impl TryMatch for Lit {
@@ -75,6 +83,10 @@ impl TryMatch for Lit {
 
         default_try_match_lit(self, target, mcx)
     }
+
+    fn match_span(&self) -> Option<Span> {
+        Some(self.span)
+    }
 }
 
 impl TryMatch for Expr {
@@ -83,6 +95,10 @@ impl TryMatch for Expr {
             return Ok(());
         }
 
+        if mcx.maybe_match_def_call(self, target)? {
+            return Ok(());
+        }
+
         if let ExprKind::Mac(ref mac) = self.kind {
             let name = macro_name(mac);
             return match &name.as_str() as &str {
@@ -91,12 +107,20 @@ impl TryMatch for Expr {
                     |p| p.parse_expr().map(|p| p.into_inner()),
                     target,
                 ),
+                "not" => mcx.do_not(
+                    &mac.args,
+                    |p| p.parse_expr().map(|p| p.into_inner()),
+                    target,
+                ),
                 "def" => mcx.do_def_expr(&mac.args, target),
+                "guard" => mcx.do_guard(&mac.args, target),
                 "typed" => mcx.do_typed(
                     &mac.args,
                     |p| p.parse_expr().map(|p| p.into_inner()),
                     target,
                 ),
+                "typed_bound" => mcx.do_typed_bound(&mac.args, target),
+                "semantic_eq" => mcx.do_semantic_eq(&mac.args, target),
                 "cast" => mcx.do_cast(&mac.args, |p| p.parse_expr(), target),
                 _ => Err(matcher::Error::BadSpecialPattern(name)),
             };
@@ -104,6 +128,10 @@ impl TryMatch for Expr {
 
         default_try_match_expr(self, target, mcx)
     }
+
+    fn match_span(&self) -> Option<Span> {
+        Some(self.span)
+    }
 }
 
 impl TryMatch for Pat {
@@ -120,6 +148,11 @@ impl TryMatch for Pat {
                     |p| p.parse_pat(None).map(|p| p.into_inner()),
                     target,
                 ),
+                "not" => mcx.do_not(
+                    &mac.args,
+                    |p| p.parse_pat(None).map(|p| p.into_inner()),
+                    target,
+                ),
                 "typed" => mcx.do_typed(
                     &mac.args,
                     |p| p.parse_pat(None).map(|p| p.into_inner()),
@@ -131,6 +164,10 @@ impl TryMatch for Pat {
 
         default_try_match_pat(self, target, mcx)
     }
+
+    fn match_span(&self) -> Option<Span> {
+        Some(self.span)
+    }
 }
 
 impl TryMatch for Ty {
@@ -147,6 +184,11 @@ impl TryMatch for Ty {
                     |p| p.parse_ty().map(|p| p.into_inner()),
                     target,
                 ),
+                "not" => mcx.do_not(
+                    &mac.args,
+                    |p| p.parse_ty().map(|p| p.into_inner()),
+                    target,
+                ),
                 "def" => mcx.do_def_ty(&mac.args, target),
                 _ => Err(matcher::Error::BadSpecialPattern(name)),
             };
@@ -154,6 +196,10 @@ impl TryMatch for Ty {
 
         default_try_match_ty(self, target, mcx)
     }
+
+    fn match_span(&self) -> Option<Span> {
+        Some(self.span)
+    }
 }
 
 impl TryMatch for Stmt {
@@ -164,6 +210,54 @@ impl TryMatch for Stmt {
 
         default_try_match_stmt(self, target, mcx)
     }
+
+    fn match_span(&self) -> Option<Span> {
+        Some(self.span)
+    }
+}
+
+impl TryMatch for Attribute {
+    fn try_match(&self, target: &Self, mcx: &mut MatchCtxt) -> matcher::Result<()> {
+        if mcx.maybe_capture_attr(self, target)? {
+            return Ok(());
+        }
+
+        if self.style != target.style {
+            return Err(matcher::Error::VariantMismatch);
+        }
+        match (&self.kind, &target.kind) {
+            (&AttrKind::Normal(ref item1), &AttrKind::Normal(ref item2)) => {
+                mcx.try_match(&item1.path, &item2.path)?;
+                match (&item1.args, &item2.args) {
+                    (&MacArgs::Empty, &MacArgs::Empty) => Ok(()),
+                    (&MacArgs::Delimited(_, delim1, ref tts1),
+                     &MacArgs::Delimited(_, delim2, ref tts2)) if delim1 == delim2 => {
+                        matcher::match_tokens(mcx, tts1, tts2)
+                    }
+                    (&MacArgs::Eq(_, ref tts1), &MacArgs::Eq(_, ref tts2)) => {
+                        matcher::match_tokens(mcx, tts1, tts2)
+                    }
+                    (_, _) => Err(matcher::Error::VariantMismatch),
+                }
+            }
+            (&AttrKind::DocComment(sym1), &AttrKind::DocComment(sym2)) if sym1 == sym2 => Ok(()),
+            (_, _) => Err(matcher::Error::VariantMismatch),
+        }
+    }
+
+    fn match_span(&self) -> Option<Span> {
+        Some(self.span)
+    }
+}
+
+impl TryMatch for FnHeader {
+    fn try_match(&self, target: &Self, mcx: &mut MatchCtxt) -> matcher::Result<()> {
+        if mcx.maybe_capture_fn_qual(self, target)? {
+            return Ok(());
+        }
+
+        default_try_match_fn_header(self, target, mcx)
+    }
 }
 
 impl TryMatch for Block {
@@ -179,6 +273,10 @@ impl TryMatch for Block {
         }
         Err(matcher::Error::LengthMismatch)
     }
+
+    fn match_span(&self) -> Option<Span> {
+        Some(self.span)
+    }
 }
 
 impl<T: TryMatch> TryMatch for [T] {
@@ -194,11 +292,29 @@ impl<T: TryMatch> TryMatch for [T] {
 }
 
 impl<T: TryMatch> TryMatch for Vec<T> {
-    fn try_match(&self, target: &Self, mcx: &mut MatchCtxt) -> matcher::Result<()> {
+    default fn try_match(&self, target: &Self, mcx: &mut MatchCtxt) -> matcher::Result<()> {
         <[T] as TryMatch>::try_match(self, target, mcx)
     }
 }
 
+// Specialized implementation for lists of expressions (call arguments, array and tuple
+// elements), which can contain repetitions like `$args:Expr*`
+impl TryMatch for Vec<P<Expr>> {
+    fn try_match(&self, target: &Self, mcx: &mut MatchCtxt) -> matcher::Result<()> {
+        if matcher::match_multi_expr(mcx, self, target) {
+            Ok(())
+        } else {
+            Err(matcher::Error::LengthMismatch)
+        }
+    }
+}
+
+impl TryMatch for Vec<Attribute> {
+    fn try_match(&self, target: &Self, mcx: &mut MatchCtxt) -> matcher::Result<()> {
+        matcher::match_attrs(mcx, self, target)
+    }
+}
+
 impl<T: TryMatch> TryMatch for ThinVec<T> {
     fn try_match(&self, target: &Self, mcx: &mut MatchCtxt) -> matcher::Result<()> {
         <[T] as TryMatch>::try_match(self, target, mcx)
@@ -221,6 +337,10 @@ impl<T: TryMatch> TryMatch for Spanned<T> {
     fn try_match(&self, target: &Self, mcx: &mut MatchCtxt) -> matcher::Result<()> {
         mcx.try_match(&self.node, &target.node)
     }
+
+    fn match_span(&self) -> Option<Span> {
+        Some(self.span)
+    }
 }
 
 #[inline]
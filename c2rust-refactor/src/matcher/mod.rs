@@ -23,35 +23,131 @@
 //!    ASTs. The capture can also have the form `$x:?NODE`, which matches an optional AST of type
 //!    `Option<Node>`, e.g., `$l:?Ident` matches against `Option<Ident>` for optional loop labels.
 //!
+//!    A binding used more than once in a pattern is a backreference: every later occurrence has
+//!    to match a node that is structurally equal to the one captured first, ignoring spans and
+//!    `NodeId`s.  For example, `$x:Expr = $x` matches self-assignments like `a.b = a.b`.  The
+//!    type only has to be given on one of the occurrences.
+//!
+//!  * `$x:LitInt(range)` and `$x:LitStr(s)`: Capture a literal `Expr` whose value satisfies a
+//!    constraint.  For integers, the constraint is a range like `0..8`, `..=4096`, or `-1..`, or
+//!    a single value, and a negated literal like `-1` counts as a literal with a negative value.
+//!    For strings, the constraint is either `"s"` to match the string exactly or `"s"..` to match
+//!    strings starting with `s`.  The constraint can be left off, e.g., `$x:LitInt` matches any
+//!    integer literal.
+//!
+//!  * `$x:Expr*` and `$x:Expr+`: Repetitions, which capture a sequence of zero or more (or one or
+//!    more) expressions inside the arguments of a call or method call, or the elements of an array
+//!    or tuple, e.g., `printf($fmt:Expr, $args:Expr*)`.  The sequence is saved in the `Bindings`
+//!    as a `Vec<P<Expr>>`.  Repetitions are greedy: they capture as many expressions as they can
+//!    while still letting the rest of the list match.  `$s:Stmt*` and `$s:Stmt+` do the same for
+//!    statements, and are equivalent to `$s:MultiStmt`.
+//!
+//!  * `$(...)?`: An optional group of list elements, e.g., `foo($x:Expr $(, $flag:Expr)?)`
+//!    matches both `foo(x)` and `foo(x, 0)`.  The group is matched if possible; otherwise, all
+//!    the bindings inside the group are bound as absent, which `Bindings::is_present` can check.
+//!    Optional groups can be nested, and can contain repetitions.
+//!
 //!  * `marked!(x [, label])`: Matches `x` only if the node is marked with the given label.  The
 //!    label defaults to "target" if omitted.
 //!
 //!  * `def!(path)`: Matches a path `Expr` or `Ty` that refers to a definition whose absolute path
 //!    is `path`.  Specifically, the path of the definition is converted back to an AST using the
-//!    `reflect` module, and the new AST is matched against `path`.
+//!    `reflect` module, and the new AST is matched against `path`.  Methods of inherent impls on
+//!    non-path types use a qualified path, like `<*const $t:Ty>::offset`.
+//!
+//!    Calls can be matched by their resolved callee, ignoring how the call is spelled:
+//!    `def!(path, $x.f($n))` matches a call or method call to `path`, and `def!(path)($x, $n)`
+//!    also matches method calls to `path`, with the receiver as the first argument.  Since only
+//!    the callee's `DefId` is compared, the receiver matches as written, regardless of any
+//!    auto-ref or auto-deref adjustments.
 //!
 //!  * `typed!(x, ty)`: Matches an `Expr` or `Ty` whose resolved type matches `ty`.  Specifically,
 //!    the resolved type of the node is converted back to an AST using the `reflect` module, and
 //!    the new AST is matched against `ty`.
 //!
+//!  * `typed_bound!(x, Trait<...>)`: Matches an `Expr` whose type implements `Trait`, as decided
+//!    by the trait solver in the environment of the enclosing item, so bounds that hold through a
+//!    where-clause count too.  The trait is found by name, and the pattern's path only has to
+//!    match the end of the trait's absolute path, e.g., `AsRef<[u8]>` or `convert::AsRef<[u8]>`.
+//!    The trait's arguments and associated types, e.g., `Iterator<Item = $t:Ty>`, are converted
+//!    back to ASTs and matched against the pattern, like in `typed!`.  If the pattern gives no
+//!    arguments, any implementation of the trait matches.
+//!
+//!  * `not!(neg)` or `not!(x, neg)`: Matches a node only if the pattern `neg` fails to match it.
+//!    The two-argument form also requires the node to match `x`, e.g., `not!($e:Expr, $a:Expr as
+//!    $t:Ty)` matches any expression that isn't a cast.  The negated pattern is checked first,
+//!    against a copy of the bindings captured so far, so it can refer to earlier bindings, but
+//!    anything it captures is thrown away.  Bindings that only appear inside `neg` can't be used
+//!    in a replacement.
+//!
+//!  * `guard!(name, x)`: Matches an `Expr` that matches `x`, but only if the guard registered
+//!    as `name` accepts it.  Guards are predicates written in Rust, which transforms and plugins
+//!    register with `Registry::register_guard`, e.g., `guard!(no_side_effects, $e:Expr)`.  A
+//!    guard sees the bindings captured before it, but not the ones from `x`.
+//!
+//!  * `semantic_eq!(x)`: Matches an `Expr` that matches `x`, but backreferences to `Expr`
+//!    bindings inside `x` must also resolve to the same definitions, not just be written the
+//!    same way.  For example, `f($x:Expr, semantic_eq!({ let $p:Pat = $e:Expr; $x }))` doesn't
+//!    match `f(n, { let n = 1; n })`, since the second `n` refers to the shadowing local.
+//!
 //!  * `cast!(x)`: Matches the `Expr`s `x`, `x as __t`, `x as __t as __u`, etc.
+//!
+//! Special forms can be nested, e.g., `typed!(not!(...), ty)` or `not!(marked!(...))`.  Each
+//! form checks its own condition (the type, the mark, the negated pattern, etc.) before matching
+//! its inner pattern, so nested forms are evaluated from the outside in, and bindings are captured
+//! from left to right.
+//!
+//! Item patterns can have attributes, e.g., `#[no_mangle] fn $f:Ident() {}`.  Each attribute in
+//! the pattern has to match a different attribute of the item, in order, but the item can have
+//! other attributes too.  `#[$a:Attr]` captures any attribute except a doc comment.  In the
+//! arguments of an attribute, `$x` captures a single identifier token and `$x:Lit` captures a
+//! single literal token, as in `#[export_name = $name:Lit]`.
+//!
+//! Function item patterns can capture the function's qualifiers with `$q:FnQual`, written where
+//! the `extern "ABI"` would go, e.g., `unsafe $q:FnQual fn $f:Ident() {}`.  Qualifiers written
+//! next to the binding must be present on the function, and `$q` captures the rest, including
+//! the ABI.  Without a `$q:FnQual` binding, qualifiers are matched exactly.
+//!
+//! Matching can be restricted to the items selected by a `MatchScope`, either with
+//! `MatchCtxt::set_scope` or with the `--scope PATH` and `--scope-mark LABEL` options that every
+//! command accepts.  Items outside the scope are skipped entirely.
+//!
+//! Search-and-replace only rewrites a node produced by a macro invocation if the node came
+//! entirely from one argument of the invocation, like `x as u32` in `double!(x as u32)`.  The
+//! rewrite is then applied to the argument at the call site.  Nodes that include tokens from the
+//! macro's own body are skipped.  For macros defined in the crate being refactored, this is
+//! opt-in, with `MatchCtxt::set_match_in_macros` or the `--match-in-macros` command option.
+//!
+//! To debug a pattern that matches nothing, pass `--explain-matches N` to the command.  Failed
+//! match attempts then record how many sub-patterns matched and which one failed, and the `N`
+//! attempts that got furthest are printed after the command, with the reason for each failure
+//! (structural mismatch, type guard, `def!` resolution, literal constraint, etc.) and its span.
 
+use rustc::hir::def::Res;
 use rustc::hir::def_id::DefId;
 use rustc::session::Session;
-use smallvec::SmallVec;
+use rustc::ty::subst::SubstsRef;
+use rustc::ty::ParamEnv;
+use smallvec::{smallvec, SmallVec};
 use std::cmp;
+use std::rc::Rc;
 use std::result;
-use syntax::ast::{Block, Expr, ExprKind, Ident, Item, Label, Lit, MacArgs, Pat, Path, Stmt, Ty};
+use syntax::ast::{AttrKind, Attribute, Block, Expr, ExprKind, Ident, ImplItem, Item, Label, Lit};
+use syntax::ast::{Mac, MacArgs, Pat, Path};
+use syntax::ast::{AssocTyConstraintKind, GenericArg, GenericArgs, NodeId, PathSegment, QSelf};
+use syntax::ast::{Constness, FnHeader, IsAsync, Stmt, Ty, TyKind, Unsafety};
 use syntax::mut_visit::{self, MutVisitor};
 use rustc_parse::parser::{Parser, PathStyle};
-use syntax::token::{TokenKind};
+use syntax::token::{LitKind as TokenLitKind, TokenKind};
 use rustc_errors::PResult;
 use syntax::ptr::P;
 use syntax::symbol::Symbol;
-use syntax::tokenstream::TokenStream;
-use syntax_pos::FileName;
+use syntax::tokenstream::{TokenStream, TokenTree};
+use syntax::visit::{self, Visitor};
+use syntax_pos::{FileName, Span};
 
-use crate::ast_manip::util::PatternSymbol;
+use crate::ast_manip::util::{macro_name, PatternSymbol};
+use crate::ast_manip::number_nodes::number_new_nodes_with;
 use crate::ast_manip::{remove_paren, GetNodeId, MutVisit};
 use crate::command::CommandState;
 use crate::driver::{self, emit_and_panic};
@@ -60,11 +156,20 @@ use crate::RefactorCtxt;
 use c2rust_ast_builder::IntoSymbol;
 
 mod bindings;
+mod bound;
+mod expansion;
+mod explain;
 mod impls;
+mod scope;
 mod subst;
 
-pub use self::bindings::{parse_bindings, BindingTypes, Bindings, Type as BindingType};
+pub use self::bindings::{parse_bindings, BindingTypes, Bindings, LitConstraint};
+pub use self::bindings::Type as BindingType;
+pub use self::explain::{MatchExplanation, MatchTrace};
+pub use self::scope::MatchScope;
 pub use self::subst::Subst;
+use self::expansion::{from_single_arg, ExpansionTarget};
+use self::scope::{item_scope_name, SavedScope, ScopeTracker};
 
 pub type Result<T> = result::Result<T, Error>;
 
@@ -74,6 +179,9 @@ pub enum Error {
     LengthMismatch,
     SymbolMismatch,
 
+    /// A `not!` pattern failed to match because its negated pattern matched the target.
+    NegatedMatch,
+
     /// Parse error while parsing pattern
     InvalidParse,
 
@@ -88,9 +196,17 @@ pub enum Error {
     /// item.
     DefMismatch,
 
-    /// A `typed!` macro failed to match because the target's type did not match the type pattern.
+    /// A literal binding like `$n:LitInt(0..8)` tried to match a node that isn't a literal, or a
+    /// literal whose value doesn't satisfy the constraint.
+    LitMismatch,
+
+    /// A `typed!` or `typed_bound!` macro failed to match because the target's type did not match
+    /// the type pattern.
     WrongType,
 
+    /// A `guard!` pattern failed to match because the guard rejected the target.
+    GuardFailed,
+
     /// A `typed!` macro failed to match because the type of the target expression was not
     /// available.
     TypeUnavailable,
@@ -106,20 +222,81 @@ pub struct MatchCtxt<'a, 'tcx: 'a> {
     pub types: BindingTypes,
     st: &'a CommandState,
     cx: &'a RefactorCtxt<'a, 'tcx>,
+    scope: Option<Rc<MatchScope>>,
+    match_in_macros: bool,
+    /// Whether backreferences to `Expr` bindings must resolve to the same definitions, inside
+    /// `semantic_eq!`.
+    semantic_eq: bool,
+    /// Progress of the current match attempt, kept only for `--explain-matches`.
+    trace: Option<MatchTrace>,
     pub debug: bool,
 }
 
 impl<'a, 'tcx> MatchCtxt<'a, 'tcx> {
+    /// Build a new `MatchCtxt`, restricted to the `MatchScope` of the current command, if any.
     pub fn new(st: &'a CommandState, cx: &'a RefactorCtxt<'a, 'tcx>) -> MatchCtxt<'a, 'tcx> {
         MatchCtxt {
             bindings: Bindings::new(),
             types: BindingTypes::new(),
             st,
             cx,
+            scope: st.match_scope().cloned().map(Rc::new),
+            match_in_macros: st.match_in_macros(),
+            semantic_eq: false,
+            trace: if st.explain_matches() {
+                Some(MatchTrace::default())
+            } else {
+                None
+            },
             debug: false,
         }
     }
 
+    /// Restrict search-and-replace with this context to the items selected by `scope`, or lift
+    /// the restriction if `scope` is `None`.
+    pub fn set_scope(&mut self, scope: Option<MatchScope>) {
+        self.scope = scope.map(Rc::new);
+    }
+
+    fn scope(&self) -> Option<&MatchScope> {
+        self.scope.as_ref().map(|s| &**s)
+    }
+
+    /// Allow or forbid search-and-replace with this context inside the expansions of macros
+    /// defined in the crate.
+    pub fn set_match_in_macros(&mut self, match_in_macros: bool) {
+        self.match_in_macros = match_in_macros;
+    }
+
+    /// Check whether search-and-replace may rewrite `target`.  Nodes from a macro expansion must
+    /// come entirely from one argument of the invocation, and nodes from local macros are only
+    /// considered when matching in macros is enabled.
+    fn can_match_in_macro<T: ExpansionTarget>(&self, target: &T) -> bool {
+        let exp = match self.st.macro_expansion(target.node_id()) {
+            Some(x) => x,
+            None => return true,
+        };
+        if exp.local && !self.match_in_macros {
+            return false;
+        }
+        from_single_arg(target, exp)
+    }
+
+    /// Track entering an item during search-and-replace.  Returns `None` if the whole item is
+    /// outside the scope and should be skipped.
+    fn enter_scope(
+        &self,
+        tracker: &mut ScopeTracker,
+        name: Symbol,
+        id: NodeId,
+    ) -> Option<SavedScope> {
+        tracker.enter(self.scope(), self.st, name, id)
+    }
+
+    fn leave_scope(&self, tracker: &mut ScopeTracker, saved: SavedScope) {
+        tracker.leave(self.scope(), saved)
+    }
+
     pub fn parse_expr(&mut self, src: &str) -> P<Expr> {
         let (mut p, bt) = make_bindings_parser(self.cx.session(), src);
         match p.parse_expr() {
@@ -156,6 +333,17 @@ impl<'a, 'tcx> MatchCtxt<'a, 'tcx> {
         }
     }
 
+    pub fn parse_attr(&mut self, src: &str) -> Attribute {
+        let (mut p, bt) = make_bindings_parser(self.cx.session(), src);
+        match p.parse_attribute(false) {
+            Ok(attr) => {
+                self.types.merge(bt);
+                attr
+            }
+            Err(db) => emit_and_panic(db, "attr"),
+        }
+    }
+
     pub fn parse_stmts(&mut self, src: &str) -> Vec<Stmt> {
         // TODO: rustc no longer exposes `parse_full_stmt`. `parse_block` is a hacky
         // workaround that may cause suboptimal error messages.
@@ -192,7 +380,14 @@ impl<'a, 'tcx> MatchCtxt<'a, 'tcx> {
 
     /// Try to match `target` against `pat`, updating `self.bindings` with the results.
     pub fn try_match<T: TryMatch>(&mut self, pat: &T, target: &T) -> Result<()> {
+        if self.trace.is_none() {
+            return pat.try_match(target, self);
+        }
+
+        let span = target.match_span();
+        self.trace.as_mut().unwrap().enter(span);
         let r = pat.try_match(target, self);
+        self.trace.as_mut().unwrap().leave(span, &r);
         r
     }
 
@@ -211,7 +406,12 @@ impl<'a, 'tcx> MatchCtxt<'a, 'tcx> {
     /// Clone this context and try to perform a match in the clone, returning `Ok` if it succeeds.
     pub fn clone_match<T: TryMatch>(&self, pat: &T, target: &T) -> Result<MatchCtxt<'a, 'tcx>> {
         let mut m = self.clone();
-        m.try_match(pat, target)?;
+        if let Err(e) = m.try_match(pat, target) {
+            if let Some(trace) = m.trace.take() {
+                self.st.record_near_miss(self.cx, trace);
+            }
+            return Err(e);
+        }
         Ok(m)
     }
 
@@ -389,6 +589,20 @@ impl<'a, 'tcx> MatchCtxt<'a, 'tcx> {
             _ => return Ok(false),
         }
 
+        if let Some(constraint) = self.types.lit_constraint(&sym) {
+            if !constraint.matches(target) {
+                return Err(Error::LitMismatch);
+            }
+        }
+
+        if self.semantic_eq {
+            if let Some(old) = self.bindings.get::<_, P<Expr>>(sym) {
+                if path_resolutions(self.cx, old) != path_resolutions(self.cx, target) {
+                    return Err(Error::NonlinearMismatch);
+                }
+            }
+        }
+
         let ok = self.bindings.try_add(sym, P(target.clone()));
         if ok {
             Ok(true)
@@ -397,6 +611,74 @@ impl<'a, 'tcx> MatchCtxt<'a, 'tcx> {
         }
     }
 
+    pub fn maybe_capture_attr(&mut self, pattern: &Attribute, target: &Attribute) -> Result<bool> {
+        let sym = match pattern.pattern_symbol() {
+            Some(x) => x,
+            None => return Ok(false),
+        };
+
+        match self.types.get(&sym) {
+            Some(&bindings::Type::Attr) => {}
+            Some(&bindings::Type::Unknown) => {}
+            _ => return Ok(false),
+        }
+
+        // Doc comments are only matched by doc comment patterns, so `#[$a:Attr]` never captures
+        // one.
+        if let AttrKind::DocComment(_) = target.kind {
+            return Err(Error::VariantMismatch);
+        }
+
+        let ok = self.bindings.try_add(sym, target.clone());
+        if ok {
+            Ok(true)
+        } else {
+            Err(Error::NonlinearMismatch)
+        }
+    }
+
+    /// Capture the qualifiers of a function for a `$q:FnQual` binding.  Qualifiers written next
+    /// to the binding, as in `unsafe $q:FnQual fn`, must be present on the target, and are left
+    /// out of the captured set.
+    pub fn maybe_capture_fn_qual(&mut self, pattern: &FnHeader, target: &FnHeader) -> Result<bool> {
+        let sym = match pattern.pattern_symbol() {
+            Some(x) => x,
+            None => return Ok(false),
+        };
+
+        match self.types.get(&sym) {
+            Some(&bindings::Type::FnQual) => {}
+            _ => return Ok(false),
+        }
+
+        let mut quals = target.clone();
+        if pattern.unsafety == Unsafety::Unsafe {
+            if target.unsafety != Unsafety::Unsafe {
+                return Err(Error::VariantMismatch);
+            }
+            quals.unsafety = Unsafety::Normal;
+        }
+        if pattern.constness.node == Constness::Const {
+            if target.constness.node != Constness::Const {
+                return Err(Error::VariantMismatch);
+            }
+            quals.constness.node = Constness::NotConst;
+        }
+        if pattern.asyncness.node.is_async() {
+            if !target.asyncness.node.is_async() {
+                return Err(Error::VariantMismatch);
+            }
+            quals.asyncness.node = IsAsync::NotAsync;
+        }
+
+        let ok = self.bindings.try_add(sym, quals);
+        if ok {
+            Ok(true)
+        } else {
+            Err(Error::NonlinearMismatch)
+        }
+    }
+
     pub fn maybe_capture_pat(&mut self, pattern: &Pat, target: &Pat) -> Result<bool> {
         let sym = match pattern.pattern_symbol() {
             Some(x) => x,
@@ -507,13 +789,12 @@ impl<'a, 'tcx> MatchCtxt<'a, 'tcx> {
         self.try_match(&pattern, target)
     }
 
-    /// Core implementation of the `def!(...)` matching form.
-    fn do_def_impl(
-        &mut self,
-        args: &MacArgs,
-        style: PathStyle,
-        opt_def_id: Option<DefId>,
-    ) -> Result<()> {
+    /// Handle the `not!(...)` matching form.
+    pub fn do_not<T, F>(&mut self, args: &MacArgs, func: F, target: &T) -> Result<()>
+    where
+        T: TryMatch,
+        F: for<'b> Fn(&mut Parser<'b>) -> PResult<'b, T>,
+    {
         let mut p = Parser::new(
             &self.cx.session().parse_sess,
             args.inner_tokens(),
@@ -522,37 +803,164 @@ impl<'a, 'tcx> MatchCtxt<'a, 'tcx> {
             false,
             None,
         );
-        let path_pattern = p.parse_path(style).unwrap();
+        let first = func(&mut p).unwrap();
+        let (pattern, neg_pattern) = if p.eat(&TokenKind::Comma) {
+            (Some(first), func(&mut p).unwrap())
+        } else {
+            (None, first)
+        };
+
+        // Match the negated pattern on a copy of the context, so that nothing it captures leaks
+        // into the real bindings.
+        let mut neg_mcx = self.clone();
+        if neg_mcx.try_match(&neg_pattern, target).is_ok() {
+            return Err(Error::NegatedMatch);
+        }
 
+        if let Some(pattern) = pattern {
+            self.try_match(&pattern, target)?;
+        }
+
+        // Anything still unbound was only mentioned by the negated pattern.
+        for name in macro_bindings(args) {
+            self.bindings.add_negated(name);
+        }
+        Ok(())
+    }
+
+    /// Core implementation of the `def!(...)` matching form.  Matches the absolute path of
+    /// `opt_def_id` against `qself` and `path_pattern`.  If the pattern has no `QSelf`, the `QSelf`
+    /// of the def path is ignored, so `<S as T>::f` gets matched as just `T::f`.
+    fn do_def_impl(
+        &mut self,
+        qself: Option<&QSelf>,
+        path_pattern: &Path,
+        opt_def_id: Option<DefId>,
+    ) -> Result<()> {
         let def_id = match_or!([opt_def_id] Some(x) => x;
                                return Err(Error::DefMismatch));
-        // TODO: We currently ignore the QSelf.  This means `<S as T>::f` gets matched as just
-        // `T::f`.  This would be a little annoying to fix, since `parse_qpath` is private.
-        let (_qself, def_path) = reflect::reflect_def_path(self.cx.ty_ctxt(), def_id);
+        let (def_qself, def_path) = reflect::reflect_def_path(self.cx.ty_ctxt(), def_id);
 
         if self.debug {
             eprintln!(
-                "def!(): trying to match pattern {:?} against AST {:?}",
-                path_pattern, def_path
+                "def!(): trying to match pattern {:?} {:?} against AST {:?} {:?}",
+                qself, path_pattern, def_qself, def_path
             );
         }
-        if self.try_match(&path_pattern, &def_path).is_err() {
+        if let Some(qself) = qself {
+            let def_qself = def_qself.ok_or(Error::DefMismatch)?;
+            if qself.position != def_qself.position
+                || self.try_match(&qself.ty, &def_qself.ty).is_err()
+            {
+                return Err(Error::DefMismatch);
+            }
+        }
+        if self.try_match(path_pattern, &def_path).is_err() {
             return Err(Error::DefMismatch);
         }
 
         Ok(())
     }
 
-    /// Handle the `def!(...)` matching form for exprs.
+    /// Handle the `def!(...)` matching form for exprs.  The two-argument form `def!(path, call)`
+    /// matches a call or method call whose callee is `path`.
     pub fn do_def_expr(&mut self, args: &MacArgs, target: &Expr) -> Result<()> {
+        let mut p = Parser::new(
+            &self.cx.session().parse_sess,
+            args.inner_tokens(),
+            None,
+            false,
+            false,
+            None,
+        );
+        let (qself, path) = def_qpath(p.parse_expr().unwrap());
+
+        if p.eat(&TokenKind::Comma) {
+            let call_pattern = p.parse_expr().unwrap();
+            self.do_def_impl(qself.as_ref(), &path, self.opt_callee(target))?;
+            return self.try_match_call(&call_pattern, target);
+        }
+
         let opt_def_id = self.cx.try_resolve_expr(target);
-        self.do_def_impl(args, PathStyle::Expr, opt_def_id)
+        self.do_def_impl(qself.as_ref(), &path, opt_def_id)
     }
 
-    /// Handle the `def!(...)` matching form for exprs.
+    /// Handle the `def!(...)` matching form for tys.
     pub fn do_def_ty(&mut self, args: &MacArgs, target: &Ty) -> Result<()> {
+        let mut p = Parser::new(
+            &self.cx.session().parse_sess,
+            args.inner_tokens(),
+            None,
+            false,
+            false,
+            None,
+        );
+        let (qself, path) = match p.parse_ty().unwrap().into_inner().kind {
+            TyKind::Path(qself, path) => (qself, path),
+            kind => panic!("def!: expected a path, but got {:?}", kind),
+        };
+
         let opt_def_id = self.cx.try_resolve_ty(target);
-        self.do_def_impl(args, PathStyle::Type, opt_def_id)
+        self.do_def_impl(qself.as_ref(), &path, opt_def_id)
+    }
+
+    /// Handle a call pattern whose callee is `def!(path)`, like `def!(path)($x, $n)`, matched
+    /// against a method call.  The method call matches if its callee is `path`, with the receiver
+    /// as the first argument.  Returns `Ok(false)` if the pattern or target is some other kind of
+    /// node.  (Calls written as calls are handled by the normal `Call` and `def!` matching.)
+    pub fn maybe_match_def_call(&mut self, pattern: &Expr, target: &Expr) -> Result<bool> {
+        let (callee, args) = match pattern.kind {
+            ExprKind::Call(ref callee, ref args) => (callee, args),
+            _ => return Ok(false),
+        };
+        let mac = match callee.kind {
+            ExprKind::Mac(ref mac) if macro_name(mac).as_str() == "def" => mac,
+            _ => return Ok(false),
+        };
+        let target_args = match target.kind {
+            ExprKind::MethodCall(_, ref target_args) => target_args,
+            _ => return Ok(false),
+        };
+
+        let mut p = Parser::new(
+            &self.cx.session().parse_sess,
+            mac.args.inner_tokens(),
+            None,
+            false,
+            false,
+            None,
+        );
+        let (qself, path) = def_qpath(p.parse_expr().unwrap());
+        self.do_def_impl(qself.as_ref(), &path, self.opt_callee(target))?;
+        self.try_match(args, target_args)?;
+        Ok(true)
+    }
+
+    /// Match a call or method call pattern against a call or method call whose callee is already
+    /// known to match, so only the arguments are compared.  The pattern can use either spelling:
+    /// a method call `x.f(y)` matches the call `f(x, y)`, and vice versa.
+    fn try_match_call(&mut self, pattern: &Expr, target: &Expr) -> Result<()> {
+        fn call_args(e: &Expr) -> Option<&Vec<P<Expr>>> {
+            match e.kind {
+                ExprKind::Call(_, ref args) | ExprKind::MethodCall(_, ref args) => Some(args),
+                _ => None,
+            }
+        }
+        match (call_args(pattern), call_args(target)) {
+            (Some(args), Some(target_args)) => self.try_match(args, target_args),
+            _ => self.try_match(pattern, target),
+        }
+    }
+
+    /// Get the callee of a call or method call, ignoring the original spelling of the call.
+    /// Returns `None` for nodes that weren't typechecked.
+    fn opt_callee(&self, e: &Expr) -> Option<DefId> {
+        match e.kind {
+            ExprKind::Call(..) | ExprKind::MethodCall(..) => {}
+            _ => return None,
+        }
+        self.cx.opt_node_to_hir_id(e.id)?;
+        self.cx.opt_callee(e)
     }
 
     /// Handle the `typed!(...)` matching form.
@@ -592,6 +1000,169 @@ impl<'a, 'tcx> MatchCtxt<'a, 'tcx> {
         self.try_match(&pattern, target)
     }
 
+    /// Handle the `guard!(...)` matching form.
+    pub fn do_guard(&mut self, args: &MacArgs, target: &Expr) -> Result<()> {
+        let mut p = Parser::new(
+            &self.cx.session().parse_sess,
+            args.inner_tokens(),
+            None,
+            false,
+            false,
+            None,
+        );
+        let name = p.parse_ident().unwrap();
+        p.expect(&TokenKind::Comma).unwrap();
+        let pattern = p.parse_expr().unwrap();
+
+        let guard = match self.st.guard(&name.as_str()) {
+            Some(x) => x,
+            None => {
+                self.cx
+                    .session()
+                    .struct_span_err(name.span, &format!("unknown guard `{}`", name))
+                    .emit();
+                panic!("guard!() pattern refers to unknown guard `{}`", name);
+            }
+        };
+        if !guard(target, self, self.cx) {
+            return Err(Error::GuardFailed);
+        }
+
+        self.try_match(&*pattern, target)
+    }
+
+    /// Handle the `semantic_eq!(...)` matching form.
+    pub fn do_semantic_eq(&mut self, args: &MacArgs, target: &Expr) -> Result<()> {
+        let mut p = Parser::new(
+            &self.cx.session().parse_sess,
+            args.inner_tokens(),
+            None,
+            false,
+            false,
+            None,
+        );
+        let pattern = p.parse_expr().unwrap();
+
+        let old_semantic_eq = self.semantic_eq;
+        self.semantic_eq = true;
+        let res = self.try_match(&*pattern, target);
+        self.semantic_eq = old_semantic_eq;
+        res
+    }
+
+    /// Handle the `typed_bound!(...)` matching form.
+    pub fn do_typed_bound(&mut self, args: &MacArgs, target: &Expr) -> Result<()> {
+        let mut p = Parser::new(
+            &self.cx.session().parse_sess,
+            args.inner_tokens(),
+            None,
+            false,
+            false,
+            None,
+        );
+        let pattern = p.parse_expr().unwrap();
+        p.expect(&TokenKind::Comma).unwrap();
+        let bound_pattern = p.parse_path(PathStyle::Type).unwrap();
+
+        let tcx = self.cx.ty_ctxt();
+        let self_ty = self
+            .cx
+            .opt_node_type(target.id)
+            .ok_or(Error::TypeUnavailable)?;
+        let hir_map = self.cx.hir_map();
+        let param_env = self
+            .cx
+            .opt_node_to_hir_id(target.id)
+            .and_then(|id| hir_map.opt_local_def_id(hir_map.get_parent_item(id)))
+            .map_or(ParamEnv::empty(), |did| tcx.param_env(did));
+
+        let last_seg = bound_pattern.segments.last().unwrap();
+        for trait_did in bound::traits_named(tcx, last_seg.ident.name) {
+            let (_qself, trait_path) = reflect::reflect_def_path(tcx, trait_did);
+            let n = bound_pattern.segments.len();
+            if n > trait_path.segments.len()
+                || bound_pattern.segments[..n - 1]
+                    .iter()
+                    .zip(&trait_path.segments[trait_path.segments.len() - n..])
+                    .any(|(a, b)| a.ident.name != b.ident.name)
+            {
+                continue;
+            }
+
+            for substs in bound::bound_substs(tcx, param_env, self_ty, trait_did) {
+                if self.debug {
+                    eprintln!(
+                        "typed_bound!(): trying to match pattern {:?} against bound {:?}",
+                        bound_pattern, substs
+                    );
+                }
+                let mut mcx = self.clone();
+                if mcx
+                    .match_bound_args(last_seg, param_env, trait_did, substs)
+                    .and_then(|()| mcx.try_match(&pattern, target))
+                    .is_ok()
+                {
+                    *self = mcx;
+                    return Ok(());
+                }
+            }
+        }
+
+        Err(Error::WrongType)
+    }
+
+    /// Match the generic arguments and associated type constraints from the last segment of a
+    /// `typed_bound!` trait pattern against the trait bound `trait_did<substs>`.
+    fn match_bound_args(
+        &mut self,
+        seg: &PathSegment,
+        param_env: ParamEnv<'tcx>,
+        trait_did: DefId,
+        substs: SubstsRef<'tcx>,
+    ) -> Result<()> {
+        let data = match seg.args.as_ref().map(|args| &**args) {
+            None => return Ok(()),
+            Some(GenericArgs::AngleBracketed(data)) => data,
+            Some(GenericArgs::Parenthesized(_)) => {
+                panic!("typed_bound!: parenthesized trait arguments are not supported")
+            }
+        };
+        let tcx = self.cx.ty_ctxt();
+
+        let ty_patterns = data
+            .args
+            .iter()
+            .filter_map(|arg| match *arg {
+                GenericArg::Type(ref ty) => Some(ty),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if !ty_patterns.is_empty() {
+            // Skip the `Self` argument, which is the type of the target.
+            let tys = substs.types().skip(1).collect::<Vec<_>>();
+            if tys.len() != ty_patterns.len() {
+                return Err(Error::LengthMismatch);
+            }
+            for (ty_pattern, ty) in ty_patterns.into_iter().zip(tys) {
+                self.try_match(ty_pattern, &reflect::reflect_tcx_ty(tcx, ty))?;
+            }
+        }
+
+        for constraint in &data.constraints {
+            let ty_pattern = match constraint.kind {
+                AssocTyConstraintKind::Equality { ref ty } => ty,
+                AssocTyConstraintKind::Bound { .. } => {
+                    panic!("typed_bound!: associated type bounds are not supported")
+                }
+            };
+            let ty = bound::assoc_ty(tcx, param_env, trait_did, substs, constraint.ident.name)
+                .ok_or(Error::WrongType)?;
+            self.try_match(ty_pattern, &reflect::reflect_tcx_ty(tcx, ty))?;
+        }
+
+        Ok(())
+    }
+
     pub fn do_cast<F>(&mut self, args: &MacArgs, func: F, target: &Expr) -> Result<()>
     where
         F: for<'b> FnOnce(&mut Parser<'b>) -> PResult<'b, P<Expr>>,
@@ -631,6 +1202,11 @@ fn make_bindings_parser<'a>(sess: &'a Session, src: &str) -> (Parser<'a>, Bindin
 
 pub trait TryMatch {
     fn try_match(&self, target: &Self, mcx: &mut MatchCtxt) -> Result<()>;
+
+    /// The span of this node, for locating failures in `--explain-matches` reports.
+    fn match_span(&self) -> Option<Span> {
+        None
+    }
 }
 
 /// Trait for AST types that can be used as patterns in a search-and-replace (`mut_visit_match`).
@@ -666,6 +1242,7 @@ macro_rules! gen_pattern_impl {
             pattern: $Pat,
             init_mcx: MatchCtxt<'a, 'tcx>,
             callback: F,
+            scope: ScopeTracker,
         }
 
         impl<'a, 'tcx, F> MutVisitor for $PatternFolder<'a, 'tcx, F>
@@ -673,13 +1250,44 @@ macro_rules! gen_pattern_impl {
             #[allow(unused_mut)]
             fn $fold_thing(&mut $slf, $arg: $ArgTy) -> $RetTy {
                 let $arg = $walk;
+                let in_scope = $slf.scope.in_scope($slf.init_mcx.scope());
                 let mut $match_one = |x: &mut $ArgTy| {
+                    if !in_scope || !$slf.init_mcx.can_match_in_macro(&*x) {
+                        return;
+                    }
                     if let Ok(mcx) = $slf.init_mcx.clone_match(&$slf.pattern, &x) {
-                        ($slf.callback)(x, mcx)
+                        let id = x.node_id();
+                        ($slf.callback)(x, mcx);
+                        // Keep the old ID on rewritten expansion nodes, so macro collapsing can
+                        // find the argument to update.
+                        if $slf.init_mcx.st.macro_expansion(id).is_some() {
+                            x.set_node_id(id);
+                        }
                     }
                 };
                 $map
             }
+
+            fn flat_map_item(&mut self, i: P<Item>) -> SmallVec<[P<Item>; 1]> {
+                let name = item_scope_name(&i);
+                let saved = match self.init_mcx.enter_scope(&mut self.scope, name, i.id) {
+                    Some(x) => x,
+                    None => return smallvec![i],
+                };
+                let r = mut_visit::noop_flat_map_item(i, self);
+                self.init_mcx.leave_scope(&mut self.scope, saved);
+                r
+            }
+
+            fn flat_map_impl_item(&mut self, i: ImplItem) -> SmallVec<[ImplItem; 1]> {
+                let saved = match self.init_mcx.enter_scope(&mut self.scope, i.ident.name, i.id) {
+                    Some(x) => x,
+                    None => return smallvec![i],
+                };
+                let r = mut_visit::noop_flat_map_impl_item(i, self);
+                self.init_mcx.leave_scope(&mut self.scope, saved);
+                r
+            }
         }
 
         impl Pattern<$Pat> for $Pat {
@@ -696,6 +1304,7 @@ macro_rules! gen_pattern_impl {
                     pattern: self,
                     init_mcx: init_mcx,
                     callback: callback,
+                    scope: ScopeTracker::default(),
                 };
                 target.visit(&mut f)
             }
@@ -716,6 +1325,7 @@ macro_rules! gen_pattern_impl {
             pattern: $Pat,
             init_mcx: MatchCtxt<'a, 'tcx>,
             callback: F,
+            scope: ScopeTracker,
         }
 
         impl<'a, 'tcx, F> MutVisitor for $PatternFolder<'a, 'tcx, F>
@@ -724,13 +1334,44 @@ macro_rules! gen_pattern_impl {
             #[allow(unused_mut)]
             fn $fold_thing(&mut $slf, $arg: &mut $ArgTy) {
                 $walk;
+                let in_scope = $slf.scope.in_scope($slf.init_mcx.scope());
                 let mut $match_one = |x: &mut $ArgTy| {
+                    if !in_scope || !$slf.init_mcx.can_match_in_macro(&*x) {
+                        return;
+                    }
                     if let Ok(mcx) = $slf.init_mcx.clone_match(&$slf.pattern, &x) {
+                        let id = x.node_id();
                         ($slf.callback)(x, mcx);
+                        // Keep the old ID on rewritten expansion nodes, so macro collapsing can
+                        // find the argument to update.
+                        if $slf.init_mcx.st.macro_expansion(id).is_some() {
+                            x.set_node_id(id);
+                        }
                     }
                 };
                 $map
             }
+
+            fn flat_map_item(&mut self, i: P<Item>) -> SmallVec<[P<Item>; 1]> {
+                let name = item_scope_name(&i);
+                let saved = match self.init_mcx.enter_scope(&mut self.scope, name, i.id) {
+                    Some(x) => x,
+                    None => return smallvec![i],
+                };
+                let r = mut_visit::noop_flat_map_item(i, self);
+                self.init_mcx.leave_scope(&mut self.scope, saved);
+                r
+            }
+
+            fn flat_map_impl_item(&mut self, i: ImplItem) -> SmallVec<[ImplItem; 1]> {
+                let saved = match self.init_mcx.enter_scope(&mut self.scope, i.ident.name, i.id) {
+                    Some(x) => x,
+                    None => return smallvec![i],
+                };
+                let r = mut_visit::noop_flat_map_impl_item(i, self);
+                self.init_mcx.leave_scope(&mut self.scope, saved);
+                r
+            }
         }
 
         impl Pattern<$Pat> for $Pat {
@@ -747,6 +1388,7 @@ macro_rules! gen_pattern_impl {
                     pattern: self,
                     init_mcx: init_mcx,
                     callback: callback,
+                    scope: ScopeTracker::default(),
                 };
                 target.visit(&mut f)
             }
@@ -779,6 +1421,24 @@ gen_pattern_impl! {
     map(match_one) = match_one(t);
 }
 
+gen_pattern_impl! {
+    pattern = P<Pat>;
+    folder = PatPatternFolder;
+
+    fn visit_pat(&mut self, p: &mut P<Pat>);
+    walk = mut_visit::noop_visit_pat(p, self);
+    map(match_one) = match_one(p);
+}
+
+gen_pattern_impl! {
+    pattern = Attribute;
+    folder = AttributePatternFolder;
+
+    fn visit_attribute(&mut self, a: &mut Attribute);
+    walk = mut_visit::noop_visit_attribute(a, self);
+    map(match_one) = match_one(a);
+}
+
 gen_pattern_impl! {
     pattern = Stmt;
     folder = StmtPatternFolder;
@@ -788,6 +1448,79 @@ gen_pattern_impl! {
     map(match_one) = { let mut s = s; s.iter_mut().for_each(match_one); s };
 }
 
+// Implementation of item matching.
+
+/// Custom `Folder` for item `Pattern`s.  This can't use `gen_pattern_impl!`, which already
+/// overrides `flat_map_item` to track the `MatchScope`.  Only top-level and nested items are
+/// matched, not impl items, trait items, or foreign items.
+pub struct ItemPatternFolder<'a, 'tcx: 'a, F>
+where
+    F: FnMut(&mut P<Item>, MatchCtxt<'a, 'tcx>),
+{
+    pattern: P<Item>,
+    init_mcx: MatchCtxt<'a, 'tcx>,
+    callback: F,
+    scope: ScopeTracker,
+}
+
+impl<'a, 'tcx, F> MutVisitor for ItemPatternFolder<'a, 'tcx, F>
+where
+    F: FnMut(&mut P<Item>, MatchCtxt<'a, 'tcx>),
+{
+    fn flat_map_item(&mut self, i: P<Item>) -> SmallVec<[P<Item>; 1]> {
+        let name = item_scope_name(&i);
+        let saved = match self.init_mcx.enter_scope(&mut self.scope, name, i.id) {
+            Some(x) => x,
+            None => return smallvec![i],
+        };
+        let mut r = mut_visit::noop_flat_map_item(i, self);
+        let in_scope = self.scope.in_scope(self.init_mcx.scope());
+        self.init_mcx.leave_scope(&mut self.scope, saved);
+
+        if in_scope {
+            for i in r.iter_mut() {
+                if !self.init_mcx.can_match_in_macro(&*i) {
+                    continue;
+                }
+                if let Ok(mcx) = self.init_mcx.clone_match(&self.pattern, i) {
+                    let id = i.id;
+                    (self.callback)(i, mcx);
+                    if self.init_mcx.st.macro_expansion(id).is_some() {
+                        i.id = id;
+                    }
+                }
+            }
+        }
+        r
+    }
+
+    fn flat_map_impl_item(&mut self, i: ImplItem) -> SmallVec<[ImplItem; 1]> {
+        let saved = match self.init_mcx.enter_scope(&mut self.scope, i.ident.name, i.id) {
+            Some(x) => x,
+            None => return smallvec![i],
+        };
+        let r = mut_visit::noop_flat_map_impl_item(i, self);
+        self.init_mcx.leave_scope(&mut self.scope, saved);
+        r
+    }
+}
+
+impl Pattern<P<Item>> for P<Item> {
+    fn visit<'a, 'tcx, T, F>(self, init_mcx: MatchCtxt<'a, 'tcx>, callback: F, target: &mut T)
+    where
+        T: MutVisit,
+        F: FnMut(&mut P<Item>, MatchCtxt<'a, 'tcx>),
+    {
+        let mut f = ItemPatternFolder {
+            pattern: self,
+            init_mcx,
+            callback,
+            scope: ScopeTracker::default(),
+        };
+        target.visit(&mut f)
+    }
+}
+
 // Implementation of multi-statement matching.
 
 /// Custom `Folder` for multi-statement `Pattern`s.
@@ -798,6 +1531,7 @@ where
     pattern: Vec<Stmt>,
     init_mcx: MatchCtxt<'a, 'tcx>,
     callback: F,
+    scope: ScopeTracker,
 }
 
 impl<'a, 'tcx, F> MutVisitor for MultiStmtPatternFolder<'a, 'tcx, F>
@@ -808,6 +1542,9 @@ where
         assert!(!self.pattern.is_empty());
 
         mut_visit::noop_visit_block(b, self);
+        if !self.scope.in_scope(self.init_mcx.scope()) {
+            return;
+        }
 
         let mut new_stmts = Vec::with_capacity(b.stmts.len());
         let mut last = 0;
@@ -841,6 +1578,27 @@ where
             b.stmts = new_stmts;
         }
     }
+
+    fn flat_map_item(&mut self, i: P<Item>) -> SmallVec<[P<Item>; 1]> {
+        let name = item_scope_name(&i);
+        let saved = match self.init_mcx.enter_scope(&mut self.scope, name, i.id) {
+            Some(x) => x,
+            None => return smallvec![i],
+        };
+        let r = mut_visit::noop_flat_map_item(i, self);
+        self.init_mcx.leave_scope(&mut self.scope, saved);
+        r
+    }
+
+    fn flat_map_impl_item(&mut self, i: ImplItem) -> SmallVec<[ImplItem; 1]> {
+        let saved = match self.init_mcx.enter_scope(&mut self.scope, i.ident.name, i.id) {
+            Some(x) => x,
+            None => return smallvec![i],
+        };
+        let r = mut_visit::noop_flat_map_impl_item(i, self);
+        self.init_mcx.leave_scope(&mut self.scope, saved);
+        r
+    }
 }
 
 pub fn match_multi_stmt(mcx: &mut MatchCtxt, pattern: &[Stmt], target: &[Stmt]) -> Option<usize> {
@@ -850,7 +1608,8 @@ pub fn match_multi_stmt(mcx: &mut MatchCtxt, pattern: &[Stmt], target: &[Stmt])
 
     if is_multi_stmt_glob(mcx, &pattern[0]) {
         let name = pattern[0].pattern_symbol().unwrap();
-        for i in (0..=target.len()).rev() {
+        let min = if mcx.types.is_nonempty(&name) { 1 } else { 0 };
+        for i in (min..=target.len()).rev() {
             let orig_mcx = mcx.clone();
             if let Some(consumed) = match_multi_stmt(mcx, &pattern[1..], &target[i..]) {
                 let ok = mcx.bindings.try_add(name, target[..i].to_owned());
@@ -903,6 +1662,223 @@ fn is_multi_stmt_glob(mcx: &MatchCtxt, pattern: &Stmt) -> bool {
     true
 }
 
+/// Match a list of expressions, such as the arguments of a call, against a `pattern` that may
+/// contain repetitions like `$args:Expr*`.  Unlike `match_multi_stmt`, the whole `target` needs
+/// to match.
+pub fn match_multi_expr(mcx: &mut MatchCtxt, pattern: &[P<Expr>], target: &[P<Expr>]) -> bool {
+    let (first, rest) = match pattern.split_first() {
+        Some(x) => x,
+        None => return target.is_empty(),
+    };
+
+    if let Some(group) = parse_optional_group(mcx.cx.session(), first) {
+        // Try to match the group first, and only leave it out if that fails
+        let orig_mcx = mcx.clone();
+        let with_group = group.into_iter().chain(rest.iter().cloned()).collect::<Vec<_>>();
+        if match_multi_expr(mcx, &with_group, target) {
+            return true;
+        }
+        *mcx = orig_mcx;
+        for name in optional_group_bindings(first) {
+            if !mcx.bindings.try_add_none(name) {
+                return false;
+            }
+        }
+        return match_multi_expr(mcx, rest, target);
+    }
+
+    if is_multi_expr_glob(mcx, first) {
+        let name = first.pattern_symbol().unwrap();
+        let min = if mcx.types.is_nonempty(&name) { 1 } else { 0 };
+        // Try the longest sequence first, and backtrack until the rest of the pattern matches
+        for i in (min..=target.len()).rev() {
+            let orig_mcx = mcx.clone();
+            if match_multi_expr(mcx, rest, &target[i..]) &&
+               mcx.bindings.try_add(name, target[..i].to_owned()) {
+                return true;
+            }
+            *mcx = orig_mcx;
+        }
+        false
+    } else {
+        match target.split_first() {
+            Some((t, target_rest)) => {
+                mcx.try_match(first, t).is_ok() && match_multi_expr(mcx, rest, target_rest)
+            }
+            None => false,
+        }
+    }
+}
+
+/// If `e` is an optional group `optional!(...)` (written as `$(...)?` in patterns), parse the
+/// list of expressions inside it.
+fn parse_optional_group(sess: &Session, e: &Expr) -> Option<Vec<P<Expr>>> {
+    let mac = match e.kind {
+        ExprKind::Mac(ref mac) if macro_name(mac).as_str() == "optional" => mac,
+        _ => return None,
+    };
+    let tts = mac.args.inner_tokens().into_trees().collect();
+    let exprs = driver::run_parser_tts(sess, tts, |p| {
+        let mut exprs = Vec::new();
+        while p.token.kind != TokenKind::Eof {
+            let mut expr = p.parse_expr()?;
+            remove_paren(&mut expr);
+            exprs.push(expr);
+            if !p.eat(&TokenKind::Comma) {
+                p.expect(&TokenKind::Eof)?;
+            }
+        }
+        Ok(exprs)
+    });
+    Some(exprs)
+}
+
+/// Split the path in a `def!` pattern into its `QSelf` and `Path`.
+fn def_qpath(e: P<Expr>) -> (Option<QSelf>, Path) {
+    match e.into_inner().kind {
+        ExprKind::Path(qself, path) => (qself, path),
+        kind => panic!("def!: expected a path, but got {:?}", kind),
+    }
+}
+
+/// Resolve every path `Expr` and `Ty` inside `e`, in order.  Paths that don't resolve, like
+/// nodes created by an earlier rewrite, give `None`.
+fn path_resolutions(cx: &RefactorCtxt, e: &Expr) -> Vec<Option<Res>> {
+    struct PathVisitor<'a, 'b, 'tcx> {
+        cx: &'a RefactorCtxt<'b, 'tcx>,
+        res: Vec<Option<Res>>,
+    }
+
+    impl<'a, 'b, 'tcx, 'ast> Visitor<'ast> for PathVisitor<'a, 'b, 'tcx> {
+        fn visit_expr(&mut self, e: &'ast Expr) {
+            if let ExprKind::Path(..) = e.kind {
+                self.res.push(self.cx.try_resolve_expr_hir(e));
+            }
+            visit::walk_expr(self, e);
+        }
+
+        fn visit_ty(&mut self, t: &'ast Ty) {
+            if let TyKind::Path(..) = t.kind {
+                self.res.push(self.cx.try_resolve_ty_hir(t));
+            }
+            visit::walk_ty(self, t);
+        }
+
+        fn visit_mac(&mut self, mac: &'ast Mac) {
+            visit::walk_mac(self, mac)
+        }
+    }
+
+    let mut v = PathVisitor { cx, res: Vec::new() };
+    v.visit_expr(e);
+    v.res
+}
+
+/// Collect the names of all the bindings inside the optional group `e`.
+fn optional_group_bindings(e: &Expr) -> Vec<Symbol> {
+    match e.kind {
+        ExprKind::Mac(ref mac) => macro_bindings(&mac.args),
+        _ => Vec::new(),
+    }
+}
+
+/// Collect the names of all the bindings mentioned in the arguments of a special matching form.
+fn macro_bindings(args: &MacArgs) -> Vec<Symbol> {
+    fn collect(tts: TokenStream, names: &mut Vec<Symbol>) {
+        for tt in tts.into_trees() {
+            match tt {
+                TokenTree::Token(token) => match token.kind {
+                    TokenKind::Ident(name, _) | TokenKind::Lifetime(name)
+                        if name.as_str().contains('$') => names.push(name),
+                    TokenKind::Literal(lit) if lit.symbol.as_str().starts_with('$') => {
+                        names.push(lit.symbol)
+                    }
+                    _ => {}
+                },
+                TokenTree::Delimited(_, _, tts) => collect(tts, names),
+            }
+        }
+    }
+
+    let mut names = Vec::new();
+    collect(args.inner_tokens(), &mut names);
+    names
+}
+
+/// Match the attributes of an item pattern against the attributes of an item.  Each attribute in
+/// the pattern has to match a different attribute of the item, in the same order, but the item
+/// can have other attributes too, so a pattern with no attributes matches items with any
+/// attributes.
+pub fn match_attrs(mcx: &mut MatchCtxt, pattern: &[Attribute], target: &[Attribute]) -> Result<()> {
+    let (first, rest) = match pattern.split_first() {
+        Some(x) => x,
+        None => return Ok(()),
+    };
+    for (i, attr) in target.iter().enumerate() {
+        let mut new_mcx = mcx.clone();
+        if new_mcx.try_match(first, attr).is_ok()
+            && match_attrs(&mut new_mcx, rest, &target[i + 1..]).is_ok()
+        {
+            *mcx = new_mcx;
+            return Ok(());
+        }
+    }
+    Err(Error::LengthMismatch)
+}
+
+/// Match the argument tokens of an attribute pattern against the tokens of an attribute,
+/// ignoring spans.  A `$x` token captures a single identifier, and a `$x:Lit` token captures a
+/// single literal.
+pub fn match_tokens(mcx: &mut MatchCtxt, pattern: &TokenStream, target: &TokenStream) -> Result<()> {
+    let pattern = pattern.trees().collect::<Vec<_>>();
+    let target = target.trees().collect::<Vec<_>>();
+    if pattern.len() != target.len() {
+        return Err(Error::LengthMismatch);
+    }
+
+    for (p, t) in pattern.iter().zip(target.iter()) {
+        match (p, t) {
+            (TokenTree::Token(p), TokenTree::Token(t)) => match (&p.kind, &t.kind) {
+                (&TokenKind::Ident(sym, _), &TokenKind::Ident(name, _))
+                    if sym.as_str().starts_with('$') =>
+                {
+                    if !mcx.bindings.try_add(sym, Ident::new(name, t.span)) {
+                        return Err(Error::NonlinearMismatch);
+                    }
+                }
+                (&TokenKind::Literal(lit), &TokenKind::Literal(target_lit))
+                    if lit.kind == TokenLitKind::Err && lit.symbol.as_str().starts_with('$') =>
+                {
+                    let target_lit = Lit::from_lit_token(target_lit, t.span)
+                        .map_err(|_| Error::VariantMismatch)?;
+                    if !mcx.bindings.try_add(lit.symbol, target_lit) {
+                        return Err(Error::NonlinearMismatch);
+                    }
+                }
+                (pk, tk) if pk == tk => {}
+                _ => return Err(Error::SymbolMismatch),
+            },
+            (TokenTree::Delimited(_, pd, ptts), TokenTree::Delimited(_, td, ttts)) if pd == td => {
+                match_tokens(mcx, ptts, ttts)?;
+            }
+            _ => return Err(Error::VariantMismatch),
+        }
+    }
+    Ok(())
+}
+
+fn is_multi_expr_glob(mcx: &MatchCtxt, pattern: &Expr) -> bool {
+    let sym = match pattern.pattern_symbol() {
+        Some(x) => x,
+        None => return false,
+    };
+
+    match mcx.types.get(&sym) {
+        Some(&bindings::Type::MultiExpr) => true,
+        _ => false,
+    }
+}
+
 impl Pattern<Vec<Stmt>> for Vec<Stmt> {
     fn visit<'a, 'tcx, T, F>(self, init_mcx: MatchCtxt<'a, 'tcx>, callback: F, target: &mut T)
     where
@@ -913,6 +1889,7 @@ impl Pattern<Vec<Stmt>> for Vec<Stmt> {
             pattern: self,
             init_mcx,
             callback,
+            scope: ScopeTracker::default(),
         };
         target.visit(&mut f)
     }
@@ -947,6 +1924,114 @@ pub fn mut_visit_match_with<'a, 'tcx, P, T, V, F>(
     pattern.visit(init_mcx, callback, target)
 }
 
+/// Find every expression within `target` that matches any of `patterns`, and rewrite it by
+/// invoking `callback` with the index of the pattern that matched.  The patterns are tried in
+/// order at each node, all in a single traversal.  If `chain` is set, the patterns after the one
+/// that matched are then tried on the rewritten node; otherwise, each node is rewritten at most
+/// once.  Rewritten nodes are never matched against earlier patterns again.
+pub fn mut_visit_match_rules_with<'a, 'tcx, T, F>(
+    init_mcx: MatchCtxt<'a, 'tcx>,
+    patterns: Vec<P<Expr>>,
+    chain: bool,
+    target: &mut T,
+    callback: F,
+) where
+    T: MutVisit,
+    F: FnMut(&mut P<Expr>, usize, MatchCtxt<'a, 'tcx>),
+{
+    let mut f = ExprRulesFolder {
+        patterns,
+        chain,
+        init_mcx,
+        callback,
+        scope: ScopeTracker::default(),
+    };
+    target.visit(&mut f)
+}
+
+/// Custom `Folder` for `mut_visit_match_rules_with`.
+struct ExprRulesFolder<'a, 'tcx: 'a, F>
+where
+    F: FnMut(&mut P<Expr>, usize, MatchCtxt<'a, 'tcx>),
+{
+    patterns: Vec<P<Expr>>,
+    chain: bool,
+    init_mcx: MatchCtxt<'a, 'tcx>,
+    callback: F,
+    scope: ScopeTracker,
+}
+
+impl<'a, 'tcx, F> MutVisitor for ExprRulesFolder<'a, 'tcx, F>
+where
+    F: FnMut(&mut P<Expr>, usize, MatchCtxt<'a, 'tcx>),
+{
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        mut_visit::noop_visit_expr(e, self);
+        if !self.scope.in_scope(self.init_mcx.scope()) {
+            return;
+        }
+
+        let id = e.id;
+        let in_macro = self.init_mcx.st.macro_expansion(id).is_some();
+        for (i, pattern) in self.patterns.iter().enumerate() {
+            if !self.init_mcx.can_match_in_macro(&*e) {
+                break;
+            }
+            if let Ok(mcx) = self.init_mcx.clone_match(pattern, &*e) {
+                (self.callback)(e, i, mcx);
+                if in_macro {
+                    e.set_node_id(id);
+                }
+                if !self.chain {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn flat_map_item(&mut self, i: P<Item>) -> SmallVec<[P<Item>; 1]> {
+        let name = item_scope_name(&i);
+        let saved = match self.init_mcx.enter_scope(&mut self.scope, name, i.id) {
+            Some(x) => x,
+            None => return smallvec![i],
+        };
+        let r = mut_visit::noop_flat_map_item(i, self);
+        self.init_mcx.leave_scope(&mut self.scope, saved);
+        r
+    }
+
+    fn flat_map_impl_item(&mut self, i: ImplItem) -> SmallVec<[ImplItem; 1]> {
+        let saved = match self.init_mcx.enter_scope(&mut self.scope, i.ident.name, i.id) {
+            Some(x) => x,
+            None => return smallvec![i],
+        };
+        let r = mut_visit::noop_flat_map_impl_item(i, self);
+        self.init_mcx.leave_scope(&mut self.scope, saved);
+        r
+    }
+}
+
+/// Find every window of consecutive statements within a block that matches the statement
+/// sequence `pattern`, and rewrite each one by invoking `callback`.  The callback can replace the
+/// window with any number of statements.  Nodes that the callback builds from scratch or copies
+/// more than once get fresh `NodeId`s afterward, while nodes moved over from the original window
+/// keep theirs.
+pub fn mut_visit_stmts_match_with<'a, 'tcx, T, F>(
+    init_mcx: MatchCtxt<'a, 'tcx>,
+    pattern: Vec<Stmt>,
+    target: &mut T,
+    mut callback: F,
+) where
+    T: MutVisit,
+    F: FnMut(&mut Vec<Stmt>, MatchCtxt<'a, 'tcx>),
+{
+    let st = init_mcx.st;
+    mut_visit_match_with(init_mcx, pattern, target, |stmts, mcx| {
+        callback(stmts, mcx);
+        number_new_nodes_with(stmts, st.node_id_counter());
+    })
+}
+
 pub fn flat_map_match_with<'a, 'tcx, P, T, V, F>(
     init_mcx: MatchCtxt<'a, 'tcx>,
     pattern: P,
@@ -1020,7 +2105,7 @@ pub fn replace_stmts<T: MutVisit>(
     let pat = mcx.parse_stmts(pat);
     let repl = mcx.parse_stmts(repl);
     // TODO: Make Subst modify in place
-    mut_visit_match_with(mcx, pat, ast, |x, mcx| {
+    mut_visit_stmts_match_with(mcx, pat, ast, |x, mcx| {
         *x = repl.clone().subst(st, cx, &mcx.bindings)
     })
 }
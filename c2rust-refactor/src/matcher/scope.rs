@@ -0,0 +1,146 @@
+//! Restricting pattern matching to some of the items in the crate.
+use syntax::ast::{Item, ItemKind, NodeId, TyKind};
+use syntax::symbol::Symbol;
+
+use crate::command::CommandState;
+
+/// A set of items that pattern matching is restricted to, selected by a path glob, a mark, or
+/// both.  The path glob narrows first: a node is only in scope if it's inside an item whose path
+/// matches the glob, and also inside an item (possibly a different one) with the mark.
+#[derive(Clone, Debug)]
+pub struct MatchScope {
+    /// Segments of the path glob, without the leading `crate`.
+    path: Option<Vec<String>>,
+    mark: Option<Symbol>,
+}
+
+impl MatchScope {
+    /// Build a scope from a path glob like `crate::parser::*`, and a mark label.  Each segment of
+    /// the glob can use `*` to match any sequence of characters, e.g., `crate::parser::parse_*`.
+    /// Items match if their own path matches the glob, and everything inside them is in scope.
+    pub fn new(path: Option<&str>, mark: Option<Symbol>) -> MatchScope {
+        let path = path.map(|p| {
+            let mut segs = p.split("::").map(|s| s.trim().to_owned()).collect::<Vec<_>>();
+            if segs.first().map_or(false, |s| s == "crate") {
+                segs.remove(0);
+            }
+            segs
+        });
+        MatchScope { path, mark }
+    }
+
+    /// Check the path of an item against the glob.  Returns `Some(true)` if the glob selects the
+    /// item, `Some(false)` if it doesn't but might select one of its children, and `None` if it
+    /// can't select anything inside the item.
+    fn check_path(&self, path: &[Symbol]) -> Option<bool> {
+        let glob = match self.path {
+            Some(ref glob) => glob,
+            None => return Some(true),
+        };
+        if path.len() > glob.len() {
+            return None;
+        }
+        for (pat, sym) in glob.iter().zip(path) {
+            if !glob_match(pat, &sym.as_str()) {
+                return None;
+            }
+        }
+        Some(path.len() == glob.len())
+    }
+}
+
+/// Match `s` against the glob `pat`, where `*` matches any sequence of characters.
+fn glob_match(pat: &str, s: &str) -> bool {
+    match pat.find('*') {
+        None => pat == s,
+        Some(i) => {
+            let (prefix, rest) = (&pat[..i], &pat[i + 1..]);
+            if !s.starts_with(prefix) {
+                return false;
+            }
+            let s = &s[prefix.len()..];
+            (0..=s.len())
+                .filter(|&j| s.is_char_boundary(j))
+                .any(|j| glob_match(rest, &s[j..]))
+        }
+    }
+}
+
+/// Tracks the items that a pattern folder is currently inside of, so it can skip the items
+/// outside of the `MatchScope`.
+#[derive(Clone, Debug, Default)]
+pub struct ScopeTracker {
+    path: Vec<Symbol>,
+    path_selected: bool,
+    marked: bool,
+}
+
+/// State saved by `ScopeTracker::enter`, to be restored by `ScopeTracker::leave`.
+pub struct SavedScope {
+    path_selected: bool,
+    marked: bool,
+}
+
+impl ScopeTracker {
+    /// Check whether nodes at the current position can be matched.
+    pub fn in_scope(&self, scope: Option<&MatchScope>) -> bool {
+        let scope = match scope {
+            Some(x) => x,
+            None => return true,
+        };
+        (scope.path.is_none() || self.path_selected) && (scope.mark.is_none() || self.marked)
+    }
+
+    /// Enter the item `name` with id `id`.  Returns `None` if nothing inside the item is in
+    /// scope, in which case the item should be skipped entirely.
+    pub fn enter(
+        &mut self,
+        scope: Option<&MatchScope>,
+        st: &CommandState,
+        name: Symbol,
+        id: NodeId,
+    ) -> Option<SavedScope> {
+        let saved = SavedScope {
+            path_selected: self.path_selected,
+            marked: self.marked,
+        };
+        if let Some(scope) = scope {
+            self.path.push(name);
+            if !self.path_selected {
+                match scope.check_path(&self.path) {
+                    Some(selected) => self.path_selected = selected,
+                    None => {
+                        self.path.pop();
+                        return None;
+                    }
+                }
+            }
+            if let Some(label) = scope.mark {
+                self.marked = self.marked || st.marked(id, label);
+            }
+        }
+        Some(saved)
+    }
+
+    pub fn leave(&mut self, scope: Option<&MatchScope>, saved: SavedScope) {
+        if scope.is_some() {
+            self.path.pop();
+        }
+        self.path_selected = saved.path_selected;
+        self.marked = saved.marked;
+    }
+}
+
+/// The name of `i` in item paths.  `impl` blocks are named after their self type, so methods get
+/// paths like `crate::parser::Parser::parse`.
+pub fn item_scope_name(i: &Item) -> Symbol {
+    match i.kind {
+        ItemKind::Impl(_, _, _, _, _, ref ty, _) => match ty.kind {
+            TyKind::Path(_, ref path) if !path.segments.is_empty() => {
+                path.segments.last().unwrap().ident.name
+            }
+            _ => i.ident.name,
+        },
+        _ => i.ident.name,
+    }
+}
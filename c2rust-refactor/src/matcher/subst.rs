@@ -16,27 +16,65 @@
 //!
 //!    For itemlikes, a lone ident can't be used as a placeholder because it's not a valid
 //!    itemlike.  Use a zero-argument macro invocation `__x!()` instead.
+//!
+//!  * `$x` or `$x:Expr*`: In the arguments of a call or method call, or the elements of an array
+//!    or tuple, a name bound to a sequence of expressions by a repetition pattern gets replaced
+//!    with all the expressions in the sequence, e.g., `foo($args)` becomes `foo(1, 2)`.
+//!
+//!  * Names that only appear inside `not!` patterns are never bound, and using one in the
+//!    template is an error.
+//!
+//!  * `#[$a]`: An attribute whose name is bound to an `Attribute` is replaced with it.  In the
+//!    arguments of other attributes, `$x` and `$x:Lit` tokens are replaced with the identifier or
+//!    literal bound to the name.
+//!
+//!  * `$(...)?`: In the same positions, an optional group is replaced with its contents if none
+//!    of the bindings inside it are absent, and dropped otherwise.
+//!
+//!  * `mark!($x [, label])`: Replaced with the expression bound to `$x`, which also gets marked
+//!    with `label` (default: "target").  The expression keeps its `NodeId`, so the mark can be
+//!    used by later commands, e.g., `rewrite_expr 'free($p:Expr)' 'free(mark!($p, freed))'`
+//!    followed by `rewrite_expr 'marked!($e:Expr, freed)' ...`.
+//!
+//!  * `$q:FnQual`: In the place of a function's ABI, as in `$q:FnQual fn f() {}`, this is
+//!    replaced with the qualifiers bound to `$q`.  Qualifiers written in the template, like the
+//!    `unsafe` in `unsafe $q:FnQual fn`, are kept as well.
+//!
+//!  * `ty_of!($x)` and `pointee_of!($x)`: In type positions, these are replaced with the type of
+//!    the expression bound to `$x`, or with the type it points to, e.g., `size_of::<pointee_of!(
+//!    $p)>()`.  If `$x` is a local, parameter, or static declared with an explicit type, the type
+//!    is copied from the declaration, so aliases keep their spelling.  Otherwise, the type comes
+//!    from the type checker.  If the type can't be written in Rust syntax, like the type of a
+//!    closure, the substitution fails.
 
+use rustc::hir::{self, Node};
+use rustc::ty::{self, TyKind as TyTyKind};
+use rustc_parse::parser::Parser;
 use smallvec::SmallVec;
-use syntax::ast::Mac;
-use syntax::ast::{Expr, ExprKind, Ident, ImplItem, Item, Label, Pat, Path, Stmt, Ty};
+use std::mem;
+use syntax::ast::{AttrKind, Attribute, Constness, FnHeader, Mac, MacArgs, Unsafety};
+use syntax::ast::{Expr, ExprKind, Ident, ImplItem, Item, Label, Lit, Pat, Path, Stmt, Ty, TyKind};
 use syntax::mut_visit::{self, MutVisitor};
 use syntax::ptr::P;
+use syntax::token::{Token, TokenKind};
+use syntax::tokenstream::{TokenStream, TokenTree};
 use smallvec::smallvec;
 
-use crate::ast_manip::util::PatternSymbol;
+use crate::ast_manip::util::{macro_name, PatternSymbol};
 use crate::ast_manip::{AstNode, MutVisit};
 use crate::command::CommandState;
-use crate::matcher::Bindings;
+use crate::driver;
+use crate::matcher::{optional_group_bindings, parse_optional_group, Bindings};
+use crate::reflect;
 use crate::RefactorCtxt;
+use c2rust_ast_builder::IntoSymbol;
 
-// `st` and `cx` were previously used for `def!` substitution, which has been removed.  I expect
-// they'll be needed again for future subst extensions, so I've left them in to reduce API churn.
-#[allow(unused)]
 struct SubstFolder<'a, 'tcx: 'a> {
     st: &'a CommandState,
     cx: &'a RefactorCtxt<'a, 'tcx>,
     bindings: &'a Bindings,
+    /// Set if some part of the template couldn't be substituted.
+    failed: bool,
 }
 
 impl<'a, 'tcx> SubstFolder<'a, 'tcx> {
@@ -59,6 +97,131 @@ impl<'a, 'tcx> SubstFolder<'a, 'tcx> {
         }
     }
 
+    /// Splice sequences of expressions captured by repetitions into `exprs`, and expand or
+    /// drop optional groups depending on whether their bindings are present.
+    fn subst_expr_list(&mut self, exprs: &mut Vec<P<Expr>>) {
+        let old_exprs = mem::replace(exprs, Vec::new());
+        for e in old_exprs {
+            self.subst_list_elem(e, exprs);
+        }
+    }
+
+    fn subst_list_elem(&mut self, e: P<Expr>, exprs: &mut Vec<P<Expr>>) {
+        if let Some(group) = parse_optional_group(self.cx.session(), &e) {
+            let bindings = self.bindings;
+            if optional_group_bindings(&e).into_iter().all(|sym| !bindings.is_absent(sym)) {
+                for ge in group {
+                    self.subst_list_elem(ge, exprs);
+                }
+            }
+        } else if let Some(binding) = e
+            .pattern_symbol()
+            .and_then(|sym| self.bindings.get::<_, Vec<P<Expr>>>(sym))
+        {
+            exprs.extend(binding.iter().cloned());
+        } else {
+            exprs.push(e);
+        }
+    }
+
+    /// Handle a `mark!($x, label)` form: look up the expression bound to `$x`, and mark it.
+    fn mark_binding(&self, mac: &Mac) -> P<Expr> {
+        let mut p = Parser::new(
+            &self.cx.session().parse_sess,
+            mac.args.inner_tokens(),
+            None,
+            false,
+            false,
+            None,
+        );
+        let arg = p.parse_expr().unwrap();
+        let label = if p.eat(&TokenKind::Comma) {
+            p.parse_ident().unwrap().name
+        } else {
+            "target".into_symbol()
+        };
+
+        let e = arg
+            .pattern_symbol()
+            .and_then(|sym| self.bindings.get::<_, P<Expr>>(sym))
+            .unwrap_or_else(|| panic!("mark!() requires an Expr binding, but got {:?}", arg));
+        self.st.add_mark(e.id, label);
+        e.clone()
+    }
+
+    /// Build the type for a `ty_of!($x)` or `pointee_of!($x)` form.  Returns `None` if the type
+    /// can't be written in Rust syntax.
+    fn binding_ty(&self, mac: &Mac, pointee: bool) -> Option<P<Ty>> {
+        let mut p = Parser::new(
+            &self.cx.session().parse_sess,
+            mac.args.inner_tokens(),
+            None,
+            false,
+            false,
+            None,
+        );
+        let arg = p.parse_expr().unwrap();
+        let sym = arg
+            .pattern_symbol()
+            .unwrap_or_else(|| panic!("{}!() requires a binding, but got {:?}", macro_name(mac), arg));
+        let e = self
+            .bindings
+            .get::<_, P<Expr>>(sym)
+            .unwrap_or_else(|| panic!("{}!() requires an Expr binding for {:?}", macro_name(mac), sym));
+
+        assert!(self.cx.has_ty_ctxt(), "{}!() requires type information", macro_name(mac));
+        self.cx.opt_node_to_hir_id(e.id)?;
+
+        // Copy the declared type if it's the type the checker used for this expression.
+        if self.cx.opt_node_type(e.id) == self.cx.opt_adjusted_node_type(e.id) {
+            if let Some(ty) = declared_ty(self.cx, e) {
+                if !pointee {
+                    return Some(ty);
+                }
+                match ty.kind {
+                    TyKind::Ptr(ref mty) | TyKind::Rptr(_, ref mty) => return Some(mty.ty.clone()),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut ty = self.cx.opt_adjusted_node_type(e.id)?;
+        if pointee {
+            ty = ty.builtin_deref(true)?.ty;
+        }
+        if ty.walk().any(|t| !can_reflect_ty(t)) {
+            return None;
+        }
+        Some(reflect::reflect_tcx_ty(self.cx.ty_ctxt(), ty))
+    }
+
+    /// Replace the `$x` tokens in the arguments of an attribute with the identifiers and
+    /// literals they're bound to.
+    fn subst_tokens(&mut self, ts: &TokenStream) -> TokenStream {
+        ts.trees()
+            .map(|tt| match tt {
+                TokenTree::Token(Token { kind: TokenKind::Ident(sym, is_raw), span }) => {
+                    if let Some(i) = self.bindings.get::<_, Ident>(sym) {
+                        TokenTree::token(TokenKind::Ident(i.name, false), span)
+                    } else if let Some(l) = self.bindings.get::<_, Lit>(sym) {
+                        TokenTree::token(TokenKind::Literal(l.token), span)
+                    } else {
+                        TokenTree::token(TokenKind::Ident(sym, is_raw), span)
+                    }
+                }
+                TokenTree::Token(Token { kind: TokenKind::Literal(lit), span }) => {
+                    match self.bindings.get::<_, Lit>(lit.symbol) {
+                        Some(l) => TokenTree::token(TokenKind::Literal(l.token), span),
+                        None => TokenTree::token(TokenKind::Literal(lit), span),
+                    }
+                }
+                TokenTree::Delimited(sp, delim, tts) => {
+                    TokenTree::Delimited(sp, delim, self.subst_tokens(&tts))
+                }
+                tt => tt,
+            })
+            .collect()
+    }
 }
 
 impl<'a, 'tcx> MutVisitor for SubstFolder<'a, 'tcx> {
@@ -70,6 +233,13 @@ impl<'a, 'tcx> MutVisitor for SubstFolder<'a, 'tcx> {
         // but is not an `Ident`.
 
         if let Some(sym) = i.pattern_symbol() {
+            if self.bindings.is_negated(sym) {
+                panic!(
+                    "binding {:?} only appears inside a not!() pattern, so it can't be used \
+                     in the replacement",
+                    sym
+                );
+            }
             if let Some(binding) = self.bindings.get::<_, Ident>(sym) {
                 *i = *binding;
             } else if let Some(ty) = self.bindings.get::<_, P<Ty>>(sym) {
@@ -95,6 +265,17 @@ impl<'a, 'tcx> MutVisitor for SubstFolder<'a, 'tcx> {
     }
 
     fn visit_expr(&mut self, e: &mut P<Expr>) {
+        let marked = match e.kind {
+            ExprKind::Mac(ref mac) if macro_name(mac).as_str() == "mark" => {
+                Some(self.mark_binding(mac))
+            }
+            _ => None,
+        };
+        if let Some(marked) = marked {
+            *e = marked;
+            return;
+        }
+
         if let Some(sym) = e.pattern_symbol() {
             if let Some(binding) = self.bindings.get::<_, P<Expr>>(sym) {
                 *e = binding.clone();
@@ -117,6 +298,16 @@ impl<'a, 'tcx> MutVisitor for SubstFolder<'a, 'tcx> {
             _ => {}
         }
 
+        match e.kind {
+            ExprKind::Call(_, ref mut args) |
+            ExprKind::MethodCall(_, ref mut args) |
+            ExprKind::Array(ref mut args) |
+            ExprKind::Tup(ref mut args) => {
+                self.subst_expr_list(args);
+            }
+            _ => {}
+        }
+
         mut_visit::noop_visit_expr(e, self);
     }
 
@@ -132,6 +323,27 @@ impl<'a, 'tcx> MutVisitor for SubstFolder<'a, 'tcx> {
     }
 
     fn visit_ty(&mut self, ty: &mut P<Ty>) {
+        let binding_ty = match ty.kind {
+            TyKind::Mac(ref mac) if macro_name(mac).as_str() == "ty_of" => {
+                Some(self.binding_ty(mac, false))
+            }
+            TyKind::Mac(ref mac) if macro_name(mac).as_str() == "pointee_of" => {
+                Some(self.binding_ty(mac, true))
+            }
+            _ => None,
+        };
+        match binding_ty {
+            Some(Some(new_ty)) => {
+                *ty = new_ty;
+                return;
+            }
+            Some(None) => {
+                self.failed = true;
+                return;
+            }
+            None => {}
+        }
+
         if let Some(sym) = ty.pattern_symbol() {
             if let Some(binding) = self.bindings.get::<_, P<Ty>>(sym) {
                 *ty = binding.clone();
@@ -170,40 +382,152 @@ impl<'a, 'tcx> MutVisitor for SubstFolder<'a, 'tcx> {
         }
     }
 
+    fn visit_fn_header(&mut self, header: &mut FnHeader) {
+        if let Some(quals) = header
+            .pattern_symbol()
+            .and_then(|sym| self.bindings.get::<_, FnHeader>(sym))
+        {
+            if quals.unsafety == Unsafety::Unsafe {
+                header.unsafety = Unsafety::Unsafe;
+            }
+            if quals.constness.node == Constness::Const {
+                header.constness = quals.constness;
+            }
+            if quals.asyncness.node.is_async() {
+                header.asyncness = quals.asyncness;
+            }
+            header.ext = quals.ext;
+        }
+
+        mut_visit::noop_visit_fn_header(header, self)
+    }
+
+    fn visit_attribute(&mut self, attr: &mut Attribute) {
+        if let Some(binding) = attr
+            .pattern_symbol()
+            .and_then(|sym| self.bindings.get::<_, Attribute>(sym))
+        {
+            *attr = binding.clone();
+            return;
+        }
+
+        if let AttrKind::Normal(ref mut item) = attr.kind {
+            match item.args {
+                MacArgs::Delimited(_, _, ref mut tts) | MacArgs::Eq(_, ref mut tts) => {
+                    *tts = self.subst_tokens(tts);
+                }
+                MacArgs::Empty => {}
+            }
+        }
+        mut_visit::noop_visit_attribute(attr, self)
+    }
+
     fn visit_mac(&mut self, mac: &mut Mac) {
         mut_visit::noop_visit_mac(mac, self)
     }
 }
 
-pub trait Subst {
-    fn subst(self, st: &CommandState, cx: &RefactorCtxt, bindings: &Bindings) -> Self;
+/// Find the type written in the declaration of the local, parameter, or static that `e` refers
+/// to, if there is one.
+fn declared_ty(cx: &RefactorCtxt, e: &Expr) -> Option<P<Ty>> {
+    match e.kind {
+        ExprKind::Path(..) => {}
+        _ => return None,
+    }
+    let hir_map = cx.hir_map();
+    let hir_id = cx.try_resolve_expr_to_hid(e)?;
+    let hir_ty: &hir::Ty = match hir_map.find(hir_id)? {
+        Node::Binding(_) => match hir_map.find(hir_map.get_parent_node(hir_id))? {
+            Node::Local(l) if l.pat.hir_id == hir_id => &**l.ty.as_ref()?,
+            Node::Param(param) if param.pat.hir_id == hir_id => {
+                let owner = hir_map.get_parent_item(hir_id);
+                let decl = hir_map.fn_decl_by_hir_id(owner)?;
+                let body = hir_map.body(hir_map.maybe_body_owned_by(owner)?);
+                let idx = body.params.iter().position(|p| p.hir_id == param.hir_id)?;
+                &decl.inputs[idx]
+            }
+            _ => return None,
+        },
+        Node::Item(item) => match item.kind {
+            hir::ItemKind::Static(ref ty, _, _) | hir::ItemKind::Const(ref ty, _) => &**ty,
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    if hir_ty.span.from_expansion() {
+        return None;
+    }
+    let src = cx.session().source_map().span_to_snippet(hir_ty.span).ok()?;
+    Some(driver::parse_ty(cx.session(), &src))
 }
 
-impl Subst for AstNode {
+/// Check whether `reflect` can turn `ty` (not counting its components) into a type AST that
+/// means the same thing.
+fn can_reflect_ty(ty: ty::Ty) -> bool {
+    match ty.kind {
+        TyTyKind::FnPtr(sig) => sig.no_bound_vars().is_some(),
+        TyTyKind::FnDef(..) |
+        TyTyKind::Dynamic(..) |
+        TyTyKind::Closure(..) |
+        TyTyKind::Generator(..) |
+        TyTyKind::GeneratorWitness(..) |
+        TyTyKind::Projection(..) |
+        TyTyKind::UnnormalizedProjection(..) |
+        TyTyKind::Opaque(..) |
+        TyTyKind::Bound(..) |
+        TyTyKind::Placeholder(..) |
+        TyTyKind::Infer(..) |
+        TyTyKind::Error => false,
+        _ => true,
+    }
+}
+
+pub trait Subst: Sized {
+    /// Substitute `bindings` into this template.  Returns `None` if some part of the template
+    /// can't be substituted, like a `ty_of!` form for an expression whose type can't be written.
+    fn try_subst(self, st: &CommandState, cx: &RefactorCtxt, bindings: &Bindings) -> Option<Self>;
+
     fn subst(self, st: &CommandState, cx: &RefactorCtxt, bindings: &Bindings) -> Self {
-        match self {
+        self.try_subst(st, cx, bindings)
+            .unwrap_or_else(|| panic!("substitution failed: a type in the template can't be written"))
+    }
+}
+
+impl Subst for AstNode {
+    fn try_subst(self, st: &CommandState, cx: &RefactorCtxt, bindings: &Bindings) -> Option<Self> {
+        Some(match self {
             AstNode::Crate(_) => panic!("Can't subst Crates"),
-            AstNode::Expr(x) => AstNode::Expr(x.subst(st, cx, bindings)),
-            AstNode::Pat(x) => AstNode::Pat(x.subst(st, cx, bindings)),
-            AstNode::Ty(x) => AstNode::Ty(x.subst(st, cx, bindings)),
-            AstNode::Stmts(x) => AstNode::Stmts(x.subst(st, cx, bindings)),
-            AstNode::Stmt(x) => AstNode::Stmt(x.subst(st, cx, bindings)),
-            AstNode::Item(x) => AstNode::Item(x.subst(st, cx, bindings)),
-        }
+            AstNode::Expr(x) => AstNode::Expr(x.try_subst(st, cx, bindings)?),
+            AstNode::Pat(x) => AstNode::Pat(x.try_subst(st, cx, bindings)?),
+            AstNode::Ty(x) => AstNode::Ty(x.try_subst(st, cx, bindings)?),
+            AstNode::Stmts(x) => AstNode::Stmts(x.try_subst(st, cx, bindings)?),
+            AstNode::Stmt(x) => AstNode::Stmt(x.try_subst(st, cx, bindings)?),
+            AstNode::Item(x) => AstNode::Item(x.try_subst(st, cx, bindings)?),
+        })
     }
 }
 
 macro_rules! subst_impl {
     ($ty:ty, $fold_func:ident) => {
         impl Subst for $ty {
-            fn subst(mut self, st: &CommandState, cx: &RefactorCtxt, bindings: &Bindings) -> Self {
+            fn try_subst(
+                mut self,
+                st: &CommandState,
+                cx: &RefactorCtxt,
+                bindings: &Bindings,
+            ) -> Option<Self> {
                 let mut f = SubstFolder {
                     st: st,
                     cx: cx,
                     bindings: bindings,
+                    failed: false,
                 };
                 self.visit(&mut f);
-                self
+                if f.failed {
+                    return None;
+                }
+                Some(self)
             }
         }
     };
@@ -212,17 +536,26 @@ macro_rules! subst_impl {
 macro_rules! multi_subst_impl {
     ($ty:ty, $fold_func:ident) => {
         impl Subst for Vec<$ty> {
-            fn subst(self, st: &CommandState, cx: &RefactorCtxt, bindings: &Bindings) -> Self {
+            fn try_subst(
+                self,
+                st: &CommandState,
+                cx: &RefactorCtxt,
+                bindings: &Bindings,
+            ) -> Option<Self> {
                 let mut f = SubstFolder {
                     st: st,
                     cx: cx,
                     bindings: bindings,
+                    failed: false,
                 };
                 let mut results = Vec::with_capacity(self.len());
                 for x in self {
                     results.extend_from_slice(&x.flat_map(&mut f));
                 }
-                results
+                if f.failed {
+                    return None;
+                }
+                Some(results)
             }
         }
     };
@@ -235,6 +568,7 @@ subst_impl!(P<Ty>, fold_ty);
 subst_impl!(Stmt, fold_stmt);
 subst_impl!(P<Item>, fold_item);
 subst_impl!(ImplItem, fold_impl_item);
+subst_impl!(Attribute, fold_attribute);
 
 multi_subst_impl!(Stmt, fold_stmt);
 multi_subst_impl!(P<Item>, fold_item);
@@ -12,6 +12,7 @@ use syntax_pos::FileName;
 
 use crate::ast_manip::Visit;
 use crate::command::{DriverCommand, Registry};
+use crate::command_args::{ArgType, CommandSpec};
 use crate::driver::Phase;
 use crate::RefactorCtxt;
 
@@ -334,8 +335,6 @@ pub fn pick_node_at_loc(
 ///
 /// Test command - not intended for general use.
 ///
-/// Usage: `pick_node KIND FILE LINE COL`
-///
 /// Find a node of kind `KIND` at location `FILE:LINE:COL`.
 /// If successful, logs the node's ID and span at level `info`.
 pub fn pick_node_command(krate: &Crate, cx: &RefactorCtxt, args: &[String]) {
@@ -371,10 +370,20 @@ pub fn pick_node_command(krate: &Crate, cx: &RefactorCtxt, args: &[String]) {
 }
 
 pub fn register_commands(reg: &mut Registry) {
-    reg.register("pick_node", |args| {
-        let args = args.to_owned();
-        Box::new(DriverCommand::new(Phase::Phase2, move |st, cx| {
+    let spec = CommandSpec::new("Find a node of kind KIND at FILE:LINE:COL and log it.")
+        .arg("KIND", ArgType::Str, "the kind of node, such as `item` or `expr`")
+        .arg("FILE", ArgType::Str, "the source file")
+        .arg("LINE", ArgType::Int, "the line number")
+        .arg("COL", ArgType::Int, "the column number");
+    reg.register("pick_node", spec, |args| {
+        let args = vec![
+            args.str("KIND").to_owned(),
+            args.str("FILE").to_owned(),
+            args.int("LINE").to_string(),
+            args.int("COL").to_string(),
+        ];
+        Ok(Box::new(DriverCommand::new(Phase::Phase2, move |st, cx| {
             pick_node_command(&st.krate(), &cx, &args);
-        }))
+        })))
     });
 }
@@ -7,6 +7,7 @@ use syntax::visit::Visitor;
 
 use crate::ast_manip::{visit_nodes, Visit};
 use crate::command::{DriverCommand, Registry};
+use crate::command_args::CommandSpec;
 use crate::driver::Phase;
 
 struct PrintSpanVisitor<'a> {
@@ -122,15 +123,14 @@ pub fn print_one_span<T: Visit>(id: usize, root: &T, cm: &SourceMap, msg: &str)
 ///
 /// Test command - not intended for general use.
 ///
-/// Usage: `print_spans`
-///
 /// Print IDs, spans, and pretty-printed source for all
 /// exprs, pats, tys, stmts, and items.
 fn register_print_spans(reg: &mut Registry) {
-    reg.register("print_spans", |_args| {
-        Box::new(DriverCommand::new(Phase::Phase2, move |st, cx| {
+    let spec = CommandSpec::new("Print the IDs, spans, and source of all nodes.");
+    reg.register("print_spans", spec, |_args| {
+        Ok(Box::new(DriverCommand::new(Phase::Phase2, move |st, cx| {
             print_spans(&st.krate() as &Crate, cx.session().source_map());
-        }))
+        })))
     });
 }
 
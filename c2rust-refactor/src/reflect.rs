@@ -17,6 +17,7 @@ use std::collections::HashMap;
 
 use crate::ast_manip::MutVisitNodes;
 use crate::command::{DriverCommand, Registry};
+use crate::command_args::CommandSpec;
 use crate::context::RefactorCtxt;
 use crate::driver::Phase;
 
@@ -358,12 +359,11 @@ pub fn can_reflect_path(cx: &RefactorCtxt, id: NodeId) -> bool {
 ///
 /// Test command - not intended for general use.
 ///
-/// Usage: `test_reflect`
-///
 /// Applies path and ty reflection on every expr in the program.
 fn register_test_reflect(reg: &mut Registry) {
-    reg.register("test_reflect", |_args| {
-        Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+    let spec = CommandSpec::new("Apply path and type reflection to every expression.");
+    reg.register("test_reflect", spec, |_args| {
+        Ok(Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
             let reflector = Reflector::new(cx.ty_ctxt());
             st.map_krate(|krate| {
                 use rustc::ty::TyKind;
@@ -395,7 +395,7 @@ fn register_test_reflect(reg: &mut Registry) {
                     *e = mk().type_expr(new_expr, reflect_tcx_ty(cx.ty_ctxt(), ty));
                 });
             });
-        }))
+        })))
     });
 }
 
@@ -0,0 +1,76 @@
+//! Summaries of the rewrites a command made, so large runs can be audited by category instead of
+//! by reading through the whole diff.
+use indexmap::IndexMap;
+use json::{self, JsonValue};
+use std::fs;
+
+/// Counts of the rewrites a command made, grouped by category, along with the source location
+/// of each one.
+pub struct Report {
+    title: String,
+    output: Option<String>,
+    categories: IndexMap<String, Vec<String>>,
+}
+
+impl Report {
+    /// Create an empty report.  If `output` is set, the report also gets written there as JSON
+    /// when it's emitted.
+    pub fn new(title: &str, output: Option<String>) -> Report {
+        Report {
+            title: title.to_owned(),
+            output,
+            categories: IndexMap::new(),
+        }
+    }
+
+    /// Record one rewrite of kind `category` at `location`.
+    pub fn record(&mut self, category: &str, location: String) {
+        if let Some(locs) = self.categories.get_mut(category) {
+            locs.push(location);
+            return;
+        }
+        self.categories.insert(category.to_owned(), vec![location]);
+    }
+
+    /// Merge the entries of `other` into this report.
+    pub fn merge(&mut self, other: Report) {
+        for (category, locs) in other.categories {
+            self.categories.entry(category).or_insert_with(Vec::new).extend(locs);
+        }
+    }
+
+    /// Print a summary table to stderr, and write the JSON version of the report to the
+    /// requested output file, if any.
+    pub fn emit(&self) {
+        let width = self.categories.keys().map(|c| c.len()).max().unwrap_or(0);
+        eprintln!("{}:", self.title);
+        for (category, locs) in &self.categories {
+            eprintln!("  {:width$}  {:>8}", category, locs.len(), width = width);
+        }
+        if self.categories.is_empty() {
+            eprintln!("  (no rewrites)");
+        }
+
+        if let Some(ref path) = self.output {
+            let s = json::stringify_pretty(self.encode(), 2);
+            if let Err(e) = fs::write(path, s) {
+                warn!("failed to write report to {}: {}", path, e);
+            }
+        }
+    }
+
+    fn encode(&self) -> JsonValue {
+        let mut categories = JsonValue::new_object();
+        for (category, locs) in &self.categories {
+            categories[category.as_str()] = object! {
+                "count" => locs.len(),
+                "locations" => JsonValue::Array(
+                    locs.iter().map(|loc| loc.as_str().into()).collect()),
+            };
+        }
+        object! {
+            "title" => self.title.as_str(),
+            "categories" => categories,
+        }
+    }
+}
@@ -1,17 +1,50 @@
 //! Code for applying `TextRewrite`s to the actual source files.
 use diff;
+use rustc_data_structures::sync::Lrc;
 use std::collections::{HashMap, VecDeque};
-use std::io;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::slice;
+use syntax::ast::NodeId;
 use syntax::source_map::{SourceFile, SourceMap};
-use syntax_pos::{BytePos, FileName};
+use syntax_pos::{BytePos, FileName, Span};
 
 use crate::file_io::FileIO;
 use crate::rewrite::cleanup::cleanup_rewrites;
 use crate::rewrite::{TextAdjust, TextRewrite};
 
-/// Apply a sequence of rewrites to the source code, handling the results by passing the new text
-/// to `callback` along with the `SourceFile` describing the original source file.
-pub fn rewrite_files_with(cm: &SourceMap, rw: &TextRewrite, io: &dyn FileIO) -> io::Result<()> {
+/// The edits that a rewrite makes to one file.
+pub struct FileEdits {
+    pub sf: Lrc<SourceFile>,
+    pub path: PathBuf,
+    /// Whether rewriting creates the file: it has no text yet, and doesn't exist on disk.
+    pub created: bool,
+    /// The rewrites in the file, as `FileIO::save_rewrites` gets them.
+    pub rewrites: Vec<TextRewrite>,
+    pub nodes: Vec<(Span, NodeId)>,
+    /// The same rewrites, as replacements of byte ranges of the file's old text, in order.
+    pub edits: Vec<TextEdit>,
+}
+
+/// A replacement of the bytes `lo .. hi` of a file's old text with `text`.
+pub struct TextEdit {
+    pub lo: usize,
+    pub hi: usize,
+    pub text: String,
+    /// The span of the replaced text.
+    pub old_span: Span,
+    /// The IDs of the new nodes printed in `text`.
+    pub nodes: Vec<NodeId>,
+    /// The commands that produced the edit, if they're being tracked.
+    pub commands: Vec<String>,
+    /// The labels of the marks on the nodes that the edit touches, if commands are being
+    /// tracked.
+    pub labels: Vec<String>,
+}
+
+/// Split the rewrites in `rw` by file, and turn them into `TextEdit`s.  The files are sorted by
+/// path.
+pub fn file_edits(cm: &SourceMap, rw: &TextRewrite, io: &dyn FileIO) -> Vec<FileEdits> {
     let mut by_file = HashMap::new();
 
     for rw in &rw.rewrites {
@@ -34,23 +67,94 @@ pub fn rewrite_files_with(cm: &SourceMap, rw: &TextRewrite, io: &dyn FileIO) ->
             .push((span, id));
     }
 
+    let mut files = Vec::new();
     for (_, (rewrites, nodes, sf)) in by_file {
         let path = match sf.name {
-            FileName::Real(ref path) => path,
+            FileName::Real(ref path) => path.clone(),
             _ => {
                 warn!("can't rewrite virtual file {:?}", sf.name);
                 continue;
             }
         };
 
-        // TODO: do something with nodes
-        io.save_rewrites(cm, &sf, &rewrites, &nodes)?;
-        let mut buf = String::new();
-        let rewrites = cleanup_rewrites(cm, rewrites);
-        rewrite_range(cm, sf.start_pos, sf.end_pos, &rewrites, &mut |s| {
-            buf.push_str(s)
+        // Files from other crates' metadata don't have their text loaded, so there's nothing to
+        // apply the edits to
+        let src_empty = match sf.src {
+            Some(ref src) => src.is_empty(),
+            None => {
+                warn!("can't rewrite {:?}: its source text isn't loaded", path);
+                continue;
+            }
+        };
+        let created = src_empty && !io.file_exists(&path);
+        let edits = cleanup_rewrites(cm, rewrites.clone())
+            .iter()
+            .map(|rw| text_edit(cm, rw))
+            .collect();
+        files.push(FileEdits {
+            sf,
+            path,
+            created,
+            rewrites,
+            nodes,
+            edits,
         });
-        io.write_file(path, &buf)?;
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    files
+}
+
+/// Turn the top-level rewrite `rw` into an edit of its file.
+fn text_edit(cm: &SourceMap, rw: &TextRewrite) -> TextEdit {
+    fn collect_nodes(rw: &TextRewrite, nodes: &mut Vec<NodeId>) {
+        nodes.extend(rw.nodes.iter().map(|&(_, id)| id));
+        for rw in &rw.rewrites {
+            collect_nodes(rw, nodes);
+        }
+    }
+
+    let mut text = String::new();
+    rewrite_range(cm, rw.old_span.lo(), rw.old_span.hi(), slice::from_ref(rw), &mut |s| {
+        text.push_str(s)
+    });
+    let mut nodes = Vec::new();
+    collect_nodes(rw, &mut nodes);
+    TextEdit {
+        lo: cm.lookup_byte_offset(rw.old_span.lo()).pos.0 as usize,
+        hi: cm.lookup_byte_offset(rw.old_span.hi()).pos.0 as usize,
+        text,
+        old_span: rw.old_span,
+        nodes,
+        commands: Vec::new(),
+        labels: Vec::new(),
+    }
+}
+
+/// Apply `edits`, which must be in order and must not overlap, to the text `old`.
+pub fn apply_edits(old: &str, edits: &[TextEdit]) -> String {
+    let mut new = String::new();
+    let mut cur = 0;
+    for e in edits {
+        new.push_str(&old[cur..e.lo]);
+        new.push_str(&e.text);
+        cur = e.hi;
+    }
+    new.push_str(&old[cur..]);
+    new
+}
+
+/// Write the new text of each file in `files` through `io`.
+pub fn write_file_edits(cm: &SourceMap, files: &[FileEdits], io: &dyn FileIO) -> io::Result<()> {
+    for f in files {
+        // TODO: do something with nodes
+        io.save_rewrites(cm, &f.sf, &f.rewrites, &f.nodes)?;
+        let old = f.sf.src.as_ref().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("source text of {:?} isn't loaded", f.path),
+            )
+        })?;
+        io.write_file(&f.path, &apply_edits(old, &f.edits))?;
     }
 
     io.end_rewrite(cm)?;
@@ -58,6 +162,12 @@ pub fn rewrite_files_with(cm: &SourceMap, rw: &TextRewrite, io: &dyn FileIO) ->
     Ok(())
 }
 
+/// Apply a sequence of rewrites to the source code, and write the new text of each file through
+/// `io`.
+pub fn rewrite_files_with(cm: &SourceMap, rw: &TextRewrite, io: &dyn FileIO) -> io::Result<()> {
+    write_file_edits(cm, &file_edits(cm, rw, io), io)
+}
+
 #[allow(dead_code)] // Helper function for debugging
 fn print_rewrite(rw: &TextRewrite, depth: usize) {
     for _ in 0..depth {
@@ -143,8 +253,8 @@ fn emit_chunk<F: FnMut(&str)>(cm: &SourceMap, lo: BytePos, hi: BytePos, mut call
     callback(&src[lo.pos.0 as usize..hi.pos.0 as usize]);
 }
 
-/// Print a unified diff between lines of `s1` and lines of `s2`.
-pub fn print_diff(s1: &str, s2: &str) {
+/// Write the hunks of a unified diff between lines of `s1` and lines of `s2` to `out`.
+pub fn write_diff<W: Write>(out: &mut W, s1: &str, s2: &str) -> io::Result<()> {
     enum State {
         /// We're not in a hunk, just keeping `buf` populated with `CONTEXT` lines of history.
         History,
@@ -206,7 +316,7 @@ pub fn print_diff(s1: &str, s2: &str) {
                         // End of the hunk
                         let end = buf.len() - CONTEXT;
                         let suffix = buf.split_off(end);
-                        print_hunk(&buf, l_start, r_start);
+                        write_hunk(out, &buf, l_start, r_start)?;
                         buf = suffix;
                         state = State::History;
                     } else {
@@ -251,15 +361,21 @@ pub fn print_diff(s1: &str, s2: &str) {
                 let end = buf.len() - (CONTEXT - unchanged_limit);
                 buf.truncate(end);
             }
-            print_hunk(&buf, l_start, r_start);
+            write_hunk(out, &buf, l_start, r_start)?;
         }
         _ => {}
     }
+    Ok(())
 }
 
-/// Print a single diff hunk, starting at line `l_start` in the left file and `r_start` in the
+/// Write a single diff hunk, starting at line `l_start` in the left file and `r_start` in the
 /// right file.
-fn print_hunk(buf: &VecDeque<diff::Result<&str>>, l_start: usize, r_start: usize) {
+fn write_hunk<W: Write>(
+    out: &mut W,
+    buf: &VecDeque<diff::Result<&str>>,
+    l_start: usize,
+    r_start: usize,
+) -> io::Result<()> {
     let l_size = buf
         .iter()
         .filter(|r| match r {
@@ -276,7 +392,10 @@ fn print_hunk(buf: &VecDeque<diff::Result<&str>>, l_start: usize, r_start: usize
         })
         .count();
 
-    println!("@@ -{},{} +{},{} @@", l_start, l_size, r_start, r_size);
+    // An empty range starts at the line before it, e.g. `-0,0` for a new file.
+    let l_start = if l_size == 0 { l_start - 1 } else { l_start };
+    let r_start = if r_size == 0 { r_start - 1 } else { r_start };
+    writeln!(out, "@@ -{},{} +{},{} @@", l_start, l_size, r_start, r_size)?;
 
     // Print all "left" lines immediately.  Keep all "right" lines and print them just before the
     // next unchanged line.  This way we get the usual output, with separate old and new blocks:
@@ -290,22 +409,27 @@ fn print_hunk(buf: &VecDeque<diff::Result<&str>>, l_start: usize, r_start: usize
     for r in buf {
         match r {
             diff::Result::Left(s) => {
-                println!("-{}", s);
+                writeln!(out, "-{}", s)?;
             }
             diff::Result::Right(s) => {
                 right_buf.push(s);
             }
             diff::Result::Both(s1, s2) => {
                 if s1 != s2 {
-                    println!("-{}", s1);
+                    writeln!(out, "-{}", s1)?;
                     right_buf.push(s2);
                 } else {
                     for s in right_buf.drain(..) {
-                        println!("+{}", s);
+                        writeln!(out, "+{}", s)?;
                     }
-                    println!(" {}", s1);
+                    writeln!(out, " {}", s1)?;
                 }
             }
         }
     }
+    // A hunk at the end of the file has no unchanged line after it.
+    for s in right_buf {
+        writeln!(out, "+{}", s)?;
+    }
+    Ok(())
 }
@@ -5,6 +5,7 @@ use syntax::source_map::{SourceMap, Span};
 use syntax::symbol::Symbol;
 use syntax::visit::{self, FnKind, Visitor};
 
+use crate::rewrite::files::{apply_edits, FileEdits, TextEdit};
 use crate::rewrite::{TextAdjust, TextRewrite};
 
 fn encode_span(sm: &SourceMap, sp: Span) -> JsonValue {
@@ -73,6 +74,49 @@ pub fn stringify_rewrites(sm: &SourceMap, rs: &[TextRewrite]) -> String {
     json::stringify_pretty(encode_rewrites(sm, rs), 2)
 }
 
+/// Encode the edits of one rewrite, like an LSP `WorkspaceEdit`.  Each edit replaces a byte range
+/// of its file's text as of the last time the crate was loaded, whose count is `base`, so of the
+/// edits with the same `base`, only the last set applies.  New files are listed separately with
+/// their full text.
+pub fn encode_file_edits(base: usize, files: &[FileEdits]) -> JsonValue {
+    let changes = files
+        .iter()
+        .filter(|f| !f.created)
+        .map(|f| {
+            object! {
+                "file" => f.path.display().to_string(),
+                "edits" => JsonValue::Array(f.edits.iter().map(encode_text_edit).collect()),
+            }
+        })
+        .collect();
+    let created = files
+        .iter()
+        .filter(|f| f.created)
+        .map(|f| {
+            object! {
+                "file" => f.path.display().to_string(),
+                "text" => apply_edits("", &f.edits),
+            }
+        })
+        .collect();
+    object! {
+        "base" => base,
+        "changes" => JsonValue::Array(changes),
+        "created_files" => JsonValue::Array(created),
+    }
+}
+
+fn encode_text_edit(e: &TextEdit) -> JsonValue {
+    let strings = |v: &[String]| JsonValue::Array(v.iter().map(|s| s.clone().into()).collect());
+    object! {
+        "lo" => e.lo,
+        "hi" => e.hi,
+        "text" => e.text.clone(),
+        "commands" => strings(&e.commands),
+        "labels" => strings(&e.labels),
+    }
+}
+
 struct MarkVisitor<'a> {
     node_id_map: &'a HashMap<NodeId, NodeId>,
     marks: HashMap<NodeId, Vec<Symbol>>,
@@ -55,7 +55,7 @@
 //! `print`), which can perform rewrites to correct the error at this higher level.
 
 use rustc::session::Session;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::mem;
 use std::ops::{Deref, DerefMut};
 use syntax::ast::*;
@@ -163,6 +163,8 @@ pub struct RewriteCtxt<'s> {
     sess: &'s Session,
     old_nodes: AstMap<'s>,
     comment_map: &'s CommentMap,
+    /// Old nodes that must be pretty-printed, even if they're unchanged.
+    reprint_nodes: &'s HashSet<NodeId>,
     text_span_cache: HashMap<String, Span>,
 
     /// The span of the new AST the last time we entered "fresh" mode.  This lets us avoid infinite
@@ -188,12 +190,14 @@ impl<'s> RewriteCtxt<'s> {
         sess: &'s Session,
         old_nodes: AstMap<'s>,
         comment_map: &'s CommentMap,
+        reprint_nodes: &'s HashSet<NodeId>,
         node_id_map: HashMap<NodeId, NodeId>,
     ) -> RewriteCtxt<'s> {
         RewriteCtxt {
             sess,
             old_nodes,
             comment_map,
+            reprint_nodes,
             text_span_cache: HashMap::new(),
 
             fresh_start: DUMMY_SP,
@@ -214,6 +218,11 @@ impl<'s> RewriteCtxt<'s> {
         &self.comment_map
     }
 
+    /// Check whether the old node `id` must be pretty-printed instead of keeping its text.
+    pub fn must_reprint(&self, id: NodeId) -> bool {
+        self.reprint_nodes.contains(&id)
+    }
+
     pub fn fresh_start(&self) -> Span {
         self.fresh_start
     }
@@ -308,6 +317,7 @@ pub fn rewrite<'s, T>(
     old: &'s T,
     new: &T,
     comment_map: &CommentMap,
+    reprint_nodes: &HashSet<NodeId>,
     node_id_map: HashMap<NodeId, NodeId>,
     map_extra_ast: impl FnOnce(&mut AstMap<'s>),
 ) -> TextRewrite
@@ -318,7 +328,7 @@ where
     map_extra_ast(&mut map);
 
     let mut rw = TextRewrite::new(DUMMY_SP, old.get_span());
-    let mut rcx = RewriteCtxt::new(sess, map, comment_map, node_id_map);
+    let mut rcx = RewriteCtxt::new(sess, map, comment_map, reprint_nodes, node_id_map);
     let ok = Rewrite::rewrite(old, new, rcx.enter(&mut rw));
     assert!(ok, "rewriting did not complete");
     rw
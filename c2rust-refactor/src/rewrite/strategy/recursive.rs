@@ -4,14 +4,17 @@ use syntax::token::{Lit as TokenLit, LitKind as TokenLitKind};
 use syntax::tokenstream::{DelimSpan, TokenTree};
 use syntax::util::parser;
 
-use crate::ast_manip::{GetNodeId, GetSpan};
+use crate::ast_manip::{GetNodeId, GetSpan, MaybeGetNodeId};
 use crate::rewrite::base::{binop_left_prec, binop_right_prec, calc_outer_span, rewrite_seq};
 use crate::rewrite::{ExprPrec, Rewrite, RewriteCtxtRef};
 
 /// Try rewriting every child of `old` into the corresponding child of `new`.  Fails if `old` and
 /// `new` don't have the same structure (for example, if they are different variants of an enum),
-/// or if rewriting fails on any child.
-pub fn rewrite<T: Recursive>(old: &T, new: &T, rcx: RewriteCtxtRef) -> bool {
+/// if rewriting fails on any child, or if `old` was marked to be reprinted.
+pub fn rewrite<T: Recursive + MaybeGetNodeId>(old: &T, new: &T, rcx: RewriteCtxtRef) -> bool {
+    if <T as MaybeGetNodeId>::supported() && rcx.must_reprint(MaybeGetNodeId::get_node_id(old)) {
+        return false;
+    }
     <T as Recursive>::recursive(old, new, rcx)
 }
 
@@ -26,7 +26,7 @@ use crate::ast_manip::MutVisit;
 use crate::ast_manip::fn_edit::{mut_visit_fns, FnLike};
 use crate::command::{self, CommandState, RefactorState};
 use crate::driver::{self, Phase};
-use crate::file_io::{OutputMode, RealFileIO};
+use crate::file_io::FileIO;
 use crate::matcher::{self, mut_visit_match_with, replace_expr, MatchCtxt, Pattern, Subst, TryMatch};
 use crate::path_edit::fold_resolved_paths_with_id;
 use crate::reflect::reflect_tcx_ty;
@@ -65,14 +65,16 @@ pub fn run_lua_file(
     script_path: &Path,
     config: interface::Config,
     registry: command::Registry,
-    rewrite_modes: Vec<OutputMode>,
+    io: Arc<dyn FileIO + Sync + Send>,
+    undo_limit: usize,
 ) -> io::Result<()> {
     let mut file = File::open(script_path)?;
     let mut script = vec![];
     file.read_to_end(&mut script)?;
-    let io = Arc::new(RealFileIO::new(rewrite_modes));
 
-    driver::run_refactoring(config, registry, io, HashSet::new(), |state| {
+    driver::run_refactoring(config, registry, io, HashSet::new(), |mut state| {
+        // Scripts only keep the snapshots for `undo` once they call `enable_undo`.
+        state.set_undo_limit(undo_limit);
         // We use the unsafe _with_debug method because we want to be able to use
         // lua libraries which happen to support pretty printing. This should be fine
         // so long as we're confident they don't use riskier parts of the debug lib.
@@ -176,6 +178,14 @@ impl UserData for RefactorState {
             },
         );
 
+        /// Keep the state from before each command, so the `undo` command can go back to it.
+        /// Each saved state is a copy of the crate, so this is off until a script asks for it.
+        // @function enable_undo
+        methods.add_method_mut(
+            "enable_undo",
+            |_lua_ctx, this, ()| Ok(this.enable_undo()),
+        );
+
         methods.add_method_mut(
             "save_crate",
             |_lua_ctx, this, ()| Ok(this.save_crate()),
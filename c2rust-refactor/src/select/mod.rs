@@ -13,6 +13,7 @@ use syntax::symbol::Symbol;
 
 use crate::command::CommandState;
 use crate::command::{DriverCommand, Registry};
+use crate::command_args::{ArgType, CommandSpec};
 use crate::driver::Phase;
 use crate::pick_node::NodeKind;
 use crate::resolve;
@@ -189,42 +190,44 @@ pub fn run_select<S: IntoSymbol>(st: &CommandState, cx: &RefactorCtxt, ops: &[Se
 
 /// # `select` Command
 ///
-/// Usage: `select MARK SCRIPT`
-///
 /// Marks: sets `MARK`; may set/clear other marks depending on `SCRIPT`
 ///
 /// Run node-selection script `SCRIPT`, and apply `MARK` to the nodes it selects.
 /// See `select::SelectOp`, `select::Filter`, and `select::parser` for details on
 /// select script syntax.
 fn register_select(reg: &mut Registry) {
-    reg.register("select", |args| {
-        let label = (&args[0]).into_symbol();
-        let ops_str = args[1].clone();
-        Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+    let spec = CommandSpec::new("Apply MARK to the nodes that SCRIPT selects.")
+        .arg("MARK", ArgType::Str, "the mark to apply to the selected nodes")
+        .arg("SCRIPT", ArgType::Str, "the node-selection script");
+    reg.register("select", spec, |args| {
+        let label = args.str("MARK").into_symbol();
+        let ops_str = args.str("SCRIPT").to_owned();
+        Ok(Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
             let ops = parse::parse(cx.session(), &ops_str);
             eprintln!("running select: {:?} -> {}", ops, label);
             run_select(st, cx, &ops, label);
-        }))
+        })))
     });
 }
 
 /// # `select_phase2` Command
 ///
-/// Usage: `select_phase2 MARK SCRIPT`
-///
 /// Marks: sets `MARK`; may set/clear other marks depending on `SCRIPT`
 ///
 /// Works like [`select`](#select), but stops the compiler's analyses before typechecking happens.
 /// This means type information will not available, and script commands that refer to it will fail.
 fn register_select_phase2(reg: &mut Registry) {
-    reg.register("select_phase2", |args| {
-        let label = (&args[0]).into_symbol();
-        let ops_str = args[1].clone();
-        Box::new(DriverCommand::new(Phase::Phase2, move |st, cx| {
+    let spec = CommandSpec::new("Like `select`, but without type information.")
+        .arg("MARK", ArgType::Str, "the mark to apply to the selected nodes")
+        .arg("SCRIPT", ArgType::Str, "the node-selection script");
+    reg.register("select_phase2", spec, |args| {
+        let label = args.str("MARK").into_symbol();
+        let ops_str = args.str("SCRIPT").to_owned();
+        Ok(Box::new(DriverCommand::new(Phase::Phase2, move |st, cx| {
             let ops = parse::parse(cx.session(), &ops_str);
             eprintln!("running select (phase2): {:?} -> {}", ops, label);
             run_select(st, cx, &ops, label);
-        }))
+        })))
     });
 }
 
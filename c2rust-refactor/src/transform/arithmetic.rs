@@ -0,0 +1,260 @@
+use regex::Regex;
+use rustc::ty::{ParamEnv, TyKind};
+use std::cmp;
+use syntax::ast::*;
+use syntax::ptr::P;
+
+use crate::ast_manip::MutVisitNodes;
+use crate::ast_manip::fn_edit::mut_visit_fns;
+use crate::command::{CommandState, Registry};
+use crate::command_args::{ArgType, CommandSpec};
+use crate::driver::Phase;
+use crate::transform::casts::SimpleTy;
+use crate::transform::index_exprs::wrapping_op;
+use crate::transform::Transform;
+use crate::RefactorCtxt;
+use c2rust_ast_builder::mk;
+
+/// # `unwrap_arithmetic` Command
+///
+/// Rewrites the `wrapping_add`, `wrapping_sub` and `wrapping_mul` calls that the transpiler
+/// emits for C arithmetic back into the plain `+`, `-` and `*` operators.  By default, a call
+/// is only rewritten when a value-range analysis of its operands shows that it can't
+/// overflow, e.g., `(x as u32).wrapping_add(1)`, where `x` is a `u8`, or
+/// `(i & 0xff).wrapping_mul(4)`.  The analysis looks through literals, integer casts, masks,
+/// remainders, divisions and right shifts by constants, and nested arithmetic.  Plain
+/// operators are assumed not to overflow, since they panic in debug builds if they do.
+///
+/// With `--signed-only`, all calls on signed integers are rewritten as well.  Signed overflow
+/// is undefined behavior in C, so this keeps the semantics of the original program.  With
+/// `--all`, every call is rewritten, accepting that unsigned arithmetic that used to wrap will
+/// now panic on overflow in debug builds, which is often useful for finding bugs.
+///
+/// `GLOBS` is a comma-separated list of function names, with `*` and `?` wildcards, like
+/// `'*hash*,crc*,*rand*'`.  Calls in matching functions are left alone, since hash functions,
+/// checksums and random number generators usually rely on wrapping.
+///
+/// Once done, prints how many calls were rewritten, kept and skipped in each function, so the
+/// functions with the most kept calls can be audited first.  If `-o REPORT` is passed, the
+/// source locations of the calls are also written to the `REPORT` file as JSON.
+pub struct UnwrapArithmetic {
+    pub mode: UnwrapMode,
+    pub skip: Vec<String>,
+    pub report_path: Option<String>,
+}
+
+/// Which wrapping operations `unwrap_arithmetic` rewrites: the ones that provably can't
+/// overflow, those and all signed ones, or all of them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UnwrapMode {
+    Proven,
+    Signed,
+    All,
+}
+
+impl Transform for UnwrapArithmetic {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        st.start_report("unwrap_arithmetic", self.report_path.clone());
+        let tcx = cx.ty_ctxt();
+        let skip = self.skip.iter().map(|glob| glob_regex(glob)).collect::<Vec<_>>();
+        let ranges = IntRanges { cx };
+
+        mut_visit_fns(krate, |fl| {
+            let name = tcx.def_path_str(cx.node_def_id(fl.id));
+            let skipped = skip.iter().any(|re| re.is_match(&fl.ident.as_str()));
+            let block = match_or!([fl.block] Some(ref mut b) => b; return);
+            MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                let (op, lhs, rhs) = match_or!([wrapping_op(e)] Some(x) => x; return);
+                let ty = match_or!([ranges.int_ty(e)] Some(x) => x; return);
+                if skipped {
+                    st.report(cx, &format!("{}: skipped", name), e.span);
+                    return;
+                }
+                let unwrap = ranges.op_range(op, lhs, rhs, ty).is_some() || match self.mode {
+                    UnwrapMode::Proven => false,
+                    UnwrapMode::Signed => ty.is_signed(),
+                    UnwrapMode::All => true,
+                };
+                if !unwrap {
+                    st.report(cx, &format!("{}: kept", name), e.span);
+                    return;
+                }
+                st.report(cx, &format!("{}: rewritten", name), e.span);
+                // Keep the ID, so the type of the operation is still known when checking the
+                // operations that use it.
+                let mut new_e = mk().span(e.span).binary_expr(op, lhs.clone(), rhs.clone());
+                new_e.id = e.id;
+                *e = new_e;
+            });
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Build a regex that matches a whole name against the glob `glob`, which may contain `*` and
+/// `?` wildcards.
+fn glob_regex(glob: &str) -> Regex {
+    let re = regex::escape(glob).replace(r"\*", ".*").replace(r"\?", ".");
+    Regex::new(&format!("^{}$", re)).unwrap()
+}
+
+/// Value-range analysis of integer expressions, for proving that arithmetic can't overflow.
+/// Ranges are computed as `i128`s, so 128-bit integers are left out.
+struct IntRanges<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+}
+
+impl<'a, 'b, 'tcx> IntRanges<'a, 'b, 'tcx> {
+    /// Get the integer type of `e`, if it has one.
+    fn int_ty(&self, e: &Expr) -> Option<SimpleTy> {
+        let tcx = self.cx.ty_ctxt();
+        let ty = tcx.normalize_erasing_regions(ParamEnv::empty(), self.cx.opt_node_type(e.id)?);
+        Some(SimpleTy::from_ty(tcx, ty)).filter(|ty| ty.is_integer())
+    }
+
+    /// Get the range of values `e` can take.
+    fn range(&self, e: &Expr) -> Option<(i128, i128)> {
+        let full = self.int_ty(e).and_then(int_ty_range);
+        let (lo, hi) = match e.kind {
+            ExprKind::Paren(ref inner) => return self.range(inner),
+            ExprKind::Lit(ref lit) => match lit.kind {
+                LitKind::Int(i, _) if i <= i128::max_value() as u128 => (i as i128, i as i128),
+                _ => return full,
+            },
+            ExprKind::Unary(UnOp::Neg, ref inner) => {
+                let (lo, hi) = self.range(inner)?;
+                (-hi, -lo)
+            }
+            ExprKind::Cast(ref inner, _) => {
+                let inner_ty = self.cx.opt_node_type(inner.id)?;
+                match inner_ty.kind {
+                    TyKind::Bool => (0, 1),
+                    _ if self.int_ty(inner).is_some() => self.range(inner)?,
+                    _ => return full,
+                }
+            }
+            ExprKind::Binary(op, ref a, ref b) => {
+                let (a, b) = (self.range(a), self.range(b));
+                let nonneg = |r: Option<(i128, i128)>| r.filter(|&(lo, _)| lo >= 0);
+                match op.node {
+                    BinOpKind::Add | BinOpKind::Sub | BinOpKind::Mul => {
+                        match arith_range(op.node, a?, b?) {
+                            Some(r) => r,
+                            None => return full,
+                        }
+                    }
+                    BinOpKind::BitAnd => match (nonneg(a), nonneg(b)) {
+                        (Some((_, ahi)), Some((_, bhi))) => (0, cmp::min(ahi, bhi)),
+                        (Some((_, hi)), None) | (None, Some((_, hi))) => (0, hi),
+                        (None, None) => return full,
+                    },
+                    BinOpKind::Rem => match (nonneg(a), b) {
+                        (Some((_, ahi)), Some((blo, bhi))) if blo > 0 => {
+                            (0, cmp::min(ahi, bhi - 1))
+                        }
+                        _ => return full,
+                    },
+                    BinOpKind::Div => match (nonneg(a), b) {
+                        (Some((alo, ahi)), Some((blo, bhi))) if blo > 0 => (alo / bhi, ahi / blo),
+                        _ => return full,
+                    },
+                    BinOpKind::Shr => match (nonneg(a), b) {
+                        (Some((alo, ahi)), Some((n, m))) if n == m && n >= 0 && n < 128 => {
+                            (alo >> n, ahi >> n)
+                        }
+                        _ => return full,
+                    },
+                    _ => return full,
+                }
+            }
+            ExprKind::MethodCall(..) => {
+                let (op, a, b) = match_or!([wrapping_op(e)] Some(x) => x; return full);
+                match self.int_ty(e).and_then(|ty| self.op_range(op, a, b, ty)) {
+                    Some(r) => r,
+                    None => return full,
+                }
+            }
+            _ => return full,
+        };
+
+        // Plain operators that overflow panic, so their values are clamped to their type
+        match full {
+            Some((min, max)) if lo.max(min) <= hi.min(max) => Some((lo.max(min), hi.min(max))),
+            Some(_) => full,
+            None => Some((lo, hi)),
+        }
+    }
+
+    /// Get the range of values of the operation `lhs op rhs` in the type `ty`, if it can't
+    /// overflow.
+    fn op_range(&self, op: BinOpKind, lhs: &Expr, rhs: &Expr, ty: SimpleTy)
+                -> Option<(i128, i128)> {
+        let (lo, hi) = arith_range(op, self.range(lhs)?, self.range(rhs)?)?;
+        let (min, max) = int_ty_range(ty)?;
+        if lo >= min && hi <= max {
+            Some((lo, hi))
+        } else {
+            None
+        }
+    }
+}
+
+/// Get the range of values of the integer type `ty`, unless it's a 128-bit type.
+fn int_ty_range(ty: SimpleTy) -> Option<(i128, i128)> {
+    match ty {
+        SimpleTy::Int(w, signed) | SimpleTy::Size(w, signed) if w < 128 => if signed {
+            Some((-(1 << (w - 1)), (1 << (w - 1)) - 1))
+        } else {
+            Some((0, (1 << w) - 1))
+        },
+        _ => None,
+    }
+}
+
+/// Get the exact range of values of `a op b`, for operands in the ranges `a` and `b`, where
+/// `op` is `+`, `-` or `*`.
+fn arith_range(op: BinOpKind, (alo, ahi): (i128, i128), (blo, bhi): (i128, i128))
+               -> Option<(i128, i128)> {
+    match op {
+        BinOpKind::Add => Some((alo.checked_add(blo)?, ahi.checked_add(bhi)?)),
+        BinOpKind::Sub => Some((alo.checked_sub(bhi)?, ahi.checked_sub(blo)?)),
+        BinOpKind::Mul => {
+            let products = [
+                alo.checked_mul(blo)?,
+                alo.checked_mul(bhi)?,
+                ahi.checked_mul(blo)?,
+                ahi.checked_mul(bhi)?,
+            ];
+            Some((*products.iter().min()?, *products.iter().max()?))
+        }
+        _ => None,
+    }
+}
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    let spec = CommandSpec::new("Rewrite wrapping arithmetic calls into plain operators.")
+        .flag("--signed-only", "also rewrite every call on signed integers")
+        .flag("--all", "rewrite every call")
+        .option("--skip", "GLOBS", ArgType::Str, None, "comma-separated globs of functions to skip")
+        .option("-o", "REPORT", ArgType::Str, None, "also write the report to REPORT as JSON");
+    reg.register("unwrap_arithmetic", spec, |args| {
+        Ok(mk(UnwrapArithmetic {
+            mode: if args.flag("--all") {
+                UnwrapMode::All
+            } else if args.flag("--signed-only") {
+                UnwrapMode::Signed
+            } else {
+                UnwrapMode::Proven
+            },
+            skip: args
+                .opt_str("--skip")
+                .map_or_else(Vec::new, |globs| globs.split(',').map(str::to_owned).collect()),
+            report_path: args.opt_str("-o").map(|s| s.to_owned()),
+        }))
+    });
+}
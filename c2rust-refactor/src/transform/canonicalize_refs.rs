@@ -5,6 +5,7 @@ use syntax::ptr::P;
 use c2rust_ast_builder::mk;
 use crate::ast_manip::MutVisitNodes;
 use crate::command::{CommandState, Registry};
+use crate::command_args::CommandSpec;
 use crate::driver::Phase;
 use crate::transform::Transform;
 use crate::RefactorCtxt;
@@ -103,7 +104,9 @@ fn remove_reborrow(expr: &mut P<Expr>) {
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("canonicalize_refs", |_args| mk(CanonicalizeRefs));
+    let spec = CommandSpec::new("Make all autorefs and autoderefs explicit.");
+    reg.register("canonicalize_refs", spec, |_args| Ok(mk(CanonicalizeRefs)));
 
-    reg.register("remove_unnecessary_refs", |_args| mk(RemoveUnnecessaryRefs));
+    let spec = CommandSpec::new("Remove unnecessary refs and derefs.");
+    reg.register("remove_unnecessary_refs", spec, |_args| Ok(mk(RemoveUnnecessaryRefs)));
 }
@@ -1,75 +1,246 @@
-use rustc::ty::{self, ParamEnv, TyKind};
+use rustc::hir;
+use rustc::hir::def::{DefKind, Res};
+use rustc::mir::interpret::GlobalId;
+use rustc::ty::{self, Instance, ParamEnv, TyCtxt, TyKind, TypeFoldable};
+use smallvec::{smallvec, SmallVec};
+use std::cmp;
+use std::collections::HashSet;
+use std::mem;
+use syntax::ast;
 use syntax::ast::*;
+use syntax::attr;
+use syntax::mut_visit::{self, MutVisitor};
 use syntax::token;
 use syntax::ptr::P;
 use syntax_pos::Symbol;
 
-use crate::command::{CommandState, Registry};
+use crate::ast_manip::{visit_nodes, MutVisitNodes};
+use crate::command::{CommandState, DriverCommand, Registry};
+use crate::command_args::{ArgType, CommandSpec};
+use crate::contains_mark::marked_exprs;
 use crate::driver::Phase;
-use crate::matcher::{mut_visit_match_with, replace_expr, MatchCtxt};
+use crate::matcher::{mut_visit_match_with, MatchCtxt, Subst};
 use crate::transform::Transform;
 use crate::RefactorCtxt;
-use c2rust_ast_builder::mk;
+use c2rust_ast_builder::{mk, Builder, IntoSymbol};
 
 #[cfg(test)]
 mod tests;
 
 /// # `remove_redundant_casts` Command
 ///
-/// Usage: `remove_redundant_casts`
+/// Marks: `target`
 ///
 /// Removes all casts of the form `$e as $t` where the expression already has the `$t` type,
 /// and double casts like `$e as $t1 as $t2` where the inner cast is redundant.
-pub struct RemoveRedundantCasts;
+///
+/// If any nodes are marked `target`, only casts inside the marked items, statements and
+/// expressions are considered; otherwise, the whole crate is. If `--only-marked` is passed,
+/// the command prints a warning and leaves the crate unchanged when nothing is marked.
+///
+/// Casts of constant expressions built from literals and `const` items are folded into a
+/// single literal of the target type, e.g., `(SOME_CONST + 1) as u8` becomes `8u8`, and
+/// `-SOME_CONST as i8` becomes `-7i8`.
+///
+/// If `--fold-char-lits` is passed, casts of character and byte literals to integers, e.g.,
+/// `'A' as u32`, are also folded into the equivalent integer literal, e.g., `65u32`.
+///
+/// If `--keep-aliases` is passed, casts of constants to type aliases, e.g., `5u64 as uint32_t`,
+/// are folded into an unsuffixed literal that keeps the cast to the alias, e.g.,
+/// `5 as uint32_t`, instead of a literal with a primitive suffix like `5u32`.
+///
+/// Negated literals cast to unsigned types, e.g., `-1i32 as u32`, are kept by default, since
+/// unsigned literals can't be negated. If `--wrap-negative-lits` is passed, they are folded into
+/// the wrapped literal instead, e.g., `4294967295u32`. Casts to `usize` are always kept, since
+/// the wrapped value depends on the target's pointer width.
+///
+/// Casts that initialize struct fields are also removed if they only repeat the coercion
+/// that the field initializer applies anyway, e.g., `Foo { p: r as *mut T }` where
+/// `r: &mut T`.
+///
+/// Once done, prints how many casts were simplified in each way. If `-o REPORT` is passed,
+/// the counts and the source locations of all the rewrites are also written to the `REPORT`
+/// file as JSON.
+pub struct RemoveRedundantCasts {
+    pub fold_char_lits: bool,
+    pub keep_aliases: bool,
+    pub wrap_negative_lits: bool,
+    pub only_marked: bool,
+    pub report_path: Option<String>,
+}
+
+/// Upper bound on the number of times a single cast expression gets simplified,
+/// in case some future combination of rules never reaches a fixed point.
+const MAX_CAST_PASSES: usize = 32;
 
 impl Transform for RemoveRedundantCasts {
     fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
-        let tcx = cx.ty_ctxt();
+        let target = "target".into_symbol();
+        let has_marks = st.marks().iter().any(|&(_, label)| label == target);
+        if self.only_marked && !has_marks {
+            warn!("remove_redundant_casts --only-marked: no nodes are marked `target`");
+            return;
+        }
+        let targets = if has_marks {
+            Some(marked_exprs(krate, target, st))
+        } else {
+            None
+        };
+        st.start_report("remove_redundant_casts", self.report_path.clone());
+        let field_inits = struct_field_inits(krate, cx);
+
         let mut mcx = MatchCtxt::new(st, cx);
         let pat = mcx.parse_expr("$oe:Expr as $ot:Ty");
-        mut_visit_match_with(mcx, pat, krate, |ast, mcx| {
-            let oe = mcx.bindings.get::<_, P<Expr>>("$oe").unwrap();
-            let oe_ty = cx.node_type(oe.id);
-            let oe_ty = tcx.normalize_erasing_regions(ParamEnv::empty(), oe_ty);
-
-            let ot = mcx.bindings.get::<_, P<Ty>>("$ot").unwrap();
-            let ot_ty = cx.node_type(ot.id);
-            let ot_ty = tcx.normalize_erasing_regions(ParamEnv::empty(), ot_ty);
-            debug!("checking cast: {:?}, types: {:?} => {:?}",
-                   ast, oe_ty, ot_ty);
-
-            let ast_mk = mk().id(ast.id).span(ast.span);
-            match oe.kind {
-                ExprKind::Cast(ref ie, ref it) => {
-                    // Found a double cast
-                    let ie_ty = cx.node_type(ie.id);
-                    let ie_ty = tcx.normalize_erasing_regions(ParamEnv::empty(), ie_ty);
-
-                    let it_ty = cx.node_type(it.id);
-                    let it_ty = tcx.normalize_erasing_regions(ParamEnv::empty(), it_ty);
-                    debug!("inner cast: {:?} => {:?}", ie_ty, it_ty);
-
-                    match check_double_cast(ie_ty.into(), it_ty.into(), ot_ty.into()) {
-                        DoubleCastAction::RemoveBoth => {
-                            debug!("redundant cast => removing both");
-                            *ast = ie.clone();
-                            return;
-                        }
-                        DoubleCastAction::RemoveInner => {
-                            // Rewrite to `$ie as $ot`, removing the inner cast
-                            debug!("redundant cast => removing inner");
-                            *ast = ast_mk.cast_expr(ie, ot);
-                            return;
+        mut_visit_match_with(mcx, pat, krate, |ast, _mcx| {
+            if let Some(ref targets) = targets {
+                if !targets.contains(&ast.id) {
+                    return;
+                }
+            }
+            // Casts get visited bottom-up, but a rewrite can expose a new redundant
+            // cast at the same node, e.g., `p as *const u8 as *const T` becomes the
+            // no-op cast `p as *const T`, so keep simplifying the replacement.
+            // Every rewrite either drops a cast, replaces the cast with an expression
+            // that isn't one, e.g., a literal, or folds the operand into an unsuffixed
+            // literal, which is never rewritten again, so the cap is only a safeguard
+            for _ in 0..MAX_CAST_PASSES {
+                if !self.simplify_cast(ast, field_inits.contains(&ast.id), st, cx) {
+                    return;
+                }
+            }
+            warn!("giving up on simplifying the cast at {} after {} passes",
+                  cx.session().source_map().span_to_string(ast.span), MAX_CAST_PASSES);
+        })
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+impl RemoveRedundantCasts {
+    /// Simplify the cast `ast` by one step, returning whether it changed.
+    /// `field_init` is set if `ast` initializes a field in a struct expression.
+    fn simplify_cast(
+        &self,
+        ast: &mut P<Expr>,
+        field_init: bool,
+        st: &CommandState,
+        cx: &RefactorCtxt,
+    ) -> bool {
+        let tcx = cx.ty_ctxt();
+        let span = ast.span;
+        let (mut oe, ot) = match ast.kind {
+            ExprKind::Cast(ref oe, ref ot) => (oe.clone(), ot.clone()),
+            _ => return false,
+        };
+        // Parentheses that other rewrites put around the operand have no type of their own,
+        // and would hide the operand's kind from the match below
+        loop {
+            oe = match oe.kind {
+                ExprKind::Paren(ref inner) => inner.clone(),
+                _ => break,
+            };
+        }
+        let oe_ty = cx.node_type(oe.id);
+        let oe_ty = tcx.normalize_erasing_regions(ParamEnv::empty(), oe_ty);
+
+        let ot_ty = cx.node_type(ot.id);
+        let ot_ty = tcx.normalize_erasing_regions(ParamEnv::empty(), ot_ty);
+        debug!("checking cast: {:?}, types: {:?} => {:?}",
+               ast, oe_ty, ot_ty);
+
+        let ast_mk = mk().id(ast.id).span(ast.span);
+        let keep_alias = self.keep_aliases && is_alias_ty(&ot, cx);
+        match oe.kind {
+            ExprKind::Cast(ref ie, ref it) => {
+                // Found a double cast
+                let ie_ty = cx.node_type(ie.id);
+                let ie_ty = tcx.normalize_erasing_regions(ParamEnv::empty(), ie_ty);
+
+                let it_ty = cx.node_type(it.id);
+                let it_ty = tcx.normalize_erasing_regions(ParamEnv::empty(), it_ty);
+                debug!("inner cast: {:?} => {:?}", ie_ty, it_ty);
+
+                let simple_ty = |ty| SimpleTy::from_ty(tcx, ty);
+                let action = check_pointer_double_cast(tcx, ie_ty, it_ty, ot_ty)
+                    .unwrap_or_else(|| {
+                        check_double_cast(simple_ty(ie_ty), simple_ty(it_ty), simple_ty(ot_ty))
+                    });
+                match action {
+                    DoubleCastAction::RemoveBoth => {
+                        debug!("redundant cast => removing both");
+                        st.report(cx, "double cast removed", span);
+                        *ast = ie.clone();
+                        return true;
+                    }
+                    DoubleCastAction::RemoveInner => {
+                        // Rewrite to `$ie as $ot`, removing the inner cast
+                        debug!("redundant cast => removing inner");
+                        // Dropping a sign flip is the easiest rule to get wrong,
+                        // so we count those separately for auditing
+                        if is_sign_flip(simple_ty(ie_ty), simple_ty(it_ty)) {
+                            st.report(cx, "inner sign flip removed", span);
+                        } else {
+                            st.report(cx, "inner cast removed", span);
                         }
-                        DoubleCastAction::KeepBoth => {}
+                        *ast = ast_mk.cast_expr(ie, ot.clone());
+                        return true;
+                    }
+                    DoubleCastAction::KeepBoth => {
+                        st.report(cx, "double cast kept", span);
                     }
                 }
+            }
+
+            ExprKind::Lit(ref lit) if keep_alias => {
+                // `X_ty1 as alias` => `X as alias`
+                if lit.kind.is_unsuffixed() {
+                    return false;
+                }
+                if let Some(ne) = fold_alias_cast(ast, &ot, cx) {
+                    st.report(cx, "literal suffix replaced", span);
+                    *ast = ne;
+                    return true;
+                }
+                return false;
+            }
+
+            ExprKind::Lit(ref lit) => {
+                // `X_ty1 as ty2` => `X_ty2`
+                let new_lit = replace_suffix(lit, SimpleTy::from_ty(tcx, ot_ty), self.fold_char_lits);
+                if let Some(nl) = new_lit {
+                    let new_expr = ast_mk.lit_expr(nl);
+                    let ast_const = eval_const(ast.clone(), cx);
+                    let new_const = eval_const(new_expr.clone(), cx);
+                    debug!(
+                        "checking {:?} == {:?}: {:?} == {:?}",
+                        *ast, new_expr, ast_const, new_const
+                    );
+                    if new_const.is_some() && new_const == ast_const {
+                        st.report(cx, "literal suffix replaced", span);
+                        *ast = new_expr;
+                        return true;
+                    }
+                }
+                if lit.kind.is_unsuffixed() {
+                    // If we're casting an unsuffixed literal to a type,
+                    // we need to keep the cast, otherwise we get type errors
+                    return false;
+                }
+            }
 
-                ExprKind::Lit(ref lit) => {
-                    // `X_ty1 as ty2` => `X_ty2`
-                    let new_lit = replace_suffix(lit, SimpleTy::from(ot_ty));
+            ExprKind::Unary(UnOp::Neg, ref expr) => match expr.kind {
+                // Unsigned literals can't be negated, so we can't
+                // turn `-1i32 as u32` into `-1u32`
+                ExprKind::Lit(ref lit)
+                    if !keep_alias && SimpleTy::from_ty(tcx, ot_ty).is_signed() =>
+                {
+                    // `-X_ty1 as ty2` => `-X_ty2`
+                    let new_lit = replace_suffix(lit, SimpleTy::from_ty(tcx, ot_ty), false);
                     if let Some(nl) = new_lit {
-                        let new_expr = ast_mk.lit_expr(nl);
+                        let expr_mk = mk().id(expr.id).span(expr.span);
+                        let new_expr = ast_mk.unary_expr(UnOp::Neg, expr_mk.lit_expr(nl));
                         let ast_const = eval_const(ast.clone(), cx);
                         let new_const = eval_const(new_expr.clone(), cx);
                         debug!(
@@ -77,59 +248,127 @@ impl Transform for RemoveRedundantCasts {
                             *ast, new_expr, ast_const, new_const
                         );
                         if new_const.is_some() && new_const == ast_const {
+                            st.report(cx, "literal suffix replaced", span);
                             *ast = new_expr;
-                            return;
+                            return true;
                         }
                     }
                     if lit.kind.is_unsuffixed() {
-                        // If we're casting an unsuffixed literal to a type,
-                        // we need to keep the cast, otherwise we get type errors
-                        return;
+                        // See comment above on unsuffixed literals
+                        return false;
                     }
                 }
-
-                ExprKind::Unary(UnOp::Neg, ref expr) => match expr.kind {
-                    ExprKind::Lit(ref lit) => {
-                        // `-X_ty1 as ty2` => `-X_ty2`
-                        let new_lit = replace_suffix(lit, SimpleTy::from(ot_ty));
-                        if let Some(nl) = new_lit {
-                            let expr_mk = mk().id(expr.id).span(expr.span);
-                            let new_expr = ast_mk.unary_expr(UnOp::Neg, expr_mk.lit_expr(nl));
-                            let ast_const = eval_const(ast.clone(), cx);
-                            let new_const = eval_const(new_expr.clone(), cx);
-                            debug!(
-                                "checking {:?} == {:?}: {:?} == {:?}",
-                                *ast, new_expr, ast_const, new_const
-                            );
-                            if new_const.is_some() && new_const == ast_const {
-                                *ast = new_expr;
-                                return;
-                            }
+                // `-1i32 as u32` => `4294967295u32`
+                ExprKind::Lit(_) if self.wrap_negative_lits && !keep_alias => {
+                    let ot_simple = SimpleTy::from_ty(tcx, ot_ty);
+                    if let SimpleTy::Int(_, false) = ot_simple {
+                        if let Some(ConstantValue::Uint(v)) = eval_const(ast.clone(), cx) {
+                            st.report(cx, "negative literal wrapped", span);
+                            let new_lit = mk().span(span).int_lit(v, ot_simple.ast_lit_int_type());
+                            *ast = ast_mk.lit_expr(new_lit);
+                            return true;
                         }
-                        if lit.kind.is_unsuffixed() {
-                            // See comment above on unsuffixed literals
-                            return;
+                    }
+                }
+                // `-SOME_CONST as i8` => `-7i8`, or the wrapped value for an
+                // unsigned type with `--wrap-negative-lits`
+                _ if is_const_expr(expr, cx) => {
+                    let ot_simple = SimpleTy::from_ty(tcx, ot_ty);
+                    let wrap = match ot_simple {
+                        SimpleTy::Int(_, false) => self.wrap_negative_lits,
+                        _ => false,
+                    };
+                    if ot_simple.is_signed() || wrap {
+                        let new_expr = fold_const_expr_cast(ast, keep_alias, &ot, ot_simple, cx);
+                        if let Some(ne) = new_expr {
+                            st.report(cx, "constant expression folded", span);
+                            *ast = ne;
+                            return true;
                         }
                     }
-                    _ => {}
-                },
-
-                // TODO: unary/binaryop op + cast, e.g., `(x as i32 + y as i32) as i8`
+                }
                 _ => {}
+            },
+
+            ExprKind::Path(..) if is_const_expr(&oe, cx) => {
+                // `SOME_CONST as u64` => `5u64`
+                let ot_simple = SimpleTy::from_ty(tcx, ot_ty);
+                if let Some(ne) = fold_const_expr_cast(ast, keep_alias, &ot, ot_simple, cx) {
+                    st.report(cx, "constant expression folded", span);
+                    *ast = ne;
+                    return true;
+                }
+            }
+
+            ExprKind::Binary(ref op, ref lhs, ref rhs) => {
+                // `(2 + 3) as u8` => `5u8`, or `5 as alias` with `--keep-aliases`
+                if is_const_expr(&oe, cx) {
+                    let ot_simple = SimpleTy::from_ty(tcx, ot_ty);
+                    if let Some(ne) = fold_const_expr_cast(ast, keep_alias, &ot, ot_simple, cx) {
+                        st.report(cx, "constant expression folded", span);
+                        *ast = ne;
+                        return true;
+                    }
+                }
+
+                // `($a as ty1 + $b as ty1) as ty2` => `$a.wrapping_add($b)`
+                let new_expr =
+                    narrow_binary_cast(ast_mk, op.node, lhs, rhs, oe_ty, &ot, ot_ty, cx);
+                if let Some(ne) = new_expr {
+                    debug!("narrowed binary operation => {:?}", ne);
+                    st.report(cx, "binary operation narrowed", span);
+                    *ast = ne;
+                    return true;
+                }
             }
-            if oe_ty == ot_ty {
-                debug!("no-op cast");
+
+            // TODO: unary op + cast
+            _ => {}
+        }
+        if oe_ty == ot_ty {
+            debug!("no-op cast");
+            st.report(cx, "identity cast removed", span);
+            *ast = oe.clone();
+            return true;
+        }
+        if field_init {
+            // When the operand coerces to the target type, typeck records the
+            // coercion as adjustments on the operand instead of a real cast, e.g.,
+            // for `r as *mut T` where `r: &mut T`; a field initializer is a coercion
+            // site, so it applies the same adjustments without the cast
+            let adj_ty = cx.opt_adjusted_node_type(oe.id)
+                .map(|ty| tcx.normalize_erasing_regions(ParamEnv::empty(), ty));
+            if adj_ty == Some(ot_ty) {
+                debug!("coercion cast in field initializer");
+                st.report(cx, "field coercion cast removed", span);
                 *ast = oe.clone();
-                return;
+                return true;
             }
-        })
+        }
+        false
     }
+}
 
-    fn min_phase(&self) -> Phase {
-        Phase::Phase3
-    }
+/// Collect the ids of the field initializers in all struct expressions whose
+/// type has no generic parameters. Removing a cast from the initializer of a
+/// generic field could change the inferred type of the whole struct.
+fn struct_field_inits(krate: &Crate, cx: &RefactorCtxt) -> HashSet<NodeId> {
+    let mut field_inits = HashSet::new();
+    visit_nodes(krate, |e: &Expr| {
+        let fields = match e.kind {
+            ExprKind::Struct(_, ref fields, _) => fields,
+            _ => return,
+        };
+        match cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+            Some(TyKind::Adt(_, substs)) if substs.is_empty() => {}
+            _ => return,
+        }
+        field_inits.extend(fields.iter().map(|f| f.expr.id));
+    });
+    field_inits
 }
 
+#[derive(Debug)]
 enum DoubleCastAction {
     RemoveBoth,
     RemoveInner,
@@ -147,13 +386,43 @@ fn check_double_cast<'tcx>(e_ty: SimpleTy, t1_ty: SimpleTy, t2_ty: SimpleTy) ->
     match (inner_cast, outer_cast) {
         (Required, _) | (_, Required) => DoubleCastAction::KeepBoth,
 
+        // Enums and `bool`s can only be cast to integers, so we can't
+        // drop the intermediate integer if the final type isn't one
+        _ if (e_ty == SimpleTy::Bool || e_ty.is_enum()) && !t2_ty.is_integer() => {
+            DoubleCastAction::KeepBoth
+        }
+
+        // Float-to-int casts of out-of-range values are undefined behavior
+        // (unless `-Z saturating-float-casts` is on), so merging one with the
+        // cast after it, e.g., `x as i64 as i32` into `x as i32` where `x: f64`,
+        // could turn a well-defined truncation into UB; we never touch them
+        (FromFloat, _) => DoubleCastAction::KeepBoth,
+
+        // An integer that converts exactly into a float and back,
+        // e.g., `x as f64 as u64` where `x: u32`
+        (Extend(_), FromFloat) if e_ty == t2_ty => DoubleCastAction::RemoveBoth,
+        (Extend(_), FromFloat) if is_lossless_int_cast(e_ty, t2_ty) => {
+            DoubleCastAction::RemoveInner
+        }
+        (_, FromFloat) => DoubleCastAction::KeepBoth,
+
+        // Rounding an inexact conversion a second time can give a different
+        // result than rounding only once, e.g., for `x as f64 as f32`
+        // where `x: u64`
+        (Unknown, Truncate) if t2_ty.is_float() => DoubleCastAction::KeepBoth,
+
+        // `x as *const T1 as *mut T2` where `x` is a `*const T0` turns
+        // an immutable pointer into a mutable one, so we keep that step
+        // separate instead of hiding it inside another cast
+        (SameWidth, SameWidth) if is_pointer_upgrade(e_ty, t2_ty) => {
+            DoubleCastAction::KeepBoth
+        }
+
         // `x as *const T1 as *const T2` can be rewritten as
         // `x as *const T2` instead, but we can't remove both casts
         // if `t2_ty` is a pointer, since `e_ty` might have been
         // something else so we need a non-pointer-to-pointer cast
-        (SameWidth, SameWidth) if t2_ty == SimpleTy::Pointer => {
-            DoubleCastAction::RemoveInner
-        }
+        (SameWidth, SameWidth) if t2_ty.is_pointer() => DoubleCastAction::RemoveInner,
 
         // 2 consecutive sign flips or extend-truncate
         // back to the same original type
@@ -176,55 +445,277 @@ fn check_double_cast<'tcx>(e_ty: SimpleTy, t1_ty: SimpleTy, t2_ty: SimpleTy) ->
     }
 }
 
-enum CastKind {
+/// Returns `true` if the cast from `from_ty` to `to_ty` only changes
+/// the signedness of an integer, e.g., `i32` to `u32`.
+fn is_sign_flip(from_ty: SimpleTy, to_ty: SimpleTy) -> bool {
+    match cast_kind(from_ty, to_ty) {
+        CastKind::SameWidth => {
+            from_ty.is_integer() && to_ty.is_integer() && from_ty.is_signed() != to_ty.is_signed()
+        }
+        _ => false,
+    }
+}
+
+fn is_pointer_upgrade(from_ty: SimpleTy, to_ty: SimpleTy) -> bool {
+    use rustc::hir::Mutability::*;
+    match (from_ty, to_ty) {
+        (SimpleTy::Pointer(Immutable), SimpleTy::Pointer(Mutable)) => true,
+        _ => false,
+    }
+}
+
+// Check and decide what to do for a double cast that ends in a raw pointer,
+// using the pointee types that `SimpleTy` doesn't track. Returns `None` if the
+// pointee types don't matter and `check_double_cast` should decide.
+fn check_pointer_double_cast<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    e_ty: ty::Ty<'tcx>,
+    t1_ty: ty::Ty<'tcx>,
+    t2_ty: ty::Ty<'tcx>,
+) -> Option<DoubleCastAction> {
+    let (t2_pointee, t2_mutbl) = match t2_ty.kind {
+        TyKind::RawPtr(ty::TypeAndMut { ty, mutbl }) => (ty, mutbl),
+        _ => return None,
+    };
+    match (&e_ty.kind, &t1_ty.kind) {
+        // A reference can only be cast to a pointer to the same type, e.g.,
+        // `&mut x as *mut T as *const T` => `&mut x as *const T`
+        (TyKind::Ref(_, e_pointee, e_mutbl), TyKind::RawPtr(_)) => {
+            let same_pointee = *e_pointee == t2_pointee;
+            let no_upgrade = *e_mutbl == hir::Mutability::Mutable ||
+                t2_mutbl == hir::Mutability::Immutable;
+            if same_pointee && no_upgrade {
+                Some(DoubleCastAction::RemoveInner)
+            } else {
+                Some(DoubleCastAction::KeepBoth)
+            }
+        }
+
+        // Casts to pointers to unsized types need the exact pointee type
+        // of the inner cast, e.g., for `x as *const T as *const dyn Trait`
+        (_, TyKind::RawPtr(_)) if !tcx.is_sized_raw(ParamEnv::empty().and(t2_pointee)) => {
+            Some(DoubleCastAction::KeepBoth)
+        }
+
+        _ => None,
+    }
+}
+
+enum BinaryCastAction {
+    /// Move the outer cast into the operands of the operation, e.g.,
+    /// `($a as $ty1 + $b as $ty1) as $ty2` => `$a as $ty2 + $b as $ty2`.
+    /// For shifts, only the left operand gets narrowed.
+    NarrowOperands,
+    KeepAll,
+}
+
+// Check and decide what to do for a cast of a binary operation whose operands
+// are themselves casts, e.g., `($e1 as $op_ty + $e2 as $op_ty) as $t_ty`.
+// For shifts, `rhs_ty` is ignored and `shift` holds the literal shift amount.
+fn check_binary_cast(
+    op: BinOpKind,
+    lhs_ty: SimpleTy,
+    rhs_ty: SimpleTy,
+    op_ty: SimpleTy,
+    t_ty: SimpleTy,
+    shift: Option<u128>,
+) -> BinaryCastAction {
+    // WARNING!!! This set of operations is verified for soundness
+    // using Z3. If you make any changes, please re-run the verifier using
+    // `cargo test --package c2rust-refactor`
+    use BinOpKind::*;
+    if !lhs_ty.is_integer() || !op_ty.is_integer() || !t_ty.is_integer() {
+        return BinaryCastAction::KeepAll;
+    }
+    match cast_kind(op_ty, t_ty) {
+        // The low bits of the result only depend on the low bits
+        // of the operands, so we can only narrow the operation
+        CastKind::Truncate | CastKind::SameWidth => {}
+        _ => return BinaryCastAction::KeepAll,
+    }
+    // Rust panics on shifts by at least the bit width of the operand,
+    // so only narrow shifts by constants that fit in the narrower type
+    let shift_fits = |k: Option<u128>| match k {
+        Some(k) => k < t_ty.min_bit_width() as u128,
+        None => false,
+    };
+    match op {
+        Add | Sub | Mul | BitAnd | BitOr | BitXor if rhs_ty.is_integer() => {
+            BinaryCastAction::NarrowOperands
+        }
+
+        Shl if shift_fits(shift) => BinaryCastAction::NarrowOperands,
+
+        // Right shifts pull in the high bits of the wide operand, which are
+        // only correct if they're an extension of the narrow value itself,
+        // e.g., `(x as i32 >> 2) as i8` where `x: i8`
+        Shr if shift_fits(shift) && lhs_ty == t_ty => match (cast_kind(lhs_ty, op_ty), op_ty) {
+            (CastKind::Extend(_), SimpleTy::Int(..)) => BinaryCastAction::NarrowOperands,
+            // `isize`/`usize` might be just as wide as the operand,
+            // in which case the "extension" is really a sign flip
+            (CastKind::Extend(s), _) if s == op_ty.is_signed() => {
+                BinaryCastAction::NarrowOperands
+            }
+            _ => BinaryCastAction::KeepAll,
+        },
+
+        _ => BinaryCastAction::KeepAll,
+    }
+}
+
+/// Try to narrow a cast binary operation `($a as $t1 op $b as $t1) as $t2`
+/// down to `$a op $b` (or `$a as $t2 op $b as $t2`), using wrapping arithmetic
+/// methods where the narrower operation could overflow.
+fn narrow_binary_cast<'tcx>(
+    ast_mk: Builder,
+    op: BinOpKind,
+    lhs: &P<Expr>,
+    rhs: &P<Expr>,
+    op_ty: ty::Ty<'tcx>,
+    ot: &P<Ty>,
+    ot_ty: ty::Ty<'tcx>,
+    cx: &RefactorCtxt<'_, 'tcx>,
+) -> Option<P<Expr>> {
+    let tcx = cx.ty_ctxt();
+    let node_ty = |id| tcx.normalize_erasing_regions(ParamEnv::empty(), cx.node_type(id));
+
+    // We need the left operand to be a cast, since we use
+    // it to name the type of the narrowed operation
+    let (lhs_ie, lhs_ie_ty) = match lhs.kind {
+        ExprKind::Cast(ref ie, _) => (ie, node_ty(ie.id)),
+        _ => return None,
+    };
+    let is_shift = op == BinOpKind::Shl || op == BinOpKind::Shr;
+    let (rhs_ie, rhs_ie_ty, shift) = match rhs.kind {
+        ExprKind::Lit(ref lit) if is_shift => match lit.kind {
+            LitKind::Int(i, _) => (rhs, op_ty, Some(i)),
+            _ => return None,
+        },
+        ExprKind::Cast(ref ie, _) if !is_shift => (ie, node_ty(ie.id), None),
+        _ => return None,
+    };
+
+    let action = check_binary_cast(
+        op,
+        SimpleTy::from_ty(tcx, lhs_ie_ty),
+        SimpleTy::from_ty(tcx, rhs_ie_ty),
+        SimpleTy::from_ty(tcx, op_ty),
+        SimpleTy::from_ty(tcx, ot_ty),
+        shift,
+    );
+    if let BinaryCastAction::KeepAll = action {
+        return None;
+    }
+    // Only rewrite if we actually get rid of at least one cast
+    if lhs_ie_ty != ot_ty && (is_shift || rhs_ie_ty != ot_ty) {
+        return None;
+    }
+
+    let narrow = |ie: &P<Expr>, ie_ty| {
+        if ie_ty == ot_ty {
+            ie.clone()
+        } else {
+            mk().cast_expr(ie.clone(), ot.clone())
+        }
+    };
+    let new_lhs = narrow(lhs_ie, lhs_ie_ty);
+    let new_rhs = if is_shift {
+        rhs_ie.clone()
+    } else {
+        narrow(rhs_ie, rhs_ie_ty)
+    };
+    let new_expr = match op {
+        BinOpKind::Add => ast_mk.method_call_expr(new_lhs, "wrapping_add", vec![new_rhs]),
+        BinOpKind::Sub => ast_mk.method_call_expr(new_lhs, "wrapping_sub", vec![new_rhs]),
+        BinOpKind::Mul => ast_mk.method_call_expr(new_lhs, "wrapping_mul", vec![new_rhs]),
+        _ => ast_mk.binary_expr(op, new_lhs, new_rhs),
+    };
+    Some(new_expr)
+}
+
+pub(crate) enum CastKind {
     Extend(bool),
     Truncate,
     SameWidth,
     FromPointer(bool),
     ToPointer(bool),
+    FromFloat,
     Required,
     Unknown,
 }
 
-fn cast_kind(from_ty: SimpleTy, to_ty: SimpleTy) -> CastKind {
+/// Returns `true` if every value of the integer type `from_ty`
+/// is also a value of `to_ty`.
+pub(crate) fn is_lossless_int_cast(from_ty: SimpleTy, to_ty: SimpleTy) -> bool {
+    match (from_ty, to_ty) {
+        (SimpleTy::Int(fw, fs), SimpleTy::Int(tw, ts)) if fs == ts => fw <= tw,
+        (SimpleTy::Int(fw, false), SimpleTy::Int(tw, true)) => fw < tw,
+        _ => false,
+    }
+}
+
+pub(crate) fn cast_kind(from_ty: SimpleTy, to_ty: SimpleTy) -> CastKind {
     use SimpleTy::*;
     match (from_ty, to_ty) {
-        (Int(fw, fs), Int(tw, _)) if fw < tw => CastKind::Extend(fs),
-        (Int(fw, _), Int(tw, _)) if fw > tw => CastKind::Truncate,
-        (Int(..), Int(..)) => CastKind::SameWidth,
-
-        // Into size/pointer
-        (Int(fw, fs), Size(_)) | (Int(fw, fs), Pointer) if fw <= 16 => CastKind::Extend(fs),
-        (Int(fw, _), Size(_)) | (Int(fw, _), Pointer) if fw >= 64 => CastKind::Truncate,
-        (Int(..), Size(ts)) => CastKind::ToPointer(ts),
-        (Int(..), Pointer) => CastKind::ToPointer(false),
-
-        // From size/pointer
-        (Size(fs), Int(tw, _)) if tw >= 64 => CastKind::Extend(fs),
-        (Pointer, Int(tw, _)) if tw >= 64 => CastKind::Extend(false),
-        (Size(_), Int(tw, _)) | (Pointer, Int(tw, _)) if tw <= 16 => CastKind::Truncate,
-        (Size(fs), Int(..)) => CastKind::FromPointer(fs),
-        (Pointer, Int(..)) => CastKind::FromPointer(false),
+        // An enum casts like an integer holding its discriminant,
+        // and a `bool` like a 1-bit unsigned integer
+        (Enum(fw, fs), _) => cast_kind(Int(fw, fs), to_ty),
+        (Bool, _) => cast_kind(Int(1, false), to_ty),
+
+        // `usize`/`isize` have a known width on the target, so they behave like any other integer
+        (Int(fw, fs), Int(tw, _))
+        | (Int(fw, fs), Size(tw, _))
+        | (Size(fw, fs), Int(tw, _))
+        | (Size(fw, fs), Size(tw, _))
+            if fw < tw =>
+        {
+            CastKind::Extend(fs)
+        }
+        (Int(fw, _), Int(tw, _))
+        | (Int(fw, _), Size(tw, _))
+        | (Size(fw, _), Int(tw, _))
+        | (Size(fw, _), Size(tw, _))
+            if fw > tw =>
+        {
+            CastKind::Truncate
+        }
+        (Int(..), Int(..)) | (Int(..), Size(..)) | (Size(..), Int(..)) | (Size(..), Size(..)) => {
+            CastKind::SameWidth
+        }
+
+        // Into pointer
+        (Int(fw, fs), Pointer(_)) if fw <= 16 => CastKind::Extend(fs),
+        (Int(fw, _), Pointer(_)) if fw >= 64 => CastKind::Truncate,
+        // Signed integers get sign-extended to the pointer width, e.g.,
+        // `-1i32 as *const T` is all ones on 64-bit targets
+        (Int(_, fs), Pointer(_)) => CastKind::ToPointer(fs),
+
+        // From pointer
+        (Pointer(_), Int(tw, _)) if tw >= 64 => CastKind::Extend(false),
+        (Pointer(_), Int(tw, _)) if tw <= 16 => CastKind::Truncate,
+        (Pointer(_), Int(..)) => CastKind::FromPointer(false),
 
         // Pointer-to-size and vice versa
-        (Pointer, Pointer) | (Pointer, Size(_)) | (Size(_), Pointer) | (Size(_), Size(_)) => {
+        (Pointer(_), Pointer(_)) | (Pointer(_), Size(..)) | (Size(..), Pointer(_)) => {
             CastKind::SameWidth
         }
 
         // We need to keep all `&x as *const T` and `&[T; N] as *const T` casts
-        (Ref, Pointer) | (Array, Pointer) => CastKind::Required,
+        (Ref, Pointer(_)) | (Array, Pointer(_)) => CastKind::Required,
 
         (Float32, Float32) => CastKind::SameWidth,
         (Float32, Float64) => CastKind::Extend(true),
         (Float64, Float32) => CastKind::Truncate,
         (Float64, Float64) => CastKind::SameWidth,
 
-        //// Any integer that fits into sign+mantissa is getting extended
-        //// TODO: these require a Z3 bitwise simulation for the conversions
-        //(Int(fw, fs), Float32) if fw <= 23 => CastKind::Extend(fs),
-        //(Int(fw, fs), Float64) if fw <= 52 => CastKind::Extend(fs),
-        //(Int(..), Float32) => CastKind::Truncate,
-        //(Int(..), Float64) => CastKind::Truncate,
+        // Any integer that fits into the mantissa converts exactly
+        (Int(fw, fs), Float32) | (Size(fw, fs), Float32) if fw <= 16 => CastKind::Extend(fs),
+        (Int(fw, fs), Float64) | (Size(fw, fs), Float64) if fw <= 32 => CastKind::Extend(fs),
+
+        (Float32, Int(..)) | (Float32, Size(..)) | (Float64, Int(..)) | (Float64, Size(..)) => {
+            CastKind::FromFloat
+        }
+
         (_, _) => CastKind::Unknown,
     }
 }
@@ -232,22 +723,70 @@ fn cast_kind(from_ty: SimpleTy, to_ty: SimpleTy) -> CastKind {
 // We need to lower `ty::Ty` into our own `SimpleTy`
 // because the unit tests have no way of creating new `TyS` values
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-enum SimpleTy {
+pub(crate) enum SimpleTy {
     Int(usize, bool),
-    Size(bool),
+    /// `usize` or `isize`, with the pointer width of the compilation target
+    Size(usize, bool),
     Float32,
     Float64,
-    Pointer,
+    Char,
+    Bool,
+    /// Raw or function pointer, with its mutability
+    Pointer(hir::Mutability),
     Ref,
     Array,
+    /// Fieldless enum, with the width and signedness of an integer type that
+    /// holds all of its discriminants
+    Enum(usize, bool),
     Other,
 }
 
 impl SimpleTy {
-    fn is_signed(&self) -> bool {
+    pub(crate) fn is_integer(&self) -> bool {
+        match self {
+            SimpleTy::Int(..) | SimpleTy::Size(..) => true,
+            _ => false,
+        }
+    }
+
+    fn is_pointer(&self) -> bool {
+        match self {
+            SimpleTy::Pointer(_) => true,
+            _ => false,
+        }
+    }
+
+    pub(crate) fn is_float(&self) -> bool {
+        match self {
+            SimpleTy::Float32 | SimpleTy::Float64 => true,
+            _ => false,
+        }
+    }
+
+    fn is_enum(&self) -> bool {
+        match self {
+            SimpleTy::Enum(..) => true,
+            _ => false,
+        }
+    }
+
+    /// Return the smallest bit width this type can have on any target.
+    fn min_bit_width(&self) -> usize {
+        match self {
+            SimpleTy::Int(w, _) | SimpleTy::Size(w, _) | SimpleTy::Enum(w, _) => *w,
+            SimpleTy::Pointer(_) => 16,
+            SimpleTy::Bool => 1,
+            SimpleTy::Float32 => 32,
+            SimpleTy::Float64 => 64,
+            _ => panic!("min_bit_width() called with non-primitive type")
+        }
+    }
+
+    pub(crate) fn is_signed(&self) -> bool {
         match self {
             SimpleTy::Int(_, s) => *s,
-            SimpleTy::Size(s) => *s,
+            SimpleTy::Size(_, s) => *s,
+            SimpleTy::Enum(_, s) => *s,
             SimpleTy::Float32 => true,
             SimpleTy::Float64 => true,
             _ => false,
@@ -266,8 +805,8 @@ impl SimpleTy {
             SimpleTy::Int(32, true) => LitIntType::Signed(IntTy::I32),
             SimpleTy::Int(64, true) => LitIntType::Signed(IntTy::I64),
             SimpleTy::Int(128, true) => LitIntType::Signed(IntTy::I128),
-            SimpleTy::Size(false) => LitIntType::Unsigned(UintTy::Usize),
-            SimpleTy::Size(true) => LitIntType::Signed(IntTy::Isize),
+            SimpleTy::Size(_, false) => LitIntType::Unsigned(UintTy::Usize),
+            SimpleTy::Size(_, true) => LitIntType::Signed(IntTy::Isize),
             _ => panic!("ast_lit_int_type() called with non-integer type")
         }
     }
@@ -280,7 +819,7 @@ impl SimpleTy {
         }
     }
 
-    fn max_int_value(&self) -> u128 {
+    pub(crate) fn max_int_value(&self) -> u128 {
         match self {
             SimpleTy::Int(8, false) => u8::max_value() as u128,
             SimpleTy::Int(16, false) => u16::max_value() as u128,
@@ -292,17 +831,18 @@ impl SimpleTy {
             SimpleTy::Int(32, true) => i32::max_value() as u128,
             SimpleTy::Int(64, true) => i64::max_value() as u128,
             SimpleTy::Int(128, true) => i128::max_value() as u128,
+            SimpleTy::Size(w, s) => SimpleTy::Int(*w, *s).max_int_value(),
             _ => panic!("max_int_value() called with non-integer type")
         }
     }
 }
 
-impl<'tcx> From<ty::Ty<'tcx>> for SimpleTy {
-    fn from(ty: ty::Ty<'tcx>) -> Self {
+impl SimpleTy {
+    pub(crate) fn from_ty<'tcx>(tcx: TyCtxt<'tcx>, ty: ty::Ty<'tcx>) -> Self {
         use SimpleTy::*;
         match ty.kind {
-            TyKind::Int(IntTy::Isize) => Size(true),
-            TyKind::Uint(UintTy::Usize) => Size(false),
+            TyKind::Int(IntTy::Isize) => Size(pointer_width(tcx), true),
+            TyKind::Uint(UintTy::Usize) => Size(pointer_width(tcx), false),
 
             TyKind::Int(int_ty) => Int(int_ty.bit_width().unwrap(), true),
             TyKind::Uint(uint_ty) => Int(uint_ty.bit_width().unwrap(), false),
@@ -310,16 +850,66 @@ impl<'tcx> From<ty::Ty<'tcx>> for SimpleTy {
             TyKind::Float(FloatTy::F32) => Float32,
             TyKind::Float(FloatTy::F64) => Float64,
 
+            TyKind::Char => Char,
+            TyKind::Bool => Bool,
+
             TyKind::Ref(_, ty, _mutbl) => match ty.kind {
                 TyKind::Array(..) => Array,
                 _ => Ref,
             }
 
-            TyKind::RawPtr(_) | TyKind::FnPtr(_) => Pointer,
+            TyKind::RawPtr(ty::TypeAndMut { mutbl, .. }) => Pointer(mutbl),
+            TyKind::FnPtr(_) => Pointer(hir::Mutability::Immutable),
+
+            TyKind::Adt(def, _) if def.is_enum() => Self::from_enum(tcx, def),
 
             _ => Other,
         }
     }
+
+    fn from_enum<'tcx>(tcx: TyCtxt<'tcx>, def: &'tcx ty::AdtDef) -> Self {
+        if def.variants.iter().any(|v| !v.fields.is_empty()) {
+            return SimpleTy::Other;
+        }
+
+        match def.repr.int {
+            Some(attr::IntType::SignedInt(int_ty)) => {
+                let w = int_ty.bit_width().unwrap_or_else(|| pointer_width(tcx));
+                return SimpleTy::Enum(w, true);
+            }
+            Some(attr::IntType::UnsignedInt(uint_ty)) => {
+                let w = uint_ty.bit_width().unwrap_or_else(|| pointer_width(tcx));
+                return SimpleTy::Enum(w, false);
+            }
+            None => {}
+        }
+
+        // Without an explicit integer `repr`, the discriminants are `isize`
+        // values, but rustc is free to store them in a smaller type. Casts
+        // only depend on the discriminant values, so we pick the smallest
+        // integer type that holds all of them.
+        let pw = pointer_width(tcx) as u32;
+        let (mut min, mut max) = (0i128, 0i128);
+        for (_, discr) in def.discriminants(tcx) {
+            // `discr.val` holds the bits of an `isize`, so sign-extend it
+            let val = ((discr.val << (128 - pw)) as i128) >> (128 - pw);
+            min = min.min(val);
+            max = max.max(val);
+        }
+        let signed = min < 0;
+        let fits = |w: u32| if signed {
+            min >= -(1i128 << (w - 1)) && max < (1i128 << (w - 1))
+        } else {
+            max < (1i128 << w)
+        };
+        let w = [8, 16, 32, 64].iter().cloned().find(|&w| fits(w)).unwrap_or(128);
+        SimpleTy::Enum(w as usize, signed)
+    }
+}
+
+/// Returns the width in bits of pointers and `usize`/`isize` on the compilation target.
+fn pointer_width(tcx: TyCtxt) -> usize {
+    tcx.data_layout.pointer_size.bits() as usize
 }
 
 /// Returns the correct `LitKind` for the given `Symbol` encoding of an integer.
@@ -335,7 +925,7 @@ pub(crate) fn sym_token_kind(sym: Symbol) -> token::LitKind {
     }
 }
 
-fn replace_suffix<'tcx>(lit: &Lit, ty: SimpleTy) -> Option<Lit> {
+fn replace_suffix<'tcx>(lit: &Lit, ty: SimpleTy, fold_char_lits: bool) -> Option<Lit> {
     let mk_int = |i, ty| {
         // We need to build the new `Lit` ourselves instead of
         // calling `mk().int_lit()`, so we can reuse
@@ -372,34 +962,56 @@ fn replace_suffix<'tcx>(lit: &Lit, ty: SimpleTy) -> Option<Lit> {
 
     let lit_mk = mk().span(lit.span);
     match (&lit.kind, &ty) {
-        // Very conservative approach: only convert to `isize`/`usize`
-        // if the value fits in a 16-bit value
-        (LitKind::Int(i, _), SimpleTy::Size(true)) if *i <= i16::max_value() as u128 => {
-            mk_int(*i, LitIntType::Signed(IntTy::Isize))
+        // `b'A' as u8` => `b'A'`, `'A' as char` => `'A'`
+        (LitKind::Byte(_), SimpleTy::Int(8, false)) | (LitKind::Char(_), SimpleTy::Char) => {
+            Some(lit.clone())
         }
 
-        (LitKind::Int(i, _), SimpleTy::Size(false)) if *i <= u16::max_value() as u128 => {
-            mk_int(*i, LitIntType::Unsigned(UintTy::Usize))
+        // `b'A' as u32` => `65u32`, `'A' as u32` => `65u32`;
+        // these are only done on request since they hurt readability
+        (LitKind::Byte(b), SimpleTy::Int(..)) if fold_char_lits => {
+            Some(lit_mk.int_lit(*b as u128, ty.ast_lit_int_type()))
+        }
+        (LitKind::Char(c), SimpleTy::Int(..))
+            if fold_char_lits && *c as u128 <= ty.max_int_value() =>
+        {
+            Some(lit_mk.int_lit(*c as u128, ty.ast_lit_int_type()))
         }
 
-        (LitKind::Int(i, _), SimpleTy::Int(..)) if *i <= ty.max_int_value() => {
+        (LitKind::Int(i, _), SimpleTy::Int(..)) | (LitKind::Int(i, _), SimpleTy::Size(..))
+            if *i <= ty.max_int_value() =>
+        {
             mk_int(*i, ty.ast_lit_int_type())
         }
 
+        // `true as i32` => `1i32`
+        (LitKind::Bool(_), SimpleTy::Bool) => Some(lit.clone()),
+        (LitKind::Bool(b), SimpleTy::Int(..)) | (LitKind::Bool(b), SimpleTy::Size(..)) => {
+            Some(lit_mk.int_lit(*b as u128, ty.ast_lit_int_type()))
+        }
+
+        // `16777217 as f32` rounds to `16777216f32`, so we only
+        // fold integers that the float type holds exactly
         (LitKind::Int(i, _), SimpleTy::Float32)
-        | (LitKind::Int(i, _), SimpleTy::Float64) => {
+        | (LitKind::Int(i, _), SimpleTy::Float64) if is_exact_float(*i, ty.ast_float_ty()) => {
             mk_float(i.to_string(), ty.ast_float_ty())
         }
 
         (LitKind::Float(f, LitFloatType::Suffixed(FloatTy::F32)), SimpleTy::Int(..)) => {
-            let fv = f.as_str().parse::<f32>().ok()?;
-            Some(lit_mk.int_lit(fv as u128, ty.ast_lit_int_type()))
+            let i = exact_float_lit_int(&f.as_str(), FloatTy::F32)?;
+            if i > ty.max_int_value() {
+                return None;
+            }
+            Some(lit_mk.int_lit(i, ty.ast_lit_int_type()))
         }
 
         (LitKind::Float(f, LitFloatType::Suffixed(FloatTy::F64)), SimpleTy::Int(..))
         | (LitKind::Float(f, LitFloatType::Unsuffixed), SimpleTy::Int(..)) => {
-            let fv = f.as_str().parse::<f64>().ok()?;
-            Some(lit_mk.int_lit(fv as u128, ty.ast_lit_int_type()))
+            let i = exact_float_lit_int(&f.as_str(), FloatTy::F64)?;
+            if i > ty.max_int_value() {
+                return None;
+            }
+            Some(lit_mk.int_lit(i, ty.ast_lit_int_type()))
         }
 
         (LitKind::Float(f, LitFloatType::Suffixed(FloatTy::F32)), SimpleTy::Float32)
@@ -420,16 +1032,174 @@ fn replace_suffix<'tcx>(lit: &Lit, ty: SimpleTy) -> Option<Lit> {
     }
 }
 
+/// Returns `true` if the float type `ty` holds the integer `i` exactly.
+fn is_exact_float(i: u128, ty: FloatTy) -> bool {
+    match ty {
+        FloatTy::F32 => (i as f32) as u128 == i,
+        FloatTy::F64 => (i as f64) as u128 == i,
+    }
+}
+
+/// Returns the value of the float literal `s` of type `ty` if it's written as a
+/// non-negative integer, e.g., `5` or `5.0`, and `ty` holds that integer exactly.
+/// For example, `16777217f32` actually holds `16777216`, so we return `None` for it.
+fn exact_float_lit_int(s: &str, ty: FloatTy) -> Option<u128> {
+    let s = s.replace('_', "");
+    let (int_part, frac_part) = match s.find('.') {
+        Some(pos) => (&s[..pos], &s[pos + 1..]),
+        None => (&s[..], ""),
+    };
+    if !frac_part.chars().all(|c| c == '0') {
+        return None;
+    }
+    // `parse` would also accept a leading `+`
+    if int_part.is_empty() || !int_part.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let i = int_part.parse::<u128>().ok()?;
+    if !is_exact_float(i, ty) {
+        return None;
+    }
+    // Round-trip the integer back through the float and compare bit patterns
+    let exact = match ty {
+        FloatTy::F32 => s.parse::<f32>().ok()?.to_bits() == (i as f32).to_bits(),
+        FloatTy::F64 => s.parse::<f64>().ok()?.to_bits() == (i as f64).to_bits(),
+    };
+    if exact {
+        Some(i)
+    } else {
+        None
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 enum ConstantValue {
     Int(i128),
     Uint(u128),
     Float32(f32),
     Float64(f64),
+    Char(char),
+    Bool(bool),
 }
 
 impl ConstantValue {
-    fn cast(self, ty: SimpleTy) -> Self {
+    fn cast(self, ty: SimpleTy) -> Option<Self> {
+        use ConstantValue::*;
+        let this = match (self, &ty) {
+            (Char(c), SimpleTy::Char) => return Some(Char(c)),
+            // Only `u8` can be cast to `char`, but we also reject surrogates
+            // and out-of-range values here, just like `char::from_u32`
+            (Uint(v), SimpleTy::Char) if v <= u32::max_value() as u128 => {
+                return std::char::from_u32(v as u32).map(Char);
+            }
+            (_, SimpleTy::Char) => return None,
+            // Nothing but a `bool` can be cast to `bool`
+            (Bool(b), SimpleTy::Bool) => return Some(Bool(b)),
+            (_, SimpleTy::Bool) => return None,
+            // ...and a `bool` only casts to integers, as 0 or 1
+            (Bool(_), SimpleTy::Float32) | (Bool(_), SimpleTy::Float64) => return None,
+            (Bool(b), _) => Uint(b as u128),
+            // Chars can only be cast to integers, which truncate the code point
+            (Char(_), SimpleTy::Float32) | (Char(_), SimpleTy::Float64) => return None,
+            (Char(c), _) => Uint(c as u128),
+            (_, ty) if !ty.is_integer() && !ty.is_float() => return None,
+            // `usize`/`isize` behave just like integers of the target's pointer width
+            (this, SimpleTy::Size(w, s)) => return this.cast(SimpleTy::Int(*w, *s)),
+            (this, _) => this,
+        };
+        Some(this.cast_numeric(ty))
+    }
+
+    /// Build a constant from the raw bits the compiler's constant evaluator returns.
+    fn from_bits(bits: u128, ty: SimpleTy) -> Option<Self> {
+        match ty {
+            SimpleTy::Float32 => Some(ConstantValue::Float32(f32::from_bits(bits as u32))),
+            SimpleTy::Float64 => Some(ConstantValue::Float64(f64::from_bits(bits as u64))),
+            SimpleTy::Int(..) | SimpleTy::Size(..) | SimpleTy::Char => {
+                ConstantValue::Uint(bits).cast(ty)
+            }
+            _ => None,
+        }
+    }
+
+    /// Evaluate `self op rhs` in type `ty`. Returns `None` if the operation
+    /// overflows or can't be evaluated.
+    fn binary_op(self, op: BinOpKind, rhs: Self, ty: SimpleTy) -> Option<Self> {
+        use BinOpKind::*;
+        use ConstantValue::*;
+
+        if op == Shl || op == Shr {
+            // The shift amount can have any integer type,
+            // but it needs to be smaller than the bit width
+            let k = match rhs {
+                Int(k) if k >= 0 => k as u128,
+                Uint(k) => k,
+                _ => return None,
+            };
+            if k >= ty.min_bit_width() as u128 {
+                return None;
+            }
+            let k = k as u32;
+            // Left shifts silently drop the high bits, which `cast` does for us
+            return match (self, op) {
+                (Int(a), Shl) => Int(a << k).cast(ty),
+                (Uint(a), Shl) => Uint(a << k).cast(ty),
+                (Int(a), Shr) => Some(Int(a >> k)),
+                (Uint(a), Shr) => Some(Uint(a >> k)),
+                _ => None,
+            };
+        }
+
+        let result = match (self, rhs.cast(ty)?) {
+            (Int(a), Int(b)) => Int(match op {
+                Add => a.checked_add(b)?,
+                Sub => a.checked_sub(b)?,
+                Mul => a.checked_mul(b)?,
+                Div => a.checked_div(b)?,
+                Rem => a.checked_rem(b)?,
+                BitAnd => a & b,
+                BitOr => a | b,
+                BitXor => a ^ b,
+                _ => return None,
+            }),
+            (Uint(a), Uint(b)) => Uint(match op {
+                Add => a.checked_add(b)?,
+                Sub => a.checked_sub(b)?,
+                Mul => a.checked_mul(b)?,
+                Div => a.checked_div(b)?,
+                Rem => a.checked_rem(b)?,
+                BitAnd => a & b,
+                BitOr => a | b,
+                BitXor => a ^ b,
+                _ => return None,
+            }),
+            (Float32(a), Float32(b)) => Float32(match op {
+                Add => a + b,
+                Sub => a - b,
+                Mul => a * b,
+                Div => a / b,
+                Rem => a % b,
+                _ => return None,
+            }),
+            (Float64(a), Float64(b)) => Float64(match op {
+                Add => a + b,
+                Sub => a - b,
+                Mul => a * b,
+                Div => a / b,
+                Rem => a % b,
+                _ => return None,
+            }),
+            _ => return None,
+        };
+
+        // Overflowing the type of the operation is an error
+        if result.cast(ty)? != result {
+            return None;
+        }
+        Some(result)
+    }
+
+    fn cast_numeric(self, ty: SimpleTy) -> Self {
         use ConstantValue::*;
         macro_rules! match_ty {
             ($($pat:pat => $const_ty:ident[$($as_ty:ty),*]),*) => {
@@ -455,8 +1225,6 @@ impl ConstantValue {
             SimpleTy::Int(32, true) => Int[i32, i128],
             SimpleTy::Int(64, true) => Int[i64, i128],
             SimpleTy::Int(128, true) => Int[i128],
-            SimpleTy::Size(false) => Uint[usize, u128],
-            SimpleTy::Size(true) => Int[isize, i128],
             SimpleTy::Float32 => Float32[f32],
             SimpleTy::Float64 => Float64[f64]
         };
@@ -470,7 +1238,8 @@ fn eval_const<'tcx>(e: P<Expr>, cx: &RefactorCtxt) -> Option<ConstantValue> {
                 LitKind::Int(i, LitIntType::Unsuffixed) => Some(ConstantValue::Uint(i)),
 
                 LitKind::Int(i, LitIntType::Signed(IntTy::Isize)) => {
-                    Some(ConstantValue::Int(i as i16 as i128))
+                    let pw = pointer_width(cx.ty_ctxt());
+                    ConstantValue::Uint(i).cast(SimpleTy::Int(pw, true))
                 }
 
                 LitKind::Int(i, LitIntType::Signed(IntTy::I8)) => {
@@ -494,7 +1263,8 @@ fn eval_const<'tcx>(e: P<Expr>, cx: &RefactorCtxt) -> Option<ConstantValue> {
                 }
 
                 LitKind::Int(i, LitIntType::Unsigned(UintTy::Usize)) => {
-                    Some(ConstantValue::Uint(i as u16 as u128))
+                    let pw = pointer_width(cx.ty_ctxt());
+                    ConstantValue::Uint(i).cast(SimpleTy::Int(pw, false))
                 }
 
                 LitKind::Int(i, LitIntType::Unsigned(UintTy::U8)) => {
@@ -528,8 +1298,10 @@ fn eval_const<'tcx>(e: P<Expr>, cx: &RefactorCtxt) -> Option<ConstantValue> {
                     Some(ConstantValue::Float64(fv))
                 }
 
-                // TODO: Byte
-                // TODO: Char
+                LitKind::Byte(b) => Some(ConstantValue::Uint(b as u128)),
+                LitKind::Char(c) => Some(ConstantValue::Char(c)),
+                LitKind::Bool(b) => Some(ConstantValue::Bool(b)),
+
                 _ => None,
             }
         }
@@ -537,14 +1309,25 @@ fn eval_const<'tcx>(e: P<Expr>, cx: &RefactorCtxt) -> Option<ConstantValue> {
         ExprKind::Unary(UnOp::Neg, ref ie) => {
             let ic = eval_const(ie.clone(), cx)?;
             use ConstantValue::*;
-            match ic {
+            let nc = match ic {
                 // Check for overflow for Uint
-                Uint(i) if i > (i128::max_value() as u128) => None,
-                Uint(i) => Some(Int(-(i as i128))),
-
-                Int(i) => Some(Int(-i)),
-                Float32(f) => Some(Float32(-f)),
-                Float64(f) => Some(Float64(-f)),
+                Uint(i) if i > (i128::max_value() as u128) => return None,
+                Uint(i) => Int(-(i as i128)),
+
+                Int(i) => Int(i.wrapping_neg()),
+                Float32(f) => Float32(-f),
+                Float64(f) => Float64(-f),
+                Char(_) | Bool(_) => return None,
+            };
+            // Negation wraps around in the type of the expression, e.g.,
+            // `128i8` evaluates to -128, so `-128i8` needs to stay -128
+            let tcx = cx.ty_ctxt();
+            match cx.opt_node_type(e.id) {
+                Some(ty) => {
+                    let ty = tcx.normalize_erasing_regions(ParamEnv::empty(), ty);
+                    nc.cast(SimpleTy::from_ty(tcx, ty))
+                }
+                None => Some(nc),
             }
         }
 
@@ -553,61 +1336,1090 @@ fn eval_const<'tcx>(e: P<Expr>, cx: &RefactorCtxt) -> Option<ConstantValue> {
             let ty_ty = cx.node_type(ty.id);
             let ty_ty = tcx.normalize_erasing_regions(ParamEnv::empty(), ty_ty);
             let ic = eval_const(ie.clone(), cx)?;
-            Some(ic.cast(SimpleTy::from(ty_ty)))
+            ic.cast(SimpleTy::from_ty(tcx, ty_ty))
+        }
+
+        ExprKind::Path(..) => {
+            // Paths to `const` items get evaluated by the compiler itself
+            let tcx = cx.ty_ctxt();
+            let def_id = cx.try_resolve_expr(&e)?;
+            match tcx.def_kind(def_id)? {
+                DefKind::Const | DefKind::AssocConst => {}
+                _ => return None,
+            }
+            // Consts of generic impls and traits depend on substs we don't know here
+            if tcx.generics_of(def_id).count() != 0 {
+                return None;
+            }
+            let ty = tcx.type_of(def_id);
+            let gid = GlobalId {
+                instance: Instance::mono(tcx, def_id),
+                promoted: None,
+            };
+            let c = tcx.const_eval(ParamEnv::empty().and(gid)).ok()?;
+            let bits = c.try_eval_bits(tcx, ParamEnv::empty(), ty)?;
+            ConstantValue::from_bits(bits, SimpleTy::from_ty(tcx, ty))
+        }
+
+        ExprKind::Binary(ref op, ref lhs, ref rhs) => {
+            let tcx = cx.ty_ctxt();
+            let ty = cx.node_type(e.id);
+            let ty = tcx.normalize_erasing_regions(ParamEnv::empty(), ty);
+            let ty = SimpleTy::from_ty(tcx, ty);
+            let lc = eval_const(lhs.clone(), cx)?.cast(ty)?;
+            let rc = eval_const(rhs.clone(), cx)?;
+            lc.binary_op(op.node, rc, ty)
         }
 
-        _ => unreachable!("Unexpected ExprKind"),
+        ExprKind::Paren(ref ie) => eval_const(ie.clone(), cx),
+
+        _ => None,
     }
 }
 
-/// # `convert_cast_as_ptr` Command
-///
-/// Usage: `convert_cast_as_ptr`
-///
-/// Converts all expressions like `$e as *const $t` (with mutable or const pointers)
-/// where `$e` is a slice or array into `$e.as_ptr()` calls.
-pub struct ConvertCastAsPtr;
+/// Fold a cast of a constant expression, e.g., `(2 + 3) as u8`,
+/// into a single literal of the target type, negated if the value is negative.
+fn fold_const_cast(e: &P<Expr>, ty: SimpleTy, cx: &RefactorCtxt) -> Option<P<Expr>> {
+    let value = eval_const(e.clone(), cx)?;
+    let lit_mk = mk().span(e.span);
+    let expr_mk = mk().id(e.id).span(e.span);
+    let new_expr = match (value, ty) {
+        (ConstantValue::Uint(i), SimpleTy::Int(..))
+        | (ConstantValue::Uint(i), SimpleTy::Size(..)) => {
+            expr_mk.lit_expr(lit_mk.int_lit(i, ty.ast_lit_int_type()))
+        }
 
-impl Transform for ConvertCastAsPtr {
-    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
-        replace_expr(
-            st,
-            cx,
-            krate,
-            "typed!($expr:Expr, &[$ty:Ty]) as *const $ty",
-            "$expr.as_ptr()",
-        );
-        replace_expr(
-            st,
-            cx,
-            krate,
-            "typed!($expr:Expr, &[$ty:Ty]) as *mut $ty",
-            "$expr.as_mut_ptr()",
-        );
-        replace_expr(
-            st,
-            cx,
-            krate,
-            "typed!($expr:Expr, &[$ty:Ty; $len]) as *const $ty",
-            "$expr.as_ptr()",
-        );
-        replace_expr(
-            st,
-            cx,
-            krate,
-            "typed!($expr:Expr, &[$ty:Ty; $len]) as *mut $ty",
-            "$expr.as_mut_ptr()",
-        );
-    }
+        (ConstantValue::Int(i), SimpleTy::Int(..))
+        | (ConstantValue::Int(i), SimpleTy::Size(..)) if i >= 0 => {
+            expr_mk.lit_expr(lit_mk.int_lit(i as u128, ty.ast_lit_int_type()))
+        }
 
-    fn min_phase(&self) -> Phase {
-        Phase::Phase3
+        // Literals can't be negative themselves, so negate the magnitude,
+        // e.g., `-5i8`
+        (ConstantValue::Int(i), SimpleTy::Int(..))
+        | (ConstantValue::Int(i), SimpleTy::Size(..)) => {
+            let lit = lit_mk.int_lit((i as u128).wrapping_neg(), ty.ast_lit_int_type());
+            expr_mk.unary_expr(UnOp::Neg, mk().span(e.span).lit_expr(lit))
+        }
+
+        _ => return None,
+    };
+    let new_const = eval_const(new_expr.clone(), cx);
+    debug!("checking {:?} == {:?}: {:?} == {:?}", e, new_expr, value, new_const);
+    if new_const == Some(value) {
+        Some(new_expr)
+    } else {
+        None
     }
 }
 
-pub fn register_commands(reg: &mut Registry) {
+/// Fold the cast `e` of a constant expression to `ot` with `fold_const_cast`,
+/// or with `fold_alias_cast` if `keep_alias` is set.
+fn fold_const_expr_cast(
+    e: &P<Expr>,
+    keep_alias: bool,
+    ot: &P<ast::Ty>,
+    ot_ty: SimpleTy,
+    cx: &RefactorCtxt,
+) -> Option<P<Expr>> {
+    if keep_alias {
+        fold_alias_cast(e, ot, cx)
+    } else {
+        fold_const_cast(e, ot_ty, cx)
+    }
+}
+
+/// Fold the constant cast `e` to the type alias `alias` into a cast of an unsuffixed literal,
+/// e.g., `(2 + 3) as uint8_t` => `5 as uint8_t`.
+fn fold_alias_cast(e: &P<Expr>, alias: &P<ast::Ty>, cx: &RefactorCtxt) -> Option<P<Expr>> {
+    let value = eval_const(e.clone(), cx)?;
+    let i = match value {
+        ConstantValue::Uint(i) => i,
+        ConstantValue::Int(i) if i >= 0 => i as u128,
+        _ => return None,
+    };
+    // Unsuffixed integer literals default to `i32`, so anything larger would overflow
+    if i > i32::max_value() as u128 {
+        return None;
+    }
+    let lit = mk().span(e.span).lit_expr(mk().int_lit(i, LitIntType::Unsuffixed));
+    let new_expr = mk().id(e.id).span(e.span).cast_expr(lit, alias.clone());
+    let new_const = eval_const(new_expr.clone(), cx);
+    debug!("checking {:?} == {:?}: {:?} == {:?}", e, new_expr, value, new_const);
+    if new_const == Some(value) {
+        Some(new_expr)
+    } else {
+        None
+    }
+}
+
+/// Returns `true` if `ty` names a type alias, e.g., `libc::c_int`.
+fn is_alias_ty(ty: &ast::Ty, cx: &RefactorCtxt) -> bool {
+    match cx.try_resolve_ty_hir(ty) {
+        Some(Res::Def(DefKind::TyAlias, _)) => true,
+        _ => false,
+    }
+}
+
+/// Returns `true` if `e` is built only out of literals, e.g., `(2 + 3) as u8`.
+pub(crate) fn is_lit_expr(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Lit(_) => true,
+        ExprKind::Unary(UnOp::Neg, ref ie)
+        | ExprKind::Cast(ref ie, _)
+        | ExprKind::Paren(ref ie) => is_lit_expr(ie),
+        ExprKind::Binary(_, ref lhs, ref rhs) => is_lit_expr(lhs) && is_lit_expr(rhs),
+        _ => false,
+    }
+}
+
+/// Returns `true` if `e` is built only out of literals and paths to `const` items,
+/// e.g., `(SOME_CONST + 3) as u8`, so `eval_const` can evaluate it.
+fn is_const_expr(e: &Expr, cx: &RefactorCtxt) -> bool {
+    match e.kind {
+        ExprKind::Lit(_) => true,
+        // Associated consts like `Foo::BAR` are type-relative, so they only resolve through
+        // the typeck tables
+        ExprKind::Path(..) => {
+            let def_kind = cx.try_resolve_expr(e).and_then(|id| cx.ty_ctxt().def_kind(id));
+            match def_kind {
+                Some(DefKind::Const) | Some(DefKind::AssocConst) => true,
+                _ => false,
+            }
+        }
+        ExprKind::Unary(UnOp::Neg, ref ie)
+        | ExprKind::Cast(ref ie, _)
+        | ExprKind::Paren(ref ie) => is_const_expr(ie, cx),
+        ExprKind::Binary(_, ref lhs, ref rhs) => is_const_expr(lhs, cx) && is_const_expr(rhs, cx),
+        _ => false,
+    }
+}
+
+/// # `minimize_casts` Command
+///
+/// Rewrites integer arithmetic that is done in a wide type and then cast to a narrower one,
+/// e.g., `(a as i32 & b as i32) as u8` where `a: u8` and `b: u8`, so that it operates on the
+/// narrower type directly, e.g., `a & b`. The whole expression gets narrowed at once, so all
+/// of its operands need to be literals or casts from the narrower type.
+///
+/// Only `&`, `|`, `^`, `+`, `-` and `*` get narrowed, the last three using the `wrapping_*`
+/// methods; shifts, division and remainder are always left alone. Narrowing `+`, `-` or `*`
+/// done in a signed type moves where the operation overflows, which was undefined behavior
+/// in the original C code, so those are only narrowed if `--signed` is passed.
+pub struct MinimizeCasts {
+    pub signed: bool,
+}
+
+impl Transform for MinimizeCasts {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+        let mut mcx = MatchCtxt::new(st, cx);
+        let pat = mcx.parse_expr("$e:Expr as $t:Ty");
+        mut_visit_match_with(mcx, pat, krate, |ast, mcx| {
+            let e = mcx.bindings.get::<_, P<Expr>>("$e").unwrap();
+            match e.kind {
+                ExprKind::Binary(..) => {}
+                _ => return,
+            }
+            let t_ty = cx.node_type(mcx.bindings.get::<_, P<Ty>>("$t").unwrap().id);
+            let t_ty = tcx.normalize_erasing_regions(ParamEnv::empty(), t_ty);
+            if let Some(ne) = self.narrow_expr(e, t_ty, cx) {
+                debug!("minimized {:?} => {:?}", ast, ne);
+                *ast = ne;
+            }
+        })
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+impl MinimizeCasts {
+    /// Build an expression of type `t_ty` that is equal to `e` cast to `t_ty`,
+    /// without any casts to wider types, or return `None` if we can't.
+    fn narrow_expr<'tcx>(
+        &self,
+        e: &P<Expr>,
+        t_ty: ty::Ty<'tcx>,
+        cx: &RefactorCtxt<'_, 'tcx>,
+    ) -> Option<P<Expr>> {
+        use BinOpKind::*;
+        let tcx = cx.ty_ctxt();
+        let node_ty = |id| tcx.normalize_erasing_regions(ParamEnv::empty(), cx.node_type(id));
+        let simple_t_ty = SimpleTy::from_ty(tcx, t_ty);
+        if !simple_t_ty.is_integer() {
+            return None;
+        }
+        let e_ty = SimpleTy::from_ty(tcx, node_ty(e.id));
+
+        match e.kind {
+            // `$x as $ty` where `$x` already has the narrower type
+            ExprKind::Cast(ref ie, _) => {
+                if node_ty(ie.id) != t_ty {
+                    return None;
+                }
+                match check_double_cast(simple_t_ty, e_ty, simple_t_ty) {
+                    DoubleCastAction::RemoveBoth => Some(ie.clone()),
+                    _ => None,
+                }
+            }
+
+            // Literals that fit into the narrower type keep their value
+            ExprKind::Lit(ref lit) => match lit.kind {
+                LitKind::Int(..) => {
+                    let new_lit = replace_suffix(lit, simple_t_ty, false)?;
+                    Some(mk().span(e.span).lit_expr(new_lit))
+                }
+                _ => None,
+            },
+
+            ExprKind::Binary(ref op, ref lhs, ref rhs) => {
+                let op = op.node;
+                match op {
+                    BitAnd | BitOr | BitXor => {}
+                    Add | Sub | Mul if !e_ty.is_signed() || self.signed => {}
+                    _ => return None,
+                }
+                let lhs_ty = SimpleTy::from_ty(tcx, node_ty(lhs.id));
+                let rhs_ty = SimpleTy::from_ty(tcx, node_ty(rhs.id));
+                match check_binary_cast(op, lhs_ty, rhs_ty, e_ty, simple_t_ty, None) {
+                    BinaryCastAction::NarrowOperands => {}
+                    BinaryCastAction::KeepAll => return None,
+                }
+
+                let new_lhs = self.narrow_expr(lhs, t_ty, cx)?;
+                let new_rhs = self.narrow_expr(rhs, t_ty, cx)?;
+                let e_mk = mk().span(e.span);
+                let new_expr = match op {
+                    Add => e_mk.method_call_expr(new_lhs, "wrapping_add", vec![new_rhs]),
+                    Sub => e_mk.method_call_expr(new_lhs, "wrapping_sub", vec![new_rhs]),
+                    Mul => e_mk.method_call_expr(new_lhs, "wrapping_mul", vec![new_rhs]),
+                    _ => e_mk.binary_expr(op, new_lhs, new_rhs),
+                };
+                Some(new_expr)
+            }
+
+            _ => None,
+        }
+    }
+}
+
+/// # `canonicalize_len_casts` Command
+///
+/// Removes casts that only exist to compare or combine a value with a `.len()` call in some
+/// wider integer type, so that the operation is done on `usize`s instead, e.g.,
+/// `(i as u64) < buf.len() as u64` => `i < buf.len()` where `i: usize`, and
+/// `(i as c_ulong).wrapping_rem(buf.len() as c_ulong) as usize` => `i % buf.len()`.
+///
+/// This is only done if the wider type holds every `usize` value on the compilation target.
+/// Operands of types other than `usize` get cast to `usize` instead of the wider type, if that
+/// doesn't change their value.
+pub struct CanonicalizeLenCasts;
+
+impl Transform for CanonicalizeLenCasts {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if let Some(new_expr) = canonicalize_len_cast(e, cx) {
+                debug!("canonicalized len cast: {:?} => {:?}", e, new_expr);
+                *e = new_expr;
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Rewrite a comparison or arithmetic operation between `$e.len()` and another value in some
+/// wider integer type into the same operation on `usize`s.
+fn canonicalize_len_cast(e: &P<Expr>, cx: &RefactorCtxt) -> Option<P<Expr>> {
+    use BinOpKind::*;
+    let tcx = cx.ty_ctxt();
+    let e_mk = mk().id(e.id).span(e.span);
+    match e.kind {
+        // `$a as T < $b.len() as T`
+        ExprKind::Binary(ref op, ref lhs, ref rhs) => match op.node {
+            Lt | Le | Gt | Ge | Eq | Ne => {
+                let (new_lhs, new_rhs) = len_operands_to_usize(lhs, rhs, cx)?;
+                Some(e_mk.binary_expr(op.node, new_lhs, new_rhs))
+            }
+            _ => None,
+        },
+
+        // `($a as T % $b.len() as T) as usize` or `($a as T).wrapping_rem($b.len() as T) as usize`
+        ExprKind::Cast(ref ie, ref ty) => {
+            let ty_ty = tcx.normalize_erasing_regions(ParamEnv::empty(), cx.node_type(ty.id));
+            if ty_ty != tcx.types.usize {
+                return None;
+            }
+            let (op, lhs, rhs) = match ie.kind {
+                ExprKind::Binary(ref op, ref lhs, ref rhs) => (op.node, lhs, rhs),
+                ExprKind::MethodCall(ref seg, ref args) if args.len() == 2 => {
+                    let op = match &*seg.ident.as_str() {
+                        "wrapping_add" => Add,
+                        "wrapping_sub" => Sub,
+                        "wrapping_mul" => Mul,
+                        "wrapping_div" => Div,
+                        "wrapping_rem" => Rem,
+                        _ => return None,
+                    };
+                    (op, &args[0], &args[1])
+                }
+                _ => return None,
+            };
+            match op {
+                Add | Sub | Mul | Div | Rem => {}
+                _ => return None,
+            }
+            let (new_lhs, new_rhs) = len_operands_to_usize(lhs, rhs, cx)?;
+            let new_expr = match op {
+                // Truncating to `usize` only keeps the low bits,
+                // which don't depend on the width of the operation
+                Add => e_mk.method_call_expr(new_lhs, "wrapping_add", vec![new_rhs]),
+                Sub => e_mk.method_call_expr(new_lhs, "wrapping_sub", vec![new_rhs]),
+                Mul => e_mk.method_call_expr(new_lhs, "wrapping_mul", vec![new_rhs]),
+                // Both operands are `usize` values, so the result always fits
+                _ => e_mk.binary_expr(op, new_lhs, new_rhs),
+            };
+            Some(new_expr)
+        }
+
+        _ => None,
+    }
+}
+
+/// Returns `true` if `e` is a cast of a `usize` `.len()` call, e.g., `buf.len() as u64`.
+fn is_len_cast(e: &Expr, cx: &RefactorCtxt) -> bool {
+    let ie = match e.kind {
+        ExprKind::Cast(ref ie, _) => ie,
+        _ => return false,
+    };
+    match ie.kind {
+        ExprKind::MethodCall(ref seg, ref args) if args.len() == 1 => {
+            seg.ident.as_str() == "len" && cx.node_type(ie.id) == cx.ty_ctxt().types.usize
+        }
+        _ => false,
+    }
+}
+
+/// Move the operands of a binary operation on some type `T` over to `usize`, if one of them is
+/// `$e.len() as T` and every `usize` value fits into `T`.
+fn len_operands_to_usize(
+    lhs: &P<Expr>,
+    rhs: &P<Expr>,
+    cx: &RefactorCtxt,
+) -> Option<(P<Expr>, P<Expr>)> {
+    if !is_len_cast(lhs, cx) && !is_len_cast(rhs, cx) {
+        return None;
+    }
+    let tcx = cx.ty_ctxt();
+    let op_ty = tcx.normalize_erasing_regions(ParamEnv::empty(), cx.node_type(lhs.id));
+    let op_ty = SimpleTy::from_ty(tcx, op_ty);
+    let usize_ty = SimpleTy::from_ty(tcx, tcx.types.usize);
+    // Casting `usize` to `T` and back needs to be lossless
+    let lossless = match cast_kind(usize_ty, op_ty) {
+        CastKind::Extend(_) => true,
+        CastKind::SameWidth => !op_ty.is_signed(),
+        _ => false,
+    };
+    if !op_ty.is_integer() || !lossless {
+        return None;
+    }
+    let new_lhs = operand_to_usize(lhs, op_ty, cx)?;
+    let new_rhs = operand_to_usize(rhs, op_ty, cx)?;
+    Some((new_lhs, new_rhs))
+}
+
+/// Turn the operand `e` of type `op_ty` into an equivalent `usize` operand, e.g.,
+/// `x as u64` => `x as usize`, if casting to `usize` gives the same value.
+fn operand_to_usize(e: &P<Expr>, op_ty: SimpleTy, cx: &RefactorCtxt) -> Option<P<Expr>> {
+    let tcx = cx.ty_ctxt();
+    let usize_ty = SimpleTy::from_ty(tcx, tcx.types.usize);
+    match e.kind {
+        ExprKind::Cast(ref ie, ref ty) => {
+            let (from_ty, _) = cast_tys(ie, ty, cx)?;
+            if from_ty == tcx.types.usize {
+                return Some(ie.clone());
+            }
+            // `$e as usize as T` needs to be the same as `$e as T`
+            match check_double_cast(SimpleTy::from_ty(tcx, from_ty), usize_ty, op_ty) {
+                DoubleCastAction::RemoveBoth | DoubleCastAction::RemoveInner => {
+                    Some(mk().cast_expr(ie.clone(), mk().ident_ty("usize")))
+                }
+                DoubleCastAction::KeepBoth => None,
+            }
+        }
+        ExprKind::Lit(ref lit) => match lit.kind {
+            LitKind::Int(..) => {
+                let new_lit = replace_suffix(lit, usize_ty, false)?;
+                Some(mk().span(e.span).lit_expr(new_lit))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// # `mark_dubious_casts` Command
+///
+/// Marks: sets `MARK` (`dubious` by default)
+///
+/// Marks all casts that can change the value being cast, so they can be audited by hand or
+/// passed on to other commands: integer casts that truncate, e.g., `x as u32` where `x: u64`,
+/// casts of signed integers to unsigned ones, and casts of pointers to integers that may be
+/// narrower than a pointer. Casts like `x as u32` where `x: u8` are left alone.
+///
+/// Once done, prints how many casts of each kind were marked. If `-o REPORT` is passed,
+/// the source locations of the marked casts are also written to the `REPORT` file as JSON.
+fn mark_dubious_casts(st: &CommandState, cx: &RefactorCtxt, label: Symbol) {
+    let tcx = cx.ty_ctxt();
+    visit_nodes(&*st.krate(), |e: &Expr| {
+        let (ie, ty) = match e.kind {
+            ExprKind::Cast(ref ie, ref ty) => (ie, ty),
+            _ => return,
+        };
+        let (from_ty, to_ty) = match cast_tys(ie, ty, cx) {
+            Some(tys) => tys,
+            None => return,
+        };
+        let from_ty = SimpleTy::from_ty(tcx, from_ty);
+        let to_ty = SimpleTy::from_ty(tcx, to_ty);
+        if let Some(category) = dubious_cast_category(ie, from_ty, to_ty) {
+            st.add_mark(e.id, label);
+            st.report(cx, category, e.span);
+        }
+    });
+}
+
+/// Returns the reason the cast of `e` from `from_ty` to `to_ty` can change its value, if any.
+fn dubious_cast_category(e: &Expr, from_ty: SimpleTy, to_ty: SimpleTy) -> Option<&'static str> {
+    if from_ty.is_integer() && to_ty.is_integer() {
+        if let CastKind::Truncate = cast_kind(from_ty, to_ty) {
+            return Some("truncation");
+        }
+        // Literals can't be negative, since negation is a separate operation
+        let is_lit = match e.kind {
+            ExprKind::Lit(_) => true,
+            _ => false,
+        };
+        if from_ty.is_signed() && !to_ty.is_signed() && !is_lit {
+            return Some("sign flip");
+        }
+    } else if from_ty.is_pointer() && to_ty.is_integer() {
+        match cast_kind(from_ty, to_ty) {
+            CastKind::Truncate | CastKind::FromPointer(_) => {
+                return Some("pointer to narrow integer");
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// # `convert_cast_as_ptr` Command
+///
+/// Converts all expressions like `$e as *const $t` (with mutable or const pointers)
+/// where `$e` is a slice, array, `Vec<$t>`, `Box<[$t]>` or `String` into
+/// `$e.as_ptr()` calls. Casts to `*mut $t` are only converted to `$e.as_mut_ptr()`
+/// if `$e` is a mutable slice or array reference, or an owned buffer in a mutable
+/// place; otherwise they are left alone.
+pub struct ConvertCastAsPtr;
+
+impl Transform for ConvertCastAsPtr {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // `as_mut_ptr` takes `&mut self`, so the mutable forms need a receiver
+        // that can be mutably borrowed: either a `&mut` slice or array, or a
+        // mutable place holding an owned buffer. Casts through a shared
+        // reference like `buf as *mut u8` where `buf: &[u8]` are left alone.
+        let rules = [
+            ("typed!($expr:Expr, &[$ty:Ty]) as *const $ty",
+             "$expr.as_ptr()", false),
+            ("typed!($expr:Expr, &mut [$ty:Ty]) as *mut $ty",
+             "$expr.as_mut_ptr()", false),
+            ("typed!($expr:Expr, &[$ty:Ty; $len]) as *const $ty",
+             "$expr.as_ptr()", false),
+            ("typed!($expr:Expr, &mut [$ty:Ty; $len]) as *mut $ty",
+             "$expr.as_mut_ptr()", false),
+            ("typed!($expr:Expr, ::alloc::vec::Vec<$ty:Ty>) as *const $ty",
+             "$expr.as_ptr()", false),
+            ("typed!($expr:Expr, ::alloc::vec::Vec<$ty:Ty>) as *mut $ty",
+             "$expr.as_mut_ptr()", true),
+            ("typed!($expr:Expr, ::alloc::boxed::Box<[$ty:Ty]>) as *const $ty",
+             "$expr.as_ptr()", false),
+            ("typed!($expr:Expr, ::alloc::boxed::Box<[$ty:Ty]>) as *mut $ty",
+             "$expr.as_mut_ptr()", true),
+            ("typed!($expr:Expr, ::alloc::string::String) as *const $ty:Ty",
+             "$expr.as_ptr() as *const $ty", false),
+            ("typed!($expr:Expr, ::alloc::string::String) as *mut $ty:Ty",
+             "$expr.as_mut_ptr() as *mut $ty", true),
+        ];
+        for &(pat, repl, needs_mut) in &rules {
+            let mut mcx = MatchCtxt::new(st, cx);
+            let pat = mcx.parse_expr(pat);
+            let repl = mcx.parse_expr(repl);
+            mut_visit_match_with(mcx, pat, krate, |e, mcx| {
+                if needs_mut {
+                    let expr = mcx.bindings.get::<_, P<Expr>>("$expr").unwrap();
+                    if !is_mutable_place(expr, cx) {
+                        return;
+                    }
+                }
+                *e = repl.clone().subst(st, cx, &mcx.bindings);
+            });
+        }
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Check whether `e` is a place expression that can be mutably borrowed, i.e.,
+/// whether autoref'ing it for a `&mut self` method call would typecheck.
+fn is_mutable_place(e: &Expr, cx: &RefactorCtxt) -> bool {
+    let is_mut_ref = |e: &Expr| match cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+        Some(TyKind::Ref(_, _, hir::Mutability::Mutable)) => Some(true),
+        Some(TyKind::Ref(_, _, hir::Mutability::Immutable)) => Some(false),
+        _ => None,
+    };
+    match e.kind {
+        ExprKind::Paren(ref inner) => is_mutable_place(inner, cx),
+
+        // Field accesses and indexing auto-deref through references
+        ExprKind::Field(ref base, _) | ExprKind::Index(ref base, _) => {
+            is_mut_ref(base).unwrap_or_else(|| is_mutable_place(base, cx))
+        }
+
+        ExprKind::Unary(UnOp::Deref, ref inner) => {
+            match cx.opt_node_type(inner.id).map(|ty| &ty.kind) {
+                Some(TyKind::Ref(_, _, hir::Mutability::Mutable)) |
+                Some(TyKind::RawPtr(ty::TypeAndMut { mutbl: hir::Mutability::Mutable, .. })) => true,
+                Some(TyKind::Adt(def, _)) if def.is_box() => is_mutable_place(inner, cx),
+                _ => false,
+            }
+        }
+
+        ExprKind::Path(..) => {
+            let hir_map = cx.hir_map();
+            match cx.try_resolve_expr_hir(e) {
+                Some(Res::Local(hir_id)) => match hir_map.find(hir_id) {
+                    Some(hir::Node::Binding(pat)) => match pat.kind {
+                        hir::PatKind::Binding(hir::BindingAnnotation::Mutable, ..) => true,
+                        _ => false,
+                    },
+                    _ => false,
+                },
+                Some(Res::Def(DefKind::Static, def_id)) => {
+                    match hir_map.as_local_hir_id(def_id).and_then(|id| hir_map.find(id)) {
+                        Some(hir::Node::Item(item)) => match item.kind {
+                            hir::ItemKind::Static(_, hir::Mutability::Mutable, _) => true,
+                            _ => false,
+                        },
+                        Some(hir::Node::ForeignItem(item)) => match item.kind {
+                            hir::ForeignItemKind::Static(_, hir::Mutability::Mutable) => true,
+                            _ => false,
+                        },
+                        _ => false,
+                    }
+                }
+                _ => false,
+            }
+        }
+
+        _ => false,
+    }
+}
+
+/// # `remove_arg_casts` Command
+///
+/// Removes casts of function and method call arguments, e.g., `f(x as libc::c_int)`,
+/// where the cast produces the callee's parameter type and the operand already has
+/// that type once type aliases are expanded, or coerces to it, e.g., a `&mut T` passed
+/// as a `*mut T`.  Casts are kept for variadic arguments, method receivers, and
+/// parameters whose declared type mentions a type parameter of the callee, since
+/// removing those could change how the call gets inferred.
+pub struct RemoveArgCasts;
+
+impl Transform for RemoveArgCasts {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let info = match cx.opt_callee_info(e) {
+                Some(info) => info,
+                None => return,
+            };
+            let (args, first_arg) = match e.kind {
+                ExprKind::Call(_, ref mut args) => (args, 0),
+                // The receiver can get auto-referenced, so we leave it alone
+                ExprKind::MethodCall(_, ref mut args) => (args, 1),
+                _ => return,
+            };
+
+            let param_tys = info.fn_sig.inputs();
+            let decl_param_tys = info.poly_sig.skip_binder().inputs();
+            // Variadic arguments have no parameter type to check against
+            let num_params = cmp::min(args.len(), param_tys.len());
+            for i in first_arg..num_params {
+                if decl_param_tys[i].needs_subst() {
+                    continue;
+                }
+                let new_arg = match args[i].kind {
+                    ExprKind::Cast(ref inner, ref ty) => match cast_tys(inner, ty, cx) {
+                        Some((from_ty, to_ty))
+                            if to_ty == param_tys[i] && coerces_to(from_ty, to_ty) =>
+                        {
+                            inner.clone()
+                        }
+                        _ => continue,
+                    },
+                    _ => continue,
+                };
+                debug!("removing argument cast: {:?} => {:?}", args[i], new_arg);
+                args[i] = new_arg;
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Check whether a value of type `from_ty` can be used where a `to_ty` is
+/// expected without a cast, i.e., whether the types are the same or there
+/// is a coercion between them.
+fn coerces_to<'tcx>(from_ty: ty::Ty<'tcx>, to_ty: ty::Ty<'tcx>) -> bool {
+    use rustc::hir::Mutability::*;
+    if from_ty == to_ty {
+        return true;
+    }
+    let ptr = |ty: ty::Ty<'tcx>| match ty.kind {
+        TyKind::Ref(_, pointee, mutbl) => Some((pointee, mutbl, false)),
+        TyKind::RawPtr(ty::TypeAndMut { ty: pointee, mutbl }) => Some((pointee, mutbl, true)),
+        _ => None,
+    };
+    match (ptr(from_ty), ptr(to_ty)) {
+        // References coerce to references and raw pointers, and raw pointers
+        // only to other raw pointers
+        (Some((from, from_mutbl, from_raw)), Some((to, to_mutbl, to_raw))) if !from_raw || to_raw => {
+            from == to && (from_mutbl == Mutable || to_mutbl == Immutable)
+        }
+        _ => false,
+    }
+}
+
+/// # `convert_null_ptr_casts` Command
+///
+/// Rewrites casts of the integer literal `0` to raw pointers, e.g., `0 as *mut T`,
+/// into `::std::ptr::null_mut::<T>()`, or `::std::ptr::null::<T>()` for `*const T`.
+/// Chains of casts starting from `0`, like `0 as *mut T as *const U`, become a single
+/// call producing the final pointer type.  Casts of non-zero integers are left alone.
+pub struct ConvertNullPtrCasts;
+
+impl Transform for ConvertNullPtrCasts {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, _cx: &RefactorCtxt) {
+        NullPtrCastFolder.visit_crate(krate);
+    }
+}
+
+struct NullPtrCastFolder;
+
+impl MutVisitor for NullPtrCastFolder {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        // Check the outermost cast of a chain first, so the whole chain
+        // gets replaced at once
+        if let Some(new_e) = null_ptr_for_cast(e) {
+            *e = new_e;
+            return;
+        }
+        mut_visit::noop_visit_expr(e, self);
+    }
+}
+
+/// If `e` is a chain of casts starting from a `0` literal and ending in a
+/// raw pointer type, build the equivalent `ptr::null` or `ptr::null_mut` call.
+fn null_ptr_for_cast(e: &Expr) -> Option<P<Expr>> {
+    let mut_ty = match e.kind {
+        ExprKind::Cast(_, ref ty) => match ty.kind {
+            ast::TyKind::Ptr(ref mut_ty) => mut_ty,
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    let mut inner = e;
+    loop {
+        match inner.kind {
+            ExprKind::Cast(ref ie, _) | ExprKind::Paren(ref ie) => inner = &**ie,
+            ExprKind::Lit(ref lit) => match lit.kind {
+                LitKind::Int(0, _) => break,
+                _ => return None,
+            },
+            _ => return None,
+        }
+    }
+
+    let func = match mut_ty.mutbl {
+        Mutability::Mutable => "null_mut",
+        Mutability::Immutable => "null",
+    };
+    let args = mk().angle_bracketed_args(vec![mut_ty.ty.clone()]);
+    let path = vec![
+        mk().path_segment(""),
+        mk().path_segment("std"),
+        mk().path_segment("ptr"),
+        mk().path_segment_with_args(func, args),
+    ];
+    Some(mk().id(e.id).span(e.span).call_expr(mk().path_expr(path), Vec::<P<Expr>>::new()))
+}
+
+/// # `convert_casts_to_try_into` Command
+///
+/// Rewrites potentially lossy integer casts `$e as $t` into `$t::try_from($e).unwrap()`,
+/// so that values that don't fit the target type panic instead of being silently
+/// truncated.  A cast is considered lossy if it truncates, or if it changes the sign
+/// of the value without widening it enough to hold all values of the source type.
+/// Pointer and float casts, casts of literals, and casts in `const` contexts (constant
+/// and static initializers, array lengths, enum discriminants and `const fn`s) are
+/// left unchanged.
+///
+/// If `--try-into` is passed, the casts are rewritten to `$e.try_into().unwrap()` instead,
+/// which relies on type inference to pick the target type.  If `--from` is passed, lossless
+/// casts are also rewritten to `$t::from($e)`, as in `convert_casts_to_from`.
+/// `--panic-msg MSG` uses `.expect(MSG)` instead of `.unwrap()`.
+///
+/// `use std::convert::TryFrom;` (or `TryInto`) is added to every module containing a
+/// rewritten cast.
+pub struct ConvertCastsToTryInto {
+    pub use_try_into: bool,
+    pub extend_to_from: bool,
+    pub panic_msg: Option<String>,
+}
+
+impl ConvertCastsToTryInto {
+    fn rewrite_cast(&self, e: &Expr, cx: &RefactorCtxt) -> Option<P<Expr>> {
+        let (inner, cast_ty) = match e.kind {
+            ExprKind::Cast(ref inner, ref cast_ty) => (inner, cast_ty),
+            _ => return None,
+        };
+        if is_lit_expr(inner) {
+            return None;
+        }
+
+        let tcx = cx.ty_ctxt();
+        let (from_ty, to_ty) = cast_tys(inner, cast_ty, cx)?;
+        let (from_ty, to_ty) = (SimpleTy::from_ty(tcx, from_ty), SimpleTy::from_ty(tcx, to_ty));
+        if !from_ty.is_integer() || !to_ty.is_integer() {
+            return None;
+        }
+
+        let lossy = match cast_kind(from_ty, to_ty) {
+            CastKind::Truncate => true,
+            CastKind::SameWidth => from_ty.is_signed() != to_ty.is_signed(),
+            CastKind::Extend(signed) => signed && !to_ty.is_signed(),
+            _ => false,
+        };
+        if !lossy {
+            if self.extend_to_from {
+                return ConvertCastsToFrom.rewrite_cast(e, cx);
+            }
+            return None;
+        }
+
+        let conv = if self.use_try_into {
+            mk().method_call_expr(inner.clone(), "try_into", Vec::<P<Expr>>::new())
+        } else {
+            mk().call_expr(ty_method_path(cast_ty, "try_from")?, vec![inner.clone()])
+        };
+        let ast_mk = mk().id(e.id).span(e.span);
+        Some(match self.panic_msg {
+            Some(ref msg) => ast_mk.method_call_expr(conv, "expect", vec![mk().lit_expr(msg.as_str())]),
+            None => ast_mk.method_call_expr(conv, "unwrap", Vec::<P<Expr>>::new()),
+        })
+    }
+}
+
+impl Transform for ConvertCastsToTryInto {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        let import = if self.use_try_into { "TryInto" } else { "TryFrom" };
+        let mut v = RuntimeCastFolder {
+            rewrite: |e: &Expr| self.rewrite_cast(e, cx),
+            import: Some(import),
+            rewritten: false,
+        };
+        v.visit_crate(krate);
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// # `convert_casts_to_from` Command
+///
+/// Rewrites provably lossless casts `$e as $t` into `$t::from($e)`, so that the
+/// compiler checks that the conversion stays lossless if the types involved change.
+/// This covers integer casts that widen without losing the sign, `u8 as char`, and
+/// `bool` to integer casts.  `usize` and `isize` casts are only rewritten if the
+/// standard library provides the `From` impl, i.e., the conversion is lossless on all
+/// targets.  Casts of literals are left to `remove_redundant_casts`, and casts in
+/// `const` contexts, where `From::from` can't be called, are left unchanged.
+pub struct ConvertCastsToFrom;
+
+impl ConvertCastsToFrom {
+    fn rewrite_cast(&self, e: &Expr, cx: &RefactorCtxt) -> Option<P<Expr>> {
+        let (inner, cast_ty) = match e.kind {
+            ExprKind::Cast(ref inner, ref cast_ty) => (inner, cast_ty),
+            _ => return None,
+        };
+        if is_lit_expr(inner) {
+            return None;
+        }
+
+        let tcx = cx.ty_ctxt();
+        let (from_ty, to_ty) = cast_tys(inner, cast_ty, cx)?;
+        let lossless = match (&from_ty.kind, SimpleTy::from_ty(tcx, to_ty)) {
+            (TyKind::Bool, SimpleTy::Int(..)) | (TyKind::Bool, SimpleTy::Size(..)) => true,
+            (TyKind::Uint(UintTy::U8), SimpleTy::Char) => true,
+            (_, to) => has_std_from_impl(SimpleTy::from_ty(tcx, from_ty), to),
+        };
+        if !lossless {
+            return None;
+        }
+
+        let func = ty_method_path(cast_ty, "from")?;
+        Some(mk().id(e.id).span(e.span).call_expr(func, vec![inner.clone()]))
+    }
+}
+
+impl Transform for ConvertCastsToFrom {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        let mut v = RuntimeCastFolder {
+            rewrite: |e: &Expr| self.rewrite_cast(e, cx),
+            import: None,
+            rewritten: false,
+        };
+        v.visit_crate(krate);
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Get the normalized types of the operand and target type of a cast.
+pub(crate) fn cast_tys<'a, 'tcx>(
+    inner: &Expr,
+    cast_ty: &ast::Ty,
+    cx: &RefactorCtxt<'a, 'tcx>,
+) -> Option<(ty::Ty<'tcx>, ty::Ty<'tcx>)> {
+    let tcx = cx.ty_ctxt();
+    let from_ty = cx.opt_node_type(inner.id)?;
+    let to_ty = cx.opt_node_type(cast_ty.id)?;
+    Some((
+        tcx.normalize_erasing_regions(ParamEnv::empty(), from_ty),
+        tcx.normalize_erasing_regions(ParamEnv::empty(), to_ty),
+    ))
+}
+
+/// Check whether the standard library implements `From<from_ty>` for `to_ty`.  This is
+/// stricter than `is_lossless_int_cast`, since `usize` and `isize` only get `From` impls
+/// that are lossless on every target.
+fn has_std_from_impl(from_ty: SimpleTy, to_ty: SimpleTy) -> bool {
+    match (from_ty, to_ty) {
+        (SimpleTy::Int(..), SimpleTy::Int(..)) => {
+            from_ty != to_ty && is_lossless_int_cast(from_ty, to_ty)
+        }
+        (SimpleTy::Int(fw, false), SimpleTy::Size(_, false)) => fw <= 16,
+        (SimpleTy::Int(fw, fs), SimpleTy::Size(_, true)) => fw == 8 || (fs && fw == 16),
+        _ => false,
+    }
+}
+
+/// Build the path expression `$ty::$method`, if `ty` is a plain path.
+fn ty_method_path(ty: &ast::Ty, method: &str) -> Option<P<Expr>> {
+    match ty.kind {
+        ast::TyKind::Path(None, ref path) => {
+            let mut path = path.clone();
+            path.segments.push(mk().path_segment(method));
+            Some(mk().path_expr(path))
+        }
+        _ => None,
+    }
+}
+
+/// Check whether `m` already imports `std::convert::$name` (or the `core` equivalent),
+/// either directly, as part of a nested import, or through a glob.
+fn imports_convert_trait(m: &Mod, name: &str) -> bool {
+    fn path_strs(path: &Path) -> Vec<String> {
+        path.segments.iter().map(|seg| seg.ident.as_str().to_string()).collect()
+    }
+    fn is_convert_mod(path: &[String]) -> bool {
+        let n = path.len();
+        n >= 2 && (path[n - 2] == "std" || path[n - 2] == "core") && path[n - 1] == "convert"
+    }
+
+    m.items.iter().any(|i| {
+        let tree = match i.kind {
+            ItemKind::Use(ref tree) => tree,
+            _ => return false,
+        };
+        let path = path_strs(&tree.prefix);
+        match tree.kind {
+            UseTreeKind::Simple(None, ..) => {
+                path.last().map_or(false, |last| last == name) &&
+                    is_convert_mod(&path[..path.len() - 1])
+            }
+            UseTreeKind::Glob => is_convert_mod(&path),
+            UseTreeKind::Nested(ref trees) => {
+                is_convert_mod(&path) && trees.iter().any(|(t, _)| match t.kind {
+                    UseTreeKind::Simple(None, ..) => path_strs(&t.prefix) == [name],
+                    _ => false,
+                })
+            }
+            _ => false,
+        }
+    })
+}
+
+/// Visitor that rewrites casts using `rewrite`, skipping `const` contexts (constant and
+/// static initializers, array lengths, enum discriminants and `const fn`s) where only
+/// `const fn`s may be called.
+struct RuntimeCastFolder<'a, F> {
+    rewrite: F,
+    /// A `std::convert` trait to import into every module containing a rewritten cast.
+    import: Option<&'a str>,
+    rewritten: bool,
+}
+
+impl<'a, F> MutVisitor for RuntimeCastFolder<'a, F>
+    where F: FnMut(&Expr) -> Option<P<Expr>>
+{
+    fn visit_mod(&mut self, m: &mut Mod) {
+        let outer_rewritten = mem::replace(&mut self.rewritten, false);
+        mut_visit::noop_visit_mod(m, self);
+        if let Some(name) = self.import {
+            if self.rewritten && !imports_convert_trait(m, name) {
+                let use_item = mk().use_simple_item(vec!["std", "convert", name], None as Option<Ident>);
+                m.items.insert(0, use_item);
+            }
+        }
+        self.rewritten = outer_rewritten;
+    }
+
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        mut_visit::noop_visit_expr(e, self);
+        if let ExprKind::Cast(..) = e.kind {
+            if let Some(new_e) = (self.rewrite)(&**e) {
+                *e = new_e;
+                self.rewritten = true;
+            }
+        }
+    }
+
+    fn visit_anon_const(&mut self, _c: &mut AnonConst) {}
+
+    fn visit_pat(&mut self, _p: &mut P<Pat>) {}
+
+    fn flat_map_item(&mut self, i: P<Item>) -> SmallVec<[P<Item>; 1]> {
+        match i.kind {
+            ItemKind::Const(..) | ItemKind::Static(..) => return smallvec![i],
+            ItemKind::Fn(ref sig, ..) if sig.header.constness.node == Constness::Const => {
+                return smallvec![i];
+            }
+            _ => {}
+        }
+        mut_visit::noop_flat_map_item(i, self)
+    }
+
+    fn flat_map_impl_item(&mut self, ii: ImplItem) -> SmallVec<[ImplItem; 1]> {
+        match ii.kind {
+            ImplItemKind::Const(..) => return smallvec![ii],
+            ImplItemKind::Method(ref sig, _) if sig.header.constness.node == Constness::Const => {
+                return smallvec![ii];
+            }
+            _ => {}
+        }
+        mut_visit::noop_flat_map_impl_item(ii, self)
+    }
+
+    fn flat_map_trait_item(&mut self, ti: TraitItem) -> SmallVec<[TraitItem; 1]> {
+        match ti.kind {
+            TraitItemKind::Const(..) => smallvec![ti],
+            _ => mut_visit::noop_flat_map_trait_item(ti, self),
+        }
+    }
+}
+
+pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("remove_redundant_casts", |_| mk(RemoveRedundantCasts));
-    reg.register("convert_cast_as_ptr", |_| mk(ConvertCastAsPtr));
+    let spec = CommandSpec::new("Remove casts that don't change the type of their operand.")
+        .flag("--fold-char-lits", "also fold casts of char and byte literals to integers")
+        .flag("--keep-aliases", "keep casts of folded literals to type aliases")
+        .flag("--wrap-negative-lits", "fold negated literals cast to unsigned types")
+        .flag("--only-marked", "leave the crate unchanged if nothing is marked")
+        .option("-o", "REPORT", ArgType::Str, None, "also write the report to REPORT as JSON");
+    reg.register("remove_redundant_casts", spec, |args| {
+        Ok(mk(RemoveRedundantCasts {
+            fold_char_lits: args.flag("--fold-char-lits"),
+            keep_aliases: args.flag("--keep-aliases"),
+            wrap_negative_lits: args.flag("--wrap-negative-lits"),
+            only_marked: args.flag("--only-marked"),
+            report_path: args.opt_str("-o").map(|s| s.to_owned()),
+        }))
+    });
+    let spec = CommandSpec::new("Narrow integer arithmetic that's cast to a narrower type.")
+        .flag("--signed", "also narrow arithmetic on signed integers");
+    reg.register("minimize_casts", spec, |args| {
+        Ok(mk(MinimizeCasts {
+            signed: args.flag("--signed"),
+        }))
+    });
+
+    let spec = CommandSpec::new("Remove casts that only exist to operate on `.len()` calls.");
+    reg.register("canonicalize_len_casts", spec, |_| Ok(mk(CanonicalizeLenCasts)));
+
+    let spec = CommandSpec::new("Mark the casts that can change the value being cast.")
+        .optional_arg("MARK", ArgType::Str, Some("dubious"), "the mark to apply to the casts")
+        .option("-o", "REPORT", ArgType::Str, None, "also write the report to REPORT as JSON");
+    reg.register("mark_dubious_casts", spec, |args| {
+        let report_path = args.opt_str("-o").map(|s| s.to_owned());
+        let label = args.str("MARK").into_symbol();
+        Ok(Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+            st.start_report("mark_dubious_casts", report_path.clone());
+            mark_dubious_casts(st, cx, label);
+        })))
+    });
+
+    let spec = CommandSpec::new("Convert casts of buffers to raw pointers into `as_ptr` calls.");
+    reg.register("convert_cast_as_ptr", spec, |_| Ok(mk(ConvertCastAsPtr)));
+
+    let spec = CommandSpec::new("Remove casts of call arguments to the parameter's type.");
+    reg.register("remove_arg_casts", spec, |_| Ok(mk(RemoveArgCasts)));
+
+    let spec = CommandSpec::new("Convert casts of `0` to raw pointers into `ptr::null` calls.");
+    reg.register("convert_null_ptr_casts", spec, |_| Ok(mk(ConvertNullPtrCasts)));
+
+    let spec = CommandSpec::new("Convert lossy integer casts into checked `try_from` calls.")
+        .flag("--try-into", "use `$e.try_into()` instead of `$t::try_from($e)`")
+        .flag("--from", "also convert lossless casts into `From` calls")
+        .option(
+            "--panic-msg",
+            "MSG",
+            ArgType::Str,
+            None,
+            "use `.expect(MSG)` instead of `.unwrap()`",
+        );
+    reg.register("convert_casts_to_try_into", spec, |args| {
+        Ok(mk(ConvertCastsToTryInto {
+            use_try_into: args.flag("--try-into"),
+            extend_to_from: args.flag("--from"),
+            panic_msg: args.opt_str("--panic-msg").map(|s| s.to_owned()),
+        }))
+    });
+
+    let spec = CommandSpec::new("Convert lossless casts into `From` calls.");
+    reg.register("convert_casts_to_from", spec, |_| Ok(mk(ConvertCastsToFrom)));
 }
@@ -1,6 +1,9 @@
-use super::{check_double_cast, DoubleCastAction, SimpleTy};
+use super::{check_binary_cast, check_double_cast, BinaryCastAction, DoubleCastAction, SimpleTy};
+use super::{exact_float_lit_int, is_exact_float};
+use rustc::hir::Mutability;
 use quickcheck::{quickcheck, Arbitrary, Gen};
 use rand::Rng;
+use syntax::ast::{BinOpKind, FloatTy};
 use z3::ast::{Ast, BV};
 use z3::{Config, Context, SatResult, Solver};
 
@@ -15,96 +18,437 @@ impl Arbitrary for PointerWidth {
     }
 }
 
+impl PointerWidth {
+    // `SimpleTy::Size` carries the pointer width of the target,
+    // so we need to make it agree with the width we're verifying
+    fn retarget(self, ty: SimpleTy) -> SimpleTy {
+        match ty {
+            SimpleTy::Size(_, s) => SimpleTy::Size(self.0, s),
+            _ => ty,
+        }
+    }
+}
+
 impl Arbitrary for SimpleTy {
     fn arbitrary<G: Gen>(g: &mut G) -> Self {
-        let x = g.gen_range(0, 13);
+        let x = g.gen_range(0, 14);
         match x {
             0 | 1 | 2 | 3 => SimpleTy::Int([8, 16, 32, 64][x], false),
             4 | 5 | 6 | 7 => SimpleTy::Int([8, 16, 32, 64][x - 4], true),
-            8 => SimpleTy::Size(false),
-            9 => SimpleTy::Size(true),
+            // The width gets fixed up by `PointerWidth::retarget`
+            8 => SimpleTy::Size(64, false),
+            9 => SimpleTy::Size(64, true),
             10 => SimpleTy::Float32,
             11 => SimpleTy::Float64,
-            12 => SimpleTy::Pointer,
+            12 => SimpleTy::Pointer(Mutability::Immutable),
+            13 => SimpleTy::Pointer(Mutability::Mutable),
             // TODO: generate some Other's
             _ => unreachable!(),
         }
     }
 }
 
+// Enums can only be the source of a cast, so we generate them separately
+#[derive(Debug, Copy, Clone)]
+struct EnumTy(SimpleTy);
+
+impl Arbitrary for EnumTy {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        let w = [8, 16, 32, 64][g.gen_range(0, 4)];
+        EnumTy(SimpleTy::Enum(w, g.gen()))
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct BinOp(BinOpKind);
+
+impl Arbitrary for BinOp {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        use BinOpKind::*;
+        let ops = [Add, Sub, Mul, BitAnd, BitOr, BitXor, Shl, Shr];
+        BinOp(ops[g.gen_range(0, ops.len())])
+    }
+}
+
+// Floats are modeled as the (integral) values they hold, stored in bitvectors
+// wide enough to hold every integer we might convert into them; this means
+// we don't model fractional values, infinities or NaNs
+const FLOAT_BV_WIDTH: u32 = 80;
+
 fn ty_bit_width(ty: SimpleTy, pw: PointerWidth) -> u32 {
     let bw = match ty {
-        SimpleTy::Int(w, _) => w,
-        SimpleTy::Size(_) | SimpleTy::Pointer => pw.0,
-        SimpleTy::Float32 => 32,
-        SimpleTy::Float64 => 64,
-        SimpleTy::Other => unreachable!(), // FIXME
+        SimpleTy::Int(w, _) | SimpleTy::Size(w, _) | SimpleTy::Enum(w, _) => w,
+        SimpleTy::Pointer(_) => pw.0,
+        SimpleTy::Bool => 1,
+        SimpleTy::Float32 | SimpleTy::Float64 => FLOAT_BV_WIDTH as usize,
+        SimpleTy::Char | SimpleTy::Ref | SimpleTy::Array | SimpleTy::Other => {
+            unreachable!() // FIXME
+        }
     };
     bw as u32
 }
 
-fn cast_bv<'bv>(bv: BV<'bv>, from_ty: SimpleTy, to_ty: SimpleTy, pw: PointerWidth) -> BV<'bv> {
+fn mantissa_bits(ty: SimpleTy) -> u32 {
+    match ty {
+        SimpleTy::Float32 => 24,
+        SimpleTy::Float64 => 53,
+        _ => unreachable!(),
+    }
+}
+
+// Bitwise simulation of an IEEE-754 conversion of an integral value
+// to a float with `m` significant bits, rounding to nearest, ties to even
+fn round_bv<'bv>(ctx: &'bv Context, v: &BV<'bv>, m: u32) -> BV<'bv> {
+    let w = FLOAT_BV_WIDTH;
+    let zero = BV::from_u64(ctx, 0, w);
+    let one = BV::from_u64(ctx, 1, w);
+    let bit_one = BV::from_u64(ctx, 1, 1);
+
+    let neg = v.bvslt(&zero);
+    let mag = neg.ite(&v.bvneg(), v);
+
+    // Number of significant bits in the magnitude
+    let mut len = zero.clone();
+    for i in 0..w {
+        let is_set = mag.extract(i, i)._eq(&bit_one);
+        len = is_set.ite(&BV::from_u64(ctx, (i + 1) as u64, w), &len);
+    }
+
+    // Drop all the bits that don't fit into the mantissa, then round
+    let m = BV::from_u64(ctx, m as u64, w);
+    let shift = len.bvugt(&m).ite(&len.bvsub(&m), &zero);
+    let q = mag.bvlshr(&shift);
+    let rem = mag.bvsub(&q.bvshl(&shift));
+    let half = one.bvshl(&shift).bvlshr(&one);
+    let q_odd = q.extract(0, 0)._eq(&bit_one);
+    let past_half = rem.bvugt(&half).or(&[&rem._eq(&half).and(&[&q_odd])]);
+    let round_up = shift._eq(&zero).not().and(&[&past_half]);
+    let rounded = round_up.ite(&q.bvadd(&one), &q).bvshl(&shift);
+    neg.ite(&rounded.bvneg(), &rounded)
+}
+
+// Model float-to-int casts as saturating at the bounds of the target type.
+// Out-of-range casts are actually UB without `-Z saturating-float-casts`, so
+// on top of comparing values, `verify_cast_chain` checks that the minimized
+// chain never has more float-to-int casts than the original one
+fn saturate_bv<'bv>(ctx: &'bv Context, v: &BV<'bv>, to_width: u32, signed: bool) -> BV<'bv> {
+    let w = FLOAT_BV_WIDTH;
+    let one = BV::from_u64(ctx, 1, w);
+    let (min, max) = if signed {
+        let bound = one.bvshl(&BV::from_u64(ctx, (to_width - 1) as u64, w));
+        (bound.bvneg(), bound.bvsub(&one))
+    } else {
+        let bound = one.bvshl(&BV::from_u64(ctx, to_width as u64, w));
+        (BV::from_u64(ctx, 0, w), bound.bvsub(&one))
+    };
+    let clamped = v.bvslt(&min).ite(&min, &v.bvsgt(&max).ite(&max, v));
+    clamped.extract(to_width - 1, 0)
+}
+
+fn new_value<'bv>(ctx: &'bv Context, name: &str, ty: SimpleTy, pw: PointerWidth) -> BV<'bv> {
+    if ty.is_float() {
+        // Start from any 64-bit integer, rounded to the precision of the float
+        let x = BV::new_const(ctx, name, 64).sign_ext(FLOAT_BV_WIDTH - 64);
+        round_bv(ctx, &x, mantissa_bits(ty))
+    } else {
+        BV::new_const(ctx, name, ty_bit_width(ty, pw))
+    }
+}
+
+fn cast_bv<'bv>(
+    ctx: &'bv Context,
+    bv: BV<'bv>,
+    from_ty: SimpleTy,
+    to_ty: SimpleTy,
+    pw: PointerWidth,
+) -> BV<'bv> {
     let from_width = ty_bit_width(from_ty, pw);
     let to_width = ty_bit_width(to_ty, pw);
-    if to_width == from_width {
-        bv
-    } else if to_width < from_width {
-        bv.extract(to_width - 1, 0)
-    } else if from_ty.is_signed() {
-        bv.sign_ext(to_width - from_width)
-    } else {
-        bv.zero_ext(to_width - from_width)
+    match (from_ty.is_float(), to_ty.is_float()) {
+        (true, true) => round_bv(ctx, &bv, mantissa_bits(to_ty)),
+        (true, false) => saturate_bv(ctx, &bv, to_width, to_ty.is_signed()),
+        (false, true) => {
+            let ext = cast_bv(ctx, bv, from_ty, SimpleTy::Int(to_width as usize, true), pw);
+            round_bv(ctx, &ext, mantissa_bits(to_ty))
+        }
+        (false, false) if to_width == from_width => bv,
+        (false, false) if to_width < from_width => bv.extract(to_width - 1, 0),
+        (false, false) if from_ty.is_signed() => bv.sign_ext(to_width - from_width),
+        (false, false) => bv.zero_ext(to_width - from_width),
     }
 }
 
-fn cast_tys<'bv>(bv: BV<'bv>, tys: &[SimpleTy], pw: PointerWidth) -> BV<'bv> {
-    tys.windows(2).fold(bv, |y, w| cast_bv(y, w[0], w[1], pw))
+fn cast_tys<'bv>(ctx: &'bv Context, bv: BV<'bv>, tys: &[SimpleTy], pw: PointerWidth) -> BV<'bv> {
+    tys.windows(2).fold(bv, |y, w| cast_bv(ctx, y, w[0], w[1], pw))
+}
+
+fn binop_bv<'bv>(op: BinOpKind, lhs: BV<'bv>, rhs: BV<'bv>, ty: SimpleTy) -> BV<'bv> {
+    use BinOpKind::*;
+    match op {
+        Add => lhs.bvadd(&rhs),
+        Sub => lhs.bvsub(&rhs),
+        Mul => lhs.bvmul(&rhs),
+        BitAnd => lhs.bvand(&rhs),
+        BitOr => lhs.bvor(&rhs),
+        BitXor => lhs.bvxor(&rhs),
+        Shl => lhs.bvshl(&rhs),
+        Shr if ty.is_signed() => lhs.bvashr(&rhs),
+        Shr => lhs.bvlshr(&rhs),
+        _ => unreachable!(),
+    }
 }
 
 thread_local!(static Z3_CONFIG: Config = Config::new());
 thread_local!(static Z3_CONTEXT: Context = Z3_CONFIG.with(|cfg| Context::new(cfg)));
 
+// Check that removing double casts from the cast chain `tys`
+// using `check_double_cast` gives the same value as the full chain
+fn verify_cast_chain(pw: PointerWidth, tys: Vec<SimpleTy>) -> bool {
+    if tys.len() <= 1 {
+        return true;
+    }
+    let tys = tys.into_iter().map(|ty| pw.retarget(ty)).collect::<Vec<_>>();
+
+    Z3_CONTEXT.with(|ctx| {
+        // Build a minimized list of types with double casts removed
+        let mut min_tys = vec![tys[0].clone()];
+        for ty in &tys[1..] {
+            assert!(!min_tys.is_empty());
+            if *ty == min_tys[min_tys.len() - 1] {
+                // Cast to the same type, ignore it
+                continue;
+            }
+            if min_tys.len() < 2 {
+                min_tys.push(ty.clone());
+                continue;
+            }
+            let last2 = &min_tys[min_tys.len() - 2..];
+            match check_double_cast(last2[0], last2[1], *ty) {
+                DoubleCastAction::RemoveBoth => {
+                    min_tys.pop();
+                }
+                DoubleCastAction::RemoveInner => {
+                    *min_tys.last_mut().unwrap() = ty.clone();
+                }
+                DoubleCastAction::KeepBoth => {
+                    min_tys.push(ty.clone());
+                }
+            }
+        }
+
+        let x = new_value(&ctx, "x", tys[0], pw);
+        let y = cast_tys(&ctx, x.clone(), &tys[..], pw);
+        let z = cast_tys(&ctx, x, &min_tys[..], pw);
+
+        // Removing casts must not introduce any new float-to-int casts,
+        // since those could be UB for values the original chain handled
+        let float_to_int = |tys: &[SimpleTy]| {
+            tys.windows(2)
+                .filter(|w| w[0].is_float() && !w[1].is_float())
+                .count()
+        };
+        if float_to_int(&min_tys) > float_to_int(&tys) {
+            return false;
+        }
+
+        // Check the full type list against the minimized one
+        let solver = Solver::new(&ctx);
+        solver.assert(&z._eq(&y).not());
+        solver.check() == SatResult::Unsat
+    })
+}
+
 quickcheck! {
     // Verify `check_double_cast` using QuickCheck and Z3
     fn verify_double_cast(pw: PointerWidth, tys: Vec<SimpleTy>) -> bool {
-        if tys.len() <= 1 {
-            return true;
+        verify_cast_chain(pw, tys)
+    }
+
+    // Same as above, but starting from an enum, e.g., `E::A as u32 as u64`
+    fn verify_enum_double_cast(pw: PointerWidth, e: EnumTy, tys: Vec<SimpleTy>) -> bool {
+        let mut chain = vec![e.0];
+        chain.extend(tys);
+        verify_cast_chain(pw, chain)
+    }
+
+    // Same as above, but starting from a `bool`, e.g., `(x != 0) as i32 as usize`
+    fn verify_bool_double_cast(pw: PointerWidth, tys: Vec<SimpleTy>) -> bool {
+        let mut chain = vec![SimpleTy::Bool];
+        chain.extend(tys);
+        verify_cast_chain(pw, chain)
+    }
+}
+
+// Spot-check the int/float chains we care about the most
+#[test]
+fn int_float_double_casts() {
+    let u32_ty = SimpleTy::Int(32, false);
+    let u64_ty = SimpleTy::Int(64, false);
+    let i32_ty = SimpleTy::Int(32, true);
+    match check_double_cast(u32_ty, SimpleTy::Float64, u64_ty) {
+        DoubleCastAction::RemoveInner => {}
+        _ => panic!("expected `u32 as f64 as u64` to collapse"),
+    }
+    match check_double_cast(u64_ty, SimpleTy::Float64, u64_ty) {
+        DoubleCastAction::KeepBoth => {}
+        _ => panic!("expected `u64 as f64 as u64` to stay"),
+    }
+    match check_double_cast(i32_ty, SimpleTy::Float64, u64_ty) {
+        DoubleCastAction::KeepBoth => {}
+        _ => panic!("expected `i32 as f64 as u64` to stay"),
+    }
+    match check_double_cast(i32_ty, u32_ty, SimpleTy::Float64) {
+        DoubleCastAction::KeepBoth => {}
+        _ => panic!("expected `i32 as u32 as f64` to stay"),
+    }
+}
+
+// `bool`s and enums can't be cast to floats directly
+#[test]
+fn bool_enum_double_casts() {
+    let i32_ty = SimpleTy::Int(32, true);
+    let usize_ty = SimpleTy::Size(64, false);
+    match check_double_cast(SimpleTy::Bool, i32_ty, usize_ty) {
+        DoubleCastAction::RemoveInner => {}
+        _ => panic!("expected `bool as i32 as usize` to collapse"),
+    }
+    match check_double_cast(SimpleTy::Bool, SimpleTy::Int(16, false), SimpleTy::Float32) {
+        DoubleCastAction::KeepBoth => {}
+        _ => panic!("expected `bool as u16 as f32` to stay"),
+    }
+    match check_double_cast(SimpleTy::Enum(8, false), SimpleTy::Int(16, false), SimpleTy::Float32) {
+        DoubleCastAction::KeepBoth => {}
+        _ => panic!("expected `E as u16 as f32` to stay"),
+    }
+}
+
+// Check whether the cast chains `a` and `b` give different results
+// when applied to the constant `x`
+fn chains_differ_at(pw: PointerWidth, a: &[SimpleTy], b: &[SimpleTy], x: i64) -> bool {
+    Z3_CONTEXT.with(|ctx| {
+        let x = BV::from_i64(&ctx, x, ty_bit_width(a[0], pw));
+        let ya = cast_tys(&ctx, x.clone(), a, pw);
+        let yb = cast_tys(&ctx, x, b, pw);
+        let solver = Solver::new(&ctx);
+        solver.assert(&ya._eq(&yb).not());
+        solver.check() == SatResult::Sat
+    })
+}
+
+// Dropping the intermediate type from a sign flip followed by an extension
+// changes how negative values get extended, e.g., `-1i32 as u32 as u64`
+// is `0xffff_ffff` but `-1i32 as u64` is `0xffff_ffff_ffff_ffff`
+#[test]
+fn sign_flip_double_casts() {
+    let i8_ty = SimpleTy::Int(8, true);
+    let u8_ty = SimpleTy::Int(8, false);
+    let u16_ty = SimpleTy::Int(16, false);
+    let i32_ty = SimpleTy::Int(32, true);
+    let u32_ty = SimpleTy::Int(32, false);
+    let u64_ty = SimpleTy::Int(64, false);
+    let i64_ty = SimpleTy::Int(64, true);
+    let ptr_ty = SimpleTy::Pointer(Mutability::Immutable);
+    let cases = [
+        ([i32_ty, u32_ty, u64_ty], -1),
+        ([i32_ty, u32_ty, u64_ty], i32::min_value() as i64),
+        ([i32_ty, u32_ty, i64_ty], -1),
+        ([i8_ty, u8_ty, u16_ty], -128),
+        ([u32_ty, i32_ty, u64_ty], -1),
+        ([u32_ty, i32_ty, ptr_ty], -1),
+        ([i32_ty, u32_ty, ptr_ty], i32::min_value() as i64),
+    ];
+    for &(ref tys, x) in &cases {
+        match check_double_cast(tys[0], tys[1], tys[2]) {
+            DoubleCastAction::KeepBoth => {}
+            action => panic!("expected {:?} to stay, got {:?}", tys, action),
+        }
+        for &pw in &[PointerWidth(32), PointerWidth(64)] {
+            assert!(verify_cast_chain(pw, tys.to_vec()));
+        }
+        // Z3 finds the counterexample for the naive rewrite on 64-bit targets
+        let naive = [tys[0], tys[2]];
+        assert!(chains_differ_at(PointerWidth(64), &tys[..], &naive, x), "{:?}", tys);
+    }
+}
+
+#[test]
+fn float_literal_round_trips() {
+    // 2^24 is the last integer before `f32` starts skipping odd values
+    assert_eq!(exact_float_lit_int("16777216", FloatTy::F32), Some(16777216));
+    assert_eq!(exact_float_lit_int("16777217", FloatTy::F32), None);
+    assert_eq!(exact_float_lit_int("16777218", FloatTy::F32), Some(16777218));
+    assert_eq!(exact_float_lit_int("16777217", FloatTy::F64), Some(16777217));
+    // Same for 2^53 and `f64`
+    assert_eq!(exact_float_lit_int("9007199254740992", FloatTy::F64), Some(9007199254740992));
+    assert_eq!(exact_float_lit_int("9007199254740993", FloatTy::F64), None);
+
+    assert_eq!(exact_float_lit_int("5.0", FloatTy::F64), Some(5));
+    assert_eq!(exact_float_lit_int("1_000.000", FloatTy::F32), Some(1000));
+    assert_eq!(exact_float_lit_int("5.5", FloatTy::F64), None);
+    assert_eq!(exact_float_lit_int("1e3", FloatTy::F64), None);
+
+    assert!(is_exact_float(16777216, FloatTy::F32));
+    assert!(!is_exact_float(16777217, FloatTy::F32));
+    assert!(is_exact_float(16777217, FloatTy::F64));
+    assert!(is_exact_float(9007199254740992, FloatTy::F64));
+    assert!(!is_exact_float(9007199254740993, FloatTy::F64));
+}
+
+quickcheck! {
+    // Verify `check_binary_cast` using QuickCheck and Z3
+    fn verify_binary_cast(
+        pw: PointerWidth,
+        op: BinOp,
+        lhs_ty: SimpleTy,
+        rhs_ty: SimpleTy,
+        op_ty: SimpleTy,
+        t_ty: SimpleTy,
+        shift: u8
+    ) -> bool {
+        let op = op.0;
+        let (lhs_ty, rhs_ty) = (pw.retarget(lhs_ty), pw.retarget(rhs_ty));
+        let (op_ty, t_ty) = (pw.retarget(op_ty), pw.retarget(t_ty));
+        let is_shift = op == BinOpKind::Shl || op == BinOpKind::Shr;
+        let shift = if is_shift {
+            let shift = shift as u32 % 64;
+            if !op_ty.is_integer() || shift >= ty_bit_width(op_ty, pw) {
+                // The original operation would panic
+                return true;
+            }
+            Some(shift as u128)
+        } else {
+            None
+        };
+        match check_binary_cast(op, lhs_ty, rhs_ty, op_ty, t_ty, shift) {
+            BinaryCastAction::KeepAll => return true,
+            BinaryCastAction::NarrowOperands => {}
         }
 
         Z3_CONTEXT.with(|ctx| {
-            // Build a minimized list of types with double casts removed
-            let mut min_tys = vec![tys[0].clone()];
-            for ty in &tys[1..] {
-                assert!(!min_tys.is_empty());
-                if *ty == min_tys[min_tys.len() - 1] {
-                    // Cast to the same type, ignore it
-                    continue;
+            let x = BV::new_const(&ctx, "x", ty_bit_width(lhs_ty, pw));
+            let (wide_rhs, narrow_rhs) = match shift {
+                Some(k) => (
+                    BV::from_u64(&ctx, k as u64, ty_bit_width(op_ty, pw)),
+                    BV::from_u64(&ctx, k as u64, ty_bit_width(t_ty, pw)),
+                ),
+                None => {
+                    let y = BV::new_const(&ctx, "y", ty_bit_width(rhs_ty, pw));
+                    let wide_y = cast_bv(&ctx, y.clone(), rhs_ty, op_ty, pw);
+                    (wide_y, cast_bv(&ctx, y, rhs_ty, t_ty, pw))
                 }
-                if min_tys.len() < 2 {
-                    min_tys.push(ty.clone());
-                    continue;
-                }
-                let last2 = &min_tys[min_tys.len() - 2..];
-                match check_double_cast(last2[0], last2[1], *ty) {
-                    DoubleCastAction::RemoveBoth => {
-                        min_tys.pop();
-                    }
-                    DoubleCastAction::RemoveInner => {
-                        *min_tys.last_mut().unwrap() = ty.clone();
-                    }
-                    DoubleCastAction::KeepBoth => {
-                        min_tys.push(ty.clone());
-                    }
-                }
-            }
+            };
+
+            // `($x as op_ty OP $y as op_ty) as t_ty`
+            let wide_lhs = cast_bv(&ctx, x.clone(), lhs_ty, op_ty, pw);
+            let wide = binop_bv(op, wide_lhs, wide_rhs, op_ty);
+            let orig = cast_bv(&ctx, wide, op_ty, t_ty, pw);
 
-            let x = BV::new_const(&ctx, "x", ty_bit_width(tys[0], pw));
-            let y = cast_tys(x.clone(), &tys[..], pw);
-            let z = cast_tys(x, &min_tys[..], pw);
+            // `$x as t_ty OP $y as t_ty`
+            let narrow_lhs = cast_bv(&ctx, x, lhs_ty, t_ty, pw);
+            let narrow = binop_bv(op, narrow_lhs, narrow_rhs, t_ty);
 
-            // Check the full type list against the minimized one
             let solver = Solver::new(&ctx);
-            solver.assert(&z._eq(&y).not());
+            solver.assert(&orig._eq(&narrow).not());
             solver.check() == SatResult::Unsat
         })
     }
@@ -3,6 +3,7 @@ use syntax::ptr::P;
 
 use c2rust_ast_builder::mk;
 use crate::command::{CommandState, Registry};
+use crate::command_args::CommandSpec;
 use crate::RefactorCtxt;
 use crate::driver::{self, Phase};
 use crate::matcher::{Bindings, BindingType, MatchCtxt, Subst, mut_visit_match_with};
@@ -12,8 +13,6 @@ use crate::transform::Transform;
 /// 
 /// Obsolete - the translator now does this automatically.
 /// 
-/// Usage: `char_literals`
-/// 
 /// Replace integer literals cast to `libc::c_char` with actual char literals.
 /// For example, replaces `65 as libc::c_char` with `'A' as libc::c_char`.
 struct CharLits {
@@ -45,5 +44,6 @@ impl Transform for CharLits {
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("char_literals", |_args| mk(CharLits{}))
+    let spec = CommandSpec::new("Replace integer literals cast to `c_char` with char literals.");
+    reg.register("char_literals", spec, |_args| Ok(mk(CharLits{})))
 }
@@ -1,24 +1,44 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use rustc_data_structures::sync::Lrc;
 use rustc::hir::{self, HirId};
+use rustc::hir::def::{DefKind, Res};
 use rustc::ty::{self, ParamEnv};
 use rustc_typeck::expr_use_visitor::*;
-use syntax::ast::{Crate, Expr, ExprKind, Lit, LitKind, Stmt, StmtKind};
+use syntax::ast::{Arm, BinOpKind, BindingMode, Block, BlockCheckMode, Crate, Expr, ExprKind};
+use syntax::ast::{FnDecl, FunctionRetTy, Ident, Item, Label, Lit, LitIntType, LitKind, Local};
+use syntax::ast::{Mac, MacDelimiter, Mutability, NodeId, Pat, PatKind, Stmt, StmtKind, Ty};
+use syntax::ast::{TyKind, UnOp};
+use syntax::mut_visit::{self, MutVisitor};
 use syntax::ptr::P;
+use syntax::source_map::DUMMY_SP;
+use syntax::symbol::Symbol;
+use syntax::token::{Nonterminal, Token, TokenKind};
+use syntax::tokenstream::TokenTree;
+use syntax::util::classify;
+use syntax::util::parser::{self, AssocOp, Fixity};
+use syntax::visit::{self, Visitor};
+use syntax_pos::Span;
+use smallvec::{smallvec, SmallVec};
 
+use crate::ast_manip::{visit_nodes, AstEquiv, FlatMapNodes, MutVisit, MutVisitNodes};
+use crate::ast_manip::fn_edit::mut_visit_fns;
 use crate::command::{CommandState, Registry};
+use crate::command_args::{ArgType, CommandSpec};
 use crate::context::HirMap;
 use crate::driver::Phase;
 use crate::matcher::{MatchCtxt, Subst, replace_expr, mut_visit_match_with, find_first};
+use crate::transform::format::fmt_str_lit;
+use crate::transform::funcs::{bound_names, mentioned_names};
 use crate::transform::Transform;
 use crate::RefactorCtxt;
-use c2rust_ast_builder::mk;
+use c2rust_ast_builder::{mk, IntoSymbol};
 
 
 /// # `reconstruct_while` Command
 /// 
 /// Obsolete - the translator now does this automatically.
 /// 
-/// Usage: `reconstruct_while`
-/// 
 /// Replaces all instances of `loop { if !cond { break; } ... }` with `while` loops.
 pub struct ReconstructWhile;
 
@@ -46,130 +66,196 @@ impl Transform for ReconstructWhile {
 
 /// # `reconstruct_for_range` Command
 /// 
-/// Usage: `reconstruct_for_range`
-/// 
 /// Replaces `i = start; while i < end { ...; i += step; }` with
-/// `for i in (start .. end).step_by(step) { ...; }`.
+/// `for i in (start .. end).step_by(step) { ...; }`.  The transpiler's
+/// `let mut i = start; while ...` form is converted the same way, and the
+/// `let` is removed.
 ///
 /// This takes a pretty conservative approach: the command only replaces the loop
-/// if the induction variable is written exactly once inside the loop (by the
-/// increment statement) and never read outside the loop.
+/// if the induction variable is an integer, written exactly once inside the loop
+/// (by the increment statement at the end of the body) and never read outside the
+/// loop.  The bound and the step have to be simple expressions of literals and
+/// variables that the loop doesn't write, since the `for` loop evaluates them only
+/// once.  Loops whose body can `continue` are left alone, since `continue` would
+/// skip the increment in the original loop but not in the `for` loop.
 pub struct ReconstructForRange;
 
 impl Transform for ReconstructForRange {
     fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
-        let mut mcx = MatchCtxt::new(st, cx);
-        let pat_str = r#"
-            $i:Expr = $start:Expr;
+        let while_loop = r#"
             $'label:?Ident: while $cond:Expr {
                 $body:MultiStmt;
                 $incr:Stmt;
             }"#;
-        let pat = mcx.parse_stmts(&pat_str);
+        for init in &[
+            "$i:Expr = $start:Expr;",
+            "let mut $v:Ident = $start:Expr;",
+            "let mut $v:Ident: $ty:Ty = $start:Expr;",
+        ] {
+            reconstruct_for_range(krate, st, cx, &format!("{}{}", init, while_loop));
+        }
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
 
-        let lt_cond = mcx.parse_expr("$i < $end:Expr");
-        let le_cond = mcx.parse_expr("$i <= $end:Expr");
+fn reconstruct_for_range(krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt, pat_str: &str) {
+    let mut mcx = MatchCtxt::new(st, cx);
+    let pat = mcx.parse_stmts(pat_str);
 
-        let i_plus_eq = mcx.parse_expr("$i += $step:Expr");
-        let i_eq_plus = mcx.parse_expr("$i = $i + $step:Expr");
+    let lt_cond = mcx.parse_expr("$i < $end:Expr");
+    let le_cond = mcx.parse_expr("$i <= $end:Expr");
 
-        let range_one_excl = mcx.parse_stmts("$'label: for $ipat:Pat in $start .. $end { $body; }");
-        let range_one_incl = mcx.parse_stmts("$'label: for $ipat:Pat in $start ..= $end { $body; }");
-        let range_step_excl = mcx.parse_stmts("$'label: for $ipat:Pat in ($start .. $end).step_by($step as usize) { $body; }");
-        let range_step_incl = mcx.parse_stmts("$'label: for $ipat:Pat in ($start ..= $end).step_by($step as usize) { $body; }");
+    let i_plus_eq = mcx.parse_expr("$i += $step:Expr");
+    let i_eq_plus = mcx.parse_expr("$i = $i + $step:Expr");
 
-        mut_visit_match_with(mcx, pat, krate, |orig, mut mcx| {
-            let cond = mcx.bindings.get::<_, P<Expr>>("$cond").unwrap().clone();
-            let range_excl = if mcx.try_match(&*lt_cond, &cond).is_ok() {
-                true
-            } else if mcx.try_match(&*le_cond, &cond).is_ok() {
-                false
-            } else {
-                return;
-            };
+    let range_one_excl = mcx.parse_stmts("$'label: for $ipat:Pat in $from .. $end { $body; }");
+    let range_one_incl = mcx.parse_stmts("$'label: for $ipat:Pat in $from ..= $end { $body; }");
+    let range_step_excl = mcx.parse_stmts("$'label: for $ipat:Pat in ($from .. $end).step_by($step as usize) { $body; }");
+    let range_step_incl = mcx.parse_stmts("$'label: for $ipat:Pat in ($from ..= $end).step_by($step as usize) { $body; }");
 
-            let incr = match mcx.bindings.get::<_, Stmt>("$incr").unwrap().kind {
-                StmtKind::Semi(ref e) |
-                StmtKind::Expr(ref e) => e.clone(),
-                _ => { return; }
-            };
-            if !mcx.try_match(&*i_plus_eq, &incr).is_ok() &&
-               !mcx.try_match(&*i_eq_plus, &incr).is_ok() {
-                return;
+    mut_visit_match_with(mcx, pat, krate, |orig, mut mcx| {
+        let hir_map = cx.hir_map();
+
+        // In the `let` forms, the variable is bound by the `let` itself.
+        let (var_expr, var_hir_id, var_node_id) = match orig[0].kind {
+            StmtKind::Local(ref l) => {
+                let v = *mcx.bindings.get::<_, Ident>("$v").unwrap();
+                let var_expr = mk().span(v.span).ident_expr(v);
+                mcx.bindings.add("$i", var_expr.clone());
+                (var_expr, hir_map.node_to_hir_id(l.pat.id), l.pat.id)
+            }
+            _ => {
+                let var_expr = mcx.bindings.get::<_, P<Expr>>("$i").unwrap().clone();
+                let var_hir_id = match_or!([cx.try_resolve_expr_hir(&var_expr)]
+                                           Some(hir::def::Res::Local(x)) => x; return);
+                (var_expr.clone(), var_hir_id, var_expr.id)
             }
+        };
+        if !cx.opt_node_type(var_node_id).map_or(false, |ty| ty.is_integral()) {
+            return;
+        }
 
-            let hir_map = cx.hir_map();
-			let while_hir_id = hir_map.node_to_hir_id(orig[1].id);
-			let parent_hir_id = hir_map.get_parent_item(while_hir_id);
-            let var_expr = mcx.bindings.get::<_, P<Expr>>("$i")
-                .unwrap().clone();
-            let var_hir_id = match_or!([cx.try_resolve_expr_hir(&var_expr)]
-                                       Some(hir::def::Res::Local(x)) => x; return);
-            let mut delegate = ForRangeDelegate {
-                hir_map,
-                while_hir_id,
-                parent_hir_id,
-                var_hir_id,
-
-                writes_inside_loop: 0,
-                reads_outside_loop: 0,
-            };
+        let cond = mcx.bindings.get::<_, P<Expr>>("$cond").unwrap().clone();
+        let range_excl = if mcx.try_match(&*lt_cond, &cond).is_ok() {
+            true
+        } else if mcx.try_match(&*le_cond, &cond).is_ok() {
+            false
+        } else {
+            return;
+        };
 
-            let tcx = cx.ty_ctxt();
-            let parent_did = match_or!([hir_map.opt_local_def_id(parent_hir_id)]
-                                       Some(x) => x; return);
-			let parent_body_id = match_or!([hir_map.maybe_body_owned_by(parent_hir_id)]
-                                           Some(x) => x; return);
-            let parent_body = hir_map.body(parent_body_id);
-			let tables = tcx.body_tables(parent_body_id);
-            tcx.infer_ctxt().enter(|infcx| {
-                ExprUseVisitor::new(&mut delegate, &infcx, parent_did,
-                                    ParamEnv::empty(), tables)
-                    .consume_body(&parent_body);
-            });
-            assert!(delegate.writes_inside_loop > 0);
-            debug!("Loop variable '{:?}' writes:{} reads:{}",
-                   var_expr,
-                   delegate.writes_inside_loop,
-                   delegate.reads_outside_loop);
-            if delegate.writes_inside_loop > 1 || delegate.reads_outside_loop > 0 {
+        let incr = match mcx.bindings.get::<_, Stmt>("$incr").unwrap().kind {
+            StmtKind::Semi(ref e) |
+            StmtKind::Expr(ref e) => e.clone(),
+            _ => { return; }
+        };
+        if !mcx.try_match(&*i_plus_eq, &incr).is_ok() &&
+           !mcx.try_match(&*i_eq_plus, &incr).is_ok() {
+            return;
+        }
+
+        let label = match orig[1].kind {
+            StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => match e.kind {
+                ExprKind::While(_, _, ref label) => label.map(|l| l.ident),
+                _ => return,
+            },
+            _ => return,
+        };
+        let body = mcx.bindings.get::<_, Vec<Stmt>>("$body").unwrap();
+        if has_continue(body, label) {
+            return;
+        }
+
+        // The bound and the step are evaluated once by the `for` loop, but on every
+        // iteration by the `while` loop.
+        let mut invariants = HashSet::new();
+        for name in &["$end", "$step"] {
+            let e = mcx.bindings.get::<_, P<Expr>>(*name).unwrap();
+            if !simple_operand(cx, e, &mut invariants) {
                 return;
             }
+        }
 
-            if let ExprKind::Path(ref qself, ref path) = var_expr.kind {
-                let var_pat = if qself.is_none() &&
-                    path.segments.len() == 1 &&
-                    path.segments[0].args.is_none()
-                {
-                    // If this path is a single-segment identifier,
-                    // we need to emit it as a `PatKind::Ident`
-                    mk()
-                        .span(var_expr.span)
-                        .ident_pat(path.segments[0].ident)
-                } else {
-                    mk()
-                        .span(var_expr.span)
-                        .qpath_pat(qself.clone(), path.clone())
-                };
-                mcx.bindings.add("$ipat", var_pat);
+        let while_hir_id = hir_map.node_to_hir_id(orig[1].id);
+        let parent_hir_id = hir_map.get_parent_item(while_hir_id);
+        let mut delegate = ForRangeDelegate {
+            hir_map,
+            while_hir_id,
+            parent_hir_id,
+            var_hir_id,
+            invariants,
+
+            writes_inside_loop: 0,
+            reads_outside_loop: 0,
+            invariant_writes: 0,
+        };
+
+        let tcx = cx.ty_ctxt();
+        let parent_did = match_or!([hir_map.opt_local_def_id(parent_hir_id)]
+                                   Some(x) => x; return);
+        let parent_body_id = match_or!([hir_map.maybe_body_owned_by(parent_hir_id)]
+                                       Some(x) => x; return);
+        let parent_body = hir_map.body(parent_body_id);
+        let tables = tcx.body_tables(parent_body_id);
+        tcx.infer_ctxt().enter(|infcx| {
+            ExprUseVisitor::new(&mut delegate, &infcx, parent_did,
+                                ParamEnv::empty(), tables)
+                .consume_body(&parent_body);
+        });
+        assert!(delegate.writes_inside_loop > 0);
+        debug!("Loop variable '{:?}' writes:{} reads:{} invariant writes:{}",
+               var_expr,
+               delegate.writes_inside_loop,
+               delegate.reads_outside_loop,
+               delegate.invariant_writes);
+        if delegate.writes_inside_loop > 1 || delegate.reads_outside_loop > 0 ||
+           delegate.invariant_writes > 0 {
+            return;
+        }
+
+        if let ExprKind::Path(ref qself, ref path) = var_expr.kind {
+            let var_pat = if qself.is_none() &&
+                path.segments.len() == 1 &&
+                path.segments[0].args.is_none()
+            {
+                // If this path is a single-segment identifier,
+                // we need to emit it as a `PatKind::Ident`
+                mk()
+                    .span(var_expr.span)
+                    .ident_pat(path.segments[0].ident)
             } else {
-                return;
+                mk()
+                    .span(var_expr.span)
+                    .qpath_pat(qself.clone(), path.clone())
             };
+            mcx.bindings.add("$ipat", var_pat);
+        } else {
+            return;
+        };
 
-            let step = mcx.bindings.get::<_, P<Expr>>("$step").unwrap();
-            let repl_step = match (is_one_expr(&*step), range_excl) {
-                (true, true) => range_one_excl.clone(),
-                (true, false) => range_one_incl.clone(),
-                (false, true) => range_step_excl.clone(),
-                (false, false) => range_step_incl.clone(),
-            };
-            *orig = repl_step.subst(st, cx, &mcx.bindings);
-        });
-    }
+        // The type of a `let` also constrains an untyped literal start, which the
+        // range wouldn't.
+        let start = mcx.bindings.get::<_, P<Expr>>("$start").unwrap().clone();
+        let from = match (mcx.bindings.get::<_, P<Ty>>("$ty"), &start.kind) {
+            (Some(ty), ExprKind::Lit(Lit {
+                kind: LitKind::Int(_, LitIntType::Unsuffixed), ..
+            })) => mk().cast_expr(start.clone(), ty.clone()),
+            _ => start.clone(),
+        };
+        mcx.bindings.add("$from", from);
 
-    fn min_phase(&self) -> Phase {
-        Phase::Phase3
-    }
+        let step = mcx.bindings.get::<_, P<Expr>>("$step").unwrap();
+        let repl_step = match (is_one_expr(&*step), range_excl) {
+            (true, true) => range_one_excl.clone(),
+            (true, false) => range_one_incl.clone(),
+            (false, true) => range_step_excl.clone(),
+            (false, false) => range_step_incl.clone(),
+        };
+        *orig = repl_step.subst(st, cx, &mcx.bindings);
+    });
 }
 
 fn is_one_expr(e: &Expr) -> bool {
@@ -186,14 +272,91 @@ fn is_one_lit(l: &Lit) -> bool {
     }
 }
 
+/// Check whether `e` is built only from literals, constants, immutable statics and locals, using
+/// casts and arithmetic.  The locals it uses are added to `locals`.
+fn simple_operand(cx: &RefactorCtxt, e: &Expr, locals: &mut HashSet<HirId>) -> bool {
+    match e.kind {
+        ExprKind::Lit(_) => true,
+        ExprKind::Path(..) => match cx.try_resolve_expr_hir(e) {
+            Some(hir::def::Res::Local(id)) => {
+                locals.insert(id);
+                true
+            }
+            Some(hir::def::Res::Def(DefKind::Const, _)) |
+            Some(hir::def::Res::Def(DefKind::AssocConst, _)) => true,
+            Some(hir::def::Res::Def(DefKind::Static, did)) =>
+                cx.ty_ctxt().static_mutability(did) == Some(hir::Mutability::Immutable),
+            _ => false,
+        },
+        ExprKind::Paren(ref e) |
+        ExprKind::Cast(ref e, _) |
+        ExprKind::Unary(UnOp::Neg, ref e) => simple_operand(cx, e, locals),
+        ExprKind::Binary(_, ref a, ref b) =>
+            simple_operand(cx, a, locals) && simple_operand(cx, b, locals),
+        _ => false,
+    }
+}
+
+/// Check whether any of `stmts` can `continue` the loop labeled `label`, or the innermost loop
+/// around them.
+fn has_continue(stmts: &[Stmt], label: Option<Ident>) -> bool {
+    has_loop_exit(stmts, label, false)
+}
+
+/// Like `has_continue`, but also look for a `break` out of the loop if `breaks` is set.
+fn has_loop_exit(stmts: &[Stmt], label: Option<Ident>, breaks: bool) -> bool {
+    struct ContinueVisitor {
+        label: Option<Ident>,
+        breaks: bool,
+        depth: usize,
+        found: bool,
+    }
+
+    impl<'ast> Visitor<'ast> for ContinueVisitor {
+        fn visit_expr(&mut self, e: &'ast Expr) {
+            match e.kind {
+                ExprKind::Continue(None) if self.depth == 0 => self.found = true,
+                ExprKind::Continue(Some(l)) if Some(l.ident) == self.label => self.found = true,
+                ExprKind::Break(None, _) if self.breaks && self.depth == 0 => self.found = true,
+                ExprKind::Break(Some(l), _) if self.breaks && Some(l.ident) == self.label => {
+                    self.found = true
+                }
+                ExprKind::While(..) | ExprKind::ForLoop(..) | ExprKind::Loop(..) => {
+                    self.depth += 1;
+                    visit::walk_expr(self, e);
+                    self.depth -= 1;
+                }
+                // `continue` can't leave a closure
+                ExprKind::Closure(..) => {}
+                _ => visit::walk_expr(self, e),
+            }
+        }
+
+        fn visit_item(&mut self, _i: &'ast Item) {}
+
+        fn visit_mac(&mut self, mac: &'ast Mac) {
+            visit::walk_mac(self, mac);
+        }
+    }
+
+    let mut v = ContinueVisitor { label, breaks, depth: 0, found: false };
+    for s in stmts {
+        v.visit_stmt(s);
+    }
+    v.found
+}
+
 struct ForRangeDelegate<'a, 'hir: 'a> {
     hir_map: HirMap<'a, 'hir>,
     while_hir_id: HirId,
     parent_hir_id: HirId,
     var_hir_id: HirId,
+    /// Locals used by the loop bound and step, which the loop must not write.
+    invariants: HashSet<HirId>,
 
     writes_inside_loop: usize,
     reads_outside_loop: usize,
+    invariant_writes: usize,
 }
 
 impl<'a, 'hir: 'a> ForRangeDelegate<'a, 'hir> {
@@ -215,6 +378,18 @@ impl<'a, 'hir: 'a> ForRangeDelegate<'a, 'hir> {
             cur_id = parent_id;
         }
     }
+
+    /// Record a write to an invariant local inside the loop.
+    fn check_invariant_write(&mut self, cmt: &Place<'_>) {
+        match cmt.base {
+            PlaceBase::Local(hir_id) if self.invariants.contains(&hir_id) => {},
+            _ => return
+        }
+
+        if self.node_inside_loop(cmt.hir_id) {
+            self.invariant_writes += 1;
+        }
+    }
 }
 
 impl<'a, 'hir: 'a, 'tcx> Delegate<'tcx> for ForRangeDelegate<'a, 'hir> {
@@ -230,6 +405,10 @@ impl<'a, 'hir: 'a, 'tcx> Delegate<'tcx> for ForRangeDelegate<'a, 'hir> {
     }
 
     fn borrow(&mut self, cmt: &Place<'tcx>, bk: ty::BorrowKind) {
+        if bk == ty::BorrowKind::MutBorrow {
+            self.check_invariant_write(cmt);
+        }
+
         match cmt.base {
             PlaceBase::Local(hir_id) if hir_id == self.var_hir_id => {},
             _ => return
@@ -251,6 +430,8 @@ impl<'a, 'hir: 'a, 'tcx> Delegate<'tcx> for ForRangeDelegate<'a, 'hir> {
     }
 
     fn mutate(&mut self, cmt: &Place<'tcx>) {
+        self.check_invariant_write(cmt);
+
         match cmt.base {
             PlaceBase::Local(hir_id) if hir_id == self.var_hir_id => {},
             _ => return
@@ -264,8 +445,6 @@ impl<'a, 'hir: 'a, 'tcx> Delegate<'tcx> for ForRangeDelegate<'a, 'hir> {
 
 /// # `remove_unused_labels` Command
 /// 
-/// Usage: `remove_unused_labels`
-/// 
 /// Removes loop labels that are not used in a named `break` or `continue`.
 pub struct RemoveUnusedLabels;
 
@@ -313,11 +492,1696 @@ impl Transform for RemoveUnusedLabels {
     }
 }
 
+/// # `simplify_bool_conditions` Command
+///
+/// Marks: reads `MARK` (default: `bool`)
+///
+/// Simplify the integer truth tests that C conditions are translated into:
+///
+///  * `c as T != 0` and `0 != c as T`, where `c` is a `bool`, become `c`, and `c as T == 0`
+///    becomes `!c`.  This includes tests of whole chains, like
+///    `(a != 0 && !p.is_null()) as libc::c_int != 0`.
+///
+///  * `!!c`, where `c` is a `bool`, becomes `c`.
+///
+///  * Other tests against zero are written as `x != 0` and `x == 0` instead of `0 != x` and
+///    `0 == x`.
+///
+/// Locals whose binding pattern is marked `MARK` are assumed to only ever hold 0 or 1, and are
+/// retyped to `bool`.  Their tests `x != 0` and `x == 0` become `x` and `!x`, and their
+/// initializer and assignments, which must be `0`, `1` or a `bool` cast to an integer, are
+/// converted to `bool` values.  A marked local that's used in any other way, such as in
+/// arithmetic, is left unchanged with a warning.
+pub struct SimplifyBoolConditions {
+    label: Symbol,
+}
+
+impl Transform for SimplifyBoolConditions {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut bools = HashMap::new();
+        visit_nodes(krate, |l: &Local| {
+            let ident = match_or!([l.pat.kind]
+                                  PatKind::Ident(BindingMode::ByValue(_), ident, None) => ident;
+                                  return);
+            if st.marked(l.pat.id, self.label) &&
+               cx.opt_node_type(l.pat.id).map_or(false, |ty| ty.is_integral()) {
+                bools.insert(cx.hir_map().node_to_hir_id(l.pat.id), ident);
+            }
+        });
+
+        let mut checker = BoolUseChecker { cx, bools: &bools, int_uses: HashMap::new() };
+        visit::walk_crate(&mut checker, krate);
+        let mut int_uses = checker.int_uses.into_iter().collect::<Vec<_>>();
+        int_uses.sort_by_key(|&(_, span)| span);
+        for (id, span) in int_uses {
+            cx.session().span_warn(
+                span,
+                &format!("not converting `{}` to `bool`: it's used as an integer", bools[&id]),
+            );
+            bools.remove(&id);
+        }
 
-pub fn register_commands(reg: &mut Registry) {
-    use super::mk;
+        krate.visit(&mut BoolSimplifier { cx, bools: bools.keys().cloned().collect() });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Finds the uses of the marked locals that keep them from becoming `bool`s.
+struct BoolUseChecker<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    bools: &'a HashMap<HirId, Ident>,
+    /// The first integer use of each local that has one.
+    int_uses: HashMap<HirId, Span>,
+}
+
+impl<'a, 'b, 'tcx> BoolUseChecker<'a, 'b, 'tcx> {
+    fn bool_local(&self, e: &Expr) -> Option<HirId> {
+        match self.cx.try_resolve_expr_hir(strip_parens(e)) {
+            Some(hir::def::Res::Local(id)) if self.bools.contains_key(&id) => Some(id),
+            _ => None,
+        }
+    }
+
+    fn int_use(&mut self, id: HirId, span: Span) {
+        self.int_uses.entry(id).or_insert(span);
+    }
+}
+
+impl<'a, 'b, 'tcx, 'ast> Visitor<'ast> for BoolUseChecker<'a, 'b, 'tcx> {
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        if let ExprKind::Assign(ref lhs, ref rhs) = e.kind {
+            if let Some(id) = self.bool_local(lhs) {
+                if bool_value(self.cx, rhs).is_none() {
+                    self.int_use(id, rhs.span);
+                }
+                self.visit_expr(rhs);
+                return;
+            }
+        }
+        if let Some((_, x)) = zero_test(e) {
+            if self.bool_local(x).is_some() {
+                return;
+            }
+        }
+        if let Some(id) = self.bool_local(e) {
+            self.int_use(id, e.span);
+        }
+        visit::walk_expr(self, e);
+    }
+
+    fn visit_local(&mut self, l: &'ast Local) {
+        let id = self.cx.hir_map().node_to_hir_id(l.pat.id);
+        if self.bools.contains_key(&id) {
+            if let Some(ref init) = l.init {
+                if bool_value(self.cx, init).is_none() {
+                    self.int_use(id, init.span);
+                }
+            }
+        }
+        visit::walk_local(self, l);
+    }
+
+    fn visit_mac(&mut self, mac: &'ast Mac) {
+        visit::walk_mac(self, mac);
+    }
+}
 
-    reg.register("reconstruct_while", |_args| mk(ReconstructWhile));
-    reg.register("reconstruct_for_range", |_args| mk(ReconstructForRange));
-    reg.register("remove_unused_labels", |_args| mk(RemoveUnusedLabels));
+struct BoolSimplifier<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    /// The marked locals that are being retyped to `bool`.
+    bools: HashSet<HirId>,
+}
+
+impl<'a, 'b, 'tcx> BoolSimplifier<'a, 'b, 'tcx> {
+    fn is_bool_local(&self, e: &Expr) -> bool {
+        match self.cx.try_resolve_expr_hir(strip_parens(e)) {
+            Some(hir::def::Res::Local(id)) => self.bools.contains(&id),
+            _ => false,
+        }
+    }
+
+    /// Simplify `e` by one step, returning a new expression that's built from `e`'s original
+    /// subexpressions.
+    fn simplify(&self, e: &Expr) -> Option<P<Expr>> {
+        if let ExprKind::Unary(UnOp::Not, ref inner) = e.kind {
+            return match strip_parens(inner).kind {
+                ExprKind::Unary(UnOp::Not, ref c) if is_bool(self.cx, c) =>
+                    Some(strip_parens(c).clone()),
+                _ => None,
+            };
+        }
+
+        let (op, x) = zero_test(e)?;
+        let cond = if self.is_bool_local(x) {
+            strip_parens(x)
+        } else {
+            match strip_parens(x).kind {
+                ExprKind::Cast(ref c, _) if is_bool(self.cx, c) => strip_parens(c),
+                _ if is_zero_expr(strip_parens(x)) => return None,
+                // Put the zero on the right.
+                _ => return match e.kind {
+                    ExprKind::Binary(_, ref lhs, _) if is_zero_expr(strip_parens(lhs)) => {
+                        let zero = mk().lit_expr(mk().int_lit(0, LitIntType::Unsuffixed));
+                        Some(mk().span(e.span).binary_expr(op, x.clone(), zero))
+                    }
+                    _ => None,
+                },
+            }
+        };
+        match op {
+            BinOpKind::Ne => Some(cond.clone()),
+            _ => Some(mk().span(e.span).unary_expr(UnOp::Not, cond.clone())),
+        }
+    }
+}
+
+impl<'a, 'b, 'tcx> MutVisitor for BoolSimplifier<'a, 'b, 'tcx> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        if let ExprKind::Assign(ref lhs, ref mut rhs) = e.kind {
+            if self.is_bool_local(lhs) {
+                *rhs = bool_value(self.cx, rhs).unwrap();
+                self.visit_expr(rhs);
+                return;
+            }
+        }
+
+        match self.simplify(e) {
+            Some(mut new_e) => {
+                self.visit_expr(&mut new_e);
+                *e = new_e;
+            }
+            None => mut_visit::noop_visit_expr(e, self),
+        }
+    }
+
+    fn visit_local(&mut self, l: &mut P<Local>) {
+        let id = self.cx.hir_map().node_to_hir_id(l.pat.id);
+        if self.bools.contains(&id) {
+            if let Some(ref mut ty) = l.ty {
+                *ty = mk().span(ty.span).ident_ty("bool");
+            }
+            if let Some(ref mut init) = l.init {
+                *init = bool_value(self.cx, init).unwrap();
+            }
+        }
+        mut_visit::noop_visit_local(l, self);
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+/// If `e` is `x != 0`, `x == 0`, `0 != x` or `0 == x`, return the operator and `x`.
+fn zero_test(e: &Expr) -> Option<(BinOpKind, &P<Expr>)> {
+    let (op, a, b) = match_or!([e.kind] ExprKind::Binary(op, ref a, ref b) => (op.node, a, b);
+                               return None);
+    match op {
+        BinOpKind::Eq | BinOpKind::Ne => {}
+        _ => return None,
+    }
+    if is_zero_expr(strip_parens(b)) {
+        Some((op, a))
+    } else if is_zero_expr(strip_parens(a)) {
+        Some((op, b))
+    } else {
+        None
+    }
+}
+
+/// Convert `e`, the value of a local that's being retyped to `bool`, to a `bool`.  This works
+/// if `e` is `0` or `1`, or a `bool` cast to an integer.
+fn bool_value(cx: &RefactorCtxt, e: &P<Expr>) -> Option<P<Expr>> {
+    let e = strip_parens(e);
+    match e.kind {
+        ExprKind::Lit(Lit { kind: LitKind::Int(i @ 0..=1, _), .. }) =>
+            Some(mk().span(e.span).lit_expr(mk().bool_lit(i == 1))),
+        ExprKind::Cast(ref c, _) if is_bool(cx, c) => Some(strip_parens(c).clone()),
+        ExprKind::Cast(ref c, _) => bool_value(cx, c),
+        _ => None,
+    }
+}
+
+fn is_bool(cx: &RefactorCtxt, e: &P<Expr>) -> bool {
+    match cx.opt_node_type(strip_parens(e).id).map(|ty| &ty.kind) {
+        Some(ty::TyKind::Bool) => true,
+        _ => false,
+    }
+}
+
+fn is_zero_expr(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Lit(Lit { kind: LitKind::Int(0, _), .. }) => true,
+        _ => false,
+    }
+}
+
+fn strip_parens(e: &P<Expr>) -> &P<Expr> {
+    match e.kind {
+        ExprKind::Paren(ref e) => strip_parens(e),
+        _ => e,
+    }
+}
+
+/// # `remove_trailing_returns` Command
+///
+/// Rewrite `return` statements at the end of function and closure bodies in expression style:
+/// a trailing `return x;` becomes the tail expression `x`, and a trailing `return;` in a function
+/// that returns `()` is deleted.  This also applies to the last statement of a nested block, and
+/// to each branch of a trailing `if`-`else` or `match`, as long as every branch ends in a
+/// `return` (or, for functions returning `()`, ends at all).  `return`s inside loops are left
+/// alone.
+///
+/// A `return x` is only rewritten when `x` already has the function's return type, since
+/// moving it out of `return` would lose the coercion to that type.  Similarly, an `if` or
+/// `match` is left alone if rewriting its branches would change its type.
+///
+/// Afterwards, `-> ()` return type annotations are removed from all functions and closures.
+pub struct RemoveTrailingReturns;
+
+impl Transform for RemoveTrailingReturns {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+        let ret_ty = |id| {
+            let sig = tcx.erase_late_bound_regions(&tcx.fn_sig(cx.node_def_id(id)));
+            tcx.erase_regions(&sig.output())
+        };
+
+        mut_visit_fns(krate, |fl| {
+            if let Some(ref mut block) = fl.block {
+                TrailingReturns { cx, ret_ty: ret_ty(fl.id) }.block(block);
+            }
+            remove_unit_ret_ty(&mut fl.decl);
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let id = e.id;
+            if let ExprKind::Closure(_, _, _, ref mut decl, ref mut body, _) = e.kind {
+                TrailingReturns { cx, ret_ty: ret_ty(id) }.expr(body);
+                remove_unit_ret_ty(decl);
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Rewrites the trailing `return`s of a function body, whose return type is `ret_ty`.
+struct TrailingReturns<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    ret_ty: ty::Ty<'tcx>,
+}
+
+impl<'a, 'b, 'tcx> TrailingReturns<'a, 'b, 'tcx> {
+    /// Check whether `return value` can be replaced with `value`, or deleted if there's no
+    /// `value`.
+    fn removable(&self, value: &Option<P<Expr>>) -> bool {
+        match *value {
+            Some(ref value) => self.cx.opt_node_type(value.id) == Some(self.ret_ty),
+            None => self.ret_ty.is_unit(),
+        }
+    }
+
+    /// Check whether the `if` or `match` `e` has the same type after its branches that end in
+    /// `return` produce the returned value instead.
+    fn branches_ok(&self, e: &Expr) -> bool {
+        self.cx.opt_node_type(e.id).map_or(false, |ty| ty == self.ret_ty || ty.is_never())
+    }
+
+    /// Check whether every path through the tail of `b` ends in a removable `return`.
+    fn block_returns(&self, b: &Block) -> bool {
+        match b.stmts.last().map(|s| &s.kind) {
+            Some(StmtKind::Semi(e)) | Some(StmtKind::Expr(e)) => self.expr_returns(e),
+            _ => false,
+        }
+    }
+
+    fn expr_returns(&self, e: &Expr) -> bool {
+        match e.kind {
+            ExprKind::Ret(ref value) => self.removable(value),
+            ExprKind::If(_, ref then, Some(ref els)) => {
+                self.branches_ok(e) && self.block_returns(then) && self.expr_returns(els)
+            }
+            ExprKind::Match(_, ref arms) => {
+                self.branches_ok(e) && !arms.is_empty() &&
+                    arms.iter().all(|arm| self.expr_returns(&arm.body))
+            }
+            ExprKind::Block(ref b, None) => self.block_returns(b),
+            _ => false,
+        }
+    }
+
+    /// Remove the trailing `return`s from `b`, whose value is returned from the function.
+    fn block(&self, b: &mut Block) {
+        let unit = self.ret_ty.is_unit();
+        let strip = match b.stmts.last().map(|s| &s.kind) {
+            Some(StmtKind::Expr(_)) => true,
+            // In a function returning `()`, the value of the last statement doesn't matter, so
+            // `return`s can be removed from any of its branches.
+            Some(StmtKind::Semi(e)) => unit || self.expr_returns(e),
+            _ => false,
+        };
+        if !strip {
+            return;
+        }
+
+        let s = b.stmts.pop().unwrap();
+        let (mut e, semi) = match s.kind {
+            StmtKind::Expr(e) => (e, false),
+            StmtKind::Semi(e) => (e, unit),
+            _ => unreachable!(),
+        };
+        if let ExprKind::Ret(None) = e.kind {
+            if unit {
+                return;
+            }
+        }
+        self.expr(&mut e);
+        let kind = if semi { StmtKind::Semi(e) } else { StmtKind::Expr(e) };
+        b.stmts.push(Stmt { id: s.id, kind, span: s.span });
+    }
+
+    /// Remove the trailing `return`s from `e`, whose value is returned from the function.
+    fn expr(&self, e: &mut P<Expr>) {
+        let span = e.span;
+        if let ExprKind::Ret(ref mut value) = e.kind {
+            if self.removable(value) {
+                // A unit `return` in a `match` arm becomes `{}`.
+                let value = value.take();
+                *e = value.unwrap_or_else(|| {
+                    mk().span(span).block_expr(mk().block(Vec::<Stmt>::new()))
+                });
+            }
+            return;
+        }
+        if let ExprKind::If(..) | ExprKind::Match(..) = e.kind {
+            if !self.branches_ok(e) {
+                return;
+            }
+        }
+
+        match e.kind {
+            ExprKind::If(_, ref mut then, ref mut els) => {
+                self.block(then);
+                if let Some(ref mut els) = *els {
+                    self.expr(els);
+                }
+            }
+            ExprKind::Match(_, ref mut arms) => {
+                for arm in arms {
+                    self.expr(&mut arm.body);
+                }
+            }
+            ExprKind::Block(ref mut b, None) => self.block(b),
+            _ => {}
+        }
+    }
+}
+
+/// Remove the return type from `decl` if it's `()`.
+fn remove_unit_ret_ty(decl: &mut P<FnDecl>) {
+    let span = match decl.output {
+        FunctionRetTy::Ty(ref ty) => match ty.kind {
+            TyKind::Tup(ref tys) if tys.is_empty() => ty.span.shrink_to_lo(),
+            _ => return,
+        },
+        FunctionRetTy::Default(_) => return,
+    };
+    decl.output = FunctionRetTy::Default(span);
+}
+
+/// # `cleanup_loops` Command
+///
+/// Turn the `loop`s that C loops are translated into back into `while` loops:
+///
+///  * `loop { if !c { break; } ... }` becomes `while c { ... }`.
+///
+///  * `loop { if c { ... } else { break; } }` becomes `while c { ... }`.
+///
+///  * With `--allow-peel`, the do-while form `loop { ...; if !c { break; } }` becomes
+///    `...; while c { ... }`, with the first iteration of the body copied out in front of the
+///    loop.  This is skipped if the body can `break` or `continue` the loop, since `continue`
+///    skips the test in the original loop, or if `c` uses a local declared in the body.
+///
+/// Conditions of the form `c` instead of `!c` are rewritten the same way, as `while !c`.
+pub struct CleanupLoops {
+    allow_peel: bool,
+}
+
+impl Transform for CleanupLoops {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        krate.visit(&mut LoopCleaner { cx, allow_peel: self.allow_peel });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+struct LoopCleaner<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    allow_peel: bool,
+}
+
+impl<'a, 'tcx> LoopCleaner<'a, 'tcx> {
+    /// Check whether the do-while loop `label: loop { body; if !cond { break; } }` can be
+    /// peeled.
+    fn can_peel(&self, body: &[Stmt], cond: &Expr, label: Option<Ident>) -> bool {
+        if has_loop_exit(body, label, true) {
+            return false;
+        }
+
+        let mut body_locals = HashSet::new();
+        for s in body {
+            match s.kind {
+                StmtKind::Local(ref l) => visit_nodes(&*l.pat, |p: &Pat| {
+                    if let PatKind::Ident(..) = p.kind {
+                        body_locals.insert(self.cx.hir_map().node_to_hir_id(p.id));
+                    }
+                }),
+                // The condition could name an item declared in the body.
+                StmtKind::Item(..) | StmtKind::Mac(..) => return false,
+                _ => {}
+            }
+        }
+
+        let mut uses_body_local = false;
+        visit_nodes(cond, |e: &Expr| {
+            if let Some(Res::Local(hir_id)) = self.cx.try_resolve_expr_hir(e) {
+                if body_locals.contains(&hir_id) {
+                    uses_body_local = true;
+                }
+            }
+        });
+        !uses_body_local
+    }
+}
+
+impl<'a, 'tcx> MutVisitor for LoopCleaner<'a, 'tcx> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        mut_visit::noop_visit_expr(e, self);
+
+        let (body, label) = match_or!([e.kind] ExprKind::Loop(ref body, label) => (body, label);
+                                      return);
+        let label_ident = label.map(|l| l.ident);
+
+        if let Some(cond) = body.stmts.first().and_then(|s| break_test(s, label_ident)) {
+            // `loop { if !c { break; } ... }`
+            let body = body.clone().map(|mut b| {
+                b.stmts.remove(0);
+                b
+            });
+            e.kind = ExprKind::While(cond, body, label);
+        } else if body.stmts.len() == 1 {
+            // `loop { if c { ... } else { break; } }`
+            let (cond, then, els) = match_or!([stmt_expr(&body.stmts[0]).map(|e| &e.kind)]
+                                              Some(ExprKind::If(cond, then, Some(els))) =>
+                                                  (cond, then, els);
+                                              return);
+            match els.kind {
+                ExprKind::Block(ref b, None) if is_break_block(b, label_ident) => {}
+                _ => return,
+            }
+            e.kind = ExprKind::While(cond.clone(), then.clone(), label);
+        }
+    }
+
+    fn visit_block(&mut self, b: &mut P<Block>) {
+        mut_visit::noop_visit_block(b, self);
+        if !self.allow_peel {
+            return;
+        }
+
+        let mut stmts = Vec::with_capacity(b.stmts.len());
+        for s in b.stmts.drain(..) {
+            let peeled = match stmt_expr(&s).map(|e| &e.kind) {
+                Some(ExprKind::Loop(body, label)) => body.stmts.split_last()
+                    .and_then(|(last, rest)| {
+                        let label_ident = label.map(|l| l.ident);
+                        let cond = break_test(last, label_ident)?;
+                        if !self.can_peel(rest, &cond, label_ident) {
+                            return None;
+                        }
+                        let body = mk().block(rest.to_owned());
+                        Some((rest.to_owned(), mk().while_expr(cond, body, label_ident)))
+                    }),
+                _ => None,
+            };
+            match peeled {
+                Some((first, while_expr)) => {
+                    stmts.extend(first);
+                    let while_stmt = match s.kind {
+                        StmtKind::Expr(_) => mk().expr_stmt(while_expr),
+                        _ => mk().semi_stmt(while_expr),
+                    };
+                    stmts.push(while_stmt);
+                }
+                None => stmts.push(s),
+            }
+        }
+        b.stmts = stmts;
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+/// The expression of an expression statement.
+fn stmt_expr(s: &Stmt) -> Option<&P<Expr>> {
+    match s.kind {
+        StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => Some(e),
+        _ => None,
+    }
+}
+
+/// Check whether `b` is `{ break; }`, leaving the loop labeled `label` or the innermost loop.
+fn is_break_block(b: &Block, label: Option<Ident>) -> bool {
+    match &b.stmts[..] {
+        [s] => match stmt_expr(s).map(|e| &e.kind) {
+            Some(ExprKind::Break(None, None)) => true,
+            Some(ExprKind::Break(Some(l), None)) => Some(l.ident) == label,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// If `s` is `if !c { break; }`, return the loop condition `c`.
+fn break_test(s: &Stmt, label: Option<Ident>) -> Option<P<Expr>> {
+    let (test, then) = match_or!([stmt_expr(s).map(|e| &e.kind)]
+                                 Some(ExprKind::If(test, then, None)) => (test, then);
+                                 return None);
+    if !is_break_block(then, label) {
+        return None;
+    }
+    match test.kind {
+        ExprKind::Unary(UnOp::Not, ref c) => Some(strip_parens(c).clone()),
+        _ => Some(mk().unary_expr("!", test.clone())),
+    }
+}
+
+/// # `reconstruct_switch` Command
+///
+/// Marks: sets `MARK` (default: `tangled_switch`)
+///
+/// Untangle the `current_block` state variables that the transpiler uses for C `switch`
+/// statements with fallthrough, as in:
+///
+/// ```ignore
+///     let mut current_block: u64;
+///     match x {
+///         1 => { a(); current_block = 2; }
+///         2 => { current_block = 2; }
+///         _ => { current_block = 3; }
+///     }
+///     match current_block {
+///         2 => { b(); }
+///         _ => {}
+///     }
+/// ```
+///
+/// Each arm of the first `match` gets the code that the second `match` (or `if cb == 2 {
+/// ... } else ...` chain) runs for the value it assigns, and the state variable and the second
+/// `match` are removed:
+///
+/// ```ignore
+///     match x {
+///         1 => { a(); b(); }
+///         2 => { b(); }
+///         _ => {}
+///     }
+/// ```
+///
+/// Adjacent arms that end up with the same body are merged into a single arm with a `|`
+/// pattern.  Code that more than two of the remaining arms fall through to isn't duplicated.
+/// In that case, or if the state variable is used in any other way, the state variable and
+/// all the `match`es on it are left alone, and the `match`es are marked with `MARK`.
+pub struct ReconstructSwitch {
+    label: Symbol,
+}
+
+/// A `match` on a state variable and the `match` before it, which sets the state.
+struct Switch {
+    state: HirId,
+    /// The `NodeId` of the first `match`.
+    first: Option<NodeId>,
+    /// The `NodeId` of the `match` or `if` chain on the state variable.
+    dispatch: NodeId,
+    /// The untangled arms of the first `match`, if possible.
+    arms: Option<Vec<Arm>>,
+    /// The number of uses of the state variable in the two `match`es.
+    uses: usize,
+}
+
+impl Transform for ReconstructSwitch {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // State variables are declared without an initializer, as `let mut current_block: u64;`.
+        let mut states = HashMap::new();
+        visit_nodes(krate, |l: &Local| {
+            if l.init.is_some() {
+                return;
+            }
+            if let PatKind::Ident(BindingMode::ByValue(Mutability::Mutable), _, None) = l.pat.kind {
+                if cx.opt_node_type(l.pat.id).map_or(false, |ty| ty.is_integral()) {
+                    states.insert(cx.hir_map().node_to_hir_id(l.pat.id), 0);
+                }
+            }
+        });
+        visit_nodes(krate, |e: &Expr| {
+            if let Some(hir_id) = state_var(cx, &states, e) {
+                *states.get_mut(&hir_id).unwrap() += 1;
+            }
+        });
+
+        let mut switches = Vec::new();
+        visit_nodes(krate, |b: &Block| {
+            for (i, s) in b.stmts.iter().enumerate() {
+                let e = match_or!([stmt_expr(s)] Some(e) => e; continue);
+                let (state, tails, uses) =
+                    match_or!([state_dispatch(cx, &states, e)] Some(x) => x; continue);
+                let first = i.checked_sub(1).and_then(|i| stmt_expr(&b.stmts[i]));
+                let untangled = first.and_then(|first| untangle(cx, &states, state, first, &tails));
+                let (arms, first_uses) = match untangled {
+                    Some((arms, first_uses)) => (Some(arms), first_uses),
+                    None => (None, 0),
+                };
+                switches.push(Switch {
+                    state,
+                    first: first.map(|e| e.id),
+                    dispatch: e.id,
+                    arms,
+                    uses: uses + first_uses,
+                });
+            }
+        });
+
+        // Only remove a state variable if every use of it is accounted for.
+        let mut covered = HashMap::new();
+        for sw in &switches {
+            let ok = sw.arms.is_some();
+            let entry = covered.entry(sw.state).or_insert((0, true));
+            entry.0 += sw.uses;
+            entry.1 &= ok;
+        }
+        let removed = covered.into_iter()
+            .filter(|&(state, (uses, ok))| ok && uses == states[&state])
+            .map(|(state, _)| state)
+            .collect::<HashSet<_>>();
+
+        let mut rewrites = HashMap::new();
+        let mut dispatches = HashSet::new();
+        for sw in switches {
+            if !removed.contains(&sw.state) {
+                st.add_mark(sw.dispatch, self.label);
+                continue;
+            }
+            rewrites.insert(sw.first.unwrap(), sw.arms.unwrap());
+            dispatches.insert(sw.dispatch);
+        }
+
+        krate.visit(&mut SwitchFolder { cx, removed, rewrites, dispatches });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+struct SwitchFolder<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    /// State variables to remove.
+    removed: HashSet<HirId>,
+    /// New arms for the first `match` of each switch.
+    rewrites: HashMap<NodeId, Vec<Arm>>,
+    /// `match`es and `if` chains on removed state variables.
+    dispatches: HashSet<NodeId>,
+}
+
+impl<'a, 'tcx> MutVisitor for SwitchFolder<'a, 'tcx> {
+    fn visit_block(&mut self, b: &mut P<Block>) {
+        let mut stmts: Vec<Stmt> = Vec::with_capacity(b.stmts.len());
+        for s in b.stmts.drain(..) {
+            if let StmtKind::Local(ref l) = s.kind {
+                let hir_id = self.cx.hir_map().node_to_hir_id(l.pat.id);
+                if self.removed.contains(&hir_id) {
+                    continue;
+                }
+            }
+            let dispatch = stmt_expr(&s).map_or(false, |e| self.dispatches.contains(&e.id));
+            if !dispatch {
+                stmts.push(s);
+                continue;
+            }
+
+            // Replace the first `match`, keeping the dispatch's place as the block's value.
+            let first = stmts.pop().unwrap();
+            let e = stmt_expr(&first).unwrap().clone().map(|mut e| {
+                if let ExprKind::Match(_, ref mut arms) = e.kind {
+                    *arms = self.rewrites[&e.id].clone();
+                }
+                e
+            });
+            stmts.push(match s.kind {
+                StmtKind::Expr(_) => mk().expr_stmt(e),
+                _ => mk().semi_stmt(e),
+            });
+        }
+        b.stmts = stmts;
+        // Visit the new arms, in case they contain more switches.
+        mut_visit::noop_visit_block(b, self);
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+/// If `e` is a path to one of the `states` variables, return its `HirId`.
+fn state_var(cx: &RefactorCtxt, states: &HashMap<HirId, usize>, e: &Expr) -> Option<HirId> {
+    if !matches!([e.kind] ExprKind::Path(None, _)) {
+        return None;
+    }
+    match cx.try_resolve_expr_hir(e) {
+        Some(Res::Local(hir_id)) if states.contains_key(&hir_id) => Some(hir_id),
+        _ => None,
+    }
+}
+
+pub(crate) fn int_lit_value(e: &Expr) -> Option<u128> {
+    match strip_parens_ref(e).kind {
+        ExprKind::Lit(Lit { kind: LitKind::Int(i, _), .. }) => Some(i),
+        _ => None,
+    }
+}
+
+fn strip_parens_ref(e: &Expr) -> &Expr {
+    match e.kind {
+        ExprKind::Paren(ref e) => strip_parens_ref(e),
+        _ => e,
+    }
+}
+
+/// The code that a `match` or `if` chain on a state variable runs for each value, with the
+/// values, or `None` for the default.
+type SwitchTails = Vec<(Option<Vec<u128>>, P<Block>)>;
+
+/// If `e` is a `match` or `if` chain on one of the `states` variables, return the variable, the
+/// code for each value, and the number of times `e` reads the variable.
+fn state_dispatch(cx: &RefactorCtxt, states: &HashMap<HirId, usize>, e: &Expr)
+                  -> Option<(HirId, SwitchTails, usize)> {
+    let arm_block = |body: &P<Expr>| match body.kind {
+        ExprKind::Block(ref b, None) => b.clone(),
+        _ => mk().block(vec![mk().expr_stmt(body.clone())]),
+    };
+
+    if let ExprKind::Match(ref scrut, ref arms) = e.kind {
+        let state = state_var(cx, states, strip_parens(scrut))?;
+        let mut tails = Vec::new();
+        for arm in arms {
+            if arm.guard.is_some() {
+                return None;
+            }
+            let alts = match arm.pat.kind {
+                PatKind::Or(ref pats) => pats.iter().collect(),
+                _ => vec![&arm.pat],
+            };
+            let mut values = Some(Vec::new());
+            for p in alts {
+                match p.kind {
+                    PatKind::Lit(ref lit) => values.as_mut()?.push(int_lit_value(lit)?),
+                    PatKind::Wild => values = None,
+                    _ => return None,
+                }
+            }
+            tails.push((values, arm_block(&arm.body)));
+        }
+        return Some((state, tails, 1));
+    }
+
+    let mut state = None;
+    let mut tails = Vec::new();
+    let mut cur = e;
+    loop {
+        let (cond, then, els) = match_or!([cur.kind] ExprKind::If(ref c, ref t, ref e) =>
+                                          (c, t, e); break);
+        let (lhs, rhs) = match strip_parens(cond).kind {
+            ExprKind::Binary(op, ref l, ref r) if op.node == BinOpKind::Eq => (l, r),
+            _ => return None,
+        };
+        let hir_id = state_var(cx, states, strip_parens(lhs))?;
+        if state.map_or(false, |s| s != hir_id) {
+            return None;
+        }
+        state = Some(hir_id);
+        tails.push((Some(vec![int_lit_value(rhs)?]), then.clone()));
+        match els {
+            Some(els) if matches!([els.kind] ExprKind::If(..)) => cur = &**els,
+            Some(els) => {
+                tails.push((None, arm_block(els)));
+                break;
+            }
+            None => break,
+        }
+    }
+    let uses = tails.iter().filter(|t| t.0.is_some()).count();
+    state.map(|state| (state, tails, uses))
+}
+
+/// Move the code from `tails` into the arms of the `match` expression `first`, which sets the
+/// state variable `state` at the end of each arm.  Returns the new arms and the number of uses of
+/// the state variable in `first`.
+fn untangle(cx: &RefactorCtxt, states: &HashMap<HirId, usize>, state: HirId, first: &Expr,
+            tails: &SwitchTails) -> Option<(Vec<Arm>, usize)> {
+    let arms = match_or!([first.kind] ExprKind::Match(_, ref arms) => arms; return None);
+
+    // Split each arm into its own code and the state it sets.
+    let mut split = Vec::new();
+    let mut uses = 0;
+    for arm in arms {
+        let (stmts, last) = match arm.body.kind {
+            ExprKind::Block(ref b, None) if b.rules == BlockCheckMode::Default => {
+                match b.stmts.split_last() {
+                    Some((last, rest)) => (rest.to_owned(), stmt_expr(last)),
+                    None => (vec![], None),
+                }
+            }
+            _ => (vec![], Some(&arm.body)),
+        };
+        let value = last.and_then(|e| match e.kind {
+            ExprKind::Assign(ref lhs, ref rhs)
+                if state_var(cx, states, strip_parens(lhs)) == Some(state) => int_lit_value(rhs),
+            _ => None,
+        });
+        match value {
+            Some(value) => {
+                uses += 1;
+                split.push((arm, Some((stmts, value))));
+            }
+            // An arm that doesn't set the state has to leave the switch some other way.
+            None if cx.opt_node_type(arm.body.id).map_or(false, |ty| ty.is_never()) => {
+                split.push((arm, None));
+            }
+            None => return None,
+        }
+    }
+
+    // Merge adjacent arms that run the same code.
+    let mut merged: Vec<(Arm, Option<(Vec<Stmt>, u128)>)> = Vec::new();
+    for (arm, body) in split {
+        if let (Some((prev, Some(prev_body))), Some(body)) = (merged.last_mut(), &body) {
+            if prev.guard.is_none() && arm.guard.is_none() && prev_body.1 == body.1 &&
+               prev_body.0.ast_equiv(&body.0) {
+                let mut pats = match prev.pat.kind {
+                    PatKind::Or(ref pats) => pats.clone(),
+                    _ => vec![prev.pat.clone()],
+                };
+                pats.push(arm.pat.clone());
+                prev.pat = mk().or_pat(pats);
+                continue;
+            }
+        }
+        merged.push((arm.clone(), body));
+    }
+
+    let tail_idx = |value| tails.iter().position(|(values, _)| {
+        values.as_ref().map_or(true, |values| values.contains(&value))
+    });
+    let mut tail_refs = HashMap::new();
+    for (_, body) in &merged {
+        if let Some((_, value)) = body {
+            if let Some(idx) = tail_idx(*value) {
+                *tail_refs.entry(idx).or_insert(0) += 1;
+            }
+        }
+    }
+    if tail_refs.iter().any(|(&idx, &refs)| refs > 2 && !tails[idx].1.stmts.is_empty()) {
+        return None;
+    }
+
+    let arms = merged.into_iter().map(|(mut arm, body)| {
+        if let Some((mut stmts, value)) = body {
+            // Keep the arm's locals out of scope of the code it falls through to.
+            let has_decls = stmts.iter().any(|s| {
+                matches!([s.kind] StmtKind::Local(..), StmtKind::Item(..))
+            });
+            if has_decls {
+                stmts = vec![mk().expr_stmt(mk().block_expr(mk().block(stmts)))];
+            }
+            if let Some(idx) = tail_idx(value) {
+                stmts.extend(tails[idx].1.stmts.iter().cloned());
+            }
+            arm.body = mk().block_expr(mk().block(stmts));
+        }
+        arm
+    }).collect();
+    Some((arms, uses))
+}
+
+
+/// # `convert_labeled_breaks` Command
+///
+/// Remove the labeled blocks that emulate a `goto` to the end of a function, like
+/// `'fail: { ...; if err { break 'fail; } ... } cleanup; return status;`.  Each `break 'fail`
+/// is replaced with a copy of the code following the block, which must end the function:
+/// either with a `return`, or with the function's tail expression, which becomes a `return`.
+/// When that code is a single `return`, the `break`s simply become early returns.  The block
+/// itself is then inlined into the enclosing one.
+///
+/// Nested labeled blocks are converted innermost first: a `break` out of an inner block
+/// becomes the code between the two blocks followed by a `break` out of the outer one, which
+/// is converted in turn.
+///
+/// A block is left alone if the code following it doesn't end the function or an enclosing
+/// labeled block, as in the rest of a loop body, if a `break` out of it carries a value, or if
+/// the code following it uses a name that is rebound inside the block.
+pub struct ConvertLabeledBreaks;
+
+impl Transform for ConvertLabeledBreaks {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+        mut_visit_fns(krate, |fl| {
+            let sig = tcx.fn_sig(cx.node_def_id(fl.id));
+            let unit_ret = sig.output().skip_binder().is_unit();
+            if let Some(ref mut block) = fl.block {
+                let body = block.id;
+                block.visit(&mut LabeledBreaks { body, unit_ret, labels: HashMap::new() });
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Converts the labeled blocks in a function body, whose `NodeId` is `body`.
+struct LabeledBreaks {
+    body: NodeId,
+    /// Whether the function returns `()`, so reaching the end of `body` is a `return`.
+    unit_ret: bool,
+    /// The labels of the labeled blocks, by the `NodeId` of the block.
+    labels: HashMap<NodeId, Label>,
+}
+
+impl LabeledBreaks {
+    /// The code that runs after a `break` out of the labeled block that is statement `i` of
+    /// `b`, ending with a `return` or a `break` out of `b`.
+    fn exit_stmts(&self, b: &Block, i: usize) -> Option<Vec<Stmt>> {
+        let mut stmts = b.stmts[i + 1..].to_owned();
+        let last = stmts.last().and_then(stmt_expr);
+        if last.map_or(false, |e| matches!([e.kind] ExprKind::Ret(..))) {
+            return Some(stmts);
+        }
+
+        let tail = match stmts.last().map(|s| &s.kind) {
+            Some(StmtKind::Expr(e)) => Some(e.clone()),
+            _ => None,
+        };
+        if b.id == self.body && !self.unit_ret {
+            let tail = tail?;
+            stmts.pop();
+            stmts.push(mk().semi_stmt(mk().return_expr(Some(tail))));
+            return Some(stmts);
+        }
+
+        let exit = if b.id == self.body {
+            mk().return_expr(None as Option<P<Expr>>)
+        } else {
+            let label = self.labels.get(&b.id)?;
+            mk().break_expr(Some(label.ident.name))
+        };
+        if let Some(tail) = tail {
+            stmts.pop();
+            stmts.push(expr_to_stmt(tail));
+        }
+        stmts.push(mk().semi_stmt(exit));
+        Some(stmts)
+    }
+}
+
+impl MutVisitor for LabeledBreaks {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        if let ExprKind::Block(ref b, Some(label)) = e.kind {
+            self.labels.insert(b.id, label);
+        }
+        mut_visit::noop_visit_expr(e, self)
+    }
+
+    fn visit_block(&mut self, b: &mut P<Block>) {
+        mut_visit::noop_visit_block(b, self);
+
+        for i in (0..b.stmts.len()).rev() {
+            let (inner, label) = match_or!([stmt_expr(&b.stmts[i]).map(|e| &e.kind)]
+                                           Some(ExprKind::Block(inner, Some(label))) =>
+                                               (inner, label.ident);
+                                           continue);
+            let exit = match_or!([self.exit_stmts(b, i)] Some(x) => x; continue);
+            if has_loop_exit(&exit, None, true) ||
+               exit.iter().any(|s| matches!([s.kind] StmtKind::Item(..))) {
+                continue;
+            }
+            let exit_block = mk().block(exit);
+            let bound = bound_names(&**inner);
+            if mentioned_names(&*exit_block).iter().any(|name| bound.contains(name)) {
+                continue;
+            }
+            let mut value_break = false;
+            visit_nodes(&**inner, |e: &Expr| {
+                if let ExprKind::Break(Some(l), Some(_)) = e.kind {
+                    value_break |= l.ident == label;
+                }
+            });
+            if value_break {
+                continue;
+            }
+
+            let mut inner = inner.clone();
+            replace_breaks(&mut inner, label, exit_block);
+            let mut stmts = inner.into_inner().stmts;
+            if i + 1 < b.stmts.len() {
+                if let Some(tail) = stmts.pop() {
+                    stmts.push(match tail.kind {
+                        StmtKind::Expr(e) => expr_to_stmt(e),
+                        kind => Stmt { id: tail.id, kind, span: tail.span },
+                    });
+                }
+            }
+            // Once the block returns at its end, the code after it only ran after a `break`.
+            let returns = stmts.last().and_then(stmt_expr)
+                .map_or(false, |e| matches!([e.kind] ExprKind::Ret(..)));
+            if returns {
+                b.stmts.truncate(i + 1);
+            }
+            b.stmts.splice(i..i + 1, stmts);
+        }
+    }
+
+    // Nested functions are converted separately.
+    fn flat_map_item(&mut self, i: P<Item>) -> SmallVec<[P<Item>; 1]> {
+        smallvec![i]
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+/// Replace each `break 'label` in `b` with the statements of `exit`.
+fn replace_breaks(b: &mut P<Block>, label: Ident, exit: P<Block>) {
+    let is_break = |e: &Expr| match e.kind {
+        ExprKind::Break(Some(l), None) => l.ident == label,
+        _ => false,
+    };
+
+    FlatMapNodes::visit(b, |s: Stmt| {
+        if stmt_expr(&s).map_or(false, |e| is_break(e)) {
+            exit.stmts.iter().cloned().collect()
+        } else {
+            smallvec![s]
+        }
+    });
+
+    // Breaks in expression position, like `match` arms.
+    let exit_expr = match &exit.stmts[..] {
+        [s] => stmt_expr(s).unwrap().clone(),
+        _ => mk().block_expr(exit),
+    };
+    MutVisitNodes::visit(b, |e: &mut P<Expr>| {
+        if is_break(e) {
+            *e = exit_expr.clone();
+        }
+    });
+}
+
+/// Build a statement for `e`, with a semicolon unless `e` is block-like.
+fn expr_to_stmt(e: P<Expr>) -> Stmt {
+    if classify::expr_requires_semi_to_be_stmt(&e) {
+        mk().semi_stmt(e)
+    } else {
+        mk().expr_stmt(e)
+    }
+}
+
+
+/// # `remove_redundant_parens` Command
+///
+/// Removes parentheses that don't change how an expression parses, like those in
+/// `((a + b) as u32)` or `x = (y * 2);`, along with blocks wrapping a single expression or
+/// statement, like `let x = { y };` or `{ f(x); }`.
+///
+/// Whether a pair of parentheses is needed is decided from the precedence of the expression
+/// inside and the position it appears in.  Parentheses are always kept around match and
+/// `if let` scrutinees, around conditions containing struct literals, around casts on the
+/// left of `<` or `<<`, around block-like expressions outside of plain value positions, and
+/// around a borrowed `raw` path, which would read as a raw borrow.  The parenthesized
+/// expression keeps its original text, while the expression containing it is reformatted.
+///
+/// Blocks are only unwrapped in value positions, where the block's result is used and not
+/// borrowed or assigned to, and in statement position when they contain a single statement
+/// other than a `let` or an item.  Labeled and `unsafe` blocks are kept.
+pub struct RemoveRedundantParens;
+
+impl Transform for RemoveRedundantParens {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        krate.visit(&mut UnwrapBlocks);
+
+        // The crate we're given has its parentheses removed, so parse the source again to find
+        // out which expressions were parenthesized.
+        let path = match cx.session().local_crate_source_file {
+            Some(ref path) => path.clone(),
+            None => return,
+        };
+        let parsed = match rustc_parse::parse_crate_from_file(&path, &cx.session().parse_sess) {
+            Ok(krate) => krate,
+            Err(mut db) => {
+                db.cancel();
+                warn!("remove_redundant_parens: failed to parse {:?}", path);
+                return;
+            }
+        };
+        let mut parens = HashSet::new();
+        visit_nodes(&parsed, |e: &Expr| {
+            if let ExprKind::Paren(ref inner) = e.kind {
+                parens.insert(inner.span);
+            }
+        });
+
+        let mut v = RedundantParens { st, parens };
+        visit::walk_crate(&mut v, &*krate);
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase1
+    }
+}
+
+/// How a subexpression in some position may be written without parentheses.
+#[derive(Copy, Clone)]
+enum ParenCtx {
+    /// Any expression that isn't block-like.
+    Any,
+    /// Any expression, since the position is a plain value.
+    Value,
+    /// Expressions with at least the given precedence.
+    Prec(i8),
+    /// Callee of a call: as `Prec(PREC_POSTFIX)`, but fields need parentheses too.
+    Callee,
+    /// `if` and `while` conditions: anything without an exterior struct literal.
+    Cond,
+    /// Parentheses are always kept.
+    Keep,
+}
+
+struct RedundantParens<'a> {
+    st: &'a CommandState,
+    /// Spans of the expressions that were parenthesized in the source.
+    parens: HashSet<Span>,
+}
+
+impl<'a> RedundantParens<'a> {
+    /// Check whether the parenthesized `e` could be written without them at `ctx`.
+    fn is_redundant(&self, e: &Expr, ctx: ParenCtx) -> bool {
+        if !self.parens.contains(&e.span) {
+            return false;
+        }
+        let prec = e.precedence().order();
+        match ctx {
+            ParenCtx::Value => true,
+            ParenCtx::Any => !is_block_like(e),
+            ParenCtx::Prec(min) => !is_block_like(e) && prec >= min,
+            ParenCtx::Callee => {
+                !is_block_like(e) && prec >= parser::PREC_POSTFIX &&
+                    !matches!([e.kind] ExprKind::Field(..))
+            }
+            ParenCtx::Cond => !is_block_like(e) && !parser::contains_exterior_struct_lit(e),
+            ParenCtx::Keep => false,
+        }
+    }
+
+    /// The positions of the subexpressions of `e`.
+    fn child_ctxs<'e>(&self, e: &'e Expr) -> Vec<(&'e P<Expr>, ParenCtx)> {
+        let postfix = ParenCtx::Prec(parser::PREC_POSTFIX);
+        let prefix = ParenCtx::Prec(parser::PREC_PREFIX);
+        match e.kind {
+            ExprKind::Box(ref x) | ExprKind::Unary(_, ref x) => vec![(x, prefix)],
+            ExprKind::AddrOf(_, _, ref x) => match x.kind {
+                ExprKind::Path(None, ref path) if path.segments.len() == 1 &&
+                    &*path.segments[0].ident.as_str() == "raw" => vec![(x, ParenCtx::Keep)],
+                _ => vec![(x, prefix)],
+            },
+            ExprKind::Array(ref xs) | ExprKind::Tup(ref xs) => {
+                xs.iter().map(|x| (x, ParenCtx::Value)).collect()
+            }
+            ExprKind::Call(ref f, ref args) => {
+                let mut v = vec![(f, ParenCtx::Callee)];
+                v.extend(args.iter().map(|x| (x, ParenCtx::Value)));
+                v
+            }
+            ExprKind::MethodCall(_, ref args) => {
+                let mut v = vec![(&args[0], literal_base_ctx(&args[0], postfix))];
+                v.extend(args[1..].iter().map(|x| (x, ParenCtx::Value)));
+                v
+            }
+            ExprKind::Binary(op, ref lhs, ref rhs) => {
+                let assoc = AssocOp::from_ast_binop(op.node);
+                let prec = assoc.precedence() as i8;
+                let (lhs_prec, rhs_prec) = match assoc.fixity() {
+                    Fixity::Left => (prec, prec + 1),
+                    Fixity::Right => (prec + 1, prec),
+                    Fixity::None => (prec + 1, prec + 1),
+                };
+                let lhs_ctx = match op.node {
+                    BinOpKind::Lt | BinOpKind::Shl if ends_with_cast(lhs) => ParenCtx::Keep,
+                    _ => ParenCtx::Prec(lhs_prec),
+                };
+                vec![(lhs, lhs_ctx), (rhs, ParenCtx::Prec(rhs_prec))]
+            }
+            ExprKind::Cast(ref x, _) | ExprKind::Type(ref x, _) => {
+                vec![(x, ParenCtx::Prec(AssocOp::As.precedence() as i8))]
+            }
+            ExprKind::If(ref cond, ..) | ExprKind::While(ref cond, ..) => {
+                vec![(cond, ParenCtx::Cond)]
+            }
+            ExprKind::ForLoop(_, ref iter, ..) => vec![(iter, ParenCtx::Cond)],
+            ExprKind::Match(_, ref arms) => {
+                let mut v = vec![];
+                for arm in arms {
+                    if let Some(ref guard) = arm.guard {
+                        v.push((guard, ParenCtx::Any));
+                    }
+                    v.push((&arm.body, ParenCtx::Value));
+                }
+                v
+            }
+            ExprKind::Closure(_, _, _, ref decl, ref body, _) => match decl.output {
+                FunctionRetTy::Default(_) => vec![(body, ParenCtx::Value)],
+                _ => vec![],
+            },
+            ExprKind::Assign(_, ref rhs) | ExprKind::AssignOp(_, _, ref rhs) => {
+                vec![(rhs, ParenCtx::Prec(AssocOp::Assign.precedence() as i8))]
+            }
+            // `(x.0).1` would become `x.0.1`, where `0.1` is a float
+            ExprKind::Field(ref x, _) if matches!([x.kind] ExprKind::Field(..)) => {
+                vec![(x, ParenCtx::Keep)]
+            }
+            ExprKind::Field(ref x, _) => vec![(x, literal_base_ctx(x, postfix))],
+            ExprKind::Index(ref x, ref idx) => {
+                vec![(x, literal_base_ctx(x, postfix)), (idx, ParenCtx::Value)]
+            }
+            ExprKind::Try(ref x) | ExprKind::Await(ref x) => vec![(x, postfix)],
+            ExprKind::Break(_, Some(ref x)) | ExprKind::Ret(Some(ref x)) => {
+                vec![(x, ParenCtx::Value)]
+            }
+            ExprKind::Struct(_, ref fields, ref base) => {
+                let mut v = fields.iter().map(|f| (&f.expr, ParenCtx::Value)).collect::<Vec<_>>();
+                v.extend(base.iter().map(|x| (x, ParenCtx::Value)));
+                v
+            }
+            ExprKind::Repeat(ref x, _) => vec![(x, ParenCtx::Value)],
+            _ => vec![],
+        }
+    }
+}
+
+impl<'a, 'ast> Visitor<'ast> for RedundantParens<'a> {
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        let redundant = self.child_ctxs(e).into_iter().any(|(x, ctx)| self.is_redundant(x, ctx));
+        if redundant {
+            self.st.reprint(e.id);
+        }
+        visit::walk_expr(self, e);
+    }
+
+    fn visit_stmt(&mut self, s: &'ast Stmt) {
+        let redundant = match s.kind {
+            StmtKind::Local(ref l) => {
+                l.init.as_ref().map_or(false, |x| self.is_redundant(x, ParenCtx::Value))
+            }
+            StmtKind::Expr(ref x) | StmtKind::Semi(ref x) => self.is_redundant(x, ParenCtx::Any),
+            _ => false,
+        };
+        if redundant {
+            self.st.reprint(s.id);
+        }
+        visit::walk_stmt(self, s);
+    }
+
+    fn visit_mac(&mut self, mac: &'ast Mac) {
+        visit::walk_mac(self, mac);
+    }
+}
+
+/// Literals can't lose their parentheses when a field or method is accessed on them, since
+/// `(1).0` would become the float `1.0`.
+fn literal_base_ctx(e: &Expr, ctx: ParenCtx) -> ParenCtx {
+    match e.kind {
+        ExprKind::Lit(_) => ParenCtx::Keep,
+        _ => ctx,
+    }
+}
+
+/// Check whether `e` ends with a cast, which would make a following `<` or `<<` start a list
+/// of generic arguments.
+fn ends_with_cast(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Cast(..) | ExprKind::Type(..) => true,
+        ExprKind::Binary(_, _, ref rhs) => ends_with_cast(rhs),
+        ExprKind::Unary(_, ref x) | ExprKind::AddrOf(_, _, ref x) | ExprKind::Box(ref x) => {
+            ends_with_cast(x)
+        }
+        _ => false,
+    }
+}
+
+fn is_block_like(e: &Expr) -> bool {
+    !classify::expr_requires_semi_to_be_stmt(e)
+}
+
+/// If `e` is a plain block `{ x }` holding a single expression, return `x`.
+fn single_expr_block(e: &Expr) -> Option<&P<Expr>> {
+    if !e.attrs.is_empty() {
+        return None;
+    }
+    match e.kind {
+        ExprKind::Block(ref b, None) if b.rules == BlockCheckMode::Default => match b.stmts[..] {
+            [Stmt { kind: StmtKind::Expr(ref x), .. }] => Some(x),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Unwraps blocks holding a single expression in value positions, and blocks holding a single
+/// statement in statement position.
+struct UnwrapBlocks;
+
+impl UnwrapBlocks {
+    fn unwrap_value(e: &mut P<Expr>) {
+        if let Some(x) = single_expr_block(e).cloned() {
+            *e = x;
+        }
+    }
+}
+
+impl MutVisitor for UnwrapBlocks {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        mut_visit::noop_visit_expr(e, self);
+        match e.kind {
+            ExprKind::Call(_, ref mut args) => args.iter_mut().for_each(Self::unwrap_value),
+            ExprKind::MethodCall(_, ref mut args) => {
+                args[1..].iter_mut().for_each(Self::unwrap_value)
+            }
+            ExprKind::Array(ref mut xs) | ExprKind::Tup(ref mut xs) => {
+                xs.iter_mut().for_each(Self::unwrap_value)
+            }
+            ExprKind::Binary(_, ref mut lhs, ref mut rhs) => {
+                Self::unwrap_value(lhs);
+                Self::unwrap_value(rhs);
+            }
+            ExprKind::Unary(UnOp::Neg, ref mut x) | ExprKind::Unary(UnOp::Not, ref mut x) |
+            ExprKind::Cast(ref mut x, _) | ExprKind::Assign(_, ref mut x) |
+            ExprKind::AssignOp(_, _, ref mut x) | ExprKind::Ret(Some(ref mut x)) |
+            ExprKind::Break(_, Some(ref mut x)) => Self::unwrap_value(x),
+            ExprKind::Match(_, ref mut arms) => {
+                arms.iter_mut().for_each(|arm| Self::unwrap_value(&mut arm.body))
+            }
+            ExprKind::Struct(_, ref mut fields, _) => {
+                fields.iter_mut().for_each(|f| Self::unwrap_value(&mut f.expr))
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_local(&mut self, l: &mut P<Local>) {
+        mut_visit::noop_visit_local(l, self);
+        if let Some(ref mut init) = l.init {
+            Self::unwrap_value(init);
+        }
+    }
+
+    fn visit_block(&mut self, b: &mut P<Block>) {
+        mut_visit::noop_visit_block(b, self);
+        let n = b.stmts.len();
+        let stmts = mem::replace(&mut b.stmts, Vec::new());
+        for (i, s) in stmts.into_iter().enumerate() {
+            let inner = match s.kind {
+                StmtKind::Expr(ref e) | StmtKind::Semi(ref e) if e.attrs.is_empty() => {
+                    match e.kind {
+                        ExprKind::Block(ref inner, None)
+                            if inner.rules == BlockCheckMode::Default &&
+                                inner.stmts.len() == 1 => Some(inner.stmts[0].clone()),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            let inner = match inner {
+                // A `let` or an item would leak into the enclosing block
+                Some(inner) => match inner.kind {
+                    StmtKind::Local(..) | StmtKind::Item(..) => None,
+                    _ => Some(inner),
+                },
+                None => None,
+            };
+            match inner {
+                Some(Stmt { kind: StmtKind::Expr(x), .. }) => {
+                    // Keep the value of a trailing `{ x }` block
+                    let is_tail = i + 1 == n && matches!([s.kind] StmtKind::Expr(..));
+                    if is_tail {
+                        b.stmts.push(mk().expr_stmt(x));
+                    } else {
+                        b.stmts.push(expr_to_stmt(x));
+                    }
+                }
+                Some(inner) => b.stmts.push(inner),
+                None => b.stmts.push(s),
+            }
+        }
+    }
+}
+
+/// # `convert_asserts` Command
+///
+/// Turn the `if` statements that C `assert()`s are translated into back into `assert!`s.  An
+/// `if` whose only branch, or whose only non-empty branch, consists of nothing but a call to one
+/// of the abort functions `FUNC` (default: `__assert_fail`, `__assert_rtn`, `__assert` and
+/// `abort`) becomes an assertion of the condition under which the call isn't made:
+///
+/// ```ignore
+///     if x != 0 {
+///     } else {
+///         __assert_fail(b"x != 0\x00" as *const u8 as *const libc::c_char,
+///                       b"main.c\x00" as *const u8 as *const libc::c_char,
+///                       12 as libc::c_uint, ...);
+///     };
+///     if p.is_null() { abort(); }
+/// ```
+///
+/// After running `convert_asserts`:
+///
+/// ```ignore
+///     assert!(x != 0, "x != 0");
+///     assert!(!p.is_null());
+/// ```
+///
+/// The message is the first string literal argument of the call, if it has one.  A condition
+/// that has to be negated is inverted where possible, so `x == 0` becomes `x != 0` rather than
+/// `!(x == 0)`, except for ordering comparisons of floats, which can't be inverted because of
+/// NaN.  An `if` whose abort branch does anything besides the call, like cleaning up, is left
+/// alone, since the assertion would skip that code.  With `--debug`, `debug_assert!` is used
+/// instead of `assert!`.
+pub struct ConvertAsserts {
+    debug: bool,
+    abort_fns: HashSet<Symbol>,
+}
+
+impl ConvertAsserts {
+    /// If `b` consists of nothing but a call to an abort function, return the call's arguments.
+    fn abort_args<'b>(&self, b: &'b Block) -> Option<&'b [P<Expr>]> {
+        let e = match b.stmts[..] {
+            [Stmt { kind: StmtKind::Semi(ref e), .. }] |
+            [Stmt { kind: StmtKind::Expr(ref e), .. }] => e,
+            _ => return None,
+        };
+        let (func, args) = match_or!([e.kind] ExprKind::Call(ref f, ref a) => (f, a);
+                                     return None);
+        match func.kind {
+            ExprKind::Path(None, ref path)
+                    if self.abort_fns.contains(&path.segments.last()?.ident.name) => Some(args),
+            _ => None,
+        }
+    }
+
+    /// If `e` is an assert-like `if`, return the asserted condition and the message.
+    fn assertion(&self, cx: &RefactorCtxt, e: &Expr) -> Option<(P<Expr>, Option<String>)> {
+        let (cond, then, els) = match_or!([e.kind] ExprKind::If(ref c, ref t, ref e) => (c, t, e);
+                                          return None);
+        if matches!([cond.kind] ExprKind::Let(..)) {
+            return None;
+        }
+        let els = match *els {
+            Some(ref els) => match els.kind {
+                ExprKind::Block(ref b, None) => Some(b),
+                _ => return None,
+            },
+            None => None,
+        };
+        let (cond, args) = match els {
+            Some(els) if then.stmts.is_empty() => {
+                (strip_parens(cond).clone(), self.abort_args(els)?)
+            }
+            Some(els) if !els.stmts.is_empty() => return None,
+            _ => (negate_cond(cx, cond), self.abort_args(then)?),
+        };
+        let msg = args.iter().filter_map(|arg| fmt_str_lit(arg)).next().map(|mut msg| {
+            while msg.ends_with('\0') {
+                msg.pop();
+            }
+            // The message is a format string.
+            msg.replace('{', "{{").replace('}', "}}")
+        });
+        Some((cond, msg.filter(|msg| !msg.is_empty())))
+    }
+}
+
+impl Transform for ConvertAsserts {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        let name = if self.debug { "debug_assert" } else { "assert" };
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            for s in &mut b.stmts {
+                let assertion = match s.kind {
+                    StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => self.assertion(cx, e),
+                    _ => None,
+                };
+                if let Some((cond, msg)) = assertion {
+                    let expr_tt = |e: P<Expr>| TokenTree::Token(Token {
+                        kind: TokenKind::Interpolated(Lrc::new(Nonterminal::NtExpr(e))),
+                        span: DUMMY_SP,
+                    });
+                    let mut tts = vec![expr_tt(cond)];
+                    if let Some(msg) = msg {
+                        let comma = Token { kind: TokenKind::Comma, span: DUMMY_SP };
+                        tts.push(TokenTree::Token(comma));
+                        tts.push(expr_tt(mk().lit_expr(msg)));
+                    }
+                    let mac = mk().mac(vec![name], tts, MacDelimiter::Parenthesis);
+                    *s = mk().span(s.span).mac_stmt(mac);
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// The negation of the condition `e`.  Comparisons are inverted rather than wrapped in `!`.
+fn negate_cond(cx: &RefactorCtxt, e: &P<Expr>) -> P<Expr> {
+    let e = strip_parens(e);
+    match e.kind {
+        ExprKind::Unary(UnOp::Not, ref x) => return strip_parens(x).clone(),
+        ExprKind::Binary(op, ref lhs, ref rhs) => {
+            let is_float = cx.opt_node_type(lhs.id).map_or(true, |ty| ty.is_floating_point());
+            let inverse = match op.node {
+                BinOpKind::Eq => Some(BinOpKind::Ne),
+                BinOpKind::Ne => Some(BinOpKind::Eq),
+                // `!(a < b)` isn't `a >= b` if either is NaN.
+                BinOpKind::Lt if !is_float => Some(BinOpKind::Ge),
+                BinOpKind::Le if !is_float => Some(BinOpKind::Gt),
+                BinOpKind::Gt if !is_float => Some(BinOpKind::Le),
+                BinOpKind::Ge if !is_float => Some(BinOpKind::Lt),
+                _ => None,
+            };
+            if let Some(inverse) = inverse {
+                return mk().span(e.span).binary_expr(inverse, lhs.clone(), rhs.clone());
+            }
+        }
+        _ => {}
+    }
+    let x = if e.precedence().order() < parser::PREC_PREFIX {
+        mk().span(e.span).paren_expr(e.clone())
+    } else {
+        e.clone()
+    };
+    mk().span(e.span).unary_expr(UnOp::Not, x)
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    let spec = CommandSpec::new("Replace `loop { if !cond { break; } ... }` with `while`.");
+    reg.register("reconstruct_while", spec, |_args| Ok(mk(ReconstructWhile)));
+
+    let spec = CommandSpec::new("Replace counting `while` loops with `for` loops over ranges.");
+    reg.register("reconstruct_for_range", spec, |_args| Ok(mk(ReconstructForRange)));
+
+    let spec = CommandSpec::new("Remove loop labels that no `break` or `continue` uses.");
+    reg.register("remove_unused_labels", spec, |_args| Ok(mk(RemoveUnusedLabels)));
+
+    let spec = CommandSpec::new("Rewrite trailing `return`s as tail expressions.");
+    reg.register("remove_trailing_returns", spec, |_args| Ok(mk(RemoveTrailingReturns)));
+
+    let spec = CommandSpec::new("Untangle the state variables of translated `switch`es.")
+        .optional_arg(
+            "MARK",
+            ArgType::Str,
+            Some("tangled_switch"),
+            "the mark to apply to the switches that can't be untangled",
+        );
+    reg.register("reconstruct_switch", spec, |args| {
+        Ok(mk(ReconstructSwitch {
+            label: args.str("MARK").into_symbol(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Turn the `loop`s that C loops are translated into into `while`s.")
+        .flag("--allow-peel", "also convert do-while loops, duplicating their body");
+    reg.register("cleanup_loops", spec, |args| {
+        Ok(mk(CleanupLoops {
+            allow_peel: args.flag("--allow-peel"),
+        }))
+    });
+
+    let spec = CommandSpec::new("Simplify the integer truth tests of translated conditions.")
+        .optional_arg("MARK", ArgType::Str, Some("bool"), "the mark of the variables to simplify");
+    reg.register("simplify_bool_conditions", spec, |args| {
+        Ok(mk(SimplifyBoolConditions {
+            label: args.str("MARK").into_symbol(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Remove labeled blocks that emulate a `goto` to the end.");
+    reg.register("convert_labeled_breaks", spec, |_args| Ok(mk(ConvertLabeledBreaks)));
+
+    let spec = CommandSpec::new("Remove parentheses and blocks that don't change how code parses.");
+    reg.register("remove_redundant_parens", spec, |_args| Ok(mk(RemoveRedundantParens)));
+
+    let spec = CommandSpec::new("Turn translated C `assert()`s back into `assert!`s.")
+        .flag("--debug", "use `debug_assert!` instead")
+        .rest(
+            "FUNC",
+            "the abort functions (default: `__assert_fail`, `__assert_rtn`, `__assert`, `abort`)",
+        );
+    reg.register("convert_asserts", spec, |args| {
+        let fns = args.list("FUNC");
+        let abort_fns = if fns.is_empty() {
+            ["__assert_fail", "__assert_rtn", "__assert", "abort"].iter()
+                .map(|&f| f.into_symbol())
+                .collect()
+        } else {
+            fns.iter().map(|f| f.into_symbol()).collect()
+        };
+        Ok(mk(ConvertAsserts {
+            debug: args.flag("--debug"),
+            abort_fns,
+        }))
+    });
 }
@@ -0,0 +1,1035 @@
+//! Transforms for turning the integer constants that C enums and flags are translated into back
+//! into Rust enums and flag types.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use rustc::hir::{self, HirId, def::{DefKind, Res}};
+use rustc::hir::def_id::DefId;
+use rustc::ty;
+use smallvec::{smallvec, SmallVec};
+use syntax::ast::*;
+use syntax::mut_visit::{self, MutVisitor};
+use syntax::print::pprust;
+use syntax::ptr::P;
+use syntax::symbol::Symbol;
+use syntax_pos::Span;
+
+use c2rust_ast_builder::mk;
+use crate::ast_manip::{FlatMapNodes, MutVisit, MutVisitNodes, visit_nodes};
+use crate::command::{Command, CommandState, RefactorState, Registry, TypeckLoopResult};
+use crate::command_args::{ArgType, CommandSpec};
+use crate::driver::{Phase, parse_expr};
+use crate::illtyped::{IlltypedFolder, fold_illtyped};
+use crate::path_edit::fold_resolved_paths_with_id;
+use crate::reflect;
+use crate::RefactorCtxt;
+
+
+/// # `recover_enum` Command
+///
+/// Marks: `target`
+///
+/// Turn a C enum that was translated into a type alias and a group of integer constants back
+/// into a Rust enum.  The variants are the constants whose type is the alias `NAME`, or, if no
+/// `NAME` is given, the constants marked `target`, which must all have the same alias as their
+/// type.  Each constant's value must be an integer literal, and no two constants may have the
+/// same value; otherwise the command aborts.
+///
+/// The alias is replaced by an enum with the same name, the integer type as its `repr`, and a
+/// variant for each constant, and the constants are removed:
+///
+/// ```ignore
+///     pub type Color = libc::c_uint;
+///     pub const RED: Color = 0;
+///     pub const GREEN: Color = 1;
+/// ```
+///
+/// After running `recover_enum Color`:
+///
+/// ```ignore
+///     #[repr(u32)]
+///     #[derive(Copy, Clone, PartialEq, Eq)]
+///     pub enum Color {
+///         RED = 0,
+///         GREEN = 1,
+///     }
+/// ```
+///
+/// Uses of the constants become uses of the variants, like `Color::RED`, and imports of the
+/// constants import the variants instead.  Foreign functions and statics, and functions with a
+/// non-Rust ABI, keep the integer type in their signatures.  The command then fixes the code that
+/// no longer typechecks:
+///
+///  * Where an integer is still needed, such as in arithmetic, ordering comparisons, comparisons
+///    with other integers, or calls to foreign functions, the enum value is cast with `as`.
+///  * Where an enum is needed but an integer literal is given, the literal is replaced by the
+///    variant with that value.
+///  * A `match` on an enum value whose patterns are integer literals matches on the variants
+///    instead, dropping patterns that don't match any variant.  If the arms cover every variant,
+///    the wildcard arm becomes `_ => unreachable!()`.
+///
+/// Other integers used as the enum, like values returned from C, can't be converted safely.  They
+/// are reported as warnings and left for the user to fix.
+pub struct RecoverEnum {
+    name: Option<String>,
+}
+
+/// The enum being recovered.
+struct EnumInfo {
+    name: Ident,
+    /// The integer type that the enum was an alias of.
+    int_ty: P<Ty>,
+    /// The names of the variants, by discriminant.
+    variants: BTreeMap<i128, Ident>,
+}
+
+impl Command for RecoverEnum {
+    fn run(&mut self, state: &mut RefactorState) {
+        let info = state.transform_crate(Phase::Phase3, |st, cx| {
+            st.map_krate(|krate| self.replace_consts(krate, st, cx))
+        }).expect("Failed to run compiler");
+
+        let mut unfixable = HashSet::new();
+        state.run_typeck_loop(|krate, _st, cx| {
+            let mut fixer = EnumFixer::new(cx, krate, &info, &mut unfixable);
+            krate.visit(&mut fixer);
+            if !fixer.changed {
+                fold_illtyped(cx, krate, &mut fixer);
+            }
+            if fixer.changed {
+                TypeckLoopResult::Iterate
+            } else {
+                TypeckLoopResult::Finished
+            }
+        }).expect("Could not retype crate!");
+
+        let mut unfixable = unfixable.into_iter().collect::<Vec<_>>();
+        unfixable.sort();
+        for span in unfixable {
+            state.session().span_warn(
+                span,
+                &format!("can't convert this integer to `{}`", info.name),
+            );
+        }
+    }
+}
+
+impl RecoverEnum {
+    /// Replace the alias by an enum and the constants by its variants.
+    fn replace_consts(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt)
+                      -> EnumInfo {
+        // (1) Find the alias and its constants.
+
+        let mut alias = None;
+        match self.name {
+            Some(ref name) => visit_nodes(krate, |i: &Item| {
+                if let ItemKind::TyAlias(..) = i.kind {
+                    if &*i.ident.as_str() == name {
+                        if alias.is_some() {
+                            panic!("can't recover enum `{}`: there's more than one type alias \
+                                    with that name", name);
+                        }
+                        alias = Some(cx.node_def_id(i.id));
+                    }
+                }
+            }),
+            None => visit_nodes(krate, |i: &Item| {
+                if let ItemKind::Const(ref ty, _) = i.kind {
+                    if !st.marked(i.id, "target") {
+                        return;
+                    }
+                    let ty_did = cx.try_resolve_ty(ty);
+                    if ty_did.is_none() || (alias.is_some() && alias != ty_did) {
+                        panic!("can't recover enum: the marked constants don't all have the \
+                                same type alias");
+                    }
+                    alias = ty_did;
+                }
+            }),
+        }
+        let alias = alias.unwrap_or_else(|| panic!("can't recover enum: found no type alias"));
+
+        let mut alias_item = None;
+        let mut consts = Vec::new();
+        visit_nodes(krate, |i: &Item| {
+            match i.kind {
+                ItemKind::TyAlias(ref ty, _) if cx.node_def_id(i.id) == alias => {
+                    alias_item = Some((i.ident, ty.clone()));
+                }
+                ItemKind::Const(ref ty, ref init) if cx.try_resolve_ty(ty) == Some(alias) => {
+                    if self.name.is_none() && !st.marked(i.id, "target") {
+                        return;
+                    }
+                    consts.push((cx.node_def_id(i.id), i.ident, init.clone()));
+                }
+                _ => {}
+            }
+        });
+        let (name, int_ty) = alias_item.unwrap_or_else(|| {
+            panic!("can't recover enum: the type of the constants isn't a type alias")
+        });
+
+        let repr = match cx.ty_ctxt().type_of(alias).kind {
+            ty::TyKind::Int(ity) => ity.name_str(),
+            ty::TyKind::Uint(uty) => uty.name_str(),
+            _ => panic!("can't recover enum `{}`: it isn't an alias of an integer type", name),
+        };
+
+        let mut variants = BTreeMap::new();
+        for &(_, const_name, ref init) in &consts {
+            let value = int_value(init).unwrap_or_else(|| {
+                panic!("can't recover enum `{}`: the value of `{}` isn't an integer literal",
+                       name, const_name)
+            });
+            if let Some(other) = variants.insert(value, const_name) {
+                panic!("can't recover enum `{}`: `{}` and `{}` have the same value, {}",
+                       name, other, const_name, value);
+            }
+        }
+        let const_ids = consts.iter().map(|&(did, _, _)| did).collect::<HashSet<_>>();
+
+        // (2) Keep the integer type at the FFI boundary.
+
+        let keep_int_ty = |ty: &mut P<Ty>| {
+            if cx.try_resolve_ty(ty) == Some(alias) {
+                *ty = int_ty.clone();
+            }
+        };
+        FlatMapNodes::visit(krate, |mut fi: ForeignItem| {
+            MutVisitNodes::visit(&mut fi, &keep_int_ty);
+            smallvec![fi]
+        });
+        FlatMapNodes::visit(krate, |mut i: P<Item>| {
+            if let ItemKind::Fn(ref mut sig, _, _) = i.kind {
+                match sig.header.ext {
+                    Extern::None => {}
+                    _ => MutVisitNodes::visit(&mut sig.decl, &keep_int_ty),
+                }
+            }
+            smallvec![i]
+        });
+
+        // (3) Refer to the variants instead of the constants.  Paths to a constant get the enum's
+        // name inserted before the constant's name, except for unqualified paths outside the
+        // enum's module: those use an import of the constant, which now imports the variant.
+
+        let hir_map = cx.hir_map();
+        let alias_mod = hir_map.get_module_parent_node(hir_map.as_local_hir_id(alias).unwrap());
+        fold_resolved_paths_with_id(krate, cx, |id, qself, mut path, res| {
+            match res.get(0) {
+                Some(&Res::Def(DefKind::Const, did)) if const_ids.contains(&did) => {}
+                _ => return (qself, path),
+            }
+            let n = path.segments.len();
+            if n > 1 || hir_map.get_module_parent_node(hir_map.node_to_hir_id(id)) == alias_mod {
+                path.segments.insert(n - 1, mk().path_segment(name));
+            }
+            (qself, path)
+        });
+
+        // (4) Replace the alias and remove the constants.
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            match i.kind {
+                ItemKind::Const(..) if const_ids.contains(&cx.node_def_id(i.id)) => smallvec![],
+                ItemKind::TyAlias(..) if cx.node_def_id(i.id) == alias => {
+                    let variants = variants.iter().map(|(&value, &name)| {
+                        mk().unit_variant(name, Some(int_expr(value)))
+                    }).collect();
+                    smallvec![mk().vis(i.vis.clone()).span(i.span)
+                        .call_attr("repr", vec![repr])
+                        .call_attr("derive", vec!["Copy", "Clone", "PartialEq", "Eq"])
+                        .enum_item(i.ident, variants)]
+                }
+                _ => smallvec![i],
+            }
+        });
+
+        EnumInfo { name, int_ty, variants }
+    }
+}
+
+/// Fixes the type errors caused by replacing the alias with an enum.  Conversions of integers to
+/// the enum that can't be fixed are recorded in `unfixable`.
+struct EnumFixer<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    info: &'a EnumInfo,
+    enum_did: DefId,
+    enum_mod: HirId,
+    unfixable: &'a mut HashSet<Span>,
+    changed: bool,
+}
+
+impl<'a, 'b, 'tcx> EnumFixer<'a, 'b, 'tcx> {
+    fn new(cx: &'a RefactorCtxt<'b, 'tcx>, krate: &Crate, info: &'a EnumInfo,
+           unfixable: &'a mut HashSet<Span>) -> Self {
+        let mut enum_did = None;
+        visit_nodes(krate, |i: &Item| {
+            if let ItemKind::Enum(ref def, _) = i.kind {
+                if i.ident == info.name &&
+                   def.variants.iter().map(|v| v.ident).eq(info.variants.values().cloned()) {
+                    enum_did = Some(cx.node_def_id(i.id));
+                }
+            }
+        });
+        let enum_did = enum_did.expect("recovered enum not found");
+        let hir_map = cx.hir_map();
+        let enum_mod = hir_map.get_module_parent_node(hir_map.as_local_hir_id(enum_did).unwrap());
+        EnumFixer { cx, info, enum_did, enum_mod, unfixable, changed: false }
+    }
+
+    fn is_enum_ty(&self, ty: ty::Ty) -> bool {
+        match ty.kind {
+            ty::TyKind::Adt(def, _) => def.did == self.enum_did,
+            _ => false,
+        }
+    }
+
+    fn is_enum(&self, e: &Expr) -> bool {
+        self.cx.opt_node_type(e.id).map_or(false, |ty| self.is_enum_ty(ty))
+    }
+
+    /// The path to `variant`, for use at the node `site`.
+    fn variant_path(&self, site: NodeId, variant: Ident) -> Path {
+        let hir_map = self.cx.hir_map();
+        let mut path = if site != DUMMY_NODE_ID &&
+                          hir_map.get_module_parent_node(hir_map.node_to_hir_id(site)) ==
+                          self.enum_mod {
+            mk().path(vec![self.info.name])
+        } else {
+            self.cx.def_path(self.enum_did)
+        };
+        path.segments.push(mk().path_segment(variant));
+        path
+    }
+
+    /// Cast the enum value `e` to the integer type `ty`.
+    fn to_int(&mut self, e: &mut P<Expr>, ty: P<Ty>) {
+        *e = mk().cast_expr(e.clone(), ty);
+        self.changed = true;
+    }
+
+    /// Replace the integer `e` by the variant with its value, if it's a literal.
+    fn to_enum(&mut self, e: &mut P<Expr>) {
+        match int_value(e).and_then(|value| self.info.variants.get(&value)) {
+            Some(&variant) => {
+                *e = mk().span(e.span).path_expr(self.variant_path(e.id, variant));
+                self.changed = true;
+            }
+            None => {
+                self.unfixable.insert(e.span);
+            }
+        }
+    }
+
+    /// Convert a `match` on the enum whose patterns are integer literals.
+    fn fix_match(&mut self, scrutinee: &mut P<Expr>, arms: &mut Vec<Arm>) {
+        let convertible = arms.iter().flat_map(|arm| or_pats(&arm.pat)).all(|p| match p.kind {
+            PatKind::Wild => true,
+            PatKind::Lit(ref e) => int_value(e).is_some(),
+            _ => false,
+        });
+        if !convertible || arms.iter().all(|arm| is_wild(&arm.pat)) {
+            return;
+        }
+        // `match c as u32 { ... }` can match on `c` directly.
+        if !self.is_enum(scrutinee) {
+            let inner = match_or!([scrutinee.kind] ExprKind::Cast(ref inner, _) => inner.clone();
+                                  return);
+            if !self.is_enum(&inner) {
+                return;
+            }
+            *scrutinee = inner;
+        }
+
+        let mut covered = HashSet::new();
+        let old_arms = arms.drain(..).collect::<Vec<_>>();
+        for mut arm in old_arms {
+            let mut pats = Vec::new();
+            for p in or_pats(&arm.pat) {
+                let value = match p.kind {
+                    PatKind::Lit(ref e) => int_value(e).unwrap(),
+                    _ => {
+                        pats.push(p.clone());
+                        continue;
+                    }
+                };
+                match self.info.variants.get(&value) {
+                    Some(&variant) => {
+                        if arm.guard.is_none() {
+                            covered.insert(value);
+                        }
+                        let path = self.variant_path(p.id, variant);
+                        pats.push(mk().span(p.span).qpath_pat(None, path));
+                    }
+                    None => self.cx.session().span_warn(
+                        p.span,
+                        &format!("removing pattern: no variant of `{}` has the value {}",
+                                 self.info.name, value),
+                    ),
+                }
+            }
+            if pats.is_empty() {
+                continue;
+            }
+            arm.pat = if pats.len() == 1 {
+                pats.pop().unwrap()
+            } else {
+                P(Pat { id: DUMMY_NODE_ID, kind: PatKind::Or(pats), span: arm.pat.span })
+            };
+            arms.push(arm);
+        }
+
+        if covered.len() == self.info.variants.len() {
+            for arm in arms.iter_mut() {
+                if is_wild(&arm.pat) && arm.guard.is_none() {
+                    arm.body = parse_expr(self.cx.session(), "unreachable!()");
+                }
+            }
+        }
+        self.changed = true;
+    }
+}
+
+impl<'a, 'b, 'tcx> MutVisitor for EnumFixer<'a, 'b, 'tcx> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        let int_ty = self.info.int_ty.clone();
+        let is_enum = self.is_enum(e);
+        match e.kind {
+            ExprKind::Binary(op, ref mut lhs, ref mut rhs) => match op.node {
+                BinOpKind::Eq | BinOpKind::Ne => {
+                    match (self.is_enum(lhs), self.is_enum(rhs)) {
+                        (true, false) if int_value(rhs).is_some() => self.to_enum(rhs),
+                        (true, false) => self.to_int(lhs, int_ty),
+                        (false, true) if int_value(lhs).is_some() => self.to_enum(lhs),
+                        (false, true) => self.to_int(rhs, int_ty),
+                        _ => {}
+                    }
+                }
+                BinOpKind::And | BinOpKind::Or => {}
+                _ => {
+                    if self.is_enum(lhs) {
+                        self.to_int(lhs, int_ty.clone());
+                    }
+                    if self.is_enum(rhs) {
+                        self.to_int(rhs, int_ty);
+                    }
+                }
+            },
+            ExprKind::Unary(UnOp::Neg, ref mut inner) |
+            ExprKind::Unary(UnOp::Not, ref mut inner) if self.is_enum(inner) => {
+                self.to_int(inner, int_ty);
+            }
+            ExprKind::AssignOp(_, ref lhs, ref mut rhs) => {
+                if self.is_enum(lhs) {
+                    self.unfixable.insert(lhs.span);
+                }
+                if self.is_enum(rhs) {
+                    self.to_int(rhs, int_ty);
+                }
+            }
+            ExprKind::Cast(ref inner, _) if is_enum && !self.is_enum(inner) => self.to_enum(e),
+            ExprKind::Match(ref mut scrutinee, ref mut arms) => self.fix_match(scrutinee, arms),
+            _ => {}
+        }
+        mut_visit::noop_visit_expr(e, self);
+    }
+
+    fn visit_local(&mut self, l: &mut P<Local>) {
+        let pat_is_enum = self.cx.opt_node_type(l.pat.id).map_or(false, |ty| self.is_enum_ty(ty));
+        if let Some(ref mut init) = l.init {
+            if pat_is_enum && !self.is_enum(init) {
+                self.to_enum(init);
+            }
+        }
+        mut_visit::noop_visit_local(l, self);
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+impl<'a, 'b, 'tcx> IlltypedFolder<'tcx> for EnumFixer<'a, 'b, 'tcx> {
+    fn fix_expr(&mut self, e: &mut P<Expr>, actual: ty::Ty<'tcx>, expected: ty::Ty<'tcx>) {
+        if self.is_enum_ty(actual) && expected.is_integral() {
+            let ty = reflect::reflect_tcx_ty(self.cx.ty_ctxt(), expected);
+            self.to_int(e, ty);
+        } else if self.is_enum_ty(expected) && !self.is_enum_ty(actual) {
+            self.to_enum(e);
+        }
+    }
+}
+
+/// # `recover_bitflags` Command
+///
+/// Marks: `target`, `flags`
+///
+/// Turn the integer constants marked `target`, which C code uses as bit flags, into a flag type
+/// `NAME`.  The constants must all have the same integer type, and their values must be integer
+/// literals.  `NAME` is a newtype around that integer type, with an associated constant for each
+/// of the constants, named without their common prefix.  Constants that aren't a single bit, like
+/// masks and combinations of other flags, are defined in terms of the single-bit flags where
+/// possible:
+///
+/// ```ignore
+///     pub const MODE_READ: libc::c_uint = 1;
+///     pub const MODE_WRITE: libc::c_uint = 2;
+///     pub const MODE_RW: libc::c_uint = 3;
+/// ```
+///
+/// After running `recover_bitflags Mode`:
+///
+/// ```ignore
+///     #[derive(Copy, Clone, PartialEq, Eq, Default)]
+///     #[repr(transparent)]
+///     pub struct Mode(libc::c_uint);
+///
+///     impl Mode {
+///         pub const READ: Mode = Mode(1);
+///         pub const WRITE: Mode = Mode(2);
+///         pub const RW: Mode = Mode(Mode::READ.0 | Mode::WRITE.0);
+///         ...
+///     }
+/// ```
+///
+/// The type has the methods `empty`, `bits`, `from_bits_retain`, `is_empty`, `contains`,
+/// `intersects`, `insert`, and `remove`, and implements the bitwise operators, like the types
+/// generated by the `bitflags` crate, without depending on it.
+///
+/// Type annotations marked `flags`, such as the types of struct fields, locals, or arguments that
+/// hold flag words, are changed to `NAME`.  Uses of the constants become uses of the associated
+/// constants, and the command then fixes the code that no longer typechecks:
+///
+///  * Tests like `x & FLAG != 0` become `x.contains(NAME::FLAG)`, or `x.intersects(...)` if the
+///    flag has more than one bit, and `x != 0` becomes `!x.is_empty()`.
+///  * `x |= FLAG` and `x &= !FLAG` become `x.insert(NAME::FLAG)` and `x.remove(NAME::FLAG)`.
+///  * Where an integer is still needed, like in calls to foreign functions, arithmetic, or
+///    comparisons with integers, the flags are converted with `.bits()`.
+///  * Integers used as flags are converted with `NAME::from_bits_retain`, or `NAME::empty()` for
+///    zero.
+///
+/// Compound assignments other than `|=`, `&=`, and `^=` to a flag word can't be converted.  They
+/// are reported as warnings and left for the user to fix.
+pub struct RecoverBitflags {
+    name: String,
+}
+
+/// The flag type being recovered.
+struct FlagsInfo {
+    name: Ident,
+    /// The names of the associated constants that have a single bit set.
+    single_bits: HashSet<Symbol>,
+}
+
+impl Command for RecoverBitflags {
+    fn run(&mut self, state: &mut RefactorState) {
+        let info = state.transform_crate(Phase::Phase3, |st, cx| {
+            st.map_krate(|krate| self.replace_consts(krate, st, cx))
+        }).expect("Failed to run compiler");
+
+        let mut unfixable = HashSet::new();
+        state.run_typeck_loop(|krate, _st, cx| {
+            let mut fixer = FlagsFixer::new(cx, krate, &info, &mut unfixable);
+            krate.visit(&mut fixer);
+            if !fixer.changed {
+                fold_illtyped(cx, krate, &mut fixer);
+            }
+            if fixer.changed {
+                TypeckLoopResult::Iterate
+            } else {
+                TypeckLoopResult::Finished
+            }
+        }).expect("Could not retype crate!");
+
+        let mut unfixable = unfixable.into_iter().collect::<Vec<_>>();
+        unfixable.sort();
+        for span in unfixable {
+            state.session().span_warn(
+                span,
+                &format!("can't convert this assignment to use `{}`", info.name),
+            );
+        }
+    }
+}
+
+impl RecoverBitflags {
+    /// Replace the constants by the flag type, and retype the marked type annotations.
+    fn replace_consts(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt)
+                      -> FlagsInfo {
+        let name = mk().ident(&self.name);
+
+        // (1) Collect the marked constants.
+
+        let mut consts = Vec::new();
+        visit_nodes(krate, |i: &Item| {
+            if let ItemKind::Const(ref ty, ref init) = i.kind {
+                if st.marked(i.id, "target") {
+                    consts.push((cx.node_def_id(i.id), i.ident, i.vis.clone(), ty.clone(),
+                                 init.clone()));
+                }
+            }
+        });
+        if consts.is_empty() {
+            panic!("can't recover flags `{}`: no constants are marked", name);
+        }
+
+        let tcx = cx.ty_ctxt();
+        let bits_ty = tcx.type_of(consts[0].0);
+        if !bits_ty.is_integral() ||
+           consts.iter().any(|&(did, _, _, _, _)| tcx.type_of(did) != bits_ty) {
+            panic!("can't recover flags `{}`: the constants don't all have the same integer type",
+                   name);
+        }
+
+        let mut values = Vec::new();
+        for &(_, const_name, _, _, ref init) in &consts {
+            match int_value(init) {
+                Some(value) if value >= 0 => values.push(value as u128),
+                _ => panic!("can't recover flags `{}`: the value of `{}` isn't a non-negative \
+                             integer literal", name, const_name),
+            }
+        }
+
+        let names = flag_names(&consts.iter().map(|c| c.1).collect::<Vec<_>>());
+        let single_bits = names.iter().zip(&values)
+            .filter(|&(_, &value)| value.count_ones() == 1)
+            .map(|(&flag, &value)| (value, flag))
+            .collect::<BTreeMap<_, _>>();
+
+        // (2) Build the flag type.
+
+        let (_, _, ref vis, ref int_ty, _) = consts[0];
+        let vis = pprust::vis_to_string(vis);
+        let int_ty = pprust::ty_to_string(int_ty);
+
+        let mut src = format!("#[derive(Copy, Clone, PartialEq, Eq, Default)]\n\
+                               #[repr(transparent)]\n\
+                               {vis}struct {name}({ty});\n\n\
+                               impl {name} {{\n",
+                              vis = vis, name = name, ty = int_ty);
+        let covered = single_bits.keys().fold(0, |acc, &bit| acc | bit);
+        for (flag, &value) in names.iter().zip(&values) {
+            let def = if value.count_ones() > 1 && value & !covered == 0 {
+                single_bits.iter()
+                    .filter(|&(&bit, _)| value & bit != 0)
+                    .map(|(_, bit_name)| format!("{}::{}.0", name, bit_name))
+                    .collect::<Vec<_>>()
+                    .join(" | ")
+            } else {
+                value.to_string()
+            };
+            src.push_str(&format!("    {}const {}: {} = {}({});\n", vis, flag, name, name, def));
+        }
+        src.push_str(&format!(
+            "    {vis}const fn empty() -> {name} {{ {name}(0) }}\n\
+             {vis}const fn bits(self) -> {ty} {{ self.0 }}\n\
+             {vis}const fn from_bits_retain(bits: {ty}) -> {name} {{ {name}(bits) }}\n\
+             {vis}fn is_empty(self) -> bool {{ self.0 == 0 }}\n\
+             {vis}fn contains(self, other: {name}) -> bool {{ self.0 & other.0 == other.0 }}\n\
+             {vis}fn intersects(self, other: {name}) -> bool {{ self.0 & other.0 != 0 }}\n\
+             {vis}fn insert(&mut self, other: {name}) {{ self.0 |= other.0; }}\n\
+             {vis}fn remove(&mut self, other: {name}) {{ self.0 &= !other.0; }}\n\
+             }}\n",
+            vis = vis, name = name, ty = int_ty));
+        for &(trait_name, method, op) in &[("BitOr", "bitor", "|"),
+                                           ("BitAnd", "bitand", "&"),
+                                           ("BitXor", "bitxor", "^")] {
+            src.push_str(&format!(
+                "impl ::std::ops::{tr} for {name} {{\n\
+                     type Output = {name};\n\
+                     fn {method}(self, other: {name}) -> {name} {{ {name}(self.0 {op} other.0) }}\n\
+                 }}\n\
+                 impl ::std::ops::{tr}Assign for {name} {{\n\
+                     fn {method}_assign(&mut self, other: {name}) {{ self.0 {op}= other.0; }}\n\
+                 }}\n",
+                tr = trait_name, name = name, method = method, op = op));
+        }
+        src.push_str(&format!(
+            "impl ::std::ops::Not for {name} {{\n\
+                 type Output = {name};\n\
+                 fn not(self) -> {name} {{ {name}(!self.0) }}\n\
+             }}\n",
+            name = name));
+        let mut new_items = Some(st.parse_items(cx, &src));
+
+        // (3) Retype the marked type annotations.  The flag type is defined in the module of the
+        // first constant, so it's referred to by name there, and by absolute path elsewhere.
+
+        let hir_map = cx.hir_map();
+        let flags_mod = hir_map.get_module_parent_node(hir_map.as_local_hir_id(consts[0].0)
+                                                       .unwrap());
+        let flags_path = |site: NodeId| {
+            if hir_map.get_module_parent_node(hir_map.node_to_hir_id(site)) == flags_mod {
+                mk().path(vec![name])
+            } else {
+                let mut path = cx.def_path(consts[0].0);
+                path.segments.last_mut().unwrap().ident = name;
+                path
+            }
+        };
+
+        MutVisitNodes::visit(krate, |ty: &mut P<Ty>| {
+            if st.marked(ty.id, "flags") {
+                *ty = mk().path_ty(flags_path(ty.id));
+            }
+        });
+
+        // (4) Refer to the associated constants instead of the constants.  Imports of the
+        // constants import the flag type instead.
+
+        let flags = consts.iter().map(|c| c.0).zip(names.iter().cloned())
+            .collect::<HashMap<_, _>>();
+        fold_resolved_paths_with_id(krate, cx, |id, qself, mut path, res| {
+            let flag = match res.get(0) {
+                Some(&Res::Def(DefKind::Const, did)) => match_or!([flags.get(&did)] Some(&x) => x;
+                                                                  return (qself, path)),
+                _ => return (qself, path),
+            };
+            let is_use = match hir_map.find(id) {
+                Some(hir::Node::Item(i)) => match i.kind {
+                    hir::ItemKind::Use(..) => true,
+                    _ => false,
+                },
+                _ => false,
+            };
+            if is_use {
+                path.segments.last_mut().unwrap().ident = name;
+            } else {
+                path = flags_path(id);
+                path.segments.push(mk().path_segment(flag));
+            }
+            (qself, path)
+        });
+        MutVisitNodes::visit(krate, |m: &mut Mod| {
+            let mut seen = HashSet::new();
+            m.items.retain(|i| match i.kind {
+                ItemKind::Use(..) => seen.insert(pprust::item_to_string(i)),
+                _ => true,
+            });
+        });
+
+        // (5) Put the flag type in place of the first constant, and remove the others.
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            match i.kind {
+                ItemKind::Const(..) if flags.contains_key(&cx.node_def_id(i.id)) => {
+                    new_items.take().map_or_else(SmallVec::new, |items| {
+                        items.into_iter().collect()
+                    })
+                }
+                _ => smallvec![i],
+            }
+        });
+
+        FlagsInfo {
+            name,
+            single_bits: single_bits.values().map(|flag| flag.name).collect(),
+        }
+    }
+}
+
+/// The names of the associated constants for the constants `names`: the names without their
+/// common prefix, up to the last `_` in it.  If that would leave any name empty or starting with a
+/// digit, the names are kept as they are.
+fn flag_names(names: &[Ident]) -> Vec<Ident> {
+    let strs = names.iter().map(|n| n.as_str().to_string()).collect::<Vec<_>>();
+    let first = &strs[0];
+    let common = strs.iter().fold(first.len(), |len, s| {
+        first.bytes().zip(s.bytes()).take(len).take_while(|&(a, b)| a == b).count()
+    });
+    let prefix_len = first[..common].rfind('_').map_or(0, |i| i + 1);
+    let usable = strs.iter().all(|s| {
+        s[prefix_len..].chars().next().map_or(false, |c| !c.is_ascii_digit())
+    });
+    if !usable {
+        return names.to_owned();
+    }
+    strs.iter().zip(names).map(|(s, n)| Ident::new(Symbol::intern(&s[prefix_len..]), n.span))
+        .collect()
+}
+
+/// Fixes the type errors caused by changing flag words to the flag type.  Assignments that can't
+/// be fixed are recorded in `unfixable`.
+struct FlagsFixer<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    info: &'a FlagsInfo,
+    flags_did: DefId,
+    flags_mod: HirId,
+    bits_ty: ty::Ty<'tcx>,
+    unfixable: &'a mut HashSet<Span>,
+    changed: bool,
+}
+
+impl<'a, 'b, 'tcx> FlagsFixer<'a, 'b, 'tcx> {
+    fn new(cx: &'a RefactorCtxt<'b, 'tcx>, krate: &Crate, info: &'a FlagsInfo,
+           unfixable: &'a mut HashSet<Span>) -> Self {
+        let mut flags_did = None;
+        visit_nodes(krate, |i: &Item| {
+            if let ItemKind::Struct(VariantData::Tuple(ref fields, _), _) = i.kind {
+                if i.ident == info.name && fields.len() == 1 {
+                    flags_did = Some(cx.node_def_id(i.id));
+                }
+            }
+        });
+        let flags_did = flags_did.expect("recovered flag type not found");
+        let tcx = cx.ty_ctxt();
+        let bits_ty = match tcx.type_of(flags_did).kind {
+            ty::TyKind::Adt(def, substs) => def.non_enum_variant().fields[0].ty(tcx, substs),
+            _ => panic!("recovered flag type isn't a struct"),
+        };
+        let hir_map = cx.hir_map();
+        let flags_mod = hir_map.get_module_parent_node(hir_map.as_local_hir_id(flags_did)
+                                                       .unwrap());
+        FlagsFixer { cx, info, flags_did, flags_mod, bits_ty, unfixable, changed: false }
+    }
+
+    fn is_flags_ty(&self, ty: ty::Ty) -> bool {
+        match ty.kind {
+            ty::TyKind::Adt(def, _) => def.did == self.flags_did,
+            _ => false,
+        }
+    }
+
+    fn is_flags(&self, e: &Expr) -> bool {
+        self.cx.opt_node_type(e.id).map_or(false, |ty| self.is_flags_ty(ty))
+    }
+
+    /// The path to the associated item `name` of the flag type, for use at the node `site`.
+    fn flags_path(&self, site: NodeId, name: &str) -> Path {
+        let hir_map = self.cx.hir_map();
+        let mut path = if site != DUMMY_NODE_ID &&
+                          hir_map.get_module_parent_node(hir_map.node_to_hir_id(site)) ==
+                          self.flags_mod {
+            mk().path(vec![self.info.name])
+        } else {
+            self.cx.def_path(self.flags_did)
+        };
+        path.segments.push(mk().path_segment(name));
+        path
+    }
+
+    /// Convert the flags `e` to the integer type `ty`, or to the type of the bits if `ty` is
+    /// `None`.
+    fn to_bits(&mut self, e: &mut P<Expr>, ty: Option<ty::Ty<'tcx>>) {
+        *e = mk().method_call_expr(e.clone(), "bits", Vec::<P<Expr>>::new());
+        if let Some(ty) = ty.filter(|&ty| ty != self.bits_ty) {
+            *e = mk().cast_expr(e.clone(), reflect::reflect_tcx_ty(self.cx.ty_ctxt(), ty));
+        }
+        self.changed = true;
+    }
+
+    /// Convert the integer `e` to flags.
+    fn to_flags(&mut self, e: &mut P<Expr>) {
+        let site = e.id;
+        *e = if int_value(e) == Some(0) {
+            mk().call_expr(mk().path_expr(self.flags_path(site, "empty")), Vec::<P<Expr>>::new())
+        } else {
+            let mut bits = e.clone();
+            let is_literal = match e.kind {
+                ExprKind::Lit(..) => true,
+                _ => false,
+            };
+            if !is_literal && self.cx.opt_node_type(e.id).map_or(false, |ty| ty != self.bits_ty) {
+                bits = mk().cast_expr(bits, reflect::reflect_tcx_ty(self.cx.ty_ctxt(),
+                                                                      self.bits_ty));
+            }
+            mk().call_expr(mk().path_expr(self.flags_path(site, "from_bits_retain")),
+                           vec![bits])
+        };
+        self.changed = true;
+    }
+
+    /// Is `e` one of the associated constants with a single bit set?
+    fn is_single_bit(&self, e: &Expr) -> bool {
+        let path = match_or!([e.kind] ExprKind::Path(None, ref path) => path; return false);
+        let n = path.segments.len();
+        n >= 2 && path.segments[n - 2].ident == self.info.name &&
+            self.info.single_bits.contains(&path.segments[n - 1].ident.name)
+    }
+
+    /// Build a test of whether the flags `e` are zero (if `is_eq`) or nonzero.
+    fn zero_test(&self, e: &P<Expr>, is_eq: bool) -> P<Expr> {
+        let e = strip_parens(e);
+        let (test, negate) = match e.kind {
+            ExprKind::Binary(op, ref lhs, ref rhs)
+                    if op.node == BinOpKind::BitAnd && self.is_flags(lhs) && self.is_flags(rhs) => {
+                let method = if self.is_single_bit(rhs) { "contains" } else { "intersects" };
+                (mk().method_call_expr(lhs.clone(), method, vec![rhs.clone()]), is_eq)
+            }
+            _ => (mk().method_call_expr(e.clone(), "is_empty", Vec::<P<Expr>>::new()), !is_eq),
+        };
+        if negate {
+            mk().unary_expr(UnOp::Not, test)
+        } else {
+            test
+        }
+    }
+}
+
+impl<'a, 'b, 'tcx> MutVisitor for FlagsFixer<'a, 'b, 'tcx> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        let mut new_expr = None;
+        match e.kind {
+            ExprKind::Binary(op, ref mut lhs, ref mut rhs) => match op.node {
+                BinOpKind::Eq | BinOpKind::Ne => {
+                    let is_eq = op.node == BinOpKind::Eq;
+                    match (self.is_flags(lhs), self.is_flags(rhs)) {
+                        (true, false) if int_value(rhs) == Some(0) => {
+                            new_expr = Some(self.zero_test(lhs, is_eq));
+                        }
+                        (false, true) if int_value(lhs) == Some(0) => {
+                            new_expr = Some(self.zero_test(rhs, is_eq));
+                        }
+                        (true, false) => self.to_bits(lhs, None),
+                        (false, true) => self.to_bits(rhs, None),
+                        _ => {}
+                    }
+                }
+                BinOpKind::BitAnd | BinOpKind::BitOr | BinOpKind::BitXor => {
+                    match (self.is_flags(lhs), self.is_flags(rhs)) {
+                        (true, false) => self.to_flags(rhs),
+                        (false, true) => self.to_flags(lhs),
+                        _ => {}
+                    }
+                }
+                BinOpKind::And | BinOpKind::Or => {}
+                _ => {
+                    if self.is_flags(lhs) {
+                        self.to_bits(lhs, None);
+                    }
+                    if self.is_flags(rhs) {
+                        self.to_bits(rhs, None);
+                    }
+                }
+            },
+            ExprKind::Unary(UnOp::Neg, ref mut inner) if self.is_flags(inner) => {
+                self.to_bits(inner, None);
+            }
+            ExprKind::AssignOp(op, ref lhs, ref mut rhs) if self.is_flags(lhs) => {
+                match op.node {
+                    BinOpKind::BitOr if self.is_flags(rhs) => {
+                        new_expr = Some(mk().method_call_expr(lhs.clone(), "insert",
+                                                              vec![rhs.clone()]));
+                    }
+                    BinOpKind::BitAnd => {
+                        let removed = match rhs.kind {
+                            ExprKind::Unary(UnOp::Not, ref flag) if self.is_flags(flag) => {
+                                Some(flag.clone())
+                            }
+                            _ => None,
+                        };
+                        if let Some(flag) = removed {
+                            new_expr = Some(mk().method_call_expr(lhs.clone(), "remove",
+                                                                  vec![flag]));
+                        } else if !self.is_flags(rhs) {
+                            self.to_flags(rhs);
+                        }
+                    }
+                    BinOpKind::BitOr | BinOpKind::BitXor if !self.is_flags(rhs) => {
+                        self.to_flags(rhs);
+                    }
+                    BinOpKind::BitOr | BinOpKind::BitXor => {}
+                    _ => {
+                        self.unfixable.insert(e.span);
+                    }
+                }
+            }
+            ExprKind::AssignOp(_, _, ref mut rhs) if self.is_flags(rhs) => {
+                self.to_bits(rhs, None);
+            }
+            ExprKind::Cast(ref mut inner, _) if self.is_flags(inner) => {
+                self.to_bits(inner, None);
+            }
+            _ => {}
+        }
+        if let Some(new_expr) = new_expr {
+            *e = new_expr;
+            self.changed = true;
+        }
+        mut_visit::noop_visit_expr(e, self);
+    }
+
+    fn visit_local(&mut self, l: &mut P<Local>) {
+        let pat_is_flags = self.cx.opt_node_type(l.pat.id).map_or(false, |ty| self.is_flags_ty(ty));
+        if let Some(ref mut init) = l.init {
+            if pat_is_flags && !self.is_flags(init) {
+                self.to_flags(init);
+            }
+        }
+        mut_visit::noop_visit_local(l, self);
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+impl<'a, 'b, 'tcx> IlltypedFolder<'tcx> for FlagsFixer<'a, 'b, 'tcx> {
+    fn fix_expr(&mut self, e: &mut P<Expr>, actual: ty::Ty<'tcx>, expected: ty::Ty<'tcx>) {
+        if self.is_flags_ty(actual) && expected.is_integral() {
+            self.to_bits(e, Some(expected));
+        } else if self.is_flags_ty(expected) && actual.is_integral() {
+            self.to_flags(e);
+        }
+    }
+}
+
+fn is_wild(p: &Pat) -> bool {
+    match p.kind {
+        PatKind::Wild => true,
+        _ => false,
+    }
+}
+
+/// The alternatives of the pattern `p`.
+fn or_pats(p: &P<Pat>) -> Vec<&P<Pat>> {
+    match p.kind {
+        PatKind::Or(ref pats) => pats.iter().collect(),
+        _ => vec![p],
+    }
+}
+
+fn strip_parens(e: &P<Expr>) -> &P<Expr> {
+    match e.kind {
+        ExprKind::Paren(ref inner) => strip_parens(inner),
+        _ => e,
+    }
+}
+
+/// The value of `e`, if it's an integer literal, possibly negated or cast.
+pub(crate) fn int_value(e: &Expr) -> Option<i128> {
+    match e.kind {
+        ExprKind::Lit(Lit { kind: LitKind::Int(i, _), .. }) => Some(i as i128),
+        ExprKind::Unary(UnOp::Neg, ref e) => int_value(e).map(|i| -i),
+        ExprKind::Paren(ref e) | ExprKind::Cast(ref e, _) => int_value(e),
+        _ => None,
+    }
+}
+
+/// Build an integer literal expression with the value `value`.
+pub(crate) fn int_expr(value: i128) -> P<Expr> {
+    let lit = mk().lit_expr(mk().int_lit(value.abs() as u128, LitIntType::Unsuffixed));
+    if value < 0 {
+        mk().unary_expr(UnOp::Neg, lit)
+    } else {
+        lit
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    let spec = CommandSpec::new("Turn a translated C enum back into a Rust enum.")
+        .optional_arg("NAME", ArgType::Str, None, "the type alias of the enum's constants");
+    reg.register("recover_enum", spec, |args| {
+        Ok(Box::new(RecoverEnum {
+            name: args.opt_str("NAME").map(|s| s.to_owned()),
+        }))
+    });
+
+    let spec = CommandSpec::new("Turn the bit flag constants marked `target` into a flag type.")
+        .arg("NAME", ArgType::Str, "the name of the flag type");
+    reg.register("recover_bitflags", spec, |args| {
+        Ok(Box::new(RecoverBitflags {
+            name: args.str("NAME").to_owned(),
+        }))
+    });
+}
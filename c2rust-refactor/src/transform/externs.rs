@@ -1,17 +1,25 @@
 use std::collections::{HashMap, HashSet};
+use std::mem;
+use indexmap::IndexMap;
 use log::Level;
+use rustc::hir::HirId;
 use rustc::hir::def::DefKind;
-use rustc::hir::def_id::{DefId};
+use rustc::hir::def_id::{DefId, CRATE_DEF_INDEX};
 use rustc::ty::{Instance, TyCtxt, TyKind, Ty};
+use smallvec::smallvec;
 use syntax::ast::*;
+use syntax::print::pprust;
 use syntax::ptr::P;
+use syntax::symbol::Symbol;
+use syntax_pos::Span;
 
 use c2rust_ast_builder::mk;
-use crate::ast_manip::{MutVisitNodes, visit_nodes};
+use crate::ast_manip::{FlatMapNodes, ListNodeIds, MutVisitNodes, visit_nodes};
 use crate::command::{CommandState, Registry};
+use crate::command_args::{ArgType, CommandSpec};
 use crate::driver::{Phase};
 use crate::path_edit::fold_resolved_paths_with_id;
-use crate::reflect::Reflector;
+use crate::reflect::{Reflector, reflect_tcx_ty};
 use crate::resolve;
 use crate::transform::Transform;
 use crate::RefactorCtxt;
@@ -198,8 +206,6 @@ fn make_cast<'a, 'tcx>(
 
 /// # `canonicalize_externs` Command
 /// 
-/// Usage: `canonicalize_externs MOD_PATH`
-/// 
 /// Marks: `target`
 /// 
 /// Replace foreign items ("externs") with references to externs
@@ -307,9 +313,559 @@ impl Transform for CanonicalizeExterns {
 }
 
 
+/// # `wrap_variadic_calls` Command
+///
+/// Replace calls to variadic foreign functions, like `open` or `fcntl`, with calls to
+/// non-variadic wrapper functions.  For each variadic function declared in the crate, one wrapper
+/// is generated for each combination of argument types passed in place of the `...`, and placed
+/// right after the `extern` block that declares the function, so that all the untyped calls are
+/// in one place.  The wrappers are named after the function and their number of arguments:
+///
+/// ```ignore
+///     pub unsafe fn open3(path: *const libc::c_char, oflag: libc::c_int, vararg0: u32)
+///                         -> libc::c_int {
+///         open(path, oflag, vararg0)
+///     }
+/// ```
+///
+/// Calls whose variadic arguments differ only in the widths or signedness of integer arguments
+/// share a wrapper, which takes the widest of the integer types, and the narrower arguments are
+/// cast to it.  If a name is already taken, for example because two calls with the same number
+/// of arguments pass different types, a suffix like `_1` is added.
+pub struct WrapVariadicCalls;
+
+/// A non-variadic wrapper for a variadic foreign function.
+struct VariadicWrapper<'tcx> {
+    name: String,
+    /// The types of the arguments passed in place of the `...`.
+    arg_tys: Vec<Ty<'tcx>>,
+}
+
+impl Transform for WrapVariadicCalls {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+        let hir_map = cx.hir_map();
+
+        // (1) Find the variadic foreign functions and their numbers of fixed arguments, and the
+        // names that are already taken in each module.
+
+        let mut variadic_fns = HashMap::new();
+        let mut module_names: HashMap<HirId, HashSet<Name>> = HashMap::new();
+        visit_nodes(krate, |fi: &ForeignItem| {
+            let module = hir_map.get_module_parent_node(hir_map.node_to_hir_id(fi.id));
+            module_names.entry(module).or_default().insert(fi.ident.name);
+            if let ForeignItemKind::Fn(ref decl, _) = fi.kind {
+                if decl.c_variadic() {
+                    variadic_fns.insert(cx.node_def_id(fi.id), (decl.inputs.len() - 1, module));
+                }
+            }
+        });
+        visit_nodes(krate, |i: &Item| {
+            if let Some(hir_id) = hir_map.opt_node_to_hir_id(i.id) {
+                let module = hir_map.get_module_parent_node(hir_id);
+                module_names.entry(module).or_default().insert(i.ident.name);
+            }
+        });
+
+        // (2) Group the calls by the types of their variadic arguments.
+
+        let mut wrappers: HashMap<DefId, Vec<VariadicWrapper>> = HashMap::new();
+        let mut call_wrappers = HashMap::new();
+        visit_nodes(krate, |e: &Expr| {
+            let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return);
+            let did = match_or!([cx.opt_callee(e)] Some(x) => x; return);
+            let &(fixed, _) = match_or!([variadic_fns.get(&did)] Some(x) => x; return);
+            let arg_tys = args[fixed..].iter()
+                .map(|arg| cx.opt_adjusted_node_type(arg.id))
+                .collect::<Option<Vec<_>>>();
+            let arg_tys = match_or!([arg_tys] Some(x) => x; return);
+
+            let fn_wrappers = wrappers.entry(did).or_default();
+            let idx = fn_wrappers.iter().position(|w| {
+                w.arg_tys.len() == arg_tys.len() &&
+                    w.arg_tys.iter().zip(&arg_tys).all(|(&a, &b)| {
+                        a == b || (a.is_integral() && b.is_integral())
+                    })
+            });
+            let idx = match idx {
+                Some(idx) => {
+                    let w = &mut fn_wrappers[idx];
+                    for (w_ty, &ty) in w.arg_tys.iter_mut().zip(&arg_tys) {
+                        if int_width(tcx, ty) > int_width(tcx, *w_ty) {
+                            *w_ty = ty;
+                        }
+                    }
+                    idx
+                }
+                None => {
+                    fn_wrappers.push(VariadicWrapper { name: String::new(), arg_tys });
+                    fn_wrappers.len() - 1
+                }
+            };
+            call_wrappers.insert(e.id, (did, idx));
+        });
+
+        if wrappers.is_empty() {
+            return;
+        }
+
+        // (3) Name the wrappers.
+
+        let mut dids = wrappers.keys().cloned().collect::<Vec<_>>();
+        dids.sort_by_key(|&did| tcx.def_span(did));
+        for did in dids {
+            let (fixed, module) = variadic_fns[&did];
+            let names = module_names.entry(module).or_default();
+            for w in wrappers.get_mut(&did).unwrap() {
+                let base = format!("{}{}", tcx.item_name(did), fixed + w.arg_tys.len());
+                let mut name = base.clone();
+                let mut suffix = 0;
+                while names.contains(&Symbol::intern(&name)) {
+                    suffix += 1;
+                    name = format!("{}_{}", base, suffix);
+                }
+                names.insert(Symbol::intern(&name));
+                w.name = name;
+            }
+        }
+
+        // (4) Call the wrappers, casting integer arguments to the wrappers' argument types.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let &(did, idx) = match_or!([call_wrappers.get(&e.id)] Some(x) => x; return);
+            let w = &wrappers[&did][idx];
+            let (fixed, _) = variadic_fns[&did];
+            let (callee, args) = expect!([e.kind]
+                                         ExprKind::Call(ref mut f, ref mut args) => (f, args));
+
+            let (qself, mut path) = cx.def_qpath(did);
+            path.segments.last_mut().unwrap().ident = Ident::from_str(&w.name);
+            *callee = mk().span(callee.span).qpath_expr(qself, path);
+
+            for (arg, &ty) in args[fixed..].iter_mut().zip(&w.arg_tys) {
+                if cx.opt_adjusted_node_type(arg.id) != Some(ty) {
+                    *arg = mk().span(arg.span).cast_expr(arg.clone(), reflect_tcx_ty(tcx, ty));
+                }
+            }
+        });
+
+        // (5) Add the wrappers after the `extern` blocks that declare the functions.
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let mut src = String::new();
+            if let ItemKind::ForeignMod(ref fm) = i.kind {
+                for fi in &fm.items {
+                    let fn_wrappers = match_or!([wrappers.get(&cx.node_def_id(fi.id))]
+                                                Some(x) => x; continue);
+                    for w in fn_wrappers {
+                        src.push_str(&variadic_wrapper_src(tcx, fi, w));
+                    }
+                }
+            }
+            if src.is_empty() {
+                return smallvec![i];
+            }
+            let mut items = smallvec![i];
+            items.extend(st.parse_items(cx, &src));
+            items
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Get the width in bits of the integer type `ty`.
+fn int_width(tcx: TyCtxt, ty: Ty) -> Option<u64> {
+    let pointer_width = tcx.data_layout.pointer_size.bits();
+    match ty.kind {
+        TyKind::Int(ity) => Some(ity.bit_width().map_or(pointer_width, |w| w as u64)),
+        TyKind::Uint(uty) => Some(uty.bit_width().map_or(pointer_width, |w| w as u64)),
+        _ => None,
+    }
+}
+
+/// Generate the source of the wrapper `w` for the variadic foreign function `fi`.
+fn variadic_wrapper_src<'tcx>(tcx: TyCtxt<'tcx>, fi: &ForeignItem, w: &VariadicWrapper<'tcx>)
+                              -> String {
+    let decl = expect!([fi.kind] ForeignItemKind::Fn(ref decl, _) => decl);
+    let fixed = &decl.inputs[..decl.inputs.len() - 1];
+
+    let mut params = Vec::new();
+    let mut args = Vec::new();
+    for (i, param) in fixed.iter().enumerate() {
+        let name = match param.pat.kind {
+            PatKind::Ident(_, ident, None) => ident.to_string(),
+            _ => format!("arg{}", i),
+        };
+        params.push(format!("{}: {}", name, pprust::ty_to_string(&param.ty)));
+        args.push(name);
+    }
+    for (i, &ty) in w.arg_tys.iter().enumerate() {
+        let name = format!("vararg{}", i);
+        params.push(format!("{}: {}", name, pprust::ty_to_string(&reflect_tcx_ty(tcx, ty))));
+        args.push(name);
+    }
+    let ret = match decl.output {
+        FunctionRetTy::Ty(ref ty) => format!(" -> {}", pprust::ty_to_string(ty)),
+        FunctionRetTy::Default(_) => String::new(),
+    };
+
+    format!("{vis}unsafe fn {name}({params}){ret} {{\n    {callee}({args})\n}}\n\n",
+            vis = pprust::vis_to_string(&fi.vis), name = w.name, params = params.join(", "),
+            ret = ret, callee = fi.ident, args = args.join(", "))
+}
+
+
+/// # `dedup_extern_decls` Command
+///
+/// Merge the foreign `fn`s and `static`s that several modules declare for the same symbol, like
+/// the copy of `malloc` that every transpiled file declares, into a single declaration in the
+/// module at `MOD_PATH`, and replace all uses of the copies with uses of that declaration.
+///
+/// The declarations of each symbol are grouped by signature, and the signature shared by the
+/// most declarations becomes the canonical one.  `fn` declarations whose argument and return
+/// types differ from it only in integer, float or raw pointer types are merged as well, and
+/// casts are inserted at their call sites, as in `canonicalize_externs`.  Declarations that
+/// can't be reconciled this way are left in place and listed in the report, together with the
+/// location of the canonical declaration.
+///
+/// `#[repr(C)]` structs and unions with the same name and identical definitions are merged the
+/// same way, so that declarations mentioning different copies of a struct can be merged too.
+///
+/// Moved items become `pub`, and paths inside them to other items of the crate are made
+/// absolute, so they still resolve from `MOD_PATH`.  Once done, prints how many declarations
+/// were merged or left in place.  If `-o REPORT` is passed, their source locations are also
+/// written to the `REPORT` file as JSON.
+pub struct DedupExternDecls {
+    path: String,
+    report_path: Option<String>,
+}
+
+/// A foreign `fn` or `static` found by `dedup_extern_decls`.
+struct ExternDecl {
+    def_id: DefId,
+    span: Span,
+}
+
+impl Transform for DedupExternDecls {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+        let ty_compare = TypeCompare::new(cx);
+        st.start_report("dedup_extern_decls", self.report_path.clone());
+
+        let dest_path = self.path.split("::").map(|s| Ident::from_str(s)).collect::<Vec<_>>();
+        let dest = resolve::resolve_absolute(tcx, &dest_path).def_id();
+        let in_dest = |did: DefId| tcx.parent(did) == Some(dest);
+
+        // (1) Merge duplicated `#[repr(C)]` structs.
+
+        let mut struct_groups: IndexMap<Symbol, Vec<P<Item>>> = IndexMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if !matches!([i.kind] ItemKind::Struct(..), ItemKind::Union(..)) {
+                return;
+            }
+            if tcx.adt_def(cx.node_def_id(i.id)).repr.c() {
+                struct_groups.entry(i.ident.name).or_insert_with(Vec::new).push(P(i.clone()));
+            }
+        });
+
+        // Map from each removed definition to the one replacing it
+        let mut replace_map: HashMap<DefId, DefId> = HashMap::new();
+        // Definitions that move to the destination module
+        let mut moved: HashSet<DefId> = HashSet::new();
+
+        for group in struct_groups.values().filter(|g| g.len() > 1) {
+            let canon = group.iter()
+                .find(|i| in_dest(cx.node_def_id(i.id)))
+                .unwrap_or(&group[0]);
+            let canon_did = cx.node_def_id(canon.id);
+            for i in group {
+                let did = cx.node_def_id(i.id);
+                if did == canon_did {
+                    continue;
+                }
+                if ty_compare.compatible_types(canon, i) {
+                    replace_map.insert(did, canon_did);
+                    st.report(cx, "struct merged", i.span);
+                } else {
+                    st.report_related(cx, "conflicting struct kept", i.span, canon.span);
+                }
+            }
+            if !in_dest(canon_did) && replace_map.values().any(|&did| did == canon_did) {
+                moved.insert(canon_did);
+            }
+        }
+
+        // (2) Merge foreign items with the same symbol.
+
+        let mut decl_groups = IndexMap::new();
+        visit_nodes(krate, |fi: &ForeignItem| {
+            let did = cx.node_def_id(fi.id);
+            if !is_foreign_symbol(tcx, did) {
+                return;
+            }
+            let sym = tcx.symbol_name(Instance::new(did, tcx.intern_substs(&[]))).name;
+            decl_groups.entry(sym).or_insert_with(Vec::new)
+                .push(ExternDecl { def_id: did, span: fi.span });
+        });
+
+        for group in decl_groups.values().filter(|g| g.len() > 1) {
+            // Group the declarations by signature, and pick the most common one
+            let mut classes: Vec<Vec<&ExternDecl>> = Vec::new();
+            for decl in group {
+                match classes.iter_mut()
+                    .find(|c| compatible_decls(tcx, &ty_compare, c[0].def_id, decl.def_id))
+                {
+                    Some(class) => class.push(decl),
+                    None => classes.push(vec![decl]),
+                }
+            }
+            let mut canon_class = 0;
+            for (i, class) in classes.iter().enumerate() {
+                if class.len() > classes[canon_class].len() {
+                    canon_class = i;
+                }
+            }
+            let canon = classes[canon_class].iter()
+                .find(|d| in_dest(d.def_id))
+                .unwrap_or(&classes[canon_class][0]);
+
+            for (i, class) in classes.iter().enumerate() {
+                let category = if i == canon_class {
+                    "declaration merged"
+                } else if castable_decls(tcx, &ty_compare, canon.def_id, class[0].def_id) {
+                    "declaration merged with casts"
+                } else {
+                    for decl in class {
+                        st.report_related(cx, "conflicting declaration kept", decl.span, canon.span);
+                    }
+                    continue;
+                };
+                for decl in class.iter().filter(|d| d.def_id != canon.def_id) {
+                    replace_map.insert(decl.def_id, canon.def_id);
+                    st.report(cx, category, decl.span);
+                }
+            }
+            if !in_dest(canon.def_id) && replace_map.values().any(|&did| did == canon.def_id) {
+                moved.insert(canon.def_id);
+            }
+        }
+
+        if replace_map.is_empty() {
+            return;
+        }
+
+        // Impls of removed structs go away with them
+        let mut dead_impls = HashSet::new();
+        visit_nodes(krate, |i: &Item| {
+            if let ItemKind::Impl(_, _, _, _, _, ref ty, _) = i.kind {
+                if cx.try_resolve_ty(ty).map_or(false, |did| replace_map.contains_key(&did)) {
+                    dead_impls.insert(i.id);
+                }
+            }
+        });
+
+        // Nodes inside the items that are moving, whose relative paths would break
+        let mut moved_nodes = HashSet::new();
+        visit_nodes(krate, |i: &Item| {
+            if moved.contains(&cx.node_def_id(i.id)) {
+                moved_nodes.extend(i.list_node_ids());
+            }
+        });
+        visit_nodes(krate, |fi: &ForeignItem| {
+            if moved.contains(&cx.node_def_id(fi.id)) {
+                moved_nodes.extend(fi.list_node_ids());
+            }
+        });
+
+        // (3) Rewrite uses of the removed and moved definitions.
+
+        let dest_path = cx.def_path(dest);
+        let mut new_paths = HashMap::new();
+        for &did in &moved {
+            let mut path = dest_path.clone();
+            path.segments.push(mk().path_segment(tcx.item_name(did)));
+            new_paths.insert(did, (None, path));
+        }
+        for (&old_did, &new_did) in &replace_map {
+            let new_path = new_paths.get(&new_did).cloned()
+                .unwrap_or_else(|| cx.def_qpath(new_did));
+            new_paths.insert(old_did, new_path);
+        }
+
+        let mut path_ids = HashMap::new();
+        fold_resolved_paths_with_id(krate, cx, |id, qself, path, def| {
+            let did = match_or!([def[0].opt_def_id()] Some(x) => x; return (qself, path));
+            if let Some(new_path) = new_paths.get(&did) {
+                if replace_map.contains_key(&did) {
+                    path_ids.insert(id, did);
+                }
+                return new_path.clone();
+            }
+            if did.is_local() && moved_nodes.contains(&id) {
+                return cx.def_qpath(did);
+            }
+            (qself, path)
+        });
+
+        fix_users(krate, &replace_map, &path_ids, &new_paths, cx);
+
+        // (4) Take the removed and moved items out of their modules.
+
+        let mut moved_items = Vec::new();
+        let mut moved_foreign = Vec::new();
+        FlatMapNodes::visit(krate, |mut i: P<Item>| {
+            if dead_impls.contains(&i.id) {
+                return smallvec![];
+            }
+            let did = cx.node_def_id(i.id);
+            if replace_map.contains_key(&did) {
+                return smallvec![];
+            }
+            if moved.contains(&did) {
+                moved_items.push(i);
+                return smallvec![];
+            }
+
+            if let ItemKind::ForeignMod(ref mut fm) = i.kind {
+                let old_len = fm.items.len();
+                for fi in mem::replace(&mut fm.items, Vec::new()) {
+                    let did = cx.node_def_id(fi.id);
+                    if moved.contains(&did) {
+                        moved_foreign.push((fm.abi, fi));
+                    } else if !replace_map.contains_key(&did) {
+                        fm.items.push(fi);
+                    }
+                }
+                if fm.items.is_empty() && old_len > 0 {
+                    return smallvec![];
+                }
+            }
+            smallvec![i]
+        });
+
+        // (5) Add the moved items to the destination module.
+
+        let mut add_items = |m: &mut Mod| {
+            for mut i in moved_items.drain(..) {
+                i.vis = mk().vis("pub");
+                m.items.push(i);
+            }
+            for (abi, mut fi) in moved_foreign.drain(..) {
+                fi.vis = mk().vis("pub");
+                let abi_name = abi.map(|a| a.symbol);
+                let block = m.items.iter_mut().find_map(|i| match i.kind {
+                    ItemKind::ForeignMod(ref mut fm) if fm.abi.map(|a| a.symbol) == abi_name => {
+                        Some(fm)
+                    }
+                    _ => None,
+                });
+                match block {
+                    Some(fm) => fm.items.push(fi),
+                    None => {
+                        let mut block = mk().foreign_items(vec![fi]);
+                        if let ItemKind::ForeignMod(ref mut fm) = block.kind {
+                            fm.abi = abi;
+                        }
+                        m.items.push(block);
+                    }
+                }
+            }
+        };
+        if dest.index == CRATE_DEF_INDEX {
+            add_items(&mut krate.module);
+        } else {
+            MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+                if cx.node_def_id(i.id) != dest {
+                    return;
+                }
+                if let ItemKind::Mod(ref mut m) = i.kind {
+                    add_items(m);
+                }
+            });
+        }
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Check whether the foreign items `did1` and `did2` have the same signature, up to the names
+/// of the types involved.
+fn compatible_decls<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    ty_compare: &TypeCompare<'_, 'tcx, '_>,
+    did1: DefId,
+    did2: DefId,
+) -> bool {
+    match (tcx.def_kind(did1), tcx.def_kind(did2)) {
+        (Some(DefKind::Fn), Some(DefKind::Fn)) => {
+            let (sig1, sig2) = (tcx.fn_sig(did1), tcx.fn_sig(did2));
+            let (sig1, sig2) = (sig1.skip_binder(), sig2.skip_binder());
+            sig1.abi == sig2.abi && ty_compare.compatible_fn_sigs(sig1, sig2)
+        }
+        (Some(DefKind::Static), Some(DefKind::Static)) => {
+            tcx.static_mutability(did1) == tcx.static_mutability(did2) &&
+                ty_compare.structural_eq_tys(tcx.type_of(did1), tcx.type_of(did2))
+        }
+        _ => false,
+    }
+}
+
+/// Check whether calls to the foreign `fn` `old_did` can be redirected to `new_did` by casting
+/// arguments and return values, i.e., whether all differing types are integers, floats or raw
+/// pointers.
+fn castable_decls<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    ty_compare: &TypeCompare<'_, 'tcx, '_>,
+    new_did: DefId,
+    old_did: DefId,
+) -> bool {
+    if tcx.def_kind(new_did) != Some(DefKind::Fn) || tcx.def_kind(old_did) != Some(DefKind::Fn) {
+        return false;
+    }
+    let (new_sig, old_sig) = (tcx.fn_sig(new_did), tcx.fn_sig(old_did));
+    let (new_sig, old_sig) = (new_sig.skip_binder(), old_sig.skip_binder());
+    if new_sig.abi != old_sig.abi || new_sig.c_variadic != old_sig.c_variadic ||
+        new_sig.inputs().len() != old_sig.inputs().len()
+    {
+        return false;
+    }
+
+    let castable = |ty: Ty| ty.is_integral() || ty.is_floating_point() || ty.is_unsafe_ptr();
+    let tys = new_sig.inputs_and_output.iter().zip(old_sig.inputs_and_output.iter());
+    for (&new_ty, &old_ty) in tys {
+        if !ty_compare.structural_eq_tys(new_ty, old_ty) && !(castable(new_ty) && castable(old_ty)) {
+            return false;
+        }
+    }
+    true
+}
+
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
-    reg.register("canonicalize_externs", |args| mk(CanonicalizeExterns {
-        path: args[0].clone(),
-    }));
+    let spec = CommandSpec::new("Replace externs marked `target` with the ones in MOD_PATH.")
+        .arg("MOD_PATH", ArgType::Str, "the path of the module declaring the canonical externs");
+    reg.register("canonicalize_externs", spec, |args| {
+        Ok(mk(CanonicalizeExterns {
+            path: args.str("MOD_PATH").to_owned(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Replace calls to variadic foreign functions with wrappers.");
+    reg.register("wrap_variadic_calls", spec, |_args| Ok(mk(WrapVariadicCalls)));
+
+    let spec = CommandSpec::new("Merge duplicate foreign declarations into the module MOD_PATH.")
+        .arg("MOD_PATH", ArgType::Str, "the path of the module to declare them in")
+        .option("-o", "REPORT", ArgType::Str, None, "also write the report to REPORT as JSON");
+    reg.register("dedup_extern_decls", spec, |args| {
+        Ok(mk(DedupExternDecls {
+            path: args.str("MOD_PATH").to_owned(),
+            report_path: args.opt_str("-o").map(|s| s.to_owned()),
+        }))
+    });
 }
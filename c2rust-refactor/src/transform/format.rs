@@ -15,14 +15,13 @@ use smallvec::smallvec;
 use c2rust_ast_builder::mk;
 use crate::ast_manip::{FlatMapNodes, MutVisitNodes, visit_nodes};
 use crate::command::{CommandState, Registry};
+use crate::command_args::CommandSpec;
 use crate::transform::Transform;
 use crate::RefactorCtxt;
 
 
 /// # `convert_format_args` Command
 ///
-/// Usage: `convert_format_args`
-///
 /// Marks: `target`
 ///
 /// For each function call, if one of its argument expressions is marked `target`,
@@ -93,18 +92,10 @@ impl Transform for ConvertFormatArgs {
 }
 
 
-fn build_format_macro(
-    macro_name: &str,
-    ln_macro_name: Option<&str>,
-    old_fmt_str_expr: Option<P<Expr>>,
-    fmt_args: &[P<Expr>],
-    span: Option<Span>,
-) -> Mac {
-    let old_fmt_str_expr = old_fmt_str_expr.unwrap_or_else(|| fmt_args[0].clone());
-
-    info!("  found fmt str {:?}", old_fmt_str_expr);
-
-    let mut ep = &old_fmt_str_expr;
+/// Get the contents of the string literal `e`, looking through any casts and `as_ptr` calls
+/// wrapped around it.
+pub(crate) fn fmt_str_lit(e: &Expr) -> Option<String> {
+    let mut ep = e;
     let lit = loop {
         // Peel off any casts and retrieve the inner string
         match ep.kind {
@@ -115,18 +106,24 @@ fn build_format_macro(
             ExprKind::MethodCall(ref ps, ref args) if args.len() == 1 &&
                 (ps.ident.as_str() == "as_ptr" ||
                  ps.ident.as_str() == "as_mut_ptr") => ep = &args[0],
-            _ => panic!("unexpected format string: {:?}", old_fmt_str_expr)
+            _ => return None,
         }
     };
-    let s = expect!([lit.kind]
-        LitKind::Str(s, _) => (&s.as_str() as &str).to_owned(),
-        LitKind::ByteStr(ref b) => str::from_utf8(b).unwrap().to_owned());
+    match lit.kind {
+        LitKind::Str(s, _) => Some((&s.as_str() as &str).to_owned()),
+        LitKind::ByteStr(ref b) => str::from_utf8(b).ok().map(|s| s.to_owned()),
+        _ => None,
+    }
+}
 
+/// Translate the C format string `s` into a Rust one.  Returns the new format string, the casts
+/// to apply to each format argument, and the number of arguments the format string consumes.
+fn translate_format(s: &str) -> Result<(String, HashMap<usize, CastType>, usize), String> {
     let mut new_s = String::with_capacity(s.len());
     let mut casts = HashMap::new();
 
     let mut idx = 0;
-    Parser::new(&s, |piece| match piece {
+    Parser::new(s, |piece| match piece {
         Piece::Text(s) => {
             // Find all occurrences of brace characters in `s`
             let mut brace_indices = s.match_indices('{')
@@ -152,7 +149,25 @@ fn build_format_macro(
             c.push_spec(&mut new_s);
             c.add_casts(&mut idx, &mut casts);
         },
-    }).parse();
+    }).parse()?;
+
+    Ok((new_s, casts, idx))
+}
+
+fn build_format_macro(
+    macro_name: &str,
+    ln_macro_name: Option<&str>,
+    old_fmt_str_expr: Option<P<Expr>>,
+    fmt_args: &[P<Expr>],
+    span: Option<Span>,
+) -> Mac {
+    let old_fmt_str_expr = old_fmt_str_expr.unwrap_or_else(|| fmt_args[0].clone());
+
+    info!("  found fmt str {:?}", old_fmt_str_expr);
+
+    let s = fmt_str_lit(&old_fmt_str_expr)
+        .unwrap_or_else(|| panic!("unexpected format string: {:?}", old_fmt_str_expr));
+    let (mut new_s, casts, _) = translate_format(&s).unwrap_or_else(|e| panic!("{}", e));
 
     while new_s.ends_with('\0') {
         new_s.pop();
@@ -197,8 +212,6 @@ fn build_format_macro(
 
 /// # `convert_printfs` Command
 ///
-/// Usage: `convert_printfs`
-///
 /// Marks: none
 ///
 /// Converts each call to `printf(...)` and `fprintf(stderr, ...)` into
@@ -223,50 +236,150 @@ pub struct ConvertPrintfs;
 
 impl Transform for ConvertPrintfs {
     fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
-        let mut printf_defs = HashSet::<DefId>::new();
-        let mut fprintf_defs = HashSet::<DefId>::new();
-        let mut stderr_defs = HashSet::<DefId>::new();
+        let defs = PrintDefs::collect(krate, cx);
+        FlatMapNodes::visit(krate, |s: Stmt| {
+            match s.kind {
+                StmtKind::Semi(ref expr) => {
+                    if let Some((name, ln_name, args)) = defs.match_call(cx, expr) {
+                        let mac = build_format_macro(name, Some(ln_name), None, args, Some(expr.span));
+                        return smallvec![mk().span(s.span).mac_stmt(mac)];
+                    }
+                    smallvec![s]
+                },
+                _ => smallvec![s]
+            }
+        })
+    }
+}
+
+/// # `convert_printf` Command
+///
+/// Marks: none
+///
+/// Like `convert_printfs`, but only converts calls whose format string is a
+/// literal that can be translated exactly.  Calls are left unchanged, with a
+/// warning, if the format string uses `%n`, `%p`, or a width or precision
+/// taken from an argument (`*`), or if the number of arguments doesn't match
+/// the number of conversions.
+///
+/// Example:
+///
+/// ```ignore
+/// printf("%s: %u\n", name, count);
+/// ```
+///
+/// gets converted to:
+///
+/// ```ignore
+/// println!("{:}: {:}",
+///          unsafe { std::ffi::CStr::from_ptr(name as *const libc::c_char).to_str().unwrap() },
+///          count as libc::c_uint);
+/// ```
+pub struct ConvertPrintf;
+
+impl Transform for ConvertPrintf {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        let defs = PrintDefs::collect(krate, cx);
+        FlatMapNodes::visit(krate, |s: Stmt| {
+            let mac = match s.kind {
+                StmtKind::Semi(ref expr) => match defs.match_call(cx, expr) {
+                    Some((name, ln_name, args)) => match check_printf_args(args) {
+                        Ok(()) => Some(build_format_macro(
+                            name, Some(ln_name), None, args, Some(expr.span))),
+                        Err(why) => {
+                            cx.session().span_warn(
+                                expr.span,
+                                &format!("not converting call to `{}!`: {}", name, why),
+                            );
+                            None
+                        }
+                    },
+                    None => None,
+                },
+                _ => None,
+            };
+            match mac {
+                Some(mac) => smallvec![mk().span(s.span).mac_stmt(mac)],
+                None => smallvec![s],
+            }
+        })
+    }
+}
+
+/// Check that the `printf` arguments `args`, starting with the format string, can be converted
+/// to a Rust format macro without changing their meaning.
+fn check_printf_args(args: &[P<Expr>]) -> Result<(), String> {
+    let s = match args.first().and_then(|e| fmt_str_lit(e)) {
+        Some(s) => s,
+        None => return Err("format string is not a literal".to_owned()),
+    };
+    let (_, casts, count) = translate_format(&s)?;
+    if casts.values().any(|&c| c == CastType::Usize) {
+        return Err("width or precision is taken from an argument".to_owned());
+    }
+    if count != args.len() - 1 {
+        return Err(format!("format string takes {} arguments, but {} were given",
+                           count, args.len() - 1));
+    }
+    Ok(())
+}
+
+/// The libc definitions of `printf`, `fprintf` and `stderr`.
+struct PrintDefs {
+    printf: HashSet<DefId>,
+    fprintf: HashSet<DefId>,
+    stderr: HashSet<DefId>,
+}
+
+impl PrintDefs {
+    /// Find the definitions in `krate`.  These must be foreign items marked `#[no_mangle]`, to
+    /// make sure callers are actually calling the libc functions.
+    fn collect(krate: &Crate, cx: &RefactorCtxt) -> PrintDefs {
+        let mut defs = PrintDefs {
+            printf: HashSet::new(),
+            fprintf: HashSet::new(),
+            stderr: HashSet::new(),
+        };
         visit_nodes(krate, |fi: &ForeignItem| {
             if attr::contains_name(&fi.attrs, sym::no_mangle) {
                 match (&*fi.ident.as_str(), &fi.kind) {
                     ("printf", ForeignItemKind::Fn(_, _)) => {
-                        printf_defs.insert(cx.node_def_id(fi.id));
+                        defs.printf.insert(cx.node_def_id(fi.id));
                     }
                     ("fprintf", ForeignItemKind::Fn(_, _)) => {
-                        fprintf_defs.insert(cx.node_def_id(fi.id));
+                        defs.fprintf.insert(cx.node_def_id(fi.id));
                     }
                     ("stderr", ForeignItemKind::Static(_, _)) => {
-                        stderr_defs.insert(cx.node_def_id(fi.id));
+                        defs.stderr.insert(cx.node_def_id(fi.id));
                     }
                     _ => {}
                 }
             }
         });
-        FlatMapNodes::visit(krate, |s: Stmt| {
-            match s.kind {
-                StmtKind::Semi(ref expr) => {
-                    if let ExprKind::Call(ref f, ref args) = expr.kind {
-                        if args.len() < 1 {
-                            return smallvec![s];
-                        }
-                        match (cx.try_resolve_expr(f), cx.try_resolve_expr(&*args[0])) {
-                            (Some(ref f_id), Some(ref arg0_id)) if fprintf_defs.contains(f_id) &&
-                                stderr_defs.contains(arg0_id) => {
-                                let mac = build_format_macro("eprint", Some("eprintln"), None, &args[1..], Some(expr.span));
-                                return smallvec![mk().span(s.span).mac_stmt(mac)];
-                            }
-                            (Some(ref f_id), _) if printf_defs.contains(f_id) => {
-                                let mac = build_format_macro("print", Some("println"), None, &args[..], Some(expr.span));
-                                return smallvec![mk().span(s.span).mac_stmt(mac)];
-                            },
-                            _ => {}
-                        };
-                    };
-                    smallvec![s]
-                },
-                _ => smallvec![s]
+        defs
+    }
+
+    /// If `e` is a call to `printf(...)` or `fprintf(stderr, ...)`, return the names of the
+    /// corresponding print macros and the arguments starting at the format string.
+    fn match_call<'a>(
+        &self,
+        cx: &RefactorCtxt,
+        e: &'a Expr,
+    ) -> Option<(&'static str, &'static str, &'a [P<Expr>])> {
+        let (f, args) = match e.kind {
+            ExprKind::Call(ref f, ref args) if args.len() >= 1 => (f, args),
+            _ => return None,
+        };
+        match (cx.try_resolve_expr(f), cx.try_resolve_expr(&*args[0])) {
+            (Some(ref f_id), Some(ref arg0_id)) if self.fprintf.contains(f_id) &&
+                self.stderr.contains(arg0_id) && args.len() >= 2 => {
+                Some(("eprint", "eprintln", &args[1..]))
             }
-        })
+            (Some(ref f_id), _) if self.printf.contains(f_id) => {
+                Some(("print", "println", &args[..]))
+            }
+            _ => None,
+        }
     }
 }
 
@@ -457,7 +570,8 @@ impl<'a, F: FnMut(Piece)> Parser<'a, F> {
     }
 
     fn peek(&self) -> u8 {
-        self.sb[self.pos]
+        // A conversion cut off by the end of the string reads as NUL, which is never valid
+        self.sb.get(self.pos).cloned().unwrap_or(0)
     }
     fn skip(&mut self) {
         self.pos += 1;
@@ -487,7 +601,7 @@ impl<'a, F: FnMut(Piece)> Parser<'a, F> {
         }
     }
 
-    fn parse(&mut self) {
+    fn parse(&mut self) -> Result<(), String> {
         while self.next_conv() {
             self.skip();
             let mut conv = Conv::new();
@@ -503,13 +617,14 @@ impl<'a, F: FnMut(Piece)> Parser<'a, F> {
             if self.eat(b'.') {
                 conv.prec = Some(self.parse_amount());
             }
-            conv.ty = self.parse_conv_type();
+            conv.ty = self.parse_conv_type()?;
             (self.callback)(Piece::Conv(Box::new(conv)));
         }
 
         if self.pos < self.s.len() {
             (self.callback)(Piece::Text(&self.s[self.pos..]));
         }
+        Ok(())
     }
 
     fn parse_amount(&mut self) -> Amount {
@@ -562,12 +677,12 @@ impl<'a, F: FnMut(Piece)> Parser<'a, F> {
         }
     }
 
-    fn parse_conv_type(&mut self) -> ConvType {
+    fn parse_conv_type(&mut self) -> Result<ConvType, String> {
         let len = self.parse_length();
         let c = self.peek() as char;
         self.skip();
 
-        match c {
+        Ok(match c {
             'd' => ConvType::Int(len),
             'u' => ConvType::Uint(len),
             'x' => ConvType::Hex(len, false),
@@ -575,8 +690,10 @@ impl<'a, F: FnMut(Piece)> Parser<'a, F> {
             'c' => ConvType::Char,
             's' => ConvType::Str,
             'f' => ConvType::Float,
-            _ => panic!("unrecognized conversion spec `{}`", c),
-        }
+            'n' => return Err("`%n` has no Rust equivalent".to_owned()),
+            'p' => return Err("`%p` is not supported".to_owned()),
+            _ => return Err(format!("unrecognized conversion spec `{}`", c)),
+        })
     }
 }
 
@@ -584,6 +701,12 @@ impl<'a, F: FnMut(Piece)> Parser<'a, F> {
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("convert_format_args", |_args| mk(ConvertFormatArgs));
-    reg.register("convert_printfs", |_| mk(ConvertPrintfs));
+    let spec = CommandSpec::new("Turn the format string marked `target` into `format_args!`.");
+    reg.register("convert_format_args", spec, |_args| Ok(mk(ConvertFormatArgs)));
+
+    let spec = CommandSpec::new("Convert `printf` calls into `print!` and its relatives.");
+    reg.register("convert_printfs", spec, |_| Ok(mk(ConvertPrintfs)));
+
+    let spec = CommandSpec::new("Convert `printf` calls with an exactly translatable format.");
+    reg.register("convert_printf", spec, |_| Ok(mk(ConvertPrintf)));
 }
@@ -1,29 +1,45 @@
 use std::collections::{HashMap, HashSet};
+use rustc::hir;
+use rustc::hir::def::{DefKind, Res};
 use rustc::hir::def_id::DefId;
-use rustc::ty::TyKind;
+use rustc::ty::{self, TyKind};
+use rustc_target::spec::abi::Abi;
 use syntax::ast;
 use syntax::ast::*;
 use syntax::attr;
 use syntax::mut_visit::{self, MutVisitor};
+use syntax::print::pprust;
 use syntax::ptr::P;
-use syntax_pos::sym;
+use syntax::symbol::Symbol;
+use syntax::visit::{self, Visitor};
+use syntax::util::classify;
+use syntax::ThinVec;
+use syntax_pos::{sym, Span, DUMMY_SP};
 use smallvec::{smallvec, SmallVec};
 
 use c2rust_ast_builder::{mk, IntoSymbol};
-use crate::ast_manip::{FlatMapNodes, MutVisitNodes, fold_modules, visit_nodes, MutVisit};
+use crate::ast_manip::{FlatMapNodes, MutVisitNodes, fold_modules, fold_output_exprs, visit_nodes,
+                       MutVisit, Visit};
+use crate::ast_manip::util::is_exported;
 use crate::command::{CommandState, Registry};
-use crate::driver::{Phase, parse_expr};
-use crate::matcher::{BindingType, MatchCtxt, Subst, mut_visit_match_with};
+use crate::command_args::{ArgType, CommandSpec};
+use crate::contains_mark::marked_exprs;
+use crate::driver::{Phase, parse_expr, parse_items, parse_ty};
+use crate::matcher::{Bindings, BindingType, MatchCtxt, Subst, mut_visit_match_with};
 use crate::path_edit::{fold_resolved_paths, fold_resolved_paths_with_id};
+use crate::reflect::reflect_tcx_ty;
 use crate::transform::Transform;
+use crate::transform::enums::{int_expr, int_value};
+use crate::transform::heap::{is_std_fn, strip_casts, type_implements};
+use crate::transform::literals::{checked_cstr, cstr_cast, is_valid_cstr, nul_escaped};
+use crate::transform::structs::is_option;
+use crate::transform::vars::{local_writes, mentions_local};
 use crate::util::Lone;
 use crate::RefactorCtxt;
 
 
 /// # `func_to_method` Command
 ///
-/// Usage: `func_to_method`
-///
 /// Marks: `target`, `dest`
 ///
 /// Turn functions marked `target` into static methods (no `self`) in the `impl`
@@ -265,8 +281,6 @@ impl Transform for ToMethod {
 
 /// # `fix_unused_unsafe` Command
 ///
-/// Usage: `fix_unused_unsafe`
-///
 /// Find unused `unsafe` blocks and turn them into ordinary blocks.
 pub struct FixUnusedUnsafe;
 
@@ -295,8 +309,6 @@ impl Transform for FixUnusedUnsafe {
 
 /// # `sink_unsafe` Command
 ///
-/// Usage: `sink_unsafe`
-///
 /// Marks: `target`
 ///
 /// For functions marked `target`, convert `unsafe fn f() { ... }` into `fn () {
@@ -358,9 +370,271 @@ impl Transform for SinkUnsafe {
 }
 
 
-/// # `wrap_extern` Command
+/// # `remove_redundant_unsafe` Command
 ///
-/// Usage: `wrap_extern`
+/// Remove `unsafe` that's no longer needed, typically after other transforms have replaced raw
+/// pointers with references or boxes.  Unsafe operations are found by walking the AST with type
+/// information: dereferences of raw pointers, calls to `unsafe` functions, uses of `static mut`s
+/// and foreign statics, union field accesses, and inline assembly.  Operations inside a nested
+/// `unsafe` block are covered by that block, so they don't count for the code around it.
+///
+///  * An `unsafe fn` whose body contains no unsafe operations becomes a plain `fn`.  Demoting a
+///    function makes calls to it safe, which can let its callers be demoted too, so this is
+///    repeated until nothing changes.  Functions that C code may call (`#[no_mangle]`,
+///    `#[export_name]`, or a non-Rust ABI) keep their `unsafe`, since C callers may rely on the
+///    unsafe contract, and so do methods of trait impls.
+///  * An `unsafe` block with no unsafe operations, counting calls to demoted functions as safe,
+///    becomes a plain block, or just its expression if it contains nothing else.
+///  * With `--shrink`, an `unsafe` block where only some statements need `unsafe` becomes a plain
+///    block, and `unsafe` moves onto those statements: each expression statement or `let`
+///    initializer that needs it is wrapped in its own `unsafe` block.
+pub struct RemoveRedundantUnsafe {
+    shrink: bool,
+}
+
+impl Transform for RemoveRedundantUnsafe {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the `unsafe fn`s to demote.  Start by assuming all of them can be, and remove
+        // the ones that need `unsafe` until nothing changes.  Unlike demoting one at a time, this
+        // handles (mutually) recursive functions.
+
+        let mut collector = UnsafeFnCollector { cx, fns: Vec::new() };
+        visit::walk_crate(&mut collector, krate);
+        let mut demoted = collector.fns.iter().map(|&(did, _)| did).collect::<HashSet<_>>();
+        loop {
+            let needed = collector.fns.iter()
+                .filter(|&&(did, body)| {
+                    demoted.contains(&did) &&
+                    body.stmts.iter().any(|s| needs_unsafe(cx, &demoted, s))
+                })
+                .map(|&(did, _)| did)
+                .collect::<Vec<_>>();
+            if needed.is_empty() {
+                break;
+            }
+            for did in needed {
+                demoted.remove(&did);
+            }
+        }
+
+        // (2) Find the `unsafe` blocks to remove or shrink.
+
+        let mut redundant = HashSet::new();
+        let mut shrinks = HashMap::new();
+        visit_nodes(krate, |e: &Expr| {
+            let b = match_or!([e.kind] ExprKind::Block(ref b, _) => b; return);
+            if b.rules != BlockCheckMode::Unsafe(UnsafeSource::UserProvided) {
+                return;
+            }
+            let needs = b.stmts.iter()
+                .map(|s| needs_unsafe(cx, &demoted, s))
+                .collect::<Vec<_>>();
+            if !needs.contains(&true) {
+                redundant.insert(b.id);
+            } else if self.shrink && needs.contains(&false) {
+                let wrappable = b.stmts.iter().zip(&needs).all(|(s, &needed)| {
+                    !needed || match s.kind {
+                        StmtKind::Local(ref l) => l.init.is_some(),
+                        StmtKind::Expr(..) | StmtKind::Semi(..) => true,
+                        _ => false,
+                    }
+                });
+                if wrappable {
+                    shrinks.insert(b.id, needs);
+                }
+            }
+        });
+
+        // (3) Rewrite the functions and blocks.
+
+        FlatMapNodes::visit(krate, |mut i: P<Item>| {
+            if let ItemKind::Fn(ref mut sig, ..) = i.kind {
+                if demoted.contains(&cx.node_def_id(i.id)) {
+                    sig.header.unsafety = Unsafety::Normal;
+                }
+            }
+            smallvec![i]
+        });
+        FlatMapNodes::visit(krate, |mut ii: ImplItem| {
+            if let ImplItemKind::Method(ref mut sig, _) = ii.kind {
+                if demoted.contains(&cx.node_def_id(ii.id)) {
+                    sig.header.unsafety = Unsafety::Normal;
+                }
+            }
+            smallvec![ii]
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let id = match_or!([e.kind] ExprKind::Block(ref b, _) => b.id; return);
+            if redundant.contains(&id) {
+                remove_unsafe_block(e);
+            } else if let Some(needs) = shrinks.get(&id) {
+                let b = expect!([e.kind] ExprKind::Block(ref mut b, _) => b);
+                b.rules = BlockCheckMode::Default;
+                for (s, &needed) in b.stmts.iter_mut().zip(needs) {
+                    if !needed {
+                        continue;
+                    }
+                    let e = match s.kind {
+                        StmtKind::Local(ref mut l) => l.init.as_mut(),
+                        StmtKind::Expr(ref mut e) | StmtKind::Semi(ref mut e) => Some(e),
+                        _ => None,
+                    };
+                    if let Some(e) = e {
+                        wrap_unsafe(e);
+                    }
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Collects the bodies of `unsafe fn`s that may be demoted to safe functions.
+struct UnsafeFnCollector<'a, 'b, 'tcx, 'ast> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    fns: Vec<(DefId, &'ast Block)>,
+}
+
+impl<'a, 'b, 'tcx, 'ast> UnsafeFnCollector<'a, 'b, 'tcx, 'ast> {
+    fn demotable(header: &FnHeader, attrs: &[Attribute]) -> bool {
+        let rust_abi = match header.ext {
+            Extern::None => true,
+            _ => false,
+        };
+        header.unsafety == Unsafety::Unsafe && rust_abi &&
+            !attr::contains_name(attrs, sym::no_mangle) &&
+            !attr::contains_name(attrs, sym::export_name)
+    }
+}
+
+impl<'a, 'b, 'tcx, 'ast> Visitor<'ast> for UnsafeFnCollector<'a, 'b, 'tcx, 'ast> {
+    fn visit_item(&mut self, i: &'ast Item) {
+        match i.kind {
+            ItemKind::Fn(ref sig, _, ref block) if Self::demotable(&sig.header, &i.attrs) => {
+                self.fns.push((self.cx.node_def_id(i.id), block));
+            }
+            // Methods of trait impls have to match the trait.
+            ItemKind::Impl(_, _, _, _, Some(_), _, _) => return,
+            _ => {}
+        }
+        visit::walk_item(self, i);
+    }
+
+    fn visit_impl_item(&mut self, ii: &'ast ImplItem) {
+        if let ImplItemKind::Method(ref sig, ref block) = ii.kind {
+            if Self::demotable(&sig.header, &ii.attrs) {
+                self.fns.push((self.cx.node_def_id(ii.id), block));
+            }
+        }
+        visit::walk_impl_item(self, ii);
+    }
+
+    fn visit_mac(&mut self, mac: &'ast Mac) {
+        visit::walk_mac(self, mac)
+    }
+}
+
+/// Check whether `s` contains an operation that needs `unsafe`, outside of any nested `unsafe`
+/// block, assuming calls to the functions in `safe`, and uses of the statics in it, are safe.
+pub(crate) fn needs_unsafe(cx: &RefactorCtxt, safe: &HashSet<DefId>, s: &Stmt) -> bool {
+    struct UnsafeOpFinder<'a, 'b, 'tcx> {
+        cx: &'a RefactorCtxt<'b, 'tcx>,
+        safe: &'a HashSet<DefId>,
+        found: bool,
+    }
+
+    impl<'a, 'b, 'tcx> UnsafeOpFinder<'a, 'b, 'tcx> {
+        fn is_unsafe_op(&self, e: &Expr) -> bool {
+            let tcx = self.cx.ty_ctxt();
+            match e.kind {
+                ExprKind::Unary(UnOp::Deref, ref inner) => {
+                    self.cx.opt_node_type(inner.id).map_or(false, |ty| ty.is_unsafe_ptr())
+                }
+                ExprKind::Call(..) | ExprKind::MethodCall(..) => {
+                    self.cx.opt_callee_info(e).map_or(false, |info| {
+                        info.fn_sig.unsafety == hir::Unsafety::Unsafe &&
+                            !info.def_id.map_or(false, |did| self.safe.contains(&did))
+                    })
+                }
+                ExprKind::Path(..) => {
+                    self.cx.try_resolve_expr(e).map_or(false, |did| {
+                        if self.safe.contains(&did) {
+                            return false;
+                        }
+                        match tcx.static_mutability(did) {
+                            Some(hir::Mutability::Mutable) => true,
+                            Some(hir::Mutability::Immutable) => tcx.is_foreign_item(did),
+                            None => false,
+                        }
+                    })
+                }
+                ExprKind::Field(ref base, _) => {
+                    let mut ty = match_or!([self.cx.opt_node_type(base.id)] Some(x) => x;
+                                           return false);
+                    while let TyKind::Ref(_, inner, _) = ty.kind {
+                        ty = inner;
+                    }
+                    ty.ty_adt_def().map_or(false, |adt| adt.is_union())
+                }
+                ExprKind::InlineAsm(..) => true,
+                _ => false,
+            }
+        }
+    }
+
+    impl<'a, 'b, 'tcx, 'ast> Visitor<'ast> for UnsafeOpFinder<'a, 'b, 'tcx> {
+        fn visit_expr(&mut self, e: &'ast Expr) {
+            if self.found {
+                return;
+            }
+            if let ExprKind::Block(ref b, _) = e.kind {
+                if let BlockCheckMode::Unsafe(_) = b.rules {
+                    return;
+                }
+            }
+            if self.is_unsafe_op(e) {
+                self.found = true;
+                return;
+            }
+            visit::walk_expr(self, e);
+        }
+
+        fn visit_item(&mut self, _i: &'ast Item) {
+            // Nested items are checked on their own.
+        }
+
+        fn visit_mac(&mut self, mac: &'ast Mac) {
+            visit::walk_mac(self, mac)
+        }
+    }
+
+    let mut v = UnsafeOpFinder { cx, safe, found: false };
+    v.visit_stmt(s);
+    v.found
+}
+
+/// Turn the `unsafe` block `e` into a plain block, or just its expression if it contains nothing
+/// else.
+pub(crate) fn remove_unsafe_block(e: &mut P<Expr>) {
+    let b = match_or!([e.kind] ExprKind::Block(ref mut b, _) => b; return);
+    b.rules = BlockCheckMode::Default;
+    let inner = match b.stmts[..] {
+        [Stmt { kind: StmtKind::Expr(ref inner), .. }] => inner.clone(),
+        _ => return,
+    };
+    *e = inner;
+}
+
+fn wrap_unsafe(e: &mut P<Expr>) {
+    *e = mk().block_expr(mk().unsafe_().block(vec![mk().expr_stmt(e.clone())]));
+}
+
+
+/// # `wrap_extern` Command
 ///
 /// Marks: `target`, `dest`
 ///
@@ -536,8 +810,6 @@ impl Transform for WrapExtern {
 
 /// # `wrap_api` Command
 ///
-/// Usage: `wrap_api`
-///
 /// Marks: `target`
 ///
 /// For each function `foo` marked `target`:
@@ -571,89 +843,16 @@ impl Transform for WrapApi {
                 ItemKind::Fn(ref sig, _, _) => (sig.decl.clone(), sig.header.ext));
 
             // Get the exported symbol name of the function
-            let symbol =
-                if let Some(sym) = attr::first_attr_value_str_by_name(&i.attrs, sym::export_name) {
-                    sym
-                } else if attr::contains_name(&i.attrs, sym::no_mangle) {
-                    i.ident.name
-                } else {
-                    warn!("marked function `{:?}` does not have a stable symbol", i.ident.name);
-                    return smallvec![i];
-                };
-
-            // Remove export-related attrs from the original function, and set it to Abi::Rust.
-            let i = i.map(|mut i| {
-                i.attrs.retain(|attr| {
-                    let attr = attr.name_or_empty();
-                    attr != sym::no_mangle && attr != sym::export_name
-                });
-
-                match i.kind {
-                    ItemKind::Fn(ref mut sig, _, _) => sig.header.ext = Extern::None,
-                    _ => unreachable!(),
-                }
-
-                i
-            });
-
-            // Pick distinct names for the arguments in the wrapper.
-            let mut used_names = HashSet::new();
-
-            let arg_names = decl.inputs.iter().enumerate().map(|(idx, arg)| {
-                let base = match arg.pat.kind {
-                    // Use the name from the original function, if there is one.  Otherwise, fall
-                    // back on `arg0`, `arg1`, ...
-                    PatKind::Ident(_, ref ident, _) => ident.name,
-                    _ => format!("arg{}", idx).into_symbol(),
-                };
-
-                let name;
-                if !used_names.contains(&base) {
-                    name = base;
-                } else {
-                    let mut i = 0;
-                    loop {
-                        let gen_name = format!("{}_{}", base.as_str(), i).into_symbol();
-                        if !used_names.contains(&gen_name) {
-                            name = gen_name;
-                            break;
-                        }
-                        i += 1;
-                    }
-                }
-
-                used_names.insert(name);
-                name
-            }).collect::<Vec<_>>();
-
-            // Generate the wrapper.  It gets an `#[export_name]`  attr and the original function's
-            // old ABI.
-            let wrapper_decl = decl.clone().map(|decl| {
-                let new_inputs = decl.inputs.iter().zip(arg_names.iter()).map(|(arg, &name)| {
-                    mk().arg(&arg.ty, mk().ident_pat(name))
-                }).collect();
-                FnDecl {
-                    inputs: new_inputs,
-                    .. decl
-                }
+            let symbol = match_or!([export_symbol(&i)] Some(x) => x; {
+                warn!("marked function `{:?}` does not have a stable symbol", i.ident.name);
+                return smallvec![i];
             });
 
-            let wrapper_args = arg_names.iter().map(|&name| mk().ident_expr(name)).collect();
+            // Remove export-related attrs from the original function, and set it to Abi::Rust.
+            let i = unexport(i);
 
             let wrapper_name = format!("{}_wrapper", symbol.as_str());
-            let wrapper =
-                mk().vis(i.vis.clone()).unsafe_().extern_(old_ext)
-                        .str_attr(vec![sym::export_name], symbol).fn_item(
-                    &wrapper_name,
-                    wrapper_decl,
-                    mk().block(vec![
-                        mk().expr_stmt(mk().call_expr(
-                                mk().path_expr(vec![i.ident.name]),
-                                wrapper_args,
-                        ))
-                    ])
-                );
-
+            let wrapper = api_wrapper(&i, &decl, old_ext, symbol, &wrapper_name, |call| call);
 
             let item_hir_id = cx.hir_map().node_to_hir_id(i.id);
             wrapper_map.insert(item_hir_id, wrapper_name);
@@ -695,119 +894,3586 @@ impl Transform for WrapApi {
 }
 
 
-/// # `abstract` Command
-///
-/// Usage: `abstract SIG PAT [BODY]`
-///
-/// Replace all instances of `pat` with calls to a new function whose name and signature is given
-/// by `sig`.  Example:
-///
-/// Input:
-///
-/// ```ignore
-///     1 + 2
-/// ```
-///
-/// After running `abstract 'add(x: u32, y: u32) -> u32' 'x + y'`:
+/// Get the symbol name that the function `i` is exported under, if it has a stable one.
+fn export_symbol(i: &Item) -> Option<Symbol> {
+    if let Some(sym) = attr::first_attr_value_str_by_name(&i.attrs, sym::export_name) {
+        Some(sym)
+    } else if attr::contains_name(&i.attrs, sym::no_mangle) {
+        Some(i.ident.name)
+    } else {
+        None
+    }
+}
+
+/// Remove export-related attrs from the function `i`, and set it to Abi::Rust.
+fn unexport(i: P<Item>) -> P<Item> {
+    i.map(|mut i| {
+        i.attrs.retain(|attr| {
+            let attr = attr.name_or_empty();
+            attr != sym::no_mangle && attr != sym::export_name
+        });
+
+        match i.kind {
+            ItemKind::Fn(ref mut sig, _, _) => sig.header.ext = Extern::None,
+            _ => unreachable!(),
+        }
+
+        i
+    })
+}
+
+/// Generate a wrapper called `wrapper_name` for the function `i`, whose original declaration was
+/// `decl`.  The wrapper gets an `#[export_name]` attr for `symbol` and the ABI `old_ext`, and its
+/// body is the result of applying `body` to a call of `i`.
+fn api_wrapper<F>(i: &Item, decl: &P<FnDecl>, old_ext: Extern, symbol: Symbol,
+                  wrapper_name: &str, body: F) -> P<Item>
+    where F: FnOnce(P<Expr>) -> P<Expr> {
+    // Pick distinct names for the arguments in the wrapper.
+    let mut used_names = HashSet::new();
+
+    let arg_names = decl.inputs.iter().enumerate().map(|(idx, arg)| {
+        let base = match arg.pat.kind {
+            // Use the name from the original function, if there is one.  Otherwise, fall
+            // back on `arg0`, `arg1`, ...
+            PatKind::Ident(_, ref ident, _) => ident.name,
+            _ => format!("arg{}", idx).into_symbol(),
+        };
+
+        let name;
+        if !used_names.contains(&base) {
+            name = base;
+        } else {
+            let mut i = 0;
+            loop {
+                let gen_name = format!("{}_{}", base.as_str(), i).into_symbol();
+                if !used_names.contains(&gen_name) {
+                    name = gen_name;
+                    break;
+                }
+                i += 1;
+            }
+        }
+
+        used_names.insert(name);
+        name
+    }).collect::<Vec<_>>();
+
+    // Generate the wrapper.  It gets an `#[export_name]`  attr and the original function's
+    // old ABI.
+    let wrapper_decl = decl.clone().map(|decl| {
+        let new_inputs = decl.inputs.iter().zip(arg_names.iter()).map(|(arg, &name)| {
+            mk().arg(&arg.ty, mk().ident_pat(name))
+        }).collect();
+        FnDecl {
+            inputs: new_inputs,
+            .. decl
+        }
+    });
+
+    let wrapper_args = arg_names.iter().map(|&name| mk().ident_expr(name)).collect();
+
+    mk().vis(i.vis.clone()).unsafe_().extern_(old_ext)
+            .str_attr(vec![sym::export_name], symbol).fn_item(
+        wrapper_name,
+        wrapper_decl,
+        mk().block(vec![
+            mk().expr_stmt(body(mk().call_expr(
+                    mk().path_expr(vec![i.ident.name]),
+                    wrapper_args,
+            )))
+        ])
+    )
+}
+
+
+/// # `convert_cstr_params` Command
 ///
-/// ```ignore
-///     add(1, 2)
+/// Marks: `target`
 ///
-///     // Elsewhere:
-///     fn add(x: u32, y: u32) -> u32 { x + y }
-/// ```
+/// For each function marked `target`, change the `*const c_char` parameters that are only used
+/// as NUL-terminated strings to `&str`, or to `&CStr` if `--cstr` is given.  A parameter qualifies
+/// if the body only passes it to `strlen`, to `strcmp`-like or `printf`-family functions, or to
+/// another converted parameter, or reads characters through it with `*p` or `*p.offset(i)`.  In
+/// the body:
 ///
-/// All type and value parameter names in `sig` act as bindings when matching `pat`.  The captured
-/// exprs and types are passed as parameters when building the new call expression.  The body of
-/// the function is `body`, if provided, otherwise `pat` itself.
+///  * `strlen(p)` becomes `p.len()` (or `p.to_bytes().len()`),
+///  * other C library functions get a NUL-terminated pointer: `p.as_ptr()`, or a temporary
+///    `CString` for `&str`, and
+///  * character reads index into the string's bytes, reading `0` past the end of a `&str`.
 ///
-/// Non-ident patterns in `sig` are not supported.  It is also an error for any type parameter's
-/// name to collide with any value parameter.
+/// Any other use, such as pointer arithmetic that walks the string, leaves the parameter alone
+/// with a warning.
 ///
-/// If matching with `pat` fails to capture expressions for any of the value parameters of `sig`,
-/// it is an error.  If it fails to capture for a type parameter, the parameter is filled in with
-/// `_` (infer).
-struct Abstract {
-    sig: String,
-    pat: String,
-    body: Option<String>,
+/// Callers in the crate pass C string literals as plain Rust literals, and other pointers
+/// through `CStr::from_ptr(...).to_str().unwrap()`.  An exported function keeps its C signature
+/// through a wrapper, as in `wrap_api`.
+pub struct ConvertCStrParams {
+    cstr: bool,
 }
 
-impl Transform for Abstract {
-    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
-        let pat = parse_expr(cx.session(), &self.pat);
+/// How the body of a function uses one of its `*const c_char` parameters.
+enum CStrUse {
+    /// `strlen(p)`.  The `NodeId` is the call's.
+    Len,
+    /// An argument to a C library function that needs a NUL-terminated pointer.
+    Ptr,
+    /// An argument to the given parameter of another function being converted.
+    Forward(DefId, usize),
+    /// `*p` or `*p.offset(i)`.  The `NodeId` is the deref's.
+    Read,
+}
 
-        let func_src = format!("unsafe fn {} {{\n    {}\n}}",
-                               self.sig, self.body.as_ref().unwrap_or(&self.pat));
-        let func: P<Item> = st.parse_items(cx, &func_src).lone();
-        st.add_mark(func.id, sym::new);
+/// C library functions that read a NUL-terminated string argument without keeping it.
+const CSTR_ARG_FNS: &[&str] = &[
+    "strcmp", "strncmp", "strcasecmp", "strncasecmp", "printf", "fprintf", "sprintf", "snprintf",
+    "dprintf", "puts", "fputs",
+];
 
-        // Build the call expression template
+impl Transform for ConvertCStrParams {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+        let hir_map = cx.hir_map();
 
-        let mut value_args = Vec::new();
-        let mut type_args = Vec::new();
-        {
-            let (decl, generics) = expect!([func.kind]
-                    ItemKind::Fn(ref sig, ref gen, _) => (&sig.decl, gen));
-            for arg in &decl.inputs {
-                let name = expect!([arg.pat.kind] PatKind::Ident(_, ident, _) => ident);
-                value_args.push(name);
+        // Find candidate parameters: immutable `*const c_char` bindings.
+        let mut fns: HashMap<DefId, Vec<(usize, hir::HirId, Ident)>> = HashMap::new();
+        let mut blocks = Vec::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") {
+                return;
             }
-            for param in &generics.params {
-                if let GenericParamKind::Type { .. } = param.kind {
-                    type_args.push(param.ident);
+            let (sig, block) = match_or!([&i.kind] ItemKind::Fn(sig, _, block) => (sig, block);
+                                         return);
+            let params = sig.decl.inputs.iter().enumerate().filter_map(|(idx, arg)| {
+                match arg.pat.kind {
+                    PatKind::Ident(BindingMode::ByValue(Mutability::Immutable), ident, None)
+                        if is_c_char_ptr(&arg.ty) =>
+                        Some((idx, hir_map.node_to_hir_id(arg.pat.id), ident)),
+                    _ => None,
                 }
+            }).collect::<Vec<_>>();
+            if !params.is_empty() {
+                fns.insert(cx.node_def_id(i.id), params);
+                blocks.push(block.clone());
             }
-        }
+        });
 
-        let aba = mk().angle_bracketed_args(
-            type_args.iter().map(|name| mk().ident_ty(name)).collect());
-        let seg = mk().path_segment_with_args(func.ident, aba);
-        let call_expr = mk().call_expr(
-            mk().path_expr(vec![seg]),
-            value_args.iter().map(|name| mk().ident_expr(name)).collect());
+        let param_names = fns.values().flatten()
+            .map(|&(_, hir_id, ident)| (hir_id, ident))
+            .collect::<HashMap<_, _>>();
+        let param_path = |e: &Expr| -> Option<(hir::HirId, NodeId)> {
+            let e = strip_parens(e);
+            if !matches!([e.kind] ExprKind::Path(None, _)) {
+                return None;
+            }
+            match cx.try_resolve_expr_hir(e) {
+                Some(Res::Local(hir_id)) if param_names.contains_key(&hir_id) => {
+                    Some((hir_id, e.id))
+                }
+                _ => None,
+            }
+        };
 
-        // Search and replace
+        // Classify every use of each candidate.
+        let mut uses = Vec::new();
+        let mut bad = HashMap::new();
+        for block in &blocks {
+            let mut handled = HashSet::new();
+            visit_nodes(&**block, |e: &Expr| {
+                match &e.kind {
+                    ExprKind::Call(_, args) => {
+                        let callee = match_or!([cx.opt_callee(e)] Some(x) => x; return);
+                        if let Some(params) = fns.get(&callee) {
+                            for (idx, arg) in args.iter().enumerate() {
+                                if !params.iter().any(|p| p.0 == idx) {
+                                    continue;
+                                }
+                                if let Some((hir_id, path_id)) = param_path(arg) {
+                                    uses.push((hir_id, path_id, CStrUse::Forward(callee, idx)));
+                                    handled.insert(path_id);
+                                }
+                            }
+                            return;
+                        }
+                        if !tcx.is_foreign_item(callee) {
+                            return;
+                        }
+                        let name = tcx.item_name(callee).as_str();
+                        if &*name == "strlen" && args.len() == 1 {
+                            if let Some((hir_id, path_id)) = param_path(&args[0]) {
+                                uses.push((hir_id, e.id, CStrUse::Len));
+                                handled.insert(path_id);
+                            }
+                        } else if CSTR_ARG_FNS.contains(&&*name) {
+                            for arg in args {
+                                if let Some((hir_id, path_id)) = param_path(arg) {
+                                    uses.push((hir_id, path_id, CStrUse::Ptr));
+                                    handled.insert(path_id);
+                                }
+                            }
+                        }
+                    }
+                    ExprKind::Unary(UnOp::Deref, inner) => {
+                        let ptr = match &strip_parens(inner).kind {
+                            ExprKind::MethodCall(seg, args)
+                                if args.len() == 2 && is_offset_method(seg) => &args[0],
+                            _ => inner,
+                        };
+                        if let Some((hir_id, path_id)) = param_path(ptr) {
+                            uses.push((hir_id, e.id, CStrUse::Read));
+                            handled.insert(path_id);
+                        }
+                    }
+                    ExprKind::AddrOf(_, _, inner) => {
+                        // `&*p` would point into a temporary after the conversion.
+                        if let ExprKind::Unary(UnOp::Deref, ptr) = &strip_parens(inner).kind {
+                            if let Some((hir_id, _)) = param_path(ptr) {
+                                bad.entry(hir_id).or_insert(e.span);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            });
 
-        let mut init_mcx = MatchCtxt::new(st, cx);
-        for name in &value_args {
-            init_mcx.set_type(name.name, BindingType::Expr);
+            visit_nodes(&**block, |e: &Expr| {
+                if let Some((hir_id, path_id)) = param_path(e) {
+                    if path_id == e.id && !handled.contains(&e.id) {
+                        bad.entry(hir_id).or_insert(e.span);
+                    }
+                }
+            });
         }
-        for name in &type_args {
-            init_mcx.set_type(name.name, BindingType::Ty);
+        for (hir_id, span) in &bad {
+            cx.session().span_warn(*span, &format!(
+                "not converting `{}`: it's used as a raw pointer", param_names[hir_id]));
         }
 
-        mut_visit_match_with(init_mcx, pat, krate, |ast, mut mcx| {
-            for name in &type_args {
-                if mcx.bindings.get::<_, P<Ty>>(name.name).is_none() {
-                    mcx.bindings.add(name.name, mk().infer_ty());
-                }
+        let mut callee_ids = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Call(func, _) = &e.kind {
+                callee_ids.insert(func.id);
+            }
+        });
+        // A function used as a value can't have its signature changed.
+        let mut bad = bad.keys().cloned().collect::<HashSet<_>>();
+        visit_nodes(krate, |e: &Expr| {
+            if !matches!([e.kind] ExprKind::Path(..)) || callee_ids.contains(&e.id) {
+                return;
+            }
+            if let Some(params) = cx.try_resolve_expr(e).and_then(|def_id| fns.get(&def_id)) {
+                cx.session().span_warn(e.span, "not converting parameters of a function \
+                                                used as a value");
+                bad.extend(params.iter().map(|p| p.1));
             }
-            *ast = call_expr.clone().subst(st, cx, &mcx.bindings);
         });
 
-        // Add the function definition to the crate
-
-        krate.module.items.push(func);
-    }
+        // Forwarding to another parameter only works if that one is converted too.
+        loop {
+            let mut changed = false;
+            for (hir_id, _, u) in &uses {
+                if let CStrUse::Forward(callee, idx) = *u {
+                    let ok = fns[&callee].iter().any(|p| p.0 == idx && !bad.contains(&p.1));
+                    if !ok && bad.insert(*hir_id) {
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        let mut converted = HashMap::new();
+        for (def_id, params) in &fns {
+            let idxs = params.iter()
+                .filter(|p| !bad.contains(&p.1))
+                .map(|p| p.0)
+                .collect::<Vec<_>>();
+            if !idxs.is_empty() {
+                converted.insert(*def_id, idxs);
+            }
+        }
+        let converted_params = param_names.keys()
+            .filter(|hir_id| !bad.contains(hir_id))
+            .cloned()
+            .collect::<HashSet<_>>();
+
+        let mut rewrites = HashMap::new();
+        for (hir_id, id, u) in uses {
+            if converted_params.contains(&hir_id) {
+                rewrites.insert(id, (u, param_names[&hir_id]));
+            }
+        }
+        let mut calls = HashMap::new();
+        visit_nodes(krate, |e: &Expr| {
+            if !matches!([e.kind] ExprKind::Call(..)) {
+                return;
+            }
+            if let Some(idxs) = cx.opt_callee(e).and_then(|def_id| converted.get(&def_id)) {
+                calls.insert(e.id, idxs.clone());
+            }
+        });
+
+        krate.visit(&mut CStrParamFolder {
+            cx,
+            cstr: self.cstr,
+            rewrites: &rewrites,
+            calls: &calls,
+            converted_params: &converted_params,
+        });
+
+        // Change the signatures, and wrap exported functions.
+        let cstr = self.cstr;
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            if !matches!([i.kind] ItemKind::Fn(..)) {
+                return smallvec![i];
+            }
+            let idxs = match_or!([converted.get(&cx.node_def_id(i.id))] Some(x) => x.clone();
+                                 return smallvec![i]);
+            let (old_decl, old_ext, unsafety) = expect!([i.kind]
+                ItemKind::Fn(ref sig, _, _) =>
+                    (sig.decl.clone(), sig.header.ext, sig.header.unsafety));
+            let symbol = export_symbol(&i);
+
+            let mut i = i.map(|mut i| {
+                let decl = expect!([i.kind] ItemKind::Fn(ref mut sig, _, _) => &mut sig.decl);
+                for &idx in &idxs {
+                    decl.inputs[idx].ty = cstr_param_ty(cstr);
+                }
+                i
+            });
+
+            let symbol = match_or!([symbol] Some(x) => x; return smallvec![i]);
+            i = unexport(i);
+            let wrapper_name = format!("{}_wrapper", symbol.as_str());
+            let wrapper = api_wrapper(&i, &old_decl, old_ext, symbol, &wrapper_name, |call| {
+                let call = call.map(|mut call| {
+                    if let ExprKind::Call(_, ref mut args) = call.kind {
+                        for &idx in &idxs {
+                            args[idx] = cstr_from_ptr(args[idx].clone(), cstr);
+                        }
+                    }
+                    call
+                });
+                match unsafety {
+                    Unsafety::Unsafe => call,
+                    Unsafety::Normal => {
+                        mk().block_expr(mk().unsafe_().block(vec![mk().expr_stmt(call)]))
+                    }
+                }
+            });
+            smallvec![i, wrapper]
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+struct CStrParamFolder<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    cstr: bool,
+    rewrites: &'a HashMap<NodeId, (CStrUse, Ident)>,
+    /// Calls to converted functions, with the indices of the converted parameters.
+    calls: &'a HashMap<NodeId, Vec<usize>>,
+    converted_params: &'a HashSet<hir::HirId>,
+}
+
+impl<'a, 'tcx> CStrParamFolder<'a, 'tcx> {
+    /// Cast `e` to the type of the node `id`, unless it already has type `unless`.
+    fn cast_to_type_of(&self, e: P<Expr>, id: NodeId, unless: TyKind) -> P<Expr> {
+        match self.cx.opt_node_type(id) {
+            Some(ty) if ty.kind != unless => {
+                mk().cast_expr(e, reflect_tcx_ty(self.cx.ty_ctxt(), ty))
+            }
+            _ => e,
+        }
+    }
+
+    /// Build an argument for a converted parameter from `arg`, which was a `*const c_char`.
+    fn convert_arg(&self, arg: P<Expr>) -> P<Expr> {
+        if let Some(Res::Local(hir_id)) = self.cx.try_resolve_expr_hir(strip_parens(&arg)) {
+            if self.converted_params.contains(&hir_id) {
+                return arg;
+            }
+        }
+        if let Some(cast) = cstr_cast(&arg) {
+            if is_valid_cstr(cast.bytes) {
+                if self.cstr {
+                    return checked_cstr(nul_escaped(cast.lit));
+                }
+                if let Ok(s) = std::str::from_utf8(&cast.bytes[..cast.bytes.len() - 1]) {
+                    return mk().lit_expr(s);
+                }
+            }
+        }
+        let e = cstr_from_ptr(arg, self.cstr);
+        mk().block_expr(mk().unsafe_().block(vec![mk().expr_stmt(e)]))
+    }
+}
+
+impl<'a, 'tcx> MutVisitor for CStrParamFolder<'a, 'tcx> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        mut_visit::noop_visit_expr(e, self);
+
+        if let Some(idxs) = self.calls.get(&e.id) {
+            if let ExprKind::Call(_, ref mut args) = e.kind {
+                for &idx in idxs {
+                    args[idx] = self.convert_arg(args[idx].clone());
+                }
+            }
+            return;
+        }
+
+        let (u, name) = match_or!([self.rewrites.get(&e.id)] Some(&(ref u, name)) => (u, name);
+                                  return);
+        let p = mk().ident_expr(name);
+        let no_args = Vec::<P<Expr>>::new();
+        let new_e = match u {
+            CStrUse::Len => {
+                let len = if self.cstr {
+                    let bytes = mk().method_call_expr(p, "to_bytes", no_args.clone());
+                    mk().method_call_expr(bytes, "len", no_args)
+                } else {
+                    mk().method_call_expr(p, "len", no_args)
+                };
+                self.cast_to_type_of(len, e.id, TyKind::Uint(ast::UintTy::Usize))
+            }
+            CStrUse::Ptr => {
+                let cstr = if self.cstr {
+                    p
+                } else {
+                    let new = mk().call_expr(
+                        mk().path_expr(vec!["std", "ffi", "CString", "new"]), vec![p]);
+                    mk().method_call_expr(new, "unwrap", no_args.clone())
+                };
+                mk().method_call_expr(cstr, "as_ptr", no_args)
+            }
+            CStrUse::Read => {
+                let inner = expect!([e.kind] ExprKind::Unary(UnOp::Deref, ref inner) => inner);
+                let idx = match &strip_parens(inner).kind {
+                    ExprKind::MethodCall(seg, args) => {
+                        let idx = args[1].clone();
+                        match idx.kind {
+                            ExprKind::Lit(Lit { kind: LitKind::Int(_, LitIntType::Unsuffixed),
+                                                .. }) => idx,
+                            _ if seg.ident.as_str() == "add" => idx,
+                            _ => mk().cast_expr(idx, mk().ident_ty("usize")),
+                        }
+                    }
+                    _ => mk().lit_expr(mk().int_lit(0, "")),
+                };
+                let byte = if self.cstr {
+                    let bytes = mk().method_call_expr(p, "to_bytes_with_nul", no_args);
+                    mk().index_expr(bytes, idx)
+                } else {
+                    let bytes = mk().method_call_expr(p, "as_bytes", no_args);
+                    let get = mk().method_call_expr(bytes, "get", vec![idx]);
+                    let zero = mk().addr_of_expr(mk().lit_expr(mk().int_lit(0, "")));
+                    mk().unary_expr("*", mk().method_call_expr(get, "unwrap_or", vec![zero]))
+                };
+                self.cast_to_type_of(byte, e.id, TyKind::Uint(ast::UintTy::U8))
+            }
+            CStrUse::Forward(..) => return,
+        };
+        *e = new_e;
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+/// Check whether `ty` is written as `*const c_char`.
+fn is_c_char_ptr(ty: &Ty) -> bool {
+    match &ty.kind {
+        ast::TyKind::Ptr(MutTy { ty: pointee, mutbl: Mutability::Immutable }) => {
+            match &pointee.kind {
+                ast::TyKind::Path(None, path) => {
+                    path.segments.last().map_or(false, |seg| seg.ident.as_str() == "c_char")
+                }
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+fn is_offset_method(seg: &PathSegment) -> bool {
+    let name = seg.ident.as_str();
+    name == "offset" || name == "add"
+}
+
+/// `&str`, or `&std::ffi::CStr` if `cstr` is set.
+fn cstr_param_ty(cstr: bool) -> P<Ty> {
+    if cstr {
+        mk().ref_ty(mk().path_ty(vec!["std", "ffi", "CStr"]))
+    } else {
+        mk().ref_ty(mk().ident_ty("str"))
+    }
+}
+
+/// Turn the C string pointer `ptr` into a `&CStr`, or a `&str` if `cstr` isn't set.  The result
+/// needs an `unsafe` context.
+fn cstr_from_ptr(ptr: P<Expr>, cstr: bool) -> P<Expr> {
+    let e = mk().call_expr(mk().path_expr(vec!["std", "ffi", "CStr", "from_ptr"]), vec![ptr]);
+    if cstr {
+        return e;
+    }
+    let no_args = Vec::<P<Expr>>::new();
+    let s = mk().method_call_expr(e, "to_str", no_args.clone());
+    mk().method_call_expr(s, "unwrap", no_args)
+}
+
+
+/// # `convert_string_fns` Command
+///
+/// Replace calls to the C string functions `strcmp`, `strncmp`, `strchr` and `strlen` with
+/// methods of `str`, `CStr` or `[u8]`, once earlier passes like `convert_cstr_params` have
+/// converted their arguments and only turn them back into pointers for the call.  An argument
+/// qualifies if it's one of:
+///
+///  * `std::ffi::CString::new(s).unwrap().as_ptr()`, where `s: &str`,
+///  * `s.as_ptr()`, where `s: &CStr`,
+///  * `s.as_ptr() as *const c_char`, where `s: &[u8]` holds the bytes of the string, without
+///    the NUL, or
+///  * a C string literal like `b"abc\0" as *const u8 as *const c_char`, which becomes `"abc"`.
+///
+/// The calls are rewritten as follows:
+///
+///  * `strcmp(a, b) == 0` becomes `a == b`, and likewise for `!=`, and for `<`, `<=`, `>` and
+///    `>=`, which become `Ord` comparisons.  Strings of different kinds are compared as bytes.
+///  * `strncmp(a, b, n) == 0` becomes `a.iter().take(n).eq(b.iter().take(n))` on the bytes of
+///    the strings, and likewise for the other comparisons.  Unlike `a[..n] == b[..n]`, this
+///    doesn't panic when a string is shorter than `n`.
+///  * `strchr(s, c).is_null()` becomes `s.find(c).is_none()` if `s` is a `&str` and `c` is a
+///    `char` literal, and `s.bytes().position(|b| b == c as u8).is_none()` otherwise, and
+///    `!strchr(s, c).is_null()` becomes `is_some()` instead.  `strchr(s, 0)` is left alone,
+///    since it finds the NUL.
+///  * `strlen(s)` becomes `s.len()`.
+///
+/// Calls with any other argument, such as a `*const c_char` that hasn't been converted yet, are
+/// left alone, so the command can be run again as the conversion progresses.
+pub struct ConvertStringFns;
+
+/// The kind of a string argument in `convert_string_fns`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum StrKind {
+    Str,
+    CStr,
+    Bytes,
+}
+
+/// If `e` calls the C library function `name`, get its arguments.
+fn c_fn_args<'e>(cx: &RefactorCtxt, e: &'e Expr, name: &str) -> Option<&'e [P<Expr>]> {
+    let e = strip_parens(e);
+    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return None);
+    let tcx = cx.ty_ctxt();
+    let callee = cx.opt_callee(e)?;
+    if !tcx.is_foreign_item(callee) || &*tcx.item_name(callee).as_str() != name {
+        return None;
+    }
+    Some(args)
+}
+
+impl Transform for ConvertStringFns {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let arg_pats = [
+            (mcx.parse_expr("std::ffi::CString::new(typed!($s:Expr, &str)).unwrap().as_ptr()"),
+             StrKind::Str),
+            (mcx.parse_expr("typed!($s:Expr, &::std::ffi::c_str::CStr).as_ptr()"),
+             StrKind::CStr),
+            (mcx.parse_expr("typed!($s:Expr, &[u8]).as_ptr() as *const $t:Ty"),
+             StrKind::Bytes),
+        ];
+        let as_bytes = mcx.parse_expr("$s.as_bytes()");
+        let to_bytes = mcx.parse_expr("$s.to_bytes()");
+        let take = mcx.parse_expr("$s.iter().take($n)");
+        let find = mcx.parse_expr("$s.find($c)");
+        let position = mcx.parse_expr("$s.bytes().position(|b| b == $c as u8)");
+        let position_ref = mcx.parse_expr("$s.iter().position(|&b| b == $c as u8)");
+        let len = mcx.parse_expr("$s.len()");
+
+        // The string that the argument `e` points to, if it's been converted.
+        let str_arg = |e: &P<Expr>| -> Option<(P<Expr>, StrKind)> {
+            if let Some(cast) = cstr_cast(e) {
+                if !is_valid_cstr(cast.bytes) {
+                    return None;
+                }
+                let s = std::str::from_utf8(&cast.bytes[..cast.bytes.len() - 1]).ok()?;
+                return Some((mk().lit_expr(s), StrKind::Str));
+            }
+            arg_pats.iter().find_map(|&(ref pat, kind)| {
+                let mut mcx = mcx.clone();
+                mcx.try_match(&**pat, &**e).ok()?;
+                Some((mcx.bindings.get::<_, P<Expr>>("$s")?.clone(), kind))
+            })
+        };
+        let subst = |template: &P<Expr>, s: P<Expr>, x: Option<(&str, P<Expr>)>| {
+            let mut bnd = Bindings::new();
+            bnd.add("$s", s);
+            if let Some((name, x)) = x {
+                bnd.add(name, x);
+            }
+            template.clone().subst(st, cx, &bnd)
+        };
+        // The bytes of the string `s`, as a `&[u8]`.
+        let bytes = |(s, kind): (P<Expr>, StrKind)| match kind {
+            StrKind::Str => subst(&as_bytes, s, None),
+            StrKind::CStr => subst(&to_bytes, s, None),
+            StrKind::Bytes => s,
+        };
+
+        // (1) Comparisons of `strcmp` and `strncmp` with zero.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let (op, lhs, rhs) = match_or!([e.kind] ExprKind::Binary(op, ref lhs, ref rhs)
+                                           => (op.node, lhs, rhs); return);
+            if !op.is_comparison() {
+                return;
+            }
+            // `0 < strcmp(a, b)` means the same as `strcmp(b, a) < 0`.
+            let (call, swap) = match (int_value(lhs), int_value(rhs)) {
+                (_, Some(0)) => (lhs, false),
+                (Some(0), _) => (rhs, true),
+                _ => return,
+            };
+            let (args, n) = if let Some(args) = c_fn_args(cx, call, "strcmp") {
+                (args, None)
+            } else if let Some(args) = c_fn_args(cx, call, "strncmp") {
+                (args, Some(&args[2]))
+            } else {
+                return;
+            };
+            let (a, b) = match (str_arg(&args[0]), str_arg(&args[1])) {
+                (Some(a), Some(b)) => (a, b),
+                _ => return,
+            };
+            let (a, b) = if swap { (b, a) } else { (a, b) };
+            *e = match n {
+                None if a.1 == b.1 => mk().binary_expr(op, a.0, b.0),
+                None => mk().binary_expr(op, bytes(a), bytes(b)),
+                Some(n) => {
+                    let n = match n.kind {
+                        ExprKind::Cast(ref inner, _) if int_value(inner).is_some() => {
+                            inner.clone()
+                        }
+                        _ if cx.opt_node_type(n.id)
+                            .map_or(false, |ty| ty.kind == TyKind::Uint(ast::UintTy::Usize)) => {
+                            n.clone()
+                        }
+                        _ => mk().cast_expr(n.clone(), mk().ident_ty("usize")),
+                    };
+                    let method = match op {
+                        BinOpKind::Eq => "eq",
+                        BinOpKind::Ne => "ne",
+                        BinOpKind::Lt => "lt",
+                        BinOpKind::Le => "le",
+                        BinOpKind::Gt => "gt",
+                        _ => "ge",
+                    };
+                    let a = subst(&take, bytes(a), Some(("$n", n.clone())));
+                    let b = subst(&take, bytes(b), Some(("$n", n)));
+                    mk().method_call_expr(a, method, vec![b])
+                }
+            };
+        });
+
+        // (2) Null checks of `strchr`.  Negated checks go first, so that they become `is_some()`
+        // rather than `!...is_none()`.
+
+        let search = |e: &Expr| -> Option<P<Expr>> {
+            let (seg, args) = match_or!([e.kind] ExprKind::MethodCall(ref seg, ref args)
+                                        => (seg, args); return None);
+            if seg.ident.as_str() != "is_null" {
+                return None;
+            }
+            let args = c_fn_args(cx, &args[0], "strchr")?;
+            let (s, kind) = str_arg(&args[0])?;
+            let c = &args[1];
+            if int_value(c) == Some(0) {
+                return None;
+            }
+            let char_lit = match c.kind {
+                ExprKind::Cast(ref inner, _) => match inner.kind {
+                    ExprKind::Lit(Lit { kind: LitKind::Char(_), .. }) => Some(inner.clone()),
+                    _ => None,
+                },
+                _ => None,
+            };
+            Some(match (kind, char_lit) {
+                (StrKind::Str, Some(ch)) => subst(&find, s, Some(("$c", ch))),
+                (StrKind::Str, None) => subst(&position, s, Some(("$c", c.clone()))),
+                _ => subst(&position_ref, bytes((s, kind)), Some(("$c", c.clone()))),
+            })
+        };
+        let no_args = Vec::<P<Expr>>::new();
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if let ExprKind::Unary(UnOp::Not, ref inner) = e.kind {
+                if let Some(found) = search(strip_parens(inner)) {
+                    *e = mk().method_call_expr(found, "is_some", no_args.clone());
+                }
+            }
+        });
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if let Some(found) = search(e) {
+                *e = mk().method_call_expr(found, "is_none", no_args.clone());
+            }
+        });
+
+        // (3) `strlen`.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let args = match_or!([c_fn_args(cx, e, "strlen")] Some(x) => x; return);
+            let (s, kind) = match_or!([str_arg(&args[0])] Some(x) => x; return);
+            let new_e = match kind {
+                StrKind::CStr => subst(&len, subst(&to_bytes, s, None), None),
+                _ => subst(&len, s, None),
+            };
+            *e = match cx.opt_node_type(e.id) {
+                Some(ty) if ty.kind != TyKind::Uint(ast::UintTy::Usize) => {
+                    mk().cast_expr(new_e, reflect_tcx_ty(cx.ty_ctxt(), ty))
+                }
+                _ => new_e,
+            };
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `convert_to_result` Command
+///
+/// Marks: `target`
+///
+/// For each function marked `target` that returns an integer status code, where every returned
+/// value is either the success code `SUCCESS` (default: 0) or some other constant error code,
+/// change the function to return `Result<(), T>`, where `T` is its old return type.  Returning
+/// `SUCCESS` becomes `Ok(())`, returning an error code `e` becomes `Err(e)`, and returning the
+/// result of calling another converted function is left as it is.  Error codes can be literals,
+/// like `-1`, or constants with literal values, like `EINVAL`.
+///
+/// Calls to the converted functions are updated:
+///
+///  * `f(...) != SUCCESS` and `f(...) == SUCCESS` become `f(...).is_err()` and `f(...).is_ok()`.
+///  * In converted functions, the error propagation pattern `let r = f(...); if r != SUCCESS {
+///    return r; }` becomes `f(...)?;`.
+///  * Calls whose result is ignored become `let _ = f(...);`.
+///  * Other uses of the status code become `f(...).err().unwrap_or(SUCCESS)`.
+///
+/// Exported functions (those with `#[no_mangle]` or `#[export_name]`) are changed as by
+/// `wrap_api`: the function itself becomes an internal, Rust ABI function returning `Result`, and
+/// a wrapper with its old ABI and symbol name converts the `Result` back to a status code.
+///
+/// A function isn't converted, with a warning, if it returns anything other than constants and
+/// the results of other converted functions, or if its address is taken and it isn't exported.
+pub struct ConvertToResult {
+    success: i128,
+}
+
+/// A value returned by a function being converted to return `Result`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum StatusValue {
+    Success,
+    Error,
+    /// The result of calling another function, which is returned as it is.
+    Propagate(DefId),
+}
+
+impl Transform for ConvertToResult {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+
+        // (1) Find the marked functions that return integers, and the constants with literal
+        // values that they may return.
+
+        let mut fns = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") || !matches!([i.kind] ItemKind::Fn(..)) {
+                return;
+            }
+            let did = cx.node_def_id(i.id);
+            if !tcx.fn_sig(did).skip_binder().output().is_integral() {
+                cx.session().span_warn(
+                    i.span,
+                    &format!("not converting `{}` to return `Result`: it doesn't return an \
+                              integer", i.ident),
+                );
+                return;
+            }
+            fns.insert(did, (i.ident, i.span, export_symbol(i)));
+        });
+
+        let mut consts = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if let ItemKind::Const(_, ref init) = i.kind {
+                if let Some(value) = int_value(init) {
+                    consts.insert(cx.node_def_id(i.id), value);
+                }
+            }
+        });
+
+        // (2) Find the error propagation patterns, `let r = f(...); if r != 0 { return r; }`.
+        // These map the `return r` value to `f`, and the `let` to the pattern.
+
+        let mut propagated = HashMap::new();
+        let mut patterns = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            let block = match_or!([i.kind] ItemKind::Fn(_, _, ref block) => block; return);
+            if !fns.contains_key(&cx.node_def_id(i.id)) {
+                return;
+            }
+            visit_nodes(&**block, |b: &Block| {
+                for pair in b.stmts.windows(2) {
+                    if let Some((callee, call, ret)) =
+                            self.propagation(cx, &fns, block, &pair[0], &pair[1]) {
+                        propagated.insert(ret, callee);
+                        patterns.insert(pair[0].id, call);
+                    }
+                }
+            });
+        });
+
+        // (3) Check the values each function returns, and whether its address is taken.
+
+        let mut returns = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            let block = match_or!([i.kind] ItemKind::Fn(_, _, ref block) => block; return);
+            let did = cx.node_def_id(i.id);
+            if !fns.contains_key(&did) {
+                return;
+            }
+            let mut values = Vec::new();
+            fold_output_exprs(&mut block.clone(), true, |e| {
+                values.push(self.status_value(cx, &fns, &consts, &propagated, e));
+            });
+            returns.insert(did, values);
+        });
+
+        let mut callees = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Call(ref callee, _) = e.kind {
+                callees.insert(callee.id);
+            }
+        });
+        let mut addr_taken = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if matches!([e.kind] ExprKind::Path(..)) && !callees.contains(&e.id) {
+                if let Some(did) = cx.try_resolve_expr(e) {
+                    addr_taken.insert(did);
+                }
+            }
+        });
+
+        let mut reasons = Vec::new();
+        let mut rejected = Vec::new();
+        for (&did, &(ident, span, ref symbol)) in &fns {
+            if returns[&did].iter().any(|v| v.is_none()) {
+                reasons.push((span, ident, "it returns a value that isn't a constant"));
+                rejected.push(did);
+            } else if addr_taken.contains(&did) && symbol.is_none() {
+                reasons.push((span, ident, "its address is taken"));
+                rejected.push(did);
+            }
+        }
+        for did in rejected {
+            fns.remove(&did);
+        }
+        loop {
+            let removed = fns.iter().filter(|&(did, _)| {
+                returns[did].iter().any(|v| match *v {
+                    Some(StatusValue::Propagate(callee)) => !fns.contains_key(&callee),
+                    _ => false,
+                })
+            }).map(|(&did, _)| did).collect::<Vec<_>>();
+            if removed.is_empty() {
+                break;
+            }
+            for did in removed {
+                let (ident, span, _) = fns.remove(&did).unwrap();
+                reasons.push((span, ident, "it returns the result of a function that can't be \
+                                           converted"));
+            }
+        }
+        reasons.sort_by_key(|&(span, _, _)| span);
+        for (span, ident, reason) in reasons {
+            cx.session().span_warn(
+                span,
+                &format!("not converting `{}` to return `Result`: {}", ident, reason),
+            );
+        }
+
+        // (4) Rewrite the converted functions' signatures and return values.
+
+        let mut handled = HashSet::new();
+        let mut old_decls = HashMap::new();
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let did = match_or!([i.kind] ItemKind::Fn(..) => cx.node_def_id(i.id);
+                                return smallvec![i]);
+            if !fns.contains_key(&did) {
+                return smallvec![i];
+            }
+            smallvec![i.map(|mut i| {
+                let (sig, block) = expect!([i.kind]
+                    ItemKind::Fn(ref mut sig, _, ref mut block) => (sig, block));
+                old_decls.insert(did, (sig.decl.clone(), sig.header.ext));
+
+                fold_output_exprs(block, true, |e| {
+                    let value = self.status_value(cx, &fns, &consts, &propagated, e).unwrap();
+                    *e = match value {
+                        StatusValue::Success => {
+                            mk().call_expr(mk().path_expr(vec!["Ok"]),
+                                           vec![mk().tuple_expr(Vec::<P<Expr>>::new())])
+                        }
+                        StatusValue::Error => {
+                            mk().call_expr(mk().path_expr(vec!["Err"]), vec![e.clone()])
+                        }
+                        StatusValue::Propagate(_) => {
+                            handled.insert(strip_parens(e).id);
+                            return;
+                        }
+                    };
+                });
+
+                MutVisitNodes::visit(block, |b: &mut P<Block>| {
+                    let mut stmts = Vec::with_capacity(b.stmts.len());
+                    let mut skip = false;
+                    for s in b.stmts.drain(..) {
+                        if skip {
+                            skip = false;
+                            continue;
+                        }
+                        match patterns.get(&s.id) {
+                            Some(call) => {
+                                handled.insert(call.id);
+                                stmts.push(mk().semi_stmt(P(Expr {
+                                    id: DUMMY_NODE_ID,
+                                    kind: ExprKind::Try(call.clone()),
+                                    span: s.span,
+                                    attrs: ThinVec::new(),
+                                })));
+                                skip = true;
+                            }
+                            None => stmts.push(s),
+                        }
+                    }
+                    b.stmts = stmts;
+                });
+
+                let ret_ty = expect!([sig.decl.output] FunctionRetTy::Ty(ref ty) => ty.clone());
+                let result_ty = parse_ty(cx.session(),
+                                         &format!("Result<(), {}>", pprust::ty_to_string(&ret_ty)));
+                sig.decl = sig.decl.clone().map(|decl| FnDecl {
+                    output: FunctionRetTy::Ty(result_ty),
+                    .. decl
+                });
+                i
+            })]
+        });
+
+        // (5) Update the calls to the converted functions.
+
+        krate.visit(&mut ResultCallFolder {
+            cx,
+            fns: &fns,
+            handled,
+            success: self.success,
+        });
+
+        // (6) Add wrappers for the exported functions, and use them where the functions' addresses
+        // are taken.
+
+        let mut wrapper_map = HashMap::new();
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let did = match_or!([i.kind] ItemKind::Fn(..) => cx.node_def_id(i.id);
+                                return smallvec![i]);
+            let symbol = match fns.get(&did) {
+                Some(&(_, _, Some(symbol))) => symbol,
+                _ => return smallvec![i],
+            };
+            let (ref decl, old_ext) = old_decls[&did];
+            let i = unexport(i);
+            let wrapper_name = format!("{}_wrapper", symbol.as_str());
+            let wrapper = api_wrapper(&i, decl, old_ext, symbol, &wrapper_name, |call| {
+                let err = mk().method_call_expr(call, "err", Vec::<P<Expr>>::new());
+                mk().method_call_expr(err, "unwrap_or", vec![int_expr(self.success)])
+            });
+            wrapper_map.insert(did, wrapper_name);
+            smallvec![i, wrapper]
+        });
+
+        fold_resolved_paths_with_id(krate, cx, |id, q, p, d| {
+            if callees.contains(&id) || q.is_some() {
+                return (q, p);
+            }
+            let did = match_or!([d[0]] Res::Def(_, did) => did; return (q, p));
+            let name = match_or!([wrapper_map.get(&did)] Some(x) => x; return (q, p));
+
+            let mut new_path = p.clone();
+            new_path.segments.pop();
+            new_path.segments.push(mk().path_segment(name));
+            (q, new_path)
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+impl ConvertToResult {
+    /// Classify the value `e` returned by a function being converted.  Returns `None` if it isn't
+    /// a status code that can be converted.
+    fn status_value<T>(&self, cx: &RefactorCtxt, fns: &HashMap<DefId, T>,
+                       consts: &HashMap<DefId, i128>, propagated: &HashMap<NodeId, DefId>,
+                       e: &Expr) -> Option<StatusValue> {
+        let e = strip_parens(e);
+        if let Some(&callee) = propagated.get(&e.id) {
+            return Some(StatusValue::Propagate(callee));
+        }
+        if let ExprKind::Call(..) = e.kind {
+            return cx.opt_callee(e).filter(|callee| fns.contains_key(callee))
+                .map(StatusValue::Propagate);
+        }
+        let value = int_value(e).or_else(|| {
+            cx.try_resolve_expr(e).and_then(|did| consts.get(&did).cloned())
+        })?;
+        if value == self.success {
+            Some(StatusValue::Success)
+        } else {
+            Some(StatusValue::Error)
+        }
+    }
+
+    /// Check whether the statements `s1` and `s2` in the function body `body` are the error
+    /// propagation pattern `let r = f(...); if r != 0 { return r; }`, where `r` isn't used
+    /// anywhere else and `f` is a function being converted.  Returns `f`, the call, and the ID of
+    /// the returned expression.
+    fn propagation<T>(&self, cx: &RefactorCtxt, fns: &HashMap<DefId, T>, body: &Block,
+                      s1: &Stmt, s2: &Stmt) -> Option<(DefId, P<Expr>, NodeId)> {
+        let local = match_or!([s1.kind] StmtKind::Local(ref l) => l; return None);
+        match_or!([local.pat.kind] PatKind::Ident(BindingMode::ByValue(_), _, None) => ();
+                  return None);
+        let call = local.init.as_ref()?;
+        let callee = cx.opt_callee(call).filter(|callee| fns.contains_key(callee))?;
+        let var = cx.hir_map().node_to_hir_id(local.pat.id);
+        let is_var = |e: &Expr| cx.try_resolve_expr_hir(strip_parens(e)) == Some(Res::Local(var));
+
+        let if_expr = match s2.kind {
+            StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => e,
+            _ => return None,
+        };
+        let (cond, then) = match_or!([if_expr.kind] ExprKind::If(ref cond, ref then, None) =>
+                                     (cond, then); return None);
+        let (op, lhs, rhs) = match_or!([strip_parens(cond).kind]
+                                       ExprKind::Binary(op, ref lhs, ref rhs) => (op, lhs, rhs);
+                                       return None);
+        let is_success = |e: &Expr| int_value(e) == Some(self.success);
+        if op.node != BinOpKind::Ne ||
+           !((is_var(lhs) && is_success(rhs)) || (is_success(lhs) && is_var(rhs))) {
+            return None;
+        }
+
+        if then.stmts.len() != 1 {
+            return None;
+        }
+        let ret = match then.stmts[0].kind {
+            StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => e,
+            _ => return None,
+        };
+        let ret = match_or!([ret.kind] ExprKind::Ret(Some(ref e)) => e; return None);
+        if !is_var(ret) {
+            return None;
+        }
+
+        let mut uses = 0;
+        visit_nodes(body, |e: &Expr| {
+            if matches!([e.kind] ExprKind::Path(..)) && is_var(e) {
+                uses += 1;
+            }
+        });
+        if uses != 2 {
+            return None;
+        }
+
+        Some((callee, call.clone(), strip_parens(ret).id))
+    }
+}
+
+/// Updates calls to functions converted to return `Result`.  Calls in `handled` have already been
+/// updated.
+struct ResultCallFolder<'a, 'b, 'tcx, T> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    fns: &'a HashMap<DefId, T>,
+    handled: HashSet<NodeId>,
+    success: i128,
+}
+
+impl<'a, 'b, 'tcx, T> ResultCallFolder<'a, 'b, 'tcx, T> {
+    /// Is `e` an unhandled call to a converted function?
+    fn is_call(&self, e: &Expr) -> bool {
+        matches!([e.kind] ExprKind::Call(..)) && !self.handled.contains(&e.id) &&
+            self.cx.opt_callee(e).map_or(false, |callee| self.fns.contains_key(&callee))
+    }
+}
+
+impl<'a, 'b, 'tcx, T> MutVisitor for ResultCallFolder<'a, 'b, 'tcx, T> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        let mut new_expr = None;
+        match e.kind {
+            ExprKind::Binary(op, ref lhs, ref rhs)
+                    if op.node == BinOpKind::Eq || op.node == BinOpKind::Ne => {
+                let call = if self.is_call(lhs) && int_value(rhs) == Some(self.success) {
+                    Some(lhs)
+                } else if self.is_call(rhs) && int_value(lhs) == Some(self.success) {
+                    Some(rhs)
+                } else {
+                    None
+                };
+                if let Some(call) = call {
+                    self.handled.insert(call.id);
+                    let method = if op.node == BinOpKind::Ne { "is_err" } else { "is_ok" };
+                    new_expr = Some(mk().method_call_expr(call.clone(), method,
+                                                          Vec::<P<Expr>>::new()));
+                }
+            }
+            ExprKind::Call(..) if self.is_call(e) => {
+                self.handled.insert(e.id);
+                let err = mk().method_call_expr(e.clone(), "err", Vec::<P<Expr>>::new());
+                new_expr = Some(mk().method_call_expr(err, "unwrap_or",
+                                                      vec![int_expr(self.success)]));
+            }
+            _ => {}
+        }
+        if let Some(new_expr) = new_expr {
+            *e = new_expr;
+        }
+        mut_visit::noop_visit_expr(e, self);
+    }
+
+    fn flat_map_stmt(&mut self, s: Stmt) -> SmallVec<[Stmt; 1]> {
+        let s = match s.kind {
+            StmtKind::Semi(ref call) if self.is_call(call) => {
+                self.handled.insert(call.id);
+                mk().local_stmt(P(mk().local(mk().wild_pat(), None as Option<P<Ty>>,
+                                             Some(call.clone()))))
+            }
+            _ => s,
+        };
+        mut_visit::noop_flat_map_stmt(s, self)
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+fn strip_parens(e: &Expr) -> &Expr {
+    match e.kind {
+        ExprKind::Paren(ref inner) => strip_parens(inner),
+        _ => e,
+    }
+}
+
+
+/// # `convert_nullable_return_to_option` Command
+///
+/// Marks: `target`
+///
+/// For each function marked `target` that returns a raw pointer `*mut T`, where every returned
+/// value is either null or known not to be null, change the function to return
+/// `Option<*mut T>`.  Returning `ptr::null_mut()` or `0 as *mut T` becomes `return None`, and
+/// returning a non-null pointer `p` becomes `return Some(p)`.  A returned pointer is known not to
+/// be null if it's the address of a place, like `&mut x as *mut T`, the result of
+/// `Box::into_raw`, or a variable bound by the first kind of null check below.  Returning the
+/// result of calling another converted function is left as it is.  `*const T` return types are
+/// handled the same way.
+///
+/// Calls to the converted functions are updated:
+///
+///  * `let p = f(...); if p.is_null() { return ...; }`, where the `if` body ends in `return`,
+///    `break` or `continue`, becomes `let p = match f(...) { Some(p) => p, None => { return ...; }
+///    };`.
+///  * `let p = f(...); if !p.is_null() { A } else { B }`, where `p` isn't used in `B` or after the
+///    `if`, becomes `if let Some(p) = f(...) { A } else { B }`.  `if p.is_null() { B } else { A }`
+///    becomes `match f(...) { Some(p) => { A } None => { B } }`.
+///  * `f(...).is_null()` and `!f(...).is_null()` become `f(...).is_none()` and `f(...).is_some()`.
+///  * Other uses of the pointer, like storing it into a struct field, become
+///    `f(...).map_or(ptr::null_mut(), |p| p)`.
+///
+/// Exported functions are changed as by `wrap_api`, with a wrapper that converts the `Option` back
+/// to a raw pointer.
+///
+/// A function isn't converted, with a warning, if it returns a pointer that may be null, or if its
+/// address is taken and it isn't exported.  The `Option` still holds a raw pointer; turning it into
+/// a reference is left to the ownership analysis.
+pub struct ConvertNullableReturnToOption;
+
+/// A value returned by a function being converted to return `Option`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum NullableValue {
+    Null,
+    NonNull,
+    /// A variable bound by a null check of the result of calling another function.
+    Checked(DefId),
+    /// The result of calling another function, which is returned as it is.
+    Propagate(DefId),
+}
+
+/// A null check of the result of calling a function being converted, `let p = f(...);` followed by
+/// `if p.is_null() { ... }`.
+#[derive(Clone, Copy, Debug)]
+struct NullCheck {
+    callee: DefId,
+    /// Whether the null branch leaves the block, so `p` can be unwrapped for the rest of it.
+    guard: bool,
+}
+
+impl Transform for ConvertNullableReturnToOption {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the marked functions that return raw pointers.
+
+        let mut fns = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            let sig = match_or!([i.kind] ItemKind::Fn(ref sig, ..) => sig; return);
+            if !st.marked(i.id, "target") {
+                return;
+            }
+            let mutbl = match sig.decl.output {
+                FunctionRetTy::Ty(ref ty) => match ty.kind {
+                    ast::TyKind::Ptr(ref mt) => Some(mt.mutbl),
+                    _ => None,
+                },
+                FunctionRetTy::Default(_) => None,
+            };
+            match mutbl {
+                Some(mutbl) => {
+                    fns.insert(cx.node_def_id(i.id), (i.ident, i.span, export_symbol(i), mutbl));
+                }
+                None => cx.session().span_warn(
+                    i.span,
+                    &format!("not converting `{}` to return `Option`: it doesn't return a raw \
+                              pointer", i.ident),
+                ),
+            }
+        });
+
+        // (2) Find the null checks of the results of calls to these functions, and the variables
+        // that are known not to be null after them.
+
+        let mut checks = HashMap::new();
+        let mut checked_vars = HashMap::new();
+        visit_nodes(krate, |b: &Block| {
+            for i in 0 .. b.stmts.len().saturating_sub(1) {
+                if let Some(check) = null_check(cx, &fns, &b.stmts[i..]) {
+                    checks.insert(b.stmts[i].id, check);
+                    let local = expect!([b.stmts[i].kind] StmtKind::Local(ref l) => l);
+                    let immutable = matches!([local.pat.kind]
+                        PatKind::Ident(BindingMode::ByValue(Mutability::Immutable), _, None));
+                    if check.guard && immutable {
+                        let var = cx.hir_map().node_to_hir_id(local.pat.id);
+                        checked_vars.insert(var, check.callee);
+                    }
+                }
+            }
+        });
+
+        // (3) Check the values each function returns, and whether its address is taken.
+
+        let mut returns = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            let block = match_or!([i.kind] ItemKind::Fn(_, _, ref block) => block; return);
+            let did = cx.node_def_id(i.id);
+            if !fns.contains_key(&did) {
+                return;
+            }
+            let mut values = Vec::new();
+            fold_output_exprs(&mut block.clone(), true, |e| {
+                values.push(nullable_value(cx, &fns, &checked_vars, e));
+            });
+            returns.insert(did, values);
+        });
+
+        let mut callees = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Call(ref callee, _) = e.kind {
+                callees.insert(callee.id);
+            }
+        });
+        let mut addr_taken = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if matches!([e.kind] ExprKind::Path(..)) && !callees.contains(&e.id) {
+                if let Some(did) = cx.try_resolve_expr(e) {
+                    addr_taken.insert(did);
+                }
+            }
+        });
+
+        let mut reasons = Vec::new();
+        let mut rejected = Vec::new();
+        for (&did, &(ident, span, ref symbol, _)) in &fns {
+            if returns[&did].iter().any(|v| v.is_none()) {
+                reasons.push((span, ident, "it returns a pointer that may be null"));
+                rejected.push(did);
+            } else if addr_taken.contains(&did) && symbol.is_none() {
+                reasons.push((span, ident, "its address is taken"));
+                rejected.push(did);
+            }
+        }
+        for did in rejected {
+            fns.remove(&did);
+        }
+        loop {
+            let removed = fns.iter().filter(|&(did, _)| {
+                returns[did].iter().any(|v| match *v {
+                    Some(NullableValue::Checked(callee)) |
+                    Some(NullableValue::Propagate(callee)) => !fns.contains_key(&callee),
+                    _ => false,
+                })
+            }).map(|(&did, _)| did).collect::<Vec<_>>();
+            if removed.is_empty() {
+                break;
+            }
+            for did in removed {
+                let (ident, span, _, _) = fns.remove(&did).unwrap();
+                reasons.push((span, ident, "it returns the result of a function that can't be \
+                                           converted"));
+            }
+        }
+        reasons.sort_by_key(|&(span, _, _)| span);
+        for (span, ident, reason) in reasons {
+            cx.session().span_warn(
+                span,
+                &format!("not converting `{}` to return `Option`: {}", ident, reason),
+            );
+        }
+
+        // (4) Rewrite the converted functions' signatures and return values.
+
+        let mut handled = HashSet::new();
+        let mut old_decls = HashMap::new();
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let did = match_or!([i.kind] ItemKind::Fn(..) => cx.node_def_id(i.id);
+                                return smallvec![i]);
+            if !fns.contains_key(&did) {
+                return smallvec![i];
+            }
+            smallvec![i.map(|mut i| {
+                let (sig, block) = expect!([i.kind]
+                    ItemKind::Fn(ref mut sig, _, ref mut block) => (sig, block));
+                old_decls.insert(did, (sig.decl.clone(), sig.header.ext));
+
+                fold_output_exprs(block, true, |e| {
+                    let value = nullable_value(cx, &fns, &checked_vars, e).unwrap();
+                    *e = match value {
+                        NullableValue::Null => mk().path_expr(vec!["None"]),
+                        NullableValue::NonNull | NullableValue::Checked(_) => {
+                            mk().call_expr(mk().path_expr(vec!["Some"]), vec![e.clone()])
+                        }
+                        NullableValue::Propagate(_) => {
+                            handled.insert(strip_parens(e).id);
+                            return;
+                        }
+                    };
+                });
+
+                let ret_ty = expect!([sig.decl.output] FunctionRetTy::Ty(ref ty) => ty.clone());
+                let option_ty = parse_ty(cx.session(),
+                                         &format!("Option<{}>", pprust::ty_to_string(&ret_ty)));
+                sig.decl = sig.decl.clone().map(|decl| FnDecl {
+                    output: FunctionRetTy::Ty(option_ty),
+                    .. decl
+                });
+                i
+            })]
+        });
+
+        // (5) Rewrite the null checks, and update the other calls to the converted functions.
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            if !b.stmts.iter().any(|s| checks.contains_key(&s.id)) {
+                return;
+            }
+            let mut stmts = Vec::with_capacity(b.stmts.len());
+            let mut old_stmts = b.stmts.drain(..);
+            while let Some(s) = old_stmts.next() {
+                let check = match checks.get(&s.id) {
+                    Some(check) if fns.contains_key(&check.callee) => check,
+                    _ => {
+                        stmts.push(s);
+                        continue;
+                    }
+                };
+                let if_stmt = old_stmts.next().unwrap();
+                let (call, s) = rewrite_null_check(s, if_stmt, check.guard);
+                handled.insert(call);
+                stmts.push(s);
+            }
+            drop(old_stmts);
+            b.stmts = stmts;
+        });
+
+        krate.visit(&mut NullableCallFolder {
+            cx,
+            fns: &fns,
+            handled,
+            unwrap_fn: parse_expr(cx.session(), "|p| p"),
+        });
+
+        // (6) Add wrappers for the exported functions, and use them where the functions' addresses
+        // are taken.
+
+        let mut wrapper_map = HashMap::new();
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let did = match_or!([i.kind] ItemKind::Fn(..) => cx.node_def_id(i.id);
+                                return smallvec![i]);
+            let (symbol, mutbl) = match fns.get(&did) {
+                Some(&(_, _, Some(symbol), mutbl)) => (symbol, mutbl),
+                _ => return smallvec![i],
+            };
+            let (ref decl, old_ext) = old_decls[&did];
+            let i = unexport(i);
+            let wrapper_name = format!("{}_wrapper", symbol.as_str());
+            let unwrap_fn = parse_expr(cx.session(), "|p| p");
+            let wrapper = api_wrapper(&i, decl, old_ext, symbol, &wrapper_name, |call| {
+                mk().method_call_expr(call, "map_or", vec![null_ptr_expr(mutbl), unwrap_fn])
+            });
+            wrapper_map.insert(did, wrapper_name);
+            smallvec![i, wrapper]
+        });
+
+        fold_resolved_paths_with_id(krate, cx, |id, q, p, d| {
+            if callees.contains(&id) || q.is_some() {
+                return (q, p);
+            }
+            let did = match_or!([d[0]] Res::Def(_, did) => did; return (q, p));
+            let name = match_or!([wrapper_map.get(&did)] Some(x) => x; return (q, p));
+
+            let mut new_path = p.clone();
+            new_path.segments.pop();
+            new_path.segments.push(mk().path_segment(name));
+            (q, new_path)
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Classify the value `e` returned by a function being converted to return `Option`.  Returns
+/// `None` if it may be null.
+fn nullable_value<T>(cx: &RefactorCtxt, fns: &HashMap<DefId, T>,
+                     checked_vars: &HashMap<hir::HirId, DefId>, e: &P<Expr>)
+                     -> Option<NullableValue> {
+    let tcx = cx.ty_ctxt();
+    let e = strip_casts(e);
+    match e.kind {
+        ExprKind::Lit(ref lit) => match lit.kind {
+            LitKind::Int(0, _) => Some(NullableValue::Null),
+            _ => None,
+        },
+        ExprKind::AddrOf(..) => Some(NullableValue::NonNull),
+        ExprKind::Call(..) => {
+            let callee = cx.opt_callee(e)?;
+            if fns.contains_key(&callee) {
+                Some(NullableValue::Propagate(callee))
+            } else if is_std_fn(cx, callee, "ptr", "null_mut") ||
+                      is_std_fn(cx, callee, "ptr", "null") {
+                Some(NullableValue::Null)
+            } else if tcx.def_kind(callee) == Some(DefKind::Method) &&
+                      tcx.item_name(callee).as_str() == "into_raw" &&
+                      tcx.parent(callee).map_or(false, |p| tcx.type_of(p).is_box()) {
+                Some(NullableValue::NonNull)
+            } else {
+                None
+            }
+        }
+        ExprKind::Path(..) => {
+            let var = cx.try_resolve_expr_to_hid(e)?;
+            checked_vars.get(&var).cloned().map(NullableValue::Checked)
+        }
+        _ => None,
+    }
+}
+
+/// Check whether `stmts` starts with a null check of the result of calling one of `fns`, `let p =
+/// f(...);` followed by `if p.is_null() { ... }` or `if !p.is_null() { ... }`, as recognized by
+/// `null_check_shape`.
+fn null_check<T>(cx: &RefactorCtxt, fns: &HashMap<DefId, T>, stmts: &[Stmt])
+                 -> Option<NullCheck> {
+    let local = match_or!([stmts[0].kind] StmtKind::Local(ref l) => l; return None);
+    let call = local.init.as_ref()?;
+    let callee = cx.opt_callee(call).filter(|callee| fns.contains_key(callee))?;
+    let guard = null_check_shape(cx, stmts)?;
+    Some(NullCheck { callee, guard })
+}
+
+/// Check whether `stmts` starts with `let p = ...;` followed by `if p.is_null() { ... }` or
+/// `if !p.is_null() { ... }`, and return whether the null branch leaves the block, so that `p`
+/// can be unwrapped for the rest of it.  The check is only recognized if the null branch ends by
+/// leaving the block, or if `p` isn't used in the null branch or after the `if`.
+fn null_check_shape(cx: &RefactorCtxt, stmts: &[Stmt]) -> Option<bool> {
+    let local = match_or!([stmts[0].kind] StmtKind::Local(ref l) => l; return None);
+    match_or!([local.pat.kind] PatKind::Ident(BindingMode::ByValue(_), _, None) => ();
+              return None);
+    local.init.as_ref()?;
+    let var = cx.hir_map().node_to_hir_id(local.pat.id);
+
+    let if_expr = match stmts[1].kind {
+        StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => e,
+        _ => return None,
+    };
+    let (cond, then, els) = match_or!([if_expr.kind] ExprKind::If(ref c, ref t, ref e) =>
+                                      (c, t, e); return None);
+    let (negated, recv) = is_null_call(cond)?;
+    if cx.try_resolve_expr_to_hid(recv) != Some(var) {
+        return None;
+    }
+
+    if !negated && els.is_none() && leaves_block(then) && !mentions_local(cx, &**then, var) {
+        return Some(true);
+    }
+    let null_branch_uses = match (negated, els) {
+        (false, _) => mentions_local(cx, &**then, var),
+        (true, &Some(ref els)) => mentions_local(cx, &**els, var),
+        (true, &None) => false,
+    };
+    if null_branch_uses || stmts[2..].iter().any(|s| mentions_local(cx, s, var)) {
+        return None;
+    }
+    Some(false)
+}
+
+/// If `e` is `p.is_null()` or `!p.is_null()`, return whether it's negated, and `p`.
+fn is_null_call(e: &Expr) -> Option<(bool, &P<Expr>)> {
+    let (negated, e) = match strip_parens(e).kind {
+        ExprKind::Unary(UnOp::Not, ref inner) => (true, strip_parens(inner)),
+        _ => (false, strip_parens(e)),
+    };
+    match e.kind {
+        ExprKind::MethodCall(ref seg, ref args)
+                if seg.ident.name.as_str() == "is_null" && args.len() == 1 => {
+            Some((negated, &args[0]))
+        }
+        _ => None,
+    }
+}
+
+/// Check whether `b` ends with `return`, `break` or `continue`.
+fn leaves_block(b: &Block) -> bool {
+    let last = match b.stmts.last().map(|s| &s.kind) {
+        Some(StmtKind::Expr(e)) | Some(StmtKind::Semi(e)) => e,
+        _ => return false,
+    };
+    matches!([last.kind] ExprKind::Ret(..), ExprKind::Break(..), ExprKind::Continue(..))
+}
+
+/// Rewrite the null check `local; if_stmt`, found by `null_check`, to use the `Option` returned by
+/// the call.  Returns the ID of the call and the new statement.
+fn rewrite_null_check(local: Stmt, if_stmt: Stmt, guard: bool) -> (NodeId, Stmt) {
+    let mut local = expect!([local.kind] StmtKind::Local(l) => l);
+    let call = local.init.take().unwrap();
+    let var = expect!([local.pat.kind] PatKind::Ident(_, ident, _) => ident);
+    let call_id = call.id;
+    let some_pat = |pat: P<Pat>| P(Pat {
+        id: DUMMY_NODE_ID,
+        kind: PatKind::TupleStruct(mk().path(vec!["Some"]), vec![pat]),
+        span: DUMMY_SP,
+    });
+
+    let (if_expr, semi) = match if_stmt.kind {
+        StmtKind::Expr(e) => (e, false),
+        StmtKind::Semi(e) => (e, true),
+        _ => unreachable!(),
+    };
+    let (cond, then, els) = expect!([if_expr.into_inner().kind]
+                                    ExprKind::If(c, t, e) => (c, t, e));
+    let negated = is_null_call(&cond).unwrap().0;
+
+    if guard {
+        let arms = vec![
+            mk().arm(some_pat(mk().ident_pat(var)), None, mk().ident_expr(var)),
+            mk().arm(mk().qpath_pat(None, vec!["None"]), None, mk().block_expr(then)),
+        ];
+        local.init = Some(mk().match_expr(call, arms));
+        return (call_id, mk().local_stmt(local));
+    }
+
+    let new_expr = if negated {
+        let cond = P(Expr {
+            id: DUMMY_NODE_ID,
+            kind: ExprKind::Let(some_pat(local.pat.clone()), call),
+            span: cond.span,
+            attrs: ThinVec::new(),
+        });
+        mk().ifte_expr(cond, then, els)
+    } else {
+        let null_branch = mk().block_expr(then);
+        let non_null_branch = els.unwrap_or_else(|| {
+            mk().block_expr(mk().block(Vec::<Stmt>::new()))
+        });
+        mk().match_expr(call, vec![
+            mk().arm(some_pat(local.pat.clone()), None, non_null_branch),
+            mk().arm(mk().qpath_pat(None, vec!["None"]), None, null_branch),
+        ])
+    };
+    let s = if semi { mk().semi_stmt(new_expr) } else { mk().expr_stmt(new_expr) };
+    (call_id, s)
+}
+
+/// Build `::std::ptr::null_mut()`, or `::std::ptr::null()` for `*const T` pointers.
+fn null_ptr_expr(mutbl: Mutability) -> P<Expr> {
+    let func = match mutbl {
+        Mutability::Mutable => "null_mut",
+        Mutability::Immutable => "null",
+    };
+    mk().call_expr(mk().path_expr(vec!["", "std", "ptr", func]), Vec::<P<Expr>>::new())
+}
+
+/// Updates calls to functions converted to return `Option`.  Calls in `handled` have already been
+/// updated.
+struct NullableCallFolder<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    fns: &'a HashMap<DefId, (Ident, Span, Option<Symbol>, Mutability)>,
+    handled: HashSet<NodeId>,
+    /// The closure `|p| p`, for unwrapping the `Option` in `map_or`.
+    unwrap_fn: P<Expr>,
+}
+
+impl<'a, 'b, 'tcx> NullableCallFolder<'a, 'b, 'tcx> {
+    /// If `e` is an unhandled call to a converted function, return the function's pointer
+    /// mutability.
+    fn call_mutbl(&self, e: &Expr) -> Option<Mutability> {
+        if !matches!([e.kind] ExprKind::Call(..)) || self.handled.contains(&e.id) {
+            return None;
+        }
+        self.cx.opt_callee(e).and_then(|callee| self.fns.get(&callee)).map(|&(.., m)| m)
+    }
+}
+
+impl<'a, 'b, 'tcx> MutVisitor for NullableCallFolder<'a, 'b, 'tcx> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        let mut new_expr = None;
+        if let Some((negated, recv)) = is_null_call(e) {
+            if self.call_mutbl(recv).is_some() {
+                self.handled.insert(recv.id);
+                let method = if negated { "is_some" } else { "is_none" };
+                new_expr = Some(mk().method_call_expr(recv.clone(), method,
+                                                      Vec::<P<Expr>>::new()));
+            }
+        } else if let Some(mutbl) = self.call_mutbl(e) {
+            self.handled.insert(e.id);
+            new_expr = Some(mk().method_call_expr(e.clone(), "map_or", vec![
+                null_ptr_expr(mutbl),
+                self.unwrap_fn.clone(),
+            ]));
+        }
+        if let Some(new_expr) = new_expr {
+            *e = new_expr;
+        }
+        mut_visit::noop_visit_expr(e, self);
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+
+/// # `convert_null_checks` Command
+///
+/// Rewrite null checks of pointers that were taken out of an `Option`, so that they match on the
+/// `Option` instead.  The pointer must be a local initialized by
+/// `$opt.map_or(ptr::null_mut(), |p| p)` or `$opt.unwrap_or(ptr::null_mut())`, where `$opt` has
+/// type `Option<_>`, like the calls that `convert_nullable_return_to_option` leaves behind for
+/// checks it doesn't recognize itself.  The check must directly follow the local:
+///
+///  * `let p = ...; if p.is_null() { return ...; }`, where the `if` body ends in `return`,
+///    `break` or `continue`, becomes `let p = match $opt { Some(p) => p, None => { return ...; }
+///    };`.
+///  * `let p = ...; if !p.is_null() { A } else { B }`, where `p` isn't used in `B` or after the
+///    `if`, becomes `if let Some(p) = $opt { A } else { B }`, and `if p.is_null() { B } else { A }`
+///    becomes `match $opt { Some(p) => { A } None => { B } }`.
+///
+/// `p` is bound to the same non-null pointer as before, so its uses in `A` and after a guard,
+/// like `(*p).len` or `(*(*p).next).key`, are left unchanged.
+pub struct ConvertNullChecks;
+
+impl Transform for ConvertNullChecks {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let producers = [
+            mcx.parse_expr("typed!($opt:Expr, ::core::option::Option<$t:Ty>)\
+                            .map_or($null:Expr, |p| p)"),
+            mcx.parse_expr("typed!($opt:Expr, ::core::option::Option<$t:Ty>)\
+                            .unwrap_or($null:Expr)"),
+        ];
+
+        // (1) Find the null checks of pointers taken out of an `Option`, along with the `Option`.
+
+        let mut checks = HashMap::new();
+        visit_nodes(krate, |b: &Block| {
+            for i in 0 .. b.stmts.len().saturating_sub(1) {
+                let init = match b.stmts[i].kind {
+                    StmtKind::Local(ref l) => match l.init {
+                        Some(ref init) => init,
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+                let opt = producers.iter().filter_map(|pat| {
+                    let mut mcx = mcx.clone();
+                    mcx.try_match(&**pat, &**init).ok()?;
+                    let null = mcx.bindings.get::<_, P<Expr>>("$null").unwrap();
+                    if !is_null_ptr(cx, null) {
+                        return None;
+                    }
+                    mcx.bindings.get::<_, P<Expr>>("$opt").cloned()
+                }).next();
+                let opt = match_or!([opt] Some(x) => x; continue);
+                if let Some(guard) = null_check_shape(cx, &b.stmts[i..]) {
+                    checks.insert(b.stmts[i].id, (guard, opt));
+                }
+            }
+        });
+
+        // (2) Rewrite them to use the `Option`.
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            if !b.stmts.iter().any(|s| checks.contains_key(&s.id)) {
+                return;
+            }
+            let mut stmts = Vec::with_capacity(b.stmts.len());
+            let mut old_stmts = b.stmts.drain(..);
+            while let Some(mut s) = old_stmts.next() {
+                let (guard, opt) = match checks.remove(&s.id) {
+                    Some(check) => check,
+                    None => {
+                        stmts.push(s);
+                        continue;
+                    }
+                };
+                if let StmtKind::Local(ref mut l) = s.kind {
+                    l.init = Some(opt);
+                }
+                let if_stmt = old_stmts.next().unwrap();
+                stmts.push(rewrite_null_check(s, if_stmt, guard).1);
+            }
+            drop(old_stmts);
+            b.stmts = stmts;
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Check whether `e` is a null pointer, like `ptr::null_mut()` or `0 as *mut T`.
+fn is_null_ptr(cx: &RefactorCtxt, e: &P<Expr>) -> bool {
+    let e = strip_casts(e);
+    match e.kind {
+        ExprKind::Lit(ref lit) => matches!([lit.kind] LitKind::Int(0, _)),
+        ExprKind::Call(..) => cx.opt_callee(e).map_or(false, |callee| {
+            is_std_fn(cx, callee, "ptr", "null_mut") || is_std_fn(cx, callee, "ptr", "null")
+        }),
+        _ => false,
+    }
+}
+
+
+/// # `abstract` Command
+///
+/// Replace all instances of `pat` with calls to a new function whose name and signature is given
+/// by `sig`.  Example:
+///
+/// Input:
+///
+/// ```ignore
+///     1 + 2
+/// ```
+///
+/// After running `abstract 'add(x: u32, y: u32) -> u32' 'x + y'`:
+///
+/// ```ignore
+///     add(1, 2)
+///
+///     // Elsewhere:
+///     fn add(x: u32, y: u32) -> u32 { x + y }
+/// ```
+///
+/// All type and value parameter names in `sig` act as bindings when matching `pat`.  The captured
+/// exprs and types are passed as parameters when building the new call expression.  The body of
+/// the function is `body`, if provided, otherwise `pat` itself.
+///
+/// Non-ident patterns in `sig` are not supported.  It is also an error for any type parameter's
+/// name to collide with any value parameter.
+///
+/// If matching with `pat` fails to capture expressions for any of the value parameters of `sig`,
+/// it is an error.  If it fails to capture for a type parameter, the parameter is filled in with
+/// `_` (infer).
+struct Abstract {
+    sig: String,
+    pat: String,
+    body: Option<String>,
+}
+
+impl Transform for Abstract {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let pat = parse_expr(cx.session(), &self.pat);
+
+        let func_src = format!("unsafe fn {} {{\n    {}\n}}",
+                               self.sig, self.body.as_ref().unwrap_or(&self.pat));
+        let func: P<Item> = st.parse_items(cx, &func_src).lone();
+        st.add_mark(func.id, sym::new);
+
+        // Build the call expression template
+
+        let mut value_args = Vec::new();
+        let mut type_args = Vec::new();
+        {
+            let (decl, generics) = expect!([func.kind]
+                    ItemKind::Fn(ref sig, ref gen, _) => (&sig.decl, gen));
+            for arg in &decl.inputs {
+                let name = expect!([arg.pat.kind] PatKind::Ident(_, ident, _) => ident);
+                value_args.push(name);
+            }
+            for param in &generics.params {
+                if let GenericParamKind::Type { .. } = param.kind {
+                    type_args.push(param.ident);
+                }
+            }
+        }
+
+        let aba = mk().angle_bracketed_args(
+            type_args.iter().map(|name| mk().ident_ty(name)).collect());
+        let seg = mk().path_segment_with_args(func.ident, aba);
+        let call_expr = mk().call_expr(
+            mk().path_expr(vec![seg]),
+            value_args.iter().map(|name| mk().ident_expr(name)).collect());
+
+        // Search and replace
+
+        let mut init_mcx = MatchCtxt::new(st, cx);
+        for name in &value_args {
+            init_mcx.set_type(name.name, BindingType::Expr);
+        }
+        for name in &type_args {
+            init_mcx.set_type(name.name, BindingType::Ty);
+        }
+
+        mut_visit_match_with(init_mcx, pat, krate, |ast, mut mcx| {
+            for name in &type_args {
+                if mcx.bindings.get::<_, P<Ty>>(name.name).is_none() {
+                    mcx.bindings.add(name.name, mk().infer_ty());
+                }
+            }
+            *ast = call_expr.clone().subst(st, cx, &mcx.bindings);
+        });
+
+        // Add the function definition to the crate
+
+        krate.module.items.push(func);
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `inline_fn` Command
+///
+/// Marks: `MARK`/`target`
+///
+/// Inline every call to a function marked `MARK` (default: `target`).  Each call is replaced with
+/// a block containing the function's body.  Arguments are evaluated in their original order into
+/// temporaries named after the parameters, except for immutable local variables, which are
+/// substituted directly.  Afterward, an inlined function with no remaining uses is deleted unless
+/// it's exported.
+///
+/// A trailing `return` in the body is converted to a tail expression.  Functions that return
+/// early (with `return` or `?`), that are recursive (directly or through other inlined
+/// functions), generic, or variadic, or that have non-identifier parameter patterns are left
+/// alone with a warning.
+pub struct InlineFn {
+    mark: Symbol,
+}
+
+/// A function that `inline_fn` will inline.
+struct InlineTarget {
+    item_id: NodeId,
+    ident: Ident,
+    span: Span,
+    module: hir::HirId,
+    params: Vec<InlineParam>,
+    /// The body, with any trailing `return` converted to a tail expression.
+    body: P<Block>,
+}
+
+struct InlineParam {
+    hir_id: hir::HirId,
+    ident: Ident,
+    mutbl: Mutability,
+    ty: P<Ty>,
+}
+
+impl Transform for InlineFn {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let hir_map = cx.hir_map();
+
+        let mut targets = HashMap::new();
+        let mut callees = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, self.mark) {
+                return;
+            }
+            let (sig, generics, block) = match_or!([&i.kind]
+                ItemKind::Fn(sig, generics, block) => (sig, generics, block); return);
+            let skip = |reason: &str| {
+                cx.session().span_warn(i.span, &format!("not inlining `{}`: {}", i.ident, reason))
+            };
+
+            if sig.decl.c_variadic() {
+                return skip("it's variadic");
+            }
+            if generics.params.iter().any(|p| !matches!([p.kind] GenericParamKind::Lifetime)) {
+                return skip("it's generic");
+            }
+
+            let mut params = Vec::new();
+            for arg in &sig.decl.inputs {
+                match &arg.pat.kind {
+                    &PatKind::Ident(BindingMode::ByValue(mutbl), ident, None) => {
+                        params.push(InlineParam {
+                            hir_id: hir_map.node_to_hir_id(arg.pat.id),
+                            ident,
+                            mutbl,
+                            ty: arg.ty.clone(),
+                        });
+                    }
+                    _ => return skip("it has a parameter pattern that isn't an identifier"),
+                }
+            }
+
+            let mut body = block.clone();
+            strip_trailing_return(&mut body);
+            if returns_early(&body) {
+                return skip("it returns early");
+            }
+
+            let def_id = cx.node_def_id(i.id);
+            let mut called = HashSet::new();
+            visit_nodes(&*body, |e: &Expr| {
+                if let Some(callee) = cx.opt_callee(e) {
+                    called.insert(callee);
+                }
+            });
+            callees.insert(def_id, called);
+
+            let hir_id = hir_map.node_to_hir_id(i.id);
+            targets.insert(def_id, InlineTarget {
+                item_id: i.id,
+                ident: i.ident,
+                span: i.span,
+                module: hir_map.get_module_parent_node(hir_id),
+                params,
+                body,
+            });
+        });
+
+        // Inlining a function that can reach itself would never finish.
+        let recursive = targets.keys()
+            .filter(|&&def_id| calls_itself(&callees, def_id))
+            .cloned()
+            .collect::<Vec<_>>();
+        for def_id in recursive {
+            let target = targets.remove(&def_id).unwrap();
+            cx.session().span_warn(
+                target.span,
+                &format!("not inlining `{}`: it's recursive", target.ident),
+            );
+        }
+
+        krate.visit(&mut Inliner { cx, targets: &targets });
+
+        // Delete inlined functions that are no longer used.
+        let mut used = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Path(..) = e.kind {
+                if let Some(def_id) = cx.try_resolve_expr(e) {
+                    used.insert(def_id);
+                }
+            }
+        });
+        let mut imported = HashSet::new();
+        visit_nodes(krate, |i: &Item| {
+            if let ItemKind::Use(tree) = &i.kind {
+                use_tree_names(tree, &mut imported);
+            }
+        });
+        let dead = targets.iter()
+            .filter(|&(def_id, target)| {
+                !used.contains(def_id) && !imported.contains(&target.ident.name)
+            })
+            .map(|(_, target)| target.item_id)
+            .collect::<HashSet<_>>();
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            if dead.contains(&i.id) && !is_exported(&i) {
+                return smallvec![];
+            }
+            smallvec![i]
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+struct Inliner<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    targets: &'a HashMap<DefId, InlineTarget>,
+}
+
+impl<'a, 'tcx> Inliner<'a, 'tcx> {
+    /// Build the expression that replaces the call `call`, whose arguments are `args`.
+    fn inline_call(&self, call: &Expr, target: &InlineTarget, args: &[P<Expr>]) -> P<Expr> {
+        let cx = self.cx;
+        let hir_map = cx.hir_map();
+
+        let mut body = target.body.clone();
+        let mut param_tys = target.params.iter().map(|p| p.ty.clone()).collect::<Vec<_>>();
+        let call_hir_id = hir_map.node_to_hir_id(call.id);
+        if hir_map.get_module_parent_node(call_hir_id) != target.module {
+            // Paths in the body were written relative to the function's module.
+            canonicalize_paths(&mut body, cx);
+            canonicalize_paths(&mut param_tys, cx);
+        }
+
+        let param_names = target.params.iter().map(|p| p.ident.name).collect::<HashSet<_>>();
+        let body_bindings = bound_names(&*body);
+        let mut taken = mentioned_names(&*body);
+        for arg in args {
+            taken.extend(mentioned_names(&**arg));
+        }
+        taken.extend(param_names.iter().cloned());
+
+        // Decide which arguments need temporaries.
+        let direct = target.params.iter().zip(args).map(|(p, arg)| {
+            p.mutbl == Mutability::Immutable && self.immutable_local(arg).map_or(false, |name| {
+                !body_bindings.contains(&name) &&
+                    (name == p.ident.name || !param_names.contains(&name))
+            })
+        }).collect::<Vec<_>>();
+
+        let mut temps = Vec::new();
+        let mut subst = HashMap::new();
+        for (i, (p, arg)) in target.params.iter().zip(args).enumerate() {
+            if direct[i] {
+                subst.insert(p.hir_id, arg.clone());
+                continue;
+            }
+
+            // The temporary can't shadow anything that a later argument refers to.
+            let later_names = args[i + 1..].iter().zip(&direct[i + 1..])
+                .filter(|&(_, &direct)| !direct)
+                .flat_map(|(arg, _)| mentioned_names(&**arg))
+                .collect::<HashSet<_>>();
+            let mut name = p.ident.name;
+            if later_names.contains(&name) {
+                let mut counter = 1;
+                while taken.contains(&name) {
+                    name = Symbol::intern(&format!("{}_{}", p.ident, counter));
+                    counter += 1;
+                }
+                taken.insert(name);
+                subst.insert(p.hir_id, mk().ident_expr(name));
+            }
+
+            let pat = mk().set_mutbl(p.mutbl).ident_pat(name);
+            let local = mk().local(pat, Some(param_tys[i].clone()), Some(arg.clone()));
+            temps.push(mk().local_stmt(P(local)));
+        }
+
+        MutVisitNodes::visit(&mut body, |e: &mut P<Expr>| {
+            if !matches!([e.kind] ExprKind::Path(..)) {
+                return;
+            }
+            if let Some(Res::Local(hir_id)) = cx.try_resolve_expr_hir(e) {
+                if let Some(arg) = subst.get(&hir_id) {
+                    *e = arg.clone();
+                }
+            }
+        });
+
+        let body = body.into_inner();
+        if temps.is_empty() && body.stmts.len() == 1 {
+            if let StmtKind::Expr(e) = &body.stmts[0].kind {
+                return e.clone();
+            }
+        }
+        let mut stmts = temps;
+        stmts.extend(body.stmts);
+        mk().block_expr(mk().block(stmts))
+    }
+
+    /// If `e` names an immutable local variable that's used without any adjustments, return
+    /// its name.
+    fn immutable_local(&self, e: &Expr) -> Option<Symbol> {
+        let path = match_or!([&e.kind] ExprKind::Path(None, path) => path; return None);
+        if path.segments.len() != 1 {
+            return None;
+        }
+        let hir_id = match_or!([self.cx.try_resolve_expr_hir(e)]
+            Some(Res::Local(hir_id)) => hir_id; return None);
+        let pat = match_or!([self.cx.hir_map().find(hir_id)]
+            Some(hir::Node::Binding(pat)) => pat; return None);
+        if !matches!([pat.kind] hir::PatKind::Binding(hir::BindingAnnotation::Unannotated, ..)) {
+            return None;
+        }
+        if self.cx.opt_node_type(e.id) != self.cx.opt_adjusted_node_type(e.id) {
+            return None;
+        }
+        Some(path.segments[0].ident.name)
+    }
+}
+
+impl<'a, 'tcx> MutVisitor for Inliner<'a, 'tcx> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        let call: &Expr = e;
+        let inlined = match &call.kind {
+            ExprKind::Call(_, args) => self.cx.opt_callee(call)
+                .and_then(|def_id| self.targets.get(&def_id))
+                .map(|target| self.inline_call(call, target, args)),
+            _ => None,
+        };
+        if let Some(inlined) = inlined {
+            *e = inlined;
+        }
+        // Also inline calls in the arguments and in the inlined body.
+        mut_visit::noop_visit_expr(e, self)
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+/// Turn a `return` at the end of `block` into a tail expression.
+fn strip_trailing_return(block: &mut Block) {
+    let ret = match block.stmts.last() {
+        Some(Stmt { kind: StmtKind::Semi(e), .. }) |
+        Some(Stmt { kind: StmtKind::Expr(e), .. }) => match &e.kind {
+            ExprKind::Ret(val) => val.clone(),
+            _ => return,
+        },
+        _ => return,
+    };
+    block.stmts.pop();
+    if let Some(val) = ret {
+        block.stmts.push(mk().expr_stmt(val));
+    }
+}
+
+/// Check whether `block` contains a `return` or `?` outside of any closures or nested items.
+fn returns_early(block: &Block) -> bool {
+    struct ReturnFinder {
+        found: bool,
+    }
+
+    impl<'ast> Visitor<'ast> for ReturnFinder {
+        fn visit_expr(&mut self, e: &'ast Expr) {
+            match e.kind {
+                ExprKind::Ret(..) | ExprKind::Try(..) => self.found = true,
+                ExprKind::Closure(..) => return,
+                _ => {}
+            }
+            visit::walk_expr(self, e)
+        }
+
+        fn visit_item(&mut self, _i: &'ast Item) {}
+
+        fn visit_mac(&mut self, mac: &'ast Mac) {
+            visit::walk_mac(self, mac)
+        }
+    }
+
+    let mut finder = ReturnFinder { found: false };
+    visit::walk_block(&mut finder, block);
+    finder.found
+}
+
+/// Check whether the inlined function `def_id` can call itself through other inlined functions.
+fn calls_itself(callees: &HashMap<DefId, HashSet<DefId>>, def_id: DefId) -> bool {
+    let mut seen = HashSet::new();
+    let mut stack = callees[&def_id].iter().cloned().collect::<Vec<_>>();
+    while let Some(callee) = stack.pop() {
+        if callee == def_id {
+            return true;
+        }
+        if seen.insert(callee) {
+            if let Some(next) = callees.get(&callee) {
+                stack.extend(next.iter().cloned());
+            }
+        }
+    }
+    false
+}
+
+/// Rewrite paths to items in the current crate as absolute paths.
+pub(crate) fn canonicalize_paths<T: MutVisit>(target: &mut T, cx: &RefactorCtxt) {
+    fold_resolved_paths(target, cx, |qself, path, defs| {
+        match defs.get(0) {
+            Some(&Res::Def(kind, def_id)) if def_id.is_local() &&
+                !matches!([kind] DefKind::TyParam, DefKind::ConstParam) => cx.def_qpath(def_id),
+            _ => (qself, path),
+        }
+    });
+}
+
+/// Collect the names of all variables bound in `target`.
+pub(crate) fn bound_names<T: Visit>(target: &T) -> HashSet<Symbol> {
+    let mut names = HashSet::new();
+    visit_nodes(target, |p: &Pat| {
+        if let PatKind::Ident(_, ident, _) = p.kind {
+            names.insert(ident.name);
+        }
+    });
+    names
+}
+
+/// Collect every name that `target` binds or that begins a path in it.
+pub(crate) fn mentioned_names<T: Visit>(target: &T) -> HashSet<Symbol> {
+    let mut names = bound_names(target);
+    visit_nodes(target, |path: &Path| {
+        if let Some(seg) = path.segments.first() {
+            names.insert(seg.ident.name);
+        }
+    });
+    names
+}
+
+/// Collect the names that a `use` tree imports.
+pub(crate) fn use_tree_names(tree: &UseTree, names: &mut HashSet<Symbol>) {
+    match &tree.kind {
+        UseTreeKind::Simple(..) => {
+            names.insert(tree.ident().name);
+            if let Some(seg) = tree.prefix.segments.last() {
+                names.insert(seg.ident.name);
+            }
+        }
+        UseTreeKind::Nested(trees) => {
+            for (tree, _) in trees {
+                use_tree_names(tree, names);
+            }
+        }
+        UseTreeKind::Glob => {}
+    }
+}
+
+
+/// # `convert_callback_to_closure` Command
+///
+/// Marks: `target`
+///
+/// Convert C-style callbacks, a function pointer plus a `*mut c_void` context pointer that gets
+/// passed back to it, into closures.  A higher-order function is converted if it takes exactly
+/// one callback (a function pointer, or an `Option` of one) and one `c_void` pointer, and only
+/// ever calls the callback, passing the context pointer straight through.  Every call of it
+/// must look like
+///
+/// ```ignore
+/// let mut ctx = Ctx { key: 3, hits: 0 };
+/// sort_with(items, Some(compare), &mut ctx as *mut Ctx as *mut libc::c_void);
+/// ```
+///
+/// where `compare` is a function of this crate used nowhere else, and `Ctx` is a struct used
+/// only by `compare` and the caller, which only uses `ctx` to access its fields.  Then
+///
+///  * the higher-order function takes `mut cmp: impl FnMut(..)` instead of the callback and
+///    the context pointer, and calls `cmp(..)` directly;
+///  * the fields of `ctx` become locals of the caller, e.g., `let mut key: i32 = 3;`, and
+///    `ctx.key` becomes `key`;
+///  * the callback becomes a closure at the call site, with its `(*ctx).key` accesses
+///    replaced by the captured locals; and
+///  * the callback and the context struct are deleted.
+///
+/// If any calls are marked `target`, only higher-order functions whose calls are all marked
+/// are converted.  Calls that pass a null context, or a callback or context struct that is
+/// shared with other code, are skipped, along with every other call of the same function.
+///
+/// Once done, prints how many calls were converted or skipped for each reason.  If `-o REPORT`
+/// is passed, their source locations are also written to the `REPORT` file as JSON.
+pub struct ConvertCallbackToClosure {
+    pub report_path: Option<String>,
+}
+
+/// A function taking a callback and the context pointer that gets passed back to it.
+struct CallbackHof {
+    item_id: NodeId,
+    /// The parameter indices of the callback and the context pointer.
+    fn_idx: usize,
+    ctx_idx: usize,
+    /// The parameter index of the context pointer in the callback's signature.
+    cb_ctx_idx: usize,
+    fn_ident: Ident,
+    /// The `impl FnMut(..)` type that replaces the callback's type.
+    closure_ty: P<Ty>,
+    /// The calls of the callback in the function's body.
+    calls: HashSet<NodeId>,
+}
+
+/// A call of a `CallbackHof` with a local callback and context struct.
+struct CallbackSite {
+    call_id: NodeId,
+    span: Span,
+    /// The argument indices of the callback and the context pointer.
+    fn_idx: usize,
+    ctx_idx: usize,
+    callback_id: NodeId,
+    ctx_struct_id: NodeId,
+    ctx_local_id: NodeId,
+    /// The locals that replace the context's fields, with their types and initializers.
+    field_locals: Vec<(Symbol, P<Ty>, P<Expr>)>,
+    /// The `ctx.field` accesses in the caller, with the locals that replace them.
+    field_uses: HashMap<NodeId, Symbol>,
+    closure: P<Expr>,
+}
+
+impl Transform for ConvertCallbackToClosure {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        st.start_report("convert_callback_to_closure", self.report_path.clone());
+        let target = "target".into_symbol();
+        let has_marks = st.marks().iter().any(|&(_, label)| label == target);
+        let targets = if has_marks {
+            Some(marked_exprs(krate, target, st))
+        } else {
+            None
+        };
+
+        // (1) Find the functions and structs of the crate, and where they're used.
+
+        let mut fns = HashMap::new();
+        let mut structs = HashMap::new();
+        visit_nodes(krate, |i: &Item| match i.kind {
+            ItemKind::Fn(..) => {
+                fns.insert(cx.node_def_id(i.id), i.clone());
+            }
+            ItemKind::Struct(VariantData::Struct(ref fields, _), _) => {
+                let fields = fields.iter()
+                    .filter_map(|f| Some((f.ident?.name, f.ty.clone())))
+                    .collect::<HashMap<_, _>>();
+                structs.insert(cx.node_def_id(i.id), (i.id, i.span, fields));
+            }
+            _ => {}
+        });
+
+        let mut refs: HashMap<DefId, Vec<Span>> = HashMap::new();
+        visit_nodes(krate, |e: &Expr| {
+            let did = match e.kind {
+                ExprKind::Path(..) => cx.try_resolve_expr(e),
+                ExprKind::Struct(..) => cx.opt_node_type(e.id).and_then(adt_did),
+                _ => None,
+            };
+            if let Some(did) = did {
+                refs.entry(did).or_default().push(e.span);
+            }
+        });
+        visit_nodes(krate, |t: &Ty| {
+            if let Some(did) = cx.try_resolve_ty(t) {
+                refs.entry(did).or_default().push(t.span);
+            }
+        });
+        let ref_count = |did: &DefId| refs.get(did).map_or(0, |spans| spans.len());
+
+        // (2) Find the higher-order functions and analyze their calls.
+
+        let hofs = fns.iter()
+            .filter_map(|(&did, i)| Some((did, callback_hof(cx, i)?)))
+            .collect::<HashMap<_, _>>();
+
+        let mut sites: HashMap<DefId, Vec<Result<CallbackSite, (&str, Span)>>> = HashMap::new();
+        for caller in fns.values() {
+            let block = expect!([caller.kind] ItemKind::Fn(_, _, ref block) => block);
+            visit_nodes(&**block, |e: &Expr| {
+                let callee = match_or!([e.kind] ExprKind::Call(ref callee, _) => callee; return);
+                let hof_did = match_or!([cx.try_resolve_expr(callee)] Some(x) => x; return);
+                let hof = match_or!([hofs.get(&hof_did)] Some(x) => x; return);
+                let site = match targets {
+                    Some(ref targets) if !targets.contains(&e.id) => Err("call not marked"),
+                    _ => callback_site(cx, &fns, &structs, &refs, hof, caller, e),
+                };
+                sites.entry(hof_did).or_default().push(site.map_err(|reason| (reason, e.span)));
+            });
+        }
+
+        // (3) Pick the functions whose calls can all be converted.
+
+        let mut converted_hofs = HashMap::new();
+        let mut converted = Vec::new();
+        for (hof_did, hof_sites) in sites {
+            // Any other use of the function would still pass it a function pointer
+            let all_ok = ref_count(&hof_did) == hof_sites.len() &&
+                hof_sites.iter().all(|site| site.is_ok());
+            for site in hof_sites {
+                match site {
+                    Ok(site) => if all_ok {
+                        st.report(cx, "converted", site.span);
+                        converted.push(site);
+                    } else {
+                        st.report(cx, "other calls can't be converted", site.span);
+                    },
+                    Err((reason, span)) => st.report(cx, reason, span),
+                }
+            }
+            if all_ok {
+                let hof = &hofs[&hof_did];
+                converted_hofs.insert(hof.item_id, hof);
+            }
+        }
+        if converted.is_empty() {
+            return;
+        }
+
+        // (4) Rewrite the higher-order functions, the calls and the callers.
+
+        let mut hof_calls = HashMap::new();
+        for hof in converted_hofs.values() {
+            for &id in &hof.calls {
+                hof_calls.insert(id, (hof.fn_ident, hof.cb_ctx_idx));
+            }
+        }
+        let mut site_calls = HashMap::new();
+        let mut field_uses = HashMap::new();
+        let mut ctx_locals = HashMap::new();
+        let mut dead = HashSet::new();
+        for site in converted {
+            site_calls.insert(site.call_id, (site.fn_idx, site.ctx_idx, site.closure));
+            field_uses.extend(site.field_uses);
+            ctx_locals.insert(site.ctx_local_id, site.field_locals);
+            dead.insert(site.callback_id);
+            dead.insert(site.ctx_struct_id);
+        }
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            if dead.contains(&i.id) {
+                return smallvec![];
+            }
+            let hof = match_or!([converted_hofs.get(&i.id)] Some(x) => x; return smallvec![i]);
+            smallvec![i.map(|mut i| {
+                if let ItemKind::Fn(ref mut sig, _, _) = i.kind {
+                    let param = &mut sig.decl.inputs[hof.fn_idx];
+                    param.ty = hof.closure_ty.clone();
+                    param.pat = mk().mutbl().ident_pat(hof.fn_ident);
+                    sig.decl.inputs.remove(hof.ctx_idx);
+                }
+                i
+            })]
+        });
+
+        FlatMapNodes::visit(krate, |s: Stmt| {
+            let id = match_or!([s.kind] StmtKind::Local(ref l) => l.id; return smallvec![s]);
+            let locals = match_or!([ctx_locals.remove(&id)] Some(x) => x; return smallvec![s]);
+            locals.into_iter().map(|(name, ty, init)| {
+                let pat = mk().mutbl().ident_pat(name);
+                mk().local_stmt(P(mk().local(pat, Some(ty), Some(init))))
+            }).collect()
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if let Some(&name) = field_uses.get(&e.id) {
+                *e = mk().span(e.span).path_expr(vec![name]);
+                return;
+            }
+            let id = e.id;
+            let (callee, args) = match_or!([e.kind] ExprKind::Call(ref mut callee, ref mut args) =>
+                                           (callee, args); return);
+            if let Some(&(ident, cb_ctx_idx)) = hof_calls.get(&id) {
+                *callee = mk().span(callee.span).path_expr(vec![ident]);
+                args.remove(cb_ctx_idx);
+            } else if let Some((fn_idx, ctx_idx, closure)) = site_calls.remove(&id) {
+                args[fn_idx] = closure;
+                args.remove(ctx_idx);
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Check whether the function `i` takes a single callback and a single `c_void` pointer that
+/// it only ever passes back to the callback.
+fn callback_hof(cx: &RefactorCtxt, i: &Item) -> Option<CallbackHof> {
+    let (sig, generics, block) = match_or!([i.kind]
+        ItemKind::Fn(ref sig, ref generics, ref block) => (sig, generics, block); return None);
+    if !generics.params.is_empty() || is_exported(i) {
+        return None;
+    }
+    let tcx = cx.ty_ctxt();
+    let fn_sig = tcx.fn_sig(cx.node_def_id(i.id));
+    if fn_sig.abi() != Abi::Rust {
+        return None;
+    }
+    let inputs = fn_sig.skip_binder().inputs();
+    let fn_idx = inputs.iter().position(|&ty| callback_sig(cx, ty).is_some())?;
+    let cb_sig = callback_sig(cx, inputs[fn_idx])?;
+    let ctx_idx = lone_position(inputs, |ty| is_void_ptr(cx, ty))?;
+    let cb_ctx_idx = lone_position(cb_sig.skip_binder().inputs(), |ty| is_void_ptr(cx, ty))?;
+    if inputs.iter().filter(|&&ty| callback_sig(cx, ty).is_some()).count() != 1 {
+        return None;
+    }
+
+    let param_hid = |idx: usize| {
+        let pat = &sig.decl.inputs[idx].pat;
+        match pat.kind {
+            PatKind::Ident(BindingMode::ByValue(_), ident, None) => {
+                Some((ident, cx.hir_map().node_to_hir_id(pat.id)))
+            }
+            _ => None,
+        }
+    };
+    let (fn_ident, fn_hid) = param_hid(fn_idx)?;
+    let (_, ctx_hid) = param_hid(ctx_idx)?;
+
+    // The callback may only be called, with the context pointer as its context argument,
+    // and the context pointer may only be passed to the callback.
+    let mut calls = HashSet::new();
+    let mut uses = 0;
+    visit_nodes(&**block, |e: &Expr| {
+        if let ExprKind::Call(ref callee, ref args) = e.kind {
+            if resolves_to_local(cx, unwrapped_callee(callee), fn_hid) &&
+                args.len() == cb_sig.skip_binder().inputs().len() &&
+                resolves_to_local(cx, strip_casts(&args[cb_ctx_idx]), ctx_hid)
+            {
+                calls.insert(e.id);
+            }
+        }
+        if resolves_to_local(cx, e, fn_hid) || resolves_to_local(cx, e, ctx_hid) {
+            uses += 1;
+        }
+    });
+    if calls.is_empty() || uses != 2 * calls.len() {
+        return None;
+    }
+
+    let decl = bare_fn_decl(&sig.decl.inputs[fn_idx].ty)?;
+    let cb_inputs = decl.inputs.iter().enumerate()
+        .filter(|&(j, _)| j != cb_ctx_idx)
+        .map(|(_, param)| pprust::ty_to_string(&param.ty))
+        .collect::<Vec<_>>();
+    let cb_output = match decl.output {
+        FunctionRetTy::Ty(ref ty) => format!(" -> {}", pprust::ty_to_string(ty)),
+        FunctionRetTy::Default(_) => String::new(),
+    };
+    let closure_ty = parse_ty(
+        cx.session(), &format!("impl FnMut({}){}", cb_inputs.join(", "), cb_output));
+
+    Some(CallbackHof {
+        item_id: i.id,
+        fn_idx,
+        ctx_idx,
+        cb_ctx_idx,
+        fn_ident,
+        closure_ty,
+        calls,
+    })
+}
+
+/// Check whether `call`, a call of `hof` in the function `caller`, passes a local callback and
+/// a pointer to a local context struct, and work out how to convert it.
+fn callback_site(
+    cx: &RefactorCtxt,
+    fns: &HashMap<DefId, Item>,
+    structs: &HashMap<DefId, (NodeId, Span, HashMap<Symbol, P<Ty>>)>,
+    refs: &HashMap<DefId, Vec<Span>>,
+    hof: &CallbackHof,
+    caller: &Item,
+    call: &Expr,
+) -> Result<CallbackSite, &'static str> {
+    let hir_map = cx.hir_map();
+    let args = expect!([call.kind] ExprKind::Call(_, ref args) => args);
+    let caller_block = expect!([caller.kind] ItemKind::Fn(_, _, ref block) => block);
+
+    // (1) The callback needs to be a function that's only used here.
+
+    let mut cb_arg = strip_casts(&args[hof.fn_idx]);
+    if let ExprKind::Call(ref ctor, ref ctor_args) = cb_arg.kind {
+        if ctor_args.len() == 1 && is_path_named(ctor, "Some") {
+            cb_arg = strip_casts(&ctor_args[0]);
+        }
+    }
+    if is_path_named(cb_arg, "None") {
+        return Err("null callback");
+    }
+    let cb_did = cx.try_resolve_expr(cb_arg).ok_or("callback isn't a local function")?;
+    let cb = fns.get(&cb_did).ok_or("callback isn't a local function")?;
+    if refs.get(&cb_did).map_or(0, |spans| spans.len()) != 1 || is_exported(cb) {
+        return Err("shared callback");
+    }
+    let (cb_sig, cb_generics, cb_block) = expect!([cb.kind]
+        ItemKind::Fn(ref sig, ref generics, ref block) => (sig, generics, block));
+    if !cb_generics.params.is_empty() || cb_sig.decl.c_variadic() {
+        return Err("unsupported callback");
+    }
+
+    // (2) The context needs to be a local struct that's only used for its fields.
+
+    let ctx_arg = &args[hof.ctx_idx];
+    if is_null_ptr(cx, ctx_arg) {
+        return Err("null context");
+    }
+    let ctx_var = match_or!([strip_casts(ctx_arg).kind] ExprKind::AddrOf(_, _, ref v) => v;
+                            return Err("context isn't a local struct"));
+    let ctx_hid = match_or!([cx.try_resolve_expr_hir(ctx_var)] Some(Res::Local(hid)) => hid;
+                            return Err("context isn't a local struct"));
+    let struct_did = cx.opt_node_type(ctx_var.id).and_then(adt_did)
+        .ok_or("context isn't a local struct")?;
+    let &(struct_id, struct_span, ref struct_fields) = structs.get(&struct_did)
+        .ok_or("context isn't a local struct")?;
+    // Derived impls of the struct are deleted along with it
+    let shared = refs.get(&struct_did).map_or(false, |spans| spans.iter().any(|&sp| {
+        !struct_span.contains(sp) && !caller.span.contains(sp) && !cb.span.contains(sp)
+    }));
+    if shared {
+        return Err("shared context struct");
+    }
+
+    let mut local = None;
+    visit_nodes(&**caller_block, |l: &Local| {
+        if let PatKind::Ident(BindingMode::ByValue(_), ident, None) = l.pat.kind {
+            if hir_map.node_to_hir_id(l.pat.id) == ctx_hid {
+                local = Some((l.id, ident, l.init.clone()));
+            }
+        }
+    });
+    let (ctx_local_id, ctx_ident, init) = local.ok_or("context isn't a local struct")?;
+    let init_fields = match init.as_ref().map(|e| &e.kind) {
+        Some(ExprKind::Struct(_, fields, None)) if fields.len() == struct_fields.len() => fields,
+        _ => return Err("context isn't initialized by a struct literal"),
+    };
+
+    let mut field_uses = HashMap::new();
+    let mut uses = 0;
+    visit_nodes(&**caller_block, |e: &Expr| {
+        if let ExprKind::Field(ref base, ident) = e.kind {
+            if resolves_to_local(cx, base, ctx_hid) {
+                field_uses.insert(e.id, ident.name);
+            }
+        }
+        if resolves_to_local(cx, e, ctx_hid) {
+            uses += 1;
+        }
+    });
+    if uses != field_uses.len() + 1 {
+        return Err("context is used as a whole");
+    }
+
+    // (3) The callback may only access the fields of its context, either directly, as in
+    // `(*(ctx as *mut Ctx)).key`, or through a local pointer, as in `let c = ctx as *mut Ctx;`
+    // followed by `(*c).key`.
+
+    let cb_ctx_param = &cb_sig.decl.inputs[hof.cb_ctx_idx];
+    let cb_ctx_hid = match cb_ctx_param.pat.kind {
+        PatKind::Ident(BindingMode::ByValue(_), _, None) => {
+            hir_map.node_to_hir_id(cb_ctx_param.pat.id)
+        }
+        _ => return Err("unsupported callback"),
+    };
+    let mut ptr_locals = HashMap::new();
+    visit_nodes(&**cb_block, |l: &Local| {
+        if let (&PatKind::Ident(BindingMode::ByValue(_), _, None), Some(init)) =
+            (&l.pat.kind, &l.init)
+        {
+            if resolves_to_local(cx, strip_casts(init), cb_ctx_hid) {
+                ptr_locals.insert(hir_map.node_to_hir_id(l.pat.id), l.id);
+            }
+        }
+    });
+    let mut cb_field_uses = HashMap::new();
+    let mut allowed_uses = HashMap::new();
+    let mut all_uses = HashMap::new();
+    visit_nodes(&**cb_block, |e: &Expr| {
+        if let ExprKind::Field(ref base, ident) = e.kind {
+            if let ExprKind::Unary(UnOp::Deref, ref ptr) = base.kind {
+                let ptr = strip_casts(ptr);
+                if let Some(Res::Local(hid)) = cx.try_resolve_expr_hir(ptr) {
+                    let is_ctx = hid == cb_ctx_hid || ptr_locals.contains_key(&hid);
+                    if is_ctx && cx.opt_node_type(base.id).and_then(adt_did) == Some(struct_did) {
+                        cb_field_uses.insert(e.id, ident.name);
+                        *allowed_uses.entry(hid).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+        if let ExprKind::Path(None, _) = e.kind {
+            if let Some(Res::Local(hid)) = cx.try_resolve_expr_hir(e) {
+                *all_uses.entry(hid).or_insert(0) += 1;
+            }
+        }
+    });
+    let count = |counts: &HashMap<hir::HirId, usize>, hid| counts.get(&hid).cloned().unwrap_or(0);
+    if count(&all_uses, cb_ctx_hid) != count(&allowed_uses, cb_ctx_hid) + ptr_locals.len() ||
+        ptr_locals.keys().any(|&hid| count(&all_uses, hid) != count(&allowed_uses, hid))
+    {
+        return Err("unsupported callback");
+    }
+
+    // (4) Pick names for the locals that replace the fields, and build the closure.
+
+    let mut taken = mentioned_names(&**caller_block);
+    taken.extend(mentioned_names(&**cb_block));
+    let mut names = HashMap::new();
+    let mut field_locals = Vec::new();
+    for field in init_fields {
+        let mut name = field.ident.name;
+        if taken.contains(&name) {
+            name = Symbol::intern(&format!("{}_{}", ctx_ident, field.ident));
+        }
+        let mut counter = 2;
+        while taken.contains(&name) {
+            name = Symbol::intern(&format!("{}_{}_{}", ctx_ident, field.ident, counter));
+            counter += 1;
+        }
+        taken.insert(name);
+        names.insert(field.ident.name, name);
+        field_locals.push((name, struct_fields[&field.ident.name].clone(), field.expr.clone()));
+    }
+    let field_uses = field_uses.into_iter()
+        .map(|(id, field)| (id, names[&field]))
+        .collect();
+
+    let mut body = cb_block.clone();
+    let call_hid = hir_map.node_to_hir_id(call.id);
+    let cb_hid = hir_map.node_to_hir_id(cb.id);
+    if hir_map.get_module_parent_node(call_hid) != hir_map.get_module_parent_node(cb_hid) {
+        // Paths in the body were written relative to the callback's module.
+        canonicalize_paths(&mut body, cx);
+    }
+    let ptr_local_ids = ptr_locals.values().cloned().collect::<HashSet<_>>();
+    FlatMapNodes::visit(&mut body, |s: Stmt| match s.kind {
+        StmtKind::Local(ref l) if ptr_local_ids.contains(&l.id) => smallvec![],
+        _ => smallvec![s],
+    });
+    MutVisitNodes::visit(&mut body, |e: &mut P<Expr>| {
+        if let Some(field) = cb_field_uses.get(&e.id) {
+            *e = mk().span(e.span).path_expr(vec![names[field]]);
+        }
+    });
+    let params = cb_sig.decl.inputs.iter().enumerate()
+        .filter(|&(j, _)| j != hof.cb_ctx_idx)
+        .map(|(_, param)| mk().arg(mk().infer_ty(), param.pat.clone()))
+        .collect();
+    let body = match cb_sig.header.unsafety {
+        Unsafety::Unsafe => mk().block_expr(mk().unsafe_().block(body.stmts.clone())),
+        Unsafety::Normal => mk().block_expr(body),
+    };
+    let closure = mk().span(args[hof.fn_idx].span).closure_expr(
+        CaptureBy::Ref,
+        Movability::Movable,
+        mk().fn_decl(params, FunctionRetTy::Default(DUMMY_SP)),
+        body,
+    );
+
+    Ok(CallbackSite {
+        call_id: call.id,
+        span: call.span,
+        fn_idx: hof.fn_idx,
+        ctx_idx: hof.ctx_idx,
+        callback_id: cb.id,
+        ctx_struct_id: struct_id,
+        ctx_local_id,
+        field_locals,
+        field_uses,
+        closure,
+    })
+}
+
+/// Get the signature of a callback type: a function pointer, or an `Option` of one.
+fn callback_sig<'tcx>(
+    cx: &RefactorCtxt<'_, 'tcx>,
+    ty: ty::Ty<'tcx>,
+) -> Option<ty::PolyFnSig<'tcx>> {
+    match ty.kind {
+        TyKind::FnPtr(sig) => Some(sig),
+        TyKind::Adt(def, substs) if is_option(cx, def.did) => match substs.type_at(0).kind {
+            TyKind::FnPtr(sig) => Some(sig),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Get the declaration of the function pointer in a callback type like
+/// `Option<unsafe extern "C" fn(*mut c_void) -> i32>`.
+fn bare_fn_decl(ty: &Ty) -> Option<&FnDecl> {
+    match ty.kind {
+        ast::TyKind::BareFn(ref f) => Some(&f.decl),
+        ast::TyKind::Path(None, ref path) => {
+            let seg = path.segments.last()?;
+            match **seg.args.as_ref()? {
+                GenericArgs::AngleBracketed(ref args) if seg.ident.as_str() == "Option" => {
+                    match args.args.get(0)? {
+                        GenericArg::Type(ref ty) => bare_fn_decl(ty),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Check whether `ty` is a raw pointer to `c_void`.
+fn is_void_ptr(cx: &RefactorCtxt, ty: ty::Ty) -> bool {
+    match ty.kind {
+        TyKind::RawPtr(ty::TypeAndMut { ty, .. }) => match ty.kind {
+            TyKind::Adt(def, _) => cx.ty_ctxt().item_name(def.did).as_str() == "c_void",
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Get the index of the only element of `tys` that satisfies `pred`.
+fn lone_position<'tcx, F>(tys: &[ty::Ty<'tcx>], pred: F) -> Option<usize>
+    where F: Fn(ty::Ty<'tcx>) -> bool
+{
+    let mut positions = tys.iter().enumerate().filter(|&(_, &ty)| pred(ty)).map(|(j, _)| j);
+    let pos = positions.next()?;
+    match positions.next() {
+        Some(_) => None,
+        None => Some(pos),
+    }
+}
+
+/// Strip `.unwrap()` or `.expect(..)` off the callee of a call through an `Option` callback.
+fn unwrapped_callee(e: &P<Expr>) -> &P<Expr> {
+    match e.kind {
+        ExprKind::MethodCall(ref seg, ref args)
+            if seg.ident.as_str() == "unwrap" || seg.ident.as_str() == "expect" => &args[0],
+        _ => e,
+    }
+}
+
+/// Check whether `e` is a path to the local variable `hid`.
+fn resolves_to_local(cx: &RefactorCtxt, e: &Expr, hid: hir::HirId) -> bool {
+    match e.kind {
+        ExprKind::Path(None, _) => cx.try_resolve_expr_hir(e) == Some(Res::Local(hid)),
+        _ => false,
+    }
+}
+
+/// Check whether `e` is a path whose last segment is `name`, like `Some` or `None`.
+fn is_path_named(e: &Expr, name: &str) -> bool {
+    match e.kind {
+        ExprKind::Path(None, ref path) => {
+            path.segments.last().map_or(false, |seg| seg.ident.as_str() == name)
+        }
+        _ => false,
+    }
+}
+
+/// Get the definition of a struct type.
+fn adt_did(ty: ty::Ty) -> Option<DefId> {
+    match ty.kind {
+        TyKind::Adt(def, _) if def.is_struct() => Some(def.did),
+        _ => None,
+    }
+}
+
+
+/// # `extract_fn` Command
+///
+/// Marks: `MARK`/`target`
+///
+/// Move the statements marked `MARK` (default: `target`) out of their function into a new
+/// function named `NAME`, placed right after it, and replace them with a call.  The marked
+/// statements must all be in the same block, and everything from the first to the last of them
+/// is moved, so marking the two ends of the region is enough.  This is meant for breaking up the
+/// huge state machines that the translator produces for C functions full of `goto`s.
+///
+/// Each local variable that the region uses but doesn't declare becomes a parameter.  Variables
+/// that the region assigns or mutably borrows are passed by `&mut`, other `Copy` variables by
+/// value, and everything else by `&`.  Variables that the region declares and the rest of the
+/// block uses are returned, in a tuple if there's more than one, and bound again at the call.
+///
+/// If the region returns early, the new function returns `ControlFlow<R, T>`, where `R` is the
+/// original return type and `T` the type of the returned variables.  Each `return v` becomes
+/// `return ControlFlow::Break(v)`, and the call site matches on the result.  The `ControlFlow`
+/// enum is added to the crate root if there's no item of that name there yet.
+///
+/// The region is left alone, with a warning, if it uses `?`, breaks out of or continues a loop
+/// that encloses it, produces the value of its block, declares an item, is inside a closure or a
+/// generic function, or initializes a variable declared before it.  It's also left alone if a
+/// variable it returns holds a borrow, since the new function has no way to name the lifetime of
+/// that borrow.
+///
+/// Example:
+///
+/// ```ignore
+///     fn sum(xs: &[i32]) -> i32 {
+///         let mut total = 0;
+///         for &x in xs {          // target
+///             if x < 0 {
+///                 return -1;
+///             }
+///             total += x;
+///         }
+///         total
+///     }
+/// ```
+///
+/// After running `extract_fn add_all`:
+///
+/// ```ignore
+///     fn sum(xs: &[i32]) -> i32 {
+///         let mut total = 0;
+///         if let ControlFlow::Break(v) = add_all(xs, &mut total) {
+///             return v;
+///         }
+///         total
+///     }
+///
+///     fn add_all(xs: &[i32], total: &mut i32) -> ControlFlow<i32, ()> {
+///         for &x in xs {
+///             if x < 0 {
+///                 return ControlFlow::Break(-1);
+///             }
+///             *total += x;
+///         }
+///         ControlFlow::Continue(())
+///     }
+/// ```
+pub struct ExtractFn {
+    name: Symbol,
+    mark: Symbol,
+}
+
+/// A run of marked statements.
+struct ExtractRegion {
+    block: NodeId,
+    /// The indices of the first and last statements of the region in `block`.
+    lo: usize,
+    hi: usize,
+    in_unsafe: bool,
+    in_closure: bool,
+}
+
+/// A variable bound by value in the function that contains the region.
+struct ExtractLocal {
+    ident: Ident,
+    mutbl: Mutability,
+    pat_id: NodeId,
+    span: Span,
+    /// The type written in its `let` or parameter, if any.
+    ty: Option<P<Ty>>,
+    /// Whether it's declared by a `let` with no initializer.
+    uninit: bool,
+}
+
+impl Transform for ExtractFn {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let hir_map = cx.hir_map();
+
+        let mut taken = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            let module = hir_map.get_module_parent_node(hir_map.node_to_hir_id(i.id));
+            taken.entry(module).or_insert_with(HashSet::new).insert(i.ident.name);
+        });
+
+        let mut uses_flow = false;
+        FlatMapNodes::visit(krate, |mut i: P<Item>| {
+            let mut finder = RegionFinder {
+                st,
+                mark: self.mark,
+                in_unsafe: false,
+                in_closure: false,
+                regions: Vec::new(),
+            };
+            match i.kind {
+                ItemKind::Fn(_, _, ref block) => finder.visit_block(block),
+                _ => return smallvec![i],
+            }
+            if finder.regions.is_empty() {
+                return smallvec![i];
+            }
+
+            let module = hir_map.get_module_parent_node(hir_map.node_to_hir_id(i.id));
+            let result = if finder.regions.len() > 1 {
+                Err("the marked statements are in more than one block".to_owned())
+            } else if taken.get(&module).map_or(false, |names| names.contains(&self.name)) {
+                Err(format!("`{}` is already defined", self.name))
+            } else {
+                let at_root = module == hir::CRATE_HIR_ID;
+                self.extract(cx, &mut i, &finder.regions[0], at_root)
+            };
+            match result {
+                Ok((new_fn, flow)) => {
+                    uses_flow |= flow;
+                    taken.entry(module).or_insert_with(HashSet::new).insert(self.name);
+                    smallvec![i, new_fn]
+                }
+                Err(why) => {
+                    cx.session().span_warn(i.span, &format!(
+                        "not extracting `{}` from `{}`: {}", self.name, i.ident, why));
+                    smallvec![i]
+                }
+            }
+        });
+
+        let has_flow = krate.module.items.iter().any(|i| i.ident.as_str() == "ControlFlow");
+        if uses_flow && !has_flow {
+            let src = "
+                pub enum ControlFlow<B, C> {
+                    Continue(C),
+                    Break(B),
+                }
+            ";
+            krate.module.items.extend(parse_items(cx.session(), src));
+        }
+    }
 
     fn min_phase(&self) -> Phase {
         Phase::Phase3
     }
 }
 
+impl ExtractFn {
+    /// Move `region` out of the function `item`.  Returns the new function and whether it uses
+    /// `ControlFlow`.
+    fn extract(&self, cx: &RefactorCtxt, item: &mut P<Item>, region: &ExtractRegion,
+               at_root: bool) -> Result<(P<Item>, bool), String> {
+        let tcx = cx.ty_ctxt();
+        let hir_map = cx.hir_map();
+        let flow_path = |variant: &str| if at_root {
+            mk().path_expr(vec!["ControlFlow", variant])
+        } else {
+            mk().path_expr(vec!["crate", "ControlFlow", variant])
+        };
+        let flow_name = if at_root { "ControlFlow" } else { "crate::ControlFlow" };
+
+        let (new_fn, call, returns) = {
+            let (sig, generics, body) = expect!([item.kind]
+                ItemKind::Fn(ref sig, ref generics, ref body) => (sig, generics, body));
+            if generics.params.iter().any(|p| !matches!([p.kind] GenericParamKind::Lifetime)) {
+                return Err("its function is generic".to_owned());
+            }
+            if region.in_closure {
+                return Err("the marked statements are inside a closure".to_owned());
+            }
+
+            let mut stmts = None;
+            if body.id == region.block {
+                stmts = Some(body.stmts.clone());
+            }
+            visit_nodes(&**body, |b: &Block| {
+                if b.id == region.block {
+                    stmts = Some(b.stmts.clone());
+                }
+            });
+            let stmts = stmts.unwrap();
+            let region_stmts = &stmts[region.lo..=region.hi];
+            let rest = &stmts[region.hi + 1..];
+
+            // (1) Check how control leaves the region.
+
+            let mut exits = RegionExits { loops: Vec::new(), returns: false, problem: None };
+            for s in region_stmts {
+                exits.visit_stmt(s);
+            }
+            if let Some(problem) = exits.problem {
+                return Err(problem.to_owned());
+            }
+            if region_stmts.iter().any(|s| matches!([s.kind] StmtKind::Item(..))) {
+                return Err("it declares an item".to_owned());
+            }
+            if rest.is_empty() {
+                if let StmtKind::Expr(ref e) = region_stmts.last().unwrap().kind {
+                    if !cx.opt_node_type(e.id).map_or(true, |ty| ty.is_unit()) {
+                        return Err("it produces the value of its block".to_owned());
+                    }
+                }
+            }
+
+            // (2) Find the variables that go in and out.
+
+            let mut let_tys = HashMap::new();
+            let mut uninit = HashSet::new();
+            visit_nodes(&**body, |l: &Local| {
+                if let Some(ref ty) = l.ty {
+                    let_tys.insert(l.pat.id, ty.clone());
+                }
+                if l.init.is_none() {
+                    uninit.insert(l.pat.id);
+                }
+            });
+            for param in &sig.decl.inputs {
+                let_tys.insert(param.pat.id, param.ty.clone());
+            }
+            let mut locals = HashMap::new();
+            let mut record_local = |p: &Pat| {
+                if let PatKind::Ident(BindingMode::ByValue(mutbl), ident, _) = p.kind {
+                    locals.insert(hir_map.node_to_hir_id(p.id), ExtractLocal {
+                        ident,
+                        mutbl,
+                        pat_id: p.id,
+                        span: p.span,
+                        ty: let_tys.get(&p.id).cloned(),
+                        uninit: uninit.contains(&p.id),
+                    });
+                }
+            };
+            for param in &sig.decl.inputs {
+                visit_nodes(&*param.pat, &mut record_local);
+            }
+            visit_nodes(&**body, &mut record_local);
+
+            let mut used = Vec::new();
+            let mut defined = HashSet::new();
+            let mut direct_places = HashSet::new();
+            for s in region_stmts {
+                visit_nodes(s, |p: &Pat| {
+                    defined.insert(hir_map.node_to_hir_id(p.id));
+                });
+                visit_nodes(s, |e: &Expr| {
+                    if is_direct_place(e) {
+                        direct_places.insert(hir_map.node_to_hir_id(e.id));
+                    }
+                    if !matches!([e.kind] ExprKind::Path(..)) {
+                        return;
+                    }
+                    if let Some(Res::Local(hir_id)) = cx.try_resolve_expr_hir(e) {
+                        if !used.contains(&hir_id) {
+                            used.push(hir_id);
+                        }
+                    }
+                });
+            }
+            let local = |hir_id: &hir::HirId| locals.get(hir_id).ok_or_else(|| {
+                format!("it uses `{}`, which isn't bound by value",
+                        hir_map.name(*hir_id))
+            });
+
+            let mut live_in = used.iter().cloned()
+                .filter(|hir_id| !defined.contains(hir_id))
+                .collect::<Vec<_>>();
+            for hir_id in &live_in {
+                local(hir_id)?;
+            }
+            live_in.sort_by_key(|hir_id| locals[hir_id].span.lo());
+            let writes = local_writes(cx, &live_in.iter().cloned().collect());
+
+            let mut live_out = Vec::new();
+            for s in region_stmts {
+                let l = match_or!([s.kind] StmtKind::Local(ref l) => l; continue);
+                visit_nodes(&*l.pat, |p: &Pat| {
+                    let hir_id = hir_map.node_to_hir_id(p.id);
+                    if matches!([p.kind] PatKind::Ident(..)) &&
+                       rest.iter().any(|s| mentions_local(cx, s, hir_id)) {
+                        live_out.push(hir_id);
+                    }
+                });
+            }
+
+            // (3) Build the parameters and the arguments.
+
+            let ty_of = |local: &ExtractLocal| local.ty.clone()
+                .unwrap_or_else(|| reflect_tcx_ty(tcx, cx.node_type(local.pat_id)));
+            let mut params = Vec::new();
+            let mut args = Vec::new();
+            let mut by_ref = HashSet::new();
+            for hir_id in &live_in {
+                let local = &locals[hir_id];
+                let name = local.ident.name;
+                let ty = ty_of(local);
+                let rty = cx.node_type(local.pat_id);
+                // Writes through a pointer that the variable holds don't count.
+                let written = writes.get(hir_id).map_or(false, |ws| {
+                    ws.iter().any(|w| direct_places.contains(w))
+                });
+                if written && local.uninit {
+                    return Err(format!("it initializes `{}`, which is declared before it",
+                                       local.ident));
+                }
+                // An immutable variable can only be written through, like `p` in `p.x = 0`.
+                if written && local.mutbl == Mutability::Mutable {
+                    params.push(mk().arg(mk().mutbl().ref_ty(ty), mk().ident_pat(name)));
+                    args.push(mk().mutbl().addr_of_expr(mk().ident_expr(name)));
+                    by_ref.insert(*hir_id);
+                } else if type_implements(cx, rty, local.pat_id, "Copy") ||
+                          matches!([rty.kind] ty::TyKind::Ref(_, _, hir::Mutability::Mutable)) {
+                    params.push(mk().arg(ty, mk().ident_pat(name)));
+                    args.push(mk().ident_expr(name));
+                } else {
+                    params.push(mk().arg(mk().ref_ty(ty), mk().ident_pat(name)));
+                    args.push(mk().addr_of_expr(mk().ident_expr(name)));
+                    by_ref.insert(*hir_id);
+                }
+            }
+
+            // (4) Build the new function.
+
+            let mut out_exprs = Vec::new();
+            let mut out_tys = Vec::new();
+            let mut out_pats = Vec::new();
+            for hir_id in &live_out {
+                let local = local(hir_id)?;
+                let holds_borrow = cx.node_type(local.pat_id).walk()
+                    .any(|ty| matches!([ty.kind] ty::TyKind::Ref(..)));
+                if holds_borrow {
+                    return Err(format!("`{}` holds a borrow, which can't be returned from the \
+                                        new function", local.ident));
+                }
+                out_exprs.push(mk().ident_expr(local.ident));
+                out_tys.push(ty_of(local));
+                out_pats.push(mk().set_mutbl(local.mutbl).ident_pat(local.ident));
+            }
+            let (out_expr, out_ty) = if out_exprs.len() == 1 {
+                (out_exprs.pop().unwrap(), out_tys.pop().unwrap())
+            } else {
+                (mk().tuple_expr(out_exprs), mk().tuple_ty(out_tys))
+            };
+
+            let mut new_stmts = region_stmts.to_vec();
+            MutVisitNodes::visit(&mut new_stmts, |e: &mut P<Expr>| {
+                if !matches!([e.kind] ExprKind::Path(..)) {
+                    return;
+                }
+                if let Some(Res::Local(hir_id)) = cx.try_resolve_expr_hir(e) {
+                    if by_ref.contains(&hir_id) {
+                        *e = mk().unary_expr(UnOp::Deref, e.clone());
+                    }
+                }
+            });
+
+            let tail = if exits.returns {
+                let ret_ty = tcx.fn_sig(cx.node_def_id(item.id)).output().skip_binder();
+                if ret_ty.walk().any(|ty| matches!([ty.kind] ty::TyKind::Ref(..))) {
+                    return Err("it returns a borrow early".to_owned());
+                }
+                let ret_ty = match sig.decl.output {
+                    FunctionRetTy::Ty(ref ty) => ty.clone(),
+                    FunctionRetTy::Default(_) => mk().tuple_ty(Vec::<P<Ty>>::new()),
+                };
+                new_stmts.visit(&mut BreakReturns { brk: flow_path("Break") });
+                let flow_ty = parse_ty(cx.session(), &format!(
+                    "{}<{}, {}>", flow_name, pprust::ty_to_string(&ret_ty),
+                    pprust::ty_to_string(&out_ty)));
+                Some((mk().call_expr(flow_path("Continue"), vec![out_expr]), flow_ty))
+            } else if !live_out.is_empty() {
+                Some((out_expr, out_ty))
+            } else {
+                None
+            };
+            let output = match tail {
+                Some((tail, ty)) => {
+                    let last = new_stmts.last_mut().unwrap();
+                    if let StmtKind::Expr(e) = last.kind.clone() {
+                        if classify::expr_requires_semi_to_be_stmt(&e) {
+                            last.kind = StmtKind::Semi(e);
+                        }
+                    }
+                    new_stmts.push(mk().expr_stmt(tail));
+                    FunctionRetTy::Ty(ty)
+                }
+                None => FunctionRetTy::Default(DUMMY_SP),
+            };
+
+            let unsafety = if region.in_unsafe { Unsafety::Unsafe } else { sig.header.unsafety };
+            let mut new_fn = mk().unsafety(unsafety).fn_item(
+                self.name, mk().fn_decl(params, output), mk().block(new_stmts));
+            if let ItemKind::Fn(_, ref mut new_generics, _) = new_fn.kind {
+                *new_generics = generics.clone();
+            }
+
+            // (5) Build the call.
+
+            let call = mk().call_expr(mk().path_expr(vec![self.name]), args);
+            let call = if !exits.returns {
+                call
+            } else if live_out.is_empty() {
+                parse_expr(cx.session(), &format!(
+                    "if let {}::Break(v) = {} {{ return v; }}",
+                    flow_name, pprust::expr_to_string(&call)))
+            } else {
+                parse_expr(cx.session(), &format!(
+                    "match {} {{ {}::Continue(v) => v, {}::Break(v) => return v, }}",
+                    pprust::expr_to_string(&call), flow_name, flow_name))
+            };
+            let call = if !live_out.is_empty() {
+                let pat = if out_pats.len() == 1 {
+                    out_pats.pop().unwrap()
+                } else {
+                    mk().tuple_pat(out_pats)
+                };
+                mk().local_stmt(P(mk().local(pat, None as Option<P<Ty>>, Some(call))))
+            } else if classify::expr_requires_semi_to_be_stmt(&call) {
+                mk().semi_stmt(call)
+            } else {
+                mk().expr_stmt(call)
+            };
+
+            (new_fn, call, exits.returns)
+        };
+
+        let mut call = Some(call);
+        MutVisitNodes::visit(item, |b: &mut P<Block>| {
+            if b.id == region.block {
+                if let Some(call) = call.take() {
+                    b.stmts.splice(region.lo..=region.hi, Some(call));
+                }
+            }
+        });
+        Ok((new_fn, returns))
+    }
+}
+
+/// Finds the runs of marked statements in a function body.
+struct RegionFinder<'a> {
+    st: &'a CommandState,
+    mark: Symbol,
+    in_unsafe: bool,
+    in_closure: bool,
+    regions: Vec<ExtractRegion>,
+}
+
+impl<'a, 'ast> Visitor<'ast> for RegionFinder<'a> {
+    fn visit_block(&mut self, b: &'ast Block) {
+        let old_unsafe = self.in_unsafe;
+        self.in_unsafe |= matches!([b.rules] BlockCheckMode::Unsafe(_));
+
+        let marked = b.stmts.iter()
+            .enumerate()
+            .filter(|&(_, s)| self.st.marked(s.id, self.mark))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        let range = match (marked.first(), marked.last()) {
+            (Some(&lo), Some(&hi)) => {
+                self.regions.push(ExtractRegion {
+                    block: b.id,
+                    lo,
+                    hi,
+                    in_unsafe: self.in_unsafe,
+                    in_closure: self.in_closure,
+                });
+                lo..hi + 1
+            }
+            _ => 0..0,
+        };
+        // Statements inside the region belong to it, even if they're marked too.
+        for (i, s) in b.stmts.iter().enumerate() {
+            if !range.contains(&i) {
+                self.visit_stmt(s);
+            }
+        }
+
+        self.in_unsafe = old_unsafe;
+    }
+
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        if let ExprKind::Closure(..) = e.kind {
+            let old_closure = self.in_closure;
+            self.in_closure = true;
+            visit::walk_expr(self, e);
+            self.in_closure = old_closure;
+        } else {
+            visit::walk_expr(self, e);
+        }
+    }
+
+    fn visit_item(&mut self, _i: &'ast Item) {}
+
+    fn visit_mac(&mut self, mac: &'ast Mac) {
+        visit::walk_mac(self, mac)
+    }
+}
+
+/// Finds the ways that control can leave a region, other than by reaching its end.
+struct RegionExits {
+    /// The labels of the loops in the region that enclose the current expression.
+    loops: Vec<Option<Label>>,
+    returns: bool,
+    problem: Option<&'static str>,
+}
+
+impl RegionExits {
+    fn check_jump(&mut self, label: Option<Label>) {
+        let inside = match label {
+            Some(label) => self.loops.iter()
+                .any(|l| l.map_or(false, |l| l.ident.name == label.ident.name)),
+            None => !self.loops.is_empty(),
+        };
+        if !inside {
+            self.problem = Some("it breaks out of or continues a loop that encloses it");
+        }
+    }
+}
+
+impl<'ast> Visitor<'ast> for RegionExits {
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        match e.kind {
+            ExprKind::Ret(..) => self.returns = true,
+            ExprKind::Try(..) => self.problem = Some("it uses `?`"),
+            ExprKind::Break(label, _) | ExprKind::Continue(label) => self.check_jump(label),
+            ExprKind::Closure(..) => return,
+            ExprKind::While(_, _, label) |
+            ExprKind::ForLoop(_, _, _, label) |
+            ExprKind::Loop(_, label) => {
+                self.loops.push(label);
+                visit::walk_expr(self, e);
+                self.loops.pop();
+                return;
+            }
+            _ => {}
+        }
+        visit::walk_expr(self, e)
+    }
+
+    fn visit_item(&mut self, _i: &'ast Item) {}
+
+    fn visit_mac(&mut self, mac: &'ast Mac) {
+        visit::walk_mac(self, mac)
+    }
+}
+
+/// Wraps the value of each `return` in `ControlFlow::Break`.
+struct BreakReturns {
+    brk: P<Expr>,
+}
+
+impl MutVisitor for BreakReturns {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        match e.kind {
+            ExprKind::Closure(..) => return,
+            ExprKind::Ret(ref mut val) => {
+                let old = val.take().unwrap_or_else(|| mk().tuple_expr(Vec::<P<Expr>>::new()));
+                *val = Some(mk().call_expr(self.brk.clone(), vec![old]));
+            }
+            _ => {}
+        }
+        mut_visit::noop_visit_expr(e, self)
+    }
+
+    fn flat_map_item(&mut self, i: P<Item>) -> SmallVec<[P<Item>; 1]> {
+        smallvec![i]
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+/// Check whether the place `e` is stored in a local variable itself, rather than behind a
+/// pointer that the variable holds.
+fn is_direct_place(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Path(..) => true,
+        ExprKind::Field(ref base, _) |
+        ExprKind::Index(ref base, _) |
+        ExprKind::Paren(ref base) => is_direct_place(base),
+        _ => false,
+    }
+}
+
 
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("func_to_method", |_args| mk(ToMethod));
-    reg.register("fix_unused_unsafe", |_args| mk(FixUnusedUnsafe));
-    reg.register("sink_unsafe", |_args| mk(SinkUnsafe));
-    reg.register("wrap_extern", |_args| mk(WrapExtern));
-    reg.register("wrap_api", |_args| mk(WrapApi));
-    reg.register("abstract", |args| mk(Abstract {
-        sig: args[0].clone(),
-        pat: args[1].clone(),
-        body: args.get(2).cloned(),
-    }));
+    let spec = CommandSpec::new("Turn functions marked `target` into methods.");
+    reg.register("func_to_method", spec, |_args| Ok(mk(ToMethod)));
+
+    let spec = CommandSpec::new("Turn unused `unsafe` blocks into ordinary blocks.");
+    reg.register("fix_unused_unsafe", spec, |_args| Ok(mk(FixUnusedUnsafe)));
+
+    let spec = CommandSpec::new("Move the `unsafe` of functions marked `target` into their body.");
+    reg.register("sink_unsafe", spec, |_args| Ok(mk(SinkUnsafe)));
+
+    let spec = CommandSpec::new("Remove `unsafe` that's no longer needed.")
+        .flag("--shrink", "also narrow `unsafe` blocks to the statements that need them");
+    reg.register("remove_redundant_unsafe", spec, |args| {
+        Ok(mk(RemoveRedundantUnsafe {
+            shrink: args.flag("--shrink"),
+        }))
+    });
+
+    let spec = CommandSpec::new("Wrap the foreign functions marked `target` in the module `dest`.");
+    reg.register("wrap_extern", spec, |_args| Ok(mk(WrapExtern)));
+
+    let spec = CommandSpec::new("Give functions marked `target` a Rust ABI and a C wrapper.");
+    reg.register("wrap_api", spec, |_args| Ok(mk(WrapApi)));
+
+    let spec = CommandSpec::new("Change string parameters of functions marked `target` to `&str`.")
+        .flag("--cstr", "use `&CStr` instead of `&str`");
+    reg.register("convert_cstr_params", spec, |args| {
+        Ok(mk(ConvertCStrParams {
+            cstr: args.flag("--cstr"),
+        }))
+    });
+
+    let spec = CommandSpec::new("Replace calls to C string functions with Rust methods.");
+    reg.register("convert_string_fns", spec, |_args| Ok(mk(ConvertStringFns)));
+
+    let spec = CommandSpec::new("Inline every call to the functions marked MARK.")
+        .optional_arg("MARK", ArgType::Str, Some("target"), "the mark of the functions");
+    reg.register("inline_fn", spec, |args| {
+        Ok(mk(InlineFn {
+            mark: args.str("MARK").into_symbol(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Make functions marked `target` return a `Result`, not a status.")
+        .optional_arg("SUCCESS", ArgType::Int, Some("0"), "the status code for success");
+    reg.register("convert_to_result", spec, |args| {
+        Ok(mk(ConvertToResult {
+            success: i128::from(args.int("SUCCESS")),
+        }))
+    });
+
+    let spec = CommandSpec::new("Make functions marked `target` return an `Option`, not null.");
+    reg.register("convert_nullable_return_to_option", spec, |_args| {
+        Ok(mk(ConvertNullableReturnToOption))
+    });
+
+    let spec = CommandSpec::new("Rewrite null checks of pointers taken out of an `Option`.");
+    reg.register("convert_null_checks", spec, |_args| Ok(mk(ConvertNullChecks)));
+
+    let spec = CommandSpec::new("Move the statements marked MARK into a new function NAME.")
+        .arg("NAME", ArgType::Str, "the name of the new function")
+        .optional_arg("MARK", ArgType::Str, Some("target"), "the mark of the statements");
+    reg.register("extract_fn", spec, |args| {
+        Ok(mk(ExtractFn {
+            name: args.str("NAME").into_symbol(),
+            mark: args.str("MARK").into_symbol(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Convert C-style callbacks with a context pointer into closures.")
+        .option("-o", "REPORT", ArgType::Str, None, "also write the report to REPORT as JSON");
+    reg.register("convert_callback_to_closure", spec, |args| {
+        Ok(mk(ConvertCallbackToClosure {
+            report_path: args.opt_str("-o").map(|s| s.to_owned()),
+        }))
+    });
+
+    let spec = CommandSpec::new("Replace all instances of PAT with calls to a new function.")
+        .arg("SIG", ArgType::Str, "the signature of the new function")
+        .arg("PAT", ArgType::Str, "the pattern to replace")
+        .optional_arg("BODY", ArgType::Str, None, "the body of the new function (default: PAT)");
+    reg.register("abstract", spec, |args| {
+        Ok(mk(Abstract {
+            sig: args.str("SIG").to_owned(),
+            pat: args.str("PAT").to_owned(),
+            body: args.opt_str("BODY").map(|s| s.to_owned()),
+        }))
+    });
 }
@@ -6,6 +6,7 @@ use smallvec::smallvec;
 
 use crate::ast_manip::{FlatMapNodes, MutVisitNodes};
 use crate::command::{CommandState, Registry};
+use crate::command_args::{ArgType, CommandSpec};
 use crate::driver::{parse_ty};
 use crate::path_edit::fold_resolved_paths_with_id;
 use crate::transform::Transform;
@@ -15,8 +16,6 @@ use c2rust_ast_builder::{mk, IntoSymbol};
 
 /// # `generalize_items` Command
 /// 
-/// Usage: `generalize_items VAR [TY]`
-/// 
 /// Marks: `target`
 /// 
 /// Replace marked types with generic type parameters.
@@ -175,8 +174,13 @@ impl Transform for GeneralizeItems {
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("generalize_items", |args| mk(GeneralizeItems {
-        ty_var_name: args.get(0).map_or("T", |x| x).into_symbol(),
-        replacement_ty: args.get(1).cloned(),
-    }));
+    let spec = CommandSpec::new("Replace types marked `target` with a new type parameter VAR.")
+        .optional_arg("VAR", ArgType::Str, Some("T"), "the name of the type parameter")
+        .optional_arg("TY", ArgType::Str, None, "the type argument to pass outside `target` items");
+    reg.register("generalize_items", spec, |args| {
+        Ok(mk(GeneralizeItems {
+            ty_var_name: args.str("VAR").into_symbol(),
+            replacement_ty: args.opt_str("TY").map(|s| s.to_owned()),
+        }))
+    });
 }
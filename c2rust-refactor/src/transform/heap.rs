@@ -0,0 +1,737 @@
+//! Transforms for heap allocations made with the C allocator.
+use std::collections::{HashMap, HashSet};
+use rustc_data_structures::sync::Lrc;
+use rustc::hir::def_id::{DefId, LOCAL_CRATE};
+use rustc::hir::HirId;
+use rustc::traits::{Obligation, ObligationCause};
+use rustc::ty::{self, ParamEnv, ToPredicate, TyCtxt};
+use syntax::ast::*;
+use syntax::mut_visit::{self, MutVisitor};
+use syntax::ptr::P;
+use syntax::source_map::DUMMY_SP;
+use syntax::symbol::Symbol;
+use syntax::token::{Nonterminal, Token, TokenKind};
+use syntax::tokenstream::TokenTree;
+use syntax::visit::{self, Visitor};
+
+use c2rust_ast_builder::{mk, IntoSymbol};
+use crate::ast_manip::{MutVisit, MutVisitNodes};
+use crate::command::{CommandState, Registry};
+use crate::command_args::{ArgType, CommandSpec};
+use crate::driver::Phase;
+use crate::transform::Transform;
+use crate::RefactorCtxt;
+
+
+/// # `convert_malloc_to_box` Command
+///
+/// Marks: sets `MARK` (`malloc_review` by default)
+///
+/// Replace single-object heap allocations made with the C allocator by `Box` allocations.  An
+/// allocation site is a local initialized with `malloc(size_of::<T>() as _) as *mut T` or
+/// `calloc(1, size_of::<T>() as _) as *mut T`, where `malloc`, `calloc`, and `free` are the
+/// foreign functions.  A site is converted only if a walk over the rest of its block shows that:
+///
+///  * the pointer is only ever dereferenced, as in `*p` or `(*p).x`, and never copied, passed
+///    to another function, stored, or captured by a closure,
+///  * it's freed by exactly one `free(p)` statement, directly in the same block, and never used
+///    after that, and
+///  * nothing between the allocation and the `free` can return, break out of the block, or
+///    propagate an error with `?`, so the `free` always runs.
+///
+/// `T` also has to implement `Default`, which initializes the new object.  Converted sites
+/// become owned boxes: `let p = Box::new(T::default());`, with the `free` call deleted, so the
+/// box is dropped at the end of the block.  Dereferences of `p` work unchanged.  With `--raw`,
+/// the pointer stays raw instead: the allocation becomes
+/// `Box::into_raw(Box::new(T::default()))` and the `free` becomes `drop(Box::from_raw(p))`.
+///
+/// Allocation sites that can't be converted are left untouched and marked `MARK` for review.
+pub struct ConvertMallocToBox {
+    raw: bool,
+    label: Symbol,
+}
+
+/// A recognized allocation site: `let p = malloc(size_of::<T>() as _) as *mut T;`.
+struct Alloc {
+    /// The local holding the pointer.
+    local: HirId,
+    /// The pointee type `T`, as written in the cast.
+    ty: P<Ty>,
+}
+
+impl Transform for ConvertMallocToBox {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            let mut replace = HashMap::new();
+            let mut remove = HashSet::new();
+
+            for (i, s) in b.stmts.iter().enumerate() {
+                let l = match_or!([s.kind] StmtKind::Local(ref l) => l; continue);
+                let alloc = match_or!([alloc_site(cx, l)] Some(x) => x; continue);
+                let init = l.init.as_ref().unwrap();
+
+                let rest = &b.stmts[i + 1..];
+                let j = match rest.iter().position(|s| free_of(cx, s, alloc.local).is_some()) {
+                    Some(j) => j,
+                    None => {
+                        st.add_mark(init.id, self.label);
+                        continue;
+                    }
+                };
+                let (before, after) = (&rest[..j], &rest[j + 1..]);
+
+                let uses = ptr_uses(cx, alloc.local, before, false);
+                let convertible = !uses.escapes
+                    && !ptr_uses(cx, alloc.local, after, false).mentioned
+                    && !has_early_exit(before)
+                    && pointee_implements(cx, init, "Default");
+                if !convertible {
+                    st.add_mark(init.id, self.label);
+                    continue;
+                }
+
+                let new_init = box_new(default_call(&alloc.ty));
+                let free_stmt = &rest[j];
+                if self.raw {
+                    let new_init = mk().call_expr(mk().path_expr(vec!["Box", "into_raw"]),
+                                                  vec![new_init]);
+                    replace.insert(i, mk().local_stmt(P(Local {
+                        init: Some(new_init),
+                        .. (**l).clone()
+                    })));
+                    let ptr = free_of(cx, free_stmt, alloc.local).unwrap().clone();
+                    let from_raw = mk().call_expr(mk().path_expr(vec!["Box", "from_raw"]),
+                                                  vec![ptr]);
+                    let drop = mk().call_expr(mk().path_expr(vec!["drop"]), vec![from_raw]);
+                    replace.insert(i + 1 + j, mk().semi_stmt(drop));
+                } else {
+                    replace.insert(i, mk().local_stmt(P(Local {
+                        pat: owner_pat(&l.pat, uses.mutated),
+                        ty: l.ty.as_ref().map(|_| generic_ty("Box", &alloc.ty)),
+                        init: Some(new_init),
+                        .. (**l).clone()
+                    })));
+                    remove.insert(i + 1 + j);
+                }
+            }
+
+            if replace.is_empty() && remove.is_empty() {
+                return;
+            }
+            let stmts = std::mem::replace(&mut b.stmts, Vec::new());
+            b.stmts = stmts.into_iter().enumerate()
+                .filter(|&(i, _)| !remove.contains(&i))
+                .map(|(i, s)| replace.remove(&i).unwrap_or(s))
+                .collect();
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// # `convert_calloc_to_vec` Command
+///
+/// Marks: sets `MARK` (`calloc_review` by default)
+///
+/// Replace array allocations made with `calloc` by vectors.  An allocation site is a local
+/// initialized with `calloc(n, size_of::<T>() as _) as *mut T`, where `calloc` and `free` are the
+/// foreign functions.  It's converted under the same conditions as in `convert_malloc_to_box`,
+/// except that the pointer may also be indexed: it's only used as `*p`, `*p.offset(i)`, or
+/// `*p.add(i)`.  Passing it to `realloc` counts as an escape like any other call.  `T` has to
+/// implement both `Default` and `Clone`.
+///
+/// The allocation becomes `let p = vec![T::default(); n];`, which evaluates `n` exactly once,
+/// like the original call.  Later dereferences become indexing: `p[0]`, and `p[i]` for both
+/// offset forms.  The `free` is deleted if it's the last statement of the block, so the vector is
+/// dropped at the same point; otherwise it becomes `drop(p)`.
+///
+/// Allocation sites that can't be converted are left untouched and marked `MARK` for review.
+pub struct ConvertCallocToVec {
+    label: Symbol,
+}
+
+impl Transform for ConvertCallocToVec {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            // Each conversion is the index of the allocation, the index of its `free`, the
+            // pointer local, and the replacements for both statements.
+            let mut convs = Vec::new();
+
+            for (i, s) in b.stmts.iter().enumerate() {
+                let l = match_or!([s.kind] StmtKind::Local(ref l) => l; continue);
+                let (alloc, len) = match_or!([calloc_site(cx, l)] Some(x) => x; continue);
+                let init = l.init.as_ref().unwrap();
+
+                let rest = &b.stmts[i + 1..];
+                let j = match rest.iter().position(|s| free_of(cx, s, alloc.local).is_some()) {
+                    Some(j) => j,
+                    None => {
+                        st.add_mark(init.id, self.label);
+                        continue;
+                    }
+                };
+                let (before, after) = (&rest[..j], &rest[j + 1..]);
+
+                let uses = ptr_uses(cx, alloc.local, before, true);
+                let convertible = !uses.escapes
+                    && !ptr_uses(cx, alloc.local, after, false).mentioned
+                    && !has_early_exit(before)
+                    && pointee_implements(cx, init, "Default")
+                    && pointee_implements(cx, init, "Clone");
+                if !convertible {
+                    st.add_mark(init.id, self.label);
+                    continue;
+                }
+
+                let new_local = mk().local_stmt(P(Local {
+                    pat: owner_pat(&l.pat, uses.mutated),
+                    ty: l.ty.as_ref().map(|_| generic_ty("Vec", &alloc.ty)),
+                    init: Some(vec_repeat(default_call(&alloc.ty), usize_expr(cx, len))),
+                    .. (**l).clone()
+                }));
+
+                // Only the block's trailing expression may follow a `free` that gets deleted.
+                let at_end = match after {
+                    [] => true,
+                    [s] => match s.kind {
+                        StmtKind::Expr(_) => true,
+                        _ => false,
+                    },
+                    _ => false,
+                };
+                let new_free = if at_end {
+                    None
+                } else {
+                    let ptr = free_of(cx, &rest[j], alloc.local).unwrap().clone();
+                    Some(mk().semi_stmt(mk().call_expr(mk().path_expr(vec!["drop"]), vec![ptr])))
+                };
+
+                convs.push((i, i + 1 + j, alloc.local, new_local, new_free));
+            }
+
+            if convs.is_empty() {
+                return;
+            }
+            let mut replace = HashMap::new();
+            let mut remove = HashSet::new();
+            for (i, j, local, new_local, new_free) in convs {
+                let mut rewriter = IndexRewriter { cx, local };
+                for s in &mut b.stmts[i + 1..j] {
+                    s.visit(&mut rewriter);
+                }
+                replace.insert(i, new_local);
+                match new_free {
+                    Some(s) => { replace.insert(j, s); }
+                    None => { remove.insert(j); }
+                }
+            }
+
+            let stmts = std::mem::replace(&mut b.stmts, Vec::new());
+            b.stmts = stmts.into_iter().enumerate()
+                .filter(|&(i, _)| !remove.contains(&i))
+                .map(|(i, s)| replace.remove(&i).unwrap_or(s))
+                .collect();
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Check whether `l` initializes a local with a single-object `malloc` or `calloc` allocation.
+fn alloc_site(cx: &RefactorCtxt, l: &Local) -> Option<Alloc> {
+    let (call, args, ty) = alloc_parts(l)?;
+    let size = match &*foreign_fn_name(cx, call)?.as_str() {
+        "malloc" if args.len() == 1 => &args[0],
+        "calloc" if args.len() == 2 && is_one(strip_casts(&args[0])) => &args[1],
+        _ => return None,
+    };
+    // The allocation has to hold exactly one `T`.
+    if !is_size_of_pointee(cx, size, l.init.as_ref()?) {
+        return None;
+    }
+
+    Some(Alloc {
+        local: cx.hir_map().node_to_hir_id(l.pat.id),
+        ty: ty.clone(),
+    })
+}
+
+/// Check whether `l` initializes a local with an array allocation `calloc(n, size_of::<T>())`.
+/// Returns the allocation and the element count `n`.
+fn calloc_site<'a>(cx: &RefactorCtxt, l: &'a Local) -> Option<(Alloc, &'a P<Expr>)> {
+    let (call, args, ty) = alloc_parts(l)?;
+    if args.len() != 2 || &*foreign_fn_name(cx, call)?.as_str() != "calloc" {
+        return None;
+    }
+    if !is_size_of_pointee(cx, &args[1], l.init.as_ref()?) {
+        return None;
+    }
+
+    let alloc = Alloc {
+        local: cx.hir_map().node_to_hir_id(l.pat.id),
+        ty: ty.clone(),
+    };
+    Some((alloc, &args[0]))
+}
+
+/// Split a local `let p = f(args) as *mut T;` into the call, its arguments, and `T`.
+fn alloc_parts(l: &Local) -> Option<(&P<Expr>, &[P<Expr>], &P<Ty>)> {
+    match l.pat.kind {
+        PatKind::Ident(BindingMode::ByValue(_), _, None) => {}
+        _ => return None,
+    }
+    let init = l.init.as_ref()?;
+    let (call, ptr_ty) = match_or!([init.kind] ExprKind::Cast(ref e, ref t) => (e, t);
+                                   return None);
+    let ty = match_or!([ptr_ty.kind]
+                       TyKind::Ptr(MutTy { ref ty, mutbl: Mutability::Mutable }) => ty;
+                       return None);
+    let args = match_or!([call.kind] ExprKind::Call(_, ref args) => args; return None);
+    Some((call, args, ty))
+}
+
+/// Check whether `size` is `size_of::<T>()`, possibly cast, where `*mut T` is the type of the
+/// allocation `init`.
+fn is_size_of_pointee(cx: &RefactorCtxt, size: &P<Expr>, init: &Expr) -> bool {
+    let size_ty = match cx.opt_callee_info(strip_casts(size))
+        .filter(|info| info.def_id.map_or(false, |did| is_mem_fn(cx, did, "size_of")))
+        .and_then(|info| info.substs)
+    {
+        Some(substs) => substs.type_at(0),
+        None => return false,
+    };
+    match cx.node_type(init.id).kind {
+        ty::TyKind::RawPtr(tm) => tm.ty == size_ty,
+        _ => false,
+    }
+}
+
+/// If `s` is a statement `free(p);` that frees `local`, return the pointer argument without
+/// its casts.
+fn free_of<'a>(cx: &RefactorCtxt, s: &'a Stmt, local: HirId) -> Option<&'a P<Expr>> {
+    let e = match_or!([s.kind] StmtKind::Semi(ref e) => e, StmtKind::Expr(ref e) => e;
+                      return None);
+    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return None);
+    if args.len() != 1 || &*foreign_fn_name(cx, e)?.as_str() != "free" {
+        return None;
+    }
+    let ptr = strip_casts(&args[0]);
+    if cx.try_resolve_expr_to_hid(ptr) != Some(local) {
+        return None;
+    }
+    Some(ptr)
+}
+
+/// The name of the foreign function called by `e`, if it's a call to one.
+pub(crate) fn foreign_fn_name(cx: &RefactorCtxt, e: &Expr) -> Option<Symbol> {
+    let did = cx.opt_callee(e)?;
+    let tcx = cx.ty_ctxt();
+    if !tcx.is_foreign_item(did) {
+        return None;
+    }
+    Some(tcx.item_name(did))
+}
+
+/// Check whether `did` is the function `std::mem::<name>` (or the `core` equivalent).
+pub(crate) fn is_mem_fn(cx: &RefactorCtxt, did: DefId, name: &str) -> bool {
+    is_std_fn(cx, did, "mem", name)
+}
+
+/// Check whether `did` is the function `std::<module>::<name>` (or the `core` equivalent).
+pub(crate) fn is_std_fn(cx: &RefactorCtxt, did: DefId, module: &str, name: &str) -> bool {
+    if did.krate == LOCAL_CRATE {
+        return false;
+    }
+    let crate_name = cx.ty_ctxt().crate_name(did.krate);
+    let path = cx.ty_ctxt().def_path(did);
+
+    (crate_name.as_str() == "std" || crate_name.as_str() == "core") &&
+    path.data.len() == 2 &&
+    path.data[0].data.get_opt_name().map_or(false, |sym| sym.as_str() == module) &&
+    path.data[1].data.get_opt_name().map_or(false, |sym| sym.as_str() == name)
+}
+
+pub(crate) fn strip_casts(e: &P<Expr>) -> &P<Expr> {
+    match e.kind {
+        ExprKind::Cast(ref e, _) | ExprKind::Paren(ref e) => strip_casts(e),
+        _ => e,
+    }
+}
+
+fn is_one(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Lit(Lit { kind: LitKind::Int(1, _), .. }) => true,
+        _ => false,
+    }
+}
+
+/// How a sequence of statements uses the pointer held in a local.
+#[derive(Default)]
+struct PtrUses {
+    /// The local appears somewhere in the statements.
+    mentioned: bool,
+    /// The local is used other than by dereferencing it (or, when indexing is allowed,
+    /// dereferencing `p.offset(i)` or `p.add(i)`).
+    escapes: bool,
+    /// Some dereference of the local is written to or mutably borrowed.
+    mutated: bool,
+}
+
+fn ptr_uses(cx: &RefactorCtxt, local: HirId, stmts: &[Stmt], indexed: bool) -> PtrUses {
+    struct UseVisitor<'a, 'b, 'tcx> {
+        cx: &'a RefactorCtxt<'b, 'tcx>,
+        local: HirId,
+        indexed: bool,
+        uses: PtrUses,
+    }
+
+    impl<'a, 'b, 'tcx> UseVisitor<'a, 'b, 'tcx> {
+        fn is_local(&self, e: &Expr) -> bool {
+            self.cx.try_resolve_expr_to_hid(e) == Some(self.local)
+        }
+
+        /// Check whether the place `e` is (part of) the object behind the local.
+        fn is_pointee_place(&self, e: &Expr) -> bool {
+            match e.kind {
+                ExprKind::Field(ref e, _) | ExprKind::Index(ref e, _) |
+                ExprKind::Paren(ref e) => self.is_pointee_place(e),
+                ExprKind::Unary(UnOp::Deref, ref e) => {
+                    self.is_local(e) ||
+                    (self.indexed && offset_of(self.cx, self.local, e).is_some())
+                }
+                _ => false,
+            }
+        }
+    }
+
+    impl<'a, 'b, 'tcx, 'ast> Visitor<'ast> for UseVisitor<'a, 'b, 'tcx> {
+        fn visit_expr(&mut self, e: &'ast Expr) {
+            match e.kind {
+                ExprKind::Unary(UnOp::Deref, ref inner) => {
+                    if self.is_local(inner) {
+                        self.uses.mentioned = true;
+                        return;
+                    }
+                    if let Some((_, idx)) = offset_of(self.cx, self.local, inner) {
+                        if self.indexed {
+                            self.uses.mentioned = true;
+                            self.visit_expr(idx);
+                            return;
+                        }
+                    }
+                }
+                ExprKind::Path(..) if self.is_local(e) => {
+                    self.uses.mentioned = true;
+                    self.uses.escapes = true;
+                    return;
+                }
+                ExprKind::Closure(..) => {
+                    // A closure would have to borrow the box, so any use inside counts as an
+                    // escape.
+                    let mut inner = UseVisitor {
+                        cx: self.cx,
+                        local: self.local,
+                        indexed: self.indexed,
+                        uses: PtrUses::default(),
+                    };
+                    visit::walk_expr(&mut inner, e);
+                    if inner.uses.mentioned {
+                        self.uses.mentioned = true;
+                        self.uses.escapes = true;
+                    }
+                    return;
+                }
+                ExprKind::Assign(ref lhs, _) | ExprKind::AssignOp(_, ref lhs, _) |
+                ExprKind::AddrOf(_, Mutability::Mutable, ref lhs) => {
+                    if self.is_pointee_place(lhs) {
+                        self.uses.mutated = true;
+                    }
+                }
+                ExprKind::MethodCall(_, ref args) => {
+                    // The method may take `&mut self`, so be conservative.
+                    if self.is_pointee_place(&args[0]) {
+                        self.uses.mutated = true;
+                    }
+                }
+                _ => {}
+            }
+            visit::walk_expr(self, e);
+        }
+
+        fn visit_item(&mut self, _i: &'ast Item) {
+            // Nested items can't refer to the local.
+        }
+
+        fn visit_mac(&mut self, mac: &'ast Mac) {
+            visit::walk_mac(self, mac)
+        }
+    }
+
+    let mut v = UseVisitor { cx, local, indexed, uses: PtrUses::default() };
+    for s in stmts {
+        v.visit_stmt(s);
+    }
+    v.uses
+}
+
+/// Check whether any of `stmts` can leave the enclosing block early, by returning, using `?`, or
+/// breaking out of (or continuing) a loop that isn't inside the statements.
+fn has_early_exit(stmts: &[Stmt]) -> bool {
+    struct ExitVisitor {
+        loop_depth: usize,
+        found: bool,
+    }
+
+    impl<'ast> Visitor<'ast> for ExitVisitor {
+        fn visit_expr(&mut self, e: &'ast Expr) {
+            match e.kind {
+                ExprKind::Ret(_) | ExprKind::Try(_) => self.found = true,
+                // Labeled jumps may target a loop outside the statements.
+                ExprKind::Break(ref label, _) | ExprKind::Continue(ref label)
+                    if label.is_some() || self.loop_depth == 0 => self.found = true,
+                ExprKind::While(..) | ExprKind::ForLoop(..) | ExprKind::Loop(..) => {
+                    self.loop_depth += 1;
+                    visit::walk_expr(self, e);
+                    self.loop_depth -= 1;
+                    return;
+                }
+                // Returns inside a closure only leave the closure.
+                ExprKind::Closure(..) => return,
+                _ => {}
+            }
+            visit::walk_expr(self, e);
+        }
+
+        fn visit_item(&mut self, _i: &'ast Item) {}
+
+        fn visit_mac(&mut self, mac: &'ast Mac) {
+            visit::walk_mac(self, mac)
+        }
+    }
+
+    let mut v = ExitVisitor { loop_depth: 0, found: false };
+    for s in stmts {
+        v.visit_stmt(s);
+    }
+    v.found
+}
+
+/// Check whether the pointee type of the allocation `init` implements the `core` trait named
+/// `trait_name`, in the environment of the enclosing item.
+fn pointee_implements(cx: &RefactorCtxt, init: &Expr, trait_name: &str) -> bool {
+    match cx.node_type(init.id).kind {
+        ty::TyKind::RawPtr(tm) => type_implements(cx, tm.ty, init.id, trait_name),
+        _ => false,
+    }
+}
+
+/// Check whether `ty` implements the `core` trait named `trait_name`, in the environment of the
+/// item enclosing the node `id`.
+pub(crate) fn type_implements<'tcx>(
+    cx: &RefactorCtxt<'_, 'tcx>,
+    ty: ty::Ty<'tcx>,
+    id: NodeId,
+    trait_name: &str,
+) -> bool {
+    let tcx = cx.ty_ctxt();
+    let trait_did = match core_trait(tcx, trait_name) {
+        Some(x) => x,
+        None => return false,
+    };
+
+    let hir_map = cx.hir_map();
+    let param_env = cx
+        .opt_node_to_hir_id(id)
+        .and_then(|id| hir_map.opt_local_def_id(hir_map.get_parent_item(id)))
+        .map_or(ParamEnv::empty(), |did| tcx.param_env(did));
+
+    let trait_ref = ty::TraitRef::new(trait_did, tcx.mk_substs_trait(ty, &[]));
+    let obligation = Obligation::new(ObligationCause::dummy(), param_env, trait_ref.to_predicate());
+    tcx.infer_ctxt()
+        .enter(|infcx| infcx.predicate_must_hold_modulo_regions(&obligation))
+}
+
+/// A trait defined in `core`, like `core::default::Default`.
+pub(crate) fn core_trait(tcx: TyCtxt, name: &str) -> Option<DefId> {
+    tcx.all_traits(LOCAL_CRATE)
+        .iter()
+        .cloned()
+        .find(|&did| {
+            tcx.item_name(did).as_str() == name &&
+            tcx.crate_name(did.krate).as_str() == "core"
+        })
+}
+
+/// Build `T::default()`, or `<T>::default()` if `T` isn't a plain path.
+pub(crate) fn default_call(ty: &P<Ty>) -> P<Expr> {
+    let func = match ty.kind {
+        TyKind::Path(None, ref path) if path.segments.iter().all(|seg| seg.args.is_none()) => {
+            let mut path = path.clone();
+            path.segments.push(mk().path_segment("default"));
+            mk().path_expr(path)
+        }
+        _ => {
+            let qself = QSelf {
+                ty: ty.clone(),
+                path_span: DUMMY_SP,
+                position: 0,
+            };
+            mk().qpath_expr(Some(qself), vec!["default"])
+        }
+    };
+    mk().call_expr(func, Vec::<P<Expr>>::new())
+}
+
+fn box_new(e: P<Expr>) -> P<Expr> {
+    mk().call_expr(mk().path_expr(vec!["Box", "new"]), vec![e])
+}
+
+/// Build `vec![elem; len]`.
+fn vec_repeat(elem: P<Expr>, len: P<Expr>) -> P<Expr> {
+    let expr_tt = |e: P<Expr>| TokenTree::Token(Token {
+        kind: TokenKind::Interpolated(Lrc::new(Nonterminal::NtExpr(e))),
+        span: DUMMY_SP,
+    });
+    let tts = vec![
+        expr_tt(elem),
+        TokenTree::Token(Token { kind: TokenKind::Semi, span: DUMMY_SP }),
+        expr_tt(len),
+    ];
+    mk().mac_expr(mk().mac(vec!["vec"], tts, MacDelimiter::Bracket))
+}
+
+/// Build the type `name<T>`.
+fn generic_ty(name: &str, ty: &P<Ty>) -> P<Ty> {
+    mk().path_ty(vec![mk().path_segment_with_args(
+        name,
+        mk().angle_bracketed_args(vec![ty.clone()]),
+    )])
+}
+
+/// The pattern of a local that now owns its allocation, made mutable if the allocation is
+/// written through the pointer.
+fn owner_pat(pat: &P<Pat>, mutated: bool) -> P<Pat> {
+    let mut pat = pat.clone();
+    if mutated {
+        if let PatKind::Ident(ref mut mode, _, _) = pat.kind {
+            *mode = BindingMode::ByValue(Mutability::Mutable);
+        }
+    }
+    pat
+}
+
+/// If `e` is `p.offset(i)` or `p.add(i)`, where `p` is `local`, return `p` and `i`.
+fn offset_of<'a>(cx: &RefactorCtxt, local: HirId, e: &'a Expr)
+                 -> Option<(&'a P<Expr>, &'a P<Expr>)> {
+    let (seg, args) = match_or!([e.kind] ExprKind::MethodCall(ref seg, ref args) => (seg, args);
+                                return None);
+    match &*seg.ident.as_str() {
+        "offset" | "add" if args.len() == 2 => {}
+        _ => return None,
+    }
+    if cx.try_resolve_expr_to_hid(&args[0]) != Some(local) {
+        return None;
+    }
+    Some((&args[0], &args[1]))
+}
+
+/// Convert the integer expression `e` to a `usize`.  A cast of an integer to `isize` or `usize`
+/// is replaced instead of cast again, since both produce the same bits as a direct cast to
+/// `usize`.
+pub(crate) fn usize_expr(cx: &RefactorCtxt, e: &P<Expr>) -> P<Expr> {
+    let is_usize = |e: &Expr| match cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+        Some(ty::TyKind::Uint(UintTy::Usize)) => true,
+        _ => false,
+    };
+    let is_pointer_sized = |e: &Expr| match cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+        Some(ty::TyKind::Int(IntTy::Isize)) | Some(ty::TyKind::Uint(UintTy::Usize)) => true,
+        _ => false,
+    };
+    let is_integral = |e: &Expr| cx.opt_node_type(e.id).map_or(false, |ty| ty.is_integral());
+
+    match e.kind {
+        ExprKind::Lit(Lit { kind: LitKind::Int(_, LitIntType::Unsuffixed), .. }) => e.clone(),
+        ExprKind::Cast(ref inner, _) if is_pointer_sized(e) && is_integral(inner) => {
+            if is_usize(inner) {
+                inner.clone()
+            } else {
+                mk().cast_expr(inner.clone(), mk().path_ty(vec!["usize"]))
+            }
+        }
+        _ if is_usize(e) => e.clone(),
+        _ => mk().cast_expr(e.clone(), mk().path_ty(vec!["usize"])),
+    }
+}
+
+/// Rewrite dereferences of the pointer `local` into indexing: `*p` becomes `p[0]`, and
+/// `*p.offset(i)` and `*p.add(i)` become `p[i]`.
+pub(crate) struct IndexRewriter<'a, 'b, 'tcx> {
+    pub(crate) cx: &'a RefactorCtxt<'b, 'tcx>,
+    pub(crate) local: HirId,
+}
+
+impl<'a, 'b, 'tcx> MutVisitor for IndexRewriter<'a, 'b, 'tcx> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        let indexed = match e.kind {
+            ExprKind::Unary(UnOp::Deref, ref inner) => {
+                if self.cx.try_resolve_expr_to_hid(inner) == Some(self.local) {
+                    let zero = mk().lit_expr(mk().int_lit(0, LitIntType::Unsuffixed));
+                    Some((inner.clone(), zero))
+                } else if let Some((ptr, idx)) = offset_of(self.cx, self.local, inner) {
+                    let mut idx = usize_expr(self.cx, idx);
+                    self.visit_expr(&mut idx);
+                    Some((ptr.clone(), idx))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        match indexed {
+            Some((ptr, idx)) => *e = mk().span(e.span).index_expr(ptr, idx),
+            None => mut_visit::noop_visit_expr(e, self),
+        }
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    let spec = CommandSpec::new("Replace single-object `malloc`s and `calloc`s with `Box`es.")
+        .flag("--raw", "keep the pointer raw, using `Box::into_raw`")
+        .optional_arg(
+            "MARK",
+            ArgType::Str,
+            Some("malloc_review"),
+            "the mark to apply to the sites that need review",
+        );
+    reg.register("convert_malloc_to_box", spec, |args| {
+        Ok(mk(ConvertMallocToBox {
+            raw: args.flag("--raw"),
+            label: args.str("MARK").into_symbol(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Replace array allocations made with `calloc` with vectors.")
+        .optional_arg(
+            "MARK",
+            ArgType::Str,
+            Some("calloc_review"),
+            "the mark to apply to the sites that need review",
+        );
+    reg.register("convert_calloc_to_vec", spec, |args| {
+        Ok(mk(ConvertCallocToVec {
+            label: args.str("MARK").into_symbol(),
+        }))
+    });
+}
@@ -0,0 +1,258 @@
+use rustc::ty::{ParamEnv, TyKind};
+use syntax::ast::*;
+use syntax::ptr::P;
+use syntax_pos::Span;
+
+use crate::ast_manip::MutVisitNodes;
+use crate::command::{CommandState, Registry};
+use crate::command_args::{ArgType, CommandSpec};
+use crate::driver::Phase;
+use crate::transform::casts::{cast_kind, cast_tys, is_lossless_int_cast, CastKind, SimpleTy};
+use crate::transform::Transform;
+use crate::RefactorCtxt;
+use c2rust_ast_builder::mk;
+
+/// # `cleanup_index_exprs` Command
+///
+/// Rewrites the integer arithmetic in transpiled index expressions into plain `usize`
+/// arithmetic, e.g., `arr[(i as libc::c_ulong).wrapping_add(1) as usize]` becomes
+/// `arr[i as usize + 1]`.  Only indexes computed in a type at least as wide as `usize` are
+/// rewritten, since the low bits of `wrapping_add`, `wrapping_sub` and `wrapping_mul` don't
+/// depend on the width of the operation.
+///
+/// A wrapping call is replaced by the plain operator when the ranges of its operands show
+/// that it can't overflow at `usize` width, e.g., because they are casts of narrower unsigned
+/// integers or literals.  If `--assume-no-overflow` is passed, all of them are replaced.
+/// Otherwise, a subtraction at the top of an array or slice index becomes
+/// `a.checked_sub(b).unwrap()`, which panics wherever the wrapped index would have been out
+/// of bounds, and all other calls are kept as wrapping operations on `usize`.  Run
+/// `remove_redundant_casts` afterwards to clean up the remaining operand casts.
+///
+/// Once done, prints how many operations of each kind were rewritten or kept.  The kept
+/// wrapping operations are the ones whose wrapping semantics may be load-bearing.  If
+/// `-o REPORT` is passed, their source locations are also written to the `REPORT` file as
+/// JSON.
+pub struct CleanupIndexExprs {
+    pub assume_no_overflow: bool,
+    pub report_path: Option<String>,
+}
+
+impl Transform for CleanupIndexExprs {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        st.start_report("cleanup_index_exprs", self.report_path.clone());
+        let tcx = cx.ty_ctxt();
+        let usize_ty = SimpleTy::from_ty(tcx, tcx.types.usize);
+        let mut c = IndexCleanup {
+            cx,
+            assume_no_overflow: self.assume_no_overflow,
+            usize_ty,
+            usize_max: usize_ty.max_int_value(),
+            sites: Vec::new(),
+        };
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let (base, idx) = match_or!([e.kind] ExprKind::Index(ref base, ref idx) => (base, idx);
+                                        return);
+            let (inner, ty) = match_or!([idx.kind] ExprKind::Cast(ref inner, ref ty) => (inner, ty);
+                                        return);
+            // Plain casts are left to `remove_redundant_casts`
+            if wrapping_op(inner).is_none() {
+                return;
+            }
+            match cast_tys(inner, ty, cx) {
+                Some((_, to_ty)) if to_ty == tcx.types.usize => {}
+                _ => return,
+            }
+            let builtin = is_builtin_index_base(cx, base);
+            let mut new_idx = match_or!([c.index_to_usize(inner, builtin)] Some(idx) => idx;
+                                        return);
+            new_idx.id = idx.id;
+            new_idx.span = idx.span;
+            if let ExprKind::Index(_, ref mut idx) = e.kind {
+                *idx = new_idx;
+            }
+        });
+
+        for (category, span) in c.sites {
+            st.report(cx, category, span);
+        }
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+struct IndexCleanup<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    assume_no_overflow: bool,
+    usize_ty: SimpleTy,
+    usize_max: u128,
+    sites: Vec<(&'static str, Span)>,
+}
+
+impl<'a, 'b, 'tcx> IndexCleanup<'a, 'b, 'tcx> {
+    /// Rewrite the index `e`, which gets cast to `usize`, into `usize` arithmetic.  The sites
+    /// of a failed rewrite are dropped, so they don't show up in the report.
+    fn index_to_usize(&mut self, e: &P<Expr>, builtin: bool) -> Option<P<Expr>> {
+        let num_sites = self.sites.len();
+        let res = self.wrapping_op_to_usize(e, builtin);
+        if res.is_none() {
+            self.sites.truncate(num_sites);
+        }
+        res.map(|(new_e, _, _)| new_e)
+    }
+
+    /// Returns `true` if `ty` is an integer type at least as wide as `usize`, so arithmetic
+    /// in `ty` gives the same low `usize` bits as arithmetic in `usize`.
+    fn keeps_usize_bits(&self, ty: SimpleTy) -> bool {
+        if !ty.is_integer() {
+            return false;
+        }
+        match cast_kind(ty, self.usize_ty) {
+            CastKind::SameWidth | CastKind::Truncate => true,
+            _ => false,
+        }
+    }
+
+    /// Rewrite the wrapping operation `e` into `usize` arithmetic, returning the new
+    /// expression along with the range of values it can take.  `top` is set for the
+    /// operation at the top of an array or slice index, which can use a checked subtraction.
+    fn wrapping_op_to_usize(&mut self, e: &P<Expr>, top: bool) -> Option<(P<Expr>, u128, u128)> {
+        let (op, lhs, rhs) = wrapping_op(e)?;
+        let tcx = self.cx.ty_ctxt();
+        let op_ty = tcx.normalize_erasing_regions(ParamEnv::empty(), self.cx.opt_node_type(e.id)?);
+        if !self.keeps_usize_bits(SimpleTy::from_ty(tcx, op_ty)) {
+            return None;
+        }
+        let (a, alo, ahi) = self.operand_to_usize(lhs)?;
+        let (b, blo, bhi) = self.operand_to_usize(rhs)?;
+        let max = self.usize_max;
+        let range = match op {
+            BinOpKind::Add if ahi + bhi <= max => Some((alo + blo, ahi + bhi)),
+            BinOpKind::Sub if alo >= bhi => Some((alo - bhi, ahi - blo)),
+            BinOpKind::Mul => match ahi.checked_mul(bhi) {
+                Some(hi) if hi <= max => Some((alo * blo, hi)),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        let b_mk = mk().span(e.span);
+        if let Some((lo, hi)) = range {
+            self.sites.push(("overflow ruled out", e.span));
+            return Some((b_mk.binary_expr(op, a, b), lo, hi));
+        }
+        if self.assume_no_overflow {
+            self.sites.push(("overflow assumed away", e.span));
+            return Some((b_mk.binary_expr(op, a, b), 0, max));
+        }
+        // The subtrahend is at most `isize::MAX`, so a wrapped result is larger than any
+        // array or slice length, and indexing with it would have panicked anyway
+        if op == BinOpKind::Sub && top && bhi <= max >> 1 {
+            self.sites.push(("checked subtraction", e.span));
+            let checked = b_mk.clone().method_call_expr(a, "checked_sub", vec![b]);
+            let new_e = b_mk.method_call_expr(checked, "unwrap", Vec::<P<Expr>>::new());
+            return Some((new_e, alo.saturating_sub(bhi), ahi.saturating_sub(blo)));
+        }
+        self.sites.push(("wrapping kept", e.span));
+        let method = match op {
+            BinOpKind::Add => "wrapping_add",
+            BinOpKind::Sub => "wrapping_sub",
+            _ => "wrapping_mul",
+        };
+        Some((b_mk.method_call_expr(a, method, vec![b]), 0, max))
+    }
+
+    /// Turn the integer operand `e` into a `usize` expression with the same low `usize` bits,
+    /// returning it along with the range of values it can take.
+    fn operand_to_usize(&mut self, e: &P<Expr>) -> Option<(P<Expr>, u128, u128)> {
+        let tcx = self.cx.ty_ctxt();
+        let max = self.usize_max;
+        match e.kind {
+            ExprKind::Lit(ref lit) => match lit.kind {
+                LitKind::Int(i, _) if i <= max => {
+                    let new_lit = mk().span(lit.span).int_lit(i, LitIntType::Unsuffixed);
+                    return Some((mk().span(e.span).lit_expr(new_lit), i, i));
+                }
+                _ => {}
+            },
+            ExprKind::MethodCall(..) if wrapping_op(e).is_some() => {
+                if let Some(res) = self.wrapping_op_to_usize(e, false) {
+                    return Some(res);
+                }
+            }
+            ExprKind::Cast(ref ie, ref ty) => {
+                // We can look through a cast that either keeps the low `usize` bits,
+                // or doesn't change the value at all
+                let (from_ty, to_ty) = cast_tys(ie, ty, self.cx)?;
+                let from_ty = SimpleTy::from_ty(tcx, from_ty);
+                let to_ty = SimpleTy::from_ty(tcx, to_ty);
+                if from_ty.is_integer() &&
+                    (self.keeps_usize_bits(to_ty) || is_lossless_int_cast(from_ty, to_ty))
+                {
+                    return self.operand_to_usize(ie);
+                }
+            }
+            _ => {}
+        }
+
+        let ty = tcx.normalize_erasing_regions(ParamEnv::empty(), self.cx.opt_node_type(e.id)?);
+        if ty == tcx.types.usize {
+            return Some((e.clone(), 0, max));
+        }
+        let ty = SimpleTy::from_ty(tcx, ty);
+        if !ty.is_integer() {
+            return None;
+        }
+        let hi = match cast_kind(ty, self.usize_ty) {
+            CastKind::Extend(false) => ty.max_int_value(),
+            _ => max,
+        };
+        Some((mk().span(e.span).cast_expr(e.clone(), mk().ident_ty("usize")), 0, hi))
+    }
+}
+
+/// If `e` is a call to `wrapping_add`, `wrapping_sub` or `wrapping_mul`, return the
+/// equivalent operator along with the operands.
+pub(crate) fn wrapping_op(e: &Expr) -> Option<(BinOpKind, &P<Expr>, &P<Expr>)> {
+    let (seg, args) = match_or!([e.kind] ExprKind::MethodCall(ref seg, ref args) => (seg, args);
+                                return None);
+    if args.len() != 2 {
+        return None;
+    }
+    let op = match &*seg.ident.as_str() {
+        "wrapping_add" => BinOpKind::Add,
+        "wrapping_sub" => BinOpKind::Sub,
+        "wrapping_mul" => BinOpKind::Mul,
+        _ => return None,
+    };
+    Some((op, &args[0], &args[1]))
+}
+
+/// Returns `true` if indexing into `base` is the built-in array or slice indexing, which
+/// panics on any index past the end.
+fn is_builtin_index_base(cx: &RefactorCtxt, base: &Expr) -> bool {
+    let mut ty = match_or!([cx.opt_node_type(base.id)] Some(ty) => ty; return false);
+    while let TyKind::Ref(_, inner, _) = ty.kind {
+        ty = inner;
+    }
+    match ty.kind {
+        TyKind::Array(..) | TyKind::Slice(_) => true,
+        _ => false,
+    }
+}
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    let spec = CommandSpec::new("Rewrite the arithmetic in index expressions on `usize`s.")
+        .flag("--assume-no-overflow", "replace wrapping calls even if they might overflow")
+        .option("-o", "REPORT", ArgType::Str, None, "also write the report to REPORT as JSON");
+    reg.register("cleanup_index_exprs", spec, |args| {
+        Ok(mk(CleanupIndexExprs {
+            assume_no_overflow: args.flag("--assume-no-overflow"),
+            report_path: args.opt_str("-o").map(|s| s.to_owned()),
+        }))
+    });
+}
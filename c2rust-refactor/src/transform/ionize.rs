@@ -1,26 +1,34 @@
+use rustc::hir::{self, HirId, def::{DefKind, Res}};
 use rustc::hir::def_id::DefId;
 use rustc::ty::TyKind;
-use std::collections::HashSet;
+use rustc_target::spec::abi::Abi;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
+use std::mem;
 use syntax::ast::*;
-use syntax::mut_visit::MutVisitor;
+use syntax::mut_visit::{self, MutVisitor};
+use syntax::print::pprust;
 use syntax::ptr::P;
-use smallvec::smallvec;
+use syntax::symbol::Symbol;
+use syntax::ThinVec;
+use syntax_pos::{Span, DUMMY_SP};
+use smallvec::{smallvec, SmallVec};
 
 use c2rust_ast_builder::mk;
-use crate::ast_manip::{FlatMapNodes, MutVisit, visit_nodes};
+use crate::ast_manip::{AstEquiv, FlatMapNodes, MutVisit, Visit, visit_nodes};
 use crate::ast_manip::lr_expr::{self, fold_expr_with_context};
 use crate::command::{CommandState, Registry};
-use crate::driver::{Phase, parse_impl_items, parse_stmts, parse_expr};
+use crate::command_args::{ArgType, CommandSpec};
+use crate::driver::{Phase, parse_impl_items, parse_items, parse_stmts, parse_expr};
 use crate::reflect::reflect_def_path;
 use crate::matcher::{Bindings, BindingType, MatchCtxt, Subst, mut_visit_match_with};
 use crate::transform::Transform;
+use crate::transform::enums::int_value;
+use crate::transform::heap::strip_casts;
 use crate::RefactorCtxt;
 
 /// # `ionize` Command
 /// 
-/// Usage: `ionize`
-/// 
 /// Marks: `target`
 /// 
 /// Convert each union marked `target` to a type-safe Rust enum.  The generated
@@ -191,8 +199,1015 @@ impl Transform for Ionize {
     }
 }
 
+/// # `convert_tagged_union` Command
+///
+/// Convert the C-style tagged union at the crate-relative path `STRUCT`, whose integer field
+/// `TAG` says which field of the union in its field `PAYLOAD` is in use, into a Rust enum.  Each
+/// `VALUE=FIELD` pair says that the tag value `VALUE`, an integer literal or the name of an
+/// integer constant, selects the union field `FIELD`:
+///
+/// ```ignore
+///     pub struct Shape {
+///         pub kind: libc::c_int,
+///         pub u: C2RustUnnamed,
+///     }
+///     pub union C2RustUnnamed {
+///         pub circle: Circle,
+///         pub rect: Rect,
+///     }
+/// ```
+///
+/// After running `convert_tagged_union Shape kind u SHAPE_CIRCLE=circle SHAPE_RECT=rect`:
+///
+/// ```ignore
+///     pub struct Shape {
+///         pub u: ShapeU,
+///     }
+///     #[derive(Copy, Clone)]
+///     pub enum ShapeU {
+///         Circle(Circle),
+///         Rect(Rect),
+///     }
+/// ```
+///
+/// The enum has methods `tag()` and `set_tag(t)`, which read and change the variant in terms of
+/// the old tag values, and `as_FIELD()` and `as_FIELD_mut()` for each field, which return the
+/// field of the variant and panic if the enum is a different variant.  The union itself is left
+/// in place.  Uses of the struct are rewritten:
+///
+///  * An `if s.TAG == VALUE`, or a `match s.TAG` whose arms each test a single value, whose
+///    bodies only use the union through the field their test selects becomes an `if let` or a
+///    `match` on `s.PAYLOAD` that binds the field by reference.
+///  * Other uses of a union field call its accessor.  They're recorded in a report as "checked
+///    access" if they're dominated by a test of the tag for their field: inside the body of an
+///    `if` or `match` arm testing the tag, after such a test in the same `&&` chain, or after an
+///    assignment of the tag or of the whole field earlier in the same block.  The others are
+///    potential bugs in the original code, and are reported as "unchecked access", or, if they're
+///    under a test for a different field, as "access under a different tag" along with a warning.
+///  * Assignments to a whole union field, like `s.PAYLOAD.FIELD = x`, set the variant, and other
+///    reads and writes of the tag call `tag()` and `set_tag()`.
+///  * Struct literals that set the tag to a value and the union to a literal of the field it
+///    selects build that variant.
+///
+/// The dominance check is syntactic: it doesn't notice changes of the tag through other pointers
+/// to the struct or by called functions.
+///
+/// The command is refused, with a warning, if the struct or the union is generic, if the struct
+/// is used in the signature of a foreign function or a function with a non-Rust ABI, since C code
+/// would still see the old layout, or if the tag or the union is used in a way that can't be
+/// converted, such as a compound assignment or mutable borrow of the tag, a use of the whole
+/// union, or a struct literal or pattern that doesn't match the shape above.  With `-o`, the
+/// report is also written to `REPORT` as JSON.
+pub struct ConvertTaggedUnion {
+    pub struct_path: String,
+    pub tag: Symbol,
+    pub payload: Symbol,
+    /// The tag values, as given on the command line, and the union fields they select.
+    pub variants: Vec<(String, Symbol)>,
+    pub report_path: Option<String>,
+}
+
+/// A tagged union being converted by `convert_tagged_union`.
+struct TaggedUnion {
+    struct_did: DefId,
+    union_did: DefId,
+    tag: Symbol,
+    payload: Symbol,
+    /// The values of the integer constants in the crate.
+    consts: HashMap<DefId, i128>,
+    /// The tag value, union field and enum variant name of each variant.
+    variants: Vec<(i128, Symbol, Ident)>,
+}
+
+impl TaggedUnion {
+    /// If `e` is `b.name`, where `b` is a value of the struct, return `b`.
+    fn field_base<'e>(&self, cx: &RefactorCtxt, e: &'e Expr, name: Symbol)
+                      -> Option<&'e P<Expr>> {
+        let base = match e.kind {
+            ExprKind::Field(ref base, ident) if ident.name == name => base,
+            _ => return None,
+        };
+        let ty = cx.opt_node_type(base.id)?;
+        let ty = match ty.kind {
+            TyKind::Ref(_, ty, _) => ty,
+            _ => ty,
+        };
+        match ty.kind {
+            TyKind::Adt(def, _) if def.did == self.struct_did => Some(base),
+            _ => None,
+        }
+    }
+
+    fn tag_base<'e>(&self, cx: &RefactorCtxt, e: &'e Expr) -> Option<&'e P<Expr>> {
+        self.field_base(cx, e, self.tag)
+    }
+
+    fn payload_base<'e>(&self, cx: &RefactorCtxt, e: &'e Expr) -> Option<&'e P<Expr>> {
+        self.field_base(cx, e, self.payload)
+    }
+
+    /// If `e` is a use of a union field, `b.PAYLOAD.f`, return `b` and the variant of `f`.
+    fn variant_access<'e>(&self, cx: &RefactorCtxt, e: &'e Expr)
+                          -> Option<(&'e P<Expr>, usize)> {
+        match e.kind {
+            ExprKind::Field(ref inner, ident) => {
+                let base = self.payload_base(cx, inner)?;
+                Some((base, self.variants.iter().position(|v| v.1 == ident.name)?))
+            }
+            _ => None,
+        }
+    }
+
+    fn is_struct(&self, cx: &RefactorCtxt, id: NodeId) -> bool {
+        match cx.opt_node_type(id).map(|ty| &ty.kind) {
+            Some(TyKind::Adt(def, _)) => def.did == self.struct_did,
+            _ => false,
+        }
+    }
+
+    /// The integer value of `e`, which may be a constant.
+    fn value(&self, cx: &RefactorCtxt, e: &Expr) -> Option<i128> {
+        match e.kind {
+            ExprKind::Paren(ref e) | ExprKind::Cast(ref e, _) => self.value(cx, e),
+            ExprKind::Path(..) => self.consts.get(&cx.try_resolve_expr(e)?).cloned(),
+            _ => int_value(e),
+        }
+    }
+
+    /// The variant selected by the tag value `e`.
+    fn variant_of(&self, cx: &RefactorCtxt, e: &Expr) -> Option<usize> {
+        let value = self.value(cx, e)?;
+        self.variants.iter().position(|v| v.0 == value)
+    }
+
+    /// The variant selected by a pattern on the tag.
+    fn pat_variant(&self, cx: &RefactorCtxt, p: &Pat) -> Option<usize> {
+        let value = match p.kind {
+            PatKind::Paren(ref p) => return self.pat_variant(cx, p),
+            PatKind::Lit(ref e) => self.value(cx, e)?,
+            PatKind::Path(..) => match cx.try_resolve_pat_hir(p)? {
+                Res::Def(DefKind::Const, did) => *self.consts.get(&did)?,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        self.variants.iter().position(|v| v.0 == value)
+    }
+
+    /// If `e` tests `b.TAG == VALUE`, return `b` and the variant of `VALUE`.
+    fn tag_test<'e>(&self, cx: &RefactorCtxt, e: &'e Expr) -> Option<(&'e P<Expr>, usize)> {
+        match e.kind {
+            ExprKind::Paren(ref e) => self.tag_test(cx, e),
+            ExprKind::Binary(op, ref l, ref r) if op.node == BinOpKind::Eq => {
+                if let Some(base) = self.tag_base(cx, strip_casts(l)) {
+                    return Some((base, self.variant_of(cx, r)?));
+                }
+                Some((self.tag_base(cx, strip_casts(r))?, self.variant_of(cx, l)?))
+            }
+            _ => None,
+        }
+    }
+
+    /// The tag tests that hold whenever the condition `e` is true.
+    fn facts<'e>(&self, cx: &RefactorCtxt, e: &'e Expr) -> Vec<(&'e P<Expr>, usize)> {
+        match e.kind {
+            ExprKind::Paren(ref e) => self.facts(cx, e),
+            ExprKind::Binary(op, ref l, ref r) if op.node == BinOpKind::And => {
+                let mut facts = self.facts(cx, l);
+                facts.extend(self.facts(cx, r));
+                facts
+            }
+            _ => self.tag_test(cx, e).into_iter().collect(),
+        }
+    }
+
+    /// If `e` is a literal of the union that sets one field, `U { f: x }`, return the variant of
+    /// `f` and `x`.
+    fn union_lit<'e>(&self, cx: &RefactorCtxt, e: &'e Expr) -> Option<(usize, &'e P<Expr>)> {
+        match cx.opt_node_type(e.id)?.kind {
+            TyKind::Adt(def, _) if def.did == self.union_did => {}
+            _ => return None,
+        }
+        match e.kind {
+            ExprKind::Struct(_, ref fields, None) if fields.len() == 1 => {
+                let v = self.variants.iter().position(|v| v.1 == fields[0].ident.name)?;
+                Some((v, &fields[0].expr))
+            }
+            _ => None,
+        }
+    }
+
+    /// If `e` changes the variant of `b`, by assigning its tag, its union or one of the union's
+    /// fields, return `b` and the new variant, if it's known.
+    fn assigned<'e>(&self, cx: &RefactorCtxt, e: &'e Expr)
+                    -> Option<(&'e P<Expr>, Option<usize>)> {
+        let (lhs, rhs) = match_or!([e.kind] ExprKind::Assign(ref l, ref r) => (l, r); return None);
+        if let Some(base) = self.tag_base(cx, lhs) {
+            return Some((base, self.variant_of(cx, rhs)));
+        }
+        if let Some((base, v)) = self.variant_access(cx, lhs) {
+            return Some((base, Some(v)));
+        }
+        let base = self.payload_base(cx, lhs)?;
+        Some((base, self.union_lit(cx, rhs).map(|(v, _)| v)))
+    }
+
+    /// For a literal of the struct with the fields `fields`, return the variant and value of the
+    /// union, or `None` if it sets neither the tag nor the union.  Returns `Err` if the literal
+    /// can't be converted.
+    fn struct_lit<'e>(&self, cx: &RefactorCtxt, fields: &'e [Field])
+                      -> Result<Option<(usize, &'e P<Expr>)>, ()> {
+        let tag = fields.iter().find(|f| f.ident.name == self.tag);
+        let payload = fields.iter().find(|f| f.ident.name == self.payload);
+        match (tag, payload) {
+            (None, None) => Ok(None),
+            (Some(tag), Some(payload)) => {
+                let v = self.variant_of(cx, &tag.expr).ok_or(())?;
+                match self.union_lit(cx, &payload.expr) {
+                    Some((w, x)) if w == v => Ok(Some((v, x))),
+                    _ => Err(()),
+                }
+            }
+            _ => Err(()),
+        }
+    }
+
+    /// Find the uses of the tag and the union that can't be converted.
+    fn unconvertible(&self, krate: &Crate, cx: &RefactorCtxt) -> Vec<(Span, &'static str)> {
+        let mut problems = Vec::new();
+        let mut convertible = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Field(ref inner, _) = e.kind {
+                if self.variant_access(cx, e).is_some() {
+                    convertible.insert(inner.id);
+                }
+            }
+            match e.kind {
+                ExprKind::Assign(ref lhs, ref rhs) if self.payload_base(cx, lhs).is_some() => {
+                    if self.union_lit(cx, rhs).is_some() {
+                        convertible.insert(lhs.id);
+                    }
+                }
+                ExprKind::AssignOp(_, ref lhs, _) if self.tag_base(cx, lhs).is_some() => {
+                    problems.push((e.span, "compound assignment to the tag"));
+                }
+                ExprKind::AddrOf(_, Mutability::Mutable, ref place)
+                        if self.tag_base(cx, place).is_some() => {
+                    problems.push((e.span, "mutable borrow of the tag"));
+                }
+                ExprKind::Struct(_, ref fields, _) if self.is_struct(cx, e.id) => {
+                    if self.struct_lit(cx, fields).is_err() {
+                        problems.push((e.span, "struct literal that doesn't set a known variant"));
+                    }
+                }
+                _ => {}
+            }
+        });
+        visit_nodes(krate, |e: &Expr| {
+            if self.payload_base(cx, e).is_some() && !convertible.contains(&e.id) {
+                problems.push((e.span, "use of the union other than through the given fields"));
+            }
+        });
+        visit_nodes(krate, |p: &Pat| {
+            if let PatKind::Struct(_, ref fields, _) = p.kind {
+                if self.is_struct(cx, p.id) &&
+                   fields.iter().any(|f| f.ident.name == self.tag || f.ident.name == self.payload) {
+                    problems.push((p.span, "struct pattern on the tag or the union"));
+                }
+            }
+        });
+        problems
+    }
+}
+
+/// Collect the places that are assigned, borrowed mutably or autoref'd for a `&mut self` method
+/// call, along with the places they're fields or elements of.
+fn mut_places(krate: &Crate, cx: &RefactorCtxt) -> HashSet<NodeId> {
+    let mut places = HashSet::new();
+    visit_nodes(krate, |e: &Expr| {
+        let mut place = match e.kind {
+            ExprKind::Assign(ref lhs, _) | ExprKind::AssignOp(_, ref lhs, _) => lhs,
+            ExprKind::AddrOf(_, Mutability::Mutable, ref place) => place,
+            ExprKind::MethodCall(_, ref args) => {
+                match cx.opt_adjusted_node_type(args[0].id).map(|ty| &ty.kind) {
+                    Some(TyKind::Ref(_, _, hir::Mutability::Mutable)) => &args[0],
+                    _ => return,
+                }
+            }
+            _ => return,
+        };
+        loop {
+            places.insert(place.id);
+            match place.kind {
+                ExprKind::Field(ref base, _) | ExprKind::Index(ref base, _) |
+                ExprKind::Paren(ref base) => place = base,
+                _ => break,
+            }
+        }
+    });
+    places
+}
+
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            let first = chars.next().unwrap();
+            first.to_uppercase().chain(chars).collect::<String>()
+        })
+        .collect()
+}
+
+impl Transform for ConvertTaggedUnion {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+
+        // (1) Find the struct, its tag and union, and the variants, and check that the conversion
+        // is possible.
+
+        let struct_path = self.struct_path.trim_start_matches("crate::");
+        let mut struct_item = None;
+        let mut const_names = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            match i.kind {
+                ItemKind::Struct(..)
+                        if tcx.def_path_str(cx.node_def_id(i.id)) == struct_path => {
+                    struct_item = Some((cx.node_def_id(i.id), i.ident, i.vis.clone()));
+                }
+                ItemKind::Const(_, ref init) => {
+                    if let Some(value) = int_value(init) {
+                        const_names.insert(cx.node_def_id(i.id), (i.ident.name, value));
+                    }
+                }
+                _ => {}
+            }
+        });
+        let (struct_did, struct_ident, vis) = match_or!([struct_item] Some(x) => x; {
+            cx.session().warn(&format!("no struct named `{}`", struct_path));
+            return;
+        });
+
+        let field_ty = |name: Symbol| {
+            tcx.adt_def(struct_did).non_enum_variant().fields.iter()
+                .find(|f| f.ident.name == name)
+                .map(|f| tcx.type_of(f.did))
+        };
+        let (tag_ty, payload_ty) = match (field_ty(self.tag), field_ty(self.payload)) {
+            (Some(tag_ty), Some(payload_ty)) => (tag_ty, payload_ty),
+            (tag_ty, _) => {
+                let missing = if tag_ty.is_none() { self.tag } else { self.payload };
+                cx.session().warn(&format!("`{}` has no field `{}`", struct_path, missing));
+                return;
+            }
+        };
+        let union_did = match payload_ty.kind {
+            TyKind::Adt(def, _) if def.is_union() && def.did.is_local() => Some(def.did),
+            _ => None,
+        };
+
+        let mut extern_use = None;
+        let mut check_sig = |id: NodeId| {
+            let did = cx.node_def_id(id);
+            let sig = tcx.fn_sig(did);
+            let uses_struct = sig.skip_binder().inputs_and_output.iter()
+                .flat_map(|ty| ty.walk())
+                .any(|ty| match ty.kind {
+                    TyKind::Adt(def, _) => def.did == struct_did,
+                    _ => false,
+                });
+            if sig.abi() != Abi::Rust && uses_struct && extern_use.is_none() {
+                extern_use = Some(did);
+            }
+        };
+        visit_nodes(krate, |i: &Item| {
+            if let ItemKind::Fn(..) = i.kind {
+                check_sig(i.id);
+            }
+        });
+        visit_nodes(krate, |i: &ForeignItem| {
+            if let ForeignItemKind::Fn(..) = i.kind {
+                check_sig(i.id);
+            }
+        });
+
+        let reason = if !tag_ty.is_integral() {
+            Some(format!("`{}` isn't an integer", self.tag))
+        } else if union_did.is_none() {
+            Some(format!("`{}` isn't a union defined in this crate", self.payload))
+        } else if !tcx.generics_of(struct_did).params.is_empty() ||
+                  !tcx.generics_of(union_did.unwrap()).params.is_empty() {
+            Some("it's generic".to_owned())
+        } else if let Some(did) = extern_use {
+            Some(format!("it's used by the extern function `{}`", tcx.def_path_str(did)))
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            cx.session().warn(&format!("not converting `{}`: {}", struct_path, reason));
+            return;
+        }
+        let union_did = union_did.unwrap();
+
+        let mut union_fields = HashMap::new();
+        let mut tag_ast_ty = None;
+        visit_nodes(krate, |i: &Item| {
+            match i.kind {
+                ItemKind::Union(VariantData::Struct(ref fields, _), _)
+                        if cx.node_def_id(i.id) == union_did => {
+                    for f in fields {
+                        union_fields.insert(f.ident.unwrap().name, f.ty.clone());
+                    }
+                }
+                ItemKind::Struct(VariantData::Struct(ref fields, _), _)
+                        if cx.node_def_id(i.id) == struct_did => {
+                    tag_ast_ty = fields.iter()
+                        .find(|f| f.ident.map(|id| id.name) == Some(self.tag))
+                        .map(|f| f.ty.clone());
+                }
+                _ => {}
+            }
+        });
+
+        let mut variants: Vec<(i128, Symbol, Ident)> = Vec::new();
+        for &(ref value_str, field) in &self.variants {
+            let value = value_str.parse::<i128>().ok().or_else(|| {
+                const_names.values()
+                    .find(|&&(name, _)| *name.as_str() == *value_str.trim_start_matches("crate::"))
+                    .map(|&(_, value)| value)
+            });
+            let value = match_or!([value] Some(x) => x; {
+                cx.session().warn(&format!("`{}` isn't an integer or an integer constant",
+                                           value_str));
+                return;
+            });
+            if !union_fields.contains_key(&field) {
+                cx.session().warn(&format!("the union in `{}::{}` has no field `{}`",
+                                           struct_path, self.payload, field));
+                return;
+            }
+            if variants.iter().any(|v| v.0 == value || v.1 == field) {
+                cx.session().warn(&format!("`{}={}` repeats a tag value or a field",
+                                           value_str, field));
+                return;
+            }
+            variants.push((value, field, mk().ident(camel_case(&field.as_str()))));
+        }
+
+        let enum_name = mk().ident(format!("{}{}", struct_ident,
+                                           camel_case(&self.payload.as_str())));
+        let mut name_taken = false;
+        visit_nodes(krate, |i: &Item| {
+            if i.ident == enum_name && !matches!([i.kind] ItemKind::Use(..)) &&
+               tcx.parent(cx.node_def_id(i.id)) == tcx.parent(struct_did) {
+                name_taken = true;
+            }
+        });
+        if name_taken {
+            cx.session().warn(&format!("not converting `{}`: `{}` is already defined",
+                                       struct_path, enum_name));
+            return;
+        }
+
+        let info = TaggedUnion {
+            struct_did,
+            union_did,
+            tag: self.tag,
+            payload: self.payload,
+            consts: const_names.iter().map(|(&did, &(_, value))| (did, value)).collect(),
+            variants,
+        };
+        let problems = info.unconvertible(krate, cx);
+        if !problems.is_empty() {
+            for (span, what) in problems {
+                cx.session().span_warn(span, &format!("can't convert `{}`: {}", struct_path,
+                                                      what));
+            }
+            cx.session().warn(&format!("not converting `{}`", struct_path));
+            return;
+        }
+
+        // (2) Rewrite the uses of the tag and the union.
+
+        st.start_report("convert_tagged_union", self.report_path.clone());
+        let hir_map = cx.hir_map();
+        let mut folder = TaggedUnionFolder {
+            cx,
+            st,
+            info: &info,
+            mut_places: mut_places(krate, cx),
+            enum_name,
+            enum_mod: hir_map.get_module_parent_node(hir_map.as_local_hir_id(struct_did).unwrap()),
+            facts: Vec::new(),
+            bindings: Vec::new(),
+        };
+        krate.visit(&mut folder);
+
+        // (3) Replace the tag and the union by the enum, and add the enum after the struct.
+
+        let vis = pprust::vis_to_string(&vis);
+        let tag_ty = pprust::ty_to_string(&tag_ast_ty.unwrap());
+        let mut src = format!("#[derive(Copy, Clone)]\n{}enum {} {{\n", vis, enum_name);
+        for &(_, field, variant) in &info.variants {
+            src.push_str(&format!("    {}({}),\n", variant,
+                                  pprust::ty_to_string(&union_fields[&field])));
+        }
+        src.push_str(&format!("}}\n\nimpl {} {{\n", enum_name));
+        src.push_str(&format!("    {}fn tag(&self) -> {} {{\n        match *self {{\n",
+                              vis, tag_ty));
+        for &(value, _, variant) in &info.variants {
+            src.push_str(&format!("            {}::{}(_) => {},\n", enum_name, variant, value));
+        }
+        src.push_str(&format!(
+            "        }}\n    }}\n\n\
+             {vis}fn set_tag(&mut self, tag: {ty}) {{\n\
+                 if self.tag() == tag {{\n\
+                     return;\n\
+                 }}\n\
+                 *self = match tag {{\n",
+            vis = vis, ty = tag_ty));
+        for &(value, _, variant) in &info.variants {
+            src.push_str(&format!("            {} => {}::{}(unsafe {{ ::std::mem::zeroed() }}),\n",
+                                  value, enum_name, variant));
+        }
+        src.push_str(&format!(
+            "            _ => panic!(\"invalid tag for {}: {{}}\", tag),\n        }};\n    }}\n",
+            enum_name));
+        for &(_, field, variant) in &info.variants {
+            for &(suffix, self_ty, binding) in &[("", "&self", "ref x"),
+                                                 ("_mut", "&mut self", "ref mut x")] {
+                src.push_str(&format!(
+                    "\n    {vis}fn as_{field}{suffix}({self_ty}) -> {ref_ty} {{\n\
+                         match *self {{\n\
+                             {name}::{variant}({binding}) => x,\n\
+                             _ => panic!(\"{name} is not {variant}\"),\n\
+                         }}\n\
+                     }}\n",
+                    vis = vis, field = field, suffix = suffix, self_ty = self_ty,
+                    ref_ty = format!("{} {}", if suffix.is_empty() { "&" } else { "&mut" },
+                                     pprust::ty_to_string(&union_fields[&field])),
+                    name = enum_name, variant = variant, binding = binding));
+            }
+        }
+        src.push_str("}\n");
+        let mut new_items = Some(parse_items(cx.session(), &src));
+
+        let enum_ty = mk().ident_ty(enum_name);
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            if !matches!([i.kind] ItemKind::Struct(..)) || cx.node_def_id(i.id) != struct_did {
+                return smallvec![i];
+            }
+            let i = i.map(|mut i| {
+                if let ItemKind::Struct(VariantData::Struct(ref mut fields, _), _) = i.kind {
+                    fields.retain(|f| f.ident.map(|id| id.name) != Some(self.tag));
+                    for f in fields.iter_mut() {
+                        if f.ident.map(|id| id.name) == Some(self.payload) {
+                            f.ty = enum_ty.clone();
+                        }
+                    }
+                }
+                i
+            });
+            let mut items: SmallVec<[P<Item>; 1]> = smallvec![i];
+            items.extend(new_items.take().unwrap_or_default());
+            items
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Rewrites the uses of the tag and the union for `convert_tagged_union`, keeping track of the
+/// variants known from enclosing tag tests and earlier assignments.
+struct TaggedUnionFolder<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    st: &'a CommandState,
+    info: &'a TaggedUnion,
+    mut_places: HashSet<NodeId>,
+    enum_name: Ident,
+    /// The module that the enum is defined in.
+    enum_mod: HirId,
+    /// What's known about the variants of values of the struct, innermost last: the value, and
+    /// its variant, or `None` if it was changed to an unknown one.
+    facts: Vec<(P<Expr>, Option<usize>)>,
+    /// The bindings of the fields in the converted `if let`s and `match`es: the value, the variant
+    /// and the name of the binding.
+    bindings: Vec<(P<Expr>, usize, Ident)>,
+}
+
+impl<'a, 'b, 'tcx> TaggedUnionFolder<'a, 'b, 'tcx> {
+    /// The path to variant `v` of the enum, from the module of `site`.
+    fn variant_path(&self, site: NodeId, v: usize) -> Path {
+        let hir_map = self.cx.hir_map();
+        let mut path = if hir_map.get_module_parent_node(hir_map.node_to_hir_id(site)) ==
+                          self.enum_mod {
+            mk().path(vec![self.enum_name])
+        } else {
+            let mut path = self.cx.def_path(self.info.struct_did);
+            path.segments.last_mut().unwrap().ident = self.enum_name;
+            path
+        };
+        path.segments.push(mk().path_segment(self.info.variants[v].2));
+        path
+    }
+
+    fn variant_expr(&self, site: NodeId, v: usize, value: P<Expr>) -> P<Expr> {
+        mk().call_expr(mk().path_expr(self.variant_path(site, v)), vec![value])
+    }
+
+    fn payload(&self, base: &P<Expr>) -> P<Expr> {
+        mk().field_expr(base.clone(), self.info.payload)
+    }
+
+    fn known_variant(&self, base: &P<Expr>) -> Option<usize> {
+        self.facts.iter().rev().find(|f| f.0.ast_equiv(base)).and_then(|f| f.1)
+    }
+
+    /// Check how `body` uses the union of `base`, where `base` is known to be variant `v`.
+    /// Returns `Ok(Some(m))` if it only uses the field of `v`, which can be bound with mutability
+    /// `m`, `Ok(None)` if it doesn't use the union, and `Err` otherwise.
+    fn field_uses<T: Visit>(&self, body: &T, base: &P<Expr>, v: usize)
+                            -> Result<Option<Mutability>, ()> {
+        let (cx, info) = (self.cx, self.info);
+        let (mut bases, mut tags, mut payloads, mut fields) = (0, 0, 0, 0);
+        let (mut mutable, mut ok) = (false, true);
+        visit_nodes(body, |e: &Expr| {
+            let is_base = |b: Option<&P<Expr>>| b.map_or(false, |b| b.ast_equiv(base));
+            if e.ast_equiv(&**base) {
+                bases += 1;
+            }
+            if is_base(info.tag_base(cx, e)) {
+                tags += 1;
+            }
+            if is_base(info.payload_base(cx, e)) {
+                payloads += 1;
+            }
+            if let Some((b, w)) = info.variant_access(cx, e) {
+                if b.ast_equiv(base) {
+                    fields += 1;
+                    ok &= w == v;
+                    mutable |= self.mut_places.contains(&e.id);
+                }
+            }
+            if is_base(info.assigned(cx, e).map(|(b, _)| b)) {
+                ok = false;
+            }
+        });
+        if payloads == 0 {
+            return Ok(None);
+        }
+        // The field can only be bound if the value isn't otherwise used while it's borrowed.
+        if !ok || fields != payloads || bases != tags + payloads || (mutable && tags > 0) {
+            return Err(());
+        }
+        Ok(Some(if mutable { Mutability::Mutable } else { Mutability::Immutable }))
+    }
+
+    /// A pattern for variant `v` that binds its field by reference with mutability `mutbl`, using
+    /// a name that isn't used in `body`.
+    fn variant_pat<T: Visit>(&self, site: NodeId, v: usize, mutbl: Option<Mutability>,
+                             body: &T) -> (P<Pat>, Option<Ident>) {
+        let path = self.variant_path(site, v);
+        let (pat, binding) = match mutbl {
+            Some(mutbl) => {
+                let mut name = self.info.variants[v].1.to_string();
+                loop {
+                    let mut used = false;
+                    visit_nodes(body, |p: &Path| {
+                        used |= p.segments.len() == 1 && *p.segments[0].ident.as_str() == *name;
+                    });
+                    visit_nodes(body, |p: &Pat| {
+                        if let PatKind::Ident(_, ident, _) = p.kind {
+                            used |= *ident.as_str() == *name;
+                        }
+                    });
+                    if !used {
+                        break;
+                    }
+                    name.push('_');
+                }
+                let binding = mk().ident(name);
+                (mk().set_mutbl(mutbl).ident_ref_pat(binding), Some(binding))
+            }
+            None => (mk().wild_pat(), None),
+        };
+        let pat = P(Pat {
+            id: DUMMY_NODE_ID,
+            kind: PatKind::TupleStruct(path, vec![pat]),
+            span: DUMMY_SP,
+        });
+        (pat, binding)
+    }
+
+    /// Convert `if b.TAG == VALUE { ... }` to `if let E::F(ref f) = b.PAYLOAD { ... }`, if the
+    /// body only uses the union through the field `F` selected by `VALUE`.
+    fn convert_if(&mut self, e: &mut P<Expr>) -> bool {
+        let (base, v, mutbl) = match e.kind {
+            ExprKind::If(ref cond, ref then, _) => {
+                let (base, v) = match_or!([self.info.tag_test(self.cx, cond)]
+                                          Some((b, v)) => (b.clone(), v); return false);
+                match self.field_uses(&**then, &base, v) {
+                    Ok(Some(mutbl)) => (base, v, mutbl),
+                    _ => return false,
+                }
+            }
+            _ => return false,
+        };
+        self.st.report(self.cx, "converted to if let", e.span);
+        let id = e.id;
+        let (pat, binding) = match e.kind {
+            ExprKind::If(_, ref then, _) => self.variant_pat(id, v, Some(mutbl), &**then),
+            _ => unreachable!(),
+        };
+        let mut scrutinee = self.payload(&base);
+        self.visit_expr(&mut scrutinee);
+        if let ExprKind::If(ref mut cond, ref mut then, ref mut els) = e.kind {
+            *cond = P(Expr {
+                id: DUMMY_NODE_ID,
+                kind: ExprKind::Let(pat, scrutinee),
+                span: cond.span,
+                attrs: ThinVec::new(),
+            });
+            self.facts.push((base.clone(), Some(v)));
+            self.bindings.push((base, v, binding.unwrap()));
+            self.visit_block(then);
+            self.bindings.pop();
+            self.facts.pop();
+            if let Some(ref mut els) = *els {
+                self.visit_expr(els);
+            }
+        }
+        true
+    }
+
+    /// Convert a `match b.TAG` whose arms each test a single value to a `match b.PAYLOAD` on the
+    /// variants, if each arm only uses the union through the field its value selects.
+    fn convert_match(&mut self, e: &mut P<Expr>) -> bool {
+        let (base, arm_variants) = match e.kind {
+            ExprKind::Match(ref scrutinee, ref arms) => {
+                let base = match_or!([self.info.tag_base(self.cx, strip_casts(scrutinee))]
+                                     Some(b) => b.clone(); return false);
+                let mut arm_variants = Vec::new();
+                for arm in arms {
+                    if arm.guard.is_some() {
+                        return false;
+                    }
+                    if let PatKind::Wild = arm.pat.kind {
+                        arm_variants.push(None);
+                        continue;
+                    }
+                    let v = match_or!([self.info.pat_variant(self.cx, &arm.pat)] Some(v) => v;
+                                      return false);
+                    match self.field_uses(&*arm.body, &base, v) {
+                        Ok(mutbl) => arm_variants.push(Some((v, mutbl))),
+                        Err(()) => return false,
+                    }
+                }
+                (base, arm_variants)
+            }
+            _ => return false,
+        };
+        self.st.report(self.cx, "converted to match", e.span);
+        let id = e.id;
+        // If the arms cover every variant, the wildcard arm can't be reached any more.
+        let covered = arm_variants.iter().filter_map(|a| a.map(|(v, _)| v))
+            .collect::<HashSet<_>>();
+        let all_covered = covered.len() == self.info.variants.len();
+
+        let mut scrutinee = self.payload(&base);
+        self.visit_expr(&mut scrutinee);
+        if let ExprKind::Match(ref mut old_scrutinee, ref mut arms) = e.kind {
+            *old_scrutinee = scrutinee;
+            let old_arms = mem::replace(arms, Vec::new());
+            for (mut arm, variant) in old_arms.into_iter().zip(arm_variants) {
+                match variant {
+                    Some((v, mutbl)) => {
+                        let (pat, binding) = self.variant_pat(id, v, mutbl, &*arm.body);
+                        arm.pat = pat;
+                        self.facts.push((base.clone(), Some(v)));
+                        if let Some(binding) = binding {
+                            self.bindings.push((base.clone(), v, binding));
+                        }
+                        self.visit_expr(&mut arm.body);
+                        if binding.is_some() {
+                            self.bindings.pop();
+                        }
+                        self.facts.pop();
+                    }
+                    None if all_covered => continue,
+                    None => self.visit_expr(&mut arm.body),
+                }
+                arms.push(arm);
+            }
+        }
+        true
+    }
+
+    /// Rewrite `e` if it's a use of a union field, `b.PAYLOAD.f`.  `autoderef` says if `e` is in
+    /// a position where references are dereferenced automatically, like the base of a field
+    /// access.
+    fn rewrite_access(&mut self, e: &mut P<Expr>, autoderef: bool) {
+        let (base, v) = match_or!([self.info.variant_access(self.cx, e)]
+                                  Some((b, v)) => (b.clone(), v); return);
+        let span = e.span;
+        let binding = self.bindings.iter().rev()
+            .find(|b| b.0.ast_equiv(&base) && b.1 == v)
+            .map(|b| b.2);
+        let new = if let Some(binding) = binding {
+            mk().span(span).ident_expr(binding)
+        } else {
+            let field = self.info.variants[v].1;
+            match self.known_variant(&base) {
+                Some(known) if known == v => self.st.report(self.cx, "checked access", e.span),
+                Some(_) => {
+                    self.st.report(self.cx, "access under a different tag", e.span);
+                    self.cx.session().span_warn(
+                        e.span,
+                        &format!("`{}` is used under a test of the tag for a different field",
+                                 field),
+                    );
+                }
+                None => self.st.report(self.cx, "unchecked access", e.span),
+            }
+            let method = if self.mut_places.contains(&e.id) {
+                format!("as_{}_mut", field)
+            } else {
+                format!("as_{}", field)
+            };
+            mk().span(span).method_call_expr(self.payload(&base), method, Vec::<P<Expr>>::new())
+        };
+        *e = if autoderef { new } else { mk().span(span).unary_expr(UnOp::Deref, new) };
+    }
+}
+
+impl<'a, 'b, 'tcx> MutVisitor for TaggedUnionFolder<'a, 'b, 'tcx> {
+    fn visit_block(&mut self, b: &mut P<Block>) {
+        let depth = self.facts.len();
+        let stmts = mem::replace(&mut b.stmts, Vec::new());
+        for s in stmts {
+            // A change of the variant anywhere in the statement, like in the body of a loop,
+            // invalidates what's known from earlier statements.
+            let (cx, info) = (self.cx, self.info);
+            let mut changed = Vec::new();
+            visit_nodes(&s, |e: &Expr| {
+                if let Some((base, _)) = info.assigned(cx, e) {
+                    changed.push((base.clone(), None));
+                }
+            });
+            self.facts.extend(changed);
+            // After `b.TAG = VALUE;` or `b.PAYLOAD.f = x;`, the variant of `b` is known.
+            let set = match s.kind {
+                StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => {
+                    info.assigned(cx, e).map(|(base, v)| (base.clone(), v))
+                }
+                _ => None,
+            };
+            b.stmts.extend(self.flat_map_stmt(s));
+            self.facts.extend(set);
+        }
+        self.facts.truncate(depth);
+    }
+
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        if self.convert_if(e) || self.convert_match(e) {
+            return;
+        }
+        let cx = self.cx;
+
+        // Writes of the tag and the union set the variant.
+        if let ExprKind::Assign(ref lhs, ref rhs) = e.kind {
+            let b = mk().span(e.span);
+            let new = if let Some(base) = self.info.tag_base(cx, lhs) {
+                Some(b.method_call_expr(self.payload(base), "set_tag", vec![rhs.clone()]))
+            } else if let Some((base, v)) = self.info.variant_access(cx, lhs) {
+                let variant = self.variant_expr(e.id, v, rhs.clone());
+                Some(b.assign_expr(self.payload(base), variant))
+            } else if let (Some(_), Some((v, x))) = (self.info.payload_base(cx, lhs),
+                                                     self.info.union_lit(cx, rhs)) {
+                Some(b.assign_expr(lhs.clone(), self.variant_expr(e.id, v, x.clone())))
+            } else {
+                None
+            };
+            if let Some(new) = new {
+                *e = new;
+            }
+        }
+
+        // Struct literals build the variant.
+        if matches!([e.kind] ExprKind::Struct(..)) && self.info.is_struct(cx, e.id) {
+            let id = e.id;
+            let lit = match e.kind {
+                ExprKind::Struct(_, ref fields, _) => self.info.struct_lit(cx, fields)
+                    .ok().and_then(|lit| lit).map(|(v, x)| (v, x.clone())),
+                _ => None,
+            };
+            if let Some((v, x)) = lit {
+                let variant = self.variant_expr(id, v, x);
+                if let ExprKind::Struct(_, ref mut fields, _) = e.kind {
+                    fields.retain(|f| f.ident.name != self.info.tag);
+                    for f in fields.iter_mut() {
+                        if f.ident.name == self.info.payload {
+                            f.expr = variant.clone();
+                        }
+                    }
+                }
+            }
+        }
+
+        // Tag tests in conditions and `match`es tell which variant is in use.
+        let facts = match e.kind {
+            ExprKind::If(ref cond, ..) => self.info.facts(cx, cond),
+            ExprKind::Binary(op, ref l, _) if op.node == BinOpKind::And => self.info.facts(cx, l),
+            _ => Vec::new(),
+        }.into_iter().map(|(base, v)| (base.clone(), Some(v))).collect::<Vec<_>>();
+        match e.kind {
+            ExprKind::If(ref mut cond, ref mut then, ref mut els) => {
+                self.visit_expr(cond);
+                let depth = self.facts.len();
+                self.facts.extend(facts);
+                self.visit_block(then);
+                self.facts.truncate(depth);
+                if let Some(ref mut els) = *els {
+                    self.visit_expr(els);
+                }
+                return;
+            }
+            ExprKind::Binary(op, ref mut l, ref mut r) if op.node == BinOpKind::And => {
+                self.visit_expr(l);
+                let depth = self.facts.len();
+                self.facts.extend(facts);
+                self.visit_expr(r);
+                self.facts.truncate(depth);
+                return;
+            }
+            ExprKind::Match(ref mut scrutinee, ref mut arms) => {
+                let base = self.info.tag_base(cx, strip_casts(scrutinee)).cloned();
+                if let Some(base) = base {
+                    self.visit_expr(scrutinee);
+                    for arm in arms {
+                        let v = self.info.pat_variant(cx, &arm.pat);
+                        self.facts.push((base.clone(), v));
+                        self.visit_pat(&mut arm.pat);
+                        if let Some(ref mut guard) = arm.guard {
+                            self.visit_expr(guard);
+                        }
+                        self.visit_expr(&mut arm.body);
+                        self.facts.pop();
+                    }
+                    return;
+                }
+            }
+            _ => {}
+        }
+
+        // Other reads of the tag and uses of the union fields.
+        if let Some(base) = self.info.tag_base(cx, e).cloned() {
+            *e = mk().span(e.span).method_call_expr(self.payload(&base), "tag",
+                                                     Vec::<P<Expr>>::new());
+        }
+        self.rewrite_access(e, false);
+        match e.kind {
+            ExprKind::Field(ref mut base, _) | ExprKind::Index(ref mut base, _) => {
+                self.rewrite_access(base, true)
+            }
+            ExprKind::MethodCall(_, ref mut args) => self.rewrite_access(&mut args[0], true),
+            _ => {}
+        }
+        mut_visit::noop_visit_expr(e, self);
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("ionize", |_args| mk(Ionize{}))
+    let spec = CommandSpec::new("Convert each union marked `target` to a Rust enum.");
+    reg.register("ionize", spec, |_args| Ok(mk(Ionize{})));
+
+    let spec = CommandSpec::new("Convert the C-style tagged union STRUCT into a Rust enum.")
+        .arg("STRUCT", ArgType::Str, "the crate-relative path of the struct")
+        .arg("TAG", ArgType::Str, "the field holding the tag")
+        .arg("PAYLOAD", ArgType::Str, "the field holding the union")
+        .rest("VALUE=FIELD", "the tag value that selects each union field")
+        .option("-o", "REPORT", ArgType::Str, None, "also write the report to REPORT as JSON");
+    reg.register("convert_tagged_union", spec, |args| {
+        let mut variants = Vec::new();
+        for arg in args.list("VALUE=FIELD") {
+            let i = arg.find('=')
+                .ok_or_else(|| format!("expected VALUE=FIELD, but got `{}`", arg))?;
+            variants.push((arg[..i].to_owned(), Symbol::intern(&arg[i + 1..])));
+        }
+        Ok(mk(ConvertTaggedUnion {
+            struct_path: args.str("STRUCT").to_owned(),
+            tag: Symbol::intern(args.str("TAG")),
+            payload: Symbol::intern(args.str("PAYLOAD")),
+            variants,
+            report_path: args.opt_str("-o").map(|s| s.to_owned()),
+        }))
+    });
 }
@@ -1,28 +1,35 @@
 use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::mem;
 use regex::Regex;
-use rustc::hir::HirId;
+use rustc::hir::def::{CtorOf, DefKind, Namespace, Res};
+use rustc::hir::def_id::{DefId, LOCAL_CRATE};
+use rustc::hir::{HirId, CRATE_HIR_ID};
+use rustc::ty;
 use rustc_parse::parser::FollowedByType;
 use syntax::ast::*;
-use syntax::source_map::DUMMY_SP;
+use syntax::source_map::{Span, DUMMY_SP};
 use syntax::mut_visit::{self, MutVisitor};
+use syntax::print::pprust;
 use syntax::ptr::P;
-use syntax::symbol::Symbol;
+use syntax::symbol::{kw, Symbol};
+use syntax::visit::{self, Visitor};
 use smallvec::{smallvec, SmallVec};
 
 use c2rust_ast_builder::{mk, Make, IntoSymbol};
-use crate::ast_manip::{FlatMapNodes, MutVisit, AstEquiv};
+use crate::ast_manip::{visit_nodes, FlatMapNodes, MutVisit, AstEquiv};
+use crate::ast_manip::util::{is_c2rust_attr, is_export_attr, is_relative_path, namespace};
 use crate::command::{CommandState, Registry};
+use crate::command_args::{ArgType, CommandSpec};
 use crate::driver::{self, Phase};
-use crate::path_edit::fold_resolved_paths;
+use crate::path_edit::{fold_resolved_paths, fold_resolved_paths_with_id};
 use crate::transform::Transform;
 use crate::RefactorCtxt;
 
 
 /// # `rename_items_regex` Command
 ///
-/// Usage: `rename_items_regex PAT REPL [FILTER]`
-///
 /// Marks: reads `FILTER`
 ///
 /// Replace `PAT` (a regular expression) with `REPL` in all item names.  If `FILTER` is provided,
@@ -79,8 +86,6 @@ impl Transform for RenameRegex {
 
 /// # `rename_unnamed` Command
 ///
-/// Usage: `rename_unnamed`
-///
 /// Renames all `Ident`s that have `unnamed` throughout the `Crate`, so the `Crate` can
 /// have a completely unique naming scheme for Anonymous Types.
 /// This command should be ran after transpiling using `c2rust-transpile`, and
@@ -174,8 +179,6 @@ impl Transform for RenameUnnamed {
 
 /// # `replace_items` Command
 ///
-/// Usage: `replace_items`
-///
 /// Marks: `target`, `repl`
 ///
 /// Replace all uses of items marked `target` with reference to the item marked
@@ -265,8 +268,6 @@ impl Transform for ReplaceItems {
 
 /// # `set_visibility` Command
 ///
-/// Usage: `set_visibility VIS`
-///
 /// Marks: `target`
 ///
 /// Set the visibility of all items marked `target` to `VIS`.  `VIS` is a Rust
@@ -337,8 +338,6 @@ impl Transform for SetVisibility {
 
 /// # `set_mutability` Command
 ///
-/// Usage: `set_mutability MUT`
-///
 /// Marks: `target`
 ///
 /// Set the mutability of all items marked `target` to `MUT`.  `MUT` is either
@@ -450,8 +449,6 @@ impl Transform for SetUnsafety {
 
 /// # `create_item` Command
 ///
-/// Usage: `create_item ITEMS <inside/after> [MARK]`
-///
 /// Marks: `MARK`/`target`
 ///
 /// Parse `ITEMS` as item definitions, and insert the parsed items either `inside` (as the first
@@ -574,8 +571,6 @@ impl Transform for CreateItem {
 
 /// # `delete_items` Command
 ///
-/// Usage: `delete_items`
-///
 /// Marks: `target`
 ///
 /// Delete all items marked `target` from the AST.  This handles items in both `mod`s and blocks,
@@ -611,38 +606,1035 @@ impl Transform for DeleteItems {
 }
 
 
-pub fn register_commands(reg: &mut Registry) {
-    use super::mk;
+/// # `remove_unused_items` Command
+///
+/// Delete the functions, statics, type aliases, and structs that nothing in the crate uses,
+/// along with the `impl`s for the deleted types.  Items used only by other deleted items are
+/// deleted too, so removing a function also removes the helpers that only it called.
+///
+/// Items that are `#[no_mangle]` or have an `#[export_name]`, declarations in `extern`
+/// blocks, and the crate's `main` function are always kept.  `FILE` can list more items to
+/// keep, one per line, by name or by absolute path (like `crate::foo::bar`).  Empty lines and
+/// lines starting with `#` are ignored.
+///
+/// Each deleted item is listed on stderr.  With `--dry-run`, nothing is deleted; the items are
+/// marked `unused` and listed instead.  If `-o REPORT` is passed, the locations of the items
+/// are also written to the `REPORT` file as JSON.
+pub struct RemoveUnusedItems {
+    keep_path: Option<String>,
+    dry_run: bool,
+    report_path: Option<String>,
+}
+
+impl Transform for RemoveUnusedItems {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+        let keep = self.keep_path.as_ref().map_or_else(HashSet::new, |path| {
+            let text = fs::read_to_string(path)
+                .unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+            text.lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| line.to_owned())
+                .collect::<HashSet<_>>()
+        });
+        let entry_fn = tcx.entry_fn(LOCAL_CRATE).map(|(def_id, _)| def_id);
+
+        // (1) Find the items that could be removed.
+        let mut candidates = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            let kind = match i.kind {
+                ItemKind::Fn(..) => "fn",
+                ItemKind::Static(..) => "static",
+                ItemKind::TyAlias(..) => "type alias",
+                ItemKind::Struct(..) => "struct",
+                _ => return,
+            };
+            if i.attrs.iter().any(is_export_attr) {
+                return;
+            }
+            let def_id = cx.node_def_id(i.id);
+            if Some(def_id) == entry_fn {
+                return;
+            }
+            let path = pprust::path_to_string(&cx.def_path(def_id));
+            if keep.contains(&*i.ident.as_str()) || keep.contains(&path) {
+                return;
+            }
+            candidates.insert(def_id, (kind, path));
+        });
+
+        // (2) Find which item owns each path: the candidate it's inside of, including the
+        // `impl`s for candidate types, or `None` for everything else.
+        struct OwnerVisitor<'a, 'tcx: 'a> {
+            cx: &'a RefactorCtxt<'a, 'tcx>,
+            candidates: &'a HashMap<DefId, (&'static str, String)>,
+            cur: Option<DefId>,
+            owners: HashMap<NodeId, Option<DefId>>,
+            impls: HashMap<DefId, DefId>,
+        }
+
+        impl<'a, 'tcx, 'ast> Visitor<'ast> for OwnerVisitor<'a, 'tcx> {
+            fn visit_item(&mut self, i: &'ast Item) {
+                let def_id = self.cx.node_def_id(i.id);
+                let owner = match i.kind {
+                    ItemKind::Impl(_, _, _, _, _, ref self_ty, _) => {
+                        self.cx.try_resolve_ty(self_ty)
+                            .filter(|did| self.candidates.contains_key(did))
+                    }
+                    _ if self.candidates.contains_key(&def_id) => Some(def_id),
+                    _ => None,
+                };
+                let old = self.cur;
+                if let Some(owner) = owner {
+                    self.cur = Some(owner);
+                    if def_id != owner {
+                        self.impls.insert(def_id, owner);
+                    }
+                }
+                self.owners.insert(i.id, self.cur);
+                visit::walk_item(self, i);
+                self.cur = old;
+            }
+
+            fn visit_expr(&mut self, e: &'ast Expr) {
+                self.owners.insert(e.id, self.cur);
+                visit::walk_expr(self, e);
+            }
+
+            fn visit_ty(&mut self, t: &'ast Ty) {
+                self.owners.insert(t.id, self.cur);
+                visit::walk_ty(self, t);
+            }
+
+            fn visit_pat(&mut self, p: &'ast Pat) {
+                self.owners.insert(p.id, self.cur);
+                visit::walk_pat(self, p);
+            }
+
+            fn visit_mac(&mut self, mac: &'ast Mac) {
+                visit::walk_mac(self, mac);
+            }
+        }
+
+        let mut v = OwnerVisitor {
+            cx,
+            candidates: &candidates,
+            cur: None,
+            owners: HashMap::new(),
+            impls: HashMap::new(),
+        };
+        visit::walk_crate(&mut v, krate);
+        let OwnerVisitor { owners, impls, .. } = v;
+
+        // Uses of constructors and associated items count as uses of the type.
+        let used_def = |mut def_id: DefId| {
+            loop {
+                match tcx.def_kind(def_id) {
+                    Some(DefKind::Ctor(..)) | Some(DefKind::Method) |
+                    Some(DefKind::AssocConst) | Some(DefKind::AssocTy) => {
+                        def_id = match_or!([tcx.parent(def_id)] Some(x) => x; return None);
+                    }
+                    Some(DefKind::Impl) => return impls.get(&def_id).cloned(),
+                    _ => break,
+                }
+            }
+            if candidates.contains_key(&def_id) { Some(def_id) } else { None }
+        };
+
+        // (3) Collect the uses in each item, and find all items reachable from the rest of the
+        // crate.
+        let mut uses: HashMap<Option<DefId>, HashSet<DefId>> = HashMap::new();
+        fold_resolved_paths_with_id(&mut krate.clone(), cx, |id, qself, path, defs| {
+            let owner = owners.get(&id).cloned().unwrap_or(None);
+            for res in defs {
+                if let Some(def_id) = res.opt_def_id().and_then(|did| used_def(did)) {
+                    if Some(def_id) != owner {
+                        uses.entry(owner).or_insert_with(HashSet::new).insert(def_id);
+                    }
+                }
+            }
+            (qself, path)
+        });
+
+        let mut live = HashSet::new();
+        let mut queue = uses.get(&None).into_iter().flatten().cloned().collect::<Vec<_>>();
+        while let Some(def_id) = queue.pop() {
+            if live.insert(def_id) {
+                queue.extend(uses.get(&Some(def_id)).into_iter().flatten().cloned());
+            }
+        }
+
+        // (4) Report and remove the rest.
+        st.start_report("remove_unused_items", self.report_path.clone());
+        let mut dead = Vec::new();
+        visit_nodes(krate, |i: &Item| {
+            let def_id = cx.node_def_id(i.id);
+            if let Some(&(kind, ref path)) = candidates.get(&def_id) {
+                if !live.contains(&def_id) {
+                    dead.push((i.span, kind, path.clone()));
+                    st.report(cx, kind, i.span);
+                    if self.dry_run {
+                        st.add_mark(i.id, "unused");
+                    }
+                }
+            }
+        });
+        dead.sort();
+        let verb = if self.dry_run { "unused" } else { "removing" };
+        for (span, kind, path) in dead {
+            eprintln!("{} {} `{}` ({})", verb, kind, path,
+                      cx.session().source_map().span_to_string(span));
+        }
+        if self.dry_run {
+            return;
+        }
+
+        let is_dead = |i: &Item| {
+            let def_id = cx.node_def_id(i.id);
+            let owner = impls.get(&def_id).cloned().unwrap_or(def_id);
+            candidates.contains_key(&owner) && !live.contains(&owner)
+        };
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            if is_dead(&i) {
+                smallvec![]
+            } else {
+                smallvec![i]
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `add_attr` Command
+///
+/// Marks: `MARK`/`target`
+///
+/// Add the outer attribute `ATTR`, such as `#[inline]` or `#[cfg(feature = "x")]`, to every
+/// item, impl item, trait item, and foreign item marked `MARK` (default: `target`).  The new
+/// attribute goes after the item's existing attributes.
+pub struct AddAttr {
+    attr: String,
+    mark: Symbol,
+}
+
+impl Transform for AddAttr {
+    fn transform(&self, _krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let attr = driver::parse_attr(cx.session(), &self.attr);
+        let ids = st.marks().iter()
+            .filter(|&&(_, label)| label == self.mark)
+            .map(|&(id, _)| id)
+            .collect::<Vec<_>>();
+        for id in ids {
+            st.add_attr(id, attr.clone());
+        }
+    }
+}
+
+
+/// # `remove_attr` Command
+///
+/// Marks: `MARK`/`target`
+///
+/// Remove all attributes named `NAME`, such as `no_mangle`, from every item, impl item, trait
+/// item, and foreign item marked `MARK` (default: `target`).  Other attributes, including doc
+/// comments, are left alone.
+pub struct RemoveAttr {
+    name: Symbol,
+    mark: Symbol,
+}
+
+impl Transform for RemoveAttr {
+    fn transform(&self, _krate: &mut Crate, st: &CommandState, _cx: &RefactorCtxt) {
+        let ids = st.marks().iter()
+            .filter(|&&(_, label)| label == self.mark)
+            .map(|&(id, _)| id)
+            .collect::<Vec<_>>();
+        for id in ids {
+            st.remove_attr(id, self.name);
+        }
+    }
+}
+
+
+/// # `split_module_by_src` Command
+///
+/// Marks: `target`
+///
+/// Split each `mod` marked `target` into one submodule per original C source file.  An item's
+/// file is taken from `MAPPING`, a JSON file containing an object that maps item names to file
+/// names, or else from a `#[c2rust::src_loc = "FILE:LINE:COL"]` attribute on the item.  Items
+/// with no known file, including `use` declarations and `extern` blocks, stay where they are.
+///
+/// Each new module is named after its file (`foo/bar.h` becomes `bar_h`), takes the place of
+/// the first item moved into it, and keeps its items in their original order.  It starts with
+/// `use super::*;` so that moved code can still name everything left behind in the parent.
+/// Paths to moved items are rewritten crate-wide to absolute `crate::` paths.  Private items,
+/// fields, and inherent methods that are used outside of their new module become `pub(super)`,
+/// which gives them the same reach they had before the move.
+pub struct SplitModuleBySrc {
+    mapping: Option<String>,
+}
+
+/// A submodule created by `split_module_by_src`.
+struct SrcModule {
+    /// The `NodeId` of the module being split.
+    parent: NodeId,
+    ident: Ident,
+    /// Absolute path to the new module.
+    path: Path,
+}
+
+impl Transform for SplitModuleBySrc {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+        let hir_map = cx.hir_map();
+        let mapping = self.mapping.as_ref()
+            .map(|path| read_src_mapping(path))
+            .unwrap_or_default();
+
+        // Assign the items of each `target` module to new modules, and note which definitions
+        // could be made inaccessible by the move.
+        let mut new_mods: Vec<SrcModule> = Vec::new();
+        let mut moved: HashMap<NodeId, usize> = HashMap::new();
+        let mut moved_defs: HashMap<DefId, usize> = HashMap::new();
+        let mut private_defs: HashMap<DefId, (NodeId, usize)> = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") {
+                return;
+            }
+            let m = match_or!([&i.kind] ItemKind::Mod(m) => m; return);
+            let mod_path = cx.def_path(cx.node_def_id(i.id));
+            let mut taken = m.items.iter().map(|i| i.ident.name).collect::<HashSet<_>>();
+            let mut file_mods: HashMap<String, usize> = HashMap::new();
+
+            for item in &m.items {
+                let file = match_or!([item_src_file(item, &mapping)] Some(x) => x; continue);
+                let idx = match file_mods.get(&file) {
+                    Some(&idx) => idx,
+                    None => {
+                        let ident = src_mod_ident(&file, &mut taken);
+                        let mut path = mod_path.clone();
+                        path.segments.push(mk().path_segment(ident));
+                        new_mods.push(SrcModule { parent: i.id, ident, path });
+                        file_mods.insert(file, new_mods.len() - 1);
+                        new_mods.len() - 1
+                    }
+                };
+                moved.insert(item.id, idx);
+
+                let def_id = cx.node_def_id(item.id);
+                if is_private(&item.vis) {
+                    private_defs.insert(def_id, (item.id, idx));
+                }
+                match &item.kind {
+                    ItemKind::Struct(vd, _) | ItemKind::Union(vd, _) => {
+                        for f in vd.fields() {
+                            if is_private(&f.vis) {
+                                private_defs.insert(cx.node_def_id(f.id), (f.id, idx));
+                            }
+                        }
+                    }
+                    ItemKind::Impl(_, _, _, _, None, _, items) => {
+                        for ii in items {
+                            if is_private(&ii.vis) {
+                                private_defs.insert(cx.node_def_id(ii.id), (ii.id, idx));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                // Impls can't be named, so paths never need to be rewritten for them.
+                if !matches!([item.kind] ItemKind::Impl(..)) {
+                    moved_defs.insert(def_id, idx);
+                }
+            }
+        });
+
+        if new_mods.is_empty() {
+            return;
+        }
+
+        // Find the new module (if any) that will contain a node.
+        let location = |id: NodeId| -> Option<usize> {
+            let mut hir_id = hir_map.opt_node_to_hir_id(id)?;
+            loop {
+                if let Some(&idx) = moved.get(&hir_map.hir_to_node_id(hir_id)) {
+                    return Some(idx);
+                }
+                if hir_id == CRATE_HIR_ID {
+                    return None;
+                }
+                hir_id = hir_map.get_parent_item(hir_id);
+            }
+        };
+
+        // Find the moved item that `def_id` is nested in, if it's one that paths can go
+        // through (such as an enum variant), along with the number of extra path segments.
+        let moved_ancestor = |def_id: DefId| -> Option<(DefId, usize)> {
+            let mut cur = def_id;
+            let mut depth = 0;
+            loop {
+                if moved_defs.contains_key(&cur) {
+                    return Some((cur, depth));
+                }
+                let parent = tcx.parent(cur)?;
+                match tcx.def_kind(cur)? {
+                    DefKind::Ctor(..) => {}
+                    DefKind::Variant => depth += 1,
+                    DefKind::Method | DefKind::AssocConst | DefKind::AssocTy
+                        if matches!([tcx.def_kind(parent)] Some(DefKind::Trait)) => depth += 1,
+                    _ => return None,
+                }
+                cur = parent;
+            }
+        };
+
+        // Uses of definitions, paired with the node where each use happens.
+        let mut uses: Vec<(DefId, NodeId)> = Vec::new();
+
+        fold_resolved_paths_with_id(krate, cx, |id, qself, path, defs| {
+            let res = match_or!([defs.get(0)] Some(&res) => res; return (qself, path));
+            let def_id = match_or!([res.opt_def_id()] Some(x) => x; return (qself, path));
+            uses.push((def_id, id));
+            if let Res::Def(DefKind::Ctor(CtorOf::Struct, _), ctor_id) = res {
+                // Tuple struct constructors are only usable if every field is visible.
+                let adt = tcx.adt_def(tcx.parent(ctor_id).unwrap());
+                uses.extend(adt.non_enum_variant().fields.iter().map(|f| (f.did, id)));
+            }
+
+            if let Some((moved_id, depth)) = moved_ancestor(def_id) {
+                if path.segments.len() <= depth {
+                    return (qself, path);
+                }
+                let split = path.segments.len() - depth - 1;
+                let mut seg = path.segments[split].clone();
+                seg.ident.name = tcx.item_name(moved_id);
+
+                let mut new_path = new_mods[moved_defs[&moved_id]].path.clone();
+                new_path.segments.push(seg);
+                new_path.segments.extend(path.segments[split + 1..].iter().cloned());
+                let qself = qself.map(|mut qself| {
+                    qself.position = new_path.segments.len() - depth;
+                    qself
+                });
+                return (qself, new_path);
+            }
+
+            // Relative paths in moved items would now start from the wrong module.
+            if is_relative_path(&path) && location(id).is_some() {
+                return cx.def_qpath(def_id);
+            }
+            (qself, path)
+        });
+
+        visit_nodes(krate, |e: &Expr| {
+            match &e.kind {
+                ExprKind::Field(base, ident) => {
+                    let ty = cx.opt_adjusted_node_type(base.id);
+                    if let Some(adt) = ty.and_then(|ty| struct_def(ty)) {
+                        uses.extend(adt.non_enum_variant().fields.iter()
+                            .filter(|f| f.ident.name == ident.name)
+                            .map(|f| (f.did, e.id)));
+                    }
+                }
+                ExprKind::Struct(_, fields, base) => {
+                    if let Some(adt) = cx.opt_node_type(e.id).and_then(|ty| struct_def(ty)) {
+                        let names = fields.iter().map(|f| f.ident.name).collect::<HashSet<_>>();
+                        uses.extend(adt.non_enum_variant().fields.iter()
+                            .filter(|f| base.is_some() || names.contains(&f.ident.name))
+                            .map(|f| (f.did, e.id)));
+                    }
+                }
+                ExprKind::MethodCall(..) | ExprKind::Call(..) => {
+                    if let Some(def_id) = cx.opt_callee(e) {
+                        uses.push((def_id, e.id));
+                    }
+                }
+                _ => {}
+            }
+        });
+
+        visit_nodes(krate, |p: &Pat| {
+            if let PatKind::Struct(_, fields, _) = &p.kind {
+                if let Some(adt) = cx.opt_node_type(p.id).and_then(|ty| struct_def(ty)) {
+                    let names = fields.iter().map(|f| f.ident.name).collect::<HashSet<_>>();
+                    uses.extend(adt.non_enum_variant().fields.iter()
+                        .filter(|f| names.contains(&f.ident.name))
+                        .map(|f| (f.did, p.id)));
+                }
+            }
+        });
+
+        let mut widen = HashSet::new();
+        for (def_id, id) in uses {
+            if let Some(&(def_node, idx)) = private_defs.get(&def_id) {
+                if location(id) != Some(idx) {
+                    widen.insert(def_node);
+                }
+            }
+        }
+
+        struct SplitFolder<'a> {
+            moved: &'a HashMap<NodeId, usize>,
+            new_mods: &'a [SrcModule],
+            widen: &'a HashSet<NodeId>,
+        }
+
+        impl<'a> SplitFolder<'a> {
+            fn fix_vis(&self, id: NodeId, vis: &mut Visibility) {
+                if self.widen.contains(&id) {
+                    *vis = mk().vis("pub(super)");
+                }
+            }
+
+            fn split_mod(&self, parent: NodeId, items: Vec<P<Item>>) -> Vec<P<Item>> {
+                // Leave a placeholder where each new module's first item was.
+                let mut slots: Vec<Result<P<Item>, usize>> = Vec::new();
+                let mut groups: HashMap<usize, Vec<P<Item>>> = HashMap::new();
+                for item in items {
+                    match self.moved.get(&item.id) {
+                        Some(&idx) => {
+                            if !groups.contains_key(&idx) {
+                                slots.push(Err(idx));
+                            }
+                            groups.entry(idx).or_insert_with(Vec::new).push(item);
+                        }
+                        None => slots.push(Ok(item)),
+                    }
+                }
+
+                slots.into_iter().map(|slot| {
+                    let idx = match slot {
+                        Ok(item) => return item,
+                        Err(idx) => idx,
+                    };
+                    let new_mod = &self.new_mods[idx];
+                    assert!(new_mod.parent == parent);
+                    let items = groups.remove(&idx).unwrap();
+                    let vis = match items.iter().map(|i| vis_reach(&i.vis.node)).max() {
+                        Some(3) => "pub",
+                        Some(2) => "pub(crate)",
+                        Some(1) => "pub(super)",
+                        _ => "",
+                    };
+                    let mut mod_items = vec![mk().use_glob_item(vec!["super"])];
+                    mod_items.extend(items);
+                    mk().vis(vis).mod_item(new_mod.ident, mk().mod_(mod_items))
+                }).collect()
+            }
+        }
+
+        impl<'a> MutVisitor for SplitFolder<'a> {
+            fn flat_map_item(&mut self, i: P<Item>) -> SmallVec<[P<Item>; 1]> {
+                let i = i.map(|mut i| {
+                    if self.moved.contains_key(&i.id) {
+                        i.vis = reanchor_vis(i.vis);
+                    }
+                    self.fix_vis(i.id, &mut i.vis);
+                    i
+                });
+
+                mut_visit::noop_flat_map_item(i, self).into_iter().map(|i| {
+                    if !self.new_mods.iter().any(|m| m.parent == i.id) {
+                        return i;
+                    }
+                    i.map(|mut i| {
+                        let id = i.id;
+                        if let ItemKind::Mod(m) = &mut i.kind {
+                            let items = mem::replace(&mut m.items, Vec::new());
+                            m.items = self.split_mod(id, items);
+                        }
+                        i
+                    })
+                }).collect()
+            }
+
+            fn flat_map_struct_field(&mut self, mut f: StructField) -> SmallVec<[StructField; 1]> {
+                self.fix_vis(f.id, &mut f.vis);
+                mut_visit::noop_flat_map_struct_field(f, self)
+            }
+
+            fn flat_map_impl_item(&mut self, mut i: ImplItem) -> SmallVec<[ImplItem; 1]> {
+                self.fix_vis(i.id, &mut i.vis);
+                mut_visit::noop_flat_map_impl_item(i, self)
+            }
+        }
 
-    reg.register("rename_items_regex", |args| mk(RenameRegex {
-        pattern: args[0].clone(),
-        repl: args[1].clone(),
-        filter: args.get(2).map(|x| (x as &str).into_symbol()),
-    }));
+        krate.visit(&mut SplitFolder {
+            moved: &moved,
+            new_mods: &new_mods,
+            widen: &widen,
+        })
+    }
 
-    reg.register("rename_unnamed", |_args| mk(RenameUnnamed));
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
 
-    reg.register("replace_items", |_args| mk(ReplaceItems));
+/// Read an item name to source file mapping from a JSON file.
+fn read_src_mapping(path: &str) -> HashMap<Symbol, String> {
+    let src = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+    let json = json::parse(&src)
+        .unwrap_or_else(|e| panic!("failed to parse {}: {}", path, e));
+    json.entries().map(|(name, file)| {
+        let file = file.as_str()
+            .unwrap_or_else(|| panic!("expected a file name for `{}` in {}", name, path));
+        (name.into_symbol(), file.to_owned())
+    }).collect()
+}
 
-    reg.register("set_visibility", |args| mk(SetVisibility {
-        vis_str: args[0].clone(),
-    }));
+/// Get the original source file of a movable item.
+fn item_src_file(item: &Item, mapping: &HashMap<Symbol, String>) -> Option<String> {
+    match item.kind {
+        ItemKind::Use(..) | ItemKind::ExternCrate(..) | ItemKind::ForeignMod(..) |
+        ItemKind::Mac(..) | ItemKind::MacroDef(..) => return None,
+        _ => {}
+    }
+    if let Some(file) = mapping.get(&item.ident.name) {
+        return Some(file.clone());
+    }
 
-    reg.register("set_mutability", |args| mk(SetMutability {
-        mut_str: args[0].clone(),
-    }));
+    // The transpiler's own `LINE:COL` locations don't say which file they're in.
+    let attr = item.attrs.iter().find(|attr| is_c2rust_attr(attr, "src_loc"))?;
+    let loc = attr.value_str()?.as_str();
+    let mut parts = loc.rsplitn(3, ':');
+    let _col = parts.next()?;
+    let _line = parts.next()?;
+    parts.next().map(|file| file.to_owned())
+}
 
-    reg.register("set_unsafety", |args| mk(SetUnsafety {
-        unsafe_str: args[0].clone(),
-    }));
+/// Pick a module name for a source file that doesn't clash with anything in `taken`.
+fn src_mod_ident(file: &str, taken: &mut HashSet<Symbol>) -> Ident {
+    let base = std::path::Path::new(file).file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(file);
+    let mut name = base.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if name.chars().next().map_or(true, |c| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
 
-    reg.register("create_item", |args| mk(CreateItem {
-        header: args[0].clone(),
-        pos: args[1].clone(),
-        mark: args.get(2).map(|s| (s as &str).into_symbol())
-            .unwrap_or_else(|| "target".into_symbol()),
-    }));
+    let mut unique = name.clone();
+    let mut counter = 1;
+    while !taken.insert(Symbol::intern(&unique)) {
+        unique = format!("{}_{}", name, counter);
+        counter += 1;
+    }
+    Ident::from_str(&unique)
+}
+
+fn is_private(vis: &Visibility) -> bool {
+    match &vis.node {
+        VisibilityKind::Inherited => true,
+        VisibilityKind::Restricted { path, .. } =>
+            path.segments.len() == 1 && path.segments[0].ident.name == kw::SelfLower,
+        _ => false,
+    }
+}
+
+/// Adjust a visibility for an item that moves one module further down.  `pub(super)` becomes
+/// `pub(in super::super)`, and so on.
+fn reanchor_vis(mut vis: Visibility) -> Visibility {
+    if let VisibilityKind::Restricted { path, .. } = &mut vis.node {
+        if path.segments[0].ident.name == kw::Super {
+            path.segments.insert(0, mk().path_segment(kw::Super));
+        }
+    }
+    vis
+}
 
-    reg.register("delete_items", |_args| mk(DeleteItems));
+/// How far a moved item can be seen from outside of its new module: 0 for nowhere outside it
+/// but the parent, 1 for the grandparent, 2 for the whole crate, and 3 for everywhere.
+fn vis_reach(vis: &VisibilityKind) -> u8 {
+    match vis {
+        VisibilityKind::Public => 3,
+        VisibilityKind::Crate(_) => 2,
+        VisibilityKind::Restricted { path, .. } => {
+            let segs = &path.segments;
+            if segs.iter().all(|s| s.ident.name == kw::Super) {
+                match segs.len() {
+                    0 | 1 => 0,
+                    2 => 1,
+                    _ => 2,
+                }
+            } else if segs.len() == 1 && segs[0].ident.name == kw::SelfLower {
+                0
+            } else {
+                2
+            }
+        }
+        VisibilityKind::Inherited => 0,
+    }
+}
+
+/// Get the definition of a struct or union type, looking through references.
+fn struct_def<'tcx>(ty: ty::Ty<'tcx>) -> Option<&'tcx ty::AdtDef> {
+    match ty.kind {
+        ty::TyKind::Adt(adt, _) if adt.is_struct() || adt.is_union() => Some(adt),
+        ty::TyKind::Ref(_, ty, _) => struct_def(ty),
+        _ => None,
+    }
+}
+
+
+/// # `canonicalize_static_names` Command
+///
+/// Marks: `target`
+///
+/// Undo the renaming the transpiler does to keep `static` functions and variables from
+/// different C files apart, once they live in separate modules (for example, after
+/// `split_module_by_src`).  Each item directly inside a `mod` marked `target` that isn't `pub`
+/// or exported and whose name has a numeric suffix, like `helper_0`, is renamed to the name
+/// without the suffix.  If `MAPPING` is given, it's a JSON file containing an object that maps
+/// old item names to new ones, and the items in `target` modules are renamed according to it
+/// instead.
+///
+/// Paths to the renamed items are updated crate-wide, including `use` declarations that import
+/// or re-export them.  A rename is refused, with a warning, if the new name would clash with
+/// another name in a module that the item is defined or imported in, or would shadow a name
+/// that code in one of those modules refers to.
+pub struct CanonicalizeStaticNames {
+    mapping: Option<String>,
+}
+
+impl Transform for CanonicalizeStaticNames {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+        let hir_map = cx.hir_map();
+        let mapping = self.mapping.as_ref().map(|path| read_rename_mapping(path));
+        let suffix = Regex::new(r"^(.+)_[0-9]+$").unwrap();
+
+        // (1) Pick new names for the items in the `target` modules.
+
+        let mut renames: HashMap<DefId, (Symbol, Symbol, Span)> = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") {
+                return;
+            }
+            let m = match_or!([&i.kind] ItemKind::Mod(m) => m; return);
+            for item in &m.items {
+                if matches!([item.kind] ItemKind::Use(..), ItemKind::Impl(..),
+                            ItemKind::ForeignMod(..), ItemKind::Mac(..)) {
+                    continue;
+                }
+                let old = item.ident.name;
+                let new = match mapping {
+                    Some(ref mapping) => match_or!([mapping.get(&old)] Some(&x) => x; continue),
+                    None => {
+                        if matches!([item.vis.node] VisibilityKind::Public) ||
+                           item.attrs.iter().any(is_export_attr) {
+                            continue;
+                        }
+                        let name = old.as_str();
+                        match_or!([suffix.captures(&name)] Some(c) => Symbol::intern(&c[1]);
+                                  continue)
+                    }
+                };
+                if new != old {
+                    renames.insert(cx.node_def_id(item.id), (old, new, item.span));
+                }
+            }
+        });
+        if renames.is_empty() {
+            return;
+        }
+
+        // The renamed definition that a path resolves to, if any.  Tuple struct constructors are
+        // renamed along with their structs.
+        let renamed_def = |renames: &HashMap<DefId, _>, res: &Res| -> Option<DefId> {
+            let did = match *res {
+                Res::Def(DefKind::Ctor(CtorOf::Struct, _), did) => tcx.parent(did)?,
+                Res::Def(_, did) => did,
+                _ => return None,
+            };
+            Some(did).filter(|did| renames.contains_key(did))
+        };
+
+        // (2) Collect the names bound in each module, and the single-segment paths used in each.
+        // This is done on a copy of the crate with its `use` declarations split up, so each
+        // imported name can be seen on its own.
+
+        let mut split_krate = krate.clone();
+        let mut path_res: HashMap<NodeId, Vec<Res>> = HashMap::new();
+        let mut names_used: Vec<(NodeId, Symbol, Res)> = Vec::new();
+        fold_resolved_paths_with_id(&mut split_krate, cx, |id, qself, path, defs| {
+            path_res.insert(id, defs.to_vec());
+            if qself.is_none() && path.segments.len() == 1 {
+                let module = hir_map.opt_node_to_hir_id(id)
+                    .map(|hir_id| hir_map.get_module_parent_node(hir_id));
+                if let Some(module) = module {
+                    let name = path.segments[0].ident.name;
+                    names_used.extend(defs.iter().map(|&res| {
+                        (hir_map.hir_to_node_id(module), name, res)
+                    }));
+                }
+            }
+            (qself, path)
+        });
+
+        let mut bindings: Vec<(NodeId, Symbol, Namespace, Option<DefId>)> = Vec::new();
+        let mut bind_items = |module: NodeId, items: &[P<Item>]| {
+            for item in items {
+                match item.kind {
+                    ItemKind::Use(ref tree) => {
+                        let rename = match_or!([tree.kind] UseTreeKind::Simple(rename, ..) =>
+                                               rename; continue);
+                        let name = rename.unwrap_or_else(|| tree.prefix.segments.last()
+                                                         .unwrap().ident).name;
+                        for res in path_res.get(&item.id).into_iter().flatten() {
+                            if let Some(ns) = namespace(res) {
+                                let did = renamed_def(&renames, res).or(res.opt_def_id());
+                                bindings.push((module, name, ns, did));
+                            }
+                        }
+                    }
+                    ItemKind::ForeignMod(ref fm) => {
+                        for fi in &fm.items {
+                            let did = cx.node_def_id(fi.id);
+                            if let Some(ns) = def_namespace(tcx, did) {
+                                bindings.push((module, fi.ident.name, ns, Some(did)));
+                            }
+                        }
+                    }
+                    ItemKind::Impl(..) | ItemKind::Mac(..) => {}
+                    _ => {
+                        let did = cx.node_def_id(item.id);
+                        if let Some(ns) = def_namespace(tcx, did) {
+                            bindings.push((module, item.ident.name, ns, Some(did)));
+                        }
+                    }
+                }
+            }
+        };
+        bind_items(CRATE_NODE_ID, &split_krate.module.items);
+        visit_nodes(&split_krate, |i: &Item| {
+            if let ItemKind::Mod(ref m) = i.kind {
+                bind_items(i.id, &m.items);
+            }
+        });
+
+        // (3) Refuse the renames that would clash with another name bound in the same module, or
+        // shadow a name used there.
+
+        let new_binding = |name: Symbol, did: Option<DefId>| -> Option<(DefId, Symbol)> {
+            let did = did?;
+            match renames.get(&did) {
+                Some(&(old, new, _)) if old == name => Some((did, new)),
+                _ => None,
+            }
+        };
+        let mut bound: HashMap<(NodeId, Symbol, Namespace), HashSet<Option<DefId>>> =
+            HashMap::new();
+        let mut renamed_bindings: HashMap<(NodeId, Symbol, Namespace), DefId> = HashMap::new();
+        for &(module, name, ns, did) in &bindings {
+            let final_name = match new_binding(name, did) {
+                Some((did, new)) => {
+                    renamed_bindings.insert((module, new, ns), did);
+                    new
+                }
+                None => name,
+            };
+            bound.entry((module, final_name, ns)).or_insert_with(HashSet::new).insert(did);
+        }
+
+        let mut clashes: HashMap<DefId, NodeId> = HashMap::new();
+        for (&(module, new, ns), &did) in &renamed_bindings {
+            if bound[&(module, new, ns)].len() > 1 {
+                clashes.entry(did).or_insert(module);
+            }
+        }
+        for &(module, name, ref res) in &names_used {
+            let ns = match_or!([namespace(res)] Some(x) => x; continue);
+            let did = match_or!([renamed_bindings.get(&(module, name, ns))] Some(&x) => x;
+                                continue);
+            if renamed_def(&renames, res) != Some(did) {
+                clashes.entry(did).or_insert(module);
+            }
+        }
+
+        let mut clashes = clashes.into_iter().collect::<Vec<_>>();
+        clashes.sort_by_key(|&(did, _)| renames[&did].2);
+        for (did, module) in clashes {
+            let (old, new, span) = renames.remove(&did).unwrap();
+            let mod_path = match tcx.def_path_str(cx.node_def_id(module)) {
+                ref s if s.is_empty() => "crate".to_owned(),
+                s => s,
+            };
+            cx.session().span_warn(
+                span,
+                &format!("not renaming `{}` to `{}`: it would clash with another name in `{}`",
+                         old, new, mod_path),
+            );
+        }
+
+        // (4) Rename the items, and the paths that refer to them.
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let did = match_or!([hir_map.opt_local_def_id_from_node_id(i.id)] Some(x) => x;
+                                return smallvec![i]);
+            let new = match_or!([renames.get(&did)] Some(&(_, new, _)) => new;
+                                return smallvec![i]);
+            smallvec![i.map(|i| Item {
+                ident: Ident::new(new, i.ident.span),
+                .. i
+            })]
+        });
+
+        fold_resolved_paths(krate, cx, |qself, mut path, defs| {
+            let did = defs.iter().filter_map(|res| renamed_def(&renames, res)).next();
+            if let Some(&(old, new, _)) = did.and_then(|did| renames.get(&did)) {
+                let seg = path.segments.last_mut().unwrap();
+                // Imports like `use foo::helper_0 as h;` rename the item already, so uses of `h`
+                // stay as they are.
+                if seg.ident.name == old {
+                    seg.ident.name = new;
+                }
+            }
+            (qself, path)
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Read an old item name to new item name mapping from a JSON file.
+fn read_rename_mapping(path: &str) -> HashMap<Symbol, Symbol> {
+    let src = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+    let json = json::parse(&src)
+        .unwrap_or_else(|e| panic!("failed to parse {}: {}", path, e));
+    json.entries().map(|(old, new)| {
+        let new = new.as_str()
+            .unwrap_or_else(|| panic!("expected a new name for `{}` in {}", old, path));
+        (old.into_symbol(), new.into_symbol())
+    }).collect()
+}
+
+/// The namespace that a local definition's name is bound in.
+fn def_namespace(tcx: ty::TyCtxt, did: DefId) -> Option<Namespace> {
+    tcx.def_kind(did).and_then(|kind| namespace(&Res::Def(kind, did)))
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    let spec = CommandSpec::new("Replace a regular expression in all item names.")
+        .arg("PAT", ArgType::Str, "the regular expression to replace")
+        .arg("REPL", ArgType::Str, "the replacement, which can refer to groups as `$1`")
+        .optional_arg("FILTER", ArgType::Str, None, "only rename items bearing this mark");
+    reg.register("rename_items_regex", spec, |args| {
+        if let Err(e) = Regex::new(args.str("PAT")) {
+            return Err(format!("invalid PAT: {}", e));
+        }
+        Ok(mk(RenameRegex {
+            pattern: args.str("PAT").to_owned(),
+            repl: args.str("REPL").to_owned(),
+            filter: args.opt_str("FILTER").map(|x| x.into_symbol()),
+        }))
+    });
+
+    let spec = CommandSpec::new("Give each type named `unnamed` a unique name.");
+    reg.register("rename_unnamed", spec, |_args| Ok(mk(RenameUnnamed)));
+
+    let spec = CommandSpec::new("Replace the uses of items marked `target` with the item `repl`.");
+    reg.register("replace_items", spec, |_args| Ok(mk(ReplaceItems)));
+
+    let spec = CommandSpec::new("Set the visibility of all items marked `target` to VIS.")
+        .arg("VIS", ArgType::Str, "a visibility qualifier such as `pub`, or the empty string");
+    reg.register("set_visibility", spec, |args| {
+        Ok(mk(SetVisibility {
+            vis_str: args.str("VIS").to_owned(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Set the mutability of all statics marked `target` to MUT.")
+        .arg("MUT", ArgType::Str, "either `imm` or `mut`");
+    reg.register("set_mutability", spec, |args| {
+        Ok(mk(SetMutability {
+            mut_str: args.str("MUT").to_owned(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Set the unsafety of all items marked `target` to UNSAFE.")
+        .arg("UNSAFE", ArgType::Str, "either `unsafe` or `safe`");
+    reg.register("set_unsafety", spec, |args| {
+        Ok(mk(SetUnsafety {
+            unsafe_str: args.str("UNSAFE").to_owned(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Insert the items ITEMS inside or after the node marked MARK.")
+        .arg("ITEMS", ArgType::Str, "the item definitions to parse")
+        .arg("POS", ArgType::Str, "either `inside` or `after`")
+        .optional_arg("MARK", ArgType::Str, Some("target"), "the mark of the node");
+    reg.register("create_item", spec, |args| {
+        Ok(mk(CreateItem {
+            header: args.str("ITEMS").to_owned(),
+            pos: args.str("POS").to_owned(),
+            mark: args.str("MARK").into_symbol(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Delete all items marked `target`.");
+    reg.register("delete_items", spec, |_args| Ok(mk(DeleteItems)));
+
+    let spec = CommandSpec::new("Delete the items that nothing in the crate uses.")
+        .option("--keep", "FILE", ArgType::Str, None, "a file listing more items to keep")
+        .flag("--dry-run", "mark the items `unused` instead of deleting them")
+        .option("-o", "REPORT", ArgType::Str, None, "also write the report to REPORT as JSON");
+    reg.register("remove_unused_items", spec, |args| {
+        Ok(mk(RemoveUnusedItems {
+            keep_path: args.opt_str("--keep").map(|s| s.to_owned()),
+            dry_run: args.flag("--dry-run"),
+            report_path: args.opt_str("-o").map(|s| s.to_owned()),
+        }))
+    });
+
+    let spec = CommandSpec::new("Add the attribute ATTR to every item marked MARK.")
+        .arg("ATTR", ArgType::Str, "the attribute, such as `#[inline]`")
+        .optional_arg("MARK", ArgType::Str, Some("target"), "the mark of the items");
+    reg.register("add_attr", spec, |args| {
+        Ok(mk(AddAttr {
+            attr: args.str("ATTR").to_owned(),
+            mark: args.str("MARK").into_symbol(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Remove the attributes named NAME from every item marked MARK.")
+        .arg("NAME", ArgType::Str, "the name of the attributes, such as `no_mangle`")
+        .optional_arg("MARK", ArgType::Str, Some("target"), "the mark of the items");
+    reg.register("remove_attr", spec, |args| {
+        Ok(mk(RemoveAttr {
+            name: args.str("NAME").into_symbol(),
+            mark: args.str("MARK").into_symbol(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Split each module marked `target` by original C source file.")
+        .optional_arg("MAPPING", ArgType::Str, None, "a JSON file mapping item names to files");
+    reg.register("split_module_by_src", spec, |args| {
+        Ok(mk(SplitModuleBySrc {
+            mapping: args.opt_str("MAPPING").map(|s| s.to_owned()),
+        }))
+    });
+
+    let spec = CommandSpec::new("Undo the renaming of `static` items in modules marked `target`.")
+        .optional_arg("MAPPING", ArgType::Str, None, "a JSON file mapping old names to new ones");
+    reg.register("canonicalize_static_names", spec, |args| {
+        Ok(mk(CanonicalizeStaticNames {
+            mapping: args.opt_str("MAPPING").map(|s| s.to_owned()),
+        }))
+    });
 }
 
@@ -10,6 +10,7 @@ use smallvec::smallvec;
 use crate::ast_manip::{FlatMapNodes, MutVisitNodes, visit_nodes};
 use crate::ast_manip::fn_edit::{visit_fns, FnKind};
 use crate::command::{CommandState, Registry};
+use crate::command_args::CommandSpec;
 use crate::driver::{Phase};
 use crate::path_edit::fold_resolved_paths;
 use crate::transform::Transform;
@@ -18,8 +19,6 @@ use crate::RefactorCtxt;
 
 /// # `link_funcs` Command
 /// 
-/// Usage: `link_funcs`
-/// 
 /// Link up function declarations and definitions with matching symbols across
 /// modules.  For every foreign `fn` whose symbol matches a `fn` definition
 /// elsewhere in the program, it replaces all uses of the foreign `fn` with a
@@ -118,8 +117,6 @@ impl Transform for LinkFuncs {
 
 /// # `link_incomplete_types` Command
 /// 
-/// Usage: `link_incomplete_types`
-/// 
 /// Link up type declarations and definitions with matching names across modules.
 /// For every foreign type whose name matches a type definition elsewhere in the
 /// program, it replaces all uses of the foreign type with the type definition, and
@@ -211,8 +208,6 @@ impl Transform for LinkIncompleteTypes {
 
 /// # `canonicalize_structs` Command
 /// 
-/// Usage: `canonicalize_structs`
-/// 
 /// Marks: `target`
 /// 
 /// For each type definition marked `target`, delete all other type definitions
@@ -337,7 +332,12 @@ impl Transform for CanonicalizeStructs {
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("link_funcs", |_args| mk(LinkFuncs));
-    reg.register("link_incomplete_types", |_args| mk(LinkIncompleteTypes));
-    reg.register("canonicalize_structs", |_args| mk(CanonicalizeStructs));
+    let spec = CommandSpec::new("Replace foreign functions with the definitions of their symbols.");
+    reg.register("link_funcs", spec, |_args| Ok(mk(LinkFuncs)));
+
+    let spec = CommandSpec::new("Replace foreign types with the definitions of the same name.");
+    reg.register("link_incomplete_types", spec, |_args| Ok(mk(LinkIncompleteTypes)));
+
+    let spec = CommandSpec::new("Replace types named like one marked `target` with that one.");
+    reg.register("canonicalize_structs", spec, |_args| Ok(mk(CanonicalizeStructs)));
 }
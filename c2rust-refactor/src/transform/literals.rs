@@ -1,22 +1,31 @@
 use arena::SyncDroplessArena;
 use ena::unify as ut;
+use regex::Regex;
 use rustc::{hir, ty};
+use rustc::ty::ParamEnv;
 use rustc::hir::def::Res;
 use rustc_data_structures::sync::Lrc;
 use syntax::ast::*;
+use syntax::mut_visit::{self, MutVisitor};
+use syntax::print::pprust;
 use syntax::token;
 use syntax::ptr::P;
-use syntax::symbol::Symbol;
+use syntax::symbol::{kw, Symbol};
 use syntax::visit::{self, Visitor};
-use syntax_pos::Span;
+use syntax_pos::{Span, DUMMY_SP};
+use smallvec::SmallVec;
 
 use std::cell::Cell;
 use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::marker::PhantomData;
 
-use crate::ast_manip::MutVisitNodes;
+use c2rust_ast_builder::{mk, IntoSymbol};
+use crate::ast_manip::{visit_nodes, MutVisit, MutVisitNodes};
 use crate::command::{CommandState, Registry};
-use crate::driver::Phase;
+use crate::command_args::{ArgType, CommandSpec};
+use crate::driver::{parse_ty, Phase};
+use crate::reflect::reflect_tcx_ty;
 use crate::transform::Transform;
 use crate::transform::casts::sym_token_kind;
 use crate::RefactorCtxt;
@@ -24,8 +33,6 @@ use crate::RefactorCtxt;
 
 /// # `bytestr_to_str` Command
 ///
-/// Usage: `bytestr_to_str`
-///
 /// Marks: `target`
 ///
 /// Convert bytestring literal expressions marked `target` to string literal
@@ -62,8 +69,6 @@ impl Transform for ByteStrToStr {
 
 /// # `remove_null_terminator` Command
 ///
-/// Usage: `remove_null_terminator`
-///
 /// Marks: `target`
 ///
 /// Remove a trailing `\0` character from marked string and bytestring literal
@@ -110,9 +115,300 @@ impl Transform for RemoveNullTerminator {
 }
 
 
-/// # `remove_literal_suffixes` Command
+/// # `convert_cstr_literals` Command
 ///
-/// Usage: `remove_literal_suffixes`
+/// Replace NUL-terminated byte string literals that are cast to C string pointers, as in
+/// `b"hello\x00" as *const u8 as *const libc::c_char`, with pointers into `CStr`s:
+/// `std::ffi::CStr::from_bytes_with_nul(b"hello\0").unwrap().as_ptr()`.  When the target type
+/// isn't a `c_char` pointer, the final cast is kept.  The literal must end in exactly one NUL and
+/// contain no other NULs; any other literal is left alone with a warning.
+///
+/// A literal that appears more than once in the crate is converted once, into a `const` of type
+/// `&CStr` at the crate root, and every occurrence becomes a pointer into that constant.  The
+/// constant is built with `CStr::from_bytes_with_nul_unchecked`, which is safe since the literal
+/// was checked, and which is usable in a constant context.
+///
+/// Other literals inside `static` and `const` initializers can't use `unwrap()`, which isn't a
+/// `const fn`, so they're left alone unless `--unchecked-in-statics` is passed.  Then they're built
+/// with the unchecked constructor too.
+pub struct ConvertCStrLiterals {
+    unchecked_in_statics: bool,
+}
+
+impl Transform for ConvertCStrLiterals {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Count the occurrences of each valid literal, warning about invalid ones.
+
+        let mut counter = CStrCounter {
+            cx,
+            counts: HashMap::new(),
+            order: Vec::new(),
+        };
+        visit::walk_crate(&mut counter, krate);
+
+        // (2) Hoist the literals that occur more than once into constants.
+
+        let mut names = krate.module.items.iter()
+            .map(|i| i.ident.name)
+            .collect::<HashSet<_>>();
+        let mut hoisted = HashMap::new();
+        let mut consts = Vec::new();
+        for (bytes, lit) in counter.order {
+            if counter.counts[&bytes] < 2 {
+                continue;
+            }
+            let name = fresh_cstr_name(&bytes, &mut names);
+            let ty = mk().ref_ty(mk().path_ty(vec!["std", "ffi", "CStr"]));
+            consts.push(mk().const_item(name, ty, unchecked_cstr(nul_escaped(&lit))));
+            hoisted.insert(bytes, name);
+        }
+
+        // Put the constants after the `use` and `extern crate` items that start the crate.
+        let pos = krate.module.items.iter()
+            .position(|i| match i.kind {
+                ItemKind::Use(..) | ItemKind::ExternCrate(..) => false,
+                _ => true,
+            })
+            .unwrap_or(krate.module.items.len());
+        for (i, item) in consts.into_iter().enumerate() {
+            krate.module.items.insert(pos + i, item);
+        }
+
+        // (3) Rewrite the casts.
+
+        krate.visit(&mut CStrFolder {
+            hoisted: &hoisted,
+            unchecked_in_statics: self.unchecked_in_statics,
+            mod_depth: 0,
+            in_const: false,
+        });
+    }
+}
+
+/// A byte string literal cast to a C string pointer.
+pub struct CStrCast<'a> {
+    pub lit: &'a Lit,
+    pub bytes: &'a [u8],
+    /// The pointer type of the outermost cast.
+    pub ty: &'a P<Ty>,
+}
+
+/// Check whether `e` is a byte string literal cast to an immutable pointer, possibly through
+/// other pointer casts and `as_ptr()`.
+pub fn cstr_cast(e: &Expr) -> Option<CStrCast> {
+    let is_const_ptr = |ty: &Ty| match ty.kind {
+        TyKind::Ptr(MutTy { mutbl: Mutability::Immutable, .. }) => true,
+        _ => false,
+    };
+
+    let (mut inner, ty) = match_or!([e.kind] ExprKind::Cast(ref inner, ref ty) => (inner, ty);
+                                    return None);
+    if !is_const_ptr(ty) {
+        return None;
+    }
+    loop {
+        match inner.kind {
+            ExprKind::Cast(ref e, ref ty) if is_const_ptr(ty) => inner = e,
+            ExprKind::MethodCall(ref seg, ref args)
+                if seg.ident.name.as_str() == "as_ptr" && args.len() == 1 => inner = &args[0],
+            ExprKind::Paren(ref e) => inner = e,
+            ExprKind::Lit(ref lit) => match lit.kind {
+                LitKind::ByteStr(ref bytes) => return Some(CStrCast { lit, bytes, ty }),
+                _ => return None,
+            },
+            _ => return None,
+        }
+    }
+}
+
+/// Check that `bytes` ends in a NUL and contains no other NULs.
+pub fn is_valid_cstr(bytes: &[u8]) -> bool {
+    bytes.last() == Some(&0) && bytes.iter().filter(|&&b| b == 0).count() == 1
+}
+
+/// Copy `lit`, writing a trailing `\x00` escape as `\0`.
+pub fn nul_escaped(lit: &Lit) -> Lit {
+    let mut lit = lit.clone();
+    let s = lit.token.symbol.as_str();
+    if lit.token.kind == token::LitKind::ByteStr && s.ends_with("\\x00") {
+        let new_sym = Symbol::intern(&format!("{}\\0", &s[..s.len() - 4]));
+        lit.token.symbol = new_sym;
+    }
+    lit
+}
+
+/// Pick a name for the constant holding `bytes` that isn't in `names`, and add it.
+fn fresh_cstr_name(bytes: &[u8], names: &mut HashSet<Symbol>) -> Symbol {
+    // `b"hello, world\0"` becomes `CSTR_HELLO_WORLD`.
+    let mut word = String::new();
+    for &b in bytes.iter().take_while(|&&b| b != 0) {
+        if b.is_ascii_alphanumeric() {
+            word.push(b.to_ascii_uppercase() as char);
+        } else if !word.is_empty() && !word.ends_with('_') {
+            word.push('_');
+        }
+        if word.len() >= 28 {
+            break;
+        }
+    }
+    let word = word.trim_end_matches('_');
+    let base = if word.is_empty() { "CSTR".to_owned() } else { format!("CSTR_{}", word) };
+
+    let mut name = Symbol::intern(&base);
+    let mut i = 2;
+    while names.contains(&name) {
+        name = Symbol::intern(&format!("{}_{}", base, i));
+        i += 1;
+    }
+    names.insert(name);
+    name
+}
+
+/// `std::ffi::CStr::from_bytes_with_nul_unchecked(lit)`, in an `unsafe` block.
+fn unchecked_cstr(lit: Lit) -> P<Expr> {
+    let call = mk().call_expr(
+        mk().path_expr(vec!["std", "ffi", "CStr", "from_bytes_with_nul_unchecked"]),
+        vec![mk().lit_expr(lit)]);
+    mk().block_expr(mk().unsafe_().block(vec![mk().expr_stmt(call)]))
+}
+
+/// `std::ffi::CStr::from_bytes_with_nul(lit).unwrap()`.
+pub fn checked_cstr(lit: Lit) -> P<Expr> {
+    let call = mk().call_expr(
+        mk().path_expr(vec!["std", "ffi", "CStr", "from_bytes_with_nul"]),
+        vec![mk().lit_expr(lit)]);
+    mk().method_call_expr(call, "unwrap", Vec::<P<Expr>>::new())
+}
+
+/// Get a pointer of type `ty` to the contents of the `CStr` expression `cstr`.
+fn cstr_ptr(cstr: P<Expr>, ty: &P<Ty>) -> P<Expr> {
+    let ptr = mk().method_call_expr(cstr, "as_ptr", Vec::<P<Expr>>::new());
+    let is_c_char = match ty.kind {
+        TyKind::Ptr(MutTy { ty: ref pointee, .. }) => match pointee.kind {
+            TyKind::Path(None, ref path) => {
+                path.segments.last().map_or(false, |seg| seg.ident.name.as_str() == "c_char")
+            }
+            _ => false,
+        },
+        _ => false,
+    };
+    if is_c_char {
+        ptr
+    } else {
+        mk().cast_expr(ptr, ty.clone())
+    }
+}
+
+struct CStrCounter<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    counts: HashMap<Vec<u8>, usize>,
+    /// Each distinct literal, in order of first occurrence.
+    order: Vec<(Vec<u8>, Lit)>,
+}
+
+impl<'a, 'b, 'tcx, 'ast> Visitor<'ast> for CStrCounter<'a, 'b, 'tcx> {
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        let cast = match_or!([cstr_cast(e)] Some(x) => x; return visit::walk_expr(self, e));
+        if !is_valid_cstr(cast.bytes) {
+            self.cx.session().span_warn(
+                e.span,
+                "not converting byte string to a `CStr`: it must end in exactly one NUL and \
+                 contain no other NULs",
+            );
+            return;
+        }
+        let count = self.counts.entry(cast.bytes.to_owned()).or_insert(0);
+        if *count == 0 {
+            self.order.push((cast.bytes.to_owned(), cast.lit.clone()));
+        }
+        *count += 1;
+    }
+
+    fn visit_mac(&mut self, mac: &'ast Mac) {
+        visit::walk_mac(self, mac)
+    }
+}
+
+struct CStrFolder<'a> {
+    /// Names of the hoisted constants, by literal contents.
+    hoisted: &'a HashMap<Vec<u8>, Symbol>,
+    unchecked_in_statics: bool,
+    mod_depth: usize,
+    /// Whether we're inside a `static` or `const` initializer or a `const fn`.
+    in_const: bool,
+}
+
+impl<'a> CStrFolder<'a> {
+    fn rewrite(&self, e: &Expr) -> Option<P<Expr>> {
+        let cast = cstr_cast(e)?;
+        if !is_valid_cstr(cast.bytes) {
+            return None;
+        }
+        let cstr = if let Some(&name) = self.hoisted.get(cast.bytes) {
+            // Only the crate root itself can name the constant without a path.
+            if self.mod_depth == 1 {
+                mk().path_expr(vec![name])
+            } else {
+                mk().path_expr(vec![Symbol::intern("crate"), name])
+            }
+        } else if !self.in_const {
+            checked_cstr(nul_escaped(cast.lit))
+        } else if self.unchecked_in_statics {
+            unchecked_cstr(nul_escaped(cast.lit))
+        } else {
+            return None;
+        };
+        let mut new_e = cstr_ptr(cstr, cast.ty);
+        new_e.span = e.span;
+        Some(new_e)
+    }
+}
+
+impl<'a> MutVisitor for CStrFolder<'a> {
+    fn visit_mod(&mut self, m: &mut Mod) {
+        self.mod_depth += 1;
+        mut_visit::noop_visit_mod(m, self);
+        self.mod_depth -= 1;
+    }
+
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        match self.rewrite(e) {
+            Some(new_e) => *e = new_e,
+            None => mut_visit::noop_visit_expr(e, self),
+        }
+    }
+
+    fn flat_map_item(&mut self, i: P<Item>) -> SmallVec<[P<Item>; 1]> {
+        let in_const = match i.kind {
+            ItemKind::Const(..) | ItemKind::Static(..) => true,
+            ItemKind::Fn(ref sig, ..) => sig.header.constness.node == Constness::Const,
+            _ => false,
+        };
+        let outer = std::mem::replace(&mut self.in_const, in_const);
+        let items = mut_visit::noop_flat_map_item(i, self);
+        self.in_const = outer;
+        items
+    }
+
+    fn flat_map_impl_item(&mut self, ii: ImplItem) -> SmallVec<[ImplItem; 1]> {
+        let in_const = match ii.kind {
+            ImplItemKind::Const(..) => true,
+            ImplItemKind::Method(ref sig, _) => sig.header.constness.node == Constness::Const,
+            _ => false,
+        };
+        let outer = std::mem::replace(&mut self.in_const, in_const);
+        let items = mut_visit::noop_flat_map_impl_item(ii, self);
+        self.in_const = outer;
+        items
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+
+/// # `remove_literal_suffixes` Command
 ///
 /// Remove suffixes from literals in cases where Rust type inference will infer
 /// the correct types anyway. For example, in `1u64 + 2u64` at most one of the
@@ -1177,10 +1473,430 @@ impl<'tcx> ut::UnifyValue for LitTySource<'tcx> {
     }
 }
 
+/// # `name_magic_numbers` Command
+///
+/// Replace integer literals that stand for a specific thing with named constants.  `MAPPING`
+/// is a JSON file containing an array of entries like
+///
+/// ```json
+/// { "value": "0x8000", "const": "O_LARGEFILE", "type": "libc::c_int",
+///   "callee": "open", "arg": 1 }
+/// ```
+///
+/// where `value` is a number or a string holding a decimal, hex, octal or binary literal, and
+/// `const` and `type` are the name and type of the constant.  Each entry needs a context
+/// filter, which limits it to literals inside functions whose names match the `fn` glob
+/// (with `*` and `?` wildcards), or to literals passed as argument number `arg` (counting from
+/// 0, not counting the receiver of a method) of a function or method named `callee`.  An entry
+/// with both only applies where both match.  Casts around a literal argument are looked
+/// through.
+///
+/// The constants are defined in the module `PATH`, e.g., `consts` or `sys::flags`, which is
+/// created at the crate root if it doesn't exist, or the crate root itself if `--module` isn't
+/// passed.  Only constants that get used are created, and existing items with the same name
+/// are reused.  Each matching literal becomes a path to its constant, with an `as` cast if the
+/// type of the constant differs from the type the literal had, or can't be determined.
+///
+/// A literal that matches entries for two different constants is left alone with a warning.
+/// Once done, prints how many literals were named or found ambiguous.  If `-o REPORT` is
+/// passed, their source locations are also written to the `REPORT` file as JSON.
+pub struct NameMagicNumbers {
+    pub mapping: String,
+    pub module: Option<String>,
+    pub report_path: Option<String>,
+}
+
+/// One entry of a `name_magic_numbers` mapping.
+struct MagicEntry {
+    value: u128,
+    /// The literal as written in the mapping, used for the new constant.
+    value_text: String,
+    name: Symbol,
+    ty: String,
+    fn_filter: Option<Regex>,
+    callee: Option<(Symbol, usize)>,
+}
+
+impl MagicEntry {
+    fn matches(&self, fn_name: Option<Symbol>, arg_site: Option<&(Symbol, usize)>) -> bool {
+        if let Some(ref re) = self.fn_filter {
+            match fn_name {
+                Some(name) if re.is_match(&name.as_str()) => {}
+                _ => return false,
+            }
+        }
+        if let Some(ref callee) = self.callee {
+            if arg_site != Some(callee) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Transform for NameMagicNumbers {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        st.start_report("name_magic_numbers", self.report_path.clone());
+        let tcx = cx.ty_ctxt();
+        let entries = read_magic_mapping(&self.mapping);
+        let const_mod = self.module.as_ref().map_or_else(Vec::new, |path| {
+            path.split("::").map(|seg| seg.into_symbol()).collect::<Vec<_>>()
+        });
+
+        // (1) Find the types of the constants, from the existing items or the casts to the
+        // same type elsewhere in the crate.
+
+        let mut existing = HashMap::new();
+        if let Some(m) = find_mod(&krate.module, &const_mod) {
+            for i in &m.items {
+                existing.insert(i.ident.name, i.id);
+            }
+        }
+        let mut cast_tys = HashMap::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Cast(_, ref ty) = e.kind {
+                if let Some(cast_ty) = cx.opt_node_type(ty.id) {
+                    cast_tys.insert(pprust::ty_to_string(ty), cast_ty);
+                }
+            }
+        });
+        let mut const_tys = HashMap::new();
+        for entry in &entries {
+            let const_ty = match existing.get(&entry.name) {
+                Some(&id) => cx.hir_map().opt_local_def_id_from_node_id(id)
+                    .map(|did| tcx.type_of(did)),
+                None => {
+                    let ty = parse_ty(cx.session(), &entry.ty);
+                    prim_ty(tcx, &entry.ty)
+                        .or_else(|| cast_tys.get(&pprust::ty_to_string(&ty)).cloned())
+                }
+            };
+            let const_ty = const_ty.map(|ty| tcx.normalize_erasing_regions(ParamEnv::empty(), ty));
+            const_tys.insert(entry.name, const_ty);
+        }
+
+        // (2) Find the literals passed directly to functions and methods.
+
+        let mut arg_sites = HashMap::new();
+        visit_nodes(krate, |e: &Expr| {
+            let (name, args) = match e.kind {
+                ExprKind::Call(_, ref args) => {
+                    let did = match_or!([cx.opt_callee(e)] Some(x) => x; return);
+                    (tcx.item_name(did), &args[..])
+                }
+                ExprKind::MethodCall(ref seg, ref args) => (seg.ident.name, &args[1..]),
+                _ => return,
+            };
+            for (i, arg) in args.iter().enumerate() {
+                let mut arg = &**arg;
+                while let ExprKind::Cast(ref inner, _) | ExprKind::Paren(ref inner) = arg.kind {
+                    arg = inner;
+                }
+                if let ExprKind::Lit(_) = arg.kind {
+                    arg_sites.insert(arg.id, (name, i));
+                }
+            }
+        });
+
+        // (3) Rewrite the literals.
+
+        let mut f = MagicNumberFolder {
+            cx,
+            entries: &entries,
+            arg_sites: &arg_sites,
+            const_tys: &const_tys,
+            const_mod: &const_mod,
+            cur_mod: Vec::new(),
+            cur_fn: Vec::new(),
+            used: Vec::new(),
+            sites: Vec::new(),
+        };
+        f.visit_crate(krate);
+        let MagicNumberFolder { used, sites, .. } = f;
+        for (category, span) in sites {
+            st.report(cx, category, span);
+        }
+
+        // (4) Define the constants that don't exist yet.
+
+        let missing = used.into_iter().filter(|name| !existing.contains_key(name));
+        let consts = missing.map(|name| {
+            let entry = entries.iter().find(|entry| entry.name == name).unwrap();
+            let lit = Lit {
+                token: token::Lit::new(token::LitKind::Integer,
+                                       Symbol::intern(&entry.value_text), None),
+                kind: LitKind::Int(entry.value, LitIntType::Unsuffixed),
+                span: DUMMY_SP,
+            };
+            let b = if const_mod.is_empty() { mk() } else { mk().pub_() };
+            b.const_item(name, parse_ty(cx.session(), &entry.ty), mk().lit_expr(lit))
+        }).collect::<Vec<_>>();
+        if consts.is_empty() {
+            return;
+        }
+        let m = find_or_create_mod(&mut krate.module, &const_mod);
+        let pos = m.items.iter()
+            .position(|i| match i.kind {
+                ItemKind::Use(..) | ItemKind::ExternCrate(..) => false,
+                _ => true,
+            })
+            .unwrap_or(m.items.len());
+        for (i, item) in consts.into_iter().enumerate() {
+            m.items.insert(pos + i, item);
+        }
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Read the entries of a `name_magic_numbers` mapping from a JSON file.
+fn read_magic_mapping(path: &str) -> Vec<MagicEntry> {
+    let src = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+    let json = json::parse(&src)
+        .unwrap_or_else(|e| panic!("failed to parse {}: {}", path, e));
+    json.members().enumerate().map(|(i, entry)| {
+        let field = |key: &str| entry[key].as_str()
+            .unwrap_or_else(|| panic!("expected a string `{}` in entry {} of {}", key, i, path));
+        let value_text = match entry["value"].as_u64() {
+            Some(v) => v.to_string(),
+            None => field("value").to_owned(),
+        };
+        let value = parse_int_value(&value_text)
+            .unwrap_or_else(|| panic!("bad value `{}` in entry {} of {}", value_text, i, path));
+        let fn_filter = entry["fn"].as_str().map(|glob| {
+            let re = regex::escape(glob).replace(r"\*", ".*").replace(r"\?", ".");
+            Regex::new(&format!("^{}$", re)).unwrap()
+        });
+        let callee = entry["callee"].as_str().map(|callee| {
+            let arg = entry["arg"].as_usize()
+                .unwrap_or_else(|| panic!("expected an `arg` for `{}` in entry {} of {}",
+                                          callee, i, path));
+            (callee.into_symbol(), arg)
+        });
+        if fn_filter.is_none() && callee.is_none() {
+            panic!("expected a `fn` or `callee` filter in entry {} of {}", i, path);
+        }
+        MagicEntry {
+            value,
+            value_text,
+            name: field("const").into_symbol(),
+            ty: field("type").to_owned(),
+            fn_filter,
+            callee,
+        }
+    }).collect()
+}
+
+/// Parse an unsuffixed integer literal in any radix, e.g., `0o777` or `4_096`.
+fn parse_int_value(s: &str) -> Option<u128> {
+    let s = s.replace('_', "");
+    let (digits, radix) = match s.get(..2) {
+        Some("0x") | Some("0X") => (&s[2..], 16),
+        Some("0o") => (&s[2..], 8),
+        Some("0b") => (&s[2..], 2),
+        _ => (&s[..], 10),
+    };
+    u128::from_str_radix(digits, radix).ok()
+}
+
+/// Get the primitive integer type called `name`.
+fn prim_ty<'tcx>(tcx: ty::TyCtxt<'tcx>, name: &str) -> Option<ty::Ty<'tcx>> {
+    Some(match name {
+        "i8" => tcx.types.i8,
+        "i16" => tcx.types.i16,
+        "i32" => tcx.types.i32,
+        "i64" => tcx.types.i64,
+        "i128" => tcx.types.i128,
+        "isize" => tcx.types.isize,
+        "u8" => tcx.types.u8,
+        "u16" => tcx.types.u16,
+        "u32" => tcx.types.u32,
+        "u64" => tcx.types.u64,
+        "u128" => tcx.types.u128,
+        "usize" => tcx.types.usize,
+        _ => return None,
+    })
+}
+
+/// Find the inline module at `path`, relative to `m`.
+fn find_mod<'m>(m: &'m Mod, path: &[Symbol]) -> Option<&'m Mod> {
+    let (first, rest) = match path.split_first() {
+        Some(x) => x,
+        None => return Some(m),
+    };
+    m.items.iter().find_map(|i| match i.kind {
+        ItemKind::Mod(ref inner) if i.ident.name == *first => find_mod(inner, rest),
+        _ => None,
+    })
+}
+
+/// Find the module at `path`, relative to `m`, creating any missing modules along the way.
+fn find_or_create_mod<'m>(m: &'m mut Mod, path: &[Symbol]) -> &'m mut Mod {
+    let (first, rest) = match path.split_first() {
+        Some(x) => x,
+        None => return m,
+    };
+    let pos = m.items.iter().position(|i| match i.kind {
+        ItemKind::Mod(_) => i.ident.name == *first,
+        _ => false,
+    });
+    let pos = pos.unwrap_or_else(|| {
+        m.items.push(mk().pub_().mod_item(*first, mk().mod_(Vec::<P<Item>>::new())));
+        m.items.len() - 1
+    });
+    let inner = expect!([m.items[pos].kind] ItemKind::Mod(ref mut inner) => inner);
+    find_or_create_mod(inner, rest)
+}
+
+struct MagicNumberFolder<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    entries: &'a [MagicEntry],
+    arg_sites: &'a HashMap<NodeId, (Symbol, usize)>,
+    const_tys: &'a HashMap<Symbol, Option<ty::Ty<'tcx>>>,
+    const_mod: &'a [Symbol],
+    cur_mod: Vec<Symbol>,
+    cur_fn: Vec<Symbol>,
+    /// The constants used so far, in order of first use.
+    used: Vec<Symbol>,
+    sites: Vec<(&'static str, Span)>,
+}
+
+impl<'a, 'b, 'tcx> MagicNumberFolder<'a, 'b, 'tcx> {
+    fn name_lit(&mut self, e: &Expr) -> Option<P<Expr>> {
+        let value = match e.kind {
+            ExprKind::Lit(Lit { kind: LitKind::Int(v, _), .. }) => v,
+            _ => return None,
+        };
+        let fn_name = self.cur_fn.last().cloned();
+        let arg_site = self.arg_sites.get(&e.id);
+        let mut names = Vec::new();
+        for entry in self.entries {
+            if entry.value == value && entry.matches(fn_name, arg_site) &&
+                !names.contains(&entry.name)
+            {
+                names.push(entry.name);
+            }
+        }
+        if names.len() > 1 {
+            self.cx.session().span_warn(
+                e.span,
+                &format!("literal matches both `{}` and `{}`, leaving it alone",
+                         names[0], names[1]),
+            );
+            self.sites.push(("ambiguous", e.span));
+            return None;
+        }
+        let name = *names.first()?;
+        if !self.used.contains(&name) {
+            self.used.push(name);
+        }
+        self.sites.push(("named", e.span));
+
+        let path = if self.cur_mod[..] == *self.const_mod {
+            vec![name]
+        } else {
+            let mut path = vec![kw::Crate];
+            path.extend_from_slice(self.const_mod);
+            path.push(name);
+            path
+        };
+        let new_e = mk().span(e.span).path_expr(path);
+        let tcx = self.cx.ty_ctxt();
+        let lit_ty = self.cx.opt_node_type(e.id)
+            .map(|ty| tcx.normalize_erasing_regions(ParamEnv::empty(), ty));
+        match (lit_ty, self.const_tys[&name]) {
+            (Some(lit_ty), Some(const_ty)) if lit_ty == const_ty => Some(new_e),
+            (Some(lit_ty), _) => {
+                Some(mk().span(e.span).cast_expr(new_e, reflect_tcx_ty(tcx, lit_ty)))
+            }
+            (None, _) => Some(new_e),
+        }
+    }
+}
+
+impl<'a, 'b, 'tcx> MutVisitor for MagicNumberFolder<'a, 'b, 'tcx> {
+    fn flat_map_item(&mut self, i: P<Item>) -> SmallVec<[P<Item>; 1]> {
+        let (is_mod, is_fn) = match i.kind {
+            ItemKind::Mod(_) => (true, false),
+            ItemKind::Fn(..) => (false, true),
+            _ => (false, false),
+        };
+        if is_mod {
+            self.cur_mod.push(i.ident.name);
+        }
+        if is_fn {
+            self.cur_fn.push(i.ident.name);
+        }
+        let items = mut_visit::noop_flat_map_item(i, self);
+        if is_mod {
+            self.cur_mod.pop();
+        }
+        if is_fn {
+            self.cur_fn.pop();
+        }
+        items
+    }
+
+    fn flat_map_impl_item(&mut self, ii: ImplItem) -> SmallVec<[ImplItem; 1]> {
+        let is_fn = match ii.kind {
+            ImplItemKind::Method(..) => true,
+            _ => false,
+        };
+        if is_fn {
+            self.cur_fn.push(ii.ident.name);
+        }
+        let items = mut_visit::noop_flat_map_impl_item(ii, self);
+        if is_fn {
+            self.cur_fn.pop();
+        }
+        items
+    }
+
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        if let Some(new_e) = self.name_lit(e) {
+            *e = new_e;
+            return;
+        }
+        mut_visit::noop_visit_expr(e, self)
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
-    reg.register("bytestr_to_str", |_args| mk(ByteStrToStr));
-    reg.register("remove_null_terminator", |_args| mk(RemoveNullTerminator));
-    reg.register("remove_literal_suffixes", |_| mk(RemoveLiteralSuffixes));
+    let spec = CommandSpec::new("Convert byte string literals marked `target` to string literals.");
+    reg.register("bytestr_to_str", spec, |_args| Ok(mk(ByteStrToStr)));
+
+    let spec = CommandSpec::new("Remove the trailing `\\0` from literals marked `target`.");
+    reg.register("remove_null_terminator", spec, |_args| Ok(mk(RemoveNullTerminator)));
+
+    let spec = CommandSpec::new("Replace byte string literals cast to C strings with `CStr`s.")
+        .flag("--unchecked-in-statics", "also convert literals in statics, unchecked");
+    reg.register("convert_cstr_literals", spec, |args| {
+        Ok(mk(ConvertCStrLiterals {
+            unchecked_in_statics: args.flag("--unchecked-in-statics"),
+        }))
+    });
+
+    let spec = CommandSpec::new("Remove literal suffixes that type inference makes redundant.");
+    reg.register("remove_literal_suffixes", spec, |_| Ok(mk(RemoveLiteralSuffixes)));
+
+    let spec = CommandSpec::new("Replace integer literals with the named constants they stand for.")
+        .arg("MAPPING", ArgType::Str, "a JSON file listing the values and their constants")
+        .option("--module", "PATH", ArgType::Str, None, "the module to define the constants in")
+        .option("-o", "REPORT", ArgType::Str, None, "also write the report to REPORT as JSON");
+    reg.register("name_magic_numbers", spec, |args| {
+        Ok(mk(NameMagicNumbers {
+            mapping: args.str("MAPPING").to_owned(),
+            module: args.opt_str("--module").map(|s| s.to_owned()),
+            report_path: args.opt_str("-o").map(|s| s.to_owned()),
+        }))
+    });
 }
 
@@ -0,0 +1,385 @@
+use smallvec::{smallvec, SmallVec};
+use syntax::ast::*;
+use syntax::mut_visit::{self, MutVisitor};
+use syntax::ptr::P;
+use syntax_pos::Span;
+
+use c2rust_ast_builder::mk;
+use crate::ast_manip::AstEquiv;
+use crate::command::{CommandState, Registry};
+use crate::command_args::{ArgType, CommandSpec};
+use crate::driver::Phase;
+use crate::transform::casts::{cast_tys, is_lit_expr, is_lossless_int_cast, SimpleTy};
+use crate::transform::Transform;
+use crate::RefactorCtxt;
+
+/// # `convert_math_idioms` Command
+///
+/// Rewrites the branchy forms that C ternaries and `MIN`/`MAX`-style macros are translated
+/// into with the equivalent standard library methods:
+///
+///  * `if a < b { a } else { b }` becomes `a.min(b)`, and `if a < b { b } else { a }` becomes
+///    `a.max(b)`.  `<=`, `>` and `>=` comparisons are handled the same way.
+///
+///  * `if x < 0 { -x } else { x }` becomes `x.abs()`, also when the negation is written
+///    `x.wrapping_neg()`.
+///
+///  * `if v < lo { lo } else if v > hi { hi } else { v }` becomes `v.max(lo).min(hi)`, but
+///    only if `lo` and `hi` are literals with `lo <= hi`, since otherwise the two forms give
+///    different results for `v < lo`.  `clamp` isn't used, as it needs Rust 1.50.
+///
+/// The compared operands may differ from the branches by lossless integer casts, e.g.,
+/// `if (a as i64) < (b as i64) { a } else { b }` becomes `a.min(b)`.  Operands with side
+/// effects are left alone, since the rewritten form evaluates them only once.
+///
+/// Float idioms are only rewritten if `--float-ok` is passed: `f32::min` and `f32::max` ignore
+/// NaN operands, and `f32::abs` clears the sign of `-0.0` and NaNs, unlike the branchy forms.
+/// Without it, float idioms are kept and listed in the report instead.  Every rewritten `abs`
+/// of a signed integer is listed as well, since it panics or wraps on the minimum value of
+/// the type, where negating it in C is undefined behavior.
+///
+/// Once done, prints how many idioms of each kind were rewritten or skipped. If `-o REPORT` is
+/// passed, their source locations are also written to the `REPORT` file as JSON.
+pub struct ConvertMathIdioms {
+    pub float_ok: bool,
+    pub report_path: Option<String>,
+}
+
+impl Transform for ConvertMathIdioms {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        st.start_report("convert_math_idioms", self.report_path.clone());
+        let mut f = MathIdiomFolder {
+            cx,
+            float_ok: self.float_ok,
+            sites: Vec::new(),
+        };
+        f.visit_crate(krate);
+        for (category, span) in f.sites {
+            st.report(cx, category, span);
+        }
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// A min, max, abs or clamp idiom, as the method call that replaces it.  Clamps become
+/// `max` and `min` calls, as `clamp` needs Rust 1.50.
+struct MathIdiom<'e> {
+    method: &'static str,
+    recv: &'e P<Expr>,
+    args: Vec<&'e P<Expr>>,
+}
+
+struct MathIdiomFolder<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    float_ok: bool,
+    /// Report entries of the rewritten and skipped idioms
+    sites: Vec<(&'static str, Span)>,
+}
+
+impl<'a, 'b, 'tcx> MathIdiomFolder<'a, 'b, 'tcx> {
+    /// Strip parentheses and lossless integer casts from `e`, which don't change the outcome
+    /// of a comparison.
+    fn strip_lossless_casts<'e>(&self, e: &'e Expr) -> &'e Expr {
+        match e.kind {
+            ExprKind::Paren(ref ie) => self.strip_lossless_casts(ie),
+            ExprKind::Cast(ref ie, ref ty) => {
+                let tcx = self.cx.ty_ctxt();
+                match cast_tys(ie, ty, self.cx) {
+                    Some((from_ty, to_ty))
+                        if is_lossless_int_cast(SimpleTy::from_ty(tcx, from_ty),
+                                                SimpleTy::from_ty(tcx, to_ty)) =>
+                    {
+                        self.strip_lossless_casts(ie)
+                    }
+                    _ => e,
+                }
+            }
+            _ => e,
+        }
+    }
+
+    fn same_operand(&self, a: &Expr, b: &Expr) -> bool {
+        self.strip_lossless_casts(a).ast_equiv(self.strip_lossless_casts(b))
+    }
+
+    /// If `e` negates an operand, either as `-x` or `x.wrapping_neg()`, return that operand.
+    fn negated_operand<'e>(&self, e: &'e Expr) -> Option<&'e Expr> {
+        match e.kind {
+            ExprKind::Unary(UnOp::Neg, ref ie) => Some(ie),
+            ExprKind::MethodCall(ref seg, ref args)
+                if seg.ident.as_str() == "wrapping_neg" && args.len() == 1 => Some(&args[0]),
+            ExprKind::Paren(ref ie) => self.negated_operand(ie),
+            _ => None,
+        }
+    }
+
+    /// Match `if v < lo { lo } else if v > hi { hi } else { v }`, with the two tests in either
+    /// order.
+    fn match_clamp<'e>(&self, e: &'e Expr) -> Option<MathIdiom<'e>> {
+        let (cond1, bound1, rest) = match e.kind {
+            ExprKind::If(ref cond, ref then, Some(ref rest)) => (cond, block_value(then)?, rest),
+            _ => return None,
+        };
+        let (cond2, bound2, v) = match rest.kind {
+            ExprKind::If(..) => if_parts(rest)?,
+            _ => return None,
+        };
+
+        let (mut lo, mut hi) = (None, None);
+        for &(cond, bound) in &[(&**cond1, bound1), (cond2, bound2)] {
+            let (small, large) = ordered_operands(cond)?;
+            if self.same_operand(small, v) && self.same_operand(large, bound) {
+                lo = Some(bound);
+            } else if self.same_operand(large, v) && self.same_operand(small, bound) {
+                hi = Some(bound);
+            } else {
+                return None;
+            }
+        }
+        let (lo, hi) = (lo?, hi?);
+        if !is_pure_operand(v) || is_lit_expr(v) || !bounds_ordered(lo, hi) {
+            return None;
+        }
+        Some(MathIdiom { method: "clamp", recv: v, args: vec![lo, hi] })
+    }
+
+    /// Match `if a < b { a } else { b }` and `if a < b { b } else { a }`.
+    fn match_min_max<'e>(&self, e: &'e Expr) -> Option<MathIdiom<'e>> {
+        let (cond, a, b) = if_parts(e)?;
+        let (small, large) = ordered_operands(cond)?;
+        let method = if self.same_operand(a, small) && self.same_operand(b, large) {
+            "min"
+        } else if self.same_operand(a, large) && self.same_operand(b, small) {
+            "max"
+        } else {
+            return None;
+        };
+        if !is_pure_operand(a) || !is_pure_operand(b) {
+            return None;
+        }
+        // Methods can't be called on unsuffixed literals
+        let (recv, arg) = if is_lit_expr(a) { (b, a) } else { (a, b) };
+        if is_lit_expr(recv) {
+            return None;
+        }
+        Some(MathIdiom { method, recv, args: vec![arg] })
+    }
+
+    /// Match `if x < 0 { -x } else { x }` and `if x > 0 { x } else { -x }`.
+    fn match_abs<'e>(&self, e: &'e Expr) -> Option<MathIdiom<'e>> {
+        let (cond, a, b) = if_parts(e)?;
+        let (small, large) = ordered_operands(cond)?;
+        let (x, neg, pos) = if is_zero_lit(large) {
+            (small, a, b)
+        } else if is_zero_lit(small) {
+            (large, b, a)
+        } else {
+            return None;
+        };
+        if !self.same_operand(x, pos) || !self.same_operand(self.negated_operand(neg)?, pos) {
+            return None;
+        }
+        if !is_pure_operand(pos) || is_lit_expr(pos) {
+            return None;
+        }
+        Some(MathIdiom { method: "abs", recv: pos, args: vec![] })
+    }
+
+    /// Get the type of the idiom `e`, if it's one the idioms can be rewritten for.
+    fn idiom_ty(&self, e: &Expr) -> Option<SimpleTy> {
+        let ty = SimpleTy::from_ty(self.cx.ty_ctxt(), self.cx.opt_node_type(e.id)?);
+        if ty.is_integer() || ty.is_float() {
+            Some(ty)
+        } else {
+            None
+        }
+    }
+
+    fn rewrite(&mut self, e: &Expr, idiom: MathIdiom) -> Option<P<Expr>> {
+        let ty = self.idiom_ty(e)?;
+        if idiom.method == "abs" && !ty.is_signed() {
+            return None;
+        }
+        if ty.is_float() && !self.float_ok {
+            self.sites.push(("float idiom skipped", e.span));
+            return None;
+        }
+
+        self.sites.push((idiom.method, e.span));
+        if idiom.method == "abs" && ty.is_integer() {
+            self.sites.push(("signed abs (overflows on MIN)", e.span));
+        }
+        let call_mk = mk().id(e.id).span(e.span);
+        if idiom.method == "clamp" {
+            // `v.max(lo).min(hi)` only matches the branches for `lo <= hi`, which
+            // `match_clamp` checks.  For floats, a NaN `v` gives `lo` instead of NaN,
+            // just like `f32::max` ignores a NaN operand, which `float_ok` accepts
+            let (lo, hi) = (idiom.args[0].clone(), idiom.args[1].clone());
+            let max = mk().method_call_expr(idiom.recv.clone(), "max", vec![lo]);
+            return Some(call_mk.method_call_expr(max, "min", vec![hi]));
+        }
+        let args = idiom.args.into_iter().cloned().collect::<Vec<_>>();
+        Some(call_mk.method_call_expr(idiom.recv.clone(), idiom.method, args))
+    }
+}
+
+impl<'a, 'b, 'tcx> MutVisitor for MathIdiomFolder<'a, 'b, 'tcx> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        // Clamps get matched before their children, since the nested `if` would
+        // otherwise become a `min` or `max` on its own
+        if let Some(idiom) = self.match_clamp(e) {
+            if let Some(new_e) = self.rewrite(e, idiom) {
+                *e = new_e;
+            }
+            return;
+        }
+
+        mut_visit::noop_visit_expr(e, self);
+        let idiom = match self.match_min_max(e) {
+            Some(idiom) => Some(idiom),
+            None => self.match_abs(e),
+        };
+        if let Some(idiom) = idiom {
+            if let Some(new_e) = self.rewrite(e, idiom) {
+                *e = new_e;
+            }
+        }
+    }
+
+    // The idiom methods can't be called in `const` contexts
+    fn visit_anon_const(&mut self, _c: &mut AnonConst) {}
+
+    fn flat_map_item(&mut self, i: P<Item>) -> SmallVec<[P<Item>; 1]> {
+        match i.kind {
+            ItemKind::Const(..) | ItemKind::Static(..) => return smallvec![i],
+            ItemKind::Fn(ref sig, ..) if sig.header.constness.node == Constness::Const => {
+                return smallvec![i];
+            }
+            _ => {}
+        }
+        mut_visit::noop_flat_map_item(i, self)
+    }
+
+    fn flat_map_impl_item(&mut self, ii: ImplItem) -> SmallVec<[ImplItem; 1]> {
+        match ii.kind {
+            ImplItemKind::Const(..) => return smallvec![ii],
+            ImplItemKind::Method(ref sig, _) if sig.header.constness.node == Constness::Const => {
+                return smallvec![ii];
+            }
+            _ => {}
+        }
+        mut_visit::noop_flat_map_impl_item(ii, self)
+    }
+}
+
+/// Get the value of a block that consists of only a trailing expression.
+fn block_value(b: &Block) -> Option<&P<Expr>> {
+    match b.stmts[..] {
+        [Stmt { kind: StmtKind::Expr(ref e), .. }] => Some(e),
+        _ => None,
+    }
+}
+
+/// Split `if $cond { $a } else { $b }` into its condition and the values of its branches.
+fn if_parts(e: &Expr) -> Option<(&Expr, &P<Expr>, &P<Expr>)> {
+    match e.kind {
+        ExprKind::If(ref cond, ref then, Some(ref els)) => match els.kind {
+            ExprKind::Block(ref b, None) => Some((&**cond, block_value(then)?, block_value(b)?)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// If `cond` is an ordering comparison, return its operands ordered so that the
+/// condition holds when the first one is the smaller one.
+fn ordered_operands(cond: &Expr) -> Option<(&P<Expr>, &P<Expr>)> {
+    match cond.kind {
+        ExprKind::Binary(op, ref lhs, ref rhs) => match op.node {
+            BinOpKind::Lt | BinOpKind::Le => Some((lhs, rhs)),
+            BinOpKind::Gt | BinOpKind::Ge => Some((rhs, lhs)),
+            _ => None,
+        },
+        ExprKind::Paren(ref ie) => ordered_operands(ie),
+        _ => None,
+    }
+}
+
+/// Returns `true` if evaluating `e` once instead of twice can't make a difference.
+fn is_pure_operand(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Lit(_) | ExprKind::Path(..) => true,
+        ExprKind::Paren(ref ie)
+        | ExprKind::Field(ref ie, _)
+        | ExprKind::Cast(ref ie, _)
+        | ExprKind::Unary(_, ref ie) => is_pure_operand(ie),
+        ExprKind::Index(ref base, ref idx) => is_pure_operand(base) && is_pure_operand(idx),
+        ExprKind::Binary(_, ref lhs, ref rhs) => is_pure_operand(lhs) && is_pure_operand(rhs),
+        // Earlier rewrites of nested idioms
+        ExprKind::MethodCall(ref seg, ref args) => {
+            ["min", "max", "abs", "wrapping_neg"].contains(&&*seg.ident.as_str()) &&
+                args.iter().all(|arg| is_pure_operand(arg))
+        }
+        _ => false,
+    }
+}
+
+#[derive(Copy, Clone)]
+enum NumLit {
+    Int(i128),
+    Float(f64),
+}
+
+/// Get the value of a possibly negated numeric literal.
+fn num_lit_value(e: &Expr) -> Option<NumLit> {
+    match e.kind {
+        ExprKind::Lit(ref lit) => match lit.kind {
+            LitKind::Int(i, _) if i <= i128::max_value() as u128 => Some(NumLit::Int(i as i128)),
+            LitKind::Float(sym, _) | LitKind::FloatUnsuffixed(sym) => {
+                sym.as_str().parse().ok().map(NumLit::Float)
+            }
+            _ => None,
+        },
+        ExprKind::Unary(UnOp::Neg, ref ie) => match num_lit_value(ie)? {
+            NumLit::Int(i) => Some(NumLit::Int(-i)),
+            NumLit::Float(f) => Some(NumLit::Float(-f)),
+        },
+        ExprKind::Paren(ref ie) => num_lit_value(ie),
+        _ => None,
+    }
+}
+
+fn is_zero_lit(e: &Expr) -> bool {
+    match num_lit_value(e) {
+        Some(NumLit::Int(0)) => true,
+        Some(NumLit::Float(f)) => f == 0.0,
+        _ => false,
+    }
+}
+
+/// Returns `true` if `lo` and `hi` are literals of the same kind and `lo <= hi`, so that
+/// `v.max(lo).min(hi)` gives the same result as testing `v` against each bound in turn.
+fn bounds_ordered(lo: &Expr, hi: &Expr) -> bool {
+    match (num_lit_value(lo), num_lit_value(hi)) {
+        (Some(NumLit::Int(lo)), Some(NumLit::Int(hi))) => lo <= hi,
+        (Some(NumLit::Float(lo)), Some(NumLit::Float(hi))) => lo <= hi,
+        _ => false,
+    }
+}
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    let spec = CommandSpec::new("Rewrite translated min, max and abs idioms into method calls.")
+        .flag("--float-ok", "also rewrite idioms on floats")
+        .option("-o", "REPORT", ArgType::Str, None, "also write the report to REPORT as JSON");
+    reg.register("convert_math_idioms", spec, |args| {
+        Ok(mk(ConvertMathIdioms {
+            float_ok: args.flag("--float-ok"),
+            report_path: args.opt_str("-o").map(|s| s.to_owned()),
+        }))
+    });
+}
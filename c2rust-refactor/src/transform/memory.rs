@@ -0,0 +1,545 @@
+//! Transforms for calls to the C memory functions, like `memcpy` and `memset`, and for other
+//! raw accesses to memory buffers.
+use rustc::hir::{self, HirId};
+use rustc::hir::def_id::DefId;
+use rustc::ty::{self, ParamEnv};
+use smallvec::smallvec;
+use syntax::ast::*;
+use syntax::mut_visit::{self, MutVisitor};
+use syntax::ptr::P;
+use syntax::source_map::DUMMY_SP;
+use syntax::symbol::Symbol;
+use syntax::ThinVec;
+
+use c2rust_ast_builder::mk;
+use crate::ast_manip::{FlatMapNodes, MutVisit};
+use crate::command::{CommandState, Registry};
+use crate::command_args::CommandSpec;
+use crate::driver::Phase;
+use crate::transform::heap::{
+    core_trait, foreign_fn_name, is_mem_fn, strip_casts, type_implements, usize_expr,
+};
+use crate::transform::Transform;
+use crate::RefactorCtxt;
+
+
+/// # `convert_memcpy` Command
+///
+/// Marks: none
+///
+/// Replace `memcpy(dst, src, n);` and `memmove(dst, src, n);` statements, where `memcpy` and
+/// `memmove` are the foreign functions, by Rust copies.  By default, `memcpy` becomes
+/// `std::ptr::copy_nonoverlapping(src as *const u8, dst as *mut u8, n)`, and `memmove` becomes
+/// `std::ptr::copy` with the same arguments.  Casts of the pointers to `void *` are removed
+/// first.
+///
+/// If both pointers point to the same type `T`, the copy counts elements of `T` instead of
+/// bytes.  This requires the byte count `n` to be an exact number of elements: it has to be
+/// `size_of::<T>()`, `count * size_of::<T>()`, or `size_of::<[T; N]>()`, possibly cast.  For
+/// one-byte types, `n` is always an element count.
+///
+/// If `dst` is `a.as_mut_ptr()` and `src` is `b.as_ptr()` or `b.as_mut_ptr()`, where `a` and `b`
+/// are arrays, slices or `Vec`s of the same `Copy` element type, and `n` is an element count as
+/// above, the copy becomes the safe `a[..count].copy_from_slice(&b[..count])`.  Since the two
+/// borrows can't overlap, this is only done when `a` and `b` are different locals or statics,
+/// or different fields of one.  Other copies, including any `memmove` within a single buffer,
+/// keep using the raw pointer functions.
+pub struct ConvertMemcpy;
+
+impl Transform for ConvertMemcpy {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        FlatMapNodes::visit(krate, |s: Stmt| {
+            let new_expr = match s.kind {
+                StmtKind::Semi(ref e) => convert_copy(cx, e),
+                _ => None,
+            };
+            match new_expr {
+                Some(e) => smallvec![mk().span(s.span).semi_stmt(e)],
+                None => smallvec![s],
+            }
+        })
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Build the replacement for `e`, if it's a call to `memcpy` or `memmove`.
+fn convert_copy(cx: &RefactorCtxt, e: &Expr) -> Option<P<Expr>> {
+    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return None);
+    let copy_fn = match &*foreign_fn_name(cx, e)?.as_str() {
+        "memcpy" if args.len() == 3 => "copy_nonoverlapping",
+        "memmove" if args.len() == 3 => "copy",
+        _ => return None,
+    };
+    let dst = uncast_ptr(cx, &args[0]);
+    let src = uncast_ptr(cx, &args[1]);
+    let n = &args[2];
+
+    if let Some(copy) = slice_copy(cx, e.id, dst, src, n) {
+        return Some(mk().span(e.span).method_call_expr(copy.0, "copy_from_slice", vec![copy.1]));
+    }
+
+    let count = match (pointee(cx, dst), pointee(cx, src)) {
+        (Some((dst_ty, hir::Mutability::Mutable)), Some((src_ty, _))) if dst_ty == src_ty =>
+            elem_count(cx, n, dst_ty),
+        _ => None,
+    };
+    let (dst, src, count) = match count {
+        Some(count) => (dst.clone(), src.clone(), count),
+        None => (
+            mk().cast_expr(dst.clone(), mk().set_mutbl("mut").ptr_ty(mk().path_ty(vec!["u8"]))),
+            mk().cast_expr(src.clone(), mk().ptr_ty(mk().path_ty(vec!["u8"]))),
+            size_expr(cx, n),
+        ),
+    };
+    Some(mk().span(e.span).call_expr(
+        mk().path_expr(vec!["std", "ptr", copy_fn]),
+        vec![src, dst, count],
+    ))
+}
+
+/// If `dst` and `src` point into two disjoint arrays, slices or `Vec`s with the same `Copy`
+/// element type, and `n` bytes is a whole number of elements, build the destination and source
+/// slices for `copy_from_slice`.
+fn slice_copy(cx: &RefactorCtxt, id: NodeId, dst: &Expr, src: &Expr, n: &P<Expr>)
+              -> Option<(P<Expr>, P<Expr>)> {
+    let (dst_buf, _, elem) = slice_buf(cx, dst, true)?;
+    let (src_buf, _, src_elem) = slice_buf(cx, src, false)?;
+    if elem != src_elem || !type_implements(cx, elem, id, "Copy") {
+        return None;
+    }
+    match (place_of(cx, dst_buf), place_of(cx, src_buf)) {
+        (Some(a), Some(b)) if disjoint(&a, &b) => {}
+        _ => return None,
+    }
+    let count = elem_count(cx, n, elem)?;
+
+    Some((prefix(dst_buf, count.clone()), mk().addr_of_expr(prefix(src_buf, count))))
+}
+
+/// Build `buf[..count]`.
+fn prefix(buf: &P<Expr>, count: P<Expr>) -> P<Expr> {
+    mk().index_expr(buf.clone(), P(Expr {
+        id: DUMMY_NODE_ID,
+        kind: ExprKind::Range(None, Some(count), RangeLimits::HalfOpen),
+        span: DUMMY_SP,
+        attrs: ThinVec::new(),
+    }))
+}
+
+/// If `e` is `a.as_mut_ptr()` (or, unless `dst` is set, `a.as_ptr()`), where `a` is an array,
+/// a slice or a `Vec`, possibly behind references, return `a`, its type without the
+/// references, and its element type.
+fn slice_buf<'a, 'tcx>(cx: &RefactorCtxt<'_, 'tcx>, e: &'a Expr, dst: bool)
+                       -> Option<(&'a P<Expr>, ty::Ty<'tcx>, ty::Ty<'tcx>)> {
+    let (seg, args) = match_or!([e.kind] ExprKind::MethodCall(ref seg, ref args) => (seg, args);
+                                return None);
+    match &*seg.ident.as_str() {
+        "as_mut_ptr" if args.len() == 1 => {}
+        "as_ptr" if args.len() == 1 && !dst => {}
+        _ => return None,
+    }
+
+    let mut ty = cx.opt_node_type(args[0].id)?;
+    while let ty::TyKind::Ref(_, inner, _) = ty.kind {
+        ty = inner;
+    }
+    let elem = match ty.kind {
+        ty::TyKind::Array(elem, _) | ty::TyKind::Slice(elem) => elem,
+        ty::TyKind::Adt(def, substs) if is_vec(cx, def.did) => substs.type_at(0),
+        _ => return None,
+    };
+    Some((&args[0], ty, elem))
+}
+
+fn is_vec(cx: &RefactorCtxt, did: DefId) -> bool {
+    let tcx = cx.ty_ctxt();
+    let crate_name = tcx.crate_name(did.krate);
+    tcx.item_name(did).as_str() == "Vec" &&
+    (crate_name.as_str() == "alloc" || crate_name.as_str() == "std")
+}
+
+/// If `e` is a local or static, or a chain of field accesses on one, like `s.buf`, return the
+/// local or static and the names of the fields.
+fn place_of(cx: &RefactorCtxt, e: &Expr) -> Option<(HirId, Vec<Symbol>)> {
+    match e.kind {
+        ExprKind::Path(..) => Some((cx.try_resolve_expr_to_hid(e)?, Vec::new())),
+        ExprKind::Field(ref base, ident) => {
+            let (root, mut fields) = place_of(cx, base)?;
+            fields.push(ident.name);
+            Some((root, fields))
+        }
+        ExprKind::Paren(ref e) => place_of(cx, e),
+        _ => None,
+    }
+}
+
+/// Check that neither of two places contains the other.
+fn disjoint(a: &(HirId, Vec<Symbol>), b: &(HirId, Vec<Symbol>)) -> bool {
+    a.0 != b.0 || a.1.iter().zip(&b.1).any(|(x, y)| x != y)
+}
+
+/// Remove a cast of the pointer `e` to another pointer type, such as `*mut c_void`.
+fn uncast_ptr<'a>(cx: &RefactorCtxt, e: &'a P<Expr>) -> &'a P<Expr> {
+    match e.kind {
+        ExprKind::Cast(ref inner, _)
+            if cx.opt_node_type(inner.id).map_or(false, |ty| ty.is_unsafe_ptr()) => inner,
+        _ => e,
+    }
+}
+
+/// The pointee type and mutability of the raw pointer `e`.
+fn pointee<'tcx>(cx: &RefactorCtxt<'_, 'tcx>, e: &Expr)
+                 -> Option<(ty::Ty<'tcx>, hir::Mutability)> {
+    match cx.opt_node_type(e.id)?.kind {
+        ty::TyKind::RawPtr(tm) => Some((tm.ty, tm.mutbl)),
+        _ => None,
+    }
+}
+
+/// Convert the byte count `n` into a number of elements of type `elem`, if it's an exact
+/// multiple of the element size that can be seen in the expression.
+fn elem_count<'tcx>(cx: &RefactorCtxt<'_, 'tcx>, n: &P<Expr>, elem: ty::Ty<'tcx>)
+                    -> Option<P<Expr>> {
+    match elem.kind {
+        ty::TyKind::Uint(UintTy::U8) | ty::TyKind::Int(IntTy::I8) => return Some(size_expr(cx, n)),
+        _ => {}
+    }
+
+    let n = strip_casts(n);
+    if let Some(ty) = size_of_arg(cx, n) {
+        let count = match ty.kind {
+            _ if ty == elem => 1,
+            ty::TyKind::Array(inner, len) if inner == elem =>
+                len.try_eval_usize(cx.ty_ctxt(), ParamEnv::empty())?,
+            _ => return None,
+        };
+        return Some(mk().lit_expr(mk().int_lit(count as u128, LitIntType::Unsuffixed)));
+    }
+
+    // `count * size_of::<T>()`, or the `wrapping_mul` the transpiler emits
+    let (a, b) = match n.kind {
+        ExprKind::Binary(ref op, ref a, ref b) if op.node == BinOpKind::Mul => (a, b),
+        ExprKind::MethodCall(ref seg, ref args)
+            if seg.ident.as_str() == "wrapping_mul" && args.len() == 2 => (&args[0], &args[1]),
+        _ => return None,
+    };
+    if size_of_arg(cx, strip_casts(b)) == Some(elem) {
+        Some(size_expr(cx, a))
+    } else if size_of_arg(cx, strip_casts(a)) == Some(elem) {
+        Some(size_expr(cx, b))
+    } else {
+        None
+    }
+}
+
+/// If `e` is `size_of::<T>()`, return `T`.
+fn size_of_arg<'tcx>(cx: &RefactorCtxt<'_, 'tcx>, e: &Expr) -> Option<ty::Ty<'tcx>> {
+    cx.opt_callee_info(e)
+        .filter(|info| info.def_id.map_or(false, |did| is_mem_fn(cx, did, "size_of")))
+        .and_then(|info| info.substs)
+        .map(|substs| substs.type_at(0))
+}
+
+/// Convert the `size_t` argument `n` to a `usize`.  The transpiler casts `usize` values and
+/// literals to the declared parameter type, so those casts are removed instead.
+fn size_expr(cx: &RefactorCtxt, n: &P<Expr>) -> P<Expr> {
+    match n.kind {
+        ExprKind::Paren(ref inner) => return size_expr(cx, inner),
+        ExprKind::Cast(ref inner, _) => {
+            let keep_inner = match (&inner.kind, cx.opt_node_type(inner.id).map(|ty| &ty.kind)) {
+                (ExprKind::Lit(Lit { kind: LitKind::Int(_, LitIntType::Unsuffixed), .. }), _) |
+                (_, Some(ty::TyKind::Uint(UintTy::Usize))) => true,
+                _ => false,
+            };
+            if keep_inner {
+                return inner.clone();
+            }
+        }
+        _ => {}
+    }
+    usize_expr(cx, n)
+}
+
+/// # `convert_memset` Command
+///
+/// Marks: none
+///
+/// Replace `memset(p, c, n);` statements, where `memset` is the foreign function, by Rust code.
+/// By default, the call becomes `std::ptr::write_bytes(p as *mut u8, c as u8, n)`, after
+/// removing any cast of `p` to `void *`.  As in `convert_memcpy`, if `n` is visibly a whole
+/// number of the elements `p` points to, the elements are counted instead of bytes.
+///
+/// Two cases are converted to safe code instead:
+///
+///  * If `p` is `a.as_mut_ptr()`, where `a` is an array, slice or `Vec`, and `n` is a number
+///    of its elements, the call becomes `a[..count].fill(c)`, or `a.fill(c)` if `n` is the
+///    size of the whole array.  Since `memset` writes bytes, this is only done for `u8` and
+///    `i8` elements, or for other integer elements if `c` is zero.
+///
+///  * If `c` is zero and `n` is `size_of::<T>()`, where `p` has type `*mut T`, the call
+///    becomes `*p = Default::default()`, or `x = Default::default()` if `p` is
+///    `&mut x as *mut T`.  This is only done if `T::default()` is all zero bytes, so `T` must
+///    be a number, a `bool` or a `char`, an array of those, or a struct with a derived
+///    `Default` impl whose fields are all of this kind.  Types with references, pointers or
+///    other fields where zero may not be valid always use `write_bytes`.
+pub struct ConvertMemset;
+
+impl Transform for ConvertMemset {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        FlatMapNodes::visit(krate, |s: Stmt| {
+            let new_expr = match s.kind {
+                StmtKind::Semi(ref e) => convert_set(cx, e),
+                _ => None,
+            };
+            match new_expr {
+                Some(e) => smallvec![mk().span(s.span).semi_stmt(e)],
+                None => smallvec![s],
+            }
+        })
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Build the replacement for `e`, if it's a call to `memset`.
+fn convert_set(cx: &RefactorCtxt, e: &Expr) -> Option<P<Expr>> {
+    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return None);
+    if args.len() != 3 || &*foreign_fn_name(cx, e)?.as_str() != "memset" {
+        return None;
+    }
+    let dst = uncast_ptr(cx, &args[0]);
+    let (c, n) = (&args[1], &args[2]);
+    let zero = is_zero(strip_casts(c));
+
+    if let Some((buf, buf_ty, elem)) = slice_buf(cx, dst, true) {
+        let value = match elem.kind {
+            ty::TyKind::Uint(UintTy::U8) => Some(byte_value(c, "u8")),
+            ty::TyKind::Int(IntTy::I8) => Some(byte_value(c, "i8")),
+            ty::TyKind::Uint(_) | ty::TyKind::Int(_) if zero =>
+                Some(mk().lit_expr(mk().int_lit(0, LitIntType::Unsuffixed))),
+            _ => None,
+        };
+        if let Some(value) = value {
+            if size_of_arg(cx, strip_casts(n)) == Some(buf_ty) {
+                return Some(mk().span(e.span).method_call_expr(buf.clone(), "fill", vec![value]));
+            }
+            if let Some(count) = elem_count(cx, n, elem) {
+                let buf = prefix(buf, count);
+                return Some(mk().span(e.span).method_call_expr(buf, "fill", vec![value]));
+            }
+        }
+    }
+
+    let (ty, count) = match pointee(cx, dst) {
+        Some((ty, hir::Mutability::Mutable)) => (ty, elem_count(cx, n, ty)),
+        _ => return Some(write_bytes(e, byte_ptr(dst), c, size_expr(cx, n))),
+    };
+    if zero && size_of_arg(cx, strip_casts(n)) == Some(ty) &&
+       type_implements(cx, ty, e.id, "Default") && default_is_zero(cx, ty) {
+        let place = match dst.kind {
+            ExprKind::Cast(ref inner, _) => match inner.kind {
+                ExprKind::AddrOf(BorrowKind::Ref, Mutability::Mutable, ref x) => Some(x.clone()),
+                _ => None,
+            },
+            _ => None,
+        };
+        let place = place.unwrap_or_else(|| mk().unary_expr(UnOp::Deref, dst.clone()));
+        let default = mk().call_expr(mk().path_expr(vec!["Default", "default"]),
+                                     Vec::<P<Expr>>::new());
+        return Some(mk().span(e.span).assign_expr(place, default));
+    }
+    Some(match count {
+        Some(count) => write_bytes(e, dst.clone(), c, count),
+        None => write_bytes(e, byte_ptr(dst), c, size_expr(cx, n)),
+    })
+}
+
+/// Build `std::ptr::write_bytes(dst, c, count)`, replacing the call `e`.
+fn write_bytes(e: &Expr, dst: P<Expr>, c: &P<Expr>, count: P<Expr>) -> P<Expr> {
+    mk().span(e.span).call_expr(
+        mk().path_expr(vec!["std", "ptr", "write_bytes"]),
+        vec![dst, byte_value(c, "u8"), count],
+    )
+}
+
+fn byte_ptr(e: &P<Expr>) -> P<Expr> {
+    mk().cast_expr(e.clone(), mk().set_mutbl("mut").ptr_ty(mk().path_ty(vec!["u8"])))
+}
+
+/// The `int` argument `c` of `memset`, converted to the byte type `ty`.  A literal that fits is
+/// used directly.
+fn byte_value(c: &P<Expr>, ty: &str) -> P<Expr> {
+    let max = if ty == "i8" { 0x7f } else { 0xff };
+    let inner = strip_casts(c);
+    match inner.kind {
+        ExprKind::Lit(Lit { kind: LitKind::Int(v, LitIntType::Unsuffixed), .. }) if v <= max =>
+            inner.clone(),
+        _ => mk().cast_expr(c.clone(), mk().path_ty(vec![ty])),
+    }
+}
+
+fn is_zero(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Lit(Lit { kind: LitKind::Int(0, _), .. }) => true,
+        _ => false,
+    }
+}
+
+/// Check whether `T::default()` is all zero bytes for the type `ty`, as described for
+/// `convert_memset`.
+pub(crate) fn default_is_zero<'tcx>(cx: &RefactorCtxt<'_, 'tcx>, ty: ty::Ty<'tcx>) -> bool {
+    let tcx = cx.ty_ctxt();
+    match ty.kind {
+        ty::TyKind::Bool | ty::TyKind::Char | ty::TyKind::Int(_) | ty::TyKind::Uint(_) |
+        ty::TyKind::Float(_) => true,
+        ty::TyKind::Array(elem, _) => default_is_zero(cx, elem),
+        ty::TyKind::Adt(def, substs) if def.is_struct() => {
+            let default_did = match core_trait(tcx, "Default") {
+                Some(x) => x,
+                None => return false,
+            };
+            let mut derived = false;
+            tcx.for_each_relevant_impl(default_did, ty, |impl_did| {
+                if tcx.has_attr(impl_did, Symbol::intern("automatically_derived")) {
+                    derived = true;
+                }
+            });
+            derived && def.all_fields().all(|f| default_is_zero(cx, f.ty(tcx, substs)))
+        }
+        _ => false,
+    }
+}
+
+/// # `convert_ptr_offset_to_index` Command
+///
+/// Marks: none
+///
+/// Replace pointer arithmetic on the buffer of a local array, slice or `Vec` by indexing.  This
+/// cleans up after passes that retype a local to `&[T]`, `&mut [T]` or `Vec<T>` but leave its
+/// accesses going through the raw pointer: `*p.as_ptr().offset(i as isize)` and
+/// `*p.as_mut_ptr().add(i)` both become `p[i as usize]`, whether they're read or written, as in
+/// `*p.as_mut_ptr().offset(i) = v`.  The index is converted to `usize` the same way as in
+/// `convert_calloc_to_vec`, so a cast of `i` to `isize` is replaced rather than cast again.
+///
+/// Offsets that are visibly negative, like `offset(-1)`, are left alone, and so are accesses
+/// that cast the pointer to a different type first.
+///
+/// With `--unchecked`, the accesses become `*p.get_unchecked(i)`, or `*p.get_unchecked_mut(i)`
+/// if the pointer came from `as_mut_ptr`.  Like the original code, this skips the bounds check,
+/// which can matter in hot loops.
+pub struct ConvertPtrOffsetToIndex {
+    unchecked: bool,
+}
+
+impl Transform for ConvertPtrOffsetToIndex {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        index_offsets(krate, cx, self.unchecked, &|buf| match cx.try_resolve_expr_hir(buf) {
+            Some(hir::def::Res::Local(_)) => true,
+            _ => false,
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Replace pointer arithmetic on the buffers accepted by `is_buf` by indexing, as in
+/// `convert_ptr_offset_to_index`.
+pub(crate) fn index_offsets(krate: &mut Crate, cx: &RefactorCtxt, unchecked: bool,
+                            is_buf: &dyn Fn(&Expr) -> bool) {
+    krate.visit(&mut OffsetIndexer { cx, unchecked, is_buf });
+}
+
+struct OffsetIndexer<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    unchecked: bool,
+    is_buf: &'a dyn Fn(&Expr) -> bool,
+}
+
+impl<'a, 'b, 'tcx> MutVisitor for OffsetIndexer<'a, 'b, 'tcx> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        let access = match e.kind {
+            ExprKind::Unary(UnOp::Deref, ref inner) => offset_access(self.cx, inner, self.is_buf)
+                .map(|(buf, idx, mutbl)| (buf.clone(), usize_expr(self.cx, idx), mutbl)),
+            _ => None,
+        };
+
+        match access {
+            Some((buf, mut idx, mutbl)) => {
+                self.visit_expr(&mut idx);
+                *e = if self.unchecked {
+                    let method = if mutbl { "get_unchecked_mut" } else { "get_unchecked" };
+                    let elem = mk().method_call_expr(buf, method, vec![idx]);
+                    mk().span(e.span).unary_expr(UnOp::Deref, elem)
+                } else {
+                    mk().span(e.span).index_expr(buf, idx)
+                };
+            }
+            None => mut_visit::noop_visit_expr(e, self),
+        }
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+/// If `e` is `p.as_ptr().offset(i)` or `p.as_mut_ptr().add(i)`, where `p` is an array, slice or
+/// `Vec` accepted by `is_buf`, return `p`, `i`, and whether the pointer came from `as_mut_ptr`.
+fn offset_access<'a>(cx: &RefactorCtxt, e: &'a Expr, is_buf: &dyn Fn(&Expr) -> bool)
+                     -> Option<(&'a P<Expr>, &'a P<Expr>, bool)> {
+    let (seg, args) = match_or!([e.kind] ExprKind::MethodCall(ref seg, ref args) => (seg, args);
+                                return None);
+    match &*seg.ident.as_str() {
+        "offset" | "add" if args.len() == 2 => {}
+        _ => return None,
+    }
+    if let ExprKind::Unary(UnOp::Neg, _) = strip_casts(&args[1]).kind {
+        return None;
+    }
+
+    // Casts that keep the pointer type are left over from the C code and can be skipped.
+    let mut ptr = &args[0];
+    loop {
+        match ptr.kind {
+            ExprKind::Paren(ref inner) => ptr = inner,
+            ExprKind::Cast(ref inner, _)
+                if cx.opt_node_type(inner.id).is_some() &&
+                   cx.opt_node_type(inner.id) == cx.opt_node_type(ptr.id) => ptr = inner,
+            _ => break,
+        }
+    }
+
+    let (buf, _, _) = slice_buf(cx, ptr, false)?;
+    if !is_buf(buf) {
+        return None;
+    }
+    let mutbl = match_or!([ptr.kind] ExprKind::MethodCall(ref seg, _) => seg; return None)
+        .ident.as_str() == "as_mut_ptr";
+    Some((buf, &args[1], mutbl))
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    let spec = CommandSpec::new("Replace `memcpy` and `memmove` calls with Rust copies.");
+    reg.register("convert_memcpy", spec, |_args| Ok(mk(ConvertMemcpy)));
+
+    let spec = CommandSpec::new("Replace `memset` calls with Rust code.");
+    reg.register("convert_memset", spec, |_args| Ok(mk(ConvertMemset)));
+
+    let spec = CommandSpec::new("Replace pointer arithmetic on local buffers with indexing.")
+        .flag("--unchecked", "use `get_unchecked` instead of checked indexing");
+    reg.register("convert_ptr_offset_to_index", spec, |args| {
+        Ok(mk(ConvertPtrOffsetToIndex {
+            unchecked: args.flag("--unchecked"),
+        }))
+    });
+}
@@ -49,18 +49,24 @@ macro_rules! transform_modules {
 }
 
 transform_modules! {
+    arithmetic,
     canonicalize_refs,
     casts,
     char_literals,
     control_flow,
+    enums,
     externs,
     format,
     funcs,
     generics,
+    heap,
+    index_exprs,
     ionize,
     items,
     linkage,
     literals,
+    math_idioms,
+    memory,
     reorganize_definitions,
     ownership,
     retype,
@@ -68,5 +74,6 @@ transform_modules! {
     statics,
     structs,
     test,
+    transmutes,
     vars,
 }
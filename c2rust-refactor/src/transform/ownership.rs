@@ -19,6 +19,7 @@ use crate::analysis::labeled_ty::LabeledTyCtxt;
 use crate::analysis::ownership::{self, ConcretePerm, Var, PTy};
 use crate::analysis::ownership::constraint::{ConstraintSet, Perm};
 use crate::command::{CommandState, Registry, DriverCommand};
+use crate::command_args::{ArgType, CommandSpec};
 use crate::context::HirMap;
 use crate::driver::{Phase};
 use crate::RefactorCtxt;
@@ -26,33 +27,36 @@ use crate::type_map;
 use c2rust_ast_builder::{mk, IntoSymbol};
 
 pub fn register_commands(reg: &mut Registry) {
-    reg.register("ownership_annotate", |args| {
-        let label = args.get(0).map_or("target", |x| x).into_symbol();
+    let spec = CommandSpec::new("Annotate functions marked MARK with their ownership properties.")
+        .optional_arg("MARK", ArgType::Str, Some("target"), "the mark of the functions");
+    reg.register("ownership_annotate", spec, |args| {
+        let label = args.str("MARK").into_symbol();
 
-        Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+        Ok(Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
             do_annotate(st, cx, label);
-        }))
+        })))
     });
 
-    reg.register("ownership_split_variants", |args| {
-        let label = args.get(0).map_or("target", |x| x).into_symbol();
+    let spec = CommandSpec::new("Split ownership-polymorphic functions marked MARK into variants.")
+        .optional_arg("MARK", ArgType::Str, Some("target"), "the mark of the functions");
+    reg.register("ownership_split_variants", spec, |args| {
+        let label = args.str("MARK").into_symbol();
 
-        Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+        Ok(Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
             do_split_variants(st, cx, label);
-        }))
+        })))
     });
 
-    reg.register("ownership_mark_pointers", |_args| {
-        Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+    let spec = CommandSpec::new("Mark pointer types `ref`, `mut` or `move` by their ownership.");
+    reg.register("ownership_mark_pointers", spec, |_args| {
+        Ok(Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
             do_mark_pointers(st, cx);
-        }))
+        })))
     });
 }
 
 /// # `ownership_annotate` Command
 ///
-/// Usage: `ownership_annotate [MARK]`
-///
 /// Marks: `MARK`/`target`
 ///
 /// Run ownership analysis on functions bearing `MARK` (default: `target`),
@@ -301,8 +305,6 @@ fn build_variant_attr(group: &str) -> Attribute {
 
 /// # `ownership_split_variants` Command
 ///
-/// Usage: `ownership_split_variants [MARK]`
-///
 /// Marks: `MARK`/`target`
 ///
 /// Run ownership analysis on functions bearing `MARK` (default: `target`),
@@ -496,14 +498,11 @@ fn callee_new_name(cx: &RefactorCtxt,
 
 /// # `ownership_mark_pointers` Command
 ///
-/// Usage: `ownership_mark_pointers [MARK]`
+/// Marks: sets `ref`, `mut`, and `move`
 ///
-/// Marks: reads `MARK`/`target`; sets `ref`, `mut`, and `box`
-///
-/// Run ownership analysis on functions bearing `MARK` (default: `target`),
-/// then for pointer type appearing in their argument and return types,
-/// apply one of the marks `ref`, `mut`, or `box`, reflecting the results
-/// of the ownership analysis.
+/// Run ownership analysis on the crate, then for each pointer type appearing
+/// in function argument and return types, apply one of the marks `ref`, `mut`,
+/// or `move`, reflecting the results of the ownership analysis.
 /// See `analysis/ownership/README.md` for details on ownership inference.
 fn do_mark_pointers(st: &CommandState, cx: &RefactorCtxt) {
     let arena = SyncDroplessArena::default();
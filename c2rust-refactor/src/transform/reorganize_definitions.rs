@@ -22,6 +22,7 @@ use smallvec::smallvec;
 use crate::ast_manip::util::{is_relative_path, join_visibility, namespace, split_uses, is_exported, is_c2rust_attr};
 use crate::ast_manip::{visit_nodes, AstEquiv, FlatMapNodes, MutVisitNodes};
 use crate::command::{CommandState, Registry};
+use crate::command_args::CommandSpec;
 use crate::driver::Phase;
 use crate::path_edit::fold_resolved_paths_with_id;
 use crate::RefactorCtxt;
@@ -33,8 +34,6 @@ use super::externs;
 
 /// # `reorganize_definitions` Command
 ///
-/// Usage: `reorganize_definitions`
-///
 /// This refactoring operates on code transpiled with the
 /// `--reorganize-definitions` flag.
 ///
@@ -1691,5 +1690,6 @@ impl Transform for ReorganizeDefinitions {
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("reorganize_definitions", |_args| mk(ReorganizeDefinitions))
+    let spec = CommandSpec::new("Deduplicate the declarations of reorganized transpiler output.");
+    reg.register("reorganize_definitions", spec, |_args| Ok(mk(ReorganizeDefinitions)))
 }
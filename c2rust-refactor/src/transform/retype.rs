@@ -1,34 +1,40 @@
 use std::collections::{HashMap, HashSet};
 use std::ops::DerefMut;
 use rustc::hir;
+use rustc::hir::def::{DefKind, Res};
 use rustc::hir::def_id::DefId;
 use rustc::ty::{self, TyKind, TyCtxt, ParamEnv};
-use syntax::ast::*;
+use syntax::ast::{self, *};
+use syntax::attr;
 use syntax::mut_visit::{self, MutVisitor};
 use rustc_errors::PResult;
 use rustc_parse::parser::Parser;
 use syntax::token::{TokenKind, BinOpToken};
 use syntax::print::pprust;
 use syntax::ptr::P;
-use syntax_pos::Span;
+use syntax::symbol::Symbol;
+use syntax::ThinVec;
+use syntax::visit::{self, Visitor};
+use syntax_pos::{sym, Span, DUMMY_SP};
 use smallvec::{smallvec, SmallVec};
 
 use c2rust_ast_builder::{mk, IntoSymbol};
-use crate::ast_manip::{FlatMapNodes, MutVisit, MutVisitNodes, fold_output_exprs};
-use crate::ast_manip::fn_edit::{mut_visit_fns, visit_fns};
+use crate::ast_manip::{FlatMapNodes, MutVisit, MutVisitNodes, fold_output_exprs, visit_nodes};
+use crate::ast_manip::fn_edit::{mut_visit_fns, visit_fns, FnKind, FnLike};
 use crate::ast_manip::lr_expr::{self, fold_expr_with_context, fold_exprs_with_context};
 use crate::command::{Command, CommandState, RefactorState, Registry, TypeckLoopResult};
+use crate::command_args::{ArgType, CommandSpec};
 use crate::driver::{self, Phase, parse_ty, parse_expr};
 use crate::illtyped::{IlltypedFolder, fold_illtyped};
 use crate::matcher::{Bindings, MatchCtxt, Subst, mut_visit_match};
 use crate::reflect::{self, reflect_tcx_ty};
+use crate::transform::casts::RemoveRedundantCasts;
+use crate::transform::heap::{strip_casts, usize_expr, IndexRewriter};
 use crate::transform::Transform;
 use crate::RefactorCtxt;
 
 /// # `retype_argument` Command
 ///
-/// Usage: `retype_argument NEW_TY WRAP UNWRAP`
-///
 /// Marks: `target`
 ///
 /// For each argument marked `target`, change the type of the argument to `NEW_TY`,
@@ -94,106 +100,1678 @@ impl Transform for RetypeArgument {
             }));
         });
 
-        // (2) Rewrite callsites of modified functions.
+        // (2) Rewrite callsites of modified functions.
+
+        // We don't need any protection against infinite recursion here, because it doesn't make
+        // sense for `wrap` to call the function whose args we're changing.
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let callee = match_or!([cx.opt_callee(&e)] Some(x) => x; return);
+            let mod_args = match_or!([mod_fns.get(&callee)] Some(x) => x; return);
+            let args: &mut [P<Expr>] = match e.kind {
+                ExprKind::Call(_, ref mut args) => args,
+                ExprKind::MethodCall(_, ref mut args) => args,
+                _ => panic!("expected Call or MethodCall"),
+            };
+            for &idx in mod_args {
+                let mut bnd = Bindings::new();
+                bnd.add("__old", args[idx].clone());
+                args[idx] = wrap.clone().subst(st, cx, &bnd);
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `retype_return` Command
+///
+/// Marks: `target`
+///
+/// For each function marked `target`, change the return type of the function to
+/// `NEW_TY`, and use `WRAP` and `UNWRAP` to convert values to and from the
+/// original type of the argument at call sites and within the function body.
+///
+/// `WRAP` should contain an expression placeholder `__old`, and should convert
+/// `__old` from the function's original return type to `NEW_TY`.
+/// `UNWRAP` should contain an expression placeholder `__new`, and should perform
+/// the opposite conversion.
+pub struct RetypeReturn {
+    pub new_ty: String,
+    pub wrap: String,
+    pub unwrap: String,
+}
+
+impl Transform for RetypeReturn {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Change argument types and rewrite function bodies.
+
+        let new_ty = parse_ty(cx.session(), &self.new_ty);
+        let wrap = parse_expr(cx.session(), &self.wrap);
+        let unwrap = parse_expr(cx.session(), &self.unwrap);
+
+        // Modified functions, by DefId.
+        let mut mod_fns: HashSet<DefId> = HashSet::new();
+
+        mut_visit_fns(krate, |fl| {
+            if !st.marked(fl.id, "target") {
+                return;
+            }
+
+            // Change the return type annotation
+            fl.decl.output = FunctionRetTy::Ty(new_ty.clone());
+
+            // Rewrite output expressions using `wrap`.
+            fl.block.as_mut().map(|b| fold_output_exprs(b, true, |e| {
+                let mut bnd = Bindings::new();
+                bnd.add("__old", e.clone());
+                *e = wrap.clone().subst(st, cx, &bnd);
+            }));
+
+            mod_fns.insert(cx.node_def_id(fl.id));
+        });
+
+        // (2) Rewrite callsites of modified functions.
+
+        // We don't need any protection against infinite recursion here, because it doesn't make
+        // sense for `unwrap` to call the function whose args we're changing.
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let callee = match_or!([cx.opt_callee(&e)] Some(x) => x; return);
+            if !mod_fns.contains(&callee) {
+                return;
+            }
+            let mut bnd = Bindings::new();
+            bnd.add("__new", e.clone());
+            *e = unwrap.clone().subst(st, cx, &bnd)
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `convert_ptr_args_to_refs` Command
+///
+/// Marks: `MARK`/`target`
+///
+/// For each function marked `MARK`, change the raw pointer parameters of type `*const T` and
+/// `*mut T` to `&T` and `&mut T`, and update every call to the function in the crate.  A
+/// parameter is converted only if the body uses it exclusively by dereferencing it, as in `*p` or
+/// `(*p).x`.  A pointer that's compared against null, offset, passed to another function
+/// (including `free`), or copied anywhere may legitimately be null or point into an array, so it
+/// stays raw, and a warning explains why.  Dereferences in the body work unchanged on the new
+/// references.
+///
+/// At call sites, an argument `&x as *const T` or `&mut x as *mut T` becomes `&x` or `&mut x`,
+/// an argument that's already a reference is left alone, and any other argument `p` becomes
+/// `&*p` or `&mut *p`.
+///
+/// A function that's used other than by calling it directly, for example as a function pointer,
+/// is skipped entirely with a warning, since changing its signature would break the function
+/// pointer types.  So are trait methods, `#[no_mangle]` functions, which C code may call with
+/// null, and functions whose return type contains references, whose lifetimes would be elided
+/// differently.
+pub struct ConvertPtrArgsToRefs {
+    pub label: Symbol,
+}
+
+impl Transform for ConvertPtrArgsToRefs {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let sess = cx.session();
+
+        // (1) Find the parameters to convert.  For each function, we track the index and
+        // mutability of each converted parameter.
+
+        let mut conv_fns: HashMap<DefId, Vec<(usize, Mutability)>> = HashMap::new();
+
+        visit_fns(krate, |fl| {
+            if !st.marked(fl.id, self.label) {
+                return;
+            }
+            let has_raw_params = fl.decl.inputs.iter().any(|param| match param.ty.kind {
+                ast::TyKind::Ptr(..) => true,
+                _ => false,
+            });
+            if !has_raw_params {
+                return;
+            }
+            let skip = |why: &str| {
+                sess.span_warn(fl.span, &format!("not converting parameters of `{}`: {}",
+                                                 fl.ident, why));
+            };
+
+            if let Some(why) = fixed_signature(cx, &fl) {
+                return skip(why);
+            }
+            let body = match_or!([fl.block] Some(ref b) => b; return);
+
+            let mut params = Vec::new();
+            for (i, param) in fl.decl.inputs.iter().enumerate() {
+                let mutbl = match_or!([param.ty.kind] ast::TyKind::Ptr(ref mt) => mt.mutbl;
+                                      continue);
+                let name = match_or!([param.pat.kind]
+                                     PatKind::Ident(BindingMode::ByValue(_), ident, None) => ident;
+                                     continue);
+                let local = cx.hir_map().node_to_hir_id(param.pat.id);
+                match raw_ptr_use(cx, local, body) {
+                    None => params.push((i, mutbl)),
+                    Some(why) => sess.span_warn(
+                        param.pat.span,
+                        &format!("not converting parameter `{}` of `{}`: the pointer {}",
+                                 name, fl.ident, why),
+                    ),
+                }
+            }
+            if !params.is_empty() {
+                conv_fns.insert(cx.node_def_id(fl.id), params);
+            }
+        });
+
+        // (2) Skip functions that are used other than by calling them directly.
+
+        remove_indirect_fns(krate, cx, &mut conv_fns);
+
+        if conv_fns.is_empty() {
+            return;
+        }
+
+        // (3) Change the parameter types.  Uses inside the function bodies are all dereferences,
+        // which work the same on references.
+
+        mut_visit_fns(krate, |fl| {
+            let params = match_or!([conv_fns.get(&cx.node_def_id(fl.id))] Some(x) => x; return);
+            for &(i, mutbl) in params {
+                let ty = &mut fl.decl.inputs[i].ty;
+                let pointee = match_or!([ty.kind] ast::TyKind::Ptr(ref mt) => mt.ty.clone();
+                                        unreachable!());
+                *ty = mk().set_mutbl(mutbl).ref_ty(pointee);
+            }
+        });
+
+        // (4) Rewrite callsites of modified functions.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let callee = match_or!([cx.opt_callee(&e)] Some(x) => x; return);
+            let params = match_or!([conv_fns.get(&callee)] Some(x) => x; return);
+            let args: &mut [P<Expr>] = match e.kind {
+                ExprKind::Call(_, ref mut args) => args,
+                ExprKind::MethodCall(_, ref mut args) => args,
+                _ => panic!("expected Call or MethodCall"),
+            };
+            for &(i, mutbl) in params {
+                args[i] = ref_arg(cx, &args[i], mutbl);
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// If the signature of the function `fl` can't be changed by updating its direct callers, say
+/// why.
+fn fixed_signature(cx: &RefactorCtxt, fl: &FnLike) -> Option<&'static str> {
+    let tcx = cx.ty_ctxt();
+    match fl.kind {
+        FnKind::Normal | FnKind::ImplMethod => {}
+        _ => return Some("it's a trait or foreign function"),
+    }
+    let did = cx.node_def_id(fl.id);
+    if tcx.impl_of_method(did).and_then(|i| tcx.trait_id_of_impl(i)).is_some() {
+        return Some("it implements a trait method");
+    }
+    if attr::contains_name(&fl.attrs, sym::no_mangle) {
+        return Some("it's `#[no_mangle]`, so C code may pass null pointers");
+    }
+    if let FunctionRetTy::Ty(ref ty) = fl.decl.output {
+        if mentions_ref(ty) {
+            return Some("its return type contains references");
+        }
+    }
+    None
+}
+
+/// Remove the functions in `conv_fns` that are used other than by calling them directly, for
+/// example as function pointers, with a warning for each.
+fn remove_indirect_fns<T>(krate: &Crate, cx: &RefactorCtxt, conv_fns: &mut HashMap<DefId, T>) {
+    let mut direct_callees = HashSet::new();
+    visit_nodes(krate, |e: &Expr| {
+        match e.kind {
+            ExprKind::Call(ref callee, _) => {
+                direct_callees.insert(callee.id);
+            }
+            ExprKind::Path(..) if !direct_callees.contains(&e.id) => {
+                let did = match_or!([cx.try_resolve_expr(e)] Some(x) => x; return);
+                if conv_fns.remove(&did).is_some() {
+                    cx.session().span_warn(e.span, &format!(
+                        "not converting parameters of `{}`: it's used as a function pointer",
+                        cx.ty_ctxt().def_path_str(did)));
+                }
+            }
+            _ => {}
+        }
+    });
+}
+
+/// Find the first use of the raw pointer `local` in `body` that isn't a dereference, and describe
+/// what it does with the pointer.
+fn raw_ptr_use(cx: &RefactorCtxt, local: hir::HirId, body: &Block) -> Option<&'static str> {
+    struct UseFinder<'a, 'b, 'tcx> {
+        cx: &'a RefactorCtxt<'b, 'tcx>,
+        local: hir::HirId,
+        found: Option<&'static str>,
+    }
+
+    impl<'a, 'b, 'tcx> UseFinder<'a, 'b, 'tcx> {
+        fn is_local(&self, e: &Expr) -> bool {
+            match e.kind {
+                ExprKind::Cast(ref e, _) | ExprKind::Paren(ref e) => self.is_local(e),
+                _ => self.cx.try_resolve_expr_to_hid(e) == Some(self.local),
+            }
+        }
+
+        fn is_free_call(&self, e: &Expr) -> bool {
+            let tcx = self.cx.ty_ctxt();
+            self.cx.opt_callee(e).map_or(false, |did| {
+                tcx.is_foreign_item(did) && tcx.item_name(did).as_str() == "free"
+            })
+        }
+    }
+
+    impl<'a, 'b, 'tcx, 'ast> Visitor<'ast> for UseFinder<'a, 'b, 'tcx> {
+        fn visit_expr(&mut self, e: &'ast Expr) {
+            if self.found.is_some() {
+                return;
+            }
+            let found = match e.kind {
+                ExprKind::Unary(UnOp::Deref, ref inner) if self.is_local(inner) => {
+                    if let ExprKind::Cast(..) = inner.kind {
+                        "is cast to another pointer type"
+                    } else {
+                        return;
+                    }
+                }
+                ExprKind::MethodCall(ref seg, ref args) if self.is_local(&args[0]) => {
+                    match &*seg.ident.as_str() {
+                        "is_null" => "is compared against null",
+                        "offset" | "add" | "sub" |
+                        "wrapping_offset" | "wrapping_add" | "wrapping_sub" => "is offset",
+                        _ => "has a method called on it",
+                    }
+                }
+                ExprKind::Binary(_, ref l, ref r) if self.is_local(l) || self.is_local(r) => {
+                    "is compared against null or another pointer"
+                }
+                ExprKind::Call(_, ref args) if args.iter().any(|a| self.is_local(a)) => {
+                    if self.is_free_call(e) {
+                        "is passed to `free`"
+                    } else {
+                        "is passed to another function"
+                    }
+                }
+                _ if self.is_local(e) => "is copied or stored",
+                _ => {
+                    visit::walk_expr(self, e);
+                    return;
+                }
+            };
+            self.found = Some(found);
+        }
+
+        fn visit_mac(&mut self, mac: &'ast Mac) {
+            visit::walk_mac(self, mac)
+        }
+    }
+
+    let mut v = UseFinder { cx, local, found: None };
+    v.visit_block(body);
+    v.found
+}
+
+/// Check whether the type `ty` contains any references.
+fn mentions_ref(ty: &ast::Ty) -> bool {
+    struct RefFinder {
+        found: bool,
+    }
+
+    impl<'ast> Visitor<'ast> for RefFinder {
+        fn visit_ty(&mut self, ty: &'ast ast::Ty) {
+            if let ast::TyKind::Rptr(..) = ty.kind {
+                self.found = true;
+            }
+            visit::walk_ty(self, ty);
+        }
+
+        fn visit_mac(&mut self, mac: &'ast Mac) {
+            visit::walk_mac(self, mac)
+        }
+    }
+
+    let mut v = RefFinder { found: false };
+    v.visit_ty(ty);
+    v.found
+}
+
+/// Convert `arg`, a raw pointer argument, to a reference with mutability `mutbl`.
+fn ref_arg(cx: &RefactorCtxt, arg: &P<Expr>, mutbl: Mutability) -> P<Expr> {
+    // A reference that was coerced to a raw pointer can be passed as it is.
+    if let Some(ty) = cx.opt_node_type(arg.id) {
+        if let TyKind::Ref(..) = ty.kind {
+            return arg.clone();
+        }
+    }
+
+    let mut inner = arg;
+    while let ExprKind::Cast(ref e, _) | ExprKind::Paren(ref e) = inner.kind {
+        inner = e;
+    }
+    if let ExprKind::AddrOf(_, inner_mutbl, _) = inner.kind {
+        if inner_mutbl == Mutability::Mutable || mutbl == Mutability::Immutable {
+            return inner.clone();
+        }
+    }
+
+    mk().set_mutbl(mutbl).addr_of_expr(mk().unary_expr(UnOp::Deref, arg.clone()))
+}
+
+
+/// # `convert_ptr_len_to_slice` Command
+///
+/// Marks: `target`
+///
+/// For each function marked `target`, replace a raw pointer parameter and the length parameter
+/// that goes with it by a single slice parameter.  With `PTR` and `LEN`, the parameters with
+/// those names are converted.  Otherwise, every `*const T` or `*mut T` parameter that's directly
+/// followed by an integer parameter is paired with it.  The new parameter is `&mut [T]` if the
+/// body writes through the pointer, and `&[T]` otherwise.
+///
+/// In the body, `*p` becomes `p[0]`, `*p.offset(i)` and `*p.add(i)` become `p[i]`, and the
+/// length becomes `p.len()`, cast back to the length's original type.  The pointer may also be
+/// passed on, possibly offset, to another function that's converted at the same time.  Any other
+/// use, like comparing, storing, or casting the pointer, or passing it to an unconverted
+/// function, lets it escape, so the function is left alone with a warning that names the use.
+/// So is a function that assigns to a length, and, as in `convert_ptr_args_to_refs`, a function
+/// whose signature can't change because it's a trait method, `#[no_mangle]`, or used as a
+/// function pointer.
+///
+/// At call sites, the pointer and length arguments are replaced by one slice argument:
+///
+///  * `s.as_ptr()` with length `s.len()`, for an array, slice or `Vec` `s`, becomes `&s` (or
+///    just `s` if it's already a reference), and a converted caller passes its own slice
+///    parameter directly,
+///  * an offset pointer `s.as_ptr().add(off)` with length `s.len() - off`, or `p.add(off)`
+///    with `n - off` in a converted caller, becomes `&s[off..]` or `&p[off..]`,
+///  * any other length `n` takes a prefix, as in `&s[..n]` or `&s[off..][..n]`, and
+///  * any other pointer `p` becomes `std::slice::from_raw_parts(p, n)`, or
+///    `from_raw_parts_mut` if the slice is mutable.
+pub struct ConvertPtrLenToSlice {
+    pub names: Option<(Symbol, Symbol)>,
+}
+
+/// A pointer parameter and its length parameter, which are converted to one slice parameter.
+struct SliceParam {
+    /// The index of the pointer parameter.
+    ptr: usize,
+    /// The index of the length parameter.
+    len: usize,
+    ptr_ident: Ident,
+    ptr_local: hir::HirId,
+    len_local: hir::HirId,
+    elem_ty: P<ast::Ty>,
+    /// The type to cast `p.len()` to, unless the length is already a `usize`.
+    len_cast: Option<P<ast::Ty>>,
+    /// Whether the pointer was declared `*mut`.
+    writable: bool,
+    /// The mutability of the new slice.
+    mutbl: Mutability,
+}
+
+impl ConvertPtrLenToSlice {
+    /// Find the pointer and length parameters to convert in `decl`.
+    fn slice_params(&self, cx: &RefactorCtxt, decl: &FnDecl) -> Vec<SliceParam> {
+        let tcx = cx.ty_ctxt();
+        let ident = |param: &Param| match param.pat.kind {
+            PatKind::Ident(BindingMode::ByValue(_), ident, None) => Some(ident),
+            _ => None,
+        };
+
+        let mut params = Vec::new();
+        for (i, param) in decl.inputs.iter().enumerate() {
+            let ptr_ident = match_or!([ident(param)] Some(x) => x; continue);
+            let mt = match_or!([param.ty.kind] ast::TyKind::Ptr(ref mt) => mt; continue);
+            let j = match self.names {
+                Some((ptr, len)) => {
+                    if ptr_ident.name != ptr {
+                        continue;
+                    }
+                    let j = decl.inputs.iter()
+                        .position(|p| ident(p).map_or(false, |x| x.name == len));
+                    match_or!([j] Some(j) => j; continue)
+                }
+                None => {
+                    // Slices of `c_void` don't make sense, so buffers like the ones `memcpy`
+                    // takes aren't paired up.
+                    let pointee = cx.opt_node_type(param.pat.id).and_then(|ty| match ty.kind {
+                        TyKind::RawPtr(mt) => Some(mt.ty),
+                        _ => None,
+                    });
+                    match pointee.map(|ty| &ty.kind) {
+                        Some(TyKind::Adt(def, _))
+                            if tcx.item_name(def.did).as_str() == "c_void" => continue,
+                        _ => {}
+                    }
+                    i + 1
+                }
+            };
+            let len = match_or!([decl.inputs.get(j)] Some(x) => x; continue);
+            let len_ty = match_or!([cx.opt_node_type(len.pat.id)] Some(x) => x; continue);
+            if ident(len).is_none() || !len_ty.is_integral() {
+                continue;
+            }
+
+            params.push(SliceParam {
+                ptr: i,
+                len: j,
+                ptr_ident,
+                ptr_local: cx.hir_map().node_to_hir_id(param.pat.id),
+                len_local: cx.hir_map().node_to_hir_id(len.pat.id),
+                elem_ty: mt.ty.clone(),
+                len_cast: match len_ty.kind {
+                    TyKind::Uint(UintTy::Usize) => None,
+                    _ => Some(len.ty.clone()),
+                },
+                writable: mt.mutbl == Mutability::Mutable,
+                mutbl: Mutability::Immutable,
+            });
+        }
+        params
+    }
+}
+
+impl Transform for ConvertPtrLenToSlice {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let sess = cx.session();
+        let warn = |span: Span, ident: Ident, why: &str| {
+            sess.span_warn(span, &format!("not converting parameters of `{}`: {}", ident, why));
+        };
+
+        // (1) Find the parameters to convert, and check everything about each function except
+        // how it uses the pointers.
+
+        let mut conv_fns: HashMap<DefId, Vec<SliceParam>> = HashMap::new();
+        let mut bodies = HashMap::new();
+
+        visit_fns(krate, |fl| {
+            if !st.marked(fl.id, "target") {
+                return;
+            }
+            let params = self.slice_params(cx, &fl.decl);
+            if params.is_empty() {
+                return;
+            }
+            if let Some(why) = fixed_signature(cx, &fl) {
+                return warn(fl.span, fl.ident, why);
+            }
+            let body = match_or!([fl.block] Some(b) => b; return);
+
+            let mut assigned = HashSet::new();
+            fold_exprs_with_context(&mut body.clone(), |e, ctx| {
+                if let lr_expr::Context::LvalueMut = ctx {
+                    assigned.extend(cx.try_resolve_expr_to_hid(e));
+                }
+            });
+            if let Some(p) = params.iter().find(|p| assigned.contains(&p.len_local)) {
+                let len = pprust::pat_to_string(&fl.decl.inputs[p.len].pat);
+                return warn(fl.span, fl.ident, &format!("its length `{}` is assigned to", len));
+            }
+
+            let did = cx.node_def_id(fl.id);
+            conv_fns.insert(did, params);
+            bodies.insert(did, (fl.ident, fl.span, body));
+        });
+
+        remove_indirect_fns(krate, cx, &mut conv_fns);
+
+        // (2) Check how the bodies use the pointers.  A pointer can be passed on to another
+        // converted function, whose slice may be mutable, so repeat until nothing changes.
+
+        loop {
+            let mut escapes = Vec::new();
+            let mut mutated = Vec::new();
+            for (&did, params) in &conv_fns {
+                let (_, _, ref body) = bodies[&did];
+                for (k, p) in params.iter().enumerate() {
+                    let uses = slice_ptr_uses(cx, p.ptr_local, body, &conv_fns);
+                    if let Some(why) = uses.escape {
+                        escapes.push((did, format!("`{}` {}", p.ptr_ident, why)));
+                        break;
+                    }
+                    if uses.mutated && p.writable && p.mutbl == Mutability::Immutable {
+                        mutated.push((did, k));
+                    }
+                }
+            }
+            if escapes.is_empty() && mutated.is_empty() {
+                break;
+            }
+
+            for (did, k) in mutated {
+                conv_fns.get_mut(&did).unwrap()[k].mutbl = Mutability::Mutable;
+            }
+            for (did, why) in escapes {
+                conv_fns.remove(&did);
+                let (ident, span, _) = bodies[&did];
+                warn(span, ident, &why);
+            }
+        }
+
+        if conv_fns.is_empty() {
+            return;
+        }
+
+        // (3) Rewrite call sites.  This has to come before the bodies, which lose the lengths
+        // that arguments like `n - off` are matched against.
+
+        let slice_lens = conv_fns.values()
+            .flat_map(|params| params.iter().map(|p| (p.ptr_local, p.len_local)))
+            .collect::<HashMap<_, _>>();
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let callee = match_or!([cx.opt_callee(&e)] Some(x) => x; return);
+            let params = match_or!([conv_fns.get(&callee)] Some(x) => x; return);
+            let args = match e.kind {
+                ExprKind::Call(_, ref mut args) => args,
+                ExprKind::MethodCall(_, ref mut args) => args,
+                _ => panic!("expected Call or MethodCall"),
+            };
+            let old_args = std::mem::replace(args, Vec::new());
+            *args = old_args.iter().enumerate()
+                .filter(|&(i, _)| !params.iter().any(|p| p.len == i))
+                .map(|(i, arg)| match params.iter().find(|p| p.ptr == i) {
+                    Some(p) => slice_arg(cx, arg, &old_args[p.len], p.mutbl, &slice_lens),
+                    None => arg.clone(),
+                })
+                .collect();
+        });
+
+        // (4) Rewrite the bodies and change the signatures.
+
+        mut_visit_fns(krate, |fl| {
+            let params = match_or!([conv_fns.get(&cx.node_def_id(fl.id))] Some(x) => x; return);
+
+            for p in params {
+                fl.block.visit(&mut IndexRewriter { cx, local: p.ptr_local });
+
+                let mut len = mk().method_call_expr(
+                    mk().ident_expr(p.ptr_ident), "len", Vec::<P<Expr>>::new());
+                if let Some(ref ty) = p.len_cast {
+                    len = mk().cast_expr(len, ty.clone());
+                }
+                MutVisitNodes::visit(&mut fl.block, |e: &mut P<Expr>| {
+                    if cx.try_resolve_expr_to_hid(e) == Some(p.len_local) {
+                        *e = len.clone();
+                    }
+                });
+
+                // Nothing assigns to the pointer, so the slice doesn't need a `mut` binding.
+                let param = &mut fl.decl.inputs[p.ptr];
+                param.ty = mk().set_mutbl(p.mutbl).ref_ty(mk().slice_ty(p.elem_ty.clone()));
+                if let PatKind::Ident(ref mut mode, _, _) = param.pat.kind {
+                    *mode = BindingMode::ByValue(Mutability::Immutable);
+                }
+            }
+
+            let inputs = std::mem::replace(&mut fl.decl.inputs, Vec::new());
+            fl.decl.inputs = inputs.into_iter().enumerate()
+                .filter(|&(i, _)| !params.iter().any(|p| p.len == i))
+                .map(|(_, param)| param)
+                .collect();
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// How a function body uses a pointer parameter that's being converted to a slice.
+#[derive(Default)]
+struct SliceUses {
+    /// What the first use that lets the pointer escape does with it.
+    escape: Option<&'static str>,
+    /// Some element is written to or mutably borrowed.
+    mutated: bool,
+}
+
+fn slice_ptr_uses(cx: &RefactorCtxt, local: hir::HirId, body: &Block,
+                  conv_fns: &HashMap<DefId, Vec<SliceParam>>) -> SliceUses {
+    struct UseFinder<'a, 'b, 'tcx> {
+        cx: &'a RefactorCtxt<'b, 'tcx>,
+        local: hir::HirId,
+        conv_fns: &'a HashMap<DefId, Vec<SliceParam>>,
+        uses: SliceUses,
+    }
+
+    impl<'a, 'b, 'tcx> UseFinder<'a, 'b, 'tcx> {
+        fn is_local(&self, e: &Expr) -> bool {
+            self.cx.try_resolve_expr_to_hid(e) == Some(self.local)
+        }
+
+        /// Check whether the place `e` is (part of) an element of the slice.
+        fn is_elem_place(&self, e: &Expr) -> bool {
+            match e.kind {
+                ExprKind::Field(ref e, _) | ExprKind::Index(ref e, _) |
+                ExprKind::Paren(ref e) => self.is_elem_place(e),
+                ExprKind::Unary(UnOp::Deref, ref e) => self.is_local(split_offset(e).0),
+                _ => false,
+            }
+        }
+
+        /// Visit the arguments of a call to a converted function, where the pointer may be
+        /// passed as a slice.
+        fn visit_slice_args(&mut self, args: &[P<Expr>], params: &[SliceParam]) {
+            for (i, arg) in args.iter().enumerate() {
+                let p = params.iter().find(|p| p.ptr == i);
+                match (p, split_offset(arg)) {
+                    (Some(p), (base, off)) if self.is_local(base) => {
+                        if p.mutbl == Mutability::Mutable {
+                            self.uses.mutated = true;
+                        }
+                        match off {
+                            Some(off) if is_negative(off) => {
+                                self.uses.escape = Some("is offset by a negative amount");
+                            }
+                            Some(off) => self.visit_expr(off),
+                            None => {}
+                        }
+                    }
+                    _ => self.visit_expr(arg),
+                }
+            }
+        }
+    }
+
+    impl<'a, 'b, 'tcx, 'ast> Visitor<'ast> for UseFinder<'a, 'b, 'tcx> {
+        fn visit_expr(&mut self, e: &'ast Expr) {
+            if self.uses.escape.is_some() {
+                return;
+            }
+
+            match e.kind {
+                ExprKind::Assign(ref lhs, _) | ExprKind::AssignOp(_, ref lhs, _) |
+                ExprKind::AddrOf(_, Mutability::Mutable, ref lhs) if self.is_elem_place(lhs) => {
+                    self.uses.mutated = true;
+                }
+                // The method may take `&mut self`, so be conservative.
+                ExprKind::MethodCall(_, ref args) if self.is_elem_place(&args[0]) => {
+                    self.uses.mutated = true;
+                }
+                _ => {}
+            }
+
+            let conv_fns = self.conv_fns;
+            let callee_params = self.cx.opt_callee(e).and_then(|did| conv_fns.get(&did));
+            let escape = match e.kind {
+                ExprKind::Unary(UnOp::Deref, ref inner) => match split_offset(inner) {
+                    (base, Some(off)) if self.is_local(base) => {
+                        if is_negative(off) {
+                            "is offset by a negative amount"
+                        } else {
+                            self.visit_expr(off);
+                            return;
+                        }
+                    }
+                    (base, None) if self.is_local(base) => return,
+                    _ => {
+                        visit::walk_expr(self, e);
+                        return;
+                    }
+                },
+                ExprKind::Call(ref callee, ref args) if callee_params.is_some() => {
+                    self.visit_expr(callee);
+                    self.visit_slice_args(args, callee_params.unwrap());
+                    return;
+                }
+                ExprKind::MethodCall(_, ref args) if callee_params.is_some() => {
+                    self.visit_slice_args(args, callee_params.unwrap());
+                    return;
+                }
+                ExprKind::MethodCall(ref seg, ref args) if self.is_local(&args[0]) => {
+                    match &*seg.ident.as_str() {
+                        "is_null" => "is compared against null",
+                        "offset" | "add" | "sub" |
+                        "wrapping_offset" | "wrapping_add" | "wrapping_sub" => {
+                            "is offset without being dereferenced"
+                        }
+                        _ => "has a method called on it",
+                    }
+                }
+                ExprKind::Binary(_, ref l, ref r) if self.is_local(l) || self.is_local(r) => {
+                    "is compared against null or another pointer"
+                }
+                ExprKind::Cast(ref inner, _) if self.is_local(inner) => "is cast to another type",
+                ExprKind::Call(_, ref args) if args.iter().any(|a| self.is_local(a)) => {
+                    "is passed to another function"
+                }
+                _ if self.is_local(e) => "is copied or stored",
+                _ => {
+                    visit::walk_expr(self, e);
+                    return;
+                }
+            };
+            self.uses.escape = Some(escape);
+        }
+
+        fn visit_item(&mut self, _i: &'ast Item) {
+            // Nested items can't refer to the parameter.
+        }
+
+        fn visit_mac(&mut self, mac: &'ast Mac) {
+            visit::walk_mac(self, mac)
+        }
+    }
+
+    let mut v = UseFinder { cx, local, conv_fns, uses: SliceUses::default() };
+    v.visit_block(body);
+    v.uses
+}
+
+/// Split `p.offset(i)` or `p.add(i)` into `p` and `i`.  Any other expression is returned with
+/// no offset.
+fn split_offset(e: &Expr) -> (&Expr, Option<&P<Expr>>) {
+    if let ExprKind::MethodCall(ref seg, ref args) = e.kind {
+        match &*seg.ident.as_str() {
+            "offset" | "add" if args.len() == 2 => return (&args[0], Some(&args[1])),
+            _ => {}
+        }
+    }
+    (e, None)
+}
+
+fn is_negative(e: &P<Expr>) -> bool {
+    match strip_casts(e).kind {
+        ExprKind::Unary(UnOp::Neg, _) => true,
+        _ => false,
+    }
+}
+
+/// Remove casts from `e` that only change the mutability of the pointer, as in
+/// `p as *const T` for a `*mut T` pointer `p`.
+fn strip_ptr_casts<'a>(cx: &RefactorCtxt, e: &'a Expr) -> &'a Expr {
+    let pointee = |e: &Expr| match cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+        Some(TyKind::RawPtr(mt)) => Some(mt.ty),
+        _ => None,
+    };
+    match e.kind {
+        ExprKind::Paren(ref inner) => strip_ptr_casts(cx, inner),
+        ExprKind::Cast(ref inner, _) if pointee(inner).is_some() &&
+                                         pointee(inner) == pointee(e) => {
+            strip_ptr_casts(cx, inner)
+        }
+        _ => e,
+    }
+}
+
+/// Build the slice that replaces the pointer argument `ptr` and the length argument `len` in a
+/// call to a converted function.  `slice_lens` maps the pointer parameters of all converted
+/// functions to their lengths.
+fn slice_arg(cx: &RefactorCtxt, ptr: &P<Expr>, len: &P<Expr>, mutbl: Mutability,
+             slice_lens: &HashMap<hir::HirId, hir::HirId>) -> P<Expr> {
+    let (base, off) = split_offset(strip_ptr_casts(cx, ptr));
+    let base = strip_ptr_casts(cx, base);
+
+    // Find the expression that holds the slice, if the caller has one: either a converted
+    // parameter of the caller, or `s` in `s.as_ptr()`.
+    let param_len = cx.try_resolve_expr_to_hid(base).and_then(|hid| slice_lens.get(&hid));
+    let buf = match base.kind {
+        ExprKind::MethodCall(ref seg, ref args) if args.len() == 1 => {
+            match &*seg.ident.as_str() {
+                "as_ptr" | "as_mut_ptr" => Some(&*args[0]),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    let slice = match (param_len, buf) {
+        (Some(_), _) => base,
+        (None, Some(buf)) if !off.map_or(false, |off| is_negative(off)) => buf,
+        _ => {
+            let func = match mutbl {
+                Mutability::Immutable => "from_raw_parts",
+                Mutability::Mutable => "from_raw_parts_mut",
+            };
+            return mk().call_expr(mk().path_expr(vec!["", "std", "slice", func]),
+                                  vec![ptr.clone(), usize_expr(cx, len)]);
+        }
+    };
+    let is_full = |e: &Expr| match param_len {
+        Some(&len_local) => cx.try_resolve_expr_to_hid(e) == Some(len_local),
+        None => match e.kind {
+            ExprKind::MethodCall(ref seg, ref args) => {
+                seg.ident.as_str() == "len" && args.len() == 1 && same_expr(&args[0], slice)
+            }
+            _ => false,
+        },
+    };
+
+    // Whether `len` covers the rest of the slice after the offset.
+    let to_end = match (off, &strip_casts(len).kind) {
+        (None, _) => is_full(strip_casts(len)),
+        (Some(off), &ExprKind::Binary(op, ref l, ref r)) => {
+            op.node == BinOpKind::Sub && is_full(strip_casts(l)) &&
+                same_expr(strip_casts(r), strip_casts(off))
+        }
+        _ => false,
+    };
+
+    let is_ref = match cx.opt_node_type(slice.id) {
+        Some(ty) => match ty.kind {
+            TyKind::Ref(..) => true,
+            _ => false,
+        },
+        None => false,
+    };
+    if off.is_none() && to_end && (param_len.is_some() || is_ref) {
+        return P(slice.clone());
+    }
+
+    let mut e = P(slice.clone());
+    if let Some(off) = off {
+        e = slice_index(e, Some(usize_expr(cx, off)), None);
+    }
+    if !to_end {
+        e = slice_index(e, None, Some(usize_expr(cx, len)));
+    }
+    mk().set_mutbl(mutbl).addr_of_expr(e)
+}
+
+fn same_expr(a: &Expr, b: &Expr) -> bool {
+    pprust::expr_to_string(a) == pprust::expr_to_string(b)
+}
+
+/// Build `e[start..end]`, where either bound may be omitted.
+fn slice_index(e: P<Expr>, start: Option<P<Expr>>, end: Option<P<Expr>>) -> P<Expr> {
+    mk().index_expr(e, P(Expr {
+        id: DUMMY_NODE_ID,
+        kind: ExprKind::Range(start, end, RangeLimits::HalfOpen),
+        span: DUMMY_SP,
+        attrs: ThinVec::new(),
+    }))
+}
+
+/// # `convert_out_params` Command
+///
+/// Marks: `MARK`/`target`
+///
+/// For each function marked `MARK`, turn its out-parameters into return values.  An
+/// out-parameter is a parameter of type `*mut T` that the function only ever assigns through, as
+/// in `*p = x`, and assigns through on every path that returns.  The parameter is removed, the
+/// function assigns to a local `p` instead, and it returns `p` along with its old return value:
+///
+/// ```ignore
+///     unsafe fn divmod(a: i32, b: i32, quot: *mut i32, rem: *mut i32) -> i32 { ... }
+///
+///     let mut q: i32 = 0;
+///     let mut r: i32 = 0;
+///     let rc = divmod(17, 5, &mut q, &mut r);
+/// ```
+///
+/// After running `convert_out_params`:
+///
+/// ```ignore
+///     unsafe fn divmod(a: i32, b: i32) -> (i32, i32, i32) { ... }
+///
+///     let (rc, q, r) = divmod(17, 5);
+/// ```
+///
+/// A function that returned `()` returns just the outputs.  A function that returns
+/// `Result<(), E>`, as produced by `convert_to_result`, returns the outputs in its `Ok` value
+/// instead, and its error returns don't need to assign them.
+///
+/// Each call to the function must be a statement, `f(...);` or `let x = f(...);`, or
+/// `f(...)?;` for a function returning `Result`, and must pass `&mut x` for each
+/// out-parameter, where `x` is a local of the right type declared earlier in the same block,
+/// with no initializer or a simple one, and not used in between.  The declaration is removed and
+/// `x` is bound by the call statement instead.
+///
+/// A parameter is left alone, with a warning, if the function reads it, if some path returns
+/// without assigning through it first, or if some call passes something other than a fresh
+/// local for it.  Functions that are used as function pointers, trait methods, and
+/// `#[no_mangle]` functions are skipped entirely.
+pub struct ConvertOutParams {
+    pub label: Symbol,
+}
+
+/// A function whose out-parameters are being converted.
+struct OutParamFn {
+    /// The indices of the parameters being converted.
+    params: Vec<usize>,
+    ret: OutRet,
+}
+
+/// How a function returns its existing value, which determines how the outputs are added to it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OutRet {
+    /// The function returns some value `x`, and will return `(x, p1, p2)`.
+    Value,
+    /// The function returns `()`, and will return `(p1, p2)`.
+    Unit,
+    /// The function returns `Result<(), E>`, and will return `Ok((p1, p2))` on success.
+    Result,
+}
+
+impl Transform for ConvertOutParams {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+        let sess = cx.session();
+
+        // (1) Find the parameters that are only ever assigned through, and are assigned
+        // through on every path that returns.
+
+        let mut conv_fns: HashMap<DefId, OutParamFn> = HashMap::new();
+        let mut param_names: HashMap<DefId, (Ident, Vec<Ident>)> = HashMap::new();
+
+        visit_fns(krate, |fl| {
+            if !st.marked(fl.id, self.label) {
+                return;
+            }
+            let has_mut_ptr_params = fl.decl.inputs.iter().any(|param| match param.ty.kind {
+                ast::TyKind::Ptr(MutTy { mutbl: Mutability::Mutable, .. }) => true,
+                _ => false,
+            });
+            if !has_mut_ptr_params {
+                return;
+            }
+            let skip = |why: &str| {
+                sess.span_warn(fl.span, &format!("not converting out-parameters of `{}`: {}",
+                                                 fl.ident, why));
+            };
+
+            let did = cx.node_def_id(fl.id);
+            match fl.kind {
+                FnKind::Normal | FnKind::ImplMethod => {}
+                _ => return skip("it's a trait or foreign function"),
+            }
+            let trait_impl = tcx.impl_of_method(did).and_then(|i| tcx.trait_id_of_impl(i));
+            if trait_impl.is_some() {
+                return skip("it implements a trait method");
+            }
+            if attr::contains_name(&fl.attrs, sym::no_mangle) {
+                return skip("it's `#[no_mangle]`, so C code may call it");
+            }
+            let body = match_or!([fl.block] Some(ref b) => b; return);
+
+            let ret = match fl.decl.output {
+                FunctionRetTy::Default(_) => OutRet::Unit,
+                FunctionRetTy::Ty(ref ty) if is_unit_ty(ty) => OutRet::Unit,
+                FunctionRetTy::Ty(ref ty) if is_unit_result_ty(ty) => OutRet::Result,
+                FunctionRetTy::Ty(_) => OutRet::Value,
+            };
+            if ret == OutRet::Result {
+                let mut plain_results = true;
+                fold_output_exprs(&mut body.clone(), true, |e| {
+                    plain_results &= is_ok_unit(e) || is_call_to(e, "Err");
+                });
+                if !plain_results {
+                    return skip("it returns a `Result` that isn't `Ok(())` or `Err(..)`");
+                }
+            }
+
+            let mut params = Vec::new();
+            let mut names = Vec::new();
+            for (i, param) in fl.decl.inputs.iter().enumerate() {
+                match param.ty.kind {
+                    ast::TyKind::Ptr(MutTy { mutbl: Mutability::Mutable, .. }) => {}
+                    _ => continue,
+                }
+                let name = match_or!([param.pat.kind]
+                                     PatKind::Ident(BindingMode::ByValue(_), ident, None) => ident;
+                                     continue);
+                let local = cx.hir_map().node_to_hir_id(param.pat.id);
+                let why = if !only_written_through(cx, local, body) {
+                    "the function uses it other than by assigning through it"
+                } else if !written_on_exit(cx, local, body, ret == OutRet::Result) {
+                    "some path returns without assigning through it"
+                } else {
+                    params.push(i);
+                    names.push(name);
+                    continue;
+                };
+                sess.span_warn(param.pat.span, &format!(
+                    "not converting parameter `{}` of `{}`: {}", name, fl.ident, why));
+            }
+            if !params.is_empty() {
+                conv_fns.insert(did, OutParamFn { params, ret });
+                param_names.insert(did, (fl.ident, names));
+            }
+        });
+
+        // (2) Check the calls.  Each call must be a statement that passes fresh locals for the
+        // out-parameters, and the function mustn't be used as a function pointer.
+
+        let mut fresh_args = HashSet::new();
+        visit_nodes(krate, |b: &Block| {
+            for (idx, s) in b.stmts.iter().enumerate() {
+                let call = match_or!([stmt_call(s)] Some(x) => x; continue);
+                let callee = match_or!([cx.opt_callee(call)] Some(x) => x; continue);
+                let f = match_or!([conv_fns.get(&callee)] Some(x) => x; continue);
+                if !call_stmt_ok(s, f.ret) {
+                    continue;
+                }
+                let args = expect!([call.kind] ExprKind::Call(_, ref args) => args);
+                let sig = tcx.fn_sig(callee);
+                let inputs = sig.skip_binder().inputs();
+                for &i in &f.params {
+                    let pointee = expect!([inputs[i].kind] TyKind::RawPtr(mt) => mt.ty);
+                    if fresh_local(cx, &b.stmts, idx, &args[i], pointee).is_some() {
+                        fresh_args.insert((call.id, i));
+                    }
+                }
+            }
+        });
+
+        let mut direct_callees = HashSet::new();
+        let mut rejected: HashMap<DefId, HashSet<usize>> = HashMap::new();
+        visit_nodes(krate, |e: &Expr| {
+            match e.kind {
+                ExprKind::Call(ref callee, _) => {
+                    direct_callees.insert(callee.id);
+                    let did = match_or!([cx.opt_callee(e)] Some(x) => x; return);
+                    let f = match_or!([conv_fns.get(&did)] Some(x) => x; return);
+                    let bad = f.params.iter().filter(|&&i| !fresh_args.contains(&(e.id, i)));
+                    rejected.entry(did).or_insert_with(HashSet::new).extend(bad);
+                }
+                ExprKind::MethodCall(..) => {
+                    let did = match_or!([cx.opt_callee(e)] Some(x) => x; return);
+                    let f = match_or!([conv_fns.get(&did)] Some(x) => x; return);
+                    rejected.entry(did).or_insert_with(HashSet::new).extend(&f.params);
+                }
+                ExprKind::Path(..) if !direct_callees.contains(&e.id) => {
+                    let did = match_or!([cx.try_resolve_expr(e)] Some(x) => x; return);
+                    if conv_fns.remove(&did).is_some() {
+                        sess.span_warn(e.span, &format!(
+                            "not converting out-parameters of `{}`: it's used as a function \
+                             pointer",
+                            tcx.def_path_str(did)));
+                    }
+                }
+                _ => {}
+            }
+        });
+
+        let mut rejected = rejected.into_iter().collect::<Vec<_>>();
+        rejected.sort_by_key(|&(did, _)| tcx.def_span(did));
+        for (did, bad) in rejected {
+            let f = match_or!([conv_fns.get_mut(&did)] Some(x) => x; continue);
+            let (fn_name, ref names) = param_names[&did];
+            for (i, name) in f.params.iter().zip(names) {
+                if bad.contains(i) {
+                    sess.span_warn(name.span, &format!(
+                        "not converting parameter `{}` of `{}`: some call doesn't pass the \
+                         address of a fresh local for it",
+                        name, fn_name));
+                }
+            }
+            f.params.retain(|i| !bad.contains(i));
+            if f.params.is_empty() {
+                conv_fns.remove(&did);
+            }
+        }
+
+        if conv_fns.is_empty() {
+            return;
+        }
+
+        // (3) Rewrite the calls, binding the outputs in place of the old declarations.  This
+        // happens before the functions change, so that their parameter types are still known.
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            let mut removed = HashSet::new();
+            let mut new_stmts = HashMap::new();
+            for (idx, s) in b.stmts.iter().enumerate() {
+                let call = match_or!([stmt_call(s)] Some(x) => x; continue);
+                let callee = match_or!([cx.opt_callee(call)] Some(x) => x; continue);
+                let f = match_or!([conv_fns.get(&callee)] Some(x) => x; continue);
+                let sig = tcx.fn_sig(callee);
+                let inputs = sig.skip_binder().inputs();
+
+                let mut call = call.clone();
+                let mut out_pats = Vec::new();
+                {
+                    let args = expect!([call.kind] ExprKind::Call(_, ref mut args) => args);
+                    for &i in &f.params {
+                        let pointee = expect!([inputs[i].kind] TyKind::RawPtr(mt) => mt.ty);
+                        let decl_idx = fresh_local(cx, &b.stmts, idx, &args[i], pointee)
+                            .expect("out-parameter argument is no longer a fresh local");
+                        let local = expect!([b.stmts[decl_idx].kind] StmtKind::Local(ref l) => l);
+                        let ident = expect!([local.pat.kind] PatKind::Ident(_, ident, _) => ident);
+                        let var = cx.hir_map().node_to_hir_id(local.pat.id);
+                        let mutbl = if mutated_elsewhere(cx, var, b) {
+                            Mutability::Mutable
+                        } else {
+                            Mutability::Immutable
+                        };
+                        out_pats.push(mk().set_mutbl(mutbl).ident_pat(ident));
+                        removed.insert(decl_idx);
+                    }
+                    for &i in f.params.iter().rev() {
+                        args.remove(i);
+                    }
+                }
+
+                let (mut pats, init) = match (&s.kind, f.ret) {
+                    (StmtKind::Local(l), _) => (vec![l.pat.clone()], call),
+                    (_, OutRet::Value) => (vec![mk().wild_pat()], call),
+                    (_, OutRet::Unit) => (vec![], call),
+                    (StmtKind::Semi(e), OutRet::Result) => (vec![], try_expr(call, e.span)),
+                    _ => unreachable!(),
+                };
+                pats.extend(out_pats);
+                let pat = if pats.len() == 1 {
+                    pats.pop().unwrap()
+                } else {
+                    mk().tuple_pat(pats)
+                };
+                let local = mk().local(pat, None as Option<P<Ty>>, Some(init));
+                new_stmts.insert(idx, mk().span(s.span).local_stmt(P(local)));
+            }
+            if new_stmts.is_empty() {
+                return;
+            }
+            let stmts = b.stmts.drain(..).enumerate()
+                .filter(|&(idx, _)| !removed.contains(&idx))
+                .map(|(idx, s)| new_stmts.remove(&idx).unwrap_or(s))
+                .collect();
+            b.stmts = stmts;
+        });
+
+        // (4) Rewrite the functions.  Assignments through the parameters become assignments to
+        // locals, which are returned along with the old return value.
+
+        mut_visit_fns(krate, |fl| {
+            let f = match_or!([conv_fns.get(&cx.node_def_id(fl.id))] Some(x) => x; return);
+            let mut outs = Vec::new();
+            for &i in &f.params {
+                let param = &fl.decl.inputs[i];
+                let ident = expect!([param.pat.kind] PatKind::Ident(_, ident, _) => ident);
+                let ty = expect!([param.ty.kind] ast::TyKind::Ptr(ref mt) => mt.ty.clone());
+                outs.push((ident, cx.hir_map().node_to_hir_id(param.pat.id), ty));
+            }
+            let out_expr = || {
+                let mut exprs = outs.iter().map(|&(ident, _, _)| mk().ident_expr(ident))
+                    .collect::<Vec<_>>();
+                if exprs.len() == 1 {
+                    exprs.pop().unwrap()
+                } else {
+                    mk().tuple_expr(exprs)
+                }
+            };
+            let out_ty = || {
+                let mut tys = outs.iter().map(|&(_, _, ref ty)| ty.clone()).collect::<Vec<_>>();
+                if tys.len() == 1 {
+                    tys.pop().unwrap()
+                } else {
+                    mk().tuple_ty(tys)
+                }
+            };
+
+            let block = fl.block.as_mut().unwrap();
+            MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                if let ExprKind::Assign(ref mut lhs, _) = e.kind {
+                    let ptr = match_or!([lhs.kind] ExprKind::Unary(UnOp::Deref, ref p) => p.clone();
+                                        return);
+                    if outs.iter().any(|&(_, local, _)| is_local(cx, &ptr, local)) {
+                        *lhs = ptr;
+                    }
+                }
+            });
+
+            let output = match (&fl.decl.output, f.ret) {
+                (FunctionRetTy::Ty(ty), OutRet::Value) => {
+                    fold_output_exprs(block, true, |e| {
+                        let mut exprs = vec![e.clone()];
+                        exprs.extend(outs.iter().map(|&(ident, _, _)| mk().ident_expr(ident)));
+                        *e = mk().tuple_expr(exprs);
+                    });
+                    let mut tys = vec![ty.clone()];
+                    tys.extend(outs.iter().map(|&(_, _, ref ty)| ty.clone()));
+                    mk().tuple_ty(tys)
+                }
+                (_, OutRet::Unit) => {
+                    MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                        if let ExprKind::Ret(ref mut value) = e.kind {
+                            if value.is_none() {
+                                *value = Some(out_expr());
+                            }
+                        }
+                    });
+                    if let Some(s) = block.stmts.pop() {
+                        block.stmts.push(match s.kind {
+                            StmtKind::Expr(e) => mk().span(s.span).semi_stmt(e),
+                            _ => s,
+                        });
+                    }
+                    block.stmts.push(mk().expr_stmt(out_expr()));
+                    out_ty()
+                }
+                (FunctionRetTy::Ty(ty), OutRet::Result) => {
+                    fold_output_exprs(block, true, |e| {
+                        if is_ok_unit(e) {
+                            let args = expect!([e.kind] ExprKind::Call(_, ref mut args) => args);
+                            args[0] = out_expr();
+                        }
+                    });
+                    let mut ty = ty.clone();
+                    *result_ok_ty(&mut ty).unwrap() = out_ty();
+                    ty
+                }
+                (FunctionRetTy::Default(_), _) => unreachable!(),
+            };
+
+            let decls = outs.iter().map(|&(ident, _, ref ty)| {
+                let pat = mk().set_mutbl(Mutability::Mutable).ident_pat(ident);
+                mk().local_stmt(P(mk().local(pat, Some(ty.clone()), None as Option<P<Expr>>)))
+            }).collect::<Vec<_>>();
+            block.stmts.splice(0..0, decls);
+
+            fl.decl = fl.decl.clone().map(|decl| {
+                let mut inputs = decl.inputs;
+                for &i in f.params.iter().rev() {
+                    inputs.remove(i);
+                }
+                FnDecl {
+                    inputs,
+                    output: FunctionRetTy::Ty(output),
+                }
+            });
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+fn is_unit_ty(ty: &ast::Ty) -> bool {
+    match ty.kind {
+        ast::TyKind::Tup(ref tys) => tys.is_empty(),
+        _ => false,
+    }
+}
+
+/// If `ty` is the type `Result<T, E>`, get `T`.
+fn result_ok_ty(ty: &mut ast::Ty) -> Option<&mut P<ast::Ty>> {
+    let path = match_or!([ty.kind] ast::TyKind::Path(None, ref mut p) => p; return None);
+    let seg = path.segments.last_mut()?;
+    if seg.ident.name.as_str() != "Result" {
+        return None;
+    }
+    let args = match_or!([seg.args] Some(ref mut a) => a; return None);
+    let args = match_or!([**args] GenericArgs::AngleBracketed(ref mut a) => a; return None);
+    match args.args.first_mut() {
+        Some(GenericArg::Type(ty)) => Some(ty),
+        _ => None,
+    }
+}
+
+fn is_unit_result_ty(ty: &ast::Ty) -> bool {
+    result_ok_ty(&mut ty.clone()).map_or(false, |ok| is_unit_ty(ok))
+}
+
+/// Check whether `e` is a call to the single-segment path `name`, such as `Ok(x)`.
+fn is_call_to(e: &Expr, name: &str) -> bool {
+    let callee = match_or!([e.kind] ExprKind::Call(ref callee, _) => callee; return false);
+    match callee.kind {
+        ExprKind::Path(None, ref path) => {
+            path.segments.len() == 1 && path.segments[0].ident.name.as_str() == name
+        }
+        _ => false,
+    }
+}
+
+/// Check whether `e` is `Ok(())`.
+fn is_ok_unit(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Call(_, ref args) if is_call_to(e, "Ok") && args.len() == 1 => {
+            match args[0].kind {
+                ExprKind::Tup(ref es) => es.is_empty(),
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+fn is_local(cx: &RefactorCtxt, e: &Expr, local: hir::HirId) -> bool {
+    match e.kind {
+        ExprKind::Paren(ref e) => is_local(cx, e, local),
+        _ => cx.try_resolve_expr_to_hid(e) == Some(local),
+    }
+}
+
+fn try_expr(e: P<Expr>, span: Span) -> P<Expr> {
+    P(Expr {
+        id: DUMMY_NODE_ID,
+        kind: ExprKind::Try(e),
+        span,
+        attrs: ThinVec::new(),
+    })
+}
 
-        // We don't need any protection against infinite recursion here, because it doesn't make
-        // sense for `wrap` to call the function whose args we're changing.
-        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
-            let callee = match_or!([cx.opt_callee(&e)] Some(x) => x; return);
-            let mod_args = match_or!([mod_fns.get(&callee)] Some(x) => x; return);
-            let args: &mut [P<Expr>] = match e.kind {
-                ExprKind::Call(_, ref mut args) => args,
-                ExprKind::MethodCall(_, ref mut args) => args,
-                _ => panic!("expected Call or MethodCall"),
-            };
-            for &idx in mod_args {
-                let mut bnd = Bindings::new();
-                bnd.add("__old", args[idx].clone());
-                args[idx] = wrap.clone().subst(st, cx, &bnd);
-            }
-        });
+/// If the statement `s` is `f(...);`, `let x = f(...);` or `f(...)?;`, get the call.
+fn stmt_call(s: &Stmt) -> Option<&P<Expr>> {
+    let e = match s.kind {
+        StmtKind::Local(ref l) => l.init.as_ref()?,
+        StmtKind::Semi(ref e) => match e.kind {
+            ExprKind::Try(ref e) => e,
+            _ => e,
+        },
+        _ => return None,
+    };
+    match e.kind {
+        ExprKind::Call(..) => Some(e),
+        _ => None,
     }
+}
 
-    fn min_phase(&self) -> Phase {
-        Phase::Phase3
+/// Check whether `s`, a call statement recognized by `stmt_call`, uses the call's result in a way
+/// that still works once the outputs are added to it.
+fn call_stmt_ok(s: &Stmt, ret: OutRet) -> bool {
+    let is_try = match s.kind {
+        StmtKind::Semi(ref e) => matches!([e.kind] ExprKind::Try(..)),
+        _ => false,
+    };
+    match (&s.kind, ret) {
+        (StmtKind::Local(..), OutRet::Value) => true,
+        (StmtKind::Semi(..), OutRet::Value) | (StmtKind::Semi(..), OutRet::Unit) => !is_try,
+        (StmtKind::Semi(..), OutRet::Result) => is_try,
+        _ => false,
     }
 }
 
+/// If `arg`, an argument of the call in `stmts[idx]`, is `&mut x`, where `x` is a local of type
+/// `ty` declared earlier in `stmts` with no initializer or a simple one, and not used between
+/// its declaration and the call or elsewhere in the call, get the index of the declaration.
+fn fresh_local<'tcx>(cx: &RefactorCtxt<'_, 'tcx>, stmts: &[Stmt], idx: usize, arg: &Expr,
+                     ty: ty::Ty<'tcx>) -> Option<usize> {
+    let mut inner = arg;
+    while let ExprKind::Cast(ref e, _) | ExprKind::Paren(ref e) = inner.kind {
+        inner = e;
+    }
+    let var_expr = match_or!([inner.kind] ExprKind::AddrOf(_, Mutability::Mutable, ref e) => e;
+                             return None);
+    if cx.opt_node_type(var_expr.id) != Some(ty) {
+        return None;
+    }
+    let var = cx.try_resolve_expr_to_hid(var_expr)?;
+
+    let decl_idx = stmts[..idx].iter().position(|s| match s.kind {
+        StmtKind::Local(ref l) => cx.hir_map().node_to_hir_id(l.pat.id) == var,
+        _ => false,
+    })?;
+    let local = expect!([stmts[decl_idx].kind] StmtKind::Local(ref l) => l);
+    if !matches!([local.pat.kind] PatKind::Ident(BindingMode::ByValue(_), _, None)) {
+        return None;
+    }
+    if !local.init.as_ref().map_or(true, |e| is_simple_init(e)) {
+        return None;
+    }
 
-/// # `retype_return` Command
-///
-/// Usage: `retype_return NEW_TY WRAP UNWRAP`
-///
-/// Marks: `target`
-///
-/// For each function marked `target`, change the return type of the function to
-/// `NEW_TY`, and use `WRAP` and `UNWRAP` to convert values to and from the
-/// original type of the argument at call sites and within the function body.
-///
-/// `WRAP` should contain an expression placeholder `__old`, and should convert
-/// `__old` from the function's original return type to `NEW_TY`.
-/// `UNWRAP` should contain an expression placeholder `__new`, and should perform
-/// the opposite conversion.
-pub struct RetypeReturn {
-    pub new_ty: String,
-    pub wrap: String,
-    pub unwrap: String,
+    let mut uses = 0;
+    for s in &stmts[decl_idx + 1..=idx] {
+        visit_nodes(s, |e: &Expr| {
+            if cx.try_resolve_expr_to_hid(e) == Some(var) {
+                uses += 1;
+            }
+        });
+    }
+    if uses != 1 {
+        return None;
+    }
+    Some(decl_idx)
 }
 
-impl Transform for RetypeReturn {
-    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
-        // (1) Change argument types and rewrite function bodies.
+/// Check whether `e` is an initializer with no side effects, whose value can be discarded.
+fn is_simple_init(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Lit(..) | ExprKind::Path(..) => true,
+        ExprKind::Unary(UnOp::Neg, ref e) |
+        ExprKind::Cast(ref e, _) |
+        ExprKind::Paren(ref e) => is_simple_init(e),
+        ExprKind::Tup(ref es) | ExprKind::Array(ref es) => es.iter().all(|e| is_simple_init(e)),
+        ExprKind::Call(ref callee, ref args) if args.is_empty() => match callee.kind {
+            ExprKind::Path(None, ref path) => {
+                let name = path.segments.last().unwrap().ident.name.as_str();
+                name == "zeroed" || name == "uninitialized"
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
 
-        let new_ty = parse_ty(cx.session(), &self.new_ty);
-        let wrap = parse_expr(cx.session(), &self.wrap);
-        let unwrap = parse_expr(cx.session(), &self.unwrap);
+/// Check whether the local `var` is mutated in `b`, other than by passing `&mut var` to an
+/// out-parameter.
+fn mutated_elsewhere(cx: &RefactorCtxt, var: hir::HirId, b: &P<Block>) -> bool {
+    let mut count = 0;
+    fold_exprs_with_context(&mut b.clone(), |e, ctx| {
+        if let lr_expr::Context::LvalueMut = ctx {
+            if cx.try_resolve_expr_to_hid(e) == Some(var) {
+                count += 1;
+            }
+        }
+    });
+    count > 1
+}
 
-        // Modified functions, by DefId.
-        let mut mod_fns: HashSet<DefId> = HashSet::new();
+/// Check whether the raw pointer `local` is used in `body` only by assigning through it, as in
+/// `*local = x`.
+fn only_written_through(cx: &RefactorCtxt, local: hir::HirId, body: &Block) -> bool {
+    struct UseFinder<'a, 'b, 'tcx> {
+        cx: &'a RefactorCtxt<'b, 'tcx>,
+        local: hir::HirId,
+        found: bool,
+    }
 
-        mut_visit_fns(krate, |fl| {
-            if !st.marked(fl.id, "target") {
-                return;
+    impl<'a, 'b, 'tcx, 'ast> Visitor<'ast> for UseFinder<'a, 'b, 'tcx> {
+        fn visit_expr(&mut self, e: &'ast Expr) {
+            if let ExprKind::Assign(ref lhs, ref rhs) = e.kind {
+                if let ExprKind::Unary(UnOp::Deref, ref ptr) = lhs.kind {
+                    if is_local(self.cx, ptr, self.local) {
+                        self.visit_expr(rhs);
+                        return;
+                    }
+                }
+            }
+            if is_local(self.cx, e, self.local) {
+                self.found = true;
+            } else {
+                visit::walk_expr(self, e);
             }
+        }
 
-            // Change the return type annotation
-            fl.decl.output = FunctionRetTy::Ty(new_ty.clone());
+        fn visit_mac(&mut self, mac: &'ast Mac) {
+            visit::walk_mac(self, mac)
+        }
+    }
 
-            // Rewrite output expressions using `wrap`.
-            fl.block.as_mut().map(|b| fold_output_exprs(b, true, |e| {
-                let mut bnd = Bindings::new();
-                bnd.add("__old", e.clone());
-                *e = wrap.clone().subst(st, cx, &bnd);
-            }));
+    let mut v = UseFinder { cx, local, found: false };
+    v.visit_block(body);
+    !v.found
+}
 
-            mod_fns.insert(cx.node_def_id(fl.id));
-        });
+/// Check whether every path through `body` that returns assigns through the raw pointer `local`
+/// first.  If `returns_result` is set, paths that return an error, with `return Err(..)` or `?`,
+/// don't need to.
+///
+/// This is a simple structured analysis: a pointer counts as assigned after an `if` or `match`
+/// only if every branch assigns it, and never counts as assigned by a loop body.
+fn written_on_exit(cx: &RefactorCtxt, local: hir::HirId, body: &Block,
+                   returns_result: bool) -> bool {
+    struct WriteChecker<'a, 'b, 'tcx> {
+        cx: &'a RefactorCtxt<'b, 'tcx>,
+        local: hir::HirId,
+        returns_result: bool,
+        ok: bool,
+    }
 
-        // (2) Rewrite callsites of modified functions.
+    impl<'a, 'b, 'tcx> WriteChecker<'a, 'b, 'tcx> {
+        /// Check a return of `value`, which is `None` for an implicit `()`.
+        fn check_return(&mut self, value: Option<&Expr>, written: bool) {
+            let is_err = value.map_or(false, |e| is_call_to(e, "Err"));
+            if !written && !(self.returns_result && is_err) {
+                self.ok = false;
+            }
+        }
 
-        // We don't need any protection against infinite recursion here, because it doesn't make
-        // sense for `unwrap` to call the function whose args we're changing.
-        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
-            let callee = match_or!([cx.opt_callee(&e)] Some(x) => x; return);
-            if !mod_fns.contains(&callee) {
-                return;
+        /// Check the block `b`, starting with the pointer assigned or not according to
+        /// `written`, and return whether it's assigned at the end.  A block that never finishes
+        /// counts as assigning it.  If `trailing` is set, the value of the block is returned
+        /// from the function.
+        fn block(&mut self, b: &Block, mut written: bool, trailing: bool) -> bool {
+            let last = b.stmts.len().wrapping_sub(1);
+            for (i, s) in b.stmts.iter().enumerate() {
+                written = match s.kind {
+                    StmtKind::Local(ref l) => match l.init {
+                        Some(ref init) => self.expr(init, written, false),
+                        None => written,
+                    },
+                    StmtKind::Expr(ref e) => self.expr(e, written, trailing && i == last),
+                    StmtKind::Semi(ref e) => self.expr(e, written, false),
+                    StmtKind::Item(..) | StmtKind::Mac(..) => written,
+                };
             }
-            let mut bnd = Bindings::new();
-            bnd.add("__new", e.clone());
-            *e = unwrap.clone().subst(st, cx, &bnd)
-        });
-    }
+            let has_tail = b.stmts.last().map_or(false, |s| matches!([s.kind] StmtKind::Expr(..)));
+            if trailing && !has_tail {
+                self.check_return(None, written);
+            }
+            written
+        }
 
-    fn min_phase(&self) -> Phase {
-        Phase::Phase3
+        /// Like `block`, but for the expression `e`.
+        fn expr(&mut self, e: &Expr, written: bool, trailing: bool) -> bool {
+            let written = match e.kind {
+                ExprKind::Assign(ref lhs, ref rhs) => {
+                    let written = self.expr(rhs, written, false);
+                    match lhs.kind {
+                        ExprKind::Unary(UnOp::Deref, ref ptr) => {
+                            written || is_local(self.cx, ptr, self.local)
+                        }
+                        _ => written,
+                    }
+                }
+                ExprKind::Ret(ref value) => {
+                    let written = match *value {
+                        Some(ref value) => self.expr(value, written, false),
+                        None => written,
+                    };
+                    self.check_return(value.as_ref().map(|v| &**v), written);
+                    return true;
+                }
+                ExprKind::Try(ref inner) => {
+                    let written = self.expr(inner, written, false);
+                    if !written && !self.returns_result {
+                        self.ok = false;
+                    }
+                    written
+                }
+                ExprKind::If(ref cond, ref then, ref els) => {
+                    let written = self.expr(cond, written, false);
+                    let then_written = self.block(then, written, trailing);
+                    let els_written = match *els {
+                        Some(ref els) => self.expr(els, written, trailing),
+                        None => {
+                            if trailing {
+                                self.check_return(None, written);
+                            }
+                            written
+                        }
+                    };
+                    return then_written && els_written;
+                }
+                ExprKind::Match(ref scrutinee, ref arms) => {
+                    let written = self.expr(scrutinee, written, false);
+                    let mut all_written = true;
+                    for arm in arms {
+                        let arm_written = match arm.guard {
+                            Some(ref guard) => self.expr(guard, written, false),
+                            None => written,
+                        };
+                        all_written &= self.expr(&arm.body, arm_written, trailing);
+                    }
+                    return all_written;
+                }
+                ExprKind::Block(ref b, _) => return self.block(b, written, trailing),
+                ExprKind::Paren(ref inner) => return self.expr(inner, written, trailing),
+                ExprKind::While(ref cond, ref body, _) => {
+                    let written = self.expr(cond, written, false);
+                    self.block(body, written, false);
+                    written
+                }
+                ExprKind::ForLoop(_, ref iter, ref body, _) => {
+                    let written = self.expr(iter, written, false);
+                    self.block(body, written, false);
+                    written
+                }
+                ExprKind::Loop(ref body, _) => {
+                    self.block(body, written, false);
+                    written
+                }
+                ExprKind::Closure(..) => written,
+                _ => {
+                    if !written && may_return(e, self.returns_result) {
+                        self.ok = false;
+                    }
+                    written
+                }
+            };
+            if trailing {
+                self.check_return(Some(e), written);
+            }
+            written
+        }
     }
+
+    let mut v = WriteChecker { cx, local, returns_result, ok: true };
+    v.block(body, false, true);
+    v.ok
 }
 
+/// Check whether `e` contains a `return`, or a `?` unless `returns_result` is set, outside of
+/// any closure.
+fn may_return(e: &Expr, returns_result: bool) -> bool {
+    struct ReturnFinder {
+        returns_result: bool,
+        found: bool,
+    }
+
+    impl<'ast> Visitor<'ast> for ReturnFinder {
+        fn visit_expr(&mut self, e: &'ast Expr) {
+            match e.kind {
+                ExprKind::Ret(..) => self.found = true,
+                ExprKind::Try(..) if !self.returns_result => self.found = true,
+                ExprKind::Closure(..) => {}
+                _ => visit::walk_expr(self, e),
+            }
+        }
+
+        fn visit_mac(&mut self, mac: &'ast Mac) {
+            visit::walk_mac(self, mac)
+        }
+    }
+
+    let mut v = ReturnFinder { returns_result, found: false };
+    v.visit_expr(e);
+    v.found
+}
 
 /// # `retype_static` Command
 ///
-/// Usage: `retype_static NEW_TY REV_CONV_ASSIGN CONV_RVAL CONV_LVAL [CONV_LVAL_MUT]`
-///
 /// Marks: `target`
 ///
 /// For each static marked `target`, change the type of the static to `NEW_TY`,
@@ -561,8 +2139,6 @@ pub fn bitcast_retype<F>(st: &CommandState, cx: &RefactorCtxt, krate: &mut Crate
 
 /// # `bitcast_retype` Command
 ///
-/// Usage: `bitcast_retype PAT REPL`
-///
 /// Marks: may read marks depending on `PAT`
 ///
 /// For every type in the crate matching `PAT`, change the type to `REPL`.  `PAT`
@@ -604,8 +2180,6 @@ impl Transform for BitcastRetype {
 
 /// # `type_fix_rules` Command
 ///
-/// Usage: `type_fix_rules RULE...`
-///
 /// Attempts to fix type errors in the crate using the provided rules.  Each rule
 /// has the form `"ectx, actual_ty, expected_ty => cast_expr"`.
 ///
@@ -746,8 +2320,6 @@ impl<'a, 'tcx> IlltypedFolder<'tcx> for TypeFixRulesFolder<'a, 'tcx> {
 
 /// # `autoretype` Command
 ///
-/// Usage: `autoretype 'A: T'...`
-///
 /// Marks: `A`... (specified in command)
 ///
 /// Change the type of nodes with mark `A` to the new type `T`, propagating
@@ -761,21 +2333,19 @@ pub struct AutoRetype {
 }
 
 impl AutoRetype {
-    fn new(args: &[String]) -> Self {
+    fn new(args: &[String]) -> Result<Self, String> {
         let mut mark_types = HashMap::new();
         for arg in args {
             let words: Vec<&str> = arg.splitn(2, ':').collect();
             if words.len() != 2 {
-                // TODO: It would be nice to return a proper error to the user
-                // here rather than having to panic.
-                panic!("retype command requires each argument to be of the form \"LABEL: TYPE\"");
+                return Err(format!("expected `LABEL: TYPE`, got `{}`", arg));
             }
             mark_types.insert(words[0].to_string(), words[1].to_string());
         }
 
-        AutoRetype {
+        Ok(AutoRetype {
             mark_types
-        }
+        })
     }
 }
 
@@ -1399,35 +2969,340 @@ fn can_coerce<'a, 'tcx>(
     }
 }
 
+/// # `convert_libc_types` Command
+///
+/// Replace the primitive type aliases from `libc` and `std::os::raw`, like `libc::c_int`,
+/// `libc::size_t` and `libc::uint32_t`, with the Rust types they stand for, like `i32`, `usize`
+/// and `u32`.  This applies to item signatures, struct fields, locals, casts, and everywhere else
+/// a type is written.  Afterwards, casts that became redundant are removed, as
+/// `remove_redundant_casts keep_aliases` does.
+///
+/// Each alias is replaced with its definition on the target being compiled for, so the
+/// target-dependent aliases like `c_char`, `c_long` and `c_ulong` become whatever they are on
+/// that target.  An argument `ALIAS=TY` replaces `ALIAS` with `TY` instead, or leaves it alone if
+/// `TY` is `keep`, as in `c_long=keep`.
+///
+/// Types whose spelling matters to the C ABI are left alone: foreign items in `extern` blocks,
+/// the signatures of functions with a non-Rust ABI like `extern "C"`, `extern "C" fn` pointer
+/// types, and the fields of `#[repr(C)]` structs that such functions take or return by value.
+/// If `--aggressive` is passed, those are converted too.
+pub struct ConvertLibcTypes {
+    pub aggressive: bool,
+    pub overrides: HashMap<String, String>,
+}
+
+impl Command for ConvertLibcTypes {
+    fn run(&mut self, state: &mut RefactorState) {
+        state.transform_crate(Phase::Phase3, |st, cx| {
+            let ffi_structs = if self.aggressive {
+                HashSet::new()
+            } else {
+                ffi_structs(&st.krate(), cx)
+            };
+            let mut folder = LibcTypeFolder {
+                cx,
+                aggressive: self.aggressive,
+                overrides: &self.overrides,
+                ffi_structs,
+            };
+            st.map_krate(|krate| {
+                krate.visit(&mut folder)
+            });
+        }).expect("Failed to run compiler");
+
+        let casts = RemoveRedundantCasts {
+            fold_char_lits: false,
+            keep_aliases: true,
+            wrap_negative_lits: false,
+            only_marked: false,
+            report_path: None,
+        };
+        state.transform_crate(casts.min_phase(), |st, cx| {
+            casts.transform(&mut *st.krate_mut(), st, cx)
+        }).expect("Failed to run compiler");
+    }
+}
+
+fn is_rust_abi(header: &FnHeader) -> bool {
+    match header.ext {
+        Extern::None => true,
+        _ => false,
+    }
+}
+
+fn is_repr_c(attrs: &[Attribute]) -> bool {
+    attrs.iter().filter(|attr| attr.check_name(sym::repr)).any(|attr| {
+        attr.meta_item_list().map_or(false, |items| items.iter().any(|i| i.check_name(sym::C)))
+    })
+}
+
+/// Find the `#[repr(C)]` structs and unions that are passed by value to or from foreign
+/// functions and functions with a non-Rust ABI.
+fn ffi_structs(krate: &Crate, cx: &RefactorCtxt) -> HashSet<DefId> {
+    let mut by_value = HashSet::new();
+    let mut add_sig = |decl: &FnDecl| {
+        let output = match decl.output {
+            FunctionRetTy::Ty(ref ty) => Some(ty),
+            FunctionRetTy::Default(_) => None,
+        };
+        for ty in decl.inputs.iter().map(|param| &param.ty).chain(output) {
+            if let Some(did) = cx.try_resolve_ty(ty) {
+                by_value.insert(did);
+            }
+        }
+    };
+    visit_nodes(krate, |i: &ForeignItem| {
+        if let ForeignItemKind::Fn(ref decl, _) = i.kind {
+            add_sig(decl);
+        }
+    });
+    visit_nodes(krate, |i: &Item| {
+        match i.kind {
+            ItemKind::Fn(ref sig, _, _) if !is_rust_abi(&sig.header) => add_sig(&sig.decl),
+            _ => {}
+        }
+    });
+    visit_nodes(krate, |i: &ImplItem| {
+        match i.kind {
+            ImplItemKind::Method(ref sig, _) if !is_rust_abi(&sig.header) => add_sig(&sig.decl),
+            _ => {}
+        }
+    });
+
+    let mut structs = HashSet::new();
+    visit_nodes(krate, |i: &Item| {
+        match i.kind {
+            ItemKind::Struct(..) | ItemKind::Union(..) => {
+                let did = cx.node_def_id(i.id);
+                if by_value.contains(&did) && is_repr_c(&i.attrs) {
+                    structs.insert(did);
+                }
+            }
+            _ => {}
+        }
+    });
+    structs
+}
+
+struct LibcTypeFolder<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    aggressive: bool,
+    overrides: &'a HashMap<String, String>,
+    /// The structs and unions whose fields are left alone.
+    ffi_structs: HashSet<DefId>,
+}
+
+impl<'a, 'b, 'tcx> LibcTypeFolder<'a, 'b, 'tcx> {
+    /// If `ty` names one of the primitive type aliases from `libc`, get the type to replace it
+    /// with.
+    fn replacement(&self, ty: &ast::Ty) -> Option<P<ast::Ty>> {
+        let tcx = self.cx.ty_ctxt();
+        let did = match_or!([self.cx.try_resolve_ty_hir(ty)]
+                            Some(Res::Def(DefKind::TyAlias, did)) => did; return None);
+        let in_libc = tcx.crate_name(did.krate).as_str() == "libc" ||
+            tcx.def_path_str(did).contains("::os::raw::");
+        if !in_libc {
+            return None;
+        }
+
+        let new_ty = match self.overrides.get(&*tcx.item_name(did).as_str()) {
+            Some(new_ty) if new_ty == "keep" => return None,
+            Some(new_ty) => new_ty.clone(),
+            None => {
+                let alias_ty = tcx.type_of(did);
+                match alias_ty.kind {
+                    TyKind::Bool | TyKind::Char | TyKind::Int(_) | TyKind::Uint(_) |
+                    TyKind::Float(_) => alias_ty.to_string(),
+                    _ => return None,
+                }
+            }
+        };
+        Some(mk().span(ty.span).ident_ty(new_ty))
+    }
+
+}
+
+impl<'a, 'b, 'tcx> MutVisitor for LibcTypeFolder<'a, 'b, 'tcx> {
+    fn visit_ty(&mut self, ty: &mut P<ast::Ty>) {
+        if let Some(new_ty) = self.replacement(ty) {
+            *ty = new_ty;
+            return;
+        }
+        if let ast::TyKind::BareFn(ref f) = ty.kind {
+            if !self.aggressive && !matches!([f.ext] Extern::None) {
+                return;
+            }
+        }
+        mut_visit::noop_visit_ty(ty, self);
+    }
+
+    fn flat_map_foreign_item(&mut self, i: ForeignItem) -> SmallVec<[ForeignItem; 1]> {
+        if self.aggressive {
+            mut_visit::noop_flat_map_foreign_item(i, self)
+        } else {
+            smallvec![i]
+        }
+    }
+
+    fn flat_map_item(&mut self, i: P<Item>) -> SmallVec<[P<Item>; 1]> {
+        if !self.aggressive {
+            match i.kind {
+                ItemKind::Struct(..) | ItemKind::Union(..)
+                        if self.ffi_structs.contains(&self.cx.node_def_id(i.id)) => {
+                    return smallvec![i];
+                }
+                // Convert the body, but keep the signature.
+                ItemKind::Fn(ref sig, _, _) if !is_rust_abi(&sig.header) => {
+                    let decl = sig.decl.clone();
+                    let mut items = mut_visit::noop_flat_map_item(i, self);
+                    for i in &mut items {
+                        if let ItemKind::Fn(ref mut sig, _, _) = i.kind {
+                            sig.decl = decl.clone();
+                        }
+                    }
+                    return items;
+                }
+                _ => {}
+            }
+        }
+        mut_visit::noop_flat_map_item(i, self)
+    }
+
+    fn flat_map_impl_item(&mut self, i: ImplItem) -> SmallVec<[ImplItem; 1]> {
+        if !self.aggressive {
+            if let ImplItemKind::Method(ref sig, _) = i.kind {
+                if !is_rust_abi(&sig.header) {
+                    let decl = sig.decl.clone();
+                    let mut items = mut_visit::noop_flat_map_impl_item(i, self);
+                    for i in &mut items {
+                        if let ImplItemKind::Method(ref mut sig, _) = i.kind {
+                            sig.decl = decl.clone();
+                        }
+                    }
+                    return items;
+                }
+            }
+        }
+        mut_visit::noop_flat_map_impl_item(i, self)
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("retype_argument", |args| mk(RetypeArgument {
-        new_ty: args[0].clone(),
-        wrap: args[1].clone(),
-        unwrap: args[2].clone(),
-    }));
-
-    reg.register("retype_return", |args| mk(RetypeReturn {
-        new_ty: args[0].clone(),
-        wrap: args[1].clone(),
-        unwrap: args[2].clone(),
-    }));
-
-    reg.register("retype_static", |args| mk(RetypeStatic {
-        new_ty: args[0].clone(),
-        rev_conv_assign: args[1].clone(),
-        conv_rval: args[2].clone(),
-        conv_lval: args[3].clone(),
-        conv_lval_mut: args.get(4).cloned(),
-    }));
-
-    reg.register("bitcast_retype", |args| mk(BitcastRetype {
-        pat: args[0].clone(),
-        repl: args[1].clone(),
-    }));
-
-    reg.register("type_fix_rules", |args| Box::new(TypeFixRules { rules: args.to_owned() }));
-
-    reg.register("autoretype", |args| Box::new(AutoRetype::new(args)));
+    let spec = CommandSpec::new("Change the type of each argument marked `target` to NEW_TY.")
+        .arg("NEW_TY", ArgType::Str, "the new type")
+        .arg("WRAP", ArgType::Str, "converts a value of the old type into NEW_TY")
+        .arg("UNWRAP", ArgType::Str, "converts a value of NEW_TY back into the old type");
+    reg.register("retype_argument", spec, |args| {
+        Ok(mk(RetypeArgument {
+            new_ty: args.str("NEW_TY").to_owned(),
+            wrap: args.str("WRAP").to_owned(),
+            unwrap: args.str("UNWRAP").to_owned(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Change the return type of functions marked `target` to NEW_TY.")
+        .arg("NEW_TY", ArgType::Str, "the new type")
+        .arg("WRAP", ArgType::Str, "converts a value of the old type into NEW_TY")
+        .arg("UNWRAP", ArgType::Str, "converts a value of NEW_TY back into the old type");
+    reg.register("retype_return", spec, |args| {
+        Ok(mk(RetypeReturn {
+            new_ty: args.str("NEW_TY").to_owned(),
+            wrap: args.str("WRAP").to_owned(),
+            unwrap: args.str("UNWRAP").to_owned(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Change raw pointer parameters of functions marked MARK to refs.")
+        .optional_arg("MARK", ArgType::Str, Some("target"), "the mark of the functions");
+    reg.register("convert_ptr_args_to_refs", spec, |args| {
+        Ok(mk(ConvertPtrArgsToRefs {
+            label: args.str("MARK").into_symbol(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Replace pointer and length parameters with a slice parameter.")
+        .optional_arg("PTR", ArgType::Str, None, "the name of the pointer parameter")
+        .optional_arg("LEN", ArgType::Str, None, "the name of the length parameter");
+    reg.register("convert_ptr_len_to_slice", spec, |args| {
+        let names = match (args.opt_str("PTR"), args.opt_str("LEN")) {
+            (Some(ptr), Some(len)) => Some((ptr.into_symbol(), len.into_symbol())),
+            (None, None) => None,
+            _ => return Err("PTR and LEN must be given together".to_owned()),
+        };
+        Ok(mk(ConvertPtrLenToSlice { names }))
+    });
+
+    let spec = CommandSpec::new("Turn the out-parameters of functions marked MARK into results.")
+        .optional_arg("MARK", ArgType::Str, Some("target"), "the mark of the functions");
+    reg.register("convert_out_params", spec, |args| {
+        Ok(mk(ConvertOutParams {
+            label: args.str("MARK").into_symbol(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Change the type of each static marked `target` to NEW_TY.")
+        .arg("NEW_TY", ArgType::Str, "the new type")
+        .arg("REV_CONV_ASSIGN", ArgType::Str, "converts an assigned value into NEW_TY")
+        .arg("CONV_RVAL", ArgType::Str, "converts the static into the old type when read")
+        .arg("CONV_LVAL", ArgType::Str, "converts the static into an old-type place")
+        .optional_arg(
+            "CONV_LVAL_MUT",
+            ArgType::Str,
+            None,
+            "converts the static into a mutable old-type place",
+        );
+    reg.register("retype_static", spec, |args| {
+        Ok(mk(RetypeStatic {
+            new_ty: args.str("NEW_TY").to_owned(),
+            rev_conv_assign: args.str("REV_CONV_ASSIGN").to_owned(),
+            conv_rval: args.str("CONV_RVAL").to_owned(),
+            conv_lval: args.str("CONV_LVAL").to_owned(),
+            conv_lval_mut: args.opt_str("CONV_LVAL_MUT").map(|s| s.to_owned()),
+        }))
+    });
+
+    let spec = CommandSpec::new("Change every type matching PAT to REPL, adding transmutes.")
+        .arg("PAT", ArgType::Str, "the type pattern to match")
+        .arg("REPL", ArgType::Str, "the replacement type");
+    reg.register("bitcast_retype", spec, |args| {
+        Ok(mk(BitcastRetype {
+            pat: args.str("PAT").to_owned(),
+            repl: args.str("REPL").to_owned(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Try to fix type errors in the crate using the given rules.")
+        .rest("RULE", "a rule of the form `ectx, actual_ty, expected_ty => cast_expr`");
+    reg.register("type_fix_rules", spec, |args| {
+        Ok(Box::new(TypeFixRules { rules: args.list("RULE").to_owned() }))
+    });
+
+    let spec = CommandSpec::new("Change the type of nodes marked A to T, propagating the change.")
+        .rest("A: T", "a mark and the new type of the nodes bearing it");
+    reg.register("autoretype", spec, |args| {
+        Ok(Box::new(AutoRetype::new(args.list("A: T"))?))
+    });
+
+    let spec = CommandSpec::new("Replace `libc` type aliases with the Rust types they stand for.")
+        .flag("--aggressive", "also convert types whose spelling matters to the C ABI")
+        .rest("ALIAS=TY", "replace ALIAS with TY, or leave it alone if TY is `keep`");
+    reg.register("convert_libc_types", spec, |args| {
+        let mut overrides = HashMap::new();
+        for arg in args.list("ALIAS=TY") {
+            let eq = arg
+                .find('=')
+                .ok_or_else(|| format!("expected `ALIAS=TY`, got `{}`", arg))?;
+            overrides.insert(arg[..eq].to_owned(), arg[eq + 1..].to_owned());
+        }
+        Ok(Box::new(ConvertLibcTypes {
+            aggressive: args.flag("--aggressive"),
+            overrides,
+        }))
+    });
 }
@@ -1,10 +1,13 @@
-use syntax::ast::Crate;
+use syntax::ast::{Crate, Item};
+use syntax::ptr::P;
 use syntax::symbol::Symbol;
 
 use crate::command::{CommandState, Registry};
+use crate::command_args::{ArgType, CommandSpec};
 use crate::contains_mark::contains_mark;
 use crate::driver::Phase;
-use crate::matcher::{MatchCtxt, Subst, mut_visit_match_with};
+use crate::matcher::{MatchCtxt, Subst, mut_visit_match_with, mut_visit_match_rules_with};
+use crate::matcher::mut_visit_stmts_match_with;
 use crate::transform::Transform;
 use c2rust_ast_builder::IntoSymbol;
 use crate::RefactorCtxt;
@@ -12,8 +15,6 @@ use crate::RefactorCtxt;
 
 /// # `rewrite_expr` Command
 /// 
-/// Usage: `rewrite_expr PAT REPL [FILTER]`
-/// 
 /// Marks: reads `FILTER`, if set; may read other marks depending on `PAT`
 /// 
 /// For every expression in the crate matching `PAT`, replace it with `REPL`.
@@ -22,6 +23,10 @@ use crate::RefactorCtxt;
 /// placeholders to substitute in the captured nodes.  See the `matcher` module for
 /// details on AST pattern matching.
 /// 
+/// `REPL` can also use `ty_of!($x)` and `pointee_of!($x)` in type positions, to
+/// refer to the type of a captured expression (see `matcher::subst`).  A match is
+/// left unchanged if the type can't be written in Rust syntax.
+/// 
 /// If `FILTER` is provided, only expressions marked `FILTER` will be rewritten.
 /// This usage is obsolete - change `PAT` to `marked!(PAT, FILTER)` to get the same
 /// behavior.
@@ -62,7 +67,50 @@ impl Transform for RewriteExpr {
                 }
             }
 
-            *ast = repl.clone().subst(st, cx, &mcx.bindings);
+            if let Some(new) = repl.clone().try_subst(st, cx, &mcx.bindings) {
+                *ast = new;
+            }
+        })
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `rewrite_exprs` Command
+///
+/// Marks: may read marks depending on the `PAT`s
+///
+/// Apply several `rewrite_expr` rules in a single pass over the crate.  At each
+/// expression, the rules are tried in the order given, and the first `PAT` that
+/// matches is replaced with its `REPL`.  The rules after it are then tried on the
+/// replacement, so `rewrite_exprs A B B C` rewrites `A` to `C`, like running
+/// `rewrite_expr A B` followed by `rewrite_expr B C`.  With `--no-chain`, each
+/// expression is rewritten by at most one rule.
+///
+/// This is faster than running `rewrite_expr` once per rule, since the crate is
+/// only traversed and type-checked once.  Rules are never tried on an expression
+/// produced by a later rule, and patterns that check types, like `typed!`, only
+/// match expressions that were present before the command started.  The rules
+/// share one set of binding types, so a placeholder name must be used with the
+/// same type in every rule.
+pub struct RewriteExprs {
+    pub rules: Vec<(String, String)>,
+    pub chain: bool,
+}
+
+impl Transform for RewriteExprs {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let (pats, repls): (Vec<_>, Vec<_>) = self.rules.iter()
+            .map(|&(ref pat, ref repl)| (mcx.parse_expr(pat), mcx.parse_expr(repl)))
+            .unzip();
+        mut_visit_match_rules_with(mcx, pats, self.chain, krate, |ast, i, mcx| {
+            if let Some(new) = repls[i].clone().try_subst(st, cx, &mcx.bindings) {
+                *ast = new;
+            }
         })
     }
 
@@ -74,8 +122,6 @@ impl Transform for RewriteExpr {
 
 /// # `rewrite_ty` Command
 /// 
-/// Usage: `rewrite_ty PAT REPL [FILTER]`
-/// 
 /// Marks: reads `FILTER`, if set; may read other marks depending on `PAT`
 /// 
 /// For every type in the crate matching `PAT`, replace it with `REPL`.  `PAT` and
@@ -108,7 +154,109 @@ impl Transform for RewriteTy {
                 }
             }
 
+            if let Some(new) = repl.clone().try_subst(st, cx, &mcx.bindings) {
+                *ast = new;
+            }
+        })
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `rewrite_pat` Command
+///
+/// Marks: may read marks depending on `PAT`
+///
+/// For every pattern in the crate matching `PAT`, replace it with `REPL`.  This
+/// includes the patterns of `match` arms, `let` statements, closure and function
+/// parameters, and the subpatterns inside them.  `PAT` and `REPL` are both Rust
+/// patterns.  `PAT` can use placeholders to capture nodes from the matched AST,
+/// and `REPL` can refer to those same placeholders to substitute in the captured
+/// nodes.  See the `matcher` module for details on AST pattern matching.
+///
+/// Example:
+///
+/// ```ignore
+///     match x {
+///         Some(ref y) => println!("{}", y),
+///         None => {}
+///     }
+/// ```
+///
+/// After running `rewrite_pat 'ref $y:Ident' '$y'`:
+///
+/// ```ignore
+///     match x {
+///         Some(y) => println!("{}", y),
+///         None => {}
+///     }
+/// ```
+pub struct RewritePat {
+    pub pat: String,
+    pub repl: String,
+}
+
+impl Transform for RewritePat {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let pat = mcx.parse_pat(&self.pat);
+        let repl = mcx.parse_pat(&self.repl);
+        mut_visit_match_with(mcx, pat, krate, |ast, mcx| {
+            if let Some(new) = repl.clone().try_subst(st, cx, &mcx.bindings) {
+                *ast = new;
+            }
+        })
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `rewrite_attr` Command
+///
+/// Marks: may read marks depending on `PAT`
+///
+/// For every attribute in the crate matching `PAT`, replace it with `REPL`.  `PAT` and `REPL`
+/// are both outer attributes, like `#[export_name = $name:Lit]`.  In the arguments of an
+/// attribute, `$x` captures an identifier and `$x:Lit` captures a literal, and `#[$a:Attr]`
+/// captures a whole attribute.  Doc comments are never matched.  See the `matcher` module for
+/// details on AST pattern matching.
+///
+/// Use `remove_attr` to delete attributes instead of replacing them.
+///
+/// Example:
+///
+/// ```ignore
+///     #[deprecated(note = "use bar instead")]
+///     pub fn foo() {}
+/// ```
+///
+/// After running `rewrite_attr '#[deprecated(note = $n:Lit)]' '#[deprecated(since = "0.2", note = $n)]'`:
+///
+/// ```ignore
+///     #[deprecated(since = "0.2", note = "use bar instead")]
+///     pub fn foo() {}
+/// ```
+pub struct RewriteAttr {
+    pub pat: String,
+    pub repl: String,
+}
+
+impl Transform for RewriteAttr {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let pat = mcx.parse_attr(&self.pat);
+        let repl = mcx.parse_attr(&self.repl);
+        mut_visit_match_with(mcx, pat, krate, |ast, mcx| {
+            let id = ast.id;
             *ast = repl.clone().subst(st, cx, &mcx.bindings);
+            // Keep the old `AttrId`, so the rewriter replaces the attribute in place.
+            ast.id = id;
         })
     }
 
@@ -118,9 +266,55 @@ impl Transform for RewriteTy {
 }
 
 
-/// # `rewrite_stmts` Command
+/// # `rewrite_item` Command
+///
+/// Marks: may read marks depending on `PAT`
 ///
-/// Usage: `rewrite_stmts PAT REPL`
+/// For every item in the crate matching `PAT`, replace it with `REPL`.  `PAT` and
+/// `REPL` are both single Rust items.  Impl items, trait items, and foreign items
+/// are never matched.  See the `matcher` module for details on AST pattern
+/// matching.
+///
+/// Function patterns can capture qualifiers with `$q:FnQual`, in the place of
+/// the `extern "ABI"`.  For example, to make the `unsafe extern "C"` functions
+/// taking an `i32` safe, keeping their ABI:
+///
+/// ```ignore
+///     rewrite_item
+///         'unsafe $q:FnQual fn $f:Ident($x:Pat: i32) -> $r:Ty { $b:MultiStmt }'
+///         '$q:FnQual fn $f($x: i32) -> $r { $b }'
+/// ```
+///
+/// Qualifiers written in `REPL` are added to the ones captured in `$q`.
+pub struct RewriteItem {
+    pub pat: String,
+    pub repl: String,
+}
+
+impl Transform for RewriteItem {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut mcx = MatchCtxt::new(st, cx);
+        let pat = single_item(mcx.parse_items(&self.pat), "PAT");
+        let repl = single_item(mcx.parse_items(&self.repl), "REPL");
+        mut_visit_match_with(mcx, pat, krate, |ast, mcx| {
+            if let Some(new) = repl.clone().try_subst(st, cx, &mcx.bindings) {
+                *ast = new;
+            }
+        })
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+fn single_item(mut items: Vec<P<Item>>, what: &str) -> P<Item> {
+    assert!(items.len() == 1, "rewrite_item: {} must be a single item", what);
+    items.pop().unwrap()
+}
+
+
+/// # `rewrite_stmts` Command
 ///
 /// For every statement sequence in the crate matching `PAT`, replace it with `REPL`.  `PAT` and
 /// `REPL` are both Rust statement sequences.  `PAT` can use placeholders to capture nodes from
@@ -128,6 +322,11 @@ impl Transform for RewriteTy {
 /// in the captured nodes.  See the `matcher` module for details on AST pattern
 /// matching.
 ///
+/// `PAT` matches any window of consecutive statements inside a block, and `REPL`
+/// can have a different number of statements than `PAT`.  Use `$s:MultiStmt` (or
+/// `$s:Stmt*`) to capture a variable number of statements, e.g., `let mut $v:Ident =
+/// Vec::new(); $s:Stmt*; return $v;`.
+///
 /// See the documentation for `rewrite_expr` for an example of this style of
 /// rewriting.
 pub struct RewriteStmts {
@@ -140,8 +339,10 @@ impl Transform for RewriteStmts {
         let mut mcx = MatchCtxt::new(st, cx);
         let pat = mcx.parse_stmts(&self.pat);
         let repl = mcx.parse_stmts(&self.repl);
-        mut_visit_match_with(mcx, pat, krate, |ast, mcx| {
-            *ast = repl.clone().subst(st, cx, &mcx.bindings);
+        mut_visit_stmts_match_with(mcx, pat, krate, |ast, mcx| {
+            if let Some(new) = repl.clone().try_subst(st, cx, &mcx.bindings) {
+                *ast = new;
+            }
         })
     }
 
@@ -176,22 +377,89 @@ impl Transform for DebugMatchExpr {
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("rewrite_expr", |args| mk(RewriteExpr {
-        pat: args[0].clone(),
-        repl: args[1].clone(),
-        filter: if args.len() >= 3 { Some((&args[2]).into_symbol()) } else { None },
-    }));
-    reg.register("rewrite_ty", |args| mk(RewriteTy {
-        pat: args[0].clone(),
-        repl: args[1].clone(),
-        filter: if args.len() >= 3 { Some((&args[2]).into_symbol()) } else { None },
-    }));
-    reg.register("rewrite_stmts", |args| mk(RewriteStmts {
-        pat: args[0].clone(),
-        repl: args[1].clone(),
-    }));
-
-    reg.register("debug_match_expr", |args| mk(DebugMatchExpr {
-        pat: args[0].clone(),
-    }));
+    let spec = CommandSpec::new("Replace every expression matching PAT with REPL.")
+        .arg("PAT", ArgType::Str, "the pattern to match")
+        .arg("REPL", ArgType::Str, "the replacement")
+        .optional_arg("FILTER", ArgType::Str, None, "only rewrite nodes bearing this mark");
+    reg.register("rewrite_expr", spec, |args| {
+        Ok(mk(RewriteExpr {
+            pat: args.str("PAT").to_owned(),
+            repl: args.str("REPL").to_owned(),
+            filter: args.opt_str("FILTER").map(|x| x.into_symbol()),
+        }))
+    });
+
+    let spec = CommandSpec::new("Apply several `rewrite_expr` rules in a single pass.")
+        .flag("--no-chain", "don't try the later rules on a replacement")
+        .rest("PAT REPL", "the rules, as pairs of a pattern and its replacement");
+    reg.register("rewrite_exprs", spec, |args| {
+        let rules = args.list("PAT REPL");
+        if rules.is_empty() || rules.len() % 2 != 0 {
+            return Err("expected pairs of PAT and REPL arguments".to_owned());
+        }
+        Ok(mk(RewriteExprs {
+            rules: rules.chunks(2).map(|r| (r[0].clone(), r[1].clone())).collect(),
+            chain: !args.flag("--no-chain"),
+        }))
+    });
+
+    let spec = CommandSpec::new("Replace every type matching PAT with REPL.")
+        .arg("PAT", ArgType::Str, "the pattern to match")
+        .arg("REPL", ArgType::Str, "the replacement")
+        .optional_arg("FILTER", ArgType::Str, None, "only rewrite nodes bearing this mark");
+    reg.register("rewrite_ty", spec, |args| {
+        Ok(mk(RewriteTy {
+            pat: args.str("PAT").to_owned(),
+            repl: args.str("REPL").to_owned(),
+            filter: args.opt_str("FILTER").map(|x| x.into_symbol()),
+        }))
+    });
+
+    let spec = CommandSpec::new("Replace every pattern matching PAT with REPL.")
+        .arg("PAT", ArgType::Str, "the pattern to match")
+        .arg("REPL", ArgType::Str, "the replacement");
+    reg.register("rewrite_pat", spec, |args| {
+        Ok(mk(RewritePat {
+            pat: args.str("PAT").to_owned(),
+            repl: args.str("REPL").to_owned(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Replace every attribute matching PAT with REPL.")
+        .arg("PAT", ArgType::Str, "the pattern to match")
+        .arg("REPL", ArgType::Str, "the replacement");
+    reg.register("rewrite_attr", spec, |args| {
+        Ok(mk(RewriteAttr {
+            pat: args.str("PAT").to_owned(),
+            repl: args.str("REPL").to_owned(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Replace every item matching PAT with REPL.")
+        .arg("PAT", ArgType::Str, "the pattern to match")
+        .arg("REPL", ArgType::Str, "the replacement");
+    reg.register("rewrite_item", spec, |args| {
+        Ok(mk(RewriteItem {
+            pat: args.str("PAT").to_owned(),
+            repl: args.str("REPL").to_owned(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Replace every statement sequence matching PAT with REPL.")
+        .arg("PAT", ArgType::Str, "the pattern to match")
+        .arg("REPL", ArgType::Str, "the replacement");
+    reg.register("rewrite_stmts", spec, |args| {
+        Ok(mk(RewriteStmts {
+            pat: args.str("PAT").to_owned(),
+            repl: args.str("REPL").to_owned(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Log the matching of PAT against every expression, for debugging.")
+        .arg("PAT", ArgType::Str, "the pattern to match");
+    reg.register("debug_match_expr", spec, |args| {
+        Ok(mk(DebugMatchExpr {
+            pat: args.str("PAT").to_owned(),
+        }))
+    });
 }
@@ -1,17 +1,34 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::HashSet;
+use std::iter;
 use std::mem;
+use rustc::hir::def::{DefKind, Res};
 use rustc::hir::def_id::DefId;
+use rustc::hir::{HirId, CRATE_HIR_ID};
+use rustc::ty::{self, ParamEnv};
 use syntax::ast::*;
+use syntax::attr;
+use syntax::mut_visit::{self, MutVisitor};
+use syntax::print::pprust;
 use syntax::ptr::P;
 use syntax::symbol::Symbol;
-use smallvec::smallvec;
+use syntax::visit::{self, Visitor};
+use syntax_pos::{sym, Span};
+use smallvec::{smallvec, SmallVec};
 
-use crate::ast_manip::{FlatMapNodes, MutVisitNodes, fold_modules};
-use crate::ast_manip::fn_edit::mut_visit_fns;
+use crate::ast_manip::{FlatMapNodes, MutVisit, MutVisitNodes, fold_modules, visit_nodes};
+use crate::ast_manip::fn_edit::{FnKind, mut_visit_fns, visit_fns};
+use crate::ast_manip::util::{is_export_attr, is_exported};
 use crate::command::{CommandState, Registry};
-use crate::driver::{parse_expr};
+use crate::command_args::{ArgType, CommandSpec};
+use crate::driver::{Phase, parse_expr, parse_items};
 use crate::matcher::{Bindings, BindingType, MatchCtxt, Subst, mut_visit_match_with};
 use crate::path_edit::fold_resolved_paths;
+use crate::reflect::reflect_tcx_ty;
+use crate::transform::funcs::{bound_names, canonicalize_paths, needs_unsafe, remove_unsafe_block,
+                               use_tree_names};
+use crate::transform::heap::{strip_casts, type_implements};
+use crate::transform::vars::local_writes;
 use crate::transform::Transform;
 use c2rust_ast_builder::{mk, IntoSymbol};
 use crate::util::dataflow;
@@ -20,8 +37,6 @@ use crate::RefactorCtxt;
 
 /// # `static_collect_to_struct` Command
 ///
-/// Usage: `static_collect_to_struct STRUCT VAR`
-///
 /// Marks: `target`
 ///
 /// Collect marked statics into a single static struct.
@@ -159,8 +174,6 @@ fn build_struct_instance(struct_name: &str,
 
 /// # `static_to_local_ref` Command
 ///
-/// Usage: `static_to_local_ref`
-///
 /// Marks: `target`, `user`
 ///
 /// For each function marked `user`, replace uses of statics marked `target` with
@@ -380,8 +393,6 @@ impl Transform for Localize {
 
 /// # `static_to_local` Command
 ///
-/// Usage: `static_to_local`
-///
 /// Marks: `target`
 ///
 /// Delete each static marked `target`.  For each function that uses a marked static, insert a new
@@ -495,16 +506,1349 @@ impl Transform for StaticToLocal {
     }
 }
 
+/// # `globals_to_struct` Command
+///
+/// Marks: `target`
+///
+/// Move the `static mut`s marked `target` into fields of a new struct named `STRUCT` (default:
+/// `State`), and pass the state explicitly to the functions that need it instead:
+///
+///  1. Add `STRUCT` to the crate root, with a field for each marked static and a `STRUCT::new()`
+///     that builds it from the statics' initializers, and delete the statics.
+///  2. Give each function that uses one of the statics, or that calls a function that gets the
+///     state this way, a new first parameter `state: &mut STRUCT`, after `self` for methods.
+///     Uses of the statics become `state.NAME`, and the state is passed along to the callees.
+///  3. Functions at the crate boundary, meaning those exported with `#[no_mangle]` or
+///     `#[export_name]` and `main`, keep their signatures.  Their body moves into a new function
+///     named `NAME_with_state`, which works like the functions in (2), and the original function
+///     just calls it with the contents of a lazily-initialized global `Mutex<STRUCT>`.  Calls to
+///     these functions from functions that already have the state go straight to the
+///     `NAME_with_state` version, so the mutex is never locked twice.
+///
+/// Nothing is changed if a function that would need the new parameter is used as a function
+/// pointer, is a trait method or implements one, or already has a variable named `state`, or if a
+/// marked static is used outside of any function, for example in the initializer of another
+/// static.  Instead, the command warns about each problem, with the chain of calls that makes
+/// the function need the state.  Marked statics that aren't `mut`, or that foreign code can see,
+/// are skipped with a warning.
+///
+/// If `-o REPORT` is given, the command writes a report of the functions it changed, or of the
+/// problems that stopped it, to the file `REPORT`.
+///
+/// Example:
+///
+/// ```ignore
+///     static mut COUNT: i32 = 0;  // COUNT: target
+///
+///     unsafe fn bump() {
+///         COUNT += 1;
+///     }
+///
+///     #[no_mangle]
+///     pub unsafe extern "C" fn lib_bump() -> i32 {
+///         bump();
+///         COUNT
+///     }
+/// ```
+///
+/// After running `globals_to_struct`:
+///
+/// ```ignore
+///     unsafe fn bump(state: &mut State) {
+///         state.COUNT += 1;
+///     }
+///
+///     #[no_mangle]
+///     pub unsafe extern "C" fn lib_bump() -> i32 {
+///         lib_bump_with_state(&mut global_state().lock().unwrap())
+///     }
+///     unsafe fn lib_bump_with_state(state: &mut State) -> i32 {
+///         bump(state);
+///         state.COUNT
+///     }
+///
+///     pub struct State {
+///         COUNT: i32,
+///     }
+///     // ... along with `State::new()` and `global_state()`
+/// ```
+pub struct GlobalsToStruct {
+    struct_name: String,
+    report_path: Option<String>,
+}
+
+/// A function that `globals_to_struct` may need to change.
+struct GlobalsFn {
+    ident: Ident,
+    span: Span,
+    at_root: bool,
+    has_self: bool,
+    /// Whether the function keeps its signature and gets the state from the global instead.
+    boundary: bool,
+    /// Why the function can't be changed, if it can't.
+    fixed: Option<&'static str>,
+    /// The functions in `fns` that this one calls, in order of the first call.
+    callees: Vec<DefId>,
+    /// Why the function needs the state, if it does.
+    reason: Option<NeedsState>,
+}
+
+#[derive(Clone, Copy)]
+enum NeedsState {
+    /// The function uses this static.
+    Uses(DefId),
+    /// The function calls this function, which needs the state.
+    Calls(DefId),
+}
+
+impl Transform for GlobalsToStruct {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        st.start_report("globals_to_struct", self.report_path.clone());
+        let tcx = cx.ty_ctxt();
+        let hir_map = cx.hir_map();
+        let state_name = Symbol::intern("state");
+
+        // (1) Collect the marked statics.
+
+        let mut statics = HashMap::new();
+        let mut static_order = Vec::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") {
+                return;
+            }
+            let (ty, mutbl, init) = match_or!([i.kind]
+                ItemKind::Static(ref ty, mutbl, ref init) => (ty, mutbl, init); return);
+            let skip = |why: &str| cx.session().span_warn(
+                i.span, &format!("not moving `{}` into `{}`: {}", i.ident, self.struct_name, why));
+            if mutbl == Mutability::Immutable {
+                return skip("it isn't `mut`");
+            }
+            if is_exported(i) {
+                return skip("it's visible to foreign code");
+            }
+
+            let mut ty = ty.clone();
+            let mut init = init.clone();
+            let hir_id = hir_map.node_to_hir_id(i.id);
+            if hir_map.get_module_parent_node(hir_id) != CRATE_HIR_ID {
+                // Paths in the static were written relative to its module.
+                canonicalize_paths(&mut ty, cx);
+                canonicalize_paths(&mut init, cx);
+            }
+            let def_id = cx.node_def_id(i.id);
+            statics.insert(def_id, (i.ident, ty, init));
+            static_order.push(def_id);
+        });
+        if statics.is_empty() {
+            return;
+        }
+
+        // (2) Collect the functions, and which statics and functions each one uses.
+
+        let mut fns = HashMap::new();
+        visit_fns(krate, |fl| {
+            let def_id = cx.node_def_id(fl.id);
+            let hir_id = hir_map.node_to_hir_id(fl.id);
+            let at_root = hir_map.get_module_parent_node(hir_id) == CRATE_HIR_ID;
+            let boundary = fl.kind == FnKind::Normal &&
+                (fl.attrs.iter().any(is_export_attr) || (at_root && fl.ident.name == sym::main));
+            let simple_params = fl.decl.inputs.iter().all(|arg| {
+                matches!([arg.pat.kind] PatKind::Ident(BindingMode::ByValue(_), _, None))
+            });
+            let fixed = match fl.kind {
+                FnKind::Foreign => return,
+                FnKind::TraitMethod => Some("it's a trait method"),
+                FnKind::ImplMethod
+                    if tcx.impl_of_method(def_id).and_then(|i| tcx.trait_id_of_impl(i))
+                          .is_some() => Some("it implements a trait method"),
+                _ if boundary && fl.decl.c_variadic() => Some("it's variadic"),
+                _ if boundary && !simple_params =>
+                    Some("it has a parameter pattern that isn't an identifier"),
+                _ if fl.block.as_ref().map_or(false, |b| {
+                    bound_names(&**b).contains(&state_name)
+                }) => Some("it already has a variable named `state`"),
+                _ => None,
+            };
+            fns.insert(def_id, GlobalsFn {
+                ident: fl.ident,
+                span: fl.span,
+                at_root,
+                has_self: fl.decl.has_self(),
+                boundary,
+                fixed,
+                callees: Vec::new(),
+                reason: None,
+            });
+        });
+
+        let mut callee_paths = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Call(ref func, _) = e.kind {
+                callee_paths.insert(func.id);
+            }
+        });
+
+        let mut outside_uses = Vec::new();
+        let mut fn_ptrs = Vec::new();
+        visit_nodes(krate, |e: &Expr| {
+            let owner = hir_map.get_parent_item(hir_map.node_to_hir_id(e.id));
+            let owner = hir_map.opt_local_def_id(owner);
+            if let Some(callee) = cx.opt_callee(e).filter(|did| fns.contains_key(did)) {
+                if let Some(f) = owner.and_then(|did| fns.get_mut(&did)) {
+                    if !f.callees.contains(&callee) {
+                        f.callees.push(callee);
+                    }
+                }
+            }
+
+            if !matches!([e.kind] ExprKind::Path(..)) {
+                return;
+            }
+            let def_id = match_or!([cx.try_resolve_expr(e)] Some(x) => x; return);
+            if statics.contains_key(&def_id) {
+                match owner.and_then(|did| fns.get_mut(&did)) {
+                    Some(f) => {
+                        if f.reason.is_none() {
+                            f.reason = Some(NeedsState::Uses(def_id));
+                        }
+                    }
+                    None => outside_uses.push((def_id, e.span)),
+                }
+            } else if fns.contains_key(&def_id) && !callee_paths.contains(&e.id) {
+                fn_ptrs.push((def_id, e.span));
+            }
+        });
+
+        // (3) Propagate the need for the state from callees to callers.  Calling a function at
+        // the boundary doesn't need it, since that function gets the state on its own.
+
+        dataflow::iterate(&mut fns, |cur_id, cur, data| {
+            if cur.reason.is_some() {
+                return false;
+            }
+            let callee = cur.callees.iter().cloned().find(|&callee| {
+                if callee == cur_id {
+                    return false;
+                }
+                let other = &data[callee];
+                other.reason.is_some() && !other.boundary
+            });
+            match callee {
+                Some(callee) => {
+                    cur.reason = Some(NeedsState::Calls(callee));
+                    true
+                }
+                None => false,
+            }
+        });
+
+        // (4) Check that every function that needs the state can get it.
+
+        let chain = |mut def_id: DefId| {
+            let mut names = vec![tcx.def_path_str(def_id)];
+            while let Some(reason) = fns.get(&def_id).and_then(|f| f.reason) {
+                match reason {
+                    NeedsState::Calls(callee) => {
+                        names.push(tcx.def_path_str(callee));
+                        def_id = callee;
+                    }
+                    NeedsState::Uses(static_id) => {
+                        names.push(tcx.def_path_str(static_id));
+                        break;
+                    }
+                }
+            }
+            names.join(" -> ")
+        };
+
+        let mut problems = Vec::new();
+        for &(static_id, span) in &outside_uses {
+            let msg = format!("`{}` is used outside of any function", statics[&static_id].0);
+            problems.push((span, "used outside of any function", msg));
+        }
+        let mut fixed_fns = fns.iter()
+            .filter(|&(_, f)| f.reason.is_some() && f.fixed.is_some())
+            .collect::<Vec<_>>();
+        fixed_fns.sort_by_key(|&(_, f)| f.span);
+        for (&def_id, f) in fixed_fns {
+            let msg = format!("`{}` needs the state ({}), but can't get it: {}",
+                              f.ident, chain(def_id), f.fixed.unwrap());
+            problems.push((f.span, "can't get the state", msg));
+        }
+        for &(def_id, span) in &fn_ptrs {
+            let f = &fns[&def_id];
+            if f.reason.is_some() && !f.boundary {
+                let msg = format!("`{}` needs the state ({}), but it's used as a function \
+                                   pointer here", f.ident, chain(def_id));
+                problems.push((span, "used as a function pointer", msg));
+            }
+        }
+        if !problems.is_empty() {
+            for (span, category, msg) in problems {
+                cx.session().span_warn(span, &format!("not moving globals into `{}`: {}",
+                                                      self.struct_name, msg));
+                st.report(cx, category, span);
+            }
+            return;
+        }
+
+        // (5) Rewrite the functions that need the state, and the calls to them.
+
+        let needs = |def_id: &DefId| fns.get(def_id).map_or(false, |f| f.reason.is_some());
+        let state_ty = |at_root: bool| if at_root {
+            mk().path_ty(vec![&self.struct_name as &str])
+        } else {
+            mk().path_ty(vec!["crate", &self.struct_name as &str])
+        };
+
+        mut_visit_fns(krate, |fl| {
+            let def_id = cx.node_def_id(fl.id);
+            let f = match_or!([fns.get(&def_id)] Some(f) => f; return);
+            if f.reason.is_none() {
+                return;
+            }
+            let block = match_or!([fl.block] Some(ref mut b) => b; return);
+
+            MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                if let ExprKind::Path(..) = e.kind {
+                    if let Some(def_id) = cx.try_resolve_expr(e) {
+                        if let Some(&(ident, _, _)) = statics.get(&def_id) {
+                            *e = mk().span(e.span).field_expr(mk().ident_expr(state_name), ident);
+                        }
+                    }
+                    return;
+                }
+
+                let callee = match_or!([cx.opt_callee(e)] Some(x) => x; return);
+                if !needs(&callee) {
+                    return;
+                }
+                let callee_fn = &fns[&callee];
+                match e.kind {
+                    ExprKind::Call(ref mut func, ref mut args) => {
+                        if callee_fn.boundary {
+                            let func_path = match_or!([func.kind]
+                                ExprKind::Path(None, ref mut p) => p; return);
+                            let seg = func_path.segments.last_mut().unwrap();
+                            seg.ident = Ident::from_str(
+                                &format!("{}_with_state", callee_fn.ident));
+                            args.insert(0, mk().ident_expr(state_name));
+                        } else {
+                            args.insert(callee_fn.has_self as usize, mk().ident_expr(state_name));
+                        }
+                    }
+                    ExprKind::MethodCall(_, ref mut args) => {
+                        args.insert(1, mk().ident_expr(state_name));
+                    }
+                    _ => {}
+                }
+            });
+
+            if !f.boundary {
+                let param = mk().arg(mk().mutbl().ref_ty(state_ty(f.at_root)),
+                                     mk().ident_pat(state_name));
+                fl.decl.inputs.insert(f.has_self as usize, param);
+                st.report(cx, "takes the state", fl.span);
+            }
+        });
+
+        // (6) Split the functions at the boundary into a wrapper and a `_with_state` version.
+
+        let global_state = |at_root: bool| if at_root {
+            mk().path_expr(vec!["global_state"])
+        } else {
+            mk().path_expr(vec!["crate", "global_state"])
+        };
+        let mut uses_global = false;
+        FlatMapNodes::visit(krate, |mut i: P<Item>| {
+            if !matches!([i.kind] ItemKind::Fn(..)) {
+                return smallvec![i];
+            }
+            let def_id = cx.node_def_id(i.id);
+            let f = match_or!([fns.get(&def_id)] Some(f) => f; return smallvec![i]);
+            if f.reason.is_none() || !f.boundary {
+                return smallvec![i];
+            }
+            uses_global = true;
+            st.report(cx, "uses the global state", i.span);
+
+            let inner_name = format!("{}_with_state", i.ident);
+            let (sig, generics, block) = expect!([i.kind]
+                ItemKind::Fn(ref mut sig, ref generics, ref mut block) => (sig, generics, block));
+
+            let mut inner_decl = sig.decl.clone();
+            inner_decl.inputs.insert(0, mk().arg(mk().mutbl().ref_ty(state_ty(f.at_root)),
+                                                 mk().ident_pat(state_name)));
+            let mut inner = mk().unsafety(sig.header.unsafety)
+                .fn_item(&inner_name as &str, inner_decl, block.clone());
+            if let ItemKind::Fn(_, ref mut inner_generics, _) = inner.kind {
+                *inner_generics = generics.clone();
+            }
+
+            let lock = mk().method_call_expr(
+                mk().method_call_expr(
+                    mk().call_expr(global_state(f.at_root), Vec::<P<Expr>>::new()),
+                    "lock", Vec::<P<Expr>>::new()),
+                "unwrap", Vec::<P<Expr>>::new());
+            let mut args = vec![mk().mutbl().addr_of_expr(lock)];
+            for arg in sig.decl.clone().into_inner().inputs {
+                let ident = expect!([arg.pat.kind] PatKind::Ident(_, ident, _) => ident);
+                args.push(mk().ident_expr(ident));
+            }
+            // The wrapper only passes its parameters along.
+            for arg in &mut sig.decl.inputs {
+                if let PatKind::Ident(ref mut mode, _, _) = arg.pat.kind {
+                    *mode = BindingMode::ByValue(Mutability::Immutable);
+                }
+            }
+            let call = mk().call_expr(mk().path_expr(vec![&inner_name as &str]), args);
+            *block = mk().block(vec![mk().expr_stmt(call)]);
+            i.tokens = None;
+
+            smallvec![i, inner]
+        });
+
+        // (7) Replace the statics with the new struct.
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            if matches!([i.kind] ItemKind::Static(..)) &&
+               statics.contains_key(&cx.node_def_id(i.id)) {
+                return smallvec![];
+            }
+            smallvec![i]
+        });
+
+        let name = &self.struct_name as &str;
+        let fields = static_order.iter().map(|did| {
+            let (ident, ref ty, _) = statics[did];
+            mk().struct_field(ident, ty)
+        }).collect::<Vec<_>>();
+        let inits = static_order.iter().map(|did| {
+            let (ident, _, ref init) = statics[did];
+            mk().field(ident, init)
+        }).collect::<Vec<_>>();
+        let init = mk().struct_expr(vec![name], inits);
+        krate.module.items.push(mk().pub_().struct_item(name, fields, false));
+        let mut src = format!("
+            impl {name} {{
+                pub fn new() -> {name} {{
+                    {init}
+                }}
+            }}
+        ", name = name, init = pprust::expr_to_string(&init));
+        if uses_global {
+            src.push_str(&format!("
+                fn global_state() -> &'static ::std::sync::Mutex<{name}> {{
+                    static INIT: ::std::sync::Once = ::std::sync::Once::new();
+                    static mut STATE: Option<::std::sync::Mutex<{name}>> = None;
+                    unsafe {{
+                        INIT.call_once(|| STATE = Some(::std::sync::Mutex::new({name}::new())));
+                        STATE.as_ref().unwrap()
+                    }}
+                }}
+            ", name = name));
+        }
+        krate.module.items.extend(parse_items(cx.session(), &src));
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
 
+/// # `convert_static_mut_to_atomic` Command
+///
+/// Marks: `target`
+///
+/// Convert each `static mut` marked `target` that holds an integer or a `bool` to the matching
+/// type from `std::sync::atomic`, like `AtomicI32` for a `libc::c_int`, and rewrite its uses:
+///
+///  * `X = v` becomes `X.store(v, ORDERING)`.
+///  * `X += v` becomes `X.fetch_add(v, ORDERING)`, and likewise for `-=`, `&=`, `|=` and `^=`.
+///  * Any other read of `X` becomes `X.load(ORDERING)`.
+///
+/// `ORDERING` is `Relaxed` (the default), `SeqCst`, or `AcqRel`, which means `Acquire` for
+/// loads, `Release` for stores, and `AcqRel` for the compound assignments.
+///
+/// Since the converted statics aren't `mut` anymore, using them doesn't need `unsafe`.  `unsafe`
+/// blocks that only needed it for these statics become plain blocks, or just their expression.
+///
+/// A marked static is left unchanged, with a warning, if foreign code can see it through
+/// `#[no_mangle]` or `#[export_name]`, if it's borrowed or its address is taken, for example to
+/// pass it to a foreign function, or if it's used with another compound assignment, like `*=`.
+///
+/// Example:
+///
+/// ```ignore
+///     static mut COUNT: libc::c_int = 0;  // COUNT: target
+///
+///     fn bump(n: libc::c_int) -> libc::c_int {
+///         unsafe { COUNT += n };
+///         unsafe { COUNT }
+///     }
+/// ```
+///
+/// After running `convert_static_mut_to_atomic`:
+///
+/// ```ignore
+///     static COUNT: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(0);
+///
+///     fn bump(n: libc::c_int) -> libc::c_int {
+///         COUNT.fetch_add(n, std::sync::atomic::Ordering::Relaxed);
+///         COUNT.load(std::sync::atomic::Ordering::Relaxed)
+///     }
+/// ```
+pub struct StaticToAtomic {
+    ordering: String,
+}
+
+impl Transform for StaticToAtomic {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let (load, store, rmw) = match &self.ordering as &str {
+            "Relaxed" => ("Relaxed", "Relaxed", "Relaxed"),
+            "SeqCst" => ("SeqCst", "SeqCst", "SeqCst"),
+            "AcqRel" => ("Acquire", "Release", "AcqRel"),
+            ord => panic!("unsupported ordering `{}`: expected `Relaxed`, `SeqCst` or `AcqRel`",
+                          ord),
+        };
+        let tcx = cx.ty_ctxt();
+        let warn = |span, name: Ident, why: &str| {
+            cx.session().span_warn(span, &format!("not converting `{}` to an atomic: {}",
+                                                  name, why));
+        };
+
+        // (1) Find the marked statics that have an atomic equivalent.
+
+        let mut statics = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") {
+                return;
+            }
+            match i.kind {
+                ItemKind::Static(_, Mutability::Mutable, _) => {}
+                _ => return,
+            }
+
+            let def_id = cx.node_def_id(i.id);
+            let atomic = match tcx.type_of(def_id).kind {
+                ty::TyKind::Bool => "AtomicBool",
+                ty::TyKind::Int(IntTy::I8) => "AtomicI8",
+                ty::TyKind::Int(IntTy::I16) => "AtomicI16",
+                ty::TyKind::Int(IntTy::I32) => "AtomicI32",
+                ty::TyKind::Int(IntTy::I64) => "AtomicI64",
+                ty::TyKind::Int(IntTy::Isize) => "AtomicIsize",
+                ty::TyKind::Uint(UintTy::U8) => "AtomicU8",
+                ty::TyKind::Uint(UintTy::U16) => "AtomicU16",
+                ty::TyKind::Uint(UintTy::U32) => "AtomicU32",
+                ty::TyKind::Uint(UintTy::U64) => "AtomicU64",
+                ty::TyKind::Uint(UintTy::Usize) => "AtomicUsize",
+                _ => return warn(i.span, i.ident, "its type has no atomic equivalent"),
+            };
+            if attr::contains_name(&i.attrs, sym::no_mangle) ||
+               attr::contains_name(&i.attrs, sym::export_name) {
+                return warn(i.span, i.ident, "it's visible to foreign code");
+            }
+            statics.insert(def_id, (i.ident, atomic));
+        });
+
+        // (2) Drop the statics that are used in ways atomics don't support.
+
+        let mut checker = AtomicUseChecker { cx, statics: &statics, bad_uses: HashMap::new() };
+        visit::walk_crate(&mut checker, krate);
+        let mut bad_uses = checker.bad_uses.into_iter().collect::<Vec<_>>();
+        bad_uses.sort_by_key(|&(_, (span, _))| span);
+        for (def_id, (span, why)) in bad_uses {
+            warn(span, statics[&def_id].0, why);
+            statics.remove(&def_id);
+        }
+        if statics.is_empty() {
+            return;
+        }
+
+        // (3) Find the `unsafe` blocks that are only needed for the converted statics.
+
+        let no_safe = HashSet::new();
+        let safe = statics.keys().cloned().collect::<HashSet<_>>();
+        let mut redundant = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            let b = match_or!([e.kind] ExprKind::Block(ref b, _) => b; return);
+            if b.rules == BlockCheckMode::Unsafe(UnsafeSource::UserProvided) &&
+               b.stmts.iter().any(|s| needs_unsafe(cx, &no_safe, s)) &&
+               !b.stmts.iter().any(|s| needs_unsafe(cx, &safe, s)) {
+                redundant.insert(b.id);
+            }
+        });
+
+        // (4) Rewrite the statics, their uses, and the blocks.
+
+        FlatMapNodes::visit(krate, |mut i: P<Item>| {
+            let init = match_or!([i.kind] ItemKind::Static(_, _, ref init) => init.clone();
+                                 return smallvec![i]);
+            if let Some(&(_, atomic)) = statics.get(&cx.node_def_id(i.id)) {
+                let ty = mk().path_ty(vec!["std", "sync", "atomic", atomic]);
+                let new = mk().path_expr(vec!["std", "sync", "atomic", atomic, "new"]);
+                i.kind = ItemKind::Static(ty, Mutability::Immutable,
+                                          mk().call_expr(new, vec![init]));
+            }
+            smallvec![i]
+        });
+
+        krate.visit(&mut AtomicRewriter { cx, statics: &statics, load, store, rmw });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let id = match_or!([e.kind] ExprKind::Block(ref b, _) => b.id; return);
+            if redundant.contains(&id) {
+                remove_unsafe_block(e);
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// If `e` is a path to one of `statics`, possibly in parentheses, return its `DefId`.
+fn static_id<T>(cx: &RefactorCtxt, statics: &HashMap<DefId, T>, e: &Expr) -> Option<DefId> {
+    match e.kind {
+        ExprKind::Path(..) => cx.try_resolve_expr(e).filter(|did| statics.contains_key(did)),
+        ExprKind::Paren(ref inner) => static_id(cx, statics, inner),
+        _ => None,
+    }
+}
+
+/// The atomic method that performs the compound assignment `op`.
+fn fetch_method(op: BinOpKind) -> Option<&'static str> {
+    match op {
+        BinOpKind::Add => Some("fetch_add"),
+        BinOpKind::Sub => Some("fetch_sub"),
+        BinOpKind::BitAnd => Some("fetch_and"),
+        BinOpKind::BitOr => Some("fetch_or"),
+        BinOpKind::BitXor => Some("fetch_xor"),
+        _ => None,
+    }
+}
+
+/// Finds the uses of the statics that can't be converted to atomic operations.
+struct AtomicUseChecker<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    statics: &'a HashMap<DefId, (Ident, &'static str)>,
+    /// The first bad use of each static that has one, and what's wrong with it.
+    bad_uses: HashMap<DefId, (Span, &'static str)>,
+}
+
+impl<'a, 'b, 'tcx> AtomicUseChecker<'a, 'b, 'tcx> {
+    fn is_static(&self, e: &Expr) -> bool {
+        static_id(self.cx, self.statics, e).is_some()
+    }
+}
+
+impl<'a, 'b, 'tcx, 'ast> Visitor<'ast> for AtomicUseChecker<'a, 'b, 'tcx> {
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        let bad_use = match e.kind {
+            ExprKind::Assign(ref lhs, ref rhs) if self.is_static(lhs) => {
+                return self.visit_expr(rhs);
+            }
+            ExprKind::AssignOp(op, ref lhs, ref rhs) if self.is_static(lhs) => {
+                if fetch_method(op.node).is_none() {
+                    let def_id = static_id(self.cx, self.statics, lhs).unwrap();
+                    let why = "it's used in a compound assignment with no atomic equivalent";
+                    self.bad_uses.entry(def_id).or_insert((e.span, why));
+                }
+                return self.visit_expr(rhs);
+            }
+            ExprKind::AddrOf(_, _, ref inner) =>
+                static_id(self.cx, self.statics, inner).map(|did| (did, "its address is taken")),
+            ExprKind::Path(..) => static_id(self.cx, self.statics, e).filter(|_| {
+                // Method calls can borrow their receiver implicitly.
+                match self.cx.opt_adjusted_node_type(e.id).map(|ty| &ty.kind) {
+                    Some(ty::TyKind::Ref(..)) => true,
+                    _ => false,
+                }
+            }).map(|did| (did, "it's borrowed")),
+            _ => None,
+        };
+
+        if let Some((def_id, why)) = bad_use {
+            self.bad_uses.entry(def_id).or_insert((e.span, why));
+        }
+        visit::walk_expr(self, e);
+    }
+
+    fn visit_mac(&mut self, mac: &'ast Mac) {
+        visit::walk_mac(self, mac);
+    }
+}
+
+/// Rewrites the uses of the statics into atomic operations.
+struct AtomicRewriter<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    statics: &'a HashMap<DefId, (Ident, &'static str)>,
+    load: &'static str,
+    store: &'static str,
+    rmw: &'static str,
+}
+
+impl<'a, 'b, 'tcx> AtomicRewriter<'a, 'b, 'tcx> {
+    fn is_static(&self, e: &Expr) -> bool {
+        static_id(self.cx, self.statics, e).is_some()
+    }
+}
+
+impl<'a, 'b, 'tcx> MutVisitor for AtomicRewriter<'a, 'b, 'tcx> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        let ordering = |name| mk().path_expr(vec!["std", "sync", "atomic", "Ordering", name]);
+        let new_expr = match e.kind {
+            ExprKind::Assign(ref lhs, ref rhs) if self.is_static(lhs) => {
+                let mut rhs = rhs.clone();
+                self.visit_expr(&mut rhs);
+                let args = vec![rhs, ordering(self.store)];
+                Some(mk().span(e.span).method_call_expr(lhs.clone(), "store", args))
+            }
+            ExprKind::AssignOp(op, ref lhs, ref rhs) if self.is_static(lhs) => {
+                let mut rhs = rhs.clone();
+                self.visit_expr(&mut rhs);
+                let method = fetch_method(op.node).unwrap();
+                let args = vec![rhs, ordering(self.rmw)];
+                Some(mk().span(e.span).method_call_expr(lhs.clone(), method, args))
+            }
+            ExprKind::Path(..) if self.is_static(e) => {
+                let args = vec![ordering(self.load)];
+                Some(mk().span(e.span).method_call_expr(e.clone(), "load", args))
+            }
+            _ => None,
+        };
+
+        match new_expr {
+            Some(new_expr) => *e = new_expr,
+            None => mut_visit::noop_visit_expr(e, self),
+        }
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+
+/// # `wrap_volatile_statics` Command
+///
+/// Marks: `target`
+///
+/// Route every access to the `static mut`s marked `target`, which usually stand for
+/// memory-mapped hardware registers, through a volatile wrapper.  This adds the wrapper to the
+/// crate root, unless an item named `Reg` is already there:
+///
+/// ```ignore
+///     pub struct Reg<T>(pub *mut T);
+///
+///     impl<T> Reg<T> {
+///         pub unsafe fn read(self) -> T { ::std::ptr::read_volatile(self.0) }
+///         pub unsafe fn write(self, val: T) { ::std::ptr::write_volatile(self.0, val) }
+///     }
+/// ```
+///
+/// and rewrites the accesses to places inside a marked static, like `UART.cr` or `REGS[i]`:
+///
+///  * `read_volatile(&P)` and `volatile_load(&P)` become `Reg(&mut P).read()`, and likewise
+///    `write_volatile(&mut P, v)` and `volatile_store(&mut P, v)` become `Reg(&mut P).write(v)`.
+///  * `P = v` becomes `Reg(&mut P).write(v)`, and `P op= v` becomes
+///    `Reg(&mut P).write(Reg(&mut P).read() op v)`.
+///  * Any other read of `P` becomes `Reg(&mut P).read()`.
+///
+/// The last two restore volatile accesses that earlier rewrites may have lost.  A place that's
+/// borrowed or whose address is taken for any other purpose is left alone, with a warning, since
+/// the accesses through that reference can't be tracked.
+///
+/// If `-o REPORT` is given, the command writes a report of each rewritten or skipped access to
+/// the file `REPORT`.
+///
+/// Example:
+///
+/// ```ignore
+///     static mut UART: uart_regs = ...;  // UART: target
+///
+///     unsafe fn enable() {
+///         if ptr::read_volatile(&UART.sr as *const u32) & 0x1 != 0 {
+///             UART.cr |= 0x4;
+///         }
+///     }
+/// ```
+///
+/// After running `wrap_volatile_statics`:
+///
+/// ```ignore
+///     unsafe fn enable() {
+///         if crate::Reg(&mut UART.sr).read() & 0x1 != 0 {
+///             crate::Reg(&mut UART.cr).write(crate::Reg(&mut UART.cr).read() | 0x4);
+///         }
+///     }
+/// ```
+pub struct WrapVolatileStatics {
+    report_path: Option<String>,
+}
+
+impl Transform for WrapVolatileStatics {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        st.start_report("wrap_volatile_statics", self.report_path.clone());
+
+        let mut statics = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") {
+                return;
+            }
+            match i.kind {
+                ItemKind::Static(_, Mutability::Mutable, _) => {
+                    statics.insert(cx.node_def_id(i.id), i.ident);
+                }
+                ItemKind::Static(..) => {
+                    cx.session().span_warn(i.span, &format!(
+                        "not wrapping `{}`: only `static mut`s can be accessed volatilely",
+                        i.ident));
+                }
+                _ => {}
+            }
+        });
+        if statics.is_empty() {
+            return;
+        }
+
+        let mut folder = VolatileFolder {
+            cx,
+            st,
+            statics: &statics,
+            mod_depth: 0,
+            changed: false,
+        };
+        krate.visit(&mut folder);
+
+        let has_reg = krate.module.items.iter().any(|i| i.ident.as_str() == "Reg");
+        if folder.changed && !has_reg {
+            let src = "
+                pub struct Reg<T>(pub *mut T);
+
+                impl<T> Clone for Reg<T> {
+                    fn clone(&self) -> Self { *self }
+                }
+
+                impl<T> Copy for Reg<T> {}
+
+                impl<T> Reg<T> {
+                    pub unsafe fn read(self) -> T { ::std::ptr::read_volatile(self.0) }
+                    pub unsafe fn write(self, val: T) { ::std::ptr::write_volatile(self.0, val) }
+                }
+            ";
+            krate.module.items.extend(parse_items(cx.session(), src));
+        }
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// If `e` is a place inside one of `statics`, such as `S`, `S.f` or `S.a[i]`, return the
+/// `DefId` of the static.
+fn static_place<T>(cx: &RefactorCtxt, statics: &HashMap<DefId, T>, e: &Expr) -> Option<DefId> {
+    match e.kind {
+        ExprKind::Path(..) => cx.try_resolve_expr(e).filter(|did| statics.contains_key(did)),
+        ExprKind::Field(ref base, _) |
+        ExprKind::Index(ref base, _) |
+        ExprKind::Paren(ref base) => static_place(cx, statics, base),
+        _ => None,
+    }
+}
+
+/// Rewrites the accesses to the statics into calls on the volatile wrapper.
+struct VolatileFolder<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    st: &'a CommandState,
+    statics: &'a HashMap<DefId, Ident>,
+    mod_depth: usize,
+    /// Whether any access was rewritten, so the crate needs the wrapper.
+    changed: bool,
+}
+
+impl<'a, 'b, 'tcx> VolatileFolder<'a, 'b, 'tcx> {
+    fn is_place(&self, e: &Expr) -> bool {
+        static_place(self.cx, self.statics, e).is_some()
+    }
+
+    /// If `e` calls the volatile function `name`, or the intrinsic `intrinsic`, with a reference
+    /// to one of the places as its first argument, return that place.
+    fn volatile_call<'e>(&self, e: &'e Expr, name: &str, intrinsic: &str)
+                         -> Option<&'e P<Expr>> {
+        let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return None);
+        let did = self.cx.opt_callee(e)?;
+        let callee = self.cx.ty_ctxt().item_name(did).as_str();
+        if &*callee != name && &*callee != intrinsic {
+            return None;
+        }
+        let place = match strip_casts(args.get(0)?).kind {
+            ExprKind::AddrOf(_, _, ref place) => place,
+            _ => return None,
+        };
+        Some(place).filter(|place| self.is_place(place))
+    }
+
+    /// Rewrite the index expressions inside `place`, which is left in place otherwise.
+    fn visit_place(&mut self, place: &mut P<Expr>) {
+        match place.kind {
+            ExprKind::Field(ref mut base, _) |
+            ExprKind::Paren(ref mut base) => self.visit_place(base),
+            ExprKind::Index(ref mut base, ref mut idx) => {
+                self.visit_place(base);
+                self.visit_expr(idx);
+            }
+            _ => {}
+        }
+    }
+
+    /// Build `Reg(&mut place)`.
+    fn reg(&mut self, place: &P<Expr>) -> P<Expr> {
+        let mut place = place.clone();
+        self.visit_place(&mut place);
+        self.changed = true;
+        // Only the crate root itself can name the wrapper without a path.
+        let ctor = if self.mod_depth == 1 {
+            mk().path_expr(vec!["Reg"])
+        } else {
+            mk().path_expr(vec!["crate", "Reg"])
+        };
+        mk().call_expr(ctor, vec![mk().mutbl().addr_of_expr(place)])
+    }
+
+    fn read(&mut self, place: &P<Expr>) -> P<Expr> {
+        let reg = self.reg(place);
+        mk().method_call_expr(reg, "read", Vec::<P<Expr>>::new())
+    }
+
+    /// Build `Reg(&mut place).write(val)`.  `val` must already be rewritten.
+    fn write(&mut self, place: &P<Expr>, val: P<Expr>) -> P<Expr> {
+        let reg = self.reg(place);
+        mk().method_call_expr(reg, "write", vec![val])
+    }
+
+    /// If `e` lets a reference to one of the places escape, say how.
+    fn escape(&self, e: &Expr) -> Option<&'static str> {
+        match e.kind {
+            ExprKind::AddrOf(_, _, ref place) if self.is_place(place) =>
+                Some("its address is taken"),
+            _ if self.is_place(e) => {
+                // Method calls can borrow their receiver implicitly.
+                match self.cx.opt_adjusted_node_type(e.id).map(|ty| &ty.kind) {
+                    Some(ty::TyKind::Ref(..)) => Some("it's borrowed"),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<'a, 'b, 'tcx> MutVisitor for VolatileFolder<'a, 'b, 'tcx> {
+    fn visit_mod(&mut self, m: &mut Mod) {
+        self.mod_depth += 1;
+        mut_visit::noop_visit_mod(m, self);
+        self.mod_depth -= 1;
+    }
+
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        if let Some(why) = self.escape(e) {
+            self.cx.session().span_warn(e.span, &format!(
+                "not wrapping this access to a volatile static: {}", why));
+            self.st.report(self.cx, "address escapes", e.span);
+            match e.kind {
+                ExprKind::AddrOf(_, _, ref mut place) => self.visit_place(place),
+                _ => self.visit_place(e),
+            }
+            return;
+        }
+
+        let (new_expr, category) = if let Some(place) =
+            self.volatile_call(e, "read_volatile", "volatile_load") {
+            (self.read(place), "wrapped volatile read")
+        } else if let Some(place) = self.volatile_call(e, "write_volatile", "volatile_store") {
+            let mut val = match_or!([e.kind] ExprKind::Call(_, ref args) => args[1].clone();
+                                    unreachable!());
+            self.visit_expr(&mut val);
+            (self.write(place, val), "wrapped volatile write")
+        } else {
+            match e.kind {
+                ExprKind::Assign(ref lhs, ref rhs) if self.is_place(lhs) => {
+                    let mut val = rhs.clone();
+                    self.visit_expr(&mut val);
+                    (self.write(lhs, val), "restored volatile write")
+                }
+                ExprKind::AssignOp(op, ref lhs, ref rhs) if self.is_place(lhs) => {
+                    let mut rhs = rhs.clone();
+                    self.visit_expr(&mut rhs);
+                    let old = self.read(lhs);
+                    let val = mk().binary_expr(op.node, old, rhs);
+                    (self.write(lhs, val), "restored volatile write")
+                }
+                _ if self.is_place(e) => (self.read(e), "restored volatile read"),
+                _ => return mut_visit::noop_visit_expr(e, self),
+            }
+        };
+
+        self.st.report(self.cx, category, e.span);
+        let span = e.span;
+        *e = new_expr;
+        e.span = span;
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+
+/// # `promote_local_consts` Command
+///
+/// Hoist large constant tables out of functions.  A local initialized with an array, struct or
+/// tuple literal becomes a module-level item if it's never mutated, its type is `Copy` and at
+/// least `MIN_SIZE` bytes (64 by default), and its initializer can be evaluated at compile time:
+/// it may only use literals, constants, functions, and calls to constructors and `const fn`s,
+/// never other locals or statics.  The new item is a `static` if the type is `Sync`, and a
+/// `const` otherwise.  The local becomes a reference to the item, so uses of `x` turn into `*x`,
+/// except for borrows, indexing, field accesses and method calls.
+///
+/// The item is named after the local in upper case, with the function's name as a prefix if
+/// that would clash with another item in the module.  Identical tables in several functions of
+/// the same module are merged into a single item.
+///
+/// Only free functions without generic parameters are changed.
+///
+/// Example:
+///
+/// ```ignore
+///     fn hex(d: u8) -> u8 {
+///         let digits: [u8; 16] = [b'0', b'1', ..., b'f'];
+///         digits[d as usize]
+///     }
+/// ```
+///
+/// After running `promote_local_consts 16`:
+///
+/// ```ignore
+///     static DIGITS: [u8; 16] = [b'0', b'1', ..., b'f'];
+///     fn hex(d: u8) -> u8 {
+///         let digits = &DIGITS;
+///         digits[d as usize]
+///     }
+/// ```
+pub struct PromoteLocalConsts {
+    min_size: u64,
+}
+
+struct LocalTable<'tcx> {
+    local: HirId,
+    ident: Ident,
+    fn_name: Ident,
+    /// The function item, which the new item is inserted before.
+    anchor: NodeId,
+    module: HirId,
+    ty: ty::Ty<'tcx>,
+    ast_ty: P<Ty>,
+    init: P<Expr>,
+    sync: bool,
+}
+
+impl Transform for PromoteLocalConsts {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+        let hir_map = cx.hir_map();
+
+        // (1) Find the functions that sit directly in a module, and the names already in use in
+        // each module.
+
+        let mut fns = HashMap::new();
+        let mut mod_names = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            let hir_id = hir_map.node_to_hir_id(i.id);
+            let module = hir_map.get_module_parent_node(hir_id);
+            if hir_map.get_parent_item(hir_id) != module {
+                return;
+            }
+            let names = mod_names.entry(module).or_insert_with(HashSet::new);
+            match i.kind {
+                ItemKind::Use(ref tree) => use_tree_names(tree, names),
+                _ => { names.insert(i.ident.name); }
+            }
+            if let ItemKind::Fn(_, ref generics, _) = i.kind {
+                if generics.params.is_empty() {
+                    fns.insert(hir_id, (i.id, module, i.ident));
+                }
+            }
+        });
+
+        // (2) Find the locals in those functions that hold a large constant table.
+
+        let mut tables = Vec::new();
+        visit_nodes(krate, |l: &Local| {
+            let ident = match_or!([l.pat.kind] PatKind::Ident(BindingMode::ByValue(_), ident, None)
+                                  => ident; return);
+            let init = match_or!([l.init] Some(ref e) => e; return);
+            match init.kind {
+                ExprKind::Array(..) | ExprKind::Repeat(..) |
+                ExprKind::Struct(..) | ExprKind::Tup(..) => {}
+                _ => return,
+            }
+            let local = hir_map.node_to_hir_id(l.pat.id);
+            let &(anchor, module, fn_name) =
+                match_or!([fns.get(&hir_map.get_parent_item(local))] Some(x) => x; return);
+            if !is_const_init(cx, init) {
+                return;
+            }
+
+            let ty = cx.node_type(l.pat.id);
+            if !type_implements(cx, ty, l.pat.id, "Copy") {
+                return;
+            }
+            let size = match_or!([tcx.layout_of(ParamEnv::reveal_all().and(ty))]
+                                 Ok(layout) => layout.size.bytes(); return);
+            if size < self.min_size {
+                return;
+            }
+            let ast_ty = match l.ty {
+                Some(ref ty) => ty.clone(),
+                None => reflect_tcx_ty(tcx, ty),
+            };
+            let mut nameable = true;
+            visit_nodes(&*ast_ty, |t: &Ty| {
+                if let TyKind::Infer = t.kind {
+                    nameable = false;
+                }
+            });
+            if !nameable {
+                return;
+            }
+
+            tables.push(LocalTable {
+                local, ident, fn_name, anchor, module, ty, ast_ty,
+                init: init.clone(),
+                sync: type_implements(cx, ty, l.pat.id, "Sync"),
+            });
+        });
+
+        // (3) Drop the tables that are mutated.  Binding the local counts as a write to it.
+
+        let locals = tables.iter().map(|t| t.local).collect::<HashSet<_>>();
+        let writes = local_writes(cx, &locals);
+        tables.retain(|t| writes.get(&t.local).map_or(true, |ws| ws.iter().all(|&w| w == t.local)));
+        if tables.is_empty() {
+            return;
+        }
+
+        // (4) Build an item for each distinct table, merging the identical ones in a module.
+
+        let mut merged = HashMap::new();
+        let mut new_items = HashMap::new();
+        let mut promoted = HashMap::new();
+        for t in &tables {
+            let key = (t.module, t.ty, pprust::expr_to_string(&t.init));
+            let name = match merged.entry(key) {
+                Entry::Occupied(e) => *e.get(),
+                Entry::Vacant(e) => {
+                    let names = mod_names.entry(t.module).or_insert_with(HashSet::new);
+                    let name = table_name(names, t.fn_name, t.ident);
+                    names.insert(name);
+                    let item = if t.sync {
+                        mk().static_item(name, t.ast_ty.clone(), t.init.clone())
+                    } else {
+                        mk().const_item(name, t.ast_ty.clone(), t.init.clone())
+                    };
+                    new_items.entry(t.anchor).or_insert_with(Vec::new).push(item);
+                    *e.insert(name)
+                }
+            };
+            promoted.insert(t.local, name);
+        }
+
+        // (5) Rewrite the uses of the locals, then the locals themselves, and add the items.
+
+        let locals = promoted.keys().cloned().collect::<HashSet<_>>();
+        krate.visit(&mut TableRefRewriter { cx, locals: &locals, auto_deref: HashSet::new() });
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            let hir_id = match_or!([cx.opt_node_to_hir_id(l.pat.id)] Some(x) => x; return);
+            let name = match_or!([promoted.get(&hir_id)] Some(&x) => x; return);
+            let ident = expect!([l.pat.kind] PatKind::Ident(_, ident, _) => ident);
+            l.pat = mk().ident_pat(ident);
+            l.ty = None;
+            l.init = Some(mk().addr_of_expr(mk().path_expr(vec![name])));
+        });
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let mut items: SmallVec<[P<Item>; 1]> =
+                new_items.remove(&i.id).unwrap_or_else(Vec::new).into();
+            items.push(i);
+            items
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Pick a name for the item holding the table in local `ident` of function `fn_name`, which
+/// doesn't clash with any of `names`.
+fn table_name(names: &HashSet<Symbol>, fn_name: Ident, ident: Ident) -> Symbol {
+    let name = ident.as_str().to_uppercase();
+    let prefixed = format!("{}_{}", fn_name.as_str().to_uppercase(), name);
+    iter::once(name)
+        .chain(iter::once(prefixed.clone()))
+        .chain((2..).map(|i| format!("{}_{}", prefixed, i)))
+        .map(|s| Symbol::intern(&s))
+        .find(|s| !names.contains(s))
+        .unwrap()
+}
+
+/// Check whether `e` can be evaluated at compile time in a module-level item: it may use
+/// literals, constants, functions, and calls to constructors and `const fn`s, as long as they
+/// aren't defined inside a function.
+fn is_const_init(cx: &RefactorCtxt, e: &Expr) -> bool {
+    let tcx = cx.ty_ctxt();
+    let visible_def = |did: DefId| {
+        !iter::successors(tcx.parent(did), |&did| tcx.parent(did))
+            .any(|did| matches!([tcx.def_kind(did)] Some(DefKind::Fn), Some(DefKind::Method)))
+    };
+    match e.kind {
+        ExprKind::Lit(_) => true,
+        ExprKind::Array(ref es) |
+        ExprKind::Tup(ref es) => es.iter().all(|e| is_const_init(cx, e)),
+        ExprKind::Repeat(ref e, _) |
+        ExprKind::Paren(ref e) |
+        ExprKind::Cast(ref e, _) |
+        ExprKind::Unary(UnOp::Neg, ref e) |
+        ExprKind::Unary(UnOp::Not, ref e) |
+        ExprKind::AddrOf(_, Mutability::Immutable, ref e) => is_const_init(cx, e),
+        ExprKind::Binary(_, ref a, ref b) => is_const_init(cx, a) && is_const_init(cx, b),
+        ExprKind::Struct(_, ref fields, None) => fields.iter().all(|f| is_const_init(cx, &f.expr)),
+        ExprKind::Call(ref func, ref args) => {
+            let const_callee = match cx.try_resolve_expr_hir(func) {
+                Some(Res::Def(DefKind::Ctor(..), did)) => visible_def(did),
+                Some(Res::Def(_, did)) => tcx.is_const_fn(did) && visible_def(did),
+                _ => false,
+            };
+            const_callee && args.iter().all(|e| is_const_init(cx, e))
+        }
+        ExprKind::Path(..) => match cx.try_resolve_expr_hir(e) {
+            Some(Res::Def(DefKind::Const, did)) |
+            Some(Res::Def(DefKind::AssocConst, did)) |
+            Some(Res::Def(DefKind::Ctor(..), did)) |
+            Some(Res::Def(DefKind::Fn, did)) |
+            Some(Res::Def(DefKind::Method, did)) => visible_def(did),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Rewrites the uses of promoted locals, which now hold a reference to their table.
+struct TableRefRewriter<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    locals: &'a HashSet<HirId>,
+    /// Uses that are auto-dereferenced: the bases of indexing, field accesses and method calls.
+    auto_deref: HashSet<NodeId>,
+}
+
+impl<'a, 'b, 'tcx> TableRefRewriter<'a, 'b, 'tcx> {
+    fn is_local(&self, e: &Expr) -> bool {
+        matches!([e.kind] ExprKind::Path(None, _)) &&
+            self.cx.try_resolve_expr_to_hid(e).map_or(false, |id| self.locals.contains(&id))
+    }
+}
+
+impl<'a, 'b, 'tcx> MutVisitor for TableRefRewriter<'a, 'b, 'tcx> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        match e.kind {
+            ExprKind::AddrOf(_, Mutability::Immutable, ref inner) if self.is_local(inner) => {
+                *e = inner.clone();
+                return;
+            }
+            ExprKind::Index(ref base, _) | ExprKind::Field(ref base, _) => {
+                self.auto_deref.insert(base.id);
+            }
+            ExprKind::MethodCall(_, ref args) => {
+                self.auto_deref.insert(args[0].id);
+            }
+            _ => {}
+        }
+        mut_visit::noop_visit_expr(e, self);
+        if self.is_local(e) && !self.auto_deref.contains(&e.id) {
+            *e = mk().span(e.span).unary_expr(UnOp::Deref, e.clone());
+        }
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
 
 
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("static_collect_to_struct", |args| mk(CollectToStruct {
-        struct_name: args[0].clone(),
-        instance_name: args[1].clone(),
-    }));
-    reg.register("static_to_local_ref", |_args| mk(Localize));
-    reg.register("static_to_local", |_args| mk(StaticToLocal));
+    let spec = CommandSpec::new("Collect the statics marked `target` into one static struct.")
+        .arg("STRUCT", ArgType::Str, "the name of the new struct type")
+        .arg("VAR", ArgType::Str, "the name of the new static");
+    reg.register("static_collect_to_struct", spec, |args| {
+        Ok(mk(CollectToStruct {
+            struct_name: args.str("STRUCT").to_owned(),
+            instance_name: args.str("VAR").to_owned(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Pass statics marked `target` to `user` functions by reference.");
+    reg.register("static_to_local_ref", spec, |_args| Ok(mk(Localize)));
+
+    let spec = CommandSpec::new("Replace each static marked `target` with locals in its users.");
+    reg.register("static_to_local", spec, |_args| Ok(mk(StaticToLocal)));
+
+    let spec = CommandSpec::new("Move the `static mut`s marked `target` into a state struct.")
+        .optional_arg("STRUCT", ArgType::Str, Some("State"), "the name of the new struct type")
+        .option("-o", "REPORT", ArgType::Str, None, "also write the report to REPORT as JSON");
+    reg.register("globals_to_struct", spec, |args| {
+        Ok(mk(GlobalsToStruct {
+            struct_name: args.str("STRUCT").to_owned(),
+            report_path: args.opt_str("-o").map(|s| s.to_owned()),
+        }))
+    });
+
+    let spec = CommandSpec::new("Convert the `static mut`s marked `target` to atomics.")
+        .optional_arg(
+            "ORDERING",
+            ArgType::Str,
+            Some("Relaxed"),
+            "the memory ordering: `Relaxed`, `SeqCst`, or `AcqRel`",
+        );
+    reg.register("convert_static_mut_to_atomic", spec, |args| {
+        Ok(mk(StaticToAtomic {
+            ordering: args.str("ORDERING").to_owned(),
+        }))
+    });
+
+    let spec = CommandSpec::new("Make accesses to the statics marked `target` volatile.")
+        .option("-o", "REPORT", ArgType::Str, None, "also write the report to REPORT as JSON");
+    reg.register("wrap_volatile_statics", spec, |args| {
+        Ok(mk(WrapVolatileStatics {
+            report_path: args.opt_str("-o").map(|s| s.to_owned()),
+        }))
+    });
+
+    let spec = CommandSpec::new("Hoist large constant tables out of functions.")
+        .optional_arg("MIN_SIZE", ArgType::Int, Some("64"), "the smallest size to hoist, in bytes");
+    reg.register("promote_local_consts", spec, |args| {
+        let min_size = args.int("MIN_SIZE");
+        if min_size < 0 {
+            return Err(format!("MIN_SIZE must not be negative, got {}", min_size));
+        }
+        Ok(mk(PromoteLocalConsts {
+            min_size: min_size as u64,
+        }))
+    });
 }
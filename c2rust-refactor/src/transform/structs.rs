@@ -1,14 +1,28 @@
-use rustc::ty;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::mem;
+use rustc::hir::{self, HirId};
+use rustc::hir::def::{CtorOf, DefKind, Res};
+use rustc::hir::def_id::DefId;
+use rustc::ty::{self, ParamEnv};
+use rustc_target::spec::abi::Abi;
 use syntax::ast::*;
+use syntax::mut_visit::{self, MutVisitor};
 use syntax::ptr::P;
+use syntax::symbol::Symbol;
+use syntax_pos::Span;
 
 use smallvec::smallvec;
 
-use crate::ast_manip::{fold_blocks, FlatMapNodes, AstEquiv};
+use crate::ast_manip::{fold_blocks, visit_nodes, FlatMapNodes, MutVisit, MutVisitNodes, AstEquiv};
+use crate::ast_manip::fn_edit::visit_fns;
 use crate::command::{CommandState, Registry};
-use crate::driver::{Phase, parse_expr};
+use crate::command_args::{ArgType, CommandSpec};
+use crate::driver::{Phase, parse_attr, parse_expr, parse_items};
 use crate::matcher::{mut_visit_match, Subst};
 use crate::path_edit::fold_resolved_paths;
+use crate::transform::heap::{default_call, is_mem_fn, is_std_fn, strip_casts, type_implements};
+use crate::transform::memory::{default_is_zero, index_offsets};
 use crate::transform::Transform;
 use c2rust_ast_builder::{mk, IntoSymbol};
 use crate::RefactorCtxt;
@@ -16,8 +30,6 @@ use crate::RefactorCtxt;
 
 /// # `struct_assign_to_update` Command
 ///
-/// Usage: `struct_assign_to_update`
-///
 /// Replace all struct field assignments with functional update expressions.
 ///
 /// Example:
@@ -64,8 +76,6 @@ impl Transform for AssignToUpdate {
 
 /// # `struct_merge_updates` Command
 ///
-/// Usage: `struct_merge_updates`
-///
 /// Merge consecutive struct updates into a single update.
 ///
 /// Example:
@@ -143,8 +153,6 @@ fn build_struct_update(path: Path, fields: Vec<Field>, base: P<Expr>) -> Stmt {
 ///
 /// Obsolete - use `rename_items_regex` instead.
 ///
-/// Usage: `rename_struct NAME`
-///
 /// Marks: `target`
 ///
 /// Rename the struct marked `target` to `NAME`.  Only supports renaming a single
@@ -209,10 +217,1615 @@ fn is_struct(i: &Item) -> bool {
 }
 
 
+/// # `derive_default_and_use` Command
+///
+/// Marks: `target`
+///
+/// Give each struct marked `target` a `Default` impl, and use it in place of zero initialization.
+/// The struct gets `#[derive(Default)]` if the types of all its fields implement `Default`,
+/// counting the other `target` structs.  Otherwise, if every field still has an obvious default
+/// value, it gets a manual `impl Default` that uses `ptr::null_mut()` for raw pointers and
+/// `[x; N]` for arrays too long to implement `Default`.  A struct with a field that has no
+/// default value, like a reference, is left alone, with a warning.
+///
+/// Then, for the structs that now implement `Default`:
+///
+///  * Struct literals that set every field to zero, like `Foo { a: 0, b: 0 as *mut T, c: None }`,
+///    become `Foo::default()`.
+///  * Calls to `mem::zeroed::<Foo>()` become `Foo::default()` (or `Default::default()` if the
+///    type is inferred), but only if the default value is all zero bytes.  It isn't if, for
+///    example, the struct has an `Option<i32>` field, since `None` isn't zero.  Structs like
+///    this are reported with a warning instead.
+pub struct DeriveDefaultAndUse;
+
+/// How to build the default value of a field's type.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum DefaultKind {
+    /// `Default::default()` works for the type.
+    Trait,
+    /// The type doesn't implement `Default`, but has an obvious default value, like a null
+    /// pointer.
+    Manual,
+}
+
+impl Transform for DeriveDefaultAndUse {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+
+        // (1) Find the marked structs, and check that all their fields have default values.
+        // Structs that contain a rejected struct are rejected in turn.
+
+        let mut structs = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") || !matches!([i.kind] ItemKind::Struct(..)) {
+                return;
+            }
+            let did = cx.node_def_id(i.id);
+            let reason = if !tcx.generics_of(did).params.is_empty() {
+                Some("it's generic".to_owned())
+            } else if type_implements(cx, tcx.type_of(did), i.id, "Default") {
+                Some("it already implements `Default`".to_owned())
+            } else {
+                None
+            };
+            match reason {
+                Some(reason) => cx.session().span_warn(
+                    i.span,
+                    &format!("not deriving `Default` for `{}`: {}", i.ident, reason),
+                ),
+                None => {
+                    structs.insert(did, (i.id, i.span, i.ident));
+                }
+            }
+        });
+
+        let mut reasons = Vec::new();
+        let mut defaults = HashMap::new();
+        loop {
+            let dids = structs.keys().cloned().collect::<HashSet<_>>();
+            defaults.clear();
+            for (&did, &(id, span, ident)) in &structs {
+                let mut fields = Vec::new();
+                for f in &tcx.adt_def(did).non_enum_variant().fields {
+                    let ty = tcx.type_of(f.did);
+                    match field_default(cx, &dids, ty, id) {
+                        Some(x) => fields.push(x),
+                        None => {
+                            reasons.push((span, ident, format!(
+                                "field `{}` of type `{}` has no default value", f.ident, ty)));
+                            break;
+                        }
+                    }
+                }
+                if fields.len() == tcx.adt_def(did).non_enum_variant().fields.len() {
+                    let derive = fields.iter().all(|&(kind, _)| kind == DefaultKind::Trait);
+                    let zero = fields.iter().all(|&(_, zero)| zero);
+                    defaults.insert(did, (derive, zero));
+                }
+            }
+            if defaults.len() == structs.len() {
+                break;
+            }
+            structs.retain(|did, _| defaults.contains_key(did));
+        }
+        reasons.sort_by_key(|&(span, _, _)| span);
+        for (span, ident, reason) in reasons {
+            cx.session().span_warn(
+                span,
+                &format!("not deriving `Default` for `{}`: {}", ident, reason),
+            );
+        }
+
+        // (2) Find the zero struct literals and `mem::zeroed()` calls to replace.
+
+        let dids = structs.keys().cloned().collect::<HashSet<_>>();
+        let struct_did = |e: &Expr| match cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+            Some(&ty::TyKind::Adt(def, _)) if dids.contains(&def.did) => Some(def.did),
+            _ => None,
+        };
+        let mut replace = HashMap::new();
+        let mut differ = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            match e.kind {
+                ExprKind::Struct(ref path, _, None) => {
+                    if struct_did(e).is_some() && is_default_value(cx, &dids, e) {
+                        replace.insert(e.id, default_call(&mk().path_ty(path.clone())));
+                    }
+                }
+                ExprKind::Call(ref callee, ref args) if args.is_empty() => {
+                    let did = match_or!([struct_did(e)] Some(x) => x; return);
+                    if !cx.opt_callee(e).map_or(false, |f| is_mem_fn(cx, f, "zeroed")) {
+                        return;
+                    }
+                    if !defaults[&did].1 {
+                        differ.insert(did);
+                        return;
+                    }
+                    replace.insert(e.id, match zeroed_ty_arg(callee) {
+                        Some(ty) => default_call(ty),
+                        None => mk().call_expr(mk().path_expr(vec!["Default", "default"]),
+                                               Vec::<P<Expr>>::new()),
+                    });
+                }
+                _ => {}
+            }
+        });
+
+        let mut differ = differ.into_iter().collect::<Vec<_>>();
+        differ.sort_by_key(|did| structs[did].1);
+        for did in differ {
+            let (_, span, ident) = structs[&did];
+            cx.session().span_warn(
+                span,
+                &format!("not replacing `mem::zeroed()` with `{}::default()`: the default \
+                          value isn't all zero bytes", ident),
+            );
+        }
+
+        // (3) Add the `Default` impls, and replace the zero values.
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let did = match_or!([i.kind] ItemKind::Struct(..) => cx.node_def_id(i.id);
+                                return smallvec![i]);
+            let derive = match_or!([defaults.get(&did)] Some(&(derive, _)) => derive;
+                                   return smallvec![i]);
+            if derive {
+                let attr = parse_attr(cx.session(), "#[derive(Default)]");
+                return smallvec![i.map(|mut i| {
+                    i.attrs.push(attr);
+                    i
+                })];
+            }
+
+            let variant = tcx.adt_def(did).non_enum_variant();
+            let values = variant.fields.iter()
+                .map(|f| (f.ident, default_src(cx, &dids, tcx.type_of(f.did), i.id)));
+            let value = match i.kind {
+                ItemKind::Struct(VariantData::Tuple(..), _) => format!(
+                    "{}({})", i.ident,
+                    values.map(|(_, v)| v).collect::<Vec<_>>().join(", ")),
+                _ => format!(
+                    "{} {{ {} }}", i.ident,
+                    values.map(|(f, v)| format!("{}: {}", f, v)).collect::<Vec<_>>().join(", ")),
+            };
+            let src = format!("impl Default for {} {{ fn default() -> Self {{ {} }} }}",
+                              i.ident, value);
+            let mut items = smallvec![i];
+            items.extend(parse_items(cx.session(), &src));
+            items
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if let Some(new_expr) = replace.get(&e.id) {
+                *e = new_expr.clone();
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Classify the default value of a field of type `ty`: how to build it, and whether it's all zero
+/// bytes.  `structs` are the structs getting `Default` impls.  Returns `None` if the type has no
+/// obvious default value.
+fn field_default<'tcx>(cx: &RefactorCtxt<'_, 'tcx>, structs: &HashSet<DefId>, ty: ty::Ty<'tcx>,
+                       id: NodeId) -> Option<(DefaultKind, bool)> {
+    let tcx = cx.ty_ctxt();
+    match ty.kind {
+        ty::TyKind::Bool | ty::TyKind::Char | ty::TyKind::Int(_) | ty::TyKind::Uint(_) |
+        ty::TyKind::Float(_) => Some((DefaultKind::Trait, true)),
+        ty::TyKind::RawPtr(_) => Some((DefaultKind::Manual, true)),
+        ty::TyKind::Array(elem, len) => {
+            let (kind, zero) = field_default(cx, structs, elem, id)?;
+            let len = len.try_eval_usize(tcx, ParamEnv::empty())?;
+            // `Default` is only implemented for arrays of up to 32 elements.  Longer ones can
+            // still be built with `[x; N]` if the element type is `Copy`.
+            if kind == DefaultKind::Trait && len <= 32 {
+                Some((DefaultKind::Trait, zero))
+            } else if type_implements(cx, elem, id, "Copy") {
+                Some((DefaultKind::Manual, zero))
+            } else {
+                None
+            }
+        }
+        ty::TyKind::Tuple(_) => {
+            let fields = ty.tuple_fields()
+                .map(|ty| field_default(cx, structs, ty, id))
+                .collect::<Option<Vec<_>>>()?;
+            if fields.len() > 12 || fields.iter().any(|&(kind, _)| kind != DefaultKind::Trait) {
+                return None;
+            }
+            Some((DefaultKind::Trait, fields.iter().all(|&(_, zero)| zero)))
+        }
+        ty::TyKind::Adt(def, substs) if structs.contains(&def.did) => {
+            let zero = def.all_fields().all(|f| {
+                field_default(cx, structs, f.ty(tcx, substs), id).map_or(false, |(_, zero)| zero)
+            });
+            Some((DefaultKind::Trait, zero))
+        }
+        ty::TyKind::Adt(def, substs) if is_option(cx, def.did) => {
+            // `None` is all zero bytes when the null pointer optimization applies.
+            let zero = match substs.type_at(0).kind {
+                ty::TyKind::FnPtr(_) | ty::TyKind::Ref(..) => true,
+                ty::TyKind::Adt(def, _) => def.is_box(),
+                _ => false,
+            };
+            Some((DefaultKind::Trait, zero))
+        }
+        _ if type_implements(cx, ty, id, "Default") => {
+            Some((DefaultKind::Trait, default_is_zero(cx, ty)))
+        }
+        _ => None,
+    }
+}
+
+/// Source code for the default value of a field of type `ty` in a manual `Default` impl.
+fn default_src<'tcx>(cx: &RefactorCtxt<'_, 'tcx>, structs: &HashSet<DefId>, ty: ty::Ty<'tcx>,
+                     id: NodeId) -> String {
+    match ty.kind {
+        ty::TyKind::RawPtr(mt) => match mt.mutbl {
+            hir::Mutability::Mutable => "::std::ptr::null_mut()".to_owned(),
+            hir::Mutability::Immutable => "::std::ptr::null()".to_owned(),
+        },
+        ty::TyKind::Array(elem, len)
+                if field_default(cx, structs, ty, id).map(|x| x.0) == Some(DefaultKind::Manual) => {
+            let len = len.try_eval_usize(cx.ty_ctxt(), ParamEnv::empty()).unwrap();
+            format!("[{}; {}]", default_src(cx, structs, elem, id), len)
+        }
+        _ => "Default::default()".to_owned(),
+    }
+}
+
+/// Check whether `e` is the default value of its type: a zero number, `false`, `'\0'`, a null
+/// pointer, `None`, or an array, tuple or literal of one of `structs` made of these.
+fn is_default_value(cx: &RefactorCtxt, structs: &HashSet<DefId>, e: &Expr) -> bool {
+    let tcx = cx.ty_ctxt();
+    let mut e = e;
+    loop {
+        match e.kind {
+            ExprKind::Cast(ref inner, _) | ExprKind::Paren(ref inner) => e = inner,
+            _ => break,
+        }
+    }
+    match e.kind {
+        ExprKind::Lit(ref lit) => match lit.kind {
+            LitKind::Int(0, _) | LitKind::Bool(false) | LitKind::Char('\0') => true,
+            LitKind::Float(sym, _) => sym.as_str().parse::<f64>() == Ok(0.0),
+            _ => false,
+        },
+        ExprKind::Path(..) => {
+            // `None` resolves to the constructor of the variant.
+            let variant = cx.try_resolve_expr(e).and_then(|ctor| tcx.parent(ctor));
+            variant.map_or(false, |variant| {
+                tcx.item_name(variant).as_str() == "None" &&
+                    tcx.parent(variant).map_or(false, |did| is_option(cx, did))
+            })
+        }
+        ExprKind::Call(_, ref args) if args.is_empty() => {
+            cx.opt_callee(e).map_or(false, |f| {
+                is_std_fn(cx, f, "ptr", "null_mut") || is_std_fn(cx, f, "ptr", "null")
+            })
+        }
+        ExprKind::Repeat(ref elem, _) => is_default_value(cx, structs, elem),
+        ExprKind::Array(ref elems) | ExprKind::Tup(ref elems) => {
+            elems.iter().all(|e| is_default_value(cx, structs, e))
+        }
+        ExprKind::Struct(_, ref fields, None) => match cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+            Some(&ty::TyKind::Adt(def, _)) if structs.contains(&def.did) => {
+                fields.len() == def.non_enum_variant().fields.len() &&
+                    fields.iter().all(|f| is_default_value(cx, structs, &f.expr))
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+pub(crate) fn is_option(cx: &RefactorCtxt, did: DefId) -> bool {
+    let tcx = cx.ty_ctxt();
+    let crate_name = tcx.crate_name(did.krate);
+    tcx.item_name(did).as_str() == "Option" &&
+    (crate_name.as_str() == "core" || crate_name.as_str() == "std")
+}
+
+/// Get `T` from the callee of `mem::zeroed::<T>()`.
+fn zeroed_ty_arg(callee: &Expr) -> Option<&P<Ty>> {
+    let path = match_or!([callee.kind] ExprKind::Path(None, ref path) => path; return None);
+    let args = path.segments.last()?.args.as_ref()?;
+    match **args {
+        GenericArgs::AngleBracketed(ref data) => match data.args.get(0) {
+            Some(GenericArg::Type(ty)) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// # `rename_fields` Command
+///
+/// Rename the field `OLD` of the struct or union at the crate-relative path `TYPE`, e.g.,
+/// `list::node`, to `NEW`.  With `--file`, the renames are read from `MAPPING`, a JSON file
+/// containing an array of `{ "type": TYPE, "old": OLD, "new": NEW }` objects.
+///
+/// The field is renamed in the type definition and in every field access, struct literal and
+/// struct pattern crate-wide, including accesses through references, `Deref` chains and raw
+/// pointer dereferences like `(*p).nxt`, and `offset_of`-style accesses like
+/// `&(*(0 as *const T)).nxt`.  Fields of the same name on other types are left alone.
+/// Shorthand literals and patterns like `T { nxt }` become `T { next: nxt }`.
+///
+/// A rename is refused, with a warning, if the new name clashes with another field of the same
+/// type.  Since the layout of a struct doesn't depend on field names, renaming never affects
+/// FFI, but a warning is still printed for each renamed type that appears in the signature of
+/// an `extern` function, since C code may refer to the field by name through macros.
+pub struct RenameFields {
+    pub renames: Vec<(String, Symbol, Symbol)>,
+}
+
+impl Transform for RenameFields {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+
+        // (1) Find the types, and check that each rename is possible.
+
+        let mut types = HashMap::new();
+        visit_nodes(krate, |i: &Item| match i.kind {
+            ItemKind::Struct(VariantData::Struct(ref fields, _), _) |
+            ItemKind::Union(VariantData::Struct(ref fields, _), _) => {
+                let did = cx.node_def_id(i.id);
+                let names = fields.iter().filter_map(|f| f.ident).map(|i| i.name).collect();
+                types.insert(tcx.def_path_str(did), (did, names));
+            }
+            _ => {}
+        });
+
+        let mut renames: HashMap<DefId, HashMap<Symbol, Symbol>> = HashMap::new();
+        let mut taken: HashMap<DefId, HashSet<Symbol>> = HashMap::new();
+        for &(ref ty_path, old, new) in &self.renames {
+            let ty_path = ty_path.trim_start_matches("crate::");
+            let (did, names) = match types.get(ty_path) {
+                Some(&(did, ref names)) => (did, names),
+                None => {
+                    cx.session().warn(&format!("no struct or union named `{}`", ty_path));
+                    continue;
+                }
+            };
+            let fields = taken.entry(did).or_insert_with(|| names.clone());
+            if !fields.contains(&old) {
+                cx.session().warn(&format!("`{}` has no field `{}`", ty_path, old));
+                continue;
+            }
+            if fields.contains(&new) {
+                cx.session().warn(&format!(
+                    "not renaming `{}::{}` to `{}`: `{}` already has a field with that name",
+                    ty_path, old, new, ty_path));
+                continue;
+            }
+            fields.remove(&old);
+            fields.insert(new);
+            renames.entry(did).or_default().insert(old, new);
+        }
+        if renames.is_empty() {
+            return;
+        }
+
+        // (2) Warn about renamed types in `extern` signatures.
+
+        let warn_extern = |id: NodeId, span: Span| {
+            let did = cx.node_def_id(id);
+            let sig = tcx.fn_sig(did);
+            if sig.abi() == Abi::Rust {
+                return;
+            }
+            let renamed = sig.skip_binder().inputs_and_output.iter()
+                .flat_map(|ty| ty.walk())
+                .filter_map(|ty| match ty.kind {
+                    ty::TyKind::Adt(def, _) if renames.contains_key(&def.did) => Some(def.did),
+                    _ => None,
+                })
+                .next();
+            if let Some(ty_did) = renamed {
+                cx.session().span_warn(
+                    span,
+                    &format!("renamed fields of `{}`, which is used by this extern function; \
+                              C code may still refer to the old field names",
+                             tcx.def_path_str(ty_did)),
+                );
+            }
+        };
+        visit_nodes(krate, |i: &Item| {
+            if let ItemKind::Fn(..) = i.kind {
+                warn_extern(i.id, i.span);
+            }
+        });
+        visit_nodes(krate, |i: &ForeignItem| {
+            if let ForeignItemKind::Fn(..) = i.kind {
+                warn_extern(i.id, i.span);
+            }
+        });
+
+        // (3) Rename the fields in the definitions, accesses, literals and patterns.
+
+        let renamed_field = |ty: Option<ty::Ty>, name: Symbol| {
+            let did = adt_def_id(ty?)?;
+            renames.get(&did)?.get(&name).cloned()
+        };
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let did = match i.kind {
+                ItemKind::Struct(..) | ItemKind::Union(..) => cx.node_def_id(i.id),
+                _ => return smallvec![i],
+            };
+            let fields = match_or!([renames.get(&did)] Some(x) => x; return smallvec![i]);
+            smallvec![i.map(|mut i| {
+                match i.kind {
+                    ItemKind::Struct(VariantData::Struct(ref mut fs, _), _) |
+                    ItemKind::Union(VariantData::Struct(ref mut fs, _), _) => {
+                        for f in fs {
+                            let ident = match_or!([f.ident] Some(ref mut x) => x; continue);
+                            if let Some(&new) = fields.get(&ident.name) {
+                                ident.name = new;
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                i
+            })]
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let ty = cx.opt_node_type(e.id);
+            match e.kind {
+                ExprKind::Field(ref base, ref mut ident) => {
+                    // Field accesses auto-deref their base, so we need the adjusted type
+                    let base_ty = cx.opt_adjusted_node_type(base.id);
+                    if let Some(new) = renamed_field(base_ty, ident.name) {
+                        ident.name = new;
+                    }
+                }
+                ExprKind::Struct(_, ref mut fields, _) => {
+                    for f in fields {
+                        if let Some(new) = renamed_field(ty, f.ident.name) {
+                            f.ident.name = new;
+                            f.is_shorthand = false;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        });
+
+        MutVisitNodes::visit(krate, |p: &mut P<Pat>| {
+            let ty = cx.opt_node_type(p.id);
+            if let PatKind::Struct(_, ref mut fields, _) = p.kind {
+                for f in fields {
+                    if let Some(new) = renamed_field(ty, f.ident.name) {
+                        f.ident.name = new;
+                        f.is_shorthand = false;
+                    }
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Get the struct or union definition of `ty`, looking through references and pointers.
+fn adt_def_id(ty: ty::Ty) -> Option<DefId> {
+    match ty.kind {
+        ty::TyKind::Adt(def, _) if def.is_struct() || def.is_union() => Some(def.did),
+        ty::TyKind::Ref(_, ty, _) | ty::TyKind::RawPtr(ty::TypeAndMut { ty, .. }) => {
+            adt_def_id(ty)
+        }
+        _ => None,
+    }
+}
+
+/// Read a list of field renames from a JSON file.
+fn read_field_renames(path: &str) -> Vec<(String, Symbol, Symbol)> {
+    let src = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+    let json = json::parse(&src)
+        .unwrap_or_else(|e| panic!("failed to parse {}: {}", path, e));
+    json.members().enumerate().map(|(i, entry)| {
+        let field = |key: &str| entry[key].as_str()
+            .unwrap_or_else(|| panic!("expected a string `{}` in entry {} of {}", key, i, path));
+        (field("type").to_owned(), field("old").into_symbol(), field("new").into_symbol())
+    }).collect()
+}
+
+
+/// # `convert_char_array_fields` Command
+///
+/// Marks: `target`
+///
+/// Retype each marked struct field of type `[c_char; N]` to `[u8; N]`, and give its struct a
+/// pair of accessors for using the field as a NUL-terminated string: `fn name_str(&self) -> &str`
+/// returns the contents up to the first NUL, or up to the first invalid UTF-8 sequence, and
+/// `fn set_name(&mut self, s: &str)` stores `s`, truncated at a character boundary to leave room
+/// for the NUL terminator, and zeroes the rest of the buffer.
+///
+/// Accesses to the field are fixed up crate-wide:
+///
+///  * Pointer arithmetic into the array, like `*(*s).name.as_mut_ptr().offset(i as isize)`,
+///    becomes indexing, as in `convert_ptr_offset_to_index`.
+///  * Pointers into the array, from `name.as_ptr()`, `name.as_mut_ptr()` or casts like
+///    `&mut (*s).name as *mut [c_char; N] as *mut c_char`, are cast back to `*const c_char` or
+///    `*mut c_char`, so they can still be passed to C functions.
+///  * A `c_char` value that is compared, combined with or assigned to an element of the array is
+///    cast to `u8` instead, so `(*s).name[0] == 0 as c_char` becomes `(*s).name[0] == 0 as u8`.
+///    Other reads of an element are cast back to `c_char`.
+///  * Initializers like `[0 as c_char; N]` and `transmute::<[u8; N], [c_char; N]>(*b"...")`
+///    become byte arrays.
+///
+/// Elements that are already cast to another type, like `(*s).name[i] as c_int`, are left
+/// alone.  On targets where `c_char` is signed, this zero-extends bytes above `0x7f` where the
+/// original code sign-extended them.  Borrows of single elements and initializers that can't
+/// be converted are left alone with a warning.
+///
+/// The accessors are named after the field, so a field is skipped, with a warning, if its
+/// struct already has a method with one of their names.  Generic structs are skipped as well.
+pub struct ConvertCharArrayFields;
+
+impl Transform for ConvertCharArrayFields {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+
+        // (1) Find the marked fields, and check that their accessors don't clash with existing
+        // methods.
+
+        let mut methods: HashMap<DefId, HashSet<Symbol>> = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if let ItemKind::Impl(_, _, _, _, None, _, ref items) = i.kind {
+                if let Some(did) = adt_def_id(tcx.type_of(cx.node_def_id(i.id))) {
+                    methods.entry(did).or_default().extend(items.iter().map(|ii| ii.ident.name));
+                }
+            }
+        });
+
+        let mut targets: HashMap<DefId, HashMap<Symbol, P<Ty>>> = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            let (fields, generics) = match_or!([i.kind]
+                ItemKind::Struct(VariantData::Struct(ref fs, _), ref g) => (fs, g); return);
+            let did = cx.node_def_id(i.id);
+            for f in fields {
+                if !st.marked(f.id, "target") {
+                    continue;
+                }
+                let ident = match_or!([f.ident] Some(x) => x; continue);
+                let elem = match f.ty.kind {
+                    TyKind::Array(ref elem, _)
+                        if is_char_array(tcx.type_of(cx.node_def_id(f.id))) => elem,
+                    _ => {
+                        cx.session().span_warn(f.span, &format!(
+                            "not converting `{}`: it is not a `c_char` array", ident));
+                        continue;
+                    }
+                };
+                if !generics.params.is_empty() {
+                    cx.session().span_warn(f.span, &format!(
+                        "not converting `{}`: `{}` is generic", ident, i.ident));
+                    continue;
+                }
+                let clash = [format!("{}_str", ident), format!("set_{}", ident)].iter()
+                    .find(|&name| methods.get(&did)
+                          .map_or(false, |names| names.contains(&Symbol::intern(name))))
+                    .cloned();
+                if let Some(name) = clash {
+                    cx.session().span_warn(f.span, &format!(
+                        "not converting `{}`: `{}` already has a method named `{}`",
+                        ident, i.ident, name));
+                    continue;
+                }
+                targets.entry(did).or_default().insert(ident.name, elem.clone());
+            }
+        });
+        if targets.is_empty() {
+            return;
+        }
+
+        // (2) Turn pointer arithmetic into the fields into indexing, then fix up the types of
+        // the remaining accesses.
+
+        let mut fixer = CharArrayFixer { cx, targets };
+        index_offsets(krate, cx, false, &|buf| fixer.target_field(buf).is_some());
+        krate.visit(&mut fixer);
+        let targets = fixer.targets;
+
+        // (3) Retype the fields and add the accessors.
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let did = match i.kind {
+                ItemKind::Struct(..) => cx.node_def_id(i.id),
+                _ => return smallvec![i],
+            };
+            let fields = match_or!([targets.get(&did)] Some(x) => x; return smallvec![i]);
+            let mut accessors = String::new();
+            let i = i.map(|mut i| {
+                if let ItemKind::Struct(VariantData::Struct(ref mut fs, _), _) = i.kind {
+                    for f in fs {
+                        let ident = match_or!([f.ident] Some(x) => x; continue);
+                        if !fields.contains_key(&ident.name) {
+                            continue;
+                        }
+                        if let TyKind::Array(ref mut elem, _) = f.ty.kind {
+                            *elem = mk().path_ty(vec!["u8"]);
+                        }
+                        accessors.push_str(&format!(r#"
+                            pub fn {f}_str(&self) -> &str {{
+                                let len = self.{f}.iter().position(|&b| b == 0)
+                                    .unwrap_or(self.{f}.len());
+                                match ::std::str::from_utf8(&self.{f}[..len]) {{
+                                    Ok(s) => s,
+                                    Err(e) => ::std::str::from_utf8(&self.{f}[..e.valid_up_to()])
+                                        .unwrap(),
+                                }}
+                            }}
+
+                            pub fn set_{f}(&mut self, s: &str) {{
+                                let mut len = ::std::cmp::min(s.len(),
+                                                              self.{f}.len().saturating_sub(1));
+                                while !s.is_char_boundary(len) {{
+                                    len -= 1;
+                                }}
+                                self.{f}[..len].copy_from_slice(&s.as_bytes()[..len]);
+                                for b in &mut self.{f}[len..] {{
+                                    *b = 0;
+                                }}
+                            }}
+                        "#, f = ident));
+                    }
+                }
+                i
+            });
+            let src = format!("impl {} {{ {} }}", i.ident, accessors);
+            let mut items = smallvec![i];
+            items.extend(parse_items(cx.session(), &src));
+            items
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Check if `ty` is an array of `i8` or `u8`, which are the possible types of `c_char`.
+fn is_char_array(ty: ty::Ty) -> bool {
+    match ty.kind {
+        ty::TyKind::Array(elem, _) => is_char_ty(elem),
+        _ => false,
+    }
+}
+
+fn is_char_ty(ty: ty::Ty) -> bool {
+    match ty.kind {
+        ty::TyKind::Int(IntTy::I8) => true,
+        _ => is_byte_ty(ty),
+    }
+}
+
+fn is_byte_ty(ty: ty::Ty) -> bool {
+    match ty.kind {
+        ty::TyKind::Uint(UintTy::U8) => true,
+        _ => false,
+    }
+}
+
+/// Cast the `c_char` value `e` to `u8`, replacing the last cast if there is one.
+fn char_to_byte(e: &P<Expr>) -> P<Expr> {
+    match e.kind {
+        ExprKind::Lit(Lit { kind: LitKind::Int(_, LitIntType::Unsuffixed), .. }) => e.clone(),
+        ExprKind::Cast(ref inner, _) => {
+            mk().span(e.span).cast_expr(inner.clone(), mk().path_ty(vec!["u8"]))
+        }
+        _ => mk().span(e.span).cast_expr(e.clone(), mk().path_ty(vec!["u8"])),
+    }
+}
+
+struct CharArrayFixer<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    /// The original element type of each converted field.
+    targets: HashMap<DefId, HashMap<Symbol, P<Ty>>>,
+}
+
+impl<'a, 'b, 'tcx> CharArrayFixer<'a, 'b, 'tcx> {
+    /// If `e` accesses a converted field, return the original element type of the field.
+    fn target_field(&self, e: &Expr) -> Option<P<Ty>> {
+        let (base, ident) = match_or!([e.kind] ExprKind::Field(ref b, i) => (b, i); return None);
+        // Field accesses auto-deref their base, so we need the adjusted type
+        let did = adt_def_id(self.cx.opt_adjusted_node_type(base.id)?)?;
+        self.targets.get(&did)?.get(&ident.name).cloned()
+    }
+
+    /// If `e` is `f[i]`, where `f` is a converted field, return the original element type.
+    fn elem(&self, e: &Expr) -> Option<P<Ty>> {
+        match e.kind {
+            ExprKind::Index(ref base, _) => self.target_field(base),
+            ExprKind::Paren(ref inner) => self.elem(inner),
+            _ => None,
+        }
+    }
+
+    /// If `e` is `f.as_ptr()` or `f.as_mut_ptr()`, where `f` is a converted field, return the
+    /// original element type and the mutability of the pointer.
+    fn field_ptr(&self, e: &Expr) -> Option<(P<Ty>, Mutability)> {
+        let (seg, args) = match_or!([e.kind] ExprKind::MethodCall(ref seg, ref args) => (seg, args);
+                                    return None);
+        let mutbl = match &*seg.ident.as_str() {
+            "as_ptr" if args.len() == 1 => Mutability::Immutable,
+            "as_mut_ptr" if args.len() == 1 => Mutability::Mutable,
+            _ => return None,
+        };
+        Some((self.target_field(&args[0])?, mutbl))
+    }
+
+    /// If `e` is `&f` or `&mut f`, possibly cast to other pointer types, where `f` is a
+    /// converted field, return `f` and the mutability of the borrow.
+    fn field_addr<'e>(&self, e: &'e Expr) -> Option<(&'e P<Expr>, Mutability)> {
+        match e.kind {
+            ExprKind::AddrOf(_, mutbl, ref inner) if self.target_field(inner).is_some() => {
+                Some((inner, mutbl))
+            }
+            ExprKind::Cast(ref inner, _) | ExprKind::Paren(ref inner) => self.field_addr(inner),
+            _ => None,
+        }
+    }
+
+    /// Visit an operand of an operation on an element of a converted field, casting it to `u8`
+    /// if it's a `c_char`.
+    fn visit_operand(&mut self, e: &mut P<Expr>) {
+        if self.elem(e).is_some() {
+            mut_visit::noop_visit_expr(e, self);
+            return;
+        }
+        let is_char = self.cx.opt_node_type(e.id).map_or(false, is_char_ty);
+        self.visit_expr(e);
+        if is_char {
+            *e = char_to_byte(e);
+        }
+    }
+
+    /// Convert an initializer of a converted field to a byte array.
+    fn byte_array(&self, e: &P<Expr>) -> P<Expr> {
+        // C string literals are translated to `transmute::<[u8; N], [c_char; N]>(*b"...")`
+        if let ExprKind::Call(ref func, ref args) = e.kind {
+            let is_transmute = match func.kind {
+                ExprKind::Path(_, ref path) => path.segments.last()
+                    .map_or(false, |seg| seg.ident.name == Symbol::intern("transmute")),
+                _ => false,
+            };
+            let of_bytes = args.len() == 1 && self.cx.opt_node_type(args[0].id)
+                .map_or(false, |ty| match ty.kind {
+                    ty::TyKind::Array(elem, _) => is_byte_ty(elem),
+                    _ => false,
+                });
+            if is_transmute && of_bytes {
+                return args[0].clone();
+            }
+        }
+
+        let mut e = e.clone();
+        match e.kind {
+            ExprKind::Repeat(ref mut elem, _) => *elem = char_to_byte(elem),
+            ExprKind::Array(ref mut elems) => {
+                for elem in elems {
+                    *elem = char_to_byte(elem);
+                }
+            }
+            _ => {
+                self.cx.session().span_warn(
+                    e.span, "can't convert this initializer of a `c_char` array to bytes");
+            }
+        }
+        e
+    }
+}
+
+impl<'a, 'b, 'tcx> MutVisitor for CharArrayFixer<'a, 'b, 'tcx> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        // `&mut f as *mut [c_char; N] as *mut c_char` becomes `f.as_mut_ptr() as *mut c_char`.
+        let addr = match e.kind {
+            ExprKind::Cast(ref inner, ref ty) => {
+                self.field_addr(inner).map(|(f, mutbl)| (f.clone(), mutbl, ty.clone()))
+            }
+            _ => None,
+        };
+        if let Some((mut field, mutbl, ty)) = addr {
+            mut_visit::noop_visit_expr(&mut field, self);
+            let method = if mutbl == Mutability::Mutable { "as_mut_ptr" } else { "as_ptr" };
+            let ptr = mk().method_call_expr(field, method, Vec::<P<Expr>>::new());
+            *e = mk().span(e.span).cast_expr(ptr, ty);
+            return;
+        }
+
+        let (cast_of_access, elem_operands, borrowed_elem, field_assign) = match e.kind {
+            // Casts of elements and pointers already give the result the right type.
+            ExprKind::Cast(ref inner, _) => {
+                (self.elem(inner).is_some() || self.field_ptr(inner).is_some(),
+                 false, false, false)
+            }
+            ExprKind::Binary(_, ref l, ref r) => {
+                (false, self.elem(l).is_some() || self.elem(r).is_some(), false, false)
+            }
+            ExprKind::Assign(ref l, _) => {
+                (false, self.elem(l).is_some(), false, self.target_field(l).is_some())
+            }
+            ExprKind::AssignOp(_, ref l, _) => (false, self.elem(l).is_some(), false, false),
+            ExprKind::AddrOf(_, _, ref inner) => (false, false, self.elem(inner).is_some(), false),
+            _ => (false, false, false, false),
+        };
+
+        if cast_of_access {
+            if let ExprKind::Cast(ref mut inner, ref mut ty) = e.kind {
+                mut_visit::noop_visit_expr(inner, self);
+                self.visit_ty(ty);
+            }
+            return;
+        }
+        if elem_operands {
+            match e.kind {
+                ExprKind::Binary(_, ref mut l, ref mut r) => {
+                    self.visit_operand(l);
+                    self.visit_operand(r);
+                }
+                ExprKind::Assign(ref mut l, ref mut r) |
+                ExprKind::AssignOp(_, ref mut l, ref mut r) => {
+                    mut_visit::noop_visit_expr(l, self);
+                    self.visit_operand(r);
+                }
+                _ => {}
+            }
+            return;
+        }
+        if borrowed_elem {
+            self.cx.session().span_warn(
+                e.span, "can't convert this borrow of an element of a `c_char` array");
+            if let ExprKind::AddrOf(_, _, ref mut inner) = e.kind {
+                mut_visit::noop_visit_expr(inner, self);
+            }
+            return;
+        }
+
+        let ty = self.cx.opt_node_type(e.id);
+        mut_visit::noop_visit_expr(e, self);
+
+        if field_assign {
+            if let ExprKind::Assign(_, ref mut r) = e.kind {
+                *r = self.byte_array(r);
+            }
+        } else if let ExprKind::Struct(_, ref mut fields, _) = e.kind {
+            let targets = ty.and_then(adt_def_id).and_then(|did| self.targets.get(&did));
+            if let Some(targets) = targets {
+                for f in fields {
+                    if targets.contains_key(&f.ident.name) {
+                        f.expr = self.byte_array(&f.expr);
+                    }
+                }
+            }
+        }
+
+        // Other uses of elements and pointers get their original types back.
+        if let Some(elem) = self.elem(e) {
+            *e = mk().span(e.span).cast_expr(e.clone(), elem);
+        } else if let Some((elem, mutbl)) = self.field_ptr(e) {
+            *e = mk().span(e.span).cast_expr(e.clone(), mk().set_mutbl(mutbl).ptr_ty(elem));
+        }
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+/// # `convert_to_drop` Command
+///
+/// Give the struct at the crate-relative path `TYPE` a `Drop` impl that calls the function at
+/// the crate-relative path `DESTROY`, turning C-style `foo_init(&mut f)` / `foo_destroy(&mut f)`
+/// pairs into RAII.  `DESTROY` must take a single `*mut TYPE` or `&mut TYPE` argument.  Since a
+/// `Drop` type can't be `Copy`, `Copy` is removed from the struct's `derive` attribute, so
+/// copies of the value become moves.
+///
+/// A call `DESTROY(&mut x)`, where `x` is a local or parameter holding the value, is removed if
+/// it immediately precedes the end of `x`'s scope: if it's the last statement of the block that
+/// declares `x`, or is followed only by that block's trailing expression or by a `return`, and
+/// the expression doesn't use `x`.  The remaining explicit calls are recorded in a report for
+/// auditing, since they now run the destroy logic twice, unless the value is reinitialized in
+/// between:
+///
+///  * Calls on a local that don't end its scope, like a destroy-then-reinit sequence, are
+///    reported as "may destroy twice", and so are calls on places like struct fields, which
+///    are dropped along with the value that contains them.
+///  * Calls through a raw pointer, like `DESTROY(p)`, are reported as "not dropped
+///    automatically": values behind raw pointers are never dropped, so these calls are still
+///    needed.
+///  * Assignments to a place of type `TYPE` are reported as "drops the old value", since the
+///    old value may already be destroyed or, behind a raw pointer, be uninitialized memory.
+///
+/// Note that the value is now destroyed on every path out of its scope, including early
+/// returns taken before it was initialized, so `DESTROY` must accept the value in whatever
+/// state those paths leave it.
+///
+/// The command is refused, with a warning, if `TYPE` is generic, already implements `Drop`, or
+/// is stored by value in a union or in a `Copy` type.  With `-o`, the report is also written to
+/// `REPORT` as JSON.
+pub struct ConvertToDrop {
+    pub ty_path: String,
+    pub destroy_path: String,
+    pub report_path: Option<String>,
+}
+
+/// How an explicit call to the destroy function in `convert_to_drop` refers to the value.
+enum Destroyed {
+    /// `DESTROY(&mut x)`, for the local `x`.
+    Local(HirId),
+    /// `DESTROY(p)`, for the raw pointer `p`.
+    RawPtr,
+    /// Some other place, like a field of another struct.
+    Place,
+}
+
+impl Transform for ConvertToDrop {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+
+        // (1) Find the type and the destroy function, and check that the conversion is possible.
+
+        let ty_path = self.ty_path.trim_start_matches("crate::");
+        let destroy_path = self.destroy_path.trim_start_matches("crate::");
+        let mut ty_did = None;
+        let mut destroy_did = None;
+        visit_nodes(krate, |i: &Item| {
+            let path = match i.kind {
+                ItemKind::Struct(..) | ItemKind::Fn(..) => tcx.def_path_str(cx.node_def_id(i.id)),
+                _ => return,
+            };
+            match i.kind {
+                ItemKind::Struct(..) if path == ty_path => ty_did = Some(cx.node_def_id(i.id)),
+                ItemKind::Fn(..) if path == destroy_path => {
+                    destroy_did = Some(cx.node_def_id(i.id))
+                }
+                _ => {}
+            }
+        });
+        let ty_did = match_or!([ty_did] Some(x) => x; {
+            cx.session().warn(&format!("no struct named `{}`", ty_path));
+            return;
+        });
+        let destroy_did = match_or!([destroy_did] Some(x) => x; {
+            cx.session().warn(&format!("no function named `{}`", destroy_path));
+            return;
+        });
+
+        let is_ty = |ty: ty::Ty| match ty.kind {
+            ty::TyKind::Adt(def, _) => def.did == ty_did,
+            _ => false,
+        };
+        let sig = tcx.fn_sig(destroy_did);
+        let takes_ptr = match sig.skip_binder().inputs() {
+            [arg] => match arg.kind {
+                ty::TyKind::RawPtr(ty::TypeAndMut { ty, mutbl: hir::Mutability::Mutable }) |
+                ty::TyKind::Ref(_, ty, hir::Mutability::Mutable) => is_ty(ty),
+                _ => false,
+            },
+            _ => false,
+        };
+
+        let mut container = None;
+        visit_nodes(krate, |i: &Item| {
+            match i.kind {
+                ItemKind::Struct(..) | ItemKind::Union(..) | ItemKind::Enum(..) => {}
+                _ => return,
+            }
+            let did = cx.node_def_id(i.id);
+            let def = tcx.adt_def(did);
+            if did == ty_did || container.is_some() ||
+               !def.all_fields().any(|f| contains_by_value(tcx.type_of(f.did), ty_did)) {
+                return;
+            }
+            if def.is_union() || type_implements(cx, tcx.type_of(did), i.id, "Copy") {
+                container = Some(i.ident);
+            }
+        });
+
+        let reason = if !takes_ptr {
+            Some(format!("`{}` doesn't take a single `*mut {}` or `&mut {}` argument",
+                         destroy_path, ty_path, ty_path))
+        } else if !tcx.generics_of(ty_did).params.is_empty() {
+            Some("it's generic".to_owned())
+        } else if tcx.adt_def(ty_did).has_dtor(tcx) {
+            Some("it already implements `Drop`".to_owned())
+        } else if let Some(ident) = container {
+            Some(format!("`{}` contains it, and can't contain a type that implements `Drop`",
+                         ident))
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            cx.session().warn(&format!("not adding a `Drop` impl to `{}`: {}", ty_path, reason));
+            return;
+        }
+
+        st.start_report("convert_to_drop", self.report_path.clone());
+
+        // (2) Find the scope of each local holding a value of the type: the block that declares
+        // it, or the body of the function that takes it as a parameter.
+
+        let mut scopes: HashMap<HirId, NodeId> = HashMap::new();
+        let mut add_bindings = |pat: &Pat, scope: NodeId| {
+            visit_nodes(pat, |p: &Pat| {
+                if let PatKind::Ident(..) = p.kind {
+                    if cx.opt_node_type(p.id).map_or(false, is_ty) {
+                        scopes.insert(cx.hir_map().node_to_hir_id(p.id), scope);
+                    }
+                }
+            });
+        };
+        visit_nodes(krate, |b: &Block| {
+            for s in &b.stmts {
+                if let StmtKind::Local(ref l) = s.kind {
+                    add_bindings(&l.pat, b.id);
+                }
+            }
+        });
+        visit_fns(krate, |fl| {
+            if let Some(ref block) = fl.block {
+                for param in &fl.decl.inputs {
+                    add_bindings(&param.pat, block.id);
+                }
+            }
+        });
+
+        // (3) Remove the explicit calls that the `Drop` impl makes redundant, and report the
+        // others.
+
+        let local = |e: &Expr| match cx.try_resolve_expr_hir(e) {
+            Some(Res::Local(id)) => Some(id),
+            _ => None,
+        };
+        let uses = |e: &Expr, id: HirId| {
+            let mut found = false;
+            visit_nodes(e, |e: &Expr| found |= local(e) == Some(id));
+            found
+        };
+        let destroyed = |s: &Stmt| {
+            let e = match s.kind {
+                StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => e,
+                _ => return None,
+            };
+            let arg = match e.kind {
+                ExprKind::Call(_, ref args) if args.len() == 1 => &args[0],
+                _ => return None,
+            };
+            if cx.opt_callee(e) != Some(destroy_did) {
+                return None;
+            }
+            Some(match strip_casts(arg).kind {
+                ExprKind::AddrOf(_, _, ref place) => {
+                    match local(place).filter(|id| scopes.contains_key(id)) {
+                        Some(id) => Destroyed::Local(id),
+                        None => Destroyed::Place,
+                    }
+                }
+                _ => Destroyed::RawPtr,
+            })
+        };
+        // Check if the end of `id`'s scope immediately follows statement `i` of `b`.
+        let ends_scope = |b: &Block, i: usize, id: HirId| {
+            let in_scope = scopes[&id] == b.id;
+            match b.stmts[i + 1..] {
+                [] => in_scope,
+                [ref last] => match last.kind {
+                    StmtKind::Semi(ref e) | StmtKind::Expr(ref e)
+                        if matches!([e.kind] ExprKind::Ret(..)) => !uses(e, id),
+                    StmtKind::Expr(ref e) => in_scope && !uses(e, id),
+                    _ => false,
+                },
+                _ => false,
+            }
+        };
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            let mut i = 0;
+            while i < b.stmts.len() {
+                let span = b.stmts[i].span;
+                match destroyed(&b.stmts[i]) {
+                    Some(Destroyed::Local(id)) if ends_scope(b, i, id) => {
+                        st.report(cx, "removed destroy call", span);
+                        b.stmts.remove(i);
+                        continue;
+                    }
+                    Some(Destroyed::Local(_)) | Some(Destroyed::Place) => {
+                        st.report(cx, "may destroy twice", span);
+                    }
+                    Some(Destroyed::RawPtr) => st.report(cx, "not dropped automatically", span),
+                    None => {}
+                }
+                i += 1;
+            }
+        });
+
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Assign(ref lhs, _) = e.kind {
+                if cx.opt_node_type(lhs.id).map_or(false, is_ty) {
+                    st.report(cx, "drops the old value", e.span);
+                }
+            }
+        });
+
+        // (4) Add the `Drop` impl, and remove `Copy` from the derives.
+
+        let destroy_name = if tcx.parent(destroy_did) == tcx.parent(ty_did) {
+            tcx.item_name(destroy_did).to_string()
+        } else {
+            format!("crate::{}", destroy_path)
+        };
+        let call = match sig.unsafety() {
+            hir::Unsafety::Unsafe => format!("unsafe {{ {}(self); }}", destroy_name),
+            hir::Unsafety::Normal => format!("{}(self);", destroy_name),
+        };
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            if !matches!([i.kind] ItemKind::Struct(..)) || cx.node_def_id(i.id) != ty_did {
+                return smallvec![i];
+            }
+            let i = i.map(|mut i| {
+                i.attrs = i.attrs.drain(..).filter_map(|attr| {
+                    if !attr.check_name(Symbol::intern("derive")) {
+                        return Some(attr);
+                    }
+                    let traits = attr.meta_item_list().unwrap_or_default().iter()
+                        .filter_map(|item| item.ident())
+                        .collect::<Vec<_>>();
+                    if !traits.iter().any(|t| t.name == Symbol::intern("Copy")) {
+                        return Some(attr);
+                    }
+                    let traits = traits.into_iter()
+                        .filter(|t| t.name != Symbol::intern("Copy"))
+                        .collect::<Vec<_>>();
+                    if traits.is_empty() {
+                        return None;
+                    }
+                    Some(mk().call_attr("derive", traits).into_attrs().remove(0))
+                }).collect();
+                i
+            });
+            let src = format!("impl Drop for {} {{ fn drop(&mut self) {{ {} }} }}", i.ident, call);
+            let mut items = smallvec![i];
+            items.extend(parse_items(cx.session(), &src));
+            items
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Check if a value of type `ty` contains a value of the struct `did` directly, rather than
+/// behind a pointer.
+fn contains_by_value(ty: ty::Ty, did: DefId) -> bool {
+    match ty.kind {
+        ty::TyKind::Adt(def, _) => def.did == did,
+        ty::TyKind::Array(elem, _) => contains_by_value(elem, did),
+        ty::TyKind::Tuple(_) => ty.tuple_fields().any(|ty| contains_by_value(ty, did)),
+        _ => false,
+    }
+}
+
+/// # `optimize_struct_layout` Command
+///
+/// Marks: `target`
+///
+/// Reorder the fields of structs to minimize padding, putting the fields with the largest
+/// alignment first.  This applies to the structs marked `target`, or to every struct in the
+/// crate if none are marked.  Generic structs and structs with a `repr` other than `C` are left
+/// alone.
+///
+/// `#[repr(C)]` structs keep their layout, since C code may depend on it, and their wasted
+/// padding is only reported.  With `--strip-repr-c`, those that C code can't see are reordered
+/// too, and lose their `repr(C)`: those that don't appear, even behind a pointer, in the
+/// signature of a foreign function or of a function with a non-Rust ABI, in the type of a
+/// foreign static, or in the fields of a struct or union that does.  The compiler already
+/// reorders the fields of other structs, so reordering their definitions doesn't change their
+/// size, but makes the declared order match the layout.
+///
+/// Uses of named fields don't depend on the order.  For tuple structs, field indices,
+/// constructor calls and patterns are rewritten to the new order.  A tuple struct is left alone,
+/// with a warning, if its constructor is used as a function value or called with arguments that
+/// may have side effects, which would then run in a different order, or if it's matched by a
+/// pattern with `..`.  Structs that derive `PartialOrd` or `Ord` are left alone with a warning
+/// too, since their comparisons follow the field order.
+///
+/// A table of each struct's size before and after, its wasted padding before, and what was done
+/// with it is printed to stderr.
+pub struct OptimizeStructLayout {
+    pub strip_repr_c: bool,
+}
+
+/// The size and alignment of each field of a struct, in order.
+type FieldLayouts = Vec<(u64, u64)>;
+
+/// The size of a `#[repr(C)]` struct with fields of the layouts `fields`, in order.
+fn c_struct_size(fields: &[(u64, u64)]) -> u64 {
+    let round_up = |x: u64, align: u64| (x + align - 1) / align * align;
+    let mut size = 0;
+    let mut align = 1;
+    for &(field_size, field_align) in fields {
+        size = round_up(size, field_align) + field_size;
+        align = align.max(field_align);
+    }
+    round_up(size, align)
+}
+
+/// Check if an argument to a tuple struct constructor can be evaluated out of order.
+fn is_pure_arg(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Lit(..) | ExprKind::Path(..) => true,
+        ExprKind::Paren(ref e) | ExprKind::Cast(ref e, _) | ExprKind::Field(ref e, _) |
+        ExprKind::AddrOf(_, _, ref e) | ExprKind::Unary(UnOp::Neg, ref e) |
+        ExprKind::Unary(UnOp::Not, ref e) => is_pure_arg(e),
+        _ => false,
+    }
+}
+
+impl Transform for OptimizeStructLayout {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+        let layout = |ty: ty::Ty| -> Option<(u64, u64)> {
+            let layout = tcx.layout_of(ParamEnv::reveal_all().and(ty)).ok()?;
+            if layout.abi.is_unsized() {
+                return None;
+            }
+            Some((layout.size.bytes(), layout.align.abi.bytes()))
+        };
+
+        // (1) Find the structs, and the types that C code can see.
+
+        let any_marked = st.marks().iter().any(|&(_, label)| label == Symbol::intern("target"));
+        let mut structs = Vec::new();
+        visit_nodes(krate, |i: &Item| {
+            if !matches!([i.kind] ItemKind::Struct(..)) ||
+               (any_marked && !st.marked(i.id, "target")) {
+                return;
+            }
+            let mut repr_c = false;
+            let mut other_repr = false;
+            let mut ordered = false;
+            for attr in &i.attrs {
+                let items = attr.meta_item_list().unwrap_or_default();
+                let names = items.iter().filter_map(|item| item.ident()).map(|ident| ident.name);
+                if attr.check_name(Symbol::intern("repr")) {
+                    for name in names {
+                        if name == Symbol::intern("C") {
+                            repr_c = true;
+                        } else {
+                            other_repr = true;
+                        }
+                    }
+                } else if attr.check_name(Symbol::intern("derive")) {
+                    ordered |= names.clone().any(|name| {
+                        name == Symbol::intern("PartialOrd") || name == Symbol::intern("Ord")
+                    });
+                    other_repr |= names.clone()
+                        .any(|name| name == Symbol::intern("BitfieldStruct"));
+                }
+            }
+            structs.push((i.span, cx.node_def_id(i.id), repr_c, other_repr, ordered));
+        });
+
+        let mut seen = HashSet::new();
+        let mut queue = Vec::new();
+        let add_types = |ty: ty::Ty, queue: &mut Vec<DefId>| {
+            for ty in ty.walk() {
+                if let ty::TyKind::Adt(def, _) = ty.kind {
+                    queue.push(def.did);
+                }
+            }
+        };
+        let check_sig = |id: NodeId, queue: &mut Vec<DefId>| {
+            let sig = tcx.fn_sig(cx.node_def_id(id));
+            if sig.abi() != Abi::Rust {
+                for &ty in sig.skip_binder().inputs_and_output.iter() {
+                    add_types(ty, queue);
+                }
+            }
+        };
+        visit_nodes(krate, |i: &Item| {
+            if let ItemKind::Fn(..) = i.kind {
+                check_sig(i.id, &mut queue);
+            }
+        });
+        visit_nodes(krate, |i: &ForeignItem| {
+            match i.kind {
+                ForeignItemKind::Fn(..) => check_sig(i.id, &mut queue),
+                ForeignItemKind::Static(..) => {
+                    add_types(tcx.type_of(cx.node_def_id(i.id)), &mut queue)
+                }
+                _ => {}
+            }
+        });
+        while let Some(did) = queue.pop() {
+            if !seen.insert(did) || !did.is_local() {
+                continue;
+            }
+            for field in tcx.adt_def(did).all_fields() {
+                add_types(tcx.type_of(field.did), &mut queue);
+            }
+        }
+        let ffi_visible = seen;
+
+        // (2) Decide which structs to reorder.
+
+        let mut table = Vec::new();
+        let mut reorders = HashMap::new();
+        let mut strip_repr = HashSet::new();
+        for &(span, did, repr_c, other_repr, ordered) in &structs {
+            let path = tcx.def_path_str(did);
+            if other_repr || !tcx.generics_of(did).params.is_empty() {
+                continue;
+            }
+            let fields = tcx.adt_def(did).non_enum_variant().fields.iter()
+                .map(|f| layout(tcx.type_of(f.did)))
+                .collect::<Option<FieldLayouts>>();
+            let (fields, (before, _)) = match (fields, layout(tcx.type_of(did))) {
+                (Some(fields), Some(size)) => (fields, size),
+                _ => continue,
+            };
+            let mut order = (0..fields.len()).collect::<Vec<_>>();
+            order.sort_by_key(|&i| std::cmp::Reverse(fields[i].1));
+            let new_fields = order.iter().map(|&i| fields[i]).collect::<FieldLayouts>();
+            let padding = before - fields.iter().map(|f| f.0).sum::<u64>();
+
+            let reorder = !order.iter().enumerate().all(|(new, &old)| new == old);
+            let (after, action) = if !reorder {
+                (before, "unchanged")
+            } else if ordered {
+                cx.session().span_warn(
+                    span,
+                    &format!("not reordering `{}`: its derived comparisons follow the field \
+                              order", path),
+                );
+                (before, "skipped: derives PartialOrd")
+            } else if !repr_c {
+                (before, "reordered")
+            } else if !self.strip_repr_c {
+                (c_struct_size(&new_fields), "reported: repr(C)")
+            } else if ffi_visible.contains(&did) {
+                (c_struct_size(&new_fields), "reported: visible to C")
+            } else {
+                strip_repr.insert(did);
+                (c_struct_size(&new_fields), "reordered, repr(C) removed")
+            };
+            if reorder && action.starts_with("reordered") {
+                // The new index of each field, by its old index.
+                let mut new_index = vec![0; order.len()];
+                for (new, &old) in order.iter().enumerate() {
+                    new_index[old] = new;
+                }
+                reorders.insert(did, new_index);
+            }
+            table.push((path, before, after, padding, action));
+        }
+
+        // (3) Tuple structs can only be reordered if their constructor calls and patterns can
+        // be.
+
+        let tuple_ctor = |res: Option<Res>| match res {
+            Some(Res::Def(DefKind::Ctor(CtorOf::Struct, _), ctor)) => tcx.parent(ctor),
+            _ => None,
+        };
+        let mut unconvertible = HashMap::new();
+        let mut callees = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Call(ref f, ref args) = e.kind {
+                if let Some(did) = tuple_ctor(cx.try_resolve_expr_hir(f))
+                        .filter(|did| reorders.contains_key(did)) {
+                    callees.insert(f.id);
+                    if !args.iter().all(|arg| is_pure_arg(arg)) {
+                        unconvertible.entry(did).or_insert((e.span, "it's constructed with \
+                                                             arguments that may have side \
+                                                             effects"));
+                    }
+                }
+            }
+        });
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Path(..) = e.kind {
+                if let Some(did) = tuple_ctor(cx.try_resolve_expr_hir(e))
+                        .filter(|did| reorders.contains_key(did)) {
+                    if !callees.contains(&e.id) {
+                        unconvertible.entry(did).or_insert((e.span, "its constructor is used as \
+                                                             a function"));
+                    }
+                }
+            }
+        });
+        visit_nodes(krate, |p: &Pat| {
+            if let PatKind::TupleStruct(_, ref pats) = p.kind {
+                if let Some(did) = tuple_ctor(cx.try_resolve_pat_hir(p))
+                        .filter(|did| reorders.contains_key(did)) {
+                    if pats.iter().any(|p| matches!([p.kind] PatKind::Rest)) {
+                        unconvertible.entry(did).or_insert((p.span, "it's matched by a pattern \
+                                                             with `..`"));
+                    }
+                }
+            }
+        });
+        for (did, (span, reason)) in unconvertible {
+            cx.session().span_warn(span, &format!("not reordering `{}`: {}",
+                                                  tcx.def_path_str(did), reason));
+            reorders.remove(&did);
+            strip_repr.remove(&did);
+            let row = table.iter_mut().find(|row| row.0 == tcx.def_path_str(did)).unwrap();
+            row.2 = row.1;
+            row.4 = "skipped: tuple struct uses";
+        }
+
+        // (4) Reorder the fields in the definitions, and rewrite the uses of tuple struct
+        // fields.
+
+        let new_name = |ty: Option<ty::Ty>, name: Symbol| {
+            let new_index = reorders.get(&adt_def_id(ty?)?)?;
+            let old = name.as_str().parse::<usize>().ok()?;
+            Some(Symbol::intern(&new_index[old].to_string()))
+        };
+        let reorder_vec = |did: DefId, xs: &mut Vec<_>| {
+            if let Some(new_index) = reorders.get(&did) {
+                let mut old = mem::replace(xs, Vec::new()).into_iter().enumerate()
+                    .map(|(i, x)| (new_index[i], x))
+                    .collect::<Vec<_>>();
+                old.sort_by_key(|&(i, _)| i);
+                xs.extend(old.into_iter().map(|(_, x)| x));
+            }
+        };
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            if !matches!([i.kind] ItemKind::Struct(..)) {
+                return smallvec![i];
+            }
+            let did = cx.node_def_id(i.id);
+            if !reorders.contains_key(&did) {
+                return smallvec![i];
+            }
+            smallvec![i.map(|mut i| {
+                match i.kind {
+                    ItemKind::Struct(VariantData::Struct(ref mut fs, _), _) |
+                    ItemKind::Struct(VariantData::Tuple(ref mut fs, _), _) => reorder_vec(did, fs),
+                    _ => {}
+                }
+                if strip_repr.contains(&did) {
+                    // Any other `repr` would have excluded the struct, so all that's left is
+                    // `C`.
+                    i.attrs.retain(|attr| !attr.check_name(Symbol::intern("repr")));
+                }
+                i
+            })]
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let ty = cx.opt_node_type(e.id);
+            match e.kind {
+                ExprKind::Field(ref base, ref mut ident) => {
+                    if let Some(new) = new_name(cx.opt_adjusted_node_type(base.id), ident.name) {
+                        ident.name = new;
+                    }
+                }
+                ExprKind::Struct(_, ref mut fields, _) => {
+                    for f in fields {
+                        if let Some(new) = new_name(ty, f.ident.name) {
+                            f.ident.name = new;
+                        }
+                    }
+                }
+                ExprKind::Call(ref f, ref mut args) => {
+                    if let Some(did) = tuple_ctor(cx.try_resolve_expr_hir(f)) {
+                        reorder_vec(did, args);
+                    }
+                }
+                _ => {}
+            }
+        });
+
+        MutVisitNodes::visit(krate, |p: &mut P<Pat>| {
+            let ty = cx.opt_node_type(p.id);
+            let ctor = tuple_ctor(cx.try_resolve_pat_hir(p));
+            match p.kind {
+                PatKind::Struct(_, ref mut fields, _) => {
+                    for f in fields {
+                        if let Some(new) = new_name(ty, f.ident.name) {
+                            f.ident.name = new;
+                        }
+                    }
+                }
+                PatKind::TupleStruct(_, ref mut pats) => {
+                    if let Some(did) = ctor {
+                        reorder_vec(did, pats);
+                    }
+                }
+                _ => {}
+            }
+        });
+
+        // (5) Print the table.
+
+        let width = table.iter().map(|row| row.0.len()).max().unwrap_or(0).max("struct".len());
+        eprintln!("{:<w$}  {:>6}  {:>6}  {:>7}  {}", "struct", "before", "after", "padding",
+                  "action", w = width);
+        for (path, before, after, padding, action) in table {
+            eprintln!("{:<w$}  {:>6}  {:>6}  {:>7}  {}", path, before, after, padding, action,
+                      w = width);
+        }
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("struct_assign_to_update", |_args| mk(AssignToUpdate));
-    reg.register("struct_merge_updates", |_args| mk(MergeUpdates));
-    reg.register("rename_struct", |args| mk(Rename(args[0].clone())));
+    let spec = CommandSpec::new("Replace struct field assignments with functional updates.");
+    reg.register("struct_assign_to_update", spec, |_args| Ok(mk(AssignToUpdate)));
+
+    let spec = CommandSpec::new("Merge consecutive struct updates into a single update.");
+    reg.register("struct_merge_updates", spec, |_args| Ok(mk(MergeUpdates)));
+
+    let spec = CommandSpec::new("Rename the struct marked `target`.")
+        .arg("NAME", ArgType::Str, "the new name of the struct");
+    reg.register("rename_struct", spec, |args| {
+        Ok(mk(Rename(args.str("NAME").to_owned())))
+    });
+
+    let spec = CommandSpec::new("Give structs marked `target` a `Default` impl and use it.");
+    reg.register("derive_default_and_use", spec, |_args| Ok(mk(DeriveDefaultAndUse)));
+
+    let spec = CommandSpec::new("Rename struct and union fields crate-wide.")
+        .option("--file", "MAPPING", ArgType::Str, None, "read the renames from a JSON file")
+        .rest("TYPE OLD NEW", "rename field OLD of the type at path TYPE to NEW");
+    reg.register("rename_fields", spec, |args| {
+        let triples = args.list("TYPE OLD NEW");
+        let renames = match args.opt_str("--file") {
+            Some(_) if !triples.is_empty() => {
+                return Err("--file can't be combined with TYPE OLD NEW triples".to_owned());
+            }
+            Some(path) => read_field_renames(path),
+            None if triples.is_empty() || triples.len() % 3 != 0 => {
+                return Err("expected TYPE OLD NEW triples".to_owned());
+            }
+            None => triples.chunks(3).map(|a| {
+                (a[0].clone(), (&a[1] as &str).into_symbol(), (&a[2] as &str).into_symbol())
+            }).collect(),
+        };
+        Ok(mk(RenameFields { renames }))
+    });
+
+    let spec = CommandSpec::new("Turn `c_char` array fields marked `target` into `u8` strings.");
+    reg.register("convert_char_array_fields", spec, |_args| Ok(mk(ConvertCharArrayFields)));
+
+    let spec = CommandSpec::new("Give the struct TYPE a `Drop` impl that calls DESTROY.")
+        .arg("TYPE", ArgType::Str, "the crate-relative path of the struct")
+        .arg("DESTROY", ArgType::Str, "the crate-relative path of the destructor function")
+        .option("-o", "REPORT", ArgType::Str, None, "also write the report to REPORT as JSON");
+    reg.register("convert_to_drop", spec, |args| {
+        Ok(mk(ConvertToDrop {
+            ty_path: args.str("TYPE").to_owned(),
+            destroy_path: args.str("DESTROY").to_owned(),
+            report_path: args.opt_str("-o").map(|s| s.to_owned()),
+        }))
+    });
+
+    let spec = CommandSpec::new("Reorder struct fields to minimize padding.")
+        .flag("--strip-repr-c", "also reorder `#[repr(C)]` structs, removing the attribute");
+    reg.register("optimize_struct_layout", spec, |args| {
+        Ok(mk(OptimizeStructLayout {
+            strip_repr_c: args.flag("--strip-repr-c"),
+        }))
+    });
 }
@@ -12,6 +12,7 @@ use c2rust_ast_builder::mk;
 use crate::ast_manip::{visit_nodes};
 use crate::ast_manip::fn_edit::mut_visit_fns;
 use crate::command::{RefactorState, CommandState, Command, Registry, TypeckLoopResult};
+use crate::command_args::{ArgType, CommandSpec};
 use crate::driver::{Phase};
 use crate::matcher::{replace_expr, replace_stmts};
 use crate::transform::Transform;
@@ -22,8 +23,6 @@ use crate::RefactorCtxt;
 /// 
 /// Test command - not intended for general use.
 /// 
-/// Usage: `test_one_plus_one`
-/// 
 /// Replace the expression `2` with `1 + 1` everywhere it appears.
 pub struct OnePlusOne;
 
@@ -39,8 +38,6 @@ impl Transform for OnePlusOne {
 /// 
 /// Test command - not intended for general use.
 /// 
-/// Usage: `test_f_plus_one`
-/// 
 /// Replace the expression `f(__x)` with `__x + 1` everywhere it appears.
 pub struct FPlusOne;
 
@@ -56,8 +53,6 @@ impl Transform for FPlusOne {
 /// 
 /// Test command - not intended for general use.
 /// 
-/// Usage: `test_replace_stmts OLD NEW`
-/// 
 /// Replace statement(s) `OLD` with `NEW` everywhere it appears.
 pub struct ReplaceStmts(pub String, pub String);
 
@@ -73,8 +68,6 @@ impl Transform for ReplaceStmts {
 /// 
 /// Test command - not intended for general use.
 /// 
-/// Usage: `test_insert_remove_args INS REM`
-/// 
 /// In each function marked `target`, insert new arguments at each index listed in
 /// `INS` (a comma-separated list of integers), then delete the arguments whose
 /// original indices are listed in `REM`.
@@ -126,8 +119,6 @@ impl Transform for InsertRemoveArgs {
 /// 
 /// Test command - not intended for general use.
 /// 
-/// Usage: `test_typeck_loop`
-/// 
 /// Runs a no-op typechecking loop for three iterations.  Used to test the typechecking loop and
 /// AST re-analysis code.
 pub struct TestTypeckLoop;
@@ -152,8 +143,6 @@ impl Command for TestTypeckLoop {
 /// 
 /// Test command - not intended for general use.
 /// 
-/// Usage: `test_debug_callees`
-/// 
 /// Inspect the details of each Call expression.  Used to debug
 /// `RefactorCtxt::opt_callee_info`.
 pub struct TestDebugCallees;
@@ -265,16 +254,27 @@ impl Transform for TestDebugCallees {
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("test_one_plus_one", |_args| mk(OnePlusOne));
-    reg.register("test_f_plus_one", |_args| mk(FPlusOne));
-    reg.register("test_replace_stmts", |args| mk(
-            ReplaceStmts(args[0].clone(), args[1].clone())));
+    let spec = CommandSpec::new("Replace the expression `2` with `1 + 1`.");
+    reg.register("test_one_plus_one", spec, |_args| Ok(mk(OnePlusOne)));
+
+    let spec = CommandSpec::new("Replace the expression `f(__x)` with `__x + 1`.");
+    reg.register("test_f_plus_one", spec, |_args| Ok(mk(FPlusOne)));
+
+    let spec = CommandSpec::new("Replace the statements OLD with NEW.")
+        .arg("OLD", ArgType::Str, "the statements to replace")
+        .arg("NEW", ArgType::Str, "the replacement");
+    reg.register("test_replace_stmts", spec, |args| {
+        Ok(mk(ReplaceStmts(args.str("OLD").to_owned(), args.str("NEW").to_owned())))
+    });
 
-    reg.register("test_insert_remove_args", |args| {
+    let spec = CommandSpec::new("Insert and remove arguments of the functions marked `target`.")
+        .arg("INS", ArgType::Str, "comma-separated indices to insert arguments at")
+        .arg("REM", ArgType::Str, "comma-separated indices of the arguments to remove");
+    reg.register("test_insert_remove_args", spec, |args| {
         let mut insert_idxs = HashMap::new();
         let mut remove_idxs = HashSet::new();
 
-        for part in args[0].split(",") {
+        for part in args.str("INS").split(",") {
             if part == "" {
                 continue;
             }
@@ -282,7 +282,7 @@ pub fn register_commands(reg: &mut Registry) {
             *insert_idxs.entry(idx).or_insert(0) += 1;
         }
 
-        for part in args[1].split(",") {
+        for part in args.str("REM").split(",") {
             if part == "" {
                 continue;
             }
@@ -290,10 +290,12 @@ pub fn register_commands(reg: &mut Registry) {
             remove_idxs.insert(idx);
         }
 
-        mk(InsertRemoveArgs { insert_idxs, remove_idxs })
+        Ok(mk(InsertRemoveArgs { insert_idxs, remove_idxs }))
     });
 
-    reg.register("test_typeck_loop", |_| Box::new(TestTypeckLoop));
+    let spec = CommandSpec::new("Run a no-op typechecking loop for three iterations.");
+    reg.register("test_typeck_loop", spec, |_| Ok(Box::new(TestTypeckLoop)));
 
-    reg.register("test_debug_callees", |_args| mk(TestDebugCallees));
+    let spec = CommandSpec::new("Log the details of each call expression.");
+    reg.register("test_debug_callees", spec, |_args| Ok(mk(TestDebugCallees)));
 }
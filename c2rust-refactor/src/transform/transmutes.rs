@@ -0,0 +1,293 @@
+use rustc::ty::{self, ParamEnv, TyCtxt, TyKind};
+use rustc_target::spec::abi::Abi;
+use std::collections::HashSet;
+use syntax::ast::*;
+use syntax::ptr::P;
+
+use crate::ast_manip::{visit_nodes, MutVisitNodes};
+use crate::command::{CommandState, Registry};
+use crate::command_args::{ArgType, CommandSpec};
+use crate::driver::Phase;
+use crate::transform::casts::{is_lit_expr, SimpleTy};
+use crate::transform::Transform;
+use crate::RefactorCtxt;
+use c2rust_ast_builder::{mk, Builder};
+
+/// # `convert_transmutes` Command
+///
+/// Rewrites type punning between numeric types into the safe standard library conversions.
+/// Both `mem::transmute::<f32, u32>(x)` and pointer-cast dereferences like
+/// `*(&x as *const f32 as *const u32)` are rewritten:
+///
+///  * Floats to and from integers of the same width become `x.to_bits()` and
+///    `f32::from_bits(x)`, with an `as` cast for signed integers.
+///
+///  * Integers to and from `u8` arrays become `x.to_ne_bytes()` and `u32::from_ne_bytes(x)`.
+///    Floats are converted the same way, going through `to_bits` and `from_bits`.
+///
+///  * Integers to integers of the same width but opposite signedness become `as` casts.
+///
+/// The byte array conversions keep the native byte order the original code relied on, so
+/// their result depends on the endianness of the target.  They are listed in the report so
+/// they can be checked.  Puns between types of different sizes, or involving structs with
+/// padding, are left unchanged and reported.  Pointer-cast dereferences that are assigned to
+/// or borrowed are also left alone, since they write through the pun.
+///
+/// Once done, prints how many puns of each kind were rewritten or skipped. If `-o REPORT` is
+/// passed, their source locations are also written to the `REPORT` file as JSON.
+pub struct ConvertTransmutes {
+    pub report_path: Option<String>,
+}
+
+impl Transform for ConvertTransmutes {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        st.start_report("convert_transmutes", self.report_path.clone());
+        let tcx = cx.ty_ctxt();
+
+        // Places that get written to or borrowed can't be replaced by a value
+        let mut places = HashSet::new();
+        visit_nodes(krate, |e: &Expr| match e.kind {
+            ExprKind::Assign(ref lhs, _)
+            | ExprKind::AssignOp(_, ref lhs, _)
+            | ExprKind::AddrOf(_, _, ref lhs) => {
+                places.insert(lhs.id);
+            }
+            _ => {}
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if places.contains(&e.id) {
+                return;
+            }
+            let (x, from_ty, to_ty) = match transmute_pun(cx, e) {
+                Some(pun) => pun,
+                None => match_or!([pointer_pun(cx, e)] Some(pun) => pun; return),
+            };
+            let from_ty = tcx.normalize_erasing_regions(ParamEnv::empty(), from_ty);
+            let to_ty = tcx.normalize_erasing_regions(ParamEnv::empty(), to_ty);
+            let b = mk().id(e.id).span(e.span);
+            match rewrite_pun(tcx, b, x.clone(), from_ty, to_ty) {
+                PunRewrite::Rewritten(new_e, category) => {
+                    st.report(cx, category, e.span);
+                    *e = new_e;
+                }
+                PunRewrite::Skipped(category) => st.report(cx, category, e.span),
+                PunRewrite::Unsupported => {}
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// If `e` is a call to `transmute`, return its argument along with the source and target
+/// types of the call.
+fn transmute_pun<'a, 'e, 'tcx>(
+    cx: &RefactorCtxt<'a, 'tcx>,
+    e: &'e Expr,
+) -> Option<(&'e P<Expr>, ty::Ty<'tcx>, ty::Ty<'tcx>)> {
+    let arg = match e.kind {
+        ExprKind::Call(_, ref args) if args.len() == 1 => &args[0],
+        _ => return None,
+    };
+    let info = cx.opt_callee_info(e)?;
+    let did = info.def_id?;
+    let tcx = cx.ty_ctxt();
+    if &*tcx.item_name(did).as_str() != "transmute" ||
+        tcx.fn_sig(did).abi() != Abi::RustIntrinsic
+    {
+        return None;
+    }
+    let substs = info.substs?;
+    Some((arg, substs.type_at(0), substs.type_at(1)))
+}
+
+/// If `e` is a pointer-cast dereference `*(&x as *const T as *const U)`, return `x` along
+/// with the types `T` and `U`.
+fn pointer_pun<'a, 'e, 'tcx>(
+    cx: &RefactorCtxt<'a, 'tcx>,
+    e: &'e Expr,
+) -> Option<(&'e P<Expr>, ty::Ty<'tcx>, ty::Ty<'tcx>)> {
+    let mut ptr = match_or!([e.kind] ExprKind::Unary(UnOp::Deref, ref ptr) => &**ptr;
+                            return None);
+    let mut casted = false;
+    loop {
+        match ptr.kind {
+            ExprKind::Paren(ref ie) => ptr = &**ie,
+            ExprKind::Cast(ref ie, _) => {
+                ptr = &**ie;
+                casted = true;
+            }
+            _ => break,
+        }
+    }
+    let x = match_or!([ptr.kind] ExprKind::AddrOf(BorrowKind::Ref, _, ref x) => x; return None);
+    if !casted {
+        return None;
+    }
+    Some((x, cx.opt_node_type(x.id)?, cx.opt_node_type(e.id)?))
+}
+
+enum PunRewrite {
+    Rewritten(P<Expr>, &'static str),
+    /// A pun that can't be rewritten safely, to be reported
+    Skipped(&'static str),
+    /// A pun between types this command doesn't handle, e.g., pointers
+    Unsupported,
+}
+
+/// The types `convert_transmutes` rewrites puns between.
+#[derive(Copy, Clone)]
+enum PunTy {
+    Int(usize, bool),
+    Float(usize),
+    /// A `u8` array
+    Bytes,
+}
+
+impl PunTy {
+    fn from_ty<'tcx>(tcx: TyCtxt<'tcx>, ty: ty::Ty<'tcx>) -> Option<Self> {
+        match SimpleTy::from_ty(tcx, ty) {
+            SimpleTy::Int(w, s) => Some(PunTy::Int(w, s)),
+            SimpleTy::Float32 => Some(PunTy::Float(32)),
+            SimpleTy::Float64 => Some(PunTy::Float(64)),
+            _ => match ty.kind {
+                TyKind::Array(elem, _) => match elem.kind {
+                    TyKind::Uint(UintTy::U8) => Some(PunTy::Bytes),
+                    _ => None,
+                },
+                _ => None,
+            },
+        }
+    }
+
+    fn name(self) -> String {
+        match self {
+            PunTy::Int(w, true) => format!("i{}", w),
+            PunTy::Int(w, false) => format!("u{}", w),
+            PunTy::Float(w) => format!("f{}", w),
+            PunTy::Bytes => panic!("name() called on a byte array"),
+        }
+    }
+
+    /// Build the path `$ty::$method`.
+    fn method_path(self, method: &str) -> P<Expr> {
+        mk().path_expr(vec![self.name(), method.to_string()])
+    }
+}
+
+/// Build the method call `x.$method()` on a value `x` of type `ty`, falling back to
+/// `$ty::$method(x)` for literals, whose type a method call can't infer.
+fn pun_method(b: Builder, x: P<Expr>, ty: PunTy, method: &str) -> P<Expr> {
+    if is_lit_expr(&x) {
+        b.call_expr(ty.method_path(method), vec![x])
+    } else {
+        b.method_call_expr(x, method, Vec::<P<Expr>>::new())
+    }
+}
+
+/// Rewrite a pun of the value `x` from `from_ty` to `to_ty`, building the outermost
+/// expression of the rewrite with `b`.
+fn rewrite_pun<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    b: Builder,
+    x: P<Expr>,
+    from_ty: ty::Ty<'tcx>,
+    to_ty: ty::Ty<'tcx>,
+) -> PunRewrite {
+    match (layout_size(tcx, from_ty), layout_size(tcx, to_ty)) {
+        (Some(from_size), Some(to_size)) if from_size != to_size => {
+            return PunRewrite::Skipped("size mismatch");
+        }
+        (Some(_), Some(_)) => {}
+        _ => return PunRewrite::Unsupported,
+    }
+    if has_padding(tcx, from_ty) || has_padding(tcx, to_ty) {
+        return PunRewrite::Skipped("padding-bearing struct");
+    }
+
+    let (from, to) = match (PunTy::from_ty(tcx, from_ty), PunTy::from_ty(tcx, to_ty)) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return PunRewrite::Unsupported,
+    };
+    match (from, to) {
+        (PunTy::Int(_, from_signed), PunTy::Int(_, to_signed)) if from_signed != to_signed => {
+            PunRewrite::Rewritten(b.cast_expr(x, mk().ident_ty(to.name())), "sign cast")
+        }
+        (PunTy::Float(_), PunTy::Int(_, signed)) => {
+            let e = if signed {
+                let bits = pun_method(mk(), x, from, "to_bits");
+                b.cast_expr(bits, mk().ident_ty(to.name()))
+            } else {
+                pun_method(b, x, from, "to_bits")
+            };
+            PunRewrite::Rewritten(e, "to_bits")
+        }
+        (PunTy::Int(w, signed), PunTy::Float(_)) => {
+            let x = if signed {
+                mk().cast_expr(x, mk().ident_ty(PunTy::Int(w, false).name()))
+            } else {
+                x
+            };
+            PunRewrite::Rewritten(b.call_expr(to.method_path("from_bits"), vec![x]), "from_bits")
+        }
+        (PunTy::Int(..), PunTy::Bytes) => PunRewrite::Rewritten(
+            pun_method(b, x, from, "to_ne_bytes"),
+            "to_ne_bytes (endian-dependent)",
+        ),
+        (PunTy::Float(w), PunTy::Bytes) => {
+            let bits = pun_method(mk(), x, from, "to_bits");
+            PunRewrite::Rewritten(
+                pun_method(b, bits, PunTy::Int(w, false), "to_ne_bytes"),
+                "to_ne_bytes (endian-dependent)",
+            )
+        }
+        (PunTy::Bytes, PunTy::Int(..)) => PunRewrite::Rewritten(
+            b.call_expr(to.method_path("from_ne_bytes"), vec![x]),
+            "from_ne_bytes (endian-dependent)",
+        ),
+        (PunTy::Bytes, PunTy::Float(w)) => {
+            let bits = mk().call_expr(PunTy::Int(w, false).method_path("from_ne_bytes"), vec![x]);
+            PunRewrite::Rewritten(
+                b.call_expr(to.method_path("from_bits"), vec![bits]),
+                "from_ne_bytes (endian-dependent)",
+            )
+        }
+        _ => PunRewrite::Unsupported,
+    }
+}
+
+fn layout_size<'tcx>(tcx: TyCtxt<'tcx>, ty: ty::Ty<'tcx>) -> Option<u64> {
+    tcx.layout_of(ParamEnv::reveal_all().and(ty)).ok().map(|layout| layout.size.bytes())
+}
+
+/// Check whether `ty` contains a struct with padding bytes, whose contents a pun would read
+/// or leave undefined.
+fn has_padding<'tcx>(tcx: TyCtxt<'tcx>, ty: ty::Ty<'tcx>) -> bool {
+    match ty.kind {
+        TyKind::Adt(def, substs) if def.is_struct() => {
+            let fields = def.non_enum_variant().fields.iter()
+                .map(|f| f.ty(tcx, substs))
+                .collect::<Vec<_>>();
+            fields.iter().map(|&f| layout_size(tcx, f)).sum::<Option<u64>>() !=
+                layout_size(tcx, ty) ||
+                fields.iter().any(|&f| has_padding(tcx, f))
+        }
+        TyKind::Array(elem, _) => has_padding(tcx, elem),
+        _ => false,
+    }
+}
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    let spec = CommandSpec::new("Rewrite type punning between numeric types into safe conversions.")
+        .option("-o", "REPORT", ArgType::Str, None, "also write the report to REPORT as JSON");
+    reg.register("convert_transmutes", spec, |args| {
+        Ok(mk(ConvertTransmutes {
+            report_path: args.opt_str("-o").map(|s| s.to_owned()),
+        }))
+    });
+}
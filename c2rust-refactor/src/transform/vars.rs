@@ -3,18 +3,24 @@ use std::collections::HashSet;
 use std::mem;
 use rustc::hir::def_id::LOCAL_CRATE;
 use rustc::hir::HirId;
-use rustc::ty::{TyKind, ParamEnv};
+use rustc::ty::{self, TyKind, ParamEnv};
+use rustc_typeck::expr_use_visitor::*;
 use syntax::ast::*;
 use syntax::mut_visit::{self, MutVisitor};
+use syntax::print::pprust;
 use syntax::ptr::P;
 use syntax::visit::{self, Visitor};
 
 use c2rust_ast_builder::mk;
-use crate::ast_manip::{MutVisit, MutVisitNodes, fold_blocks, visit_nodes};
+use crate::ast_manip::{MutVisit, MutVisitNodes, Visit, fold_blocks, visit_nodes};
 use crate::command::{CommandState, DriverCommand, Registry};
+use crate::command_args::CommandSpec;
 use crate::driver::{Phase};
 use crate::matcher::{MatchCtxt, Subst, mut_visit_match_with, replace_stmts};
 use crate::reflect::reflect_tcx_ty;
+use crate::transform::control_flow::int_lit_value;
+use crate::transform::heap::strip_casts;
+use crate::transform::structs::is_option;
 use crate::transform::Transform;
 use crate::RefactorCtxt;
 
@@ -23,8 +29,6 @@ use crate::RefactorCtxt;
 ///
 /// Obsolete - the translator now does this automatically.
 ///
-/// Usage: `let_x_uninitialized`
-///
 /// For each local variable that is uninitialized (`let x;`), add
 /// `mem::uninitialized()` as an initializer expression.
 pub struct LetXUninitialized;
@@ -43,8 +47,6 @@ impl Transform for LetXUninitialized {
 
 /// # `sink_lets` Command
 ///
-/// Usage: `sink_lets`
-///
 /// For each local variable with a trivial initializer, move the local's
 /// declaration to the innermost block containing all its uses.
 ///
@@ -221,7 +223,7 @@ impl Transform for SinkLets {
 }
 
 
-fn expr_has_side_effects(cx: &RefactorCtxt, e: &P<Expr>) -> bool {
+fn expr_has_side_effects(cx: &RefactorCtxt, e: &Expr) -> bool {
     match e.kind {
         // Literals never have side effects
         ExprKind::Lit(_) => false,
@@ -268,8 +270,6 @@ fn is_uninit_call(cx: &RefactorCtxt, e: &Expr) -> bool {
 
 /// # `fold_let_assign` Command
 ///
-/// Usage: `fold_let_assign`
-///
 /// Fold together `let`s with no initializer or a trivial one, and subsequent assignments.
 /// For example, replace `let x; x = 10;` with `let x = 10;`.
 pub struct FoldLetAssign;
@@ -440,12 +440,240 @@ fn is_self_ref(cx: &RefactorCtxt, lhs: HirId, rhs: &Expr) -> bool {
 }
 
 
+/// # `sink_let_bindings` Command
+///
+/// Move each local's declaration down to the first statement that uses it, and fold it into
+/// that statement if the statement assigns the local.  For example,
+///
+/// ```ignore
+///     let mut x: i32 = 0;
+///     foo();
+///     x = bar();
+///     print(x);
+/// ```
+///
+/// becomes `foo(); let x: i32 = bar(); print(x);`.  If the first use is an `if`-`else` chain or
+/// a `match` that assigns the local before any other use in every branch, or a block that
+/// assigns it before any other use, the declaration becomes `let x: i32;` right before it.
+/// Otherwise, the first use might read the initializer, so the local is left alone.
+///
+/// `mut` is removed if the assignments that now initialize the local were its only writes.  The
+/// declaration never leaves its original block, so it stays in scope for all the later uses,
+/// even if the first assignment is inside an inner block.
+/// Like `sink_lets`, this only moves locals with no initializer or one without side effects,
+/// so dropping or moving the initializer can't change what the function computes.
+pub struct SinkLetBindings;
+
+impl Transform for SinkLetBindings {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        let hir_map = cx.hir_map();
+
+        let mut locals = HashSet::new();
+        visit_nodes(krate, |l: &Local| {
+            if let PatKind::Ident(BindingMode::ByValue(_), _, None) = l.pat.kind {
+                if l.init.as_ref().map_or(true, |e| !expr_has_side_effects(cx, e)) {
+                    locals.insert(hir_map.node_to_hir_id(l.pat.id));
+                }
+            }
+        });
+        let writes = local_writes(cx, &locals);
+
+        let mut sunk = HashSet::new();
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            let mut i = 0;
+            while i < b.stmts.len() {
+                let (local, hir_id) = match b.stmts[i].kind {
+                    StmtKind::Local(ref l) => (l.clone(), hir_map.node_to_hir_id(l.pat.id)),
+                    _ => {
+                        i += 1;
+                        continue;
+                    }
+                };
+                if !locals.contains(&hir_id) || !sunk.insert(hir_id) {
+                    i += 1;
+                    continue;
+                }
+                let j = match b.stmts[i + 1..].iter().position(|s| mentions_local(cx, s, hir_id)) {
+                    Some(j) => i + 1 + j,
+                    None => {
+                        i += 1;
+                        continue;
+                    }
+                };
+
+                // Find the assignments that initialize the local in the first statement using it.
+                let (init, inits) = match assign_to_local(cx, &b.stmts[j], hir_id) {
+                    Some((lhs, rhs)) => (Some(rhs.clone()), vec![lhs.id]),
+                    None => match stmt_expr(&b.stmts[j]).and_then(|e| branch_inits(cx, e, hir_id)) {
+                        Some(inits) => (None, inits),
+                        // The first use might read the initializer, so leave the local alone.
+                        None => {
+                            i += 1;
+                            continue;
+                        }
+                    },
+                };
+
+                let mut other_writes = writes.get(&hir_id).into_iter().flatten()
+                    .filter(|&&id| id != hir_id)
+                    .filter(|&&id| !inits.iter().any(|&init| hir_map.node_to_hir_id(init) == id));
+                let immutable = other_writes.next().is_none();
+                let folded = inits.len() == 1 && init.is_some();
+
+                let local = local.map(|mut l| {
+                    l.init = init;
+                    if immutable {
+                        if let PatKind::Ident(BindingMode::ByValue(ref mut mutbl), _, _) =
+                                l.pat.kind {
+                            *mutbl = Mutability::Immutable;
+                        }
+                    }
+                    l
+                });
+                b.stmts.remove(i);
+                let stmt = mk().local_stmt(local);
+                if folded {
+                    b.stmts[j - 1] = stmt;
+                } else {
+                    b.stmts.insert(j - 1, stmt);
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+pub(crate) fn mentions_local<T: Visit>(cx: &RefactorCtxt, target: &T, local: HirId) -> bool {
+    let mut found = false;
+    visit_nodes(target, |e: &Expr| {
+        if cx.try_resolve_expr_to_hid(e) == Some(local) {
+            found = true;
+        }
+    });
+    found
+}
+
+fn stmt_expr(s: &Stmt) -> Option<&P<Expr>> {
+    match s.kind {
+        StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => Some(e),
+        _ => None,
+    }
+}
+
+/// If `s` is `local = rhs;`, and `rhs` doesn't use `local`, return the two sides.
+fn assign_to_local<'s>(cx: &RefactorCtxt, s: &'s Stmt, local: HirId)
+                       -> Option<(&'s P<Expr>, &'s P<Expr>)> {
+    let (lhs, rhs) = match_or!([stmt_expr(s)?.kind] ExprKind::Assign(ref l, ref r) => (l, r);
+                               return None);
+    if cx.try_resolve_expr_to_hid(lhs) != Some(local) || is_self_ref(cx, local, rhs) {
+        return None;
+    }
+    Some((lhs, rhs))
+}
+
+/// If every branch of the `if`-`else` chain or `match` `e`, or the block `e`, assigns `local`
+/// before using it in any other way, return the `NodeId`s of the assigned paths.
+fn branch_inits(cx: &RefactorCtxt, e: &Expr, local: HirId) -> Option<Vec<NodeId>> {
+    // Check that the first statement of `b` that mentions `local` assigns it.
+    let block_init = |b: &Block| {
+        let s = b.stmts.iter().find(|s| mentions_local(cx, *s, local))?;
+        assign_to_local(cx, s, local).map(|(lhs, _)| lhs.id)
+    };
+
+    let mut inits = Vec::new();
+    match e.kind {
+        // A labeled block could `break` out before the assignment
+        ExprKind::Block(ref b, None) => inits.push(block_init(b)?),
+        ExprKind::If(ref cond, ref then, ref els) => {
+            if mentions_local(cx, &**cond, local) {
+                return None;
+            }
+            inits.push(block_init(then)?);
+            match els.as_ref()?.kind {
+                ExprKind::Block(ref b, _) => inits.push(block_init(b)?),
+                _ => inits.extend(branch_inits(cx, els.as_ref()?, local)?),
+            }
+        }
+        ExprKind::Match(ref scrut, ref arms) => {
+            if mentions_local(cx, &**scrut, local) {
+                return None;
+            }
+            for arm in arms {
+                if arm.guard.as_ref().map_or(false, |g| mentions_local(cx, &**g, local)) {
+                    return None;
+                }
+                match arm.body.kind {
+                    ExprKind::Block(ref b, _) => inits.push(block_init(b)?),
+                    ExprKind::Assign(ref lhs, ref rhs)
+                        if cx.try_resolve_expr_to_hid(lhs) == Some(local) &&
+                           !is_self_ref(cx, local, rhs) => inits.push(lhs.id),
+                    _ => return None,
+                }
+            }
+        }
+        _ => return None,
+    }
+    Some(inits)
+}
+
+/// Find the places where each of `locals` is assigned, mutably borrowed, or bound.  Returns the
+/// `HirId`s of the place expressions for each local.
+pub(crate) fn local_writes(cx: &RefactorCtxt,
+                           locals: &HashSet<HirId>) -> HashMap<HirId, Vec<HirId>> {
+    struct WriteDelegate<'a> {
+        locals: &'a HashSet<HirId>,
+        writes: HashMap<HirId, Vec<HirId>>,
+    }
+
+    impl<'a> WriteDelegate<'a> {
+        fn record(&mut self, place: &Place) {
+            if let PlaceBase::Local(hir_id) = place.base {
+                if self.locals.contains(&hir_id) {
+                    self.writes.entry(hir_id).or_insert_with(Vec::new).push(place.hir_id);
+                }
+            }
+        }
+    }
+
+    impl<'a, 'tcx> Delegate<'tcx> for WriteDelegate<'a> {
+        fn consume(&mut self, _place: &Place<'tcx>, _mode: ConsumeMode) {}
+
+        fn borrow(&mut self, place: &Place<'tcx>, bk: ty::BorrowKind) {
+            if bk != ty::BorrowKind::ImmBorrow {
+                self.record(place);
+            }
+        }
+
+        fn mutate(&mut self, place: &Place<'tcx>) {
+            self.record(place);
+        }
+    }
+
+    let tcx = cx.ty_ctxt();
+    let hir_map = cx.hir_map();
+    let owners = locals.iter().map(|&id| hir_map.get_parent_item(id)).collect::<HashSet<_>>();
+    let mut delegate = WriteDelegate { locals, writes: HashMap::new() };
+    for owner in owners {
+        let owner_did = match_or!([hir_map.opt_local_def_id(owner)] Some(x) => x; continue);
+        let body_id = match_or!([hir_map.maybe_body_owned_by(owner)] Some(x) => x; continue);
+        let body = hir_map.body(body_id);
+        let tables = tcx.body_tables(body_id);
+        tcx.infer_ctxt().enter(|infcx| {
+            ExprUseVisitor::new(&mut delegate, &infcx, owner_did, ParamEnv::empty(), tables)
+                .consume_body(&body);
+        });
+    }
+    delegate.writes
+}
+
+
 /// # `uninit_to_default` Command
 ///
 /// Obsolete - works around translator problems that no longer exist.
 ///
-/// Usage: `uninit_to_default`
-///
 /// In local variable initializers, replace `mem::uninitialized()` with an
 /// appropriate default value of the variable's type.
 pub struct UninitToDefault;
@@ -476,9 +704,413 @@ impl Transform for UninitToDefault {
 }
 
 
-/// # `remove_redundant_let_types` Command
+/// # `fix_uninitialized` Command
+///
+/// Replace locals initialized with `mem::uninitialized()` by `MaybeUninit`.  For `let mut x: T
+/// = mem::uninitialized();`, the statements that follow in the same block are searched for the
+/// point where `x` becomes fully initialized, which is:
 ///
-/// Usage: `remove_redundant_let_types`
+///  * an assignment `x = v;`,
+///  * the last of a series of assignments to every field of a struct, as in `x.a = v;`, or
+///  * a loop that assigns every element of an array, either `for i in 0..N { x[i] = v; ... }` or
+///    `while i < N { x[i] = v; ... i += 1; }`, where `i` is set to zero before the loop.  The
+///    loop must not contain `break` or `continue`.
+///
+/// Until then, `x` may only be assigned to.  The local becomes `let mut x =
+/// MaybeUninit::<T>::uninit();`, assignments `x = v` become `x.write(v)`, and assignments to
+/// fields or elements go through the pointer, as in `(*x.as_mut_ptr()).a = v`.  Right after the
+/// initialization point, `let x = unsafe { x.assume_init() };` shadows the old local with the
+/// initialized value.  The new binding is `mut` if `x` is modified later on.
+///
+/// If there's no such point, because `x` is read before it's initialized or only initialized
+/// conditionally, the initializer becomes `mem::zeroed()` instead, as long as zero is a valid
+/// `T`: a number, `bool`, `char`, raw pointer, or `Option` of a reference or function pointer,
+/// or an array, tuple or struct of those.  Otherwise, the local is left alone with a warning.
+/// Assignments to fields of types that need dropping aren't counted towards initialization,
+/// since they would drop the uninitialized old values.
+pub struct FixUninitialized;
+
+impl Transform for FixUninitialized {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+
+        // (1) Find all the places where the locals are written, so we can tell which ones are
+        // modified after they're initialized.
+
+        let mut locals = HashSet::new();
+        visit_nodes(krate, |l: &Local| {
+            if l.init.as_ref().map_or(false, |e| is_uninit_call(cx, e)) {
+                locals.insert(cx.hir_map().node_to_hir_id(l.pat.id));
+            }
+        });
+        if locals.is_empty() {
+            return;
+        }
+        let write_ids = local_writes(cx, &locals).values()
+            .flat_map(|ids| ids.iter().map(|&id| cx.hir_map().hir_to_node_id(id)))
+            .collect::<HashSet<_>>();
+
+        // (2) Find the initialization point of each local in a block, then rewrite them.
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            let mut fixes = Vec::new();
+            for (i, s) in b.stmts.iter().enumerate() {
+                let l = match_or!([s.kind] StmtKind::Local(ref l) => l; continue);
+                let init = match_or!([l.init] Some(ref e) => e; continue);
+                if !is_uninit_call(cx, init) {
+                    continue;
+                }
+                match l.pat.kind {
+                    PatKind::Ident(BindingMode::ByValue(_), _, None) => {}
+                    _ => continue,
+                }
+                let local = cx.hir_map().node_to_hir_id(l.pat.id);
+                let ty = cx.node_type(init.id);
+                fixes.push((i, local, ty, uninit_init_point(cx, local, ty, &b.stmts, i + 1)));
+            }
+
+            let mut inserts = HashMap::new();
+            for (i, local, ty, init_point) in fixes {
+                let mut l = expect!([b.stmts[i].kind] StmtKind::Local(ref l) => l.clone());
+                let j = match init_point {
+                    Some(j) => j,
+                    None if zero_is_valid(cx, ty) => {
+                        let zeroed = mk().path_expr(vec!["", "std", "mem", "zeroed"]);
+                        l.init = Some(mk().call_expr(zeroed, Vec::<P<Expr>>::new()));
+                        b.stmts[i].kind = StmtKind::Local(l);
+                        continue;
+                    }
+                    None => {
+                        cx.session().span_warn(l.span, &format!(
+                            "can't convert `{}` to `MaybeUninit`: it may be read before it's \
+                             fully initialized, and zero isn't a valid `{}`",
+                            pprust::pat_to_string(&l.pat), ty));
+                        continue;
+                    }
+                };
+
+                let mut rewriter = UninitWriteRewriter { cx, local };
+                for s in &mut b.stmts[i + 1..=j] {
+                    s.visit(&mut rewriter);
+                }
+
+                let ident = expect!([l.pat.kind] PatKind::Ident(_, ident, _) => ident);
+                let ty = l.ty.clone().unwrap_or_else(|| reflect_tcx_ty(tcx, ty));
+                let maybe_uninit = mk().path_expr(vec![
+                    mk().path_segment(""),
+                    mk().path_segment("std"),
+                    mk().path_segment("mem"),
+                    mk().path_segment_with_args("MaybeUninit", mk().angle_bracketed_args(vec![ty])),
+                    mk().path_segment("uninit"),
+                ]);
+                l.pat = mk().mutbl().ident_pat(ident);
+                l.ty = None;
+                l.init = Some(mk().call_expr(maybe_uninit, Vec::<P<Expr>>::new()));
+                b.stmts[i].kind = StmtKind::Local(l);
+
+                let modified = b.stmts[j + 1..].iter().any(|s| {
+                    let mut found = false;
+                    visit_nodes(s, |e: &Expr| found |= write_ids.contains(&e.id));
+                    found
+                });
+                let assume_init = mk().method_call_expr(
+                    mk().ident_expr(ident), "assume_init", Vec::<P<Expr>>::new());
+                let init = mk().block_expr(mk().unsafe_().block(vec![mk().expr_stmt(assume_init)]));
+                let mutbl = if modified { Mutability::Mutable } else { Mutability::Immutable };
+                let pat = mk().set_mutbl(mutbl).ident_pat(ident);
+                inserts.insert(j, mk().local_stmt(P(mk().local(pat, None as Option<P<Ty>>,
+                                                               Some(init)))));
+            }
+
+            if inserts.is_empty() {
+                return;
+            }
+            let stmts = mem::replace(&mut b.stmts, Vec::new());
+            for (i, s) in stmts.into_iter().enumerate() {
+                b.stmts.push(s);
+                b.stmts.extend(inserts.remove(&i));
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+/// Find the statement in `stmts`, starting at `start`, after which the local `local` of type
+/// `ty` is fully initialized.  Returns `None` if the local may be read before that point.
+fn uninit_init_point<'tcx>(cx: &RefactorCtxt<'_, 'tcx>, local: HirId, ty: ty::Ty<'tcx>,
+                           stmts: &[Stmt], start: usize) -> Option<usize> {
+    let tcx = cx.ty_ctxt();
+    let fields = match ty.kind {
+        TyKind::Adt(def, _) if def.is_struct() => {
+            def.non_enum_variant().fields.iter().map(|f| f.ident.name).collect()
+        }
+        _ => HashSet::new(),
+    };
+    let array_len = match ty.kind {
+        TyKind::Array(_, len) => len.try_eval_usize(tcx, ParamEnv::empty()),
+        _ => None,
+    };
+    // Assigning to part of `local` through a pointer drops the uninitialized old value.
+    let partial_ok = !ty.needs_drop(tcx, ParamEnv::empty());
+
+    let mut assigned = HashSet::new();
+    for (j, s) in stmts.iter().enumerate().skip(start) {
+        let uses = uninit_uses(cx, local, s);
+        if uses.read || (uses.partial_write && !partial_ok) {
+            return None;
+        }
+
+        let e = match_or!([stmt_expr(s)] Some(x) => x; continue);
+        let done = match e.kind {
+            ExprKind::Assign(ref lhs, _) => match lhs.kind {
+                _ if cx.try_resolve_expr_to_hid(lhs) == Some(local) => true,
+                ExprKind::Field(ref base, ident)
+                    if cx.try_resolve_expr_to_hid(base) == Some(local) => {
+                    assigned.insert(ident.name);
+                    !fields.is_empty() && assigned.is_superset(&fields)
+                }
+                _ => false,
+            },
+            ExprKind::ForLoop(..) | ExprKind::While(..) => {
+                array_len.map_or(false, |len| fills_array(cx, local, len, stmts, j))
+            }
+            _ => false,
+        };
+        if done {
+            return Some(j);
+        }
+    }
+    None
+}
+
+/// How a statement uses a local that may not be initialized yet.
+#[derive(Default)]
+struct UninitUses {
+    /// The local is used other than by assigning to it.
+    read: bool,
+    /// A field or element of the local is assigned to.
+    partial_write: bool,
+}
+
+fn uninit_uses(cx: &RefactorCtxt, local: HirId, s: &Stmt) -> UninitUses {
+    struct UseVisitor<'a, 'b, 'tcx> {
+        cx: &'a RefactorCtxt<'b, 'tcx>,
+        local: HirId,
+        uses: UninitUses,
+    }
+
+    impl<'a, 'b, 'tcx> UseVisitor<'a, 'b, 'tcx> {
+        fn is_local(&self, e: &Expr) -> bool {
+            self.cx.try_resolve_expr_to_hid(e) == Some(self.local)
+        }
+
+        /// Visit the assigned place `e`, and check whether it's (part of) the local.
+        fn visit_place(&mut self, e: &Expr) -> bool {
+            match e.kind {
+                ExprKind::Field(ref base, _) | ExprKind::Paren(ref base) => self.visit_place(base),
+                ExprKind::Index(ref base, ref idx) => {
+                    self.visit_expr(idx);
+                    self.visit_place(base)
+                }
+                _ if self.is_local(e) => true,
+                _ => {
+                    self.visit_expr(e);
+                    false
+                }
+            }
+        }
+    }
+
+    impl<'a, 'b, 'tcx, 'ast> Visitor<'ast> for UseVisitor<'a, 'b, 'tcx> {
+        fn visit_expr(&mut self, e: &'ast Expr) {
+            match e.kind {
+                ExprKind::Assign(ref lhs, ref rhs) => {
+                    if self.visit_place(lhs) && !self.is_local(lhs) {
+                        self.uses.partial_write = true;
+                    }
+                    self.visit_expr(rhs);
+                }
+                _ if self.is_local(e) => self.uses.read = true,
+                _ => visit::walk_expr(self, e),
+            }
+        }
+
+        fn visit_mac(&mut self, mac: &'ast Mac) {
+            visit::walk_mac(self, mac)
+        }
+    }
+
+    let mut v = UseVisitor { cx, local, uses: UninitUses::default() };
+    v.visit_stmt(s);
+    v.uses
+}
+
+/// Check whether the loop `stmts[j]` assigns every element of the local array `local`, which
+/// has `len` elements.  The loop has to count from zero up to `len`, either as `for i in
+/// 0..len`, or as `while i < len` with `i += 1` at the end of its body, where `i` is zero on
+/// entry, and assign `local[i]` at the top level of its body.
+fn fills_array(cx: &RefactorCtxt, local: HirId, len: u64, stmts: &[Stmt], j: usize) -> bool {
+    let resolves_to = |e: &Expr, id: HirId| cx.try_resolve_expr_to_hid(e) == Some(id);
+    let is_lit = |e: &P<Expr>, n: u64| int_lit_value(strip_casts(e)) == Some(n as u128);
+
+    let e = match_or!([stmt_expr(&stmts[j])] Some(x) => x; return false);
+    let (counter, body) = match e.kind {
+        ExprKind::ForLoop(ref pat, ref iter, ref body, _) => {
+            match iter.kind {
+                ExprKind::Range(Some(ref lo), Some(ref hi), RangeLimits::HalfOpen)
+                    if is_lit(lo, 0) && is_lit(hi, len) => {}
+                _ => return false,
+            }
+            (cx.hir_map().node_to_hir_id(pat.id), body)
+        }
+        ExprKind::While(ref cond, ref body, _) => {
+            let counter = match cond.kind {
+                ExprKind::Binary(op, ref l, ref r)
+                    if op.node == BinOpKind::Lt && is_lit(r, len) => {
+                    match_or!([cx.try_resolve_expr_to_hid(strip_casts(l))] Some(x) => x;
+                              return false)
+                }
+                _ => return false,
+            };
+
+            // The last statement before the loop that touches the counter sets it to zero.
+            let prev = stmts[..j].iter().rev().find(|s| match s.kind {
+                StmtKind::Local(ref l) => cx.hir_map().node_to_hir_id(l.pat.id) == counter,
+                _ => mentions_local(cx, *s, counter),
+            });
+            let starts_at_zero = match prev.map(|s| &s.kind) {
+                Some(StmtKind::Local(l)) => l.init.as_ref().map_or(false, |e| is_lit(e, 0)),
+                Some(_) => assign_to_local(cx, prev.unwrap(), counter)
+                    .map_or(false, |(_, rhs)| is_lit(rhs, 0)),
+                None => false,
+            };
+            if !starts_at_zero {
+                return false;
+            }
+
+            // The body counts up by one at the end, and doesn't change the counter otherwise.
+            let (last, rest) = match_or!([body.stmts.split_last()] Some(x) => x; return false);
+            let inc = match_or!([stmt_expr(last)] Some(x) => x; return false);
+            match inc.kind {
+                ExprKind::AssignOp(op, ref lhs, ref rhs)
+                    if op.node == BinOpKind::Add && resolves_to(lhs, counter) &&
+                       is_lit(rhs, 1) => {}
+                _ => return false,
+            }
+            let mut modified = false;
+            for s in rest {
+                visit_nodes(s, |e: &Expr| match e.kind {
+                    ExprKind::Assign(ref lhs, _) | ExprKind::AssignOp(_, ref lhs, _) |
+                    ExprKind::AddrOf(_, Mutability::Mutable, ref lhs)
+                        if resolves_to(lhs, counter) => modified = true,
+                    _ => {}
+                });
+            }
+            if modified {
+                return false;
+            }
+            (counter, body)
+        }
+        _ => return false,
+    };
+
+    let mut exits = false;
+    visit_nodes(&**body, |e: &Expr| match e.kind {
+        ExprKind::Break(..) | ExprKind::Continue(..) => exits = true,
+        _ => {}
+    });
+    if exits {
+        return false;
+    }
+
+    body.stmts.iter().any(|s| {
+        let e = match_or!([stmt_expr(s)] Some(x) => x; return false);
+        let lhs = match_or!([e.kind] ExprKind::Assign(ref lhs, _) => lhs; return false);
+        match lhs.kind {
+            ExprKind::Index(ref base, ref idx) => {
+                resolves_to(base, local) && resolves_to(strip_casts(idx), counter)
+            }
+            _ => false,
+        }
+    })
+}
+
+/// Check whether the all-zero bit pattern is a valid value of type `ty`.
+fn zero_is_valid<'tcx>(cx: &RefactorCtxt<'_, 'tcx>, ty: ty::Ty<'tcx>) -> bool {
+    let tcx = cx.ty_ctxt();
+    match ty.kind {
+        TyKind::Bool | TyKind::Char | TyKind::Int(_) | TyKind::Uint(_) | TyKind::Float(_) |
+        TyKind::RawPtr(_) => true,
+        TyKind::Array(elem, _) => zero_is_valid(cx, elem),
+        TyKind::Tuple(_) => ty.tuple_fields().all(|ty| zero_is_valid(cx, ty)),
+        TyKind::Adt(def, substs) if def.is_struct() || def.is_union() => {
+            def.all_fields().all(|f| zero_is_valid(cx, f.ty(tcx, substs)))
+        }
+        // `None` is all zeros when the payload can't be null.
+        TyKind::Adt(def, substs) if is_option(cx, def.did) => match substs.type_at(0).kind {
+            TyKind::Ref(..) | TyKind::FnPtr(..) => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Rewrite the assignments to a local that's been converted to `MaybeUninit`: `x = v` becomes
+/// `x.write(v)`, and `x.a = v` becomes `(*x.as_mut_ptr()).a = v`.
+struct UninitWriteRewriter<'a, 'b, 'tcx> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    local: HirId,
+}
+
+impl<'a, 'b, 'tcx> MutVisitor for UninitWriteRewriter<'a, 'b, 'tcx> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        mut_visit::noop_visit_expr(e, self);
+
+        let write = match e.kind {
+            ExprKind::Assign(ref mut lhs, ref rhs) => {
+                if self.cx.try_resolve_expr_to_hid(lhs) == Some(self.local) {
+                    Some(mk().method_call_expr(lhs.clone(), "write", vec![rhs.clone()]))
+                } else {
+                    let root = place_root(lhs);
+                    if self.cx.try_resolve_expr_to_hid(root) == Some(self.local) {
+                        let ptr = mk().method_call_expr(
+                            root.clone(), "as_mut_ptr", Vec::<P<Expr>>::new());
+                        *root = mk().unary_expr(UnOp::Deref, ptr);
+                    }
+                    None
+                }
+            }
+            _ => None,
+        };
+        if let Some(write) = write {
+            *e = write;
+        }
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+/// Get the local or other expression at the root of the place `e`, below any fields and
+/// indexing.
+fn place_root(e: &mut P<Expr>) -> &mut P<Expr> {
+    match e.kind {
+        ExprKind::Field(..) | ExprKind::Index(..) | ExprKind::Paren(..) => {}
+        _ => return e,
+    }
+    match e.kind {
+        ExprKind::Field(ref mut base, _) | ExprKind::Index(ref mut base, _) |
+        ExprKind::Paren(ref mut base) => place_root(base),
+        _ => unreachable!(),
+    }
+}
+
+
+/// # `remove_redundant_let_types` Command
 ///
 /// Removes types from all `let` statements where the initializer's type matches the declared one,
 /// so the latter can be omitted and inferred.
@@ -512,8 +1144,6 @@ impl Transform for RemoveRedundantLetTypes {
 
 /// # `expand_local_ptr_tys` Command
 ///
-/// Usage: `expand_local_ptr_tys`
-///
 /// Ownership analysis now supports locals and therefore it may be necessary to
 /// add explicit type annotations to locals which are missing them. This is because
 /// ownership analysis marks types
@@ -548,14 +1178,34 @@ fn expand_local_ptr_tys(st: &CommandState, cx: &RefactorCtxt) {
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("let_x_uninitialized", |_args| mk(LetXUninitialized));
-    reg.register("sink_lets", |_args| mk(SinkLets));
-    reg.register("fold_let_assign", |_args| mk(FoldLetAssign));
-    reg.register("uninit_to_default", |_args| mk(UninitToDefault));
-    reg.register("remove_redundant_let_types", |_args| mk(RemoveRedundantLetTypes));
-    reg.register("expand_local_ptr_tys", |_args| {
-        Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+    let spec = CommandSpec::new("Initialize uninitialized locals with `mem::uninitialized()`.");
+    reg.register("let_x_uninitialized", spec, |_args| Ok(mk(LetXUninitialized)));
+
+    let spec = CommandSpec::new("Move trivially initialized locals into the block using them.");
+    reg.register("sink_lets", spec, |_args| Ok(mk(SinkLets)));
+
+    let spec = CommandSpec::new("Fold `let`s with no initializer into the following assignments.");
+    reg.register("fold_let_assign", spec, |_args| Ok(mk(FoldLetAssign)));
+
+    let spec = CommandSpec::new("Move each local's declaration down to its first use.");
+    reg.register("sink_let_bindings", spec, |_args| Ok(mk(SinkLetBindings)));
+
+    let spec = CommandSpec::new("Replace `mem::uninitialized()` initializers with defaults.");
+    reg.register("uninit_to_default", spec, |_args| Ok(mk(UninitToDefault)));
+
+    let spec = CommandSpec::new("Replace `mem::uninitialized()` locals with `MaybeUninit`.");
+    reg.register("fix_uninitialized", spec, |_args| Ok(mk(FixUninitialized)));
+
+    let spec = CommandSpec::new("Remove `let` types that match the initializer's type.");
+    reg.register("remove_redundant_let_types", spec, |_args| Ok(mk(RemoveRedundantLetTypes)));
+
+    let spec = CommandSpec::new("Add explicit types to locals for ownership analysis.");
+    reg.register("expand_local_ptr_tys", spec, |_args| {
+        Ok(Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
             expand_local_ptr_tys(st, cx);
-        }))
+        })))
     });
+
+    // `guard!(no_side_effects, $e:Expr)` matches expressions that are safe to drop or duplicate.
+    reg.register_guard("no_side_effects", |e, _mcx, cx| !expr_has_side_effects(cx, e));
 }
@@ -0,0 +1,19 @@
+#![feature(libc)]
+extern crate libc;
+
+fn main() {
+    let buf = [1u8, 2, 3, 4, 5];
+    let i: usize = 7;
+    let j: u32 = 3;
+    let k: i32 = -1;
+
+    let a = buf[i % buf.len()];
+    let b = buf[j as usize % buf.len()];
+    let c = i < buf.len();
+    let d = buf.len() >= 2usize;
+    let e = (j as usize).wrapping_add(buf.len());
+    // `i64` doesn't hold every `usize` value
+    let f = (k as i64) < buf.len() as i64;
+
+    println!("{} {} {} {} {} {}", a, b, c, d, e, f);
+}
@@ -0,0 +1,19 @@
+#![feature(libc)]
+extern crate libc;
+
+fn main() {
+    let buf = [1u8, 2, 3, 4, 5];
+    let i: usize = 7;
+    let j: u32 = 3;
+    let k: i32 = -1;
+
+    let a = buf[(i as libc::c_ulong).wrapping_rem(buf.len() as libc::c_ulong) as usize];
+    let b = buf[(j as u64 % buf.len() as u64) as usize];
+    let c = (i as u64) < buf.len() as u64;
+    let d = buf.len() as u64 >= 2;
+    let e = (j as u64).wrapping_add(buf.len() as u64) as usize;
+    // `i64` doesn't hold every `usize` value
+    let f = (k as i64) < buf.len() as i64;
+
+    println!("{} {} {} {} {} {}", a, b, c, d, e, f);
+}
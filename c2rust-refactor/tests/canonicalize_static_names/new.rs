@@ -0,0 +1,70 @@
+pub mod big {
+    pub mod list_c {
+        use super::*;
+
+        fn helper(x: i32) -> i32 {
+            x + 1
+        }
+
+        static mut COUNT: i32 = 0;
+
+        pub(super) unsafe fn push(x: i32) -> i32 {
+            COUNT += 1;
+            helper(x) + COUNT
+        }
+    }
+
+    pub mod node_c {
+        pub(crate) fn helper(x: i32) -> i32 {
+            x * 2
+        }
+
+        // Not renamed: `check` is already taken.
+        fn check_1() -> i32 {
+            1
+        }
+
+        fn check() -> i32 {
+            2
+        }
+
+        pub fn twice(x: i32) -> i32 {
+            self::helper(x) + check_1() + check()
+        }
+    }
+
+    pub mod util_c {
+        pub fn apply(x: i32) -> i32 {
+            super::node_c::helper(x)
+        }
+
+        use super::*;
+
+        // Not renamed: the new name would shadow the `fallback` imported from `super`.
+        fn fallback_2() -> i32 {
+            3
+        }
+
+        pub fn both() -> i32 {
+            fallback_2() + fallback()
+        }
+    }
+
+    pub(crate) use self::node_c::helper;
+
+    fn fallback() -> i32 {
+        4
+    }
+
+    pub unsafe fn demo() -> i32 {
+        list_c::push(1) + helper(2) + node_c::twice(3) + util_c::apply(4) + util_c::both()
+    }
+}
+
+fn main() {
+    unsafe {
+        assert_eq!(big::demo(), 3 + 4 + 9 + 8 + 7);
+    }
+    let h = big::helper(5);
+    assert_eq!(h, 10);
+}
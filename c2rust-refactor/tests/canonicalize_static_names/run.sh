@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(mod && name("_c$"));' \; \
+    canonicalize_static_names \
+    -- old.rs $rustflags
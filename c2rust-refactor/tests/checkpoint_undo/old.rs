@@ -0,0 +1,10 @@
+fn f(x: i32) -> i32 {
+    x + 1
+}
+
+fn main() {
+    let a = 1 + 1;
+    let b = 2 * 3;
+    let c = 4 - 1;
+    println!("{} {} {} {}", a, b, c, f(a));
+}
@@ -0,0 +1,20 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+# Only the rewrites of `1 + 1` and `4 - 1` survive: the `2 * 3` one is undone,
+# and everything after the checkpoint, including a mark, is reverted.
+$refactor \
+    rewrite_expr '1 + 1' '2' \; \
+    rewrite_expr '2 * 3' '6' \; \
+    undo \; \
+    checkpoint before_f \; \
+    rewrite_expr 'x + 1' 'x + 2' \; \
+    select target 'item(f);' \; \
+    rewrite_expr '4 - 1' '99' \; \
+    revert before_f \; \
+    rewrite_expr '4 - 1' '3' \
+    -- old.rs $rustflags
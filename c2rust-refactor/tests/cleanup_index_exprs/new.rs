@@ -0,0 +1,20 @@
+#![feature(libc)]
+extern crate libc;
+
+fn main() {
+    let buf = [1u8, 2, 3, 4, 5];
+    let i: u32 = 2;
+    let j: u32 = 3;
+    let k: i32 = -1;
+
+    let a = buf[i as usize + 1];
+    let b = buf[i as usize * 2 + j as usize];
+    // `j` may be zero, so this needs to stay checked
+    let c = buf[(j as usize).checked_sub(1).unwrap()];
+    // Wrapping brings `k = -1` back to `0`
+    let d = buf[(k as usize).wrapping_add(1)];
+    // The arithmetic is narrower than `usize`
+    let e = buf[(i as u16).wrapping_add(1) as usize];
+
+    println!("{} {} {} {} {}", a, b, c, d, e);
+}
@@ -0,0 +1,20 @@
+#![feature(libc)]
+extern crate libc;
+
+fn main() {
+    let buf = [1u8, 2, 3, 4, 5];
+    let i: u32 = 2;
+    let j: u32 = 3;
+    let k: i32 = -1;
+
+    let a = buf[(i as libc::c_ulong).wrapping_add(1 as libc::c_int as libc::c_ulong) as usize];
+    let b = buf[(i as libc::c_ulong).wrapping_mul(2).wrapping_add(j as libc::c_ulong) as usize];
+    // `j` may be zero, so this needs to stay checked
+    let c = buf[(j as libc::c_ulong).wrapping_sub(1 as libc::c_int as libc::c_ulong) as usize];
+    // Wrapping brings `k = -1` back to `0`
+    let d = buf[(k as libc::c_ulong).wrapping_add(1) as usize];
+    // The arithmetic is narrower than `usize`
+    let e = buf[(i as u16).wrapping_add(1) as usize];
+
+    println!("{} {} {} {} {}", a, b, c, d, e);
+}
@@ -0,0 +1,53 @@
+fn next(n: &mut i32) -> i32 {
+    *n -= 1;
+    *n
+}
+
+fn main() {
+    let mut sum = 0;
+
+    let mut i = 0;
+    while i < 10 {
+        i += 1;
+        if i % 2 == 0 {
+            continue;
+        }
+        sum += i;
+    }
+
+    let mut j = 0;
+    'outer: while j < 5 {
+        j += 1;
+    }
+
+    let mut k = 0;
+    k += 1;
+    sum += k;
+    while k < 3 {
+        k += 1;
+        sum += k;
+    }
+
+    let mut m = 0;
+    loop {
+        m += 1;
+        if m == 2 {
+            continue;
+        }
+        sum += m;
+        if !(m < 5) {
+            break;
+        }
+    }
+
+    let mut left = 3;
+    loop {
+        let n = next(&mut left);
+        sum += n;
+        if n == 0 {
+            break;
+        }
+    }
+
+    println!("{}", sum);
+}
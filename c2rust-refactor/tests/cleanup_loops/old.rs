@@ -0,0 +1,61 @@
+fn next(n: &mut i32) -> i32 {
+    *n -= 1;
+    *n
+}
+
+fn main() {
+    let mut sum = 0;
+
+    let mut i = 0;
+    loop {
+        if !(i < 10) {
+            break;
+        }
+        i += 1;
+        if i % 2 == 0 {
+            continue;
+        }
+        sum += i;
+    }
+
+    let mut j = 0;
+    'outer: loop {
+        if j < 5 {
+            j += 1;
+        } else {
+            break 'outer;
+        }
+    }
+
+    let mut k = 0;
+    loop {
+        k += 1;
+        sum += k;
+        if !(k < 3) {
+            break;
+        }
+    }
+
+    let mut m = 0;
+    loop {
+        m += 1;
+        if m == 2 {
+            continue;
+        }
+        sum += m;
+        if !(m < 5) {
+            break;
+        }
+    }
+
+    let mut left = 3;
+    loop {
+        let n = next(&mut left);
+        sum += n;
+        if n == 0 {
+            break;
+        }
+    }
+
+    println!("{}", sum);
+}
@@ -0,0 +1,31 @@
+#![feature(libc)]
+extern crate libc;
+
+extern "C" {
+    #[no_mangle]
+    fn __assert_fail(__assertion: *const libc::c_char, __file: *const libc::c_char,
+                     __line: libc::c_uint, __function: *const libc::c_char) -> !;
+    #[no_mangle]
+    fn abort() -> !;
+    #[no_mangle]
+    fn free(__ptr: *mut libc::c_void);
+}
+
+pub unsafe fn get(buf: *const libc::c_int, len: libc::c_int, i: libc::c_int) -> libc::c_int {
+    assert!(i < len, "i < len");
+    assert!(!buf.is_null());
+    assert!(len != 0);
+    return *buf.offset(i as isize);
+}
+
+pub unsafe fn ratio(a: libc::c_double, b: libc::c_double) -> libc::c_double {
+    assert!(!(b <= 0.0));
+    return a / b;
+}
+
+pub unsafe fn release(p: *mut libc::c_void) {
+    if p.is_null() {
+        free(p);
+        abort();
+    }
+}
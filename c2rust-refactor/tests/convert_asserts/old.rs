@@ -0,0 +1,37 @@
+#![feature(libc)]
+extern crate libc;
+
+extern "C" {
+    #[no_mangle]
+    fn __assert_fail(__assertion: *const libc::c_char, __file: *const libc::c_char,
+                     __line: libc::c_uint, __function: *const libc::c_char) -> !;
+    #[no_mangle]
+    fn abort() -> !;
+    #[no_mangle]
+    fn free(__ptr: *mut libc::c_void);
+}
+
+pub unsafe fn get(buf: *const libc::c_int, len: libc::c_int, i: libc::c_int) -> libc::c_int {
+    if i < len {
+    } else {
+        __assert_fail(b"i < len\x00" as *const u8 as *const libc::c_char,
+                      b"buf.c\x00" as *const u8 as *const libc::c_char,
+                      4 as libc::c_int as libc::c_uint,
+                      (*::std::mem::transmute::<&[u8; 4], &[libc::c_char; 4]>(b"get\x00")).as_ptr());
+    };
+    if buf.is_null() { abort(); }
+    if len == 0 { abort(); }
+    return *buf.offset(i as isize);
+}
+
+pub unsafe fn ratio(a: libc::c_double, b: libc::c_double) -> libc::c_double {
+    if b <= 0.0 { abort(); }
+    return a / b;
+}
+
+pub unsafe fn release(p: *mut libc::c_void) {
+    if p.is_null() {
+        free(p);
+        abort();
+    }
+}
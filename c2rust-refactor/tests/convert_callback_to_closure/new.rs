@@ -0,0 +1,57 @@
+#![feature(libc)]
+extern crate libc;
+
+unsafe fn sort_with(
+    base: *mut i32,
+    n: usize,
+    mut cmp: impl FnMut(*const libc::c_void, *const libc::c_void) -> libc::c_int,
+) {
+    for i in 1..n {
+        let mut j = i;
+        while j > 0 &&
+            cmp(
+                base.add(j - 1) as *const libc::c_void,
+                base.add(j) as *const libc::c_void,
+            ) > 0
+        {
+            std::ptr::swap(base.add(j - 1), base.add(j));
+            j -= 1;
+        }
+    }
+}
+
+unsafe extern "C" fn print_item(item: *mut i32, _ctx: *mut libc::c_void) {
+    println!("{}", *item);
+}
+
+unsafe fn visit_all(
+    items: *mut i32,
+    n: usize,
+    f: Option<unsafe extern "C" fn(*mut i32, *mut libc::c_void)>,
+    ctx: *mut libc::c_void,
+) {
+    for i in 0..n {
+        f.unwrap()(items.add(i), ctx);
+    }
+}
+
+fn main() {
+    let mut items = [5, 1, 4, 2, 3];
+    unsafe {
+        let mut key: i32 = 3;
+        let mut calls: i32 = 0;
+        sort_with(
+            items.as_mut_ptr(),
+            items.len(),
+            |a, b| unsafe {
+                calls += 1;
+                let da = (*(a as *const i32) - key).abs();
+                let db = (*(b as *const i32) - key).abs();
+                da - db
+            },
+        );
+        println!("{} comparisons", calls);
+        // A null context can't be converted
+        visit_all(items.as_mut_ptr(), items.len(), Some(print_item), 0 as *mut libc::c_void);
+    }
+}
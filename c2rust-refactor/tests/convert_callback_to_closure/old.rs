@@ -0,0 +1,75 @@
+#![feature(libc)]
+extern crate libc;
+
+#[repr(C)]
+pub struct cmp_ctx {
+    pub key: i32,
+    pub calls: i32,
+}
+
+unsafe extern "C" fn cmp_by_distance(
+    a: *const libc::c_void,
+    b: *const libc::c_void,
+    ctx: *mut libc::c_void,
+) -> libc::c_int {
+    let c = ctx as *mut cmp_ctx;
+    (*c).calls += 1;
+    let da = (*(a as *const i32) - (*c).key).abs();
+    let db = (*(b as *const i32) - (*c).key).abs();
+    da - db
+}
+
+unsafe fn sort_with(
+    base: *mut i32,
+    n: usize,
+    cmp: Option<
+        unsafe extern "C" fn(*const libc::c_void, *const libc::c_void, *mut libc::c_void)
+            -> libc::c_int,
+    >,
+    ctx: *mut libc::c_void,
+) {
+    for i in 1..n {
+        let mut j = i;
+        while j > 0 &&
+            cmp.unwrap()(
+                base.add(j - 1) as *const libc::c_void,
+                base.add(j) as *const libc::c_void,
+                ctx,
+            ) > 0
+        {
+            std::ptr::swap(base.add(j - 1), base.add(j));
+            j -= 1;
+        }
+    }
+}
+
+unsafe extern "C" fn print_item(item: *mut i32, _ctx: *mut libc::c_void) {
+    println!("{}", *item);
+}
+
+unsafe fn visit_all(
+    items: *mut i32,
+    n: usize,
+    f: Option<unsafe extern "C" fn(*mut i32, *mut libc::c_void)>,
+    ctx: *mut libc::c_void,
+) {
+    for i in 0..n {
+        f.unwrap()(items.add(i), ctx);
+    }
+}
+
+fn main() {
+    let mut items = [5, 1, 4, 2, 3];
+    unsafe {
+        let mut ctx = cmp_ctx { key: 3, calls: 0 };
+        sort_with(
+            items.as_mut_ptr(),
+            items.len(),
+            Some(cmp_by_distance),
+            &mut ctx as *mut cmp_ctx as *mut libc::c_void,
+        );
+        println!("{} comparisons", ctx.calls);
+        // A null context can't be converted
+        visit_all(items.as_mut_ptr(), items.len(), Some(print_item), 0 as *mut libc::c_void);
+    }
+}
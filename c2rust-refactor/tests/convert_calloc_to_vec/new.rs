@@ -0,0 +1,77 @@
+use std::ffi::c_void;
+use std::mem::size_of;
+
+extern "C" {
+    fn calloc(nmemb: usize, size: usize) -> *mut c_void;
+    fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void;
+    fn free(ptr: *mut c_void);
+}
+
+static mut LEN_CALLS: i32 = 0;
+
+fn next_len() -> usize {
+    unsafe {
+        LEN_CALLS += 1;
+    }
+    4
+}
+
+fn audit_alloc<T>(p: *mut T) -> *mut T {
+    p
+}
+
+unsafe fn sum_squares(n: i32) -> i32 {
+    let mut buf = vec![i32::default(); n as usize];
+    let mut i = 0;
+    while i < n {
+        buf[i as usize] = i * i;
+        i += 1;
+    }
+    let mut sum = 0;
+    i = 0;
+    while i < n {
+        sum += buf[i as usize];
+        i += 1;
+    }
+    sum
+}
+
+unsafe fn freed_early() -> u8 {
+    let mut bytes: Vec<u8> = vec![u8::default(); next_len()];
+    bytes[0] = 1;
+    bytes[1] = 2;
+    let x = bytes[0] + bytes[1];
+    drop(bytes);
+    let y = x * 2;
+    y
+}
+
+unsafe fn grows(n: usize) -> i32 {
+    let mut buf = audit_alloc(calloc(n, size_of::<i32>()) as *mut i32);
+    *buf = 7;
+    buf = realloc(buf as *mut c_void, 2 * n * size_of::<i32>()) as *mut i32;
+    let x = *buf;
+    free(buf as *mut c_void);
+    x
+}
+
+unsafe fn escapes(out: &mut *mut i32) {
+    let buf = audit_alloc(calloc(8, size_of::<i32>()) as *mut i32);
+    *buf.offset(3) = 5;
+    *out = buf;
+}
+
+fn main() {
+    unsafe {
+        let mut q = std::ptr::null_mut();
+        escapes(&mut q);
+        println!(
+            "{} {} {} {}",
+            sum_squares(4),
+            freed_early(),
+            grows(2),
+            LEN_CALLS
+        );
+        free(q as *mut c_void);
+    }
+}
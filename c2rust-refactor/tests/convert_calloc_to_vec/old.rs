@@ -0,0 +1,78 @@
+use std::ffi::c_void;
+use std::mem::size_of;
+
+extern "C" {
+    fn calloc(nmemb: usize, size: usize) -> *mut c_void;
+    fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void;
+    fn free(ptr: *mut c_void);
+}
+
+static mut LEN_CALLS: i32 = 0;
+
+fn next_len() -> usize {
+    unsafe {
+        LEN_CALLS += 1;
+    }
+    4
+}
+
+fn audit_alloc<T>(p: *mut T) -> *mut T {
+    p
+}
+
+unsafe fn sum_squares(n: i32) -> i32 {
+    let buf = calloc(n as usize, size_of::<i32>()) as *mut i32;
+    let mut i = 0;
+    while i < n {
+        *buf.offset(i as isize) = i * i;
+        i += 1;
+    }
+    let mut sum = 0;
+    i = 0;
+    while i < n {
+        sum += *buf.offset(i as isize);
+        i += 1;
+    }
+    free(buf as *mut c_void);
+    sum
+}
+
+unsafe fn freed_early() -> u8 {
+    let bytes: *mut u8 = calloc(next_len(), size_of::<u8>() as _) as *mut u8;
+    *bytes = 1;
+    *bytes.add(1) = 2;
+    let x = *bytes + *bytes.add(1);
+    free(bytes as *mut c_void);
+    let y = x * 2;
+    y
+}
+
+unsafe fn grows(n: usize) -> i32 {
+    let mut buf = calloc(n, size_of::<i32>()) as *mut i32;
+    *buf = 7;
+    buf = realloc(buf as *mut c_void, 2 * n * size_of::<i32>()) as *mut i32;
+    let x = *buf;
+    free(buf as *mut c_void);
+    x
+}
+
+unsafe fn escapes(out: &mut *mut i32) {
+    let buf = calloc(8, size_of::<i32>()) as *mut i32;
+    *buf.offset(3) = 5;
+    *out = buf;
+}
+
+fn main() {
+    unsafe {
+        let mut q = std::ptr::null_mut();
+        escapes(&mut q);
+        println!(
+            "{} {} {} {}",
+            sum_squares(4),
+            freed_early(),
+            grows(2),
+            LEN_CALLS
+        );
+        free(q as *mut c_void);
+    }
+}
@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    convert_calloc_to_vec \; \
+    rewrite_expr 'marked!($e:Expr, calloc_review)' 'audit_alloc($e)' \
+    -- old.rs $rustflags
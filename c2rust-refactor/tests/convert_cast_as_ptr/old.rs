@@ -0,0 +1,16 @@
+fn main() {
+    let mut buf = [1u8, 2, 3, 4];
+
+    let shared: &[u8; 4] = &buf;
+    let p = shared as *const u8;
+    // `as_mut_ptr` needs a `&mut`, so this one keeps its casts
+    let r = shared as *const u8 as *mut u8;
+
+    let exclusive: &mut [u8; 4] = &mut buf;
+    let q = exclusive as *mut u8;
+
+    unsafe {
+        println!("{} {}", *p, *r);
+        *q = 5;
+    }
+}
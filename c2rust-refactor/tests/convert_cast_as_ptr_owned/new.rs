@@ -0,0 +1,41 @@
+struct Buf {
+    data: Vec<u8>,
+}
+
+fn fill(buf: &mut Buf, shared: &Buf) {
+    // A field through a `&mut` can be mutably borrowed, one through a `&` can't
+    let p = buf.data.as_mut_ptr();
+    let q = shared.data as *mut u8;
+    let r = shared.data.as_ptr();
+    unsafe {
+        *p = *r;
+        println!("{:?}", q);
+    }
+}
+
+fn main() {
+    // Buffers that were retyped from raw pointers to owned types, leaving the
+    // casts at their uses behind
+    let mut v: Vec<u8> = vec![1, 2, 3];
+    let frozen: Vec<u8> = vec![4, 5, 6];
+    let mut b: Box<[u16]> = vec![7u16, 8].into_boxed_slice();
+    let mut s = String::from("hi");
+    let t = String::from("lo");
+
+    let vp = v.as_ptr();
+    let vq = v.as_mut_ptr();
+    let fp = frozen.as_ptr();
+    let fq = frozen as *mut u8;
+    let bp = b.as_ptr();
+    let bq = b.as_mut_ptr();
+    let sp = s.as_ptr() as *const i8;
+    let sq = s.as_mut_ptr() as *mut u8;
+    let tp = t.as_ptr() as *const u8;
+    let tq = t as *mut u8;
+    println!("{:?} {:?} {:?} {:?} {:?}", vp, vq, fp, fq, bp);
+    println!("{:?} {:?} {:?} {:?} {:?}", bq, sp, sq, tp, tq);
+
+    let mut buf = Buf { data: vec![0; 4] };
+    let shared = Buf { data: vec![9; 4] };
+    fill(&mut buf, &shared);
+}
@@ -0,0 +1,17 @@
+const WIDE: u64 = 7u32 as u64;
+
+fn widen(a: i32, b: u8, c: bool, d: u16, e: u32, f: i8) -> (i64, char, u32, usize, usize, isize, u64) {
+    let buf = [0u8; 4u8 as usize];
+    let x = i64::from(a);
+    let y = char::from(b);
+    let z = u32::from(c);
+    let s = usize::from(d);
+    let t = e as usize;
+    let u = isize::from(f);
+    let v = a as u64;
+    (x, y, z + buf.len() as u32, s, t, u, v)
+}
+
+fn main() {
+    println!("{} {:?}", WIDE, widen(1, 65, true, 2, 3, -4));
+}
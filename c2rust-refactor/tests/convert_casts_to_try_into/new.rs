@@ -0,0 +1,17 @@
+use std::convert::TryFrom;
+const LIMIT: u8 = 300u32 as u8;
+
+fn narrow(x: u64, y: i32, z: i8, w: u16) -> (u32, u32, u32, i64, u64) {
+    let a = u32::try_from(x).unwrap();
+    let b = u32::try_from(y).unwrap();
+    let c = u32::try_from(z).unwrap();
+    let d = i64::from(w);
+    let e = 7 as u64;
+    (a, b, c, d, e)
+}
+
+fn main() {
+    let buf = [0u8; 16 as usize];
+    let n = narrow(1, 2, 3, 4);
+    println!("{} {} {:?}", LIMIT, u8::try_from(buf.len()).unwrap(), n);
+}
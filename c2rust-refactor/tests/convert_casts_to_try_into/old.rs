@@ -0,0 +1,16 @@
+const LIMIT: u8 = 300u32 as u8;
+
+fn narrow(x: u64, y: i32, z: i8, w: u16) -> (u32, u32, u32, i64, u64) {
+    let a = x as u32;
+    let b = y as u32;
+    let c = z as u32;
+    let d = w as i64;
+    let e = 7 as u64;
+    (a, b, c, d, e)
+}
+
+fn main() {
+    let buf = [0u8; 16 as usize];
+    let n = narrow(1, 2, 3, 4);
+    println!("{} {} {:?}", LIMIT, buf.len() as u8, n);
+}
@@ -0,0 +1,75 @@
+#![feature(libc)]
+extern crate libc;
+
+extern "C" {
+    #[no_mangle]
+    fn puts(_: *const libc::c_char) -> libc::c_int;
+    #[no_mangle]
+    fn strcpy(_: *mut libc::c_char, _: *const libc::c_char) -> *mut libc::c_char;
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct User {
+    pub id: libc::c_int,
+    pub name: [u8; 16],
+}
+impl User {
+    pub fn name_str(&self) -> &str {
+        let len = self.name.iter().position(|&b| b == 0).unwrap_or(self.name.len());
+        match ::std::str::from_utf8(&self.name[..len]) {
+            Ok(s) => s,
+            Err(e) => ::std::str::from_utf8(&self.name[..e.valid_up_to()]).unwrap(),
+        }
+    }
+    pub fn set_name(&mut self, s: &str) {
+        let mut len = ::std::cmp::min(s.len(), self.name.len().saturating_sub(1));
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.name[..len].copy_from_slice(&s.as_bytes()[..len]);
+        for b in &mut self.name[len..] {
+            *b = 0;
+        }
+    }
+}
+
+pub unsafe fn user_new(id: libc::c_int) -> User {
+    User {
+        id: id,
+        name: *b"anonymous\x00\x00\x00\x00\x00\x00\x00",
+    }
+}
+
+pub unsafe fn user_clear(u: *mut User) {
+    (*u).name = [0 as u8; 16];
+}
+
+pub unsafe fn user_rename(u: *mut User, name: *const libc::c_char) {
+    strcpy((*u).name.as_mut_ptr() as *mut libc::c_char, name);
+}
+
+pub unsafe fn user_print(u: *const User) {
+    puts((*u).name.as_ptr() as *const libc::c_char);
+}
+
+pub unsafe fn user_is_hidden(u: *const User) -> bool {
+    (*u).name[0] == '.' as i32 as u8
+}
+
+pub unsafe fn user_name_len(u: *const User) -> libc::c_int {
+    let mut n: libc::c_int = 0;
+    while (*u).name[n as usize] as libc::c_int != 0 {
+        n += 1;
+    }
+    n
+}
+
+pub unsafe fn user_capitalize(u: *mut User) {
+    let c: libc::c_char = (*u).name[0] as libc::c_char;
+    if c as libc::c_int >= 'a' as i32 && c as libc::c_int <= 'z' as i32 {
+        (*u).name[0] = (c as libc::c_int - 32) as u8;
+    }
+}
+
+fn main() {}
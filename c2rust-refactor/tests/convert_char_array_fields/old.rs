@@ -0,0 +1,58 @@
+#![feature(libc)]
+extern crate libc;
+
+extern "C" {
+    #[no_mangle]
+    fn puts(_: *const libc::c_char) -> libc::c_int;
+    #[no_mangle]
+    fn strcpy(_: *mut libc::c_char, _: *const libc::c_char) -> *mut libc::c_char;
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct User {
+    pub id: libc::c_int,
+    pub name: [libc::c_char; 16],
+}
+
+pub unsafe fn user_new(id: libc::c_int) -> User {
+    User {
+        id: id,
+        name: ::std::mem::transmute::<[u8; 16], [libc::c_char; 16]>(
+            *b"anonymous\x00\x00\x00\x00\x00\x00\x00",
+        ),
+    }
+}
+
+pub unsafe fn user_clear(u: *mut User) {
+    (*u).name = [0 as libc::c_char; 16];
+}
+
+pub unsafe fn user_rename(u: *mut User, name: *const libc::c_char) {
+    strcpy((*u).name.as_mut_ptr(), name);
+}
+
+pub unsafe fn user_print(u: *const User) {
+    puts(&(*u).name as *const [libc::c_char; 16] as *const libc::c_char);
+}
+
+pub unsafe fn user_is_hidden(u: *const User) -> bool {
+    (*u).name[0] == '.' as i32 as libc::c_char
+}
+
+pub unsafe fn user_name_len(u: *const User) -> libc::c_int {
+    let mut n: libc::c_int = 0;
+    while *(*u).name.as_ptr().offset(n as isize) as libc::c_int != 0 {
+        n += 1;
+    }
+    n
+}
+
+pub unsafe fn user_capitalize(u: *mut User) {
+    let c: libc::c_char = (*u).name[0];
+    if c as libc::c_int >= 'a' as i32 && c as libc::c_int <= 'z' as i32 {
+        *(*u).name.as_mut_ptr().offset(0) = (c as libc::c_int - 32) as libc::c_char;
+    }
+}
+
+fn main() {}
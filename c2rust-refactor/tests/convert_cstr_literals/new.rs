@@ -0,0 +1,42 @@
+use std::os::raw::c_char;
+
+const CSTR_HELLO: &std::ffi::CStr =
+    unsafe { std::ffi::CStr::from_bytes_with_nul_unchecked(b"hello\0") };
+
+extern "C" {
+    fn puts(s: *const c_char) -> i32;
+    fn strlen(s: *const c_char) -> usize;
+}
+
+static mut GREETINGS: [*const c_char; 2] = [
+    CSTR_HELLO.as_ptr(),
+    unsafe { std::ffi::CStr::from_bytes_with_nul_unchecked(b"goodbye\0") }.as_ptr(),
+];
+
+mod report {
+    use std::os::raw::c_char;
+
+    pub unsafe fn show(puts: unsafe extern "C" fn(*const c_char) -> i32) {
+        puts(crate::CSTR_HELLO.as_ptr());
+        puts(
+            std::ffi::CStr::from_bytes_with_nul(b"report done\0")
+                .unwrap()
+                .as_ptr(),
+        );
+    }
+}
+
+fn main() {
+    unsafe {
+        puts(CSTR_HELLO.as_ptr());
+        let n = strlen(
+            std::ffi::CStr::from_bytes_with_nul(b"bytes\0")
+                .unwrap()
+                .as_ptr() as *const i8,
+        );
+        puts(b"two\x00nuls\x00" as *const u8 as *const c_char);
+        report::show(puts);
+        puts(GREETINGS[1]);
+        println!("{}", n);
+    }
+}
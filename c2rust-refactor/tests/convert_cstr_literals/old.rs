@@ -0,0 +1,31 @@
+use std::os::raw::c_char;
+
+extern "C" {
+    fn puts(s: *const c_char) -> i32;
+    fn strlen(s: *const c_char) -> usize;
+}
+
+static mut GREETINGS: [*const c_char; 2] = [
+    b"hello\x00" as *const u8 as *const c_char,
+    b"goodbye\x00" as *const u8 as *const c_char,
+];
+
+mod report {
+    use std::os::raw::c_char;
+
+    pub unsafe fn show(puts: unsafe extern "C" fn(*const c_char) -> i32) {
+        puts(b"hello\x00" as *const u8 as *const c_char);
+        puts(b"report done\x00" as *const u8 as *const c_char);
+    }
+}
+
+fn main() {
+    unsafe {
+        puts(b"hello\x00" as *const u8 as *const c_char);
+        let n = strlen(b"bytes\x00".as_ptr() as *const i8);
+        puts(b"two\x00nuls\x00" as *const u8 as *const c_char);
+        report::show(puts);
+        puts(GREETINGS[1]);
+        println!("{}", n);
+    }
+}
@@ -0,0 +1,54 @@
+#![feature(libc)]
+extern crate libc;
+
+use libc::{c_char, c_int};
+
+extern "C" {
+    fn strlen(s: *const c_char) -> usize;
+    fn puts(s: *const c_char) -> c_int;
+    fn getenv(name: *const c_char) -> *mut c_char;
+}
+
+unsafe fn greet(name: &str) -> usize {
+    puts(std::ffi::CString::new(name).unwrap().as_ptr());
+    if *name.as_bytes().get(0).unwrap_or(&0) as i8 == 0 {
+        return 0;
+    }
+    name.len() + (*name.as_bytes().get(1).unwrap_or(&0) as i8 as usize)
+}
+
+unsafe fn walk(mut s: *const c_char) -> usize {
+    let mut n = 0;
+    while *s != 0 {
+        s = s.offset(1);
+        n += 1;
+    }
+    n
+}
+
+unsafe fn count(s: *const c_char) -> usize {
+    let mut p = s;
+    while *p != 0 {
+        p = p.offset(1);
+    }
+    p.offset_from(s) as usize
+}
+
+pub unsafe fn shout(msg: &str) -> usize {
+    greet(msg)
+}
+#[export_name = "shout"]
+pub unsafe extern "C" fn shout_wrapper(msg: *const c_char) -> usize {
+    shout(std::ffi::CStr::from_ptr(msg).to_str().unwrap())
+}
+
+fn main() {
+    unsafe {
+        greet("world");
+        let home = getenv(b"HOME\x00".as_ptr() as *const c_char);
+        greet(unsafe { std::ffi::CStr::from_ptr(home).to_str().unwrap() });
+        shout("hey");
+        walk(home);
+        count(home);
+    }
+}
@@ -0,0 +1,51 @@
+#![feature(libc)]
+extern crate libc;
+
+use libc::{c_char, c_int};
+
+extern "C" {
+    fn strlen(s: *const c_char) -> usize;
+    fn puts(s: *const c_char) -> c_int;
+    fn getenv(name: *const c_char) -> *mut c_char;
+}
+
+unsafe fn greet(name: *const c_char) -> usize {
+    puts(name);
+    if *name == 0 {
+        return 0;
+    }
+    strlen(name) + (*name.offset(1) as usize)
+}
+
+unsafe fn walk(mut s: *const c_char) -> usize {
+    let mut n = 0;
+    while *s != 0 {
+        s = s.offset(1);
+        n += 1;
+    }
+    n
+}
+
+unsafe fn count(s: *const c_char) -> usize {
+    let mut p = s;
+    while *p != 0 {
+        p = p.offset(1);
+    }
+    p.offset_from(s) as usize
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn shout(msg: *const c_char) -> usize {
+    greet(msg)
+}
+
+fn main() {
+    unsafe {
+        greet(b"world\x00" as *const u8 as *const c_char);
+        let home = getenv(b"HOME\x00".as_ptr() as *const c_char);
+        greet(home);
+        shout(b"hey\x00".as_ptr() as *const c_char);
+        walk(home);
+        count(home);
+    }
+}
@@ -8,7 +8,7 @@ fi
 $refactor \
     select target 'crate; desc(fn && name("printf"));' \; \
     mark_arg_uses 0 target \; \
-    convert_format_args \
+    convert_format_args \; \
     clear_marks \; \
     select target 'crate; child(foreign_mod); last;' \; \
     -- old.rs $rustflags
@@ -0,0 +1,91 @@
+#![feature(label_break_value)]
+
+fn parse(data: &[u8]) -> i32 {
+    let mut sum = 0;
+    if data.is_empty() {
+        return -1;
+    }
+    for &b in data {
+        if b == 0 {
+            return -1;
+        }
+        sum += b as i32;
+    }
+    return sum;
+}
+
+fn load(n: usize) -> i32 {
+    let mut buf = Vec::new();
+    let mut status = 0;
+    if n > 10 {
+        status = -1;
+        drop(buf);
+        return status;
+    }
+    buf.resize(n, 1u8);
+    status = match buf.iter().sum::<u8>() {
+        3 => {
+            drop(buf);
+            return status;
+        }
+        x => x as i32,
+    };
+    drop(buf);
+    status
+}
+
+fn nested(a: i32, b: i32) -> i32 {
+    let mut log = Vec::new();
+    if a < 0 {
+        log.push(-1);
+        return log.len() as i32;
+    }
+    if b < 0 {
+        return log.len() as i32;
+    }
+    log.push(a + b);
+    log.push(-1);
+    log.len() as i32
+}
+
+fn report(v: &mut Vec<i32>, x: i32) {
+    if x == 0 {
+        return;
+    }
+    v.push(x);
+}
+
+fn count(xs: &[i32]) -> i32 {
+    let mut n = 0;
+    for &x in xs {
+        'skip: {
+            if x < 0 {
+                break 'skip;
+            }
+            n += x;
+        }
+        n += 1;
+    }
+    n
+}
+
+fn shadow(x: i32) -> i32 {
+    'l: {
+        let x = x * 2;
+        if x > 10 {
+            break 'l;
+        }
+        return x;
+    }
+    x
+}
+
+fn main() {
+    let mut v = Vec::new();
+    report(&mut v, 0);
+    report(&mut v, 4);
+    println!("{} {} {} {:?}", parse(b""), parse(b"ab\0"), parse(b"ab"), v);
+    println!("{} {} {}", load(11), load(3), load(5));
+    println!("{} {} {}", nested(-1, 0), nested(1, -1), nested(1, 1));
+    println!("{} {} {}", count(&[1, -2, 3]), shadow(3), shadow(7));
+}
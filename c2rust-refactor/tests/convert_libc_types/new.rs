@@ -0,0 +1,53 @@
+#![feature(libc)]
+extern crate libc;
+
+mod internal {
+    pub struct Counter {
+        pub total: i64,
+        pub hits: u32,
+    }
+
+    pub unsafe fn sum(xs: *const i32, n: usize) -> i64 {
+        let mut total: i64 = 0;
+        let mut i: usize = 0;
+        while i < n {
+            total += *xs.offset(i as isize) as i64;
+            i = i.wrapping_add(1);
+        }
+        total
+    }
+
+    pub fn record(c: &mut Counter, x: i32) {
+        let x: i32 = x;
+        c.total += x as i64;
+        c.hits = c.hits.wrapping_add(1);
+    }
+}
+
+mod ffi {
+    #[repr(C)]
+    pub struct Pair {
+        pub a: libc::c_int,
+        pub b: libc::c_int,
+    }
+
+    extern "C" {
+        pub fn abs(x: libc::c_int) -> libc::c_int;
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn pair_sum(p: Pair) -> libc::c_int {
+        let s: i32 = p.a + p.b;
+        abs(s)
+    }
+}
+
+fn main() {
+    let xs: [i32; 3] = [1, -2, 3];
+    let mut c = internal::Counter { total: 0, hits: 0 };
+    unsafe {
+        internal::record(&mut c, internal::sum(xs.as_ptr(), 3) as i32);
+        internal::record(&mut c, ffi::pair_sum(ffi::Pair { a: 4, b: -5 }));
+    }
+    println!("{} {}", c.total, c.hits);
+}
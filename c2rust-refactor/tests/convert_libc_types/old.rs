@@ -0,0 +1,53 @@
+#![feature(libc)]
+extern crate libc;
+
+mod internal {
+    pub struct Counter {
+        pub total: libc::c_long,
+        pub hits: libc::c_uint,
+    }
+
+    pub unsafe fn sum(xs: *const libc::c_int, n: libc::size_t) -> libc::c_long {
+        let mut total: libc::c_long = 0;
+        let mut i: libc::size_t = 0;
+        while i < n {
+            total += *xs.offset(i as isize) as libc::c_long;
+            i = i.wrapping_add(1);
+        }
+        total
+    }
+
+    pub fn record(c: &mut Counter, x: libc::c_int) {
+        let x: libc::c_int = x as libc::c_int;
+        c.total += x as libc::c_long;
+        c.hits = c.hits.wrapping_add(1);
+    }
+}
+
+mod ffi {
+    #[repr(C)]
+    pub struct Pair {
+        pub a: libc::c_int,
+        pub b: libc::c_int,
+    }
+
+    extern "C" {
+        pub fn abs(x: libc::c_int) -> libc::c_int;
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn pair_sum(p: Pair) -> libc::c_int {
+        let s: libc::c_int = p.a + p.b;
+        abs(s)
+    }
+}
+
+fn main() {
+    let xs: [libc::c_int; 3] = [1, -2, 3];
+    let mut c = internal::Counter { total: 0, hits: 0 };
+    unsafe {
+        internal::record(&mut c, internal::sum(xs.as_ptr(), 3) as libc::c_int);
+        internal::record(&mut c, ffi::pair_sum(ffi::Pair { a: 4, b: -5 }));
+    }
+    println!("{} {}", c.total, c.hits);
+}
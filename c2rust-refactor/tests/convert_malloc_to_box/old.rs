@@ -0,0 +1,90 @@
+use std::ffi::c_void;
+use std::mem::size_of;
+
+extern "C" {
+    fn malloc(size: usize) -> *mut c_void;
+    fn calloc(nmemb: usize, size: usize) -> *mut c_void;
+    fn free(ptr: *mut c_void);
+}
+
+#[derive(Clone, Copy, Default)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Clone, Copy)]
+struct NoDefault {
+    v: i32,
+}
+
+fn audit_alloc<T>(p: *mut T) -> *mut T {
+    p
+}
+
+unsafe fn straight_line() -> i32 {
+    let p: *mut Point = malloc(size_of::<Point>() as _) as *mut Point;
+    (*p).x = 1;
+    (*p).y = 2;
+    let sum = (*p).x + (*p).y;
+    free(p as *mut c_void);
+    sum
+}
+
+unsafe fn zeroed() -> i32 {
+    let p = calloc(1, size_of::<Point>()) as *mut Point;
+    let x = (*p).x;
+    free(p as *mut c_void);
+    x
+}
+
+unsafe fn early_return(flag: bool) -> i32 {
+    let p = malloc(size_of::<Point>()) as *mut Point;
+    (*p).x = 3;
+    if flag {
+        return -1;
+    }
+    let x = (*p).x;
+    free(p as *mut c_void);
+    x
+}
+
+unsafe fn conditional_free(flag: bool) -> i32 {
+    let p = malloc(size_of::<Point>()) as *mut Point;
+    (*p).x = 4;
+    let x = (*p).x;
+    if flag {
+        free(p as *mut c_void);
+    }
+    x
+}
+
+unsafe fn escapes(out: &mut *mut Point) {
+    let p = malloc(size_of::<Point>()) as *mut Point;
+    (*p).x = 5;
+    *out = p;
+}
+
+unsafe fn no_default() -> i32 {
+    let p = malloc(size_of::<NoDefault>()) as *mut NoDefault;
+    (*p).v = 6;
+    let v = (*p).v;
+    free(p as *mut c_void);
+    v
+}
+
+fn main() {
+    unsafe {
+        let mut q = std::ptr::null_mut();
+        escapes(&mut q);
+        println!(
+            "{} {} {} {} {}",
+            straight_line(),
+            zeroed(),
+            early_return(false),
+            conditional_free(true),
+            no_default()
+        );
+        free(q as *mut c_void);
+    }
+}
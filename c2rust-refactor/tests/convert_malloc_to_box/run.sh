@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    convert_malloc_to_box \; \
+    rewrite_expr 'marked!($e:Expr, malloc_review)' 'audit_alloc($e)' \
+    -- old.rs $rustflags
@@ -0,0 +1,62 @@
+fn next() -> i32 {
+    7
+}
+
+fn min_i32(a: i32, b: i32) -> i32 {
+    a.min(b)
+}
+
+fn max_u8(a: u8, b: u8) -> u8 {
+    a.max(b)
+}
+
+fn min_swapped(a: i64, b: i64) -> i64 {
+    a.min(b)
+}
+
+fn max_lit(a: i32) -> i32 {
+    a.max(0)
+}
+
+fn min_widened(a: i32, b: i32) -> i32 {
+    a.min(b)
+}
+
+fn abs_i32(x: i32) -> i32 {
+    x.abs()
+}
+
+fn abs_wrapping(x: i64) -> i64 {
+    x.abs()
+}
+
+fn clamp_i32(v: i32) -> i32 {
+    v.max(0).min(255)
+}
+
+fn clamp_chain(v: i32) -> i32 {
+    v.max(-10).min(10)
+}
+
+fn clamp_unknown(v: i32, lo: i32, hi: i32) -> i32 {
+    if v < lo { lo } else { v.min(hi) }
+}
+
+fn min_call(b: i32) -> i32 {
+    if next() < b { next() } else { b }
+}
+
+fn fmin(a: f32, b: f32) -> f32 {
+    if a < b { a } else { b }
+}
+
+fn fabs(x: f64) -> f64 {
+    if x < 0.0 { -x } else { x }
+}
+
+fn main() {
+    println!("{} {} {} {}", min_i32(1, 2), max_u8(3, 4), min_swapped(5, 6), max_lit(-7));
+    println!("{} {} {}", min_widened(8, 9), abs_i32(-10), abs_wrapping(-11));
+    println!("{} {} {}", clamp_i32(300), clamp_chain(-20), clamp_unknown(12, 0, 5));
+    println!("{} {} {}", min_call(13), fmin(1.5, 2.5), fabs(-3.5));
+}
@@ -0,0 +1,62 @@
+fn next() -> i32 {
+    7
+}
+
+fn min_i32(a: i32, b: i32) -> i32 {
+    if a < b { a } else { b }
+}
+
+fn max_u8(a: u8, b: u8) -> u8 {
+    if a > b { a } else { b }
+}
+
+fn min_swapped(a: i64, b: i64) -> i64 {
+    if b >= a { a } else { b }
+}
+
+fn max_lit(a: i32) -> i32 {
+    if 0 < a { a } else { 0 }
+}
+
+fn min_widened(a: i32, b: i32) -> i32 {
+    if (a as i64) < (b as i64) { a } else { b }
+}
+
+fn abs_i32(x: i32) -> i32 {
+    if x < 0 { -x } else { x }
+}
+
+fn abs_wrapping(x: i64) -> i64 {
+    if x >= 0 { x } else { x.wrapping_neg() }
+}
+
+fn clamp_i32(v: i32) -> i32 {
+    if v < 0 { 0 } else if v > 255 { 255 } else { v }
+}
+
+fn clamp_chain(v: i32) -> i32 {
+    if (if v > -10 { v } else { -10 }) < 10 { if v > -10 { v } else { -10 } } else { 10 }
+}
+
+fn clamp_unknown(v: i32, lo: i32, hi: i32) -> i32 {
+    if v < lo { lo } else if v > hi { hi } else { v }
+}
+
+fn min_call(b: i32) -> i32 {
+    if next() < b { next() } else { b }
+}
+
+fn fmin(a: f32, b: f32) -> f32 {
+    if a < b { a } else { b }
+}
+
+fn fabs(x: f64) -> f64 {
+    if x < 0.0 { -x } else { x }
+}
+
+fn main() {
+    println!("{} {} {} {}", min_i32(1, 2), max_u8(3, 4), min_swapped(5, 6), max_lit(-7));
+    println!("{} {} {}", min_widened(8, 9), abs_i32(-10), abs_wrapping(-11));
+    println!("{} {} {}", clamp_i32(300), clamp_chain(-20), clamp_unknown(12, 0, 5));
+    println!("{} {} {}", min_call(13), fmin(1.5, 2.5), fabs(-3.5));
+}
@@ -0,0 +1,63 @@
+#![feature(libc)]
+extern crate libc;
+
+extern "C" {
+    #[no_mangle]
+    fn memcpy(_: *mut libc::c_void, _: *const libc::c_void, _: libc::c_ulong) -> *mut libc::c_void;
+    #[no_mangle]
+    fn memmove(_: *mut libc::c_void, _: *const libc::c_void, _: libc::c_ulong)
+        -> *mut libc::c_void;
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Point {
+    pub x: libc::c_int,
+    pub y: libc::c_int,
+}
+
+#[repr(C)]
+pub struct Pair {
+    pub a: [libc::c_int; 8],
+    pub b: [libc::c_int; 8],
+}
+
+unsafe fn structs(mut a: Point, b: Point) -> Point {
+    std::ptr::copy_nonoverlapping(&b as *const Point, &mut a as *mut Point, 1);
+    a
+}
+
+unsafe fn arrays(src: &[Point; 4], name: &[u8], len: libc::c_ulong) {
+    let mut dst: [Point; 4] = [Point { x: 0, y: 0 }; 4];
+    dst[..4].copy_from_slice(&src[..4]);
+
+    let mut buf: Vec<u8> = vec![0; 64];
+    buf[..len as usize].copy_from_slice(&name[..len as usize]);
+
+    let mut pair = Pair {
+        a: [0; 8],
+        b: [1; 8],
+    };
+    pair.a[..8].copy_from_slice(&pair.b[..8]);
+
+    // A byte count that isn't visibly a number of elements
+    std::ptr::copy_nonoverlapping(
+        pair.b.as_ptr() as *const u8,
+        pair.a.as_mut_ptr() as *mut u8,
+        len as usize,
+    );
+}
+
+unsafe fn overlapping(buf: &mut [u8; 16]) {
+    // The buffers may overlap, so these stay raw
+    std::ptr::copy(buf.as_ptr(), buf.as_mut_ptr().offset(1), 3);
+    std::ptr::copy(buf.as_ptr(), buf.as_mut_ptr(), 16);
+}
+
+fn main() {
+    unsafe {
+        let p = structs(Point { x: 1, y: 2 }, Point { x: 3, y: 4 });
+        arrays(&[p; 4], b"name", 4);
+        overlapping(&mut [0; 16]);
+    }
+}
@@ -0,0 +1,68 @@
+#![feature(libc)]
+extern crate libc;
+
+extern "C" {
+    #[no_mangle]
+    fn memcpy(_: *mut libc::c_void, _: *const libc::c_void, _: libc::c_ulong)
+        -> *mut libc::c_void;
+    #[no_mangle]
+    fn memmove(_: *mut libc::c_void, _: *const libc::c_void, _: libc::c_ulong)
+        -> *mut libc::c_void;
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Point {
+    pub x: libc::c_int,
+    pub y: libc::c_int,
+}
+
+#[repr(C)]
+pub struct Pair {
+    pub a: [libc::c_int; 8],
+    pub b: [libc::c_int; 8],
+}
+
+unsafe fn structs(mut a: Point, b: Point) -> Point {
+    memcpy(&mut a as *mut Point as *mut libc::c_void,
+           &b as *const Point as *const libc::c_void,
+           ::std::mem::size_of::<Point>() as libc::c_ulong);
+    a
+}
+
+unsafe fn arrays(src: &[Point; 4], name: &[u8], len: libc::c_ulong) {
+    let mut dst: [Point; 4] = [Point { x: 0, y: 0 }; 4];
+    memcpy(dst.as_mut_ptr() as *mut libc::c_void,
+           src.as_ptr() as *const libc::c_void,
+           (4 as libc::c_ulong)
+               .wrapping_mul(::std::mem::size_of::<Point>() as libc::c_ulong));
+
+    let mut buf: Vec<u8> = vec![0; 64];
+    memcpy(buf.as_mut_ptr() as *mut libc::c_void,
+           name.as_ptr() as *const libc::c_void, len);
+
+    let mut pair = Pair { a: [0; 8], b: [1; 8] };
+    memmove(pair.a.as_mut_ptr() as *mut libc::c_void,
+            pair.b.as_ptr() as *const libc::c_void,
+            ::std::mem::size_of::<[libc::c_int; 8]>() as libc::c_ulong);
+
+    // A byte count that isn't visibly a number of elements
+    memcpy(pair.a.as_mut_ptr() as *mut libc::c_void,
+           pair.b.as_ptr() as *const libc::c_void, len);
+}
+
+unsafe fn overlapping(buf: &mut [u8; 16]) {
+    // The buffers may overlap, so these stay raw
+    memmove(buf.as_mut_ptr().offset(1) as *mut libc::c_void,
+            buf.as_ptr() as *const libc::c_void, 3 as libc::c_ulong);
+    memmove(buf.as_mut_ptr() as *mut libc::c_void,
+            buf.as_ptr() as *const libc::c_void, 16 as libc::c_ulong);
+}
+
+fn main() {
+    unsafe {
+        let p = structs(Point { x: 1, y: 2 }, Point { x: 3, y: 4 });
+        arrays(&[p; 4], b"name", 4);
+        overlapping(&mut [0; 16]);
+    }
+}
@@ -0,0 +1,64 @@
+#![feature(libc)]
+extern crate libc;
+
+extern "C" {
+    #[no_mangle]
+    fn memset(_: *mut libc::c_void, _: libc::c_int, _: libc::c_ulong) -> *mut libc::c_void;
+}
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct Config {
+    pub flags: libc::c_uint,
+    pub scale: libc::c_double,
+    pub name: [libc::c_char; 16],
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Node {
+    pub value: libc::c_int,
+    pub next: *mut Node,
+}
+
+unsafe fn reset(cfg: *mut Config, node: &mut Node) -> Config {
+    let mut local: Config = Config {
+        flags: 1,
+        scale: 2.0,
+        name: [0; 16],
+    };
+    local = Default::default();
+    *cfg = Default::default();
+
+    // `Node` has no `Default` impl
+    std::ptr::write_bytes(node as *mut Node, 0, 1);
+    local
+}
+
+unsafe fn buffers(len: libc::c_ulong) -> libc::c_int {
+    let mut buf: [u8; 64] = [1; 64];
+    buf.fill(0);
+
+    let mut name: [libc::c_char; 16] = [0; 16];
+    name[..len as usize].fill('-' as i32 as i8);
+
+    let mut vals: [libc::c_int; 4] = [1; 4];
+    vals[..2].fill(0);
+
+    // Filling `c_int`s with a non-zero byte has to stay raw
+    std::ptr::write_bytes(vals.as_mut_ptr(), 0xff, 4);
+
+    buf[0] as libc::c_int + name[0] as libc::c_int + vals[0]
+}
+
+fn main() {
+    unsafe {
+        let mut cfg = Config::default();
+        let mut node = Node {
+            value: 1,
+            next: 0 as *mut Node,
+        };
+        reset(&mut cfg, &mut node);
+        buffers(4);
+    }
+}
@@ -0,0 +1,64 @@
+#![feature(libc)]
+extern crate libc;
+
+extern "C" {
+    #[no_mangle]
+    fn memset(_: *mut libc::c_void, _: libc::c_int, _: libc::c_ulong) -> *mut libc::c_void;
+}
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct Config {
+    pub flags: libc::c_uint,
+    pub scale: libc::c_double,
+    pub name: [libc::c_char; 16],
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Node {
+    pub value: libc::c_int,
+    pub next: *mut Node,
+}
+
+unsafe fn reset(cfg: *mut Config, node: &mut Node) -> Config {
+    let mut local: Config = Config { flags: 1, scale: 2.0, name: [0; 16] };
+    memset(&mut local as *mut Config as *mut libc::c_void, 0 as libc::c_int,
+           ::std::mem::size_of::<Config>() as libc::c_ulong);
+    memset(cfg as *mut libc::c_void, 0 as libc::c_int,
+           ::std::mem::size_of::<Config>() as libc::c_ulong);
+
+    // `Node` has no `Default` impl
+    memset(node as *mut Node as *mut libc::c_void, 0 as libc::c_int,
+           ::std::mem::size_of::<Node>() as libc::c_ulong);
+    local
+}
+
+unsafe fn buffers(len: libc::c_ulong) -> libc::c_int {
+    let mut buf: [u8; 64] = [1; 64];
+    memset(buf.as_mut_ptr() as *mut libc::c_void, 0 as libc::c_int,
+           ::std::mem::size_of::<[u8; 64]>() as libc::c_ulong);
+
+    let mut name: [libc::c_char; 16] = [0; 16];
+    memset(name.as_mut_ptr() as *mut libc::c_void, '-' as i32, len);
+
+    let mut vals: [libc::c_int; 4] = [1; 4];
+    memset(vals.as_mut_ptr() as *mut libc::c_void, 0 as libc::c_int,
+           (2 as libc::c_ulong)
+               .wrapping_mul(::std::mem::size_of::<libc::c_int>() as libc::c_ulong));
+
+    // Filling `c_int`s with a non-zero byte has to stay raw
+    memset(vals.as_mut_ptr() as *mut libc::c_void, 0xff as libc::c_int,
+           ::std::mem::size_of::<[libc::c_int; 4]>() as libc::c_ulong);
+
+    buf[0] as libc::c_int + name[0] as libc::c_int + vals[0]
+}
+
+fn main() {
+    unsafe {
+        let mut cfg = Config::default();
+        let mut node = Node { value: 1, next: 0 as *mut Node };
+        reset(&mut cfg, &mut node);
+        buffers(4);
+    }
+}
@@ -0,0 +1,78 @@
+use std::ptr;
+
+pub struct Inner {
+    pub len: i32,
+}
+
+pub struct Node {
+    pub key: i32,
+    pub inner: Inner,
+    pub next: *mut Node,
+}
+
+unsafe fn lookup(head: *mut Node, key: i32) -> Option<*mut Node> {
+    let mut n = head;
+    while !n.is_null() {
+        if (*n).key == key {
+            return Some(n);
+        }
+        n = (*n).next;
+    }
+    None
+}
+
+unsafe fn total(head: *mut Node, key: i32) -> i32 {
+    let p = lookup(head, key).map_or(::std::ptr::null_mut(), |p| p);
+    if p.is_null() {
+        return -1;
+    }
+    (*p).key + (*p).inner.len + (*(*p).next).key
+}
+
+unsafe fn key_or_zero(head: *mut Node, key: i32) -> i32 {
+    let p = lookup(head, key).unwrap_or(ptr::null_mut());
+    if !p.is_null() {
+        (*p).key * 2
+    } else {
+        0
+    }
+}
+
+unsafe fn describe(head: *mut Node, key: i32) -> i32 {
+    let p = lookup(head, key).map_or(::std::ptr::null_mut(), |p| p);
+    if p.is_null() {
+        0
+    } else {
+        (*p).inner.len
+    }
+}
+
+// Not converted: `p` is used after the check.
+unsafe fn bump(head: *mut Node, key: i32) -> *mut Node {
+    let p = lookup(head, key).map_or(::std::ptr::null_mut(), |p| p);
+    if !p.is_null() {
+        (*p).key += 1;
+    }
+    p
+}
+
+fn main() {
+    unsafe {
+        let mut tail = Node {
+            key: 2,
+            inner: Inner { len: 20 },
+            next: ptr::null_mut(),
+        };
+        let mut head = Node {
+            key: 1,
+            inner: Inner { len: 10 },
+            next: &mut tail,
+        };
+        assert_eq!(total(&mut head, 1), 13);
+        assert_eq!(total(&mut head, 5), -1);
+        assert_eq!(key_or_zero(&mut head, 2), 4);
+        assert_eq!(key_or_zero(&mut head, 5), 0);
+        assert_eq!(describe(&mut head, 2), 20);
+        assert!(bump(&mut head, 5).is_null());
+    }
+}
@@ -0,0 +1,22 @@
+use std::os::raw::c_void;
+
+struct Node {
+    next: *mut Node,
+    data: *const u8,
+}
+
+static mut HEAD: *mut Node = ::std::ptr::null_mut::<Node>();
+static mut EMPTY: *const c_void = ::std::ptr::null::<c_void>();
+
+fn main() {
+    let n = Node {
+        next: ::std::ptr::null_mut::<Node>(),
+        data: ::std::ptr::null::<u8>(),
+    };
+    let p = ::std::ptr::null::<u32>();
+    let q = 0x1000 as *const u32;
+    let r = ::std::ptr::null_mut::<c_void>();
+    unsafe {
+        println!("{:?} {:?} {:?} {:?} {:?} {:?} {:?}", n.next, n.data, p, q, r, HEAD, EMPTY);
+    }
+}
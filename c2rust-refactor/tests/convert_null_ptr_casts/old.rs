@@ -0,0 +1,22 @@
+use std::os::raw::c_void;
+
+struct Node {
+    next: *mut Node,
+    data: *const u8,
+}
+
+static mut HEAD: *mut Node = 0 as *mut Node;
+static mut EMPTY: *const c_void = 0 as *mut u8 as *const c_void;
+
+fn main() {
+    let n = Node {
+        next: 0 as *mut Node,
+        data: 0i32 as *const u8,
+    };
+    let p = 0u64 as usize as *const u32;
+    let q = 0x1000 as *const u32;
+    let r = (0) as *mut c_void;
+    unsafe {
+        println!("{:?} {:?} {:?} {:?} {:?} {:?} {:?}", n.next, n.data, p, q, r, HEAD, EMPTY);
+    }
+}
@@ -0,0 +1,84 @@
+use std::ptr;
+
+pub struct Node {
+    pub key: i32,
+    pub next: *mut Node,
+}
+
+pub struct Cache {
+    pub last: *mut Node,
+}
+
+unsafe fn new_node(key: i32) -> Option<*mut Node> {
+    if key < 0 {
+        return None;
+    }
+    Some(Box::into_raw(Box::new(Node {
+        key: key,
+        next: ptr::null_mut(),
+    })))
+}
+
+unsafe fn push(head: *mut Node, key: i32) -> Option<*mut Node> {
+    let n = match new_node(key) {
+        Some(n) => n,
+        None => {
+            return None;
+        }
+    };
+    (*n).next = head;
+    Some(n)
+}
+
+unsafe fn push_pair(head: *mut Node, a: i32, b: i32) -> Option<*mut Node> {
+    let first = match push(head, a) {
+        Some(first) => first,
+        None => {
+            return None;
+        }
+    };
+    push(first, b)
+}
+
+// Not converted: `head` may be null.
+unsafe fn first(head: *mut Node) -> *mut Node {
+    head
+}
+
+unsafe fn remember(cache: &mut Cache, key: i32) {
+    cache.last = new_node(key).map_or(::std::ptr::null_mut(), |p| p);
+}
+
+unsafe fn sum_pair(a: i32, b: i32) -> i32 {
+    if let Some(list) = push_pair(ptr::null_mut(), a, b) {
+        (*list).key + (*(*list).next).key
+    } else {
+        -1
+    }
+}
+
+unsafe fn describe(key: i32) -> i32 {
+    match push(first(ptr::null_mut()), key) {
+        Some(n) => (*n).key * 10,
+        None => 0,
+    }
+}
+
+fn main() {
+    unsafe {
+        let mut cache = Cache {
+            last: ptr::null_mut(),
+        };
+        remember(&mut cache, 3);
+        assert_eq!((*cache.last).key, 3);
+        remember(&mut cache, -3);
+        assert!(cache.last.is_null());
+        let missing = new_node(-1).is_none();
+        let found = new_node(1).is_some();
+        assert!(missing && found);
+        assert_eq!(sum_pair(1, 2), 3);
+        assert_eq!(sum_pair(1, -2), -1);
+        assert_eq!(describe(4), 40);
+        assert_eq!(describe(-4), 0);
+    }
+}
@@ -0,0 +1,83 @@
+use std::ptr;
+
+pub struct Node {
+    pub key: i32,
+    pub next: *mut Node,
+}
+
+pub struct Cache {
+    pub last: *mut Node,
+}
+
+unsafe fn new_node(key: i32) -> *mut Node {
+    if key < 0 {
+        return ptr::null_mut();
+    }
+    Box::into_raw(Box::new(Node {
+        key: key,
+        next: ptr::null_mut(),
+    }))
+}
+
+unsafe fn push(head: *mut Node, key: i32) -> *mut Node {
+    let n = new_node(key);
+    if n.is_null() {
+        return ptr::null_mut();
+    }
+    (*n).next = head;
+    n
+}
+
+unsafe fn push_pair(head: *mut Node, a: i32, b: i32) -> *mut Node {
+    let first = push(head, a);
+    if first.is_null() {
+        return 0 as *mut Node;
+    }
+    push(first, b)
+}
+
+// Not converted: `head` may be null.
+unsafe fn first(head: *mut Node) -> *mut Node {
+    head
+}
+
+unsafe fn remember(cache: &mut Cache, key: i32) {
+    cache.last = new_node(key);
+}
+
+unsafe fn sum_pair(a: i32, b: i32) -> i32 {
+    let list = push_pair(ptr::null_mut(), a, b);
+    if !list.is_null() {
+        (*list).key + (*(*list).next).key
+    } else {
+        -1
+    }
+}
+
+unsafe fn describe(key: i32) -> i32 {
+    let n = push(first(ptr::null_mut()), key);
+    if n.is_null() {
+        0
+    } else {
+        (*n).key * 10
+    }
+}
+
+fn main() {
+    unsafe {
+        let mut cache = Cache {
+            last: ptr::null_mut(),
+        };
+        remember(&mut cache, 3);
+        assert_eq!((*cache.last).key, 3);
+        remember(&mut cache, -3);
+        assert!(cache.last.is_null());
+        let missing = new_node(-1).is_null();
+        let found = !new_node(1).is_null();
+        assert!(missing && found);
+        assert_eq!(sum_pair(1, 2), 3);
+        assert_eq!(sum_pair(1, -2), -1);
+        assert_eq!(describe(4), 40);
+        assert_eq!(describe(-4), 0);
+    }
+}
@@ -0,0 +1,47 @@
+#![feature(libc)]
+extern crate libc;
+
+unsafe fn divmod(a: libc::c_int, b: libc::c_int) -> (libc::c_int, libc::c_int, libc::c_int) {
+    let mut quot: libc::c_int;
+    let mut rem: libc::c_int;
+    if b == 0 {
+        quot = 0;
+        rem = 0;
+        return (-1, quot, rem);
+    }
+    quot = a / b;
+    rem = a % b;
+    (0, quot, rem)
+}
+
+unsafe fn parse_digit(c: u8, out: *mut libc::c_int) -> libc::c_int {
+    if c < b'0' || c > b'9' {
+        return -1;
+    }
+    *out = (c - b'0') as libc::c_int;
+    0
+}
+
+unsafe fn origin() -> (libc::c_int, libc::c_int) {
+    let mut x: libc::c_int;
+    let mut y: libc::c_int;
+    x = 0;
+    y = 0;
+    (x, y)
+}
+
+fn main() {
+    unsafe {
+        let (rc, q, r) = divmod(17, 5);
+        println!("{} {} {}", rc, q, r);
+
+        let mut d: libc::c_int = 0;
+        if parse_digit(b'7', &mut d) == 0 {
+            println!("{}", d);
+        }
+
+        let (mut x, y) = origin();
+        x += 1;
+        println!("{} {}", x, y);
+    }
+}
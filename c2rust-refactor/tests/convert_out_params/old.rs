@@ -0,0 +1,47 @@
+#![feature(libc)]
+extern crate libc;
+
+unsafe fn divmod(a: libc::c_int, b: libc::c_int, quot: *mut libc::c_int,
+                 rem: *mut libc::c_int) -> libc::c_int {
+    if b == 0 {
+        *quot = 0;
+        *rem = 0;
+        return -1;
+    }
+    *quot = a / b;
+    *rem = a % b;
+    0
+}
+
+unsafe fn parse_digit(c: u8, out: *mut libc::c_int) -> libc::c_int {
+    if c < b'0' || c > b'9' {
+        return -1;
+    }
+    *out = (c - b'0') as libc::c_int;
+    0
+}
+
+unsafe fn origin(x: *mut libc::c_int, y: *mut libc::c_int) {
+    *x = 0;
+    *y = 0;
+}
+
+fn main() {
+    unsafe {
+        let mut q: libc::c_int = 0;
+        let mut r: libc::c_int = 0;
+        let rc = divmod(17, 5, &mut q, &mut r);
+        println!("{} {} {}", rc, q, r);
+
+        let mut d: libc::c_int = 0;
+        if parse_digit(b'7', &mut d) == 0 {
+            println!("{}", d);
+        }
+
+        let mut x = 0;
+        let mut y = 0;
+        origin(&mut x, &mut y);
+        x += 1;
+        println!("{} {}", x, y);
+    }
+}
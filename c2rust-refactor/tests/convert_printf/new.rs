@@ -0,0 +1,49 @@
+#![feature(libc)]
+extern crate libc;
+
+extern "C" {
+    #[no_mangle]
+    fn printf(_: *const libc::c_char, ...) -> libc::c_int;
+    #[no_mangle]
+    fn fprintf(_: *mut libc::FILE, _: *const libc::c_char, ...) -> libc::c_int;
+    #[no_mangle]
+    static mut stderr: *mut libc::FILE;
+}
+
+unsafe fn report(name: *const libc::c_char, count: libc::c_uint, ratio: libc::c_double) {
+    println!(
+        "{:}: {:} items",
+        unsafe {
+            std::ffi::CStr::from_ptr(name as *const libc::c_char)
+                .to_str()
+                .unwrap()
+        },
+        count as libc::c_uint
+    );
+    println!(
+        "id {:} (0x{:x}), ratio {:}, 100%",
+        -1i32 as libc::c_int, 255u32 as libc::c_uint, ratio as f64
+    );
+    eprint!("{{{:}}}", unsafe {
+        std::ffi::CStr::from_ptr(name as *const libc::c_char)
+            .to_str()
+            .unwrap()
+    });
+
+    // Argument count mismatches are left alone
+    printf(b"%d %d\n\x00" as *const u8 as *const libc::c_char, 1i32);
+    printf(b"%d\n\x00" as *const u8 as *const libc::c_char, 1i32, 2i32);
+
+    // So are conversions with no exact Rust equivalent
+    printf(b"%*d\n\x00" as *const u8 as *const libc::c_char, 4i32, 1i32);
+    printf(b"%p\n\x00" as *const u8 as *const libc::c_char, name);
+
+    // And format strings that aren't literals
+    printf(name);
+}
+
+fn main() {
+    unsafe {
+        report(b"widgets\x00" as *const u8 as *const libc::c_char, 3, 0.5);
+    }
+}
@@ -0,0 +1,35 @@
+#![feature(libc)]
+extern crate libc;
+
+extern "C" {
+    #[no_mangle]
+    fn printf(_: *const libc::c_char, ...) -> libc::c_int;
+    #[no_mangle]
+    fn fprintf(_: *mut libc::FILE, _: *const libc::c_char, ...) -> libc::c_int;
+    #[no_mangle]
+    static mut stderr: *mut libc::FILE;
+}
+
+unsafe fn report(name: *const libc::c_char, count: libc::c_uint, ratio: libc::c_double) {
+    printf(b"%s: %u items\n\x00" as *const u8 as *const libc::c_char, name, count);
+    printf(b"id %d (0x%x), ratio %f, 100%%\n\x00" as *const u8 as *const libc::c_char,
+           -1i32, 255u32, ratio);
+    fprintf(stderr, b"{%s}\x00" as *const u8 as *const libc::c_char, name);
+
+    // Argument count mismatches are left alone
+    printf(b"%d %d\n\x00" as *const u8 as *const libc::c_char, 1i32);
+    printf(b"%d\n\x00" as *const u8 as *const libc::c_char, 1i32, 2i32);
+
+    // So are conversions with no exact Rust equivalent
+    printf(b"%*d\n\x00" as *const u8 as *const libc::c_char, 4i32, 1i32);
+    printf(b"%p\n\x00" as *const u8 as *const libc::c_char, name);
+
+    // And format strings that aren't literals
+    printf(name);
+}
+
+fn main() {
+    unsafe {
+        report(b"widgets\x00" as *const u8 as *const libc::c_char, 3, 0.5);
+    }
+}
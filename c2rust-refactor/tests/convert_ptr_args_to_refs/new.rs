@@ -0,0 +1,105 @@
+use std::ffi::c_void;
+
+extern "C" {
+    fn malloc(size: usize) -> *mut c_void;
+    fn free(ptr: *mut c_void);
+}
+
+pub mod point {
+    use std::ffi::c_void;
+
+    #[derive(Clone, Copy, Default)]
+    #[repr(C)]
+    pub struct Point {
+        pub x: i32,
+        pub y: i32,
+    }
+
+    pub unsafe fn point_sum(p: &Point) -> i32 {
+        (*p).x + (*p).y
+    }
+
+    pub unsafe fn point_scale(p: &mut Point, k: i32) {
+        (*p).x *= k;
+        (*p).y *= k;
+    }
+
+    pub unsafe fn point_copy(dst: &mut Point, src: &Point) {
+        *dst = *src;
+    }
+
+    pub unsafe fn point_norm1(p: *const Point) -> i32 {
+        if p.is_null() {
+            return 0;
+        }
+        (*p).x.abs() + (*p).y.abs()
+    }
+
+    pub unsafe fn point_release(p: *mut Point) {
+        super::free(p as *mut c_void);
+    }
+
+    pub unsafe fn point_reset(p: *mut Point) {
+        (*p).x = 0;
+        (*p).y = 0;
+    }
+}
+
+pub mod rect {
+    use super::point::{point_scale, point_sum, Point};
+
+    #[repr(C)]
+    pub struct Rect {
+        pub min: Point,
+        pub max: Point,
+    }
+
+    impl Rect {
+        pub unsafe fn contains(&self, p: &Point) -> bool {
+            let inside_x = self.min.x <= (*p).x && (*p).x < self.max.x;
+            inside_x && self.min.y <= (*p).y && (*p).y < self.max.y
+        }
+    }
+
+    pub unsafe fn rect_scale(r: &mut Rect, k: i32) {
+        point_scale(&mut (*r).min, k);
+        point_scale(&mut (*r).max, k);
+    }
+
+    pub unsafe fn rect_corner_sum(rects: *const Rect, i: isize) -> i32 {
+        point_sum(&(*rects.offset(i)).min)
+    }
+}
+
+use point::*;
+use rect::*;
+
+fn main() {
+    unsafe {
+        let mut p = Point { x: 1, y: 2 };
+        let raw: *mut Point = &mut p;
+        let mut q = Point::default();
+        point_copy(&mut q, &*raw);
+        point_scale(&mut *raw, 3);
+        let reset: unsafe fn(*mut Point) = point_reset;
+        reset(&mut q as *mut Point);
+
+        let mut r = Rect {
+            min: Point { x: 0, y: 0 },
+            max: Point { x: 4, y: 4 },
+        };
+        rect_scale(&mut r, 2);
+
+        let heap = malloc(std::mem::size_of::<Point>()) as *mut Point;
+        point_copy(&mut *heap, &p);
+        println!(
+            "{} {} {} {} {}",
+            point_sum(&p),
+            point_norm1(heap),
+            r.contains(&q),
+            rect_corner_sum(&r, 0),
+            point_sum(&*(raw as *const Point))
+        );
+        point_release(heap);
+    }
+}
@@ -0,0 +1,77 @@
+pub unsafe fn sum(data: &[i32]) -> i32 {
+    let mut total = 0;
+    let mut i = 0;
+    while i < data.len() as i32 {
+        total += data[i as usize];
+        i += 1;
+    }
+    total
+}
+
+pub unsafe fn fill(buf: &mut [i32], value: i32) {
+    let mut i = 0;
+    while i < buf.len() {
+        buf[i] = value;
+        i += 1;
+    }
+}
+
+pub unsafe fn largest(vals: &[u8]) -> u8 {
+    let mut best = vals[0];
+    let mut i = 1;
+    while i < vals.len() as u32 {
+        if vals[i as usize] > best {
+            best = vals[i as usize];
+        }
+        i += 1;
+    }
+    best
+}
+
+pub unsafe fn tail_sum(data: &[i32], off: i32) -> i32 {
+    if off >= data.len() as i32 {
+        return 0;
+    }
+    sum(&data[off as usize..])
+}
+
+pub unsafe fn reset(buf: &mut [i32]) {
+    fill(buf, 0);
+}
+
+pub unsafe fn find(data: *const i32, len: i32, x: i32) -> *const i32 {
+    let mut i = 0;
+    while i < len {
+        if *data.offset(i as isize) == x {
+            return data.offset(i as isize);
+        }
+        i += 1;
+    }
+    std::ptr::null()
+}
+
+fn main() {
+    let v = vec![1, 2, 3, 4, 5];
+    let mut arr = [0i32; 8];
+    let bytes = [3u8, 9, 4];
+    unsafe {
+        let total = sum(&v);
+        assert_eq!(total, 15);
+        let p = v.as_ptr();
+        let head = sum(::std::slice::from_raw_parts(p, 3));
+        assert_eq!(head, 6);
+        let rest = tail_sum(&v, 2);
+        assert_eq!(rest, 12);
+
+        fill(&mut arr[..4], 7);
+        reset(&mut arr[2..][..2]);
+        let filled = sum(&arr);
+        assert_eq!(filled, 14);
+
+        let best = largest(&bytes[..3]);
+        assert_eq!(best, 9);
+
+        let found = find(v.as_ptr(), v.len() as i32, 4);
+        assert_eq!(*found, 4);
+    }
+}
@@ -0,0 +1,77 @@
+pub unsafe fn sum(data: *const i32, len: i32) -> i32 {
+    let mut total = 0;
+    let mut i = 0;
+    while i < len {
+        total += *data.offset(i as isize);
+        i += 1;
+    }
+    total
+}
+
+pub unsafe fn fill(mut buf: *mut i32, n: usize, value: i32) {
+    let mut i = 0;
+    while i < n {
+        *buf.add(i) = value;
+        i += 1;
+    }
+}
+
+pub unsafe fn largest(vals: *mut u8, count: u32) -> u8 {
+    let mut best = *vals;
+    let mut i = 1;
+    while i < count {
+        if *vals.offset(i as isize) > best {
+            best = *vals.offset(i as isize);
+        }
+        i += 1;
+    }
+    best
+}
+
+pub unsafe fn tail_sum(data: *const i32, len: i32, off: i32) -> i32 {
+    if off >= len {
+        return 0;
+    }
+    sum(data.add(off as usize), len - off)
+}
+
+pub unsafe fn reset(buf: *mut i32, n: usize) {
+    fill(buf, n, 0);
+}
+
+pub unsafe fn find(data: *const i32, len: i32, x: i32) -> *const i32 {
+    let mut i = 0;
+    while i < len {
+        if *data.offset(i as isize) == x {
+            return data.offset(i as isize);
+        }
+        i += 1;
+    }
+    std::ptr::null()
+}
+
+fn main() {
+    let v = vec![1, 2, 3, 4, 5];
+    let mut arr = [0i32; 8];
+    let bytes = [3u8, 9, 4];
+    unsafe {
+        let total = sum(v.as_ptr(), v.len() as i32);
+        assert_eq!(total, 15);
+        let p = v.as_ptr();
+        let head = sum(p, 3);
+        assert_eq!(head, 6);
+        let rest = tail_sum(v.as_ptr(), v.len() as i32, 2);
+        assert_eq!(rest, 12);
+
+        fill(arr.as_mut_ptr(), 4, 7);
+        reset(arr.as_mut_ptr().add(2), 2);
+        let filled = sum(arr.as_ptr(), arr.len() as i32);
+        assert_eq!(filled, 14);
+
+        let best = largest(bytes.as_ptr() as *mut u8, 3);
+        assert_eq!(best, 9);
+
+        let found = find(v.as_ptr(), v.len() as i32, 4);
+        assert_eq!(*found, 4);
+    }
+}
@@ -0,0 +1,28 @@
+unsafe fn scale(src: &[f64], dst: &mut [f64], n: i32) {
+    let mut i: i32 = 0;
+    while i < n {
+        dst[i as usize] = src[i as usize] * 2.0;
+        i += 1;
+    }
+}
+
+unsafe fn histogram(data: &[u8], len: usize) -> Vec<u32> {
+    let mut counts: Vec<u32> = vec![0; 256];
+    let mut i: usize = 0;
+    while i < len {
+        counts[data[i] as usize] += 1;
+        i += 1;
+    }
+    counts[0] = 0;
+    counts
+}
+
+unsafe fn prev(buf: &[i32], i: isize) -> i32 {
+    // Negative offsets are left alone.
+    *buf.as_ptr().offset(i).offset(-1) + *buf.as_ptr().offset(-1 as isize)
+}
+
+unsafe fn bytes(buf: &[i32]) -> u8 {
+    // So are accesses through a pointer of a different type.
+    *(buf.as_ptr() as *const u8).offset(1)
+}
@@ -0,0 +1,33 @@
+#![feature(libc)]
+extern crate libc;
+
+extern "C" {
+    fn register_counter(counter: *mut libc::c_int);
+}
+
+static HITS: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(0);
+static FLAGS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+static DONE: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+// Not converted: its address is passed to C.
+static mut EXTERNAL: libc::c_int = 0;
+
+fn record(n: libc::c_int, flag: libc::c_uint) -> libc::c_int {
+    {
+        HITS.fetch_add(n, std::sync::atomic::Ordering::Relaxed);
+        HITS.fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+        FLAGS.fetch_or(flag, std::sync::atomic::Ordering::Relaxed);
+        FLAGS.fetch_and(!4, std::sync::atomic::Ordering::Relaxed);
+        FLAGS.fetch_xor(1, std::sync::atomic::Ordering::Relaxed);
+    }
+    if HITS.load(std::sync::atomic::Ordering::Relaxed) > 100 {
+        DONE.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+    HITS.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+unsafe fn reset() {
+    HITS.store(0, std::sync::atomic::Ordering::Relaxed);
+    FLAGS.store(0, std::sync::atomic::Ordering::Relaxed);
+    EXTERNAL += 1;
+    register_counter(&mut EXTERNAL);
+}
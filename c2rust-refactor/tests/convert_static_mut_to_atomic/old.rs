@@ -0,0 +1,33 @@
+#![feature(libc)]
+extern crate libc;
+
+extern "C" {
+    fn register_counter(counter: *mut libc::c_int);
+}
+
+static mut HITS: libc::c_int = 0;
+static mut FLAGS: libc::c_uint = 0;
+static mut DONE: bool = false;
+// Not converted: its address is passed to C.
+static mut EXTERNAL: libc::c_int = 0;
+
+fn record(n: libc::c_int, flag: libc::c_uint) -> libc::c_int {
+    unsafe {
+        HITS += n;
+        HITS -= 1;
+        FLAGS |= flag;
+        FLAGS &= !4;
+        FLAGS ^= 1;
+    }
+    if unsafe { HITS } > 100 {
+        unsafe { DONE = true };
+    }
+    unsafe { HITS }
+}
+
+unsafe fn reset() {
+    HITS = 0;
+    FLAGS = 0;
+    EXTERNAL += 1;
+    register_counter(&mut EXTERNAL);
+}
@@ -0,0 +1,46 @@
+#![feature(libc)]
+extern crate libc;
+
+use libc::{c_char, c_int, c_ulong};
+use std::ffi::CStr;
+
+extern "C" {
+    fn strcmp(a: *const c_char, b: *const c_char) -> c_int;
+    fn strncmp(a: *const c_char, b: *const c_char, n: c_ulong) -> c_int;
+    fn strchr(s: *const c_char, c: c_int) -> *mut c_char;
+    fn strlen(s: *const c_char) -> c_ulong;
+}
+
+unsafe fn is_help(arg: &str) -> bool {
+    arg == "--help"
+}
+
+unsafe fn before(a: &str, b: &CStr) -> bool {
+    a.as_bytes() < b.to_bytes()
+}
+
+unsafe fn same(a: &CStr, b: &CStr) -> bool {
+    b != a
+}
+
+unsafe fn is_option(arg: &str) -> bool {
+    arg.as_bytes().iter().take(2).eq("--".as_bytes().iter().take(2))
+}
+
+unsafe fn has_slash(path: &str) -> bool {
+    path.find('/').is_some()
+}
+
+unsafe fn lacks(buf: &[u8], c: c_int) -> bool {
+    buf.iter().position(|&b| b == c as u8).is_none()
+}
+
+unsafe fn lengths(a: &str, b: &CStr) -> c_ulong {
+    a.len() as u64 + b.to_bytes().len() as u64
+}
+
+unsafe fn raw(p: *const c_char) -> bool {
+    strcmp(p, b"x\x00" as *const u8 as *const c_char) == 0 && strlen(p) > 1
+}
+
+fn main() {}
@@ -0,0 +1,48 @@
+#![feature(libc)]
+extern crate libc;
+
+use libc::{c_char, c_int, c_ulong};
+use std::ffi::CStr;
+
+extern "C" {
+    fn strcmp(a: *const c_char, b: *const c_char) -> c_int;
+    fn strncmp(a: *const c_char, b: *const c_char, n: c_ulong) -> c_int;
+    fn strchr(s: *const c_char, c: c_int) -> *mut c_char;
+    fn strlen(s: *const c_char) -> c_ulong;
+}
+
+unsafe fn is_help(arg: &str) -> bool {
+    strcmp(std::ffi::CString::new(arg).unwrap().as_ptr(),
+           b"--help\x00" as *const u8 as *const c_char) == 0
+}
+
+unsafe fn before(a: &str, b: &CStr) -> bool {
+    strcmp(std::ffi::CString::new(a).unwrap().as_ptr(), b.as_ptr()) < 0
+}
+
+unsafe fn same(a: &CStr, b: &CStr) -> bool {
+    0 != strcmp(a.as_ptr(), b.as_ptr())
+}
+
+unsafe fn is_option(arg: &str) -> bool {
+    strncmp(std::ffi::CString::new(arg).unwrap().as_ptr(),
+            b"--\x00" as *const u8 as *const c_char, 2 as c_ulong) == 0
+}
+
+unsafe fn has_slash(path: &str) -> bool {
+    !strchr(std::ffi::CString::new(path).unwrap().as_ptr(), '/' as i32).is_null()
+}
+
+unsafe fn lacks(buf: &[u8], c: c_int) -> bool {
+    strchr(buf.as_ptr() as *const c_char, c).is_null()
+}
+
+unsafe fn lengths(a: &str, b: &CStr) -> c_ulong {
+    strlen(std::ffi::CString::new(a).unwrap().as_ptr()) + strlen(b.as_ptr())
+}
+
+unsafe fn raw(p: *const c_char) -> bool {
+    strcmp(p, b"x\x00" as *const u8 as *const c_char) == 0 && strlen(p) > 1
+}
+
+fn main() {}
@@ -0,0 +1,122 @@
+#![feature(libc)]
+extern crate libc;
+
+pub const SHAPE_CIRCLE: libc::c_int = 0;
+pub const SHAPE_RECT: libc::c_int = 1;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Circle {
+    pub r: libc::c_double,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Rect {
+    pub w: libc::c_double,
+    pub h: libc::c_double,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub union C2RustUnnamed {
+    pub circle: Circle,
+    pub rect: Rect,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Shape {
+    pub u: ShapeU,
+}
+#[derive(Copy, Clone)]
+pub enum ShapeU {
+    Circle(Circle),
+    Rect(Rect),
+}
+impl ShapeU {
+    pub fn tag(&self) -> libc::c_int {
+        match *self {
+            ShapeU::Circle(_) => 0,
+            ShapeU::Rect(_) => 1,
+        }
+    }
+
+    pub fn set_tag(&mut self, tag: libc::c_int) {
+        if self.tag() == tag {
+            return;
+        }
+        *self = match tag {
+            0 => ShapeU::Circle(unsafe { ::std::mem::zeroed() }),
+            1 => ShapeU::Rect(unsafe { ::std::mem::zeroed() }),
+            _ => panic!("invalid tag for ShapeU: {}", tag),
+        };
+    }
+
+    pub fn as_circle(&self) -> &Circle {
+        match *self {
+            ShapeU::Circle(ref x) => x,
+            _ => panic!("ShapeU is not Circle"),
+        }
+    }
+
+    pub fn as_circle_mut(&mut self) -> &mut Circle {
+        match *self {
+            ShapeU::Circle(ref mut x) => x,
+            _ => panic!("ShapeU is not Circle"),
+        }
+    }
+
+    pub fn as_rect(&self) -> &Rect {
+        match *self {
+            ShapeU::Rect(ref x) => x,
+            _ => panic!("ShapeU is not Rect"),
+        }
+    }
+
+    pub fn as_rect_mut(&mut self) -> &mut Rect {
+        match *self {
+            ShapeU::Rect(ref mut x) => x,
+            _ => panic!("ShapeU is not Rect"),
+        }
+    }
+}
+
+pub unsafe fn shape_circle(s: *mut Shape, r: libc::c_double) {
+    (*s).u.set_tag(SHAPE_CIRCLE);
+    (*s).u.as_circle_mut().r = r;
+}
+
+pub fn make_rect(w: libc::c_double, h: libc::c_double) -> Shape {
+    Shape { u: ShapeU::Rect(Rect { w: w, h: h }) }
+}
+
+pub unsafe fn area(s: *const Shape) -> libc::c_double {
+    match (*s).u {
+        ShapeU::Circle(ref circle) => 3.14159 * circle.r * circle.r,
+        ShapeU::Rect(ref rect) => rect.w * rect.h,
+    }
+}
+
+pub unsafe fn scale(s: *mut Shape, f: libc::c_double) {
+    if let ShapeU::Circle(ref mut circle) = (*s).u {
+        circle.r *= f
+    } else if let ShapeU::Rect(ref mut rect) = (*s).u {
+        rect.w *= f;
+        rect.h *= f
+    };
+}
+
+pub unsafe fn width(s: *const Shape) -> libc::c_double {
+    if (*s).u.tag() == SHAPE_RECT && (*s).u.as_rect().w > 0.0 {
+        return (*s).u.as_rect().w;
+    }
+    return (*s).u.as_circle().r * 2.0;
+}
+
+pub unsafe fn radius(s: *const Shape) -> libc::c_double {
+    if (*s).u.tag() == SHAPE_RECT {
+        return (*s).u.as_circle().r;
+    }
+    return 0.0;
+}
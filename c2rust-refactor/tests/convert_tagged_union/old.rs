@@ -0,0 +1,72 @@
+#![feature(libc)]
+extern crate libc;
+
+pub const SHAPE_CIRCLE: libc::c_int = 0;
+pub const SHAPE_RECT: libc::c_int = 1;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Circle {
+    pub r: libc::c_double,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Rect {
+    pub w: libc::c_double,
+    pub h: libc::c_double,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub union C2RustUnnamed {
+    pub circle: Circle,
+    pub rect: Rect,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Shape {
+    pub kind: libc::c_int,
+    pub u: C2RustUnnamed,
+}
+
+pub unsafe fn shape_circle(s: *mut Shape, r: libc::c_double) {
+    (*s).kind = SHAPE_CIRCLE;
+    (*s).u.circle.r = r;
+}
+
+pub fn make_rect(w: libc::c_double, h: libc::c_double) -> Shape {
+    Shape { kind: SHAPE_RECT, u: C2RustUnnamed { rect: Rect { w: w, h: h } } }
+}
+
+pub unsafe fn area(s: *const Shape) -> libc::c_double {
+    match (*s).kind {
+        0 => 3.14159 * (*s).u.circle.r * (*s).u.circle.r,
+        1 => (*s).u.rect.w * (*s).u.rect.h,
+        _ => 0.0,
+    }
+}
+
+pub unsafe fn scale(s: *mut Shape, f: libc::c_double) {
+    if (*s).kind == SHAPE_CIRCLE {
+        (*s).u.circle.r *= f
+    } else if (*s).kind == SHAPE_RECT {
+        (*s).u.rect.w *= f;
+        (*s).u.rect.h *= f
+    };
+}
+
+pub unsafe fn width(s: *const Shape) -> libc::c_double {
+    if (*s).kind == SHAPE_RECT && (*s).u.rect.w > 0.0 {
+        return (*s).u.rect.w;
+    }
+    return (*s).u.circle.r * 2.0;
+}
+
+pub unsafe fn radius(s: *const Shape) -> libc::c_double {
+    if (*s).kind == SHAPE_RECT {
+        return (*s).u.circle.r;
+    }
+    return 0.0;
+}
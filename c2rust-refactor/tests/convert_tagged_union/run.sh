@@ -0,0 +1,10 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    convert_tagged_union Shape kind u SHAPE_CIRCLE=circle SHAPE_RECT=rect \
+    -- old.rs $rustflags
@@ -0,0 +1,66 @@
+#![feature(libc)]
+extern crate libc;
+
+extern "C" {
+    #[no_mangle]
+    fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
+    #[no_mangle]
+    fn free(__ptr: *mut libc::c_void);
+}
+
+#[derive(Clone)]
+#[repr(C)]
+pub struct Buffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        unsafe {
+            buffer_destroy(self);
+        }
+    }
+}
+
+pub unsafe fn buffer_init(b: *mut Buffer, len: usize) -> libc::c_int {
+    (*b).data = malloc(len as libc::c_ulong) as *mut u8;
+    (*b).len = len;
+    if (*b).data.is_null() { -1 } else { 0 }
+}
+
+pub unsafe fn buffer_destroy(b: *mut Buffer) {
+    free((*b).data as *mut libc::c_void);
+    (*b).data = 0 as *mut u8;
+    (*b).len = 0;
+}
+
+pub unsafe fn checksum(len: usize, skip_empty: bool) -> libc::c_int {
+    let mut buf: Buffer = Buffer { data: 0 as *mut u8, len: 0 };
+    if buffer_init(&mut buf, len) != 0 {
+        return -1;
+    }
+    if skip_empty && len == 0 {
+        return 0;
+    }
+    let mut sum: libc::c_int = 0;
+    let mut i: usize = 0;
+    while i < buf.len {
+        sum += *buf.data.add(i) as libc::c_int;
+        i += 1;
+    }
+    sum
+}
+
+pub unsafe fn regrow(len: usize) {
+    let mut buf: Buffer = Buffer { data: 0 as *mut u8, len: 0 };
+    buffer_init(&mut buf, len);
+    buffer_destroy(&mut buf);
+    buffer_init(&mut buf, len * 2);
+}
+
+pub unsafe fn buffer_free(b: *mut Buffer) {
+    buffer_destroy(b);
+    free(b as *mut libc::c_void);
+}
+
+fn main() {}
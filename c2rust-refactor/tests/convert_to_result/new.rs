@@ -0,0 +1,56 @@
+#![feature(libc)]
+extern crate libc;
+
+pub const EINVAL: libc::c_int = 22;
+pub const ENOMEM: libc::c_int = 12;
+
+static mut POOL: libc::c_int = 64;
+
+fn check_len(len: libc::c_int) -> Result<(), libc::c_int> {
+    if len < 0 {
+        return Err(-1);
+    }
+    if len > 100 {
+        return Err(EINVAL);
+    }
+    Ok(())
+}
+
+unsafe fn reserve(len: libc::c_int) -> Result<(), libc::c_int> {
+    check_len(len)?;
+    if POOL < len {
+        return Err(ENOMEM);
+    }
+    POOL -= len;
+    Ok(())
+}
+
+// Not converted: it returns a value that isn't a constant.
+fn remaining() -> libc::c_int {
+    unsafe { POOL }
+}
+
+pub unsafe fn buf_alloc(len: libc::c_int) -> Result<(), libc::c_int> {
+    if reserve(len).is_err() {
+        return Err(-1);
+    }
+    Ok(())
+}
+#[export_name = "buf_alloc"]
+pub unsafe extern "C" fn buf_alloc_wrapper(len: libc::c_int) -> libc::c_int {
+    buf_alloc(len).err().unwrap_or(0)
+}
+
+fn status(len: libc::c_int) -> libc::c_int {
+    check_len(len).err().unwrap_or(0)
+}
+
+fn main() {
+    unsafe {
+        let _ = buf_alloc(3);
+        if check_len(5).is_ok() {
+            println!("ok");
+        }
+        println!("{} {}", status(200), remaining());
+    }
+}
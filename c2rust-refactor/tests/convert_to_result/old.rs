@@ -0,0 +1,56 @@
+#![feature(libc)]
+extern crate libc;
+
+pub const EINVAL: libc::c_int = 22;
+pub const ENOMEM: libc::c_int = 12;
+
+static mut POOL: libc::c_int = 64;
+
+fn check_len(len: libc::c_int) -> libc::c_int {
+    if len < 0 {
+        return -1;
+    }
+    if len > 100 {
+        return EINVAL;
+    }
+    0
+}
+
+unsafe fn reserve(len: libc::c_int) -> libc::c_int {
+    let rc = check_len(len);
+    if rc != 0 {
+        return rc;
+    }
+    if POOL < len {
+        return ENOMEM;
+    }
+    POOL -= len;
+    0
+}
+
+// Not converted: it returns a value that isn't a constant.
+fn remaining() -> libc::c_int {
+    unsafe { POOL }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn buf_alloc(len: libc::c_int) -> libc::c_int {
+    if reserve(len) != 0 {
+        return -1;
+    }
+    0
+}
+
+fn status(len: libc::c_int) -> libc::c_int {
+    check_len(len)
+}
+
+fn main() {
+    unsafe {
+        buf_alloc(3);
+        if check_len(5) == 0 {
+            println!("ok");
+        }
+        println!("{} {}", status(200), remaining());
+    }
+}
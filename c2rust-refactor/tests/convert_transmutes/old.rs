@@ -0,0 +1,71 @@
+use std::mem;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Padded {
+    pub tag: u8,
+    pub value: u32,
+}
+
+unsafe fn float_bits(x: f32) -> u32 {
+    mem::transmute::<f32, u32>(x)
+}
+
+unsafe fn signed_double_bits(x: f64) -> i64 {
+    mem::transmute::<f64, i64>(x)
+}
+
+unsafe fn float_from_bits(bits: i32) -> f32 {
+    mem::transmute::<i32, f32>(bits)
+}
+
+unsafe fn one_bits() -> u32 {
+    mem::transmute::<f32, u32>(1.0)
+}
+
+unsafe fn bytes_to_int(bytes: [u8; 4]) -> u32 {
+    mem::transmute::<[u8; 4], u32>(bytes)
+}
+
+unsafe fn int_to_bytes(x: u64) -> [u8; 8] {
+    mem::transmute::<u64, [u8; 8]>(x)
+}
+
+unsafe fn float_to_bytes(x: f32) -> [u8; 4] {
+    mem::transmute::<f32, [u8; 4]>(x)
+}
+
+unsafe fn flip_sign(x: u16) -> i16 {
+    mem::transmute::<u16, i16>(x)
+}
+
+unsafe fn pun(x: f32) -> u32 {
+    *(&x as *const f32 as *const u32)
+}
+
+unsafe fn pun_mut(mut x: f64) -> u64 {
+    let bits = *(&mut x as *mut f64 as *mut u64);
+    // Not converted: writes through the pun
+    *(&mut x as *mut f64 as *mut u64) = bits | 1;
+    bits
+}
+
+// Not converted: `Padded` has padding between its fields
+unsafe fn padded_bits(p: Padded) -> u64 {
+    mem::transmute::<Padded, u64>(p)
+}
+
+// Not converted: reads past the end of `x`
+unsafe fn short_pun(x: u16) -> u32 {
+    *(&x as *const u16 as *const u32)
+}
+
+fn main() {
+    unsafe {
+        println!("{} {} {}", float_bits(1.5), signed_double_bits(-2.0), float_from_bits(7));
+        println!("{} {} {:?}", one_bits(), bytes_to_int([1, 2, 3, 4]), int_to_bytes(5));
+        println!("{:?} {} {}", float_to_bytes(0.5), flip_sign(65535), pun(3.0));
+        println!("{}", pun_mut(4.0));
+        let _ = (padded_bits, short_pun);
+    }
+}
@@ -0,0 +1,57 @@
+pub mod externs {
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub struct pair {
+        pub first: i32,
+        pub second: i32,
+    }
+    extern "C" {
+        pub fn malloc(size: u64) -> *mut u8;
+        pub fn free(ptr: *mut u8);
+        pub fn swap_pair(p: *mut crate::externs::pair);
+    }
+}
+
+pub mod a {
+    extern "C" {
+        fn abs(x: i32) -> i32;
+    }
+
+    pub unsafe fn make(n: u64) -> *mut u8 {
+        crate::externs::malloc(n)
+    }
+
+    pub unsafe fn use_pair(p: *mut crate::externs::pair) -> i32 {
+        crate::externs::swap_pair(p);
+        let first = (*p).first;
+        crate::externs::free(p as *mut u8);
+        abs(first)
+    }
+}
+
+pub mod b {
+    pub unsafe fn new_pair() -> *mut crate::externs::pair {
+        let p = crate::externs::malloc(8) as *mut crate::externs::pair;
+        (*p).first = 1;
+        (*p).second = 2;
+        crate::externs::swap_pair(p);
+        p
+    }
+
+    pub unsafe fn drop_pair(p: *mut crate::externs::pair) {
+        crate::externs::free(p as *mut u8);
+    }
+}
+
+pub mod c {
+    extern "C" {
+        fn abs(x: i32);
+    }
+
+    pub unsafe fn alloc(n: u32) -> *mut u8 {
+        abs(n as i32);
+        crate::externs::malloc(n as u64)
+    }
+}
+
+fn main() {}
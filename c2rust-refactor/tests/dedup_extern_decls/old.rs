@@ -0,0 +1,72 @@
+pub mod externs {}
+
+pub mod a {
+    extern "C" {
+        fn malloc(size: u64) -> *mut u8;
+        fn free(ptr: *mut u8);
+        fn abs(x: i32) -> i32;
+    }
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub struct pair {
+        pub first: i32,
+        pub second: i32,
+    }
+
+    extern "C" {
+        fn swap_pair(p: *mut pair);
+    }
+
+    pub unsafe fn make(n: u64) -> *mut u8 {
+        malloc(n)
+    }
+
+    pub unsafe fn use_pair(p: *mut pair) -> i32 {
+        swap_pair(p);
+        let first = (*p).first;
+        free(p as *mut u8);
+        abs(first)
+    }
+}
+
+pub mod b {
+    extern "C" {
+        fn malloc(size: u64) -> *mut u8;
+        fn free(ptr: *mut u8);
+        fn swap_pair(p: *mut pair);
+    }
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub struct pair {
+        pub first: i32,
+        pub second: i32,
+    }
+
+    pub unsafe fn new_pair() -> *mut pair {
+        let p = malloc(8) as *mut pair;
+        (*p).first = 1;
+        (*p).second = 2;
+        swap_pair(p);
+        p
+    }
+
+    pub unsafe fn drop_pair(p: *mut pair) {
+        free(p as *mut u8);
+    }
+}
+
+pub mod c {
+    extern "C" {
+        fn malloc(size: u32) -> *mut u8;
+        fn abs(x: i32);
+    }
+
+    pub unsafe fn alloc(n: u32) -> *mut u8 {
+        abs(n as i32);
+        malloc(n)
+    }
+}
+
+fn main() {}
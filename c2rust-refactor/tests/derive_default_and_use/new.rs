@@ -0,0 +1,51 @@
+use std::mem;
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct point {
+    pub x: i32,
+    pub y: f64,
+}
+
+#[repr(C)]
+pub struct buffer {
+    pub data: *mut u8,
+    pub len: usize,
+    pub cache: [u8; 64],
+    pub origin: point,
+}
+impl Default for buffer {
+    fn default() -> Self {
+        buffer {
+            data: ::std::ptr::null_mut(),
+            len: Default::default(),
+            cache: [Default::default(); 64],
+            origin: Default::default(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub struct handler {
+    pub cb: Option<unsafe extern "C" fn(i32) -> i32>,
+    pub id: Option<i32>,
+}
+
+#[repr(C)]
+pub struct view {
+    pub target: &'static mut i32,
+}
+
+fn main() {
+    let p = point::default();
+    let q = point { x: 1, y: 0.0 };
+    let b = buffer::default();
+    let z: buffer = unsafe { Default::default() };
+    let pz = unsafe { point::default() };
+    let h = unsafe { mem::zeroed::<handler>() };
+    let h2 = handler::default();
+    assert_eq!(p.x + q.x + b.origin.x + z.origin.x + pz.x, 1);
+    assert!(b.data.is_null() && z.len == 0 && b.cache[63] == 0);
+    assert!(h.cb.is_none() && h2.id.is_none());
+}
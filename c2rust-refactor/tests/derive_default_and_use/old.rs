@@ -0,0 +1,45 @@
+use std::mem;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct point {
+    pub x: i32,
+    pub y: f64,
+}
+
+#[repr(C)]
+pub struct buffer {
+    pub data: *mut u8,
+    pub len: usize,
+    pub cache: [u8; 64],
+    pub origin: point,
+}
+
+#[repr(C)]
+pub struct handler {
+    pub cb: Option<unsafe extern "C" fn(i32) -> i32>,
+    pub id: Option<i32>,
+}
+
+#[repr(C)]
+pub struct view {
+    pub target: &'static mut i32,
+}
+
+fn main() {
+    let p = point { x: 0, y: 0.0 };
+    let q = point { x: 1, y: 0.0 };
+    let b = buffer {
+        data: 0 as *mut u8,
+        len: 0,
+        cache: [0; 64],
+        origin: point { x: 0, y: 0. },
+    };
+    let z: buffer = unsafe { mem::zeroed() };
+    let pz = unsafe { mem::zeroed::<point>() };
+    let h = unsafe { mem::zeroed::<handler>() };
+    let h2 = handler { cb: None, id: None };
+    assert_eq!(p.x + q.x + b.origin.x + z.origin.x + pz.x, 1);
+    assert!(b.data.is_null() && z.len == 0 && b.cache[63] == 0);
+    assert!(h.cb.is_none() && h2.id.is_none());
+}
@@ -0,0 +1,12 @@
+mod other;
+
+use crate::other::Point;
+
+fn norm(p: &Point) -> i32 {
+    p.horiz * p.horiz + p.y * p.y
+}
+
+fn main() {
+    let p = Point { horiz: 3, y: 4 };
+    println!("{}", norm(&p));
+}
@@ -0,0 +1,12 @@
+mod other;
+
+use crate::other::Point;
+
+fn norm(p: &Point) -> i32 {
+    p.x * p.x + p.y * p.y
+}
+
+fn main() {
+    let p = Point { x: 3, y: 4 };
+    println!("{}", norm(&p));
+}
@@ -0,0 +1,31 @@
+#!/bin/sh
+set -e
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+rm -f old.new other.new dry_run.diff
+before=`cksum old.rs other.rs`
+
+# A dry run prints one diff of all the changed files, leaves the files
+# alone, and exits with status 2 since there were changes.
+status=0
+$refactor_bin --dry-run --diff-out dry_run.diff \
+    rename_fields other::Point x horiz \
+    -- old.rs $rustflags || status=$?
+if [ $status -ne 2 ]; then
+    echo "expected exit status 2 from --dry-run, got $status"
+    exit 1
+fi
+if [ "`cksum old.rs other.rs`" != "$before" ] || [ -e old.new ] || [ -e other.new ]; then
+    echo "--dry-run wrote to disk"
+    exit 1
+fi
+diff -u expected.diff dry_run.diff
+
+# The real run makes the same changes.
+$refactor \
+    rename_fields other::Point x horiz \
+    -- old.rs $rustflags
@@ -0,0 +1,45 @@
+pub unsafe fn checksum(buf: *const u8, len: usize, out: *mut u32) -> i32 {
+    let mut count: i32 = 0;
+    let folded = match checksum_loop(buf, len, &mut count) {
+        ControlFlow::Continue(v) => v,
+        ControlFlow::Break(v) => return v,
+    };
+    *out = !folded;
+    count
+}
+unsafe fn checksum_loop(buf: *const u8, len: usize, count: &mut i32) -> ControlFlow<i32, u32> {
+    let mut sum: u32 = 0;
+    let mut i: usize = 0;
+    while i < len {
+        let b: u8 = *buf.offset(i as isize);
+        if b == 0xff {
+            return ControlFlow::Break(-1);
+        }
+        sum = sum.wrapping_add(b as u32);
+        *count += 1;
+        i += 1;
+    }
+    let folded: u32 = (sum & 0xffff) + (sum >> 16);
+    ControlFlow::Continue(folded)
+}
+
+pub fn report(name: String, scores: &mut Vec<i32>) -> usize {
+    let mut total: i32 = 0;
+    let label = sum_scores(&name, scores, &mut total);
+    println!("{}: {}", label, total);
+    label.len()
+}
+fn sum_scores(name: &String, scores: &mut Vec<i32>, total: &mut i32) -> String {
+    let label: String = (*name).clone();
+    scores.sort();
+    for &s in scores.iter() {
+        *total += s;
+    }
+    label
+}
+
+fn main() {}
+pub enum ControlFlow<B, C> {
+    Continue(C),
+    Break(B),
+}
@@ -0,0 +1,30 @@
+pub unsafe fn checksum(buf: *const u8, len: usize, out: *mut u32) -> i32 {
+    let mut count: i32 = 0;
+    let mut sum: u32 = 0;
+    let mut i: usize = 0;
+    while i < len {
+        let b: u8 = *buf.offset(i as isize);
+        if b == 0xff {
+            return -1;
+        }
+        sum = sum.wrapping_add(b as u32);
+        count += 1;
+        i += 1;
+    }
+    let folded: u32 = (sum & 0xffff) + (sum >> 16);
+    *out = !folded;
+    count
+}
+
+pub fn report(name: String, scores: &mut Vec<i32>) -> usize {
+    let mut total: i32 = 0;
+    let label: String = name.clone();
+    scores.sort();
+    for &s in scores.iter() {
+        total += s;
+    }
+    println!("{}: {}", label, total);
+    label.len()
+}
+
+fn main() {}
@@ -0,0 +1,16 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'item(checksum); desc(match_stmt(let mut sum: u32 = 0;) ||
+                                        match_stmt(let folded: u32 = (sum & 0xffff) + (sum >> 16);));' \; \
+    extract_fn checksum_loop \; \
+    clear_marks \; \
+    select target 'item(report); desc(match_stmt(let label: String = name.clone();) ||
+                                      match_stmt(for &s in scores.iter() { total += s; }));' \; \
+    extract_fn sum_scores \
+    -- old.rs $rustflags
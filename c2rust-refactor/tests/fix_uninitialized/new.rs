@@ -0,0 +1,83 @@
+use std::mem;
+
+#[derive(Clone, Copy)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+    pub scale: Option<unsafe fn(i32) -> i32>,
+}
+
+unsafe fn double(v: i32) -> i32 {
+    v * 2
+}
+
+pub unsafe fn make_point(x: i32, y: i32) -> Point {
+    let mut p = ::std::mem::MaybeUninit::<Point>::uninit();
+    (*p.as_mut_ptr()).x = x;
+    (*p.as_mut_ptr()).y = y;
+    (*p.as_mut_ptr()).scale = Some(double);
+    let p = unsafe { p.assume_init() };
+    p
+}
+
+pub unsafe fn squares() -> [i32; 8] {
+    let mut buf = ::std::mem::MaybeUninit::<[i32; 8]>::uninit();
+    let mut i: i32 = 0;
+    while i < 8 {
+        (*buf.as_mut_ptr())[i as usize] = i * i;
+        i += 1;
+    }
+    let buf = unsafe { buf.assume_init() };
+    buf
+}
+
+pub unsafe fn ramp(start: u8) -> [u8; 4] {
+    let mut out = ::std::mem::MaybeUninit::<[u8; 4]>::uninit();
+    for k in 0..4 {
+        (*out.as_mut_ptr())[k] = start + k as u8;
+    }
+    let out = unsafe { out.assume_init() };
+    out
+}
+
+pub unsafe fn pick(flag: bool) -> i64 {
+    let mut v = ::std::mem::MaybeUninit::<i64>::uninit();
+    v.write(if flag { 10 } else { 20 });
+    let mut v = unsafe { v.assume_init() };
+    v += 1;
+    v
+}
+
+pub unsafe fn maybe(flag: bool) -> i32 {
+    let mut r: i32 = ::std::mem::zeroed();
+    if flag {
+        r = 1;
+    }
+    r
+}
+
+pub unsafe fn label(flag: bool) -> &'static str {
+    let mut s: &'static str = mem::uninitialized();
+    if flag {
+        s = "yes";
+    } else {
+        s = "no";
+    }
+    s
+}
+
+fn main() {
+    unsafe {
+        let p = make_point(1, 2);
+        assert_eq!((p.x, p.y), (1, 2));
+        assert_eq!(p.scale.unwrap()(4), 8);
+        let sq = squares();
+        assert_eq!(sq[7], 49);
+        let r = ramp(5);
+        assert_eq!(r, [5, 6, 7, 8]);
+        let v = pick(true);
+        assert_eq!(v, 11);
+        let m = maybe(true);
+        assert_eq!(m, 1);
+    }
+}
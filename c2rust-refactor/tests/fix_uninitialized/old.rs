@@ -0,0 +1,79 @@
+use std::mem;
+
+#[derive(Clone, Copy)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+    pub scale: Option<unsafe fn(i32) -> i32>,
+}
+
+unsafe fn double(v: i32) -> i32 {
+    v * 2
+}
+
+pub unsafe fn make_point(x: i32, y: i32) -> Point {
+    let mut p: Point = mem::uninitialized();
+    p.x = x;
+    p.y = y;
+    p.scale = Some(double);
+    p
+}
+
+pub unsafe fn squares() -> [i32; 8] {
+    let mut buf: [i32; 8] = mem::uninitialized();
+    let mut i: i32 = 0;
+    while i < 8 {
+        buf[i as usize] = i * i;
+        i += 1;
+    }
+    buf
+}
+
+pub unsafe fn ramp(start: u8) -> [u8; 4] {
+    let mut out: [u8; 4] = mem::uninitialized();
+    for k in 0..4 {
+        out[k] = start + k as u8;
+    }
+    out
+}
+
+pub unsafe fn pick(flag: bool) -> i64 {
+    let mut v: i64 = mem::uninitialized();
+    v = if flag { 10 } else { 20 };
+    v += 1;
+    v
+}
+
+pub unsafe fn maybe(flag: bool) -> i32 {
+    let mut r: i32 = mem::uninitialized();
+    if flag {
+        r = 1;
+    }
+    r
+}
+
+pub unsafe fn label(flag: bool) -> &'static str {
+    let mut s: &'static str = mem::uninitialized();
+    if flag {
+        s = "yes";
+    } else {
+        s = "no";
+    }
+    s
+}
+
+fn main() {
+    unsafe {
+        let p = make_point(1, 2);
+        assert_eq!((p.x, p.y), (1, 2));
+        assert_eq!(p.scale.unwrap()(4), 8);
+        let sq = squares();
+        assert_eq!(sq[7], 49);
+        let r = ramp(5);
+        assert_eq!(r, [5, 6, 7, 8]);
+        let v = pick(true);
+        assert_eq!(v, 11);
+        let m = maybe(true);
+        assert_eq!(m, 1);
+    }
+}
@@ -0,0 +1,50 @@
+unsafe fn bump(state: &mut State, n: i32) {
+    state.counter += n;
+    state.last = n;
+}
+
+unsafe fn bump_twice(state: &mut State, n: i32) {
+    bump(state, n);
+    bump(state, n);
+}
+
+unsafe fn square(n: i32) -> i32 {
+    n * n
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lib_add(n: i32) -> i32 {
+    lib_add_with_state(&mut global_state().lock().unwrap(), n)
+}
+unsafe fn lib_add_with_state(state: &mut State, mut n: i32) -> i32 {
+    n = square(n);
+    bump_twice(state, n);
+    state.counter
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lib_add_last() -> i32 {
+    lib_add_last_with_state(&mut global_state().lock().unwrap())
+}
+unsafe fn lib_add_last_with_state(state: &mut State) -> i32 {
+    lib_add_with_state(state, 1) + state.last
+}
+
+fn main() {}
+pub struct State {
+    counter: i32,
+    last: i32,
+}
+impl State {
+    pub fn new() -> State {
+        State { counter: 0, last: -1 }
+    }
+}
+fn global_state() -> &'static ::std::sync::Mutex<State> {
+    static INIT: ::std::sync::Once = ::std::sync::Once::new();
+    static mut STATE: Option<::std::sync::Mutex<State>> = None;
+    unsafe {
+        INIT.call_once(|| STATE = Some(::std::sync::Mutex::new(State::new())));
+        STATE.as_ref().unwrap()
+    }
+}
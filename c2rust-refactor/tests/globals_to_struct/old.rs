@@ -0,0 +1,30 @@
+static mut counter: i32 = 0;
+static mut last: i32 = -1;
+
+unsafe fn bump(n: i32) {
+    counter += n;
+    last = n;
+}
+
+unsafe fn bump_twice(n: i32) {
+    bump(n);
+    bump(n);
+}
+
+unsafe fn square(n: i32) -> i32 {
+    n * n
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lib_add(mut n: i32) -> i32 {
+    n = square(n);
+    bump_twice(n);
+    counter
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lib_add_last() -> i32 {
+    lib_add(1) + last
+}
+
+fn main() {}
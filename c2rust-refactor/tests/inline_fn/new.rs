@@ -0,0 +1,37 @@
+struct Foo {
+    len: u32,
+    next: *mut Foo,
+}
+
+static mut COUNTER: u32 = 0;
+
+unsafe fn next_ptr() -> *const Foo {
+    COUNTER += 1;
+    0 as *const Foo
+}
+
+fn early(x: u32) -> u32 {
+    if x == 0 {
+        return 1;
+    }
+    x
+}
+
+fn demo(p: *const Foo) -> usize {
+    let a = unsafe { (*p).len as usize };
+    let b = {
+        let s: *const Foo = unsafe { next_ptr() };
+        unsafe { (*s).len as usize }
+    };
+    let n = 3;
+    let c = {
+        let y: u32 = 2;
+        n + n + y
+    };
+    let d = early(c);
+    a + b + d as usize
+}
+
+fn main() {
+    demo(0 as *const Foo);
+}
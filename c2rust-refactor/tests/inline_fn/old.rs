@@ -0,0 +1,39 @@
+struct Foo {
+    len: u32,
+    next: *mut Foo,
+}
+
+static mut COUNTER: u32 = 0;
+
+unsafe fn next_ptr() -> *const Foo {
+    COUNTER += 1;
+    0 as *const Foo
+}
+
+fn get_len(s: *const Foo) -> usize {
+    unsafe { (*s).len as usize }
+}
+
+fn add_twice(x: u32, y: u32) -> u32 {
+    return x + x + y;
+}
+
+fn early(x: u32) -> u32 {
+    if x == 0 {
+        return 1;
+    }
+    x
+}
+
+fn demo(p: *const Foo) -> usize {
+    let a = get_len(p);
+    let b = get_len(unsafe { next_ptr() });
+    let n = 3;
+    let c = add_twice(n, 2);
+    let d = early(c);
+    a + b + d as usize
+}
+
+fn main() {
+    demo(0 as *const Foo);
+}
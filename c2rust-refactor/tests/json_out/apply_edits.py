@@ -0,0 +1,36 @@
+#!/usr/bin/env python3
+"""Apply the edits written by `--json-out` to one file and print the result."""
+import json
+import os
+import sys
+
+
+def main():
+    edits_path, path = sys.argv[1:]
+    with open(edits_path) as f:
+        rewrites = json.load(f)
+    with open(path, 'rb') as f:
+        text = f.read()
+
+    # Only the last rewrite with each base applies.
+    last = {}
+    for rw in rewrites:
+        last[rw['base']] = rw
+
+    target = os.path.realpath(path)
+    for base in sorted(last):
+        for change in last[base]['changes']:
+            if os.path.realpath(change['file']) != target:
+                continue
+            new = b''
+            cur = 0
+            for e in change['edits']:
+                new += text[cur:e['lo']] + e['text'].encode('utf-8')
+                cur = e['hi']
+            text = new + text[cur:]
+
+    sys.stdout.buffer.write(text)
+
+
+if __name__ == '__main__':
+    main()
@@ -0,0 +1,14 @@
+pub struct Point {
+    pub horiz: i32,
+    pub y: i32,
+}
+
+// A comment that must survive
+fn norm(p: &Point) -> i32 {
+    p.horiz * p.horiz + p.y * p.y
+}
+
+fn main() {
+    let p = Point { horiz: 3, y: 4 };
+    println!("{} {}", p.horiz, norm(&p));
+}
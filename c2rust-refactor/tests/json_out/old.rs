@@ -0,0 +1,14 @@
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+// A comment that must survive
+fn norm(p: &Point) -> i32 {
+    p.x * p.x + p.y * p.y
+}
+
+fn main() {
+    let p = Point { x: 3, y: 4 };
+    println!("{} {}", p.x, norm(&p));
+}
@@ -0,0 +1,16 @@
+#!/bin/sh
+set -e
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    --json-out edits.json \
+    rename_fields Point x horiz \
+    -- old.rs $rustflags
+
+# Applying the recorded edits to the original text must give the rewritten file.
+python3 apply_edits.py edits.json old.rs > old.rs.json
+cmp old.rs.json old.rs.new
@@ -0,0 +1,18 @@
+fn audit<T>(x: T) -> T {
+    x
+}
+
+fn main() {
+    let small: u8 = 200;
+    let big: u64 = 1 << 40;
+    let neg: i32 = -5;
+    let p = &small as *const u8;
+
+    let a = small as u32;
+    let b = audit(big as u32);
+    let c = audit(neg as u32);
+    let d = 5i32 as u32;
+    let e = audit(p as u32);
+    let f = p as usize;
+    println!("{} {} {} {} {} {}", a, b, c, d, e, f);
+}
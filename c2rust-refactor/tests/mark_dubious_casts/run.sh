@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    mark_dubious_casts \; \
+    rewrite_expr 'marked!($e:Expr as $t:Ty, dubious)' 'audit($e as $t)' \
+    -- old.rs $rustflags
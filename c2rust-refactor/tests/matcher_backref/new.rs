@@ -0,0 +1,48 @@
+use std::mem::size_of_val;
+
+struct S {
+    f: i32,
+}
+
+fn copy_bytes<T>(dst: &mut T, src: &T, n: usize) {
+    let _ = n;
+    unsafe {
+        std::ptr::copy_nonoverlapping(src, dst, 1);
+    }
+}
+
+fn copy_val<T: Copy>(dst: &mut T, src: &T) {
+    *dst = *src;
+}
+
+fn sum(a: i32, b: i32) -> i32 {
+    a + b
+}
+
+fn main() {
+    let mut a = 1;
+    let b = 2;
+    let mut s = S { f: 3 };
+    let mut v = [1, 2, 3];
+    let (i, j) = (0, 1);
+
+    ();
+    ();
+    ();
+    a = b;
+    v[i] = v[j];
+
+    let mut x = 0u64;
+    let mut y = 0u32;
+    let z = 0u64;
+    copy_val(&mut x, &z);
+    copy_bytes(&mut y, &y.clone(), size_of_val(&z));
+
+    let n = 1;
+    let m = sum(n, 2);
+    let m2 = sum(n, {
+        let n = 2;
+        n
+    });
+    println!("{} {} {} {} {} {}", a, s.f, v[0], x, m, m2);
+}
@@ -0,0 +1,12 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr '$x:Expr = $x' '()' \; \
+    rewrite_expr 'copy_bytes($dst:Expr, $src:Expr, size_of_val($dst))' 'copy_val($dst, $src)' \; \
+    rewrite_expr 'sum($x:Expr, semantic_eq!({ let $p:Pat = $e:Expr; $x }))' 'sum($x, $e)' \; \
+    -- old.rs $rustflags
@@ -0,0 +1,24 @@
+struct Buf {
+    base: usize,
+}
+
+impl Buf {
+    fn offset(&self, n: isize) -> usize {
+        (self.base as isize + n) as usize
+    }
+}
+
+unsafe fn get(p: *const u8, n: isize) -> u8 {
+    *p.add(n as usize)
+}
+
+fn main() {
+    let data = [1u8, 2, 3];
+    let p = data.as_ptr();
+    let b = Buf { base: 10 };
+    let rb = &b;
+    unsafe {
+        println!("{} {} {}", *p.add(1 as usize), *p.add(2 as usize), get(p, 0));
+    }
+    println!("{} {}", b.base + 1 as usize, rb.base + 2 as usize);
+}
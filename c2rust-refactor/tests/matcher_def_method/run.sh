@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr 'def!(<*const $t:Ty>::offset, $p.offset($n))' '$p.add($n as usize)' \; \
+    rewrite_expr 'def!(crate::Buf::offset)($b, $n)' '$b.base + $n as usize' \; \
+    -- old.rs $rustflags
@@ -0,0 +1,8 @@
+fn main() {
+    let a: u8 = 1;
+    let b: u32 = 2;
+    let x = a.wrapping_add(1);
+    let y = b + 1;
+    let z = a + 2;
+    println!("{} {} {}", x, y, z);
+}
@@ -0,0 +1,18 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr --explain-matches 5 'typed!($e:Expr, u8) + 1' '$e.wrapping_add(1)' \
+    -- old.rs $rustflags 2>explain.log || { cat explain.log >&2; exit 1; }
+
+# `b + 1` fails the type guard, while `a + 2` fails on the literal.
+grep -q 'then: type guard mismatch (WrongType) at old.rs:5:13: 5:14' explain.log &&
+grep -q 'then: structural mismatch (.*) at old.rs:6:17: 6:18' explain.log || {
+    cat explain.log >&2
+    exit 1
+}
+rm explain.log
@@ -0,0 +1,43 @@
+unsafe extern "C" fn get(p: *const i32) -> i32 {
+    *p
+}
+
+extern "C" fn twice(x: i32) -> i32 {
+    x * 2
+}
+
+extern "system" fn thrice(x: i32) -> i32 {
+    x * 3
+}
+
+fn plus_one(x: i32) -> i32 {
+    x + 1
+}
+
+extern "C" fn already_safe(x: i32) -> i32 {
+    x
+}
+
+trait Op {
+    unsafe extern "C" fn run(&self, x: i32) -> i32;
+}
+
+struct Id;
+
+impl Op for Id {
+    unsafe extern "C" fn run(&self, x: i32) -> i32 {
+        x
+    }
+}
+
+fn main() {
+    let v = 1;
+    unsafe {
+        println!("{}", get(&v));
+        println!("{}", twice(v));
+        println!("{}", thrice(v));
+        println!("{}", plus_one(v));
+        println!("{}", Id.run(v));
+    }
+    println!("{}", already_safe(v));
+}
@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_item 'unsafe $q:FnQual fn $f:Ident($x:Pat: i32) -> $r:Ty { $b:MultiStmt }' \
+        '$q:FnQual fn $f($x: i32) -> $r { $b }' \; \
+    -- old.rs $rustflags
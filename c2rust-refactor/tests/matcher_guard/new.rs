@@ -0,0 +1,11 @@
+fn count() -> i32 {
+    println!("counting");
+    3
+}
+
+fn main() {
+    let a = 0;
+    let b = count() * 0;
+    let c = 0;
+    println!("{} {} {}", a, b, c);
+}
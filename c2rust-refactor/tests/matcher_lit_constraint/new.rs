@@ -0,0 +1,39 @@
+fn alloc(n: usize) -> Vec<u8> {
+    vec![0; n]
+}
+
+fn alloc_small(n: usize) -> Vec<u8> {
+    vec![0; n]
+}
+
+fn shl_small(x: u32, k: u32) -> u32 {
+    x << k
+}
+
+fn offset(d: i32) -> i32 {
+    d
+}
+
+fn log(s: &str) {
+    println!("{}", s);
+}
+
+fn log_fmt(s: &str) {
+    println!("{}", s);
+}
+
+fn main() {
+    let n = 100;
+    let a = alloc_small(16);
+    let b = alloc_small(4096);
+    let c = alloc(4097);
+    let d = alloc(n);
+    let x = shl_small(1u32, 3);
+    let y = 1u32 << 8;
+    let z = x << y;
+    let o = -4 + 4 + offset(-5) + offset(5);
+    log_fmt("fmt:%d");
+    log("format");
+    ();
+    println!("{} {} {} {} {} {} {} {}", a.len(), b.len(), c.len(), d.len(), x, y, z, o);
+}
@@ -0,0 +1,14 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr 'alloc($n:LitInt(..=4096))' 'alloc_small($n)' \; \
+    rewrite_expr '$x:Expr << $k:LitInt(0..8)' 'shl_small($x, $k)' \; \
+    rewrite_expr 'offset($d:LitInt(-4..=4))' '$d' \; \
+    rewrite_expr 'log($s:LitStr("fmt:"..))' 'log_fmt($s)' \; \
+    rewrite_expr 'log($s:LitStr("done"))' '()' \; \
+    -- old.rs $rustflags
@@ -0,0 +1,19 @@
+macro_rules! double {
+    ($e:expr) => {
+        $e * 2
+    };
+}
+
+macro_rules! widen {
+    ($e:expr) => {
+        $e as u32 as u32
+    };
+}
+
+fn main() {
+    let x: u32 = 5;
+    let a = double!(x);
+    let b = widen!(x);
+    let c = x;
+    println!("{} {} {}", a, b, c);
+}
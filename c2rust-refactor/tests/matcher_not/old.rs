@@ -0,0 +1,11 @@
+fn main() {
+    let a = 1u8;
+    let b = 2u16;
+    let c = -3i32;
+    let x = a as usize;
+    let y = c as u16 as usize;
+    let z = (b + 1) as usize;
+    let w = b + 0;
+    let v = a + 0;
+    println!("{} {} {} {} {}", x, y, z, w, v);
+}
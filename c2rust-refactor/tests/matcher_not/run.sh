@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr 'not!($e:Expr, $inner:Expr as $t:Ty) as usize' 'usize::from($e)' \; \
+    rewrite_expr 'not!($a:Expr, typed!($b:Expr, u8)) + 0' '$a' \; \
+    -- old.rs $rustflags
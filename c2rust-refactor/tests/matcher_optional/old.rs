@@ -0,0 +1,40 @@
+fn set(xs: &[i32]) -> i32 {
+    xs.len() as i32
+}
+
+fn set_flag(x: &[i32], flag: &[i32]) -> i32 {
+    x[0] + flag.len() as i32
+}
+
+fn opts(xs: &[i32]) -> i32 {
+    xs.len() as i32
+}
+
+fn opts2(a: &[i32], b: &[i32], rest: &[i32]) -> i32 {
+    a[0] + b.len() as i32 + rest.len() as i32
+}
+
+fn tail(xs: &[i32]) -> i32 {
+    xs.len() as i32
+}
+
+fn tail2(xs: &[i32], last: &[i32]) -> i32 {
+    xs.len() as i32 + last.len() as i32
+}
+
+fn main() {
+    // `$flag` is absent in the first call
+    let a = set(&[1]);
+    let b = set(&[1, 2]);
+    // Too many elements for the pattern
+    let c = set(&[1, 2, 3]);
+    // A repetition nested inside two optional groups
+    let d = opts(&[]);
+    let e = opts(&[1]);
+    let f = opts(&[1, 2]);
+    let g = opts(&[1, 2, 3, 4]);
+    // `$xs` is greedy, so it leaves nothing for the optional `$last`
+    let h = tail(&[]);
+    let i = tail(&[1, 2]);
+    println!("{} {} {} {} {} {} {} {} {}", a, b, c, d, e, f, g, h, i);
+}
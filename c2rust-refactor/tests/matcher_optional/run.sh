@@ -0,0 +1,13 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr 'set(&[$x:Expr $(, $flag:Expr)?])' 'set_flag(&[$x], &[$($flag)?])' \; \
+    rewrite_expr 'opts(&[$a:Expr $(, $b:Expr $(, $rest:Expr+)?)?])' \
+        'opts2(&[$a], &[$($b)?], &[$($rest)?])' \; \
+    rewrite_expr 'tail(&[$xs:Expr* $(, $last:Expr)?])' 'tail2(&[$xs], &[$($last)?])' \; \
+    -- old.rs $rustflags
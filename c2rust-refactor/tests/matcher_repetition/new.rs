@@ -0,0 +1,50 @@
+fn sum(xs: &[i32]) -> i32 {
+    xs.iter().sum()
+}
+
+fn add_all(xs: &[i32]) -> i32 {
+    xs.iter().fold(0, |a, b| a + b)
+}
+
+fn pick(xs: &[i32]) -> i32 {
+    xs.first().cloned().unwrap_or(0)
+}
+
+fn pick_first(x: i32, _rest: &[i32]) -> i32 {
+    x
+}
+
+fn last(xs: &[i32]) -> i32 {
+    xs.last().cloned().unwrap_or(0)
+}
+
+fn last_of(x: i32, _init: &[i32]) -> i32 {
+    x
+}
+
+fn noargs() -> i32 {
+    0
+}
+
+fn noargs2() -> i32 {
+    0
+}
+
+fn main() {
+    // `$xs:Expr+` needs at least one element
+    let a = sum(&[]);
+    let b = add_all(&[1, 0]);
+    let c = add_all(&[1, 2, 3, 0]);
+    // `$rest:Expr*` can be empty
+    let d = pick(&[]);
+    let e = pick_first(1, &[]);
+    let f = pick_first(1, &[2, 3]);
+    // `$init:Expr*` is greedy, but leaves one element for `$x`
+    let g = last(&[]);
+    let h = last_of(1, &[]);
+    let i = last_of(3, &[1, 2]);
+    let j = noargs2();
+    // `*` is only a repetition at the end of a list element
+    let k = j + j;
+    println!("{} {} {} {} {} {} {} {} {} {} {}", a, b, c, d, e, f, g, h, i, j, k);
+}
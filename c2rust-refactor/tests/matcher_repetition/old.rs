@@ -0,0 +1,50 @@
+fn sum(xs: &[i32]) -> i32 {
+    xs.iter().sum()
+}
+
+fn add_all(xs: &[i32]) -> i32 {
+    xs.iter().fold(0, |a, b| a + b)
+}
+
+fn pick(xs: &[i32]) -> i32 {
+    xs.first().cloned().unwrap_or(0)
+}
+
+fn pick_first(x: i32, _rest: &[i32]) -> i32 {
+    x
+}
+
+fn last(xs: &[i32]) -> i32 {
+    xs.last().cloned().unwrap_or(0)
+}
+
+fn last_of(x: i32, _init: &[i32]) -> i32 {
+    x
+}
+
+fn noargs() -> i32 {
+    0
+}
+
+fn noargs2() -> i32 {
+    0
+}
+
+fn main() {
+    // `$xs:Expr+` needs at least one element
+    let a = sum(&[]);
+    let b = sum(&[1]);
+    let c = sum(&[1, 2, 3]);
+    // `$rest:Expr*` can be empty
+    let d = pick(&[]);
+    let e = pick(&[1]);
+    let f = pick(&[1, 2, 3]);
+    // `$init:Expr*` is greedy, but leaves one element for `$x`
+    let g = last(&[]);
+    let h = last(&[1]);
+    let i = last(&[1, 2, 3]);
+    let j = noargs();
+    // `*` is only a repetition at the end of a list element
+    let k = j * 2;
+    println!("{} {} {} {} {} {} {} {} {} {} {}", a, b, c, d, e, f, g, h, i, j, k);
+}
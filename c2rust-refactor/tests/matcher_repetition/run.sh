@@ -0,0 +1,14 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr 'sum(&[$xs:Expr+])' 'add_all(&[$xs, 0])' \; \
+    rewrite_expr 'pick(&[$first:Expr, $rest:Expr*])' 'pick_first($first, &[$rest])' \; \
+    rewrite_expr 'last(&[$init:Expr*, $x:Expr])' 'last_of($x, &[$init:Expr*])' \; \
+    rewrite_expr 'noargs($args:Expr*)' 'noargs2($args)' \; \
+    rewrite_expr '$x:Expr * 2' '$x + $x' \; \
+    -- old.rs $rustflags
@@ -0,0 +1,44 @@
+mod parser {
+    pub fn parse() -> i32 {
+        1 + 1
+    }
+
+    pub fn parse_more() -> i32 {
+        2 + 2
+    }
+
+    pub struct Parser;
+
+    impl Parser {
+        pub fn run(&self) -> i32 {
+            3 + 3
+        }
+
+        pub fn stop(&self) -> i32 {
+            3 + 3
+        }
+    }
+}
+
+mod crypto {
+    pub fn hash() -> i32 {
+        1 + 1
+    }
+
+    pub fn parse_key() -> i32 {
+        2 + 2
+    }
+}
+
+fn main() {
+    let p = parser::Parser;
+    println!(
+        "{} {} {} {} {} {}",
+        parser::parse(),
+        parser::parse_more(),
+        p.run(),
+        p.stop(),
+        crypto::hash(),
+        crypto::parse_key()
+    );
+}
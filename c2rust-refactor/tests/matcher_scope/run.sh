@@ -0,0 +1,13 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr '1 + 1' '2' --scope 'crate::parser::*' \; \
+    rewrite_expr '3 + 3' '6' --scope 'crate::parser::Parser::run' \; \
+    select target 'item(parse_key);' \; \
+    rewrite_expr '2 + 2' '4' --scope 'crate::*::parse_*' --scope-mark target \; \
+    -- old.rs $rustflags
@@ -0,0 +1,35 @@
+fn first<T>(_x: T) -> u8 {
+    0
+}
+
+fn count<I>(_i: I) -> usize {
+    0
+}
+
+fn with_where_clause<T>(buf: T) -> u8
+where
+    T: AsRef<[u8]>,
+{
+    AsRef::<[u8]>::as_ref(&buf)[0]
+}
+
+fn with_other_bound<T: Clone>(x: T) -> u8 {
+    first(x)
+}
+
+fn with_iterator<I: Iterator<Item = u8>>(it: I) -> usize {
+    it.count()
+}
+
+fn main() {
+    let v = vec![1u8, 2, 3];
+    let n = 5i32;
+    println!(
+        "{} {} {} {}",
+        AsRef::<[u8]>::as_ref(&v.clone())[0],
+        first(n),
+        with_where_clause(v.clone()),
+        with_other_bound(n)
+    );
+    println!("{} {} {}", v.iter().count(), count(n), with_iterator(v.into_iter()));
+}
@@ -0,0 +1,35 @@
+fn first<T>(_x: T) -> u8 {
+    0
+}
+
+fn count<I>(_i: I) -> usize {
+    0
+}
+
+fn with_where_clause<T>(buf: T) -> u8
+where
+    T: AsRef<[u8]>,
+{
+    first(buf)
+}
+
+fn with_other_bound<T: Clone>(x: T) -> u8 {
+    first(x)
+}
+
+fn with_iterator<I: Iterator<Item = u8>>(it: I) -> usize {
+    count(it)
+}
+
+fn main() {
+    let v = vec![1u8, 2, 3];
+    let n = 5i32;
+    println!(
+        "{} {} {} {}",
+        first(v.clone()),
+        first(n),
+        with_where_clause(v.clone()),
+        with_other_bound(n)
+    );
+    println!("{} {} {}", count(v.iter()), count(n), with_iterator(v.into_iter()));
+}
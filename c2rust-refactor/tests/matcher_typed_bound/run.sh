@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr 'first(typed_bound!($b:Expr, AsRef<[u8]>))' 'AsRef::<[u8]>::as_ref(&$b)[0]' \; \
+    rewrite_expr 'count(typed_bound!($i:Expr, Iterator<Item = $t:Ty>))' '$i.count()' \; \
+    -- old.rs $rustflags
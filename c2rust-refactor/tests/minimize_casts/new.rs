@@ -0,0 +1,23 @@
+fn main() {
+    let a: u8 = 200;
+    let b: u8 = 100;
+    let c: i8 = -3;
+    let d: i8 = 7;
+
+    let and = a & b;
+    let or = a | b | 1u8;
+    let xor = a ^ b;
+    let sum = a.wrapping_add(b);
+    let nested = a.wrapping_add(b) & 0xf0u8;
+
+    // Signed arithmetic only gets narrowed with `signed`
+    let signed_sum = (c as i32 + d as i32) as i8;
+    let signed_and = c & d;
+
+    // Division and shifts depend on the high bits
+    let quot = (a as i32 / b as i32) as u8;
+    let shift = (a as u32 >> 1) as u8;
+
+    println!("{} {} {} {} {} {} {} {} {}",
+             and, or, xor, sum, nested, signed_sum, signed_and, quot, shift);
+}
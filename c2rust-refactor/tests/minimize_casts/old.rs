@@ -0,0 +1,23 @@
+fn main() {
+    let a: u8 = 200;
+    let b: u8 = 100;
+    let c: i8 = -3;
+    let d: i8 = 7;
+
+    let and = (a as i32 & b as i32) as u8;
+    let or = (a as u32 | b as u32 | 1) as u8;
+    let xor = (a as i32 ^ b as i32) as u8;
+    let sum = (a as u32 + b as u32) as u8;
+    let nested = ((a as u32 + b as u32) & 0xf0) as u8;
+
+    // Signed arithmetic only gets narrowed with `signed`
+    let signed_sum = (c as i32 + d as i32) as i8;
+    let signed_and = (c as i32 & d as i32) as i8;
+
+    // Division and shifts depend on the high bits
+    let quot = (a as i32 / b as i32) as u8;
+    let shift = (a as u32 >> 1) as u8;
+
+    println!("{} {} {} {} {} {} {} {} {}",
+             and, or, xor, sum, nested, signed_sum, signed_and, quot, shift);
+}
@@ -0,0 +1,28 @@
+const ALL_PERMS: u32 = 0o777;
+const O_LARGEFILE: i32 = 0x8000;
+const S_IFREG: u16 = 0x8000;
+fn open(path: &str, flags: i32) -> i32 {
+    path.len() as i32 + flags
+}
+
+fn chmod(path: &str, mode: u32) -> u32 {
+    path.len() as u32 + mode
+}
+
+fn setup_dir(path: &str) {
+    chmod(path, ALL_PERMS);
+}
+
+fn setup_file(path: &str) {
+    // Matches both `O_LARGEFILE` and `SETUP_FLAG`
+    open(path, 0x8000);
+}
+
+fn main() {
+    open("a", O_LARGEFILE);
+    chmod("b", S_IFREG as u32);
+    // Not an argument of a mapped callee
+    let _x = 0x8000;
+    setup_dir("c");
+    setup_file("d");
+}
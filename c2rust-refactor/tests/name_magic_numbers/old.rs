@@ -0,0 +1,25 @@
+fn open(path: &str, flags: i32) -> i32 {
+    path.len() as i32 + flags
+}
+
+fn chmod(path: &str, mode: u32) -> u32 {
+    path.len() as u32 + mode
+}
+
+fn setup_dir(path: &str) {
+    chmod(path, 0o777);
+}
+
+fn setup_file(path: &str) {
+    // Matches both `O_LARGEFILE` and `SETUP_FLAG`
+    open(path, 0x8000);
+}
+
+fn main() {
+    open("a", 0x8000);
+    chmod("b", 0x8000);
+    // Not an argument of a mapped callee
+    let _x = 0x8000;
+    setup_dir("c");
+    setup_file("d");
+}
@@ -0,0 +1,38 @@
+#![feature(libc)]
+extern crate libc;
+
+struct Header {
+    len: u64,
+    flags: u16,
+    tag: u8,
+}
+
+struct Pair(u32, u8, u8);
+
+#[derive(Copy, Clone)]
+struct Node {
+    next: *mut Node,
+    depth: u32,
+    kind: u8,
+}
+
+#[repr(C)]
+struct Packet {
+    kind: u8,
+    value: f64,
+}
+
+extern "C" {
+    fn send_packet(p: *const Packet);
+}
+
+fn main() {
+    let h = Header { tag: 1, len: 2, flags: 3 };
+    let p = Pair(2, 1, 3);
+    let Pair(b, a, c) = p;
+    let sum = p.1 as u32 + p.0 + p.2 as u32 + a as u32 + b + c as u32;
+    let n = Node { kind: 0, next: 0 as *mut Node, depth: h.flags as u32 };
+    let pkt = Packet { kind: h.tag, value: sum as f64 };
+    unsafe { send_packet(&pkt) };
+    let _ = (n.depth, h.len);
+}
@@ -0,0 +1,39 @@
+#![feature(libc)]
+extern crate libc;
+
+struct Header {
+    tag: u8,
+    len: u64,
+    flags: u16,
+}
+
+struct Pair(u8, u32, u8);
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Node {
+    kind: u8,
+    next: *mut Node,
+    depth: u32,
+}
+
+#[repr(C)]
+struct Packet {
+    kind: u8,
+    value: f64,
+}
+
+extern "C" {
+    fn send_packet(p: *const Packet);
+}
+
+fn main() {
+    let h = Header { tag: 1, len: 2, flags: 3 };
+    let p = Pair(1, 2, 3);
+    let Pair(a, b, c) = p;
+    let sum = p.0 as u32 + p.1 + p.2 as u32 + a as u32 + b + c as u32;
+    let n = Node { kind: 0, next: 0 as *mut Node, depth: h.flags as u32 };
+    let pkt = Packet { kind: h.tag, value: sum as f64 };
+    unsafe { send_packet(&pkt) };
+    let _ = (n.depth, h.len);
+}
@@ -0,0 +1,6 @@
+fn main() {
+    let x = 2;
+    let y = 3;
+    let z = x;
+    println!("{} {} {}", x, y, z);
+}
@@ -0,0 +1,6 @@
+fn main() {
+    let x = 1 + 1;
+    let y = 3 * 1;
+    let z = 0 + x;
+    println!("{} {} {}", x, y, z);
+}
@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+# `pipelines.toml` in the current directory is loaded by default.
+$refactor \
+    cleanup two=2 'from=3 * 1' to=3 \
+    -- old.rs $rustflags
@@ -0,0 +1,103 @@
+static TABLE: [u8; 4] = [1, 2, 3, 4];
+
+#[derive(Clone, Copy)]
+pub struct Op {
+    pub code: i32,
+    pub name: &'static str,
+    pub run: Option<fn(i32) -> i32>,
+}
+
+fn double(x: i32) -> i32 {
+    x * 2
+}
+
+fn negate(x: i32) -> i32 {
+    -x
+}
+
+static CRC4_TABLE: [u32; 16] = [
+    0x00000000, 0x1db71064, 0x3b6e20c8, 0x26d930ac, 0x76dc4190, 0x6b6b51f4, 0x4db26158, 0x5005713c,
+    0xedb88320, 0xf00f9344, 0xd6d6a3e8, 0xcb61b38c, 0x9b64c2b0, 0x86d3d2d4, 0xa00ae278, 0xbdbdf21c,
+];
+
+pub fn crc4(data: &[u8]) -> u32 {
+    let table = &CRC4_TABLE;
+    let mut crc = !0u32;
+    for &b in data {
+        crc = table[((crc ^ b as u32) & 0xf) as usize] ^ (crc >> 4);
+        crc = table[((crc ^ (b as u32 >> 4)) & 0xf) as usize] ^ (crc >> 4);
+    }
+    !crc
+}
+
+pub fn crc4_byte(b: u8) -> u32 {
+    let table = &CRC4_TABLE;
+    let crc = table[(b & 0xf) as usize];
+    table[((crc ^ (b as u32 >> 4)) & 0xf) as usize] ^ (crc >> 4)
+}
+
+fn lookup(ops: &[Op; 3], code: i32) -> Option<Op> {
+    ops.iter().cloned().find(|op| op.code == code)
+}
+
+static OPS: [Op; 3] = [
+    Op {
+        code: 1,
+        name: "double",
+        run: Some(double),
+    },
+    Op {
+        code: 2,
+        name: "negate",
+        run: Some(negate),
+    },
+    Op {
+        code: 3,
+        name: "nop",
+        run: None,
+    },
+];
+
+pub fn dispatch(code: i32, x: i32) -> i32 {
+    let ops = &OPS;
+    if code as usize > ops.len() {
+        return x;
+    }
+    match lookup(ops, code) {
+        Some(Op { run: Some(f), .. }) => f(x),
+        _ => x,
+    }
+}
+
+const PTRS: [*const u8; 10] = [0 as *const u8; 10];
+
+pub fn count_null(p: *const u8) -> usize {
+    let ptrs = &PTRS;
+    let copy = *ptrs;
+    copy.iter().filter(|&&q| q == p).count()
+}
+
+pub fn runtime(seed: u32) -> u32 {
+    let keys: [u32; 16] = [seed, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+    keys.iter().sum()
+}
+
+pub fn small() -> u8 {
+    let bytes: [u8; 4] = [1, 2, 3, 4];
+    bytes[1] + TABLE[1]
+}
+
+pub fn scratch(n: usize) -> u32 {
+    let mut buf: [u32; 32] = [0; 32];
+    for i in 0..n.min(32) {
+        buf[i] = i as u32;
+    }
+    buf.iter().sum()
+}
+
+fn main() {
+    println!("{} {}", crc4(b"abc"), crc4_byte(7));
+    println!("{} {} {}", dispatch(1, 5), dispatch(2, 5), dispatch(3, 5));
+    println!("{} {}", count_null(0 as *const u8), runtime(3));
+    println!("{} {}", small(), scratch(10));
+}
@@ -0,0 +1,47 @@
+fn main() {
+    let n: i32 = 10;
+
+    for i in 0 as i32..n {
+        println!("{}", i);
+    }
+
+    for j in (3 as u64..=30).step_by(3 as usize) {
+        println!("{}", j);
+    }
+
+    // `continue` in an inner loop doesn't skip the increment
+    'outer: for a in 0 as i32..3 {
+        for x in 0..3 {
+            if x == a {
+                continue;
+            }
+            println!("{} {}", a, x);
+        }
+    }
+
+    // `continue` would skip the increment
+    let mut k: i32 = 0;
+    while k < n {
+        if k > 100 {
+            continue;
+        }
+        println!("{}", k);
+        k += 1;
+    }
+
+    // `m` is read after the loop
+    let mut m: i32 = 0;
+    while m < n {
+        println!("{}", m);
+        m += 1;
+    }
+    println!("{}", m);
+
+    // The bound changes inside the loop
+    let mut limit: i32 = 5;
+    let mut p: i32 = 0;
+    while p < limit {
+        limit -= 1;
+        p += 1;
+    }
+}
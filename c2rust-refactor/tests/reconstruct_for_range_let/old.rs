@@ -0,0 +1,53 @@
+fn main() {
+    let n: i32 = 10;
+
+    let mut i: i32 = 0;
+    while i < n {
+        println!("{}", i);
+        i += 1;
+    }
+
+    let mut j: u64 = 3;
+    while j <= 30 {
+        println!("{}", j);
+        j = j + 3;
+    }
+
+    // `continue` in an inner loop doesn't skip the increment
+    let mut a: i32 = 0;
+    'outer: while a < 3 {
+        for x in 0..3 {
+            if x == a {
+                continue;
+            }
+            println!("{} {}", a, x);
+        }
+        a += 1;
+    }
+
+    // `continue` would skip the increment
+    let mut k: i32 = 0;
+    while k < n {
+        if k > 100 {
+            continue;
+        }
+        println!("{}", k);
+        k += 1;
+    }
+
+    // `m` is read after the loop
+    let mut m: i32 = 0;
+    while m < n {
+        println!("{}", m);
+        m += 1;
+    }
+    println!("{}", m);
+
+    // The bound changes inside the loop
+    let mut limit: i32 = 5;
+    let mut p: i32 = 0;
+    while p < limit {
+        limit -= 1;
+        p += 1;
+    }
+}
@@ -0,0 +1,71 @@
+fn classify(x: i32) -> i32 {
+    let mut n = 0;
+    match x {
+        1 => {
+            n += 1;
+            n += 10;
+        }
+        2 => {
+            n += 10;
+        }
+        3 | 4 => {
+            n += 3;
+            n += 20;
+        }
+        5 | 6 => {
+            n += 20;
+        }
+        _ => {}
+    }
+    n
+}
+
+fn chain(x: i32) -> i32 {
+    let mut n = 0;
+    match x {
+        0 => return -1,
+        1 => {
+            {
+                let y = x * 2;
+                n = y;
+            }
+            n += 1;
+        }
+        _ => {
+            n -= 1;
+        }
+    }
+    n
+}
+
+fn tangled(x: i32) -> i32 {
+    let mut n = 0;
+    let mut current_block: u64;
+    match x {
+        1 => {
+            n += 1;
+            current_block = 7;
+        }
+        2 => {
+            n += 2;
+            current_block = 7;
+        }
+        _ => {
+            n += 3;
+            current_block = 7;
+        }
+    }
+    match current_block {
+        7 => {
+            n *= 2;
+        }
+        _ => {}
+    }
+    n
+}
+
+fn main() {
+    for x in 0..8 {
+        println!("{} {} {}", classify(x), chain(x), tangled(x));
+    }
+}
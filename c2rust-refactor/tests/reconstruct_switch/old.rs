@@ -0,0 +1,90 @@
+fn classify(x: i32) -> i32 {
+    let mut n = 0;
+    let mut current_block: u64;
+    match x {
+        1 => {
+            n += 1;
+            current_block = 11;
+        }
+        2 => {
+            current_block = 11;
+        }
+        3 | 4 => {
+            n += 3;
+            current_block = 12;
+        }
+        5 => {
+            current_block = 12;
+        }
+        6 => {
+            current_block = 12;
+        }
+        _ => {
+            current_block = 13;
+        }
+    }
+    match current_block {
+        11 => {
+            n += 10;
+        }
+        12 => {
+            n += 20;
+        }
+        _ => {}
+    }
+    n
+}
+
+fn chain(x: i32) -> i32 {
+    let mut current_block: u64;
+    let mut n = 0;
+    match x {
+        0 => return -1,
+        1 => {
+            let y = x * 2;
+            n = y;
+            current_block = 3;
+        }
+        _ => {
+            current_block = 4;
+        }
+    }
+    if current_block == 3 {
+        n += 1;
+    } else {
+        n -= 1;
+    }
+    n
+}
+
+fn tangled(x: i32) -> i32 {
+    let mut n = 0;
+    let mut current_block: u64;
+    match x {
+        1 => {
+            n += 1;
+            current_block = 7;
+        }
+        2 => {
+            n += 2;
+            current_block = 7;
+        }
+        _ => {
+            n += 3;
+            current_block = 7;
+        }
+    }
+    match current_block {
+        7 => {
+            n *= 2;
+        }
+        _ => {}
+    }
+    n
+}
+
+fn main() {
+    for x in 0..8 {
+        println!("{} {} {}", classify(x), chain(x), tangled(x));
+    }
+}
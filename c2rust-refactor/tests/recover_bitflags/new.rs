@@ -0,0 +1,123 @@
+#![feature(libc)]
+extern crate libc;
+
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+#[repr(transparent)]
+pub struct Mode(libc::c_uint);
+impl Mode {
+    pub const READ: Mode = Mode(1);
+    pub const WRITE: Mode = Mode(2);
+    pub const EXEC: Mode = Mode(4);
+    pub const RW: Mode = Mode(Mode::READ.0 | Mode::WRITE.0);
+    pub const fn empty() -> Mode {
+        Mode(0)
+    }
+    pub const fn bits(self) -> libc::c_uint {
+        self.0
+    }
+    pub const fn from_bits_retain(bits: libc::c_uint) -> Mode {
+        Mode(bits)
+    }
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+    pub fn contains(self, other: Mode) -> bool {
+        self.0 & other.0 == other.0
+    }
+    pub fn intersects(self, other: Mode) -> bool {
+        self.0 & other.0 != 0
+    }
+    pub fn insert(&mut self, other: Mode) {
+        self.0 |= other.0;
+    }
+    pub fn remove(&mut self, other: Mode) {
+        self.0 &= !other.0;
+    }
+}
+impl ::std::ops::BitOr for Mode {
+    type Output = Mode;
+    fn bitor(self, other: Mode) -> Mode {
+        Mode(self.0 | other.0)
+    }
+}
+impl ::std::ops::BitOrAssign for Mode {
+    fn bitor_assign(&mut self, other: Mode) {
+        self.0 |= other.0;
+    }
+}
+impl ::std::ops::BitAnd for Mode {
+    type Output = Mode;
+    fn bitand(self, other: Mode) -> Mode {
+        Mode(self.0 & other.0)
+    }
+}
+impl ::std::ops::BitAndAssign for Mode {
+    fn bitand_assign(&mut self, other: Mode) {
+        self.0 &= other.0;
+    }
+}
+impl ::std::ops::BitXor for Mode {
+    type Output = Mode;
+    fn bitxor(self, other: Mode) -> Mode {
+        Mode(self.0 ^ other.0)
+    }
+}
+impl ::std::ops::BitXorAssign for Mode {
+    fn bitxor_assign(&mut self, other: Mode) {
+        self.0 ^= other.0;
+    }
+}
+impl ::std::ops::Not for Mode {
+    type Output = Mode;
+    fn not(self) -> Mode {
+        Mode(!self.0)
+    }
+}
+
+extern "C" {
+    fn apply_mode(mode: libc::c_uint) -> libc::c_int;
+}
+
+pub struct File {
+    pub mode: Mode,
+    pub size: libc::c_int,
+}
+
+fn grant_write(f: &mut File) {
+    f.mode.insert(Mode::WRITE);
+}
+
+fn revoke(f: &mut File) {
+    f.mode.remove(Mode::WRITE);
+    f.mode.remove(Mode::EXEC);
+}
+
+fn toggle_exec(f: &mut File) {
+    f.mode ^= Mode::EXEC;
+}
+
+fn can_read(f: &File) -> bool {
+    f.mode.contains(Mode::READ)
+}
+
+fn is_locked(f: &File) -> bool {
+    !f.mode.intersects(Mode::RW)
+}
+
+fn sync(f: &File) -> libc::c_int {
+    unsafe { apply_mode((f.mode | Mode::EXEC).bits()) }
+}
+
+fn main() {
+    let mut f = File {
+        mode: Mode::READ,
+        size: 0,
+    };
+    grant_write(&mut f);
+    toggle_exec(&mut f);
+    if can_read(&f) && !is_locked(&f) {
+        sync(&f);
+    }
+    revoke(&mut f);
+    f.mode = Mode::empty();
+}
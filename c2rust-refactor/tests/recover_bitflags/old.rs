@@ -0,0 +1,55 @@
+#![feature(libc)]
+extern crate libc;
+
+pub const MODE_READ: libc::c_uint = 1;
+pub const MODE_WRITE: libc::c_uint = 2;
+pub const MODE_EXEC: libc::c_uint = 4;
+pub const MODE_RW: libc::c_uint = 3;
+
+extern "C" {
+    fn apply_mode(mode: libc::c_uint) -> libc::c_int;
+}
+
+pub struct File {
+    pub mode: libc::c_uint,
+    pub size: libc::c_int,
+}
+
+fn grant_write(f: &mut File) {
+    f.mode |= MODE_WRITE;
+}
+
+fn revoke(f: &mut File) {
+    f.mode &= !MODE_WRITE;
+    f.mode &= !MODE_EXEC;
+}
+
+fn toggle_exec(f: &mut File) {
+    f.mode ^= MODE_EXEC;
+}
+
+fn can_read(f: &File) -> bool {
+    f.mode & MODE_READ != 0
+}
+
+fn is_locked(f: &File) -> bool {
+    f.mode & MODE_RW == 0
+}
+
+fn sync(f: &File) -> libc::c_int {
+    unsafe { apply_mode(f.mode | MODE_EXEC) }
+}
+
+fn main() {
+    let mut f = File {
+        mode: MODE_READ,
+        size: 0,
+    };
+    grant_write(&mut f);
+    toggle_exec(&mut f);
+    if can_read(&f) && !is_locked(&f) {
+        sync(&f);
+    }
+    revoke(&mut f);
+    f.mode = 0;
+}
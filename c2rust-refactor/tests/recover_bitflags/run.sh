@@ -0,0 +1,12 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(const && name("MODE_.*"));' \; \
+    select flags 'crate; desc(field && name("mode")); child(ty);' \; \
+    recover_bitflags Mode \
+    -- old.rs $rustflags
@@ -0,0 +1,48 @@
+#![feature(libc)]
+extern crate libc;
+
+#[repr(u32)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Color {
+    RED = 0,
+    GREEN = 1,
+    BLUE = 2,
+}
+
+extern "C" {
+    fn set_color(color: libc::c_uint);
+}
+
+fn name(color: Color) -> &'static str {
+    match color {
+        Color::RED => "red",
+        Color::GREEN | Color::BLUE => "green or blue",
+        _ => unreachable!(),
+    }
+}
+
+fn weight(color: Color) -> libc::c_uint {
+    color as libc::c_uint * 4 + 1
+}
+
+fn next(color: Color) -> Color {
+    if color == Color::BLUE {
+        return Color::RED;
+    }
+    if color == Color::RED {
+        return Color::GREEN;
+    }
+    Color::BLUE
+}
+
+fn paint() {
+    unsafe {
+        set_color(Color::GREEN as u32);
+    }
+}
+
+fn main() {
+    let color: Color = Color::GREEN;
+    println!("{} {}", name(next(color)), weight(color));
+    paint();
+}
@@ -0,0 +1,45 @@
+#![feature(libc)]
+extern crate libc;
+
+pub type Color = libc::c_uint;
+pub const RED: Color = 0;
+pub const GREEN: Color = 1;
+pub const BLUE: Color = 2;
+
+extern "C" {
+    fn set_color(color: Color);
+}
+
+fn name(color: Color) -> &'static str {
+    match color {
+        0 => "red",
+        1 | 2 => "green or blue",
+        _ => "unknown",
+    }
+}
+
+fn weight(color: Color) -> libc::c_uint {
+    color * 4 + 1
+}
+
+fn next(color: Color) -> Color {
+    if color == BLUE {
+        return RED;
+    }
+    if color == 0 {
+        return GREEN;
+    }
+    BLUE
+}
+
+fn paint() {
+    unsafe {
+        set_color(GREEN);
+    }
+}
+
+fn main() {
+    let color: Color = 1;
+    println!("{} {}", name(next(color)), weight(color));
+    paint();
+}
@@ -0,0 +1,48 @@
+#![allow(non_camel_case_types)]
+
+type c_int = i32;
+type c_long = i64;
+
+struct Counter {
+    n: c_int,
+}
+
+impl Counter {
+    fn add(&mut self, x: c_int) {
+        self.n += x;
+    }
+}
+
+fn takes_int(x: c_int) -> c_int {
+    x
+}
+
+fn takes_long(x: c_long) -> c_long {
+    x
+}
+
+unsafe fn takes_ptr(p: *mut u32, q: *const u32) -> u32 {
+    *p + *q
+}
+
+fn generic<T: Into<i64>>(x: T) -> i64 {
+    x.into()
+}
+
+fn main() {
+    let a: i32 = 1;
+    let b: c_int = 2;
+    let mut c = Counter { n: 0 };
+    let mut x = 3u32;
+    let y = 4u32;
+
+    takes_int(a);
+    takes_int(b);
+    takes_long(a as c_long);
+    c.add(a);
+    unsafe {
+        takes_ptr(&mut x, &y);
+        takes_ptr(&mut x as *mut u32, &y as *const u32);
+    }
+    generic(a as i64);
+}
@@ -0,0 +1,23 @@
+#![feature(libc)]
+extern crate libc;
+
+pub type size_t = libc::c_ulong;
+pub type uint8_t = libc::c_uchar;
+pub type uint32_t = libc::c_uint;
+type Counter = uint32_t;
+
+fn main() {
+    let x: u64 = 7;
+    let y: u32 = 3;
+    // Both hops are aliases of `u64`
+    let a = x as size_t as libc::c_ulong;
+    let b = y as size_t as libc::c_ulong;
+    // Folded literals keep the alias instead of getting a primitive suffix
+    let c = 5u64 as uint32_t;
+    let d = 200u32 as uint8_t;
+    let e = (2 + 3) as Counter;
+    let f = 5 as size_t;
+    // ...but primitive targets still get one
+    let g = 5u8 as u32;
+    println!("{} {} {} {} {} {} {}", a, b, c, d, e, f, g);
+}
@@ -0,0 +1,22 @@
+fn narrow(x: i8, y: i8, z: i16, u: u16) {
+    let a = (x as i32 + y as i32) as i8;
+    let b = (x as i32 - y as i32) as i8;
+    let c = (x as i32 * y as i32) as i8;
+    let d = (x as i32 & y as i32) as i8;
+    let e = (x as i32 ^ z as i32) as i8;
+    let f = ((x as i32) << 2) as i8;
+    let g = (x as i32 >> 2) as i8;
+    println!("{} {} {} {} {} {} {}", a, b, c, d, e, f, g);
+
+    // Division depends on the high bits, shifting by 8 would
+    // overflow an `i8`, and the right shift pulls in the high
+    // bits of a `u16` that the narrower result doesn't have
+    let h = (x as i32 / y as i32) as i8;
+    let i = ((x as i32) << 8) as i8;
+    let j = (u as u32 >> 2) as u8;
+    println!("{} {} {}", h, i, j);
+}
+
+fn main() {
+    narrow(1, 2, 3, 4);
+}
@@ -0,0 +1,16 @@
+use std::os::raw::c_int;
+
+fn main() {
+    let x = 5u32;
+    // `bool` widens like a 1-bit unsigned integer
+    let a = (x != 0) as usize;
+    let b = (x == 0) as i64;
+    let c = (x > 3) as u8;
+    // `bool` can't be cast to a float directly
+    let d = (x < 3) as u16 as f32;
+    // Literals fold into suffixed integers
+    let e = 1i32;
+    let f = 0usize;
+    let g = true;
+    println!("{} {} {} {} {} {} {}", a, b, c, d, e, f, g);
+}
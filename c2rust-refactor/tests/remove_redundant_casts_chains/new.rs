@@ -0,0 +1,18 @@
+use std::os::raw::c_void;
+
+fn chains(x: u8, y: u32, p: *mut u64) {
+    // Collapses completely, one link at a time
+    let a = x;
+    let b = p;
+    // Alternating sign flips
+    let c = y;
+    // More links than the cap on simplifications of a single cast, which
+    // still collapse, as each link is simplified before the cast around it
+    let d = y;
+    println!("{} {:?} {} {}", a, b, c, d);
+}
+
+fn main() {
+    let mut z = 3u64;
+    chains(1, 2, &mut z);
+}
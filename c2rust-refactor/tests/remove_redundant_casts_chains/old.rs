@@ -0,0 +1,20 @@
+use std::os::raw::c_void;
+
+fn chains(x: u8, y: u32, p: *mut u64) {
+    // Collapses completely, one link at a time
+    let a = x as u16 as u32 as u64 as u128 as u8;
+    let b = p as *mut u8 as *const u8 as *mut c_void as *const c_void as *mut u64;
+    // Alternating sign flips
+    let c = y as i32 as u32 as i32 as u32 as i32 as u32 as i32 as u32;
+    // More links than the cap on simplifications of a single cast, which
+    // still collapse, as each link is simplified before the cast around it
+    let d = y as i32 as u32 as i32 as u32 as i32 as u32 as i32 as u32 as i32 as u32 as i32 as u32
+        as i32 as u32 as i32 as u32 as i32 as u32 as i32 as u32 as i32 as u32 as i32 as u32
+        as i32 as u32 as i32 as u32 as i32 as u32 as i32 as u32 as i32 as u32 as i32 as u32;
+    println!("{} {:?} {} {}", a, b, c, d);
+}
+
+fn main() {
+    let mut z = 3u64;
+    chains(1, 2, &mut z);
+}
@@ -0,0 +1,8 @@
+fn main() {
+    let a = b'A';
+    let b = 120u32;
+    let c = 65i32;
+    let d = 'x';
+    let e = '€' as u8;
+    println!("{} {} {} {} {}", a, b, c, d, e);
+}
@@ -0,0 +1,8 @@
+fn main() {
+    let a = b'A' as u8;
+    let b = 'x' as u32;
+    let c = b'A' as i32;
+    let d = 'x' as char;
+    let e = '€' as u8;
+    println!("{} {} {} {} {}", a, b, c, d, e);
+}
@@ -0,0 +1,24 @@
+#!/bin/sh
+set -e
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+# A misspelled flag is rejected before anything is rewritten, with an error
+# naming the command and showing its usage.
+rm -f old.new
+if $refactor remove_redundant_casts --fold-char-lit -- old.rs $rustflags 2> args.err; then
+    echo "remove_redundant_casts accepted the misspelled flag --fold-char-lit"
+    exit 1
+fi
+if ! grep -qF 'remove_redundant_casts: unknown option `--fold-char-lit`' args.err \
+        || ! grep -qF 'usage: remove_redundant_casts [--fold-char-lits]' args.err \
+        || [ -e old.new ]; then
+    cat args.err
+    exit 1
+fi
+rm args.err
+
+$refactor remove_redundant_casts --fold-char-lits -- old.rs $rustflags
@@ -0,0 +1,26 @@
+const SOME_CONST: u32 = 7;
+
+struct Unit;
+impl Unit {
+    const SCALE: u32 = 2;
+}
+
+// The value of an associated const of a generic impl can depend on `T`,
+// so casts of it are left alone
+struct Wrap<T>(T);
+impl<T> Wrap<T> {
+    const SCALE: u32 = 3;
+}
+
+fn main() {
+    let a = 5i64;
+    let b = 7u64;
+    let c = 5u8;
+    let d = 8u32;
+    let e = 4096usize;
+    let f = 100000isize;
+    let g = 7u16;
+    let h = 2u64;
+    let i = Wrap::<u8>::SCALE as u64;
+    println!("{} {} {} {} {} {} {} {} {}", a, b, c, d, e, f, g, h, i);
+}
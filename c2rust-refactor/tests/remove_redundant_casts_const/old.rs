@@ -0,0 +1,26 @@
+const SOME_CONST: u32 = 7;
+
+struct Unit;
+impl Unit {
+    const SCALE: u32 = 2;
+}
+
+// The value of an associated const of a generic impl can depend on `T`,
+// so casts of it are left alone
+struct Wrap<T>(T);
+impl<T> Wrap<T> {
+    const SCALE: u32 = 3;
+}
+
+fn main() {
+    let a = (5i32) as i64;
+    let b = SOME_CONST as u64;
+    let c = (2 + 3) as u8;
+    let d = (SOME_CONST + 1) as u32;
+    let e = 4096 as usize;
+    let f = 100000 as isize;
+    let g = SOME_CONST as u16;
+    let h = Unit::SCALE as u64;
+    let i = Wrap::<u8>::SCALE as u64;
+    println!("{} {} {} {} {} {} {} {} {}", a, b, c, d, e, f, g, h, i);
+}
@@ -0,0 +1,31 @@
+#[derive(Clone, Copy)]
+enum Small {
+    A,
+    B = 200,
+}
+
+#[derive(Clone, Copy)]
+enum Negative {
+    A = -1,
+    B = 1,
+}
+
+#[derive(Clone, Copy)]
+#[repr(u16)]
+enum Wide {
+    A = 1000,
+}
+
+fn main() {
+    // No `repr`, discriminants fit into a `u8`
+    let a = Small::A as u64;
+    let b = Small::B as u64;
+    let c = Small::B as u8;
+    // Negative discriminants get sign-extended, so the casts must stay
+    let d = Negative::A as u32 as u64;
+    let e = Negative::B as i64;
+    // Explicit `repr`
+    let f = Wide::A as u64;
+    let g = Wide::A as u8 as u64;
+    println!("{} {} {} {} {} {} {}", a, b, c, d, e, f, g);
+}
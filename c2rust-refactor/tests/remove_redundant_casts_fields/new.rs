@@ -0,0 +1,35 @@
+pub struct Buf {
+    data: *mut u32,
+    first: *const u32,
+    len: usize,
+    value: u32,
+}
+
+pub struct Wrapper<T> {
+    ptr: T,
+}
+
+pub fn make(r: &mut u32, v: &Vec<u32>, b: &Box<u32>) -> Buf {
+    Buf {
+        data: r,
+        first: &v[0],
+        len: v.len(),
+        value: **b,
+    }
+}
+
+pub fn make_const(r: &mut u32) -> Buf {
+    let p = r as *mut u32;
+    Buf {
+        data: p,
+        first: unsafe { &*p },
+        len: 0,
+        value: 0,
+    }
+}
+
+pub fn make_generic(r: &mut u32) -> Wrapper<*mut u32> {
+    Wrapper {
+        ptr: r as *mut u32,
+    }
+}
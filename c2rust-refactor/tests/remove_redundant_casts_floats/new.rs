@@ -0,0 +1,16 @@
+pub fn float_to_int() {
+    let a = 16777216u32;
+    let b = 16777217f32 as u32;
+    let c = 9007199254740992u64;
+    let d = 9007199254740993f64 as u64;
+    let e = 5i32;
+    let f = 5.5 as i32;
+    let g = 300.0f32 as u8;
+}
+
+pub fn int_to_float() {
+    let a = 16777216f32;
+    let b = 16777217 as f32;
+    let c = 16777217f64;
+    let d = 9007199254740993 as f64;
+}
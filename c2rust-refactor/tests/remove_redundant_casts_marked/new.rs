@@ -0,0 +1,16 @@
+mod audited {
+    pub fn f(x: u8) -> u64 {
+        x as u64
+    }
+}
+
+mod untouched {
+    pub fn g(x: u8) -> u64 {
+        x as u32 as u64
+    }
+}
+
+fn main() {
+    let y = 5u32 as u32;
+    println!("{} {} {}", audited::f(1), untouched::g(2), y);
+}
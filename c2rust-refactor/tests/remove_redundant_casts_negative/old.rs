@@ -0,0 +1,15 @@
+fn main() {
+    // Negative values going through an unsigned type get zero-extended,
+    // so the intermediate casts must stay
+    let a = -1i32 as u32 as u64;
+    let b = std::i32::MIN as u32 as u64;
+    let c = -128i8 as u8 as u16;
+    let x = -1i32;
+    let d = x as u32 as i64;
+    let y = -128i8;
+    let e = y as u8 as u16;
+    // `-128i8` is -128, even though `128i8` on its own overflows
+    let f = -128i8 as i16;
+    let g = (-128i8 as i16 + 0) as u32;
+    println!("{} {} {} {} {} {} {}", a, b, c, d, e, f, g);
+}
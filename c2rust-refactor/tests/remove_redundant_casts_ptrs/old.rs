@@ -0,0 +1,20 @@
+use std::os::raw::c_void;
+
+unsafe fn chains(p: *mut u32, q: *const u32) {
+    let mut foo = 0u32;
+    let a = &mut foo as *mut u32 as *const u32;
+    let b = &mut foo as *mut u32 as *const u32 as *const c_void;
+    let c = p as *mut c_void as *const c_void as *const u8;
+    let d = p as *const u32 as *const c_void as *mut c_void as *mut u8;
+    let e = q as *const c_void as *mut c_void;
+    let f = q as *const c_void as *const u8 as *mut u8;
+    println!("{:?} {:?} {:?} {:?} {:?} {:?}", a, b, c, d, e, f);
+}
+
+fn main() {
+    let mut x = 1u32;
+    let y = 2u32;
+    unsafe {
+        chains(&mut x, &y);
+    }
+}
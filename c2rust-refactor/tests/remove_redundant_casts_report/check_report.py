@@ -0,0 +1,22 @@
+#!/usr/bin/env python3
+"""Check that a JSON report has the expected counts and locations in each category."""
+import json
+import sys
+
+
+def main():
+    expected_path, path = sys.argv[1:]
+    with open(expected_path) as f:
+        expected = json.load(f)
+    with open(path) as f:
+        report = json.load(f)
+
+    # Categories are listed in the order they were first hit, which depends on the
+    # order casts get visited in, so only their contents are compared.
+    if report != expected:
+        json.dump(report, sys.stderr, indent=2)
+        sys.exit('report does not match ' + expected_path)
+
+
+if __name__ == '__main__':
+    main()
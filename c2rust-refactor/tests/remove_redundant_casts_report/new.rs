@@ -0,0 +1,12 @@
+fn main() {
+    let x: i32 = -2;
+    let y: u8 = 200;
+
+    let a = x;
+    let b = 5u32;
+    let c = x;
+    let d = y as u32;
+    let e = x as u16;
+    let f = x as u32 as u64;
+    println!("{} {} {} {} {} {}", a, b, c, d, e, f);
+}
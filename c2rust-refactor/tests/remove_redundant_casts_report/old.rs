@@ -0,0 +1,12 @@
+fn main() {
+    let x: i32 = -2;
+    let y: u8 = 200;
+
+    let a = x as i32;
+    let b = 5u8 as u32;
+    let c = x as i64 as i32;
+    let d = y as u16 as u32;
+    let e = x as u32 as u16;
+    let f = x as u32 as u64;
+    println!("{} {} {} {} {} {}", a, b, c, d, e, f);
+}
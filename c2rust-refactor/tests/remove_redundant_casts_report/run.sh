@@ -0,0 +1,15 @@
+#!/bin/sh
+set -e
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    remove_redundant_casts -o report.json \
+    -- old.rs $rustflags
+
+# Every kind of double cast action and a couple of other rewrites each get their
+# own category, with one entry per rewritten or kept cast.
+python3 check_report.py report.expected.json report.json
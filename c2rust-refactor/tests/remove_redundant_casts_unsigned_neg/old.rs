@@ -0,0 +1,13 @@
+const SOME_CONST: i32 = 7;
+
+fn main() {
+    // Unsigned literals can't be negated, so these casts must stay
+    let a = -1i32 as u32;
+    let b = -1isize as usize;
+    let c = -(128i32) as u8;
+    let d = -5i64 as u64;
+    let e = -(SOME_CONST) as u8;
+    // Negative constants fold into negated literals of signed types
+    let f = -(SOME_CONST) as i8;
+    println!("{} {} {} {} {} {}", a, b, c, d, e, f);
+}
@@ -0,0 +1,14 @@
+const SOME_CONST: i32 = 7;
+
+fn main() {
+    let a = 4294967295u32;
+    // The wrapped value depends on the pointer width
+    let b = -1isize as usize;
+    let c = 128u8;
+    let d = 18446744073709551611u64;
+    let e = -1i32;
+    let f = 249u8;
+    let g = -7i8;
+    let h = 65528u16;
+    println!("{} {} {} {} {} {} {} {}", a, b, c, d, e, f, g, h);
+}
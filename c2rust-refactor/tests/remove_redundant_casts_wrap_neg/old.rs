@@ -0,0 +1,14 @@
+const SOME_CONST: i32 = 7;
+
+fn main() {
+    let a = -1i32 as u32;
+    // The wrapped value depends on the pointer width
+    let b = -1isize as usize;
+    let c = -(128i32) as u8;
+    let d = -5i64 as u64;
+    let e = -1i8 as i32;
+    let f = -(SOME_CONST) as u8;
+    let g = -(SOME_CONST) as i8;
+    let h = -(SOME_CONST + 1) as u16;
+    println!("{} {} {} {} {} {} {} {}", a, b, c, d, e, f, g, h);
+}
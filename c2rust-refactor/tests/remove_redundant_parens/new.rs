@@ -0,0 +1,29 @@
+fn f(x: i32) -> i32 {
+    x
+}
+
+fn main() {
+    let a = 1;
+    let b = 2;
+    let c = a + b;
+    let d = (a + b) as u32;
+    let mut e = a * b + c;
+    e = e - 1;
+    let g = f(a) * (b + c);
+    if a < b {
+        e += 1;
+    }
+    let h = a + b;
+    println!("{}", e);
+    {
+        let scoped = 1;
+        e += scoped;
+    }
+    let p = &a;
+    let t = (1, 2);
+    let u = t.0 + 1;
+    let v = (-a).abs();
+    let w = (a as i64) < 5;
+    println!("{} {} {} {} {} {} {}", c, d, e, g, h, p, u);
+    println!("{} {}", v, w);
+}
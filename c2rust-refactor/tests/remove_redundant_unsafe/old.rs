@@ -0,0 +1,39 @@
+static mut COUNTER: i32 = 0;
+
+unsafe fn add(a: i32, b: i32) -> i32 {
+    a + b
+}
+
+unsafe fn twice(x: &mut i32) {
+    *x = add(*x, *x);
+}
+
+unsafe fn read(p: *const i32) -> i32 {
+    *p
+}
+
+unsafe fn bump() -> i32 {
+    COUNTER += 1;
+    COUNTER
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn exported_add(a: i32, b: i32) -> i32 {
+    a + b
+}
+
+fn main() {
+    let mut x = 2;
+    unsafe {
+        twice(&mut x);
+    }
+    let y = unsafe { add(x, 1) };
+    let z = unsafe {
+        let a = add(y, 1);
+        let b = read(&a);
+        a + b
+    };
+    let w = unsafe { bump() };
+    let e = unsafe { exported_add(w, z) };
+    println!("{} {} {} {}", x, y, z, e);
+}
@@ -0,0 +1,57 @@
+static mut COUNT: i32 = 0;
+
+fn add(a: i32, b: i32) -> i32 {
+    let c = a + b;
+    c
+}
+
+fn sign(x: i32) -> i32 {
+    if x < 0 {
+        -1
+    } else if x > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+fn name(x: i32) -> &'static str {
+    match x {
+        0 => "zero",
+        1 => "one",
+        _ => "many",
+    }
+}
+
+fn find(xs: &[i32]) -> i32 {
+    for &x in xs {
+        if x > 0 {
+            return x;
+        }
+    }
+    {
+        let y = -1;
+        y
+    }
+}
+
+fn as_ptr(x: &mut i32) -> *mut i32 {
+    return x;
+}
+
+unsafe fn bump(x: i32) {
+    if x < 0 {
+        COUNT -= 1;
+        return;
+    }
+    COUNT += x;
+}
+
+fn main() {
+    let double = |x: i32| -> i32 { x * 2 };
+    unsafe {
+        bump(add(double(1), sign(-3)));
+    }
+    let _ = name(find(&[1, 2]));
+    let _ = as_ptr(&mut 0);
+}
@@ -0,0 +1,25 @@
+extern "C" {
+    fn abs(x: i32) -> i32;
+}
+
+static USED: i32 = 1;
+
+pub struct Live {
+    pub x: i32,
+}
+
+fn helper_b(x: i32) -> i32 {
+    x + 1
+}
+
+#[no_mangle]
+pub extern "C" fn exported(x: i32) -> i32 {
+    helper_b(x)
+}
+
+fn keep_me() {}
+
+fn main() {
+    let l = Live { x: USED };
+    println!("{}", l.x);
+}
@@ -0,0 +1,54 @@
+extern "C" {
+    fn abs(x: i32) -> i32;
+}
+
+static USED: i32 = 1;
+static UNUSED: i32 = 2;
+
+pub struct Live {
+    pub x: i32,
+}
+
+pub struct Dead {
+    pub live: Live,
+}
+
+impl Dead {
+    pub fn new() -> Dead {
+        Dead { live: Live { x: 0 } }
+    }
+}
+
+pub type DeadAlias = Dead;
+
+fn helper_a() -> i32 {
+    USED
+}
+
+fn unused_caller() -> i32 {
+    helper_a() + UNUSED
+}
+
+fn countdown(n: i32) -> i32 {
+    if n == 0 {
+        0
+    } else {
+        countdown(n - 1)
+    }
+}
+
+fn helper_b(x: i32) -> i32 {
+    x + 1
+}
+
+#[no_mangle]
+pub extern "C" fn exported(x: i32) -> i32 {
+    helper_b(x)
+}
+
+fn keep_me() {}
+
+fn main() {
+    let l = Live { x: USED };
+    println!("{}", l.x);
+}
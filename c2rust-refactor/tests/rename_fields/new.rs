@@ -0,0 +1,49 @@
+use std::ops::Deref;
+
+#[repr(C)]
+pub struct node {
+    pub next: *mut node,
+    pub count: i32,
+}
+
+// Fields with the same names on other types are left alone
+pub struct other {
+    pub cnt: i32,
+    pub size: i32,
+}
+
+pub struct Wrapper(node);
+
+impl Deref for Wrapper {
+    type Target = node;
+    fn deref(&self) -> &node {
+        &self.0
+    }
+}
+
+extern "C" {
+    fn visit(n: *mut node);
+}
+
+fn sum(mut p: *mut node) -> i32 {
+    let mut total = 0;
+    while !p.is_null() {
+        unsafe {
+            total += (*p).count;
+            p = (*p).next;
+        }
+    }
+    total
+}
+
+fn main() {
+    let mut n = node { next: std::ptr::null_mut(), count: 1 };
+    let cnt = 2;
+    let m = node { next: &mut n, count: cnt };
+    let o = other { cnt: 3, size: 4 };
+    let w = Wrapper(node { next: std::ptr::null_mut(), count: 5 });
+    let node { count: c, .. } = m;
+    let node { next: nxt, .. } = n;
+    let off = unsafe { &(*(0 as *const node)).count as *const i32 as usize };
+    println!("{} {} {} {} {:?} {}", sum(&mut n), o.cnt, w.count, c, nxt, off);
+}
@@ -0,0 +1,49 @@
+use std::ops::Deref;
+
+#[repr(C)]
+pub struct node {
+    pub nxt: *mut node,
+    pub cnt: i32,
+}
+
+// Fields with the same names on other types are left alone
+pub struct other {
+    pub cnt: i32,
+    pub size: i32,
+}
+
+pub struct Wrapper(node);
+
+impl Deref for Wrapper {
+    type Target = node;
+    fn deref(&self) -> &node {
+        &self.0
+    }
+}
+
+extern "C" {
+    fn visit(n: *mut node);
+}
+
+fn sum(mut p: *mut node) -> i32 {
+    let mut total = 0;
+    while !p.is_null() {
+        unsafe {
+            total += (*p).cnt;
+            p = (*p).nxt;
+        }
+    }
+    total
+}
+
+fn main() {
+    let mut n = node { nxt: std::ptr::null_mut(), cnt: 1 };
+    let cnt = 2;
+    let m = node { nxt: &mut n, cnt };
+    let o = other { cnt: 3, size: 4 };
+    let w = Wrapper(node { nxt: std::ptr::null_mut(), cnt: 5 });
+    let node { cnt: c, .. } = m;
+    let node { nxt, .. } = n;
+    let off = unsafe { &(*(0 as *const node)).cnt as *const i32 as usize };
+    println!("{} {} {} {} {:?} {}", sum(&mut n), o.cnt, w.cnt, c, nxt, off);
+}
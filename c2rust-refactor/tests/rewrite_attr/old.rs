@@ -0,0 +1,42 @@
+#![allow(dead_code)]
+
+/// Exported under its own name.
+#[no_mangle]
+pub extern "C" fn internal_only() -> i32 {
+    1
+}
+
+#[no_mangle]
+pub extern "C" fn also_internal() -> i32 {
+    2
+}
+
+/// Still part of the public interface.
+#[no_mangle]
+pub extern "C" fn public_api() -> i32 {
+    3
+}
+
+/// Exported under a versioned name.
+#[export_name = "versioned_v1"]
+pub extern "C" fn versioned() -> i32 {
+    4
+}
+
+/// Kept for old callers.
+#[deprecated(since = "0.1", note = "use versioned instead")]
+#[inline]
+fn helper(x: i32) -> i32 {
+    x
+}
+
+fn main() {
+    println!(
+        "{} {} {} {} {}",
+        internal_only(),
+        also_internal(),
+        public_api(),
+        versioned(),
+        helper(5)
+    );
+}
@@ -0,0 +1,17 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(fn && (name("internal_only") || name("also_internal")));' \; \
+    remove_attr no_mangle \; \
+    clear_marks \; \
+    select versioned 'crate; desc(fn && name("versioned"));' \; \
+    add_attr '#[inline(never)]' versioned \; \
+    rewrite_attr '#[export_name = "versioned_v1"]' '#[export_name = "versioned_v2"]' \; \
+    rewrite_attr '#[deprecated(since = $v:Lit, note = $n:Lit)]' \
+        '#[deprecated(since = "0.2", note = $n)]' \; \
+    -- old.rs $rustflags
@@ -0,0 +1,9 @@
+fn main() {
+    let x = 3;
+    let a = x;
+    let b = x;
+    let c = x;
+    let d = 4;
+    let e = 2 * 2;
+    println!("{} {} {} {} {}", a, b, c, d, e);
+}
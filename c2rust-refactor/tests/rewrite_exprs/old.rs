@@ -0,0 +1,9 @@
+fn main() {
+    let x = 3;
+    let a = x * 1;
+    let b = x + 0;
+    let c = (x + 0) * 1;
+    let d = 2 + 2;
+    let e = 4;
+    println!("{} {} {} {} {}", a, b, c, d, e);
+}
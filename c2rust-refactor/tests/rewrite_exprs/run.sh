@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_exprs '$a:Expr * 1' '$a + 0' '$a:Expr + 0' '$a' \; \
+    rewrite_exprs --no-chain '2 + 2' '4' '4' '2 * 2' \; \
+    -- old.rs $rustflags
@@ -0,0 +1,26 @@
+const NONE: i32 = 0;
+
+fn describe(kind: i32) -> &'static str {
+    match kind {
+        NONE => "none",
+        1 => "one",
+        _ => "many",
+    }
+}
+
+fn bump((a, b): (i32, i32)) -> i32 {
+    a + b
+}
+
+fn main() {
+    let x = Some(0);
+    let total = match x {
+        Some(NONE) => 0,
+        Some(n) => n + 1,
+        None => bump((1, 2)),
+    };
+    if let Some((NONE, y)) = Some((0, 1)) {
+        println!("{}", y);
+    }
+    println!("{} {}", describe(total), total);
+}
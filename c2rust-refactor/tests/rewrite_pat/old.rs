@@ -0,0 +1,26 @@
+const NONE: i32 = 0;
+
+fn describe(kind: i32) -> &'static str {
+    match kind {
+        0 => "none",
+        1 => "one",
+        _ => "many",
+    }
+}
+
+fn bump((ref a, b): (i32, i32)) -> i32 {
+    a + b
+}
+
+fn main() {
+    let x = Some(0);
+    let total = match x {
+        Option::Some(0) => 0,
+        Option::Some(ref n) => n + 1,
+        None => bump((1, 2)),
+    };
+    if let Option::Some((0, y)) = Some((0, 1)) {
+        println!("{}", y);
+    }
+    println!("{} {}", describe(total), total);
+}
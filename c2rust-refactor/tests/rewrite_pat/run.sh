@@ -0,0 +1,12 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_pat '0' 'NONE' \; \
+    rewrite_pat 'ref $x:Ident' '$x' \; \
+    rewrite_pat 'Option::Some($p:Pat)' 'Some($p)' \; \
+    -- old.rs $rustflags
@@ -0,0 +1,12 @@
+fn main() {
+    let x = 5;
+    let mut v = Vec::with_capacity(2);
+    v.extend_from_slice(&[1, 2]);
+    let mut w = Vec::with_capacity(2);
+    w.extend_from_slice(&[x, x + 1]);
+    // Pushes to a different vector don't match
+    let mut u = Vec::new();
+    u.push(3);
+    v.push(4);
+    println!("{:?} {:?} {:?}", v, w, u);
+}
@@ -0,0 +1,14 @@
+fn main() {
+    let x = 5;
+    let mut v = Vec::new();
+    v.push(1);
+    v.push(2);
+    let mut w = Vec::new();
+    w.push(x);
+    w.push(x + 1);
+    // Pushes to a different vector don't match
+    let mut u = Vec::new();
+    u.push(3);
+    v.push(4);
+    println!("{:?} {:?} {:?}", v, w, u);
+}
@@ -0,0 +1,12 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_stmts \
+        'let mut $v:Ident = Vec::new(); $v.push($a:Expr); $v.push($b:Expr);' \
+        'let mut $v = Vec::with_capacity(2); $v.extend_from_slice(&[$a, $b]);' \; \
+    -- old.rs $rustflags
@@ -0,0 +1,27 @@
+#![feature(libc)]
+extern crate libc;
+
+unsafe fn search(a: libc::c_int, b: libc::c_int, p: *const libc::c_int) -> libc::c_int {
+    let mut found: bool = false;
+    let mut steps: libc::c_int = 0;
+    if a < b && b < 10 || !p.is_null() {
+        found = true;
+    }
+    while found && !(steps > 3) {
+        steps += 1;
+        found = steps < a || (b == 0 || *p == 0);
+    }
+    if !found {
+        return -1;
+    }
+    steps
+}
+
+fn count(a: libc::c_int, b: libc::c_int) -> libc::c_int {
+    // `flag` is marked, but it's also used as a number.
+    let mut flag: libc::c_int = (a == b) as libc::c_int;
+    if flag != 0 && (a != 0 || b > 0) {
+        flag += 1;
+    }
+    flag
+}
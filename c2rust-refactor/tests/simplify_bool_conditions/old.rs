@@ -0,0 +1,29 @@
+#![feature(libc)]
+extern crate libc;
+
+unsafe fn search(a: libc::c_int, b: libc::c_int, p: *const libc::c_int) -> libc::c_int {
+    let mut found: libc::c_int = 0;
+    let mut steps: libc::c_int = 0;
+    if ((a < b) as libc::c_int != 0 && (b < 10) as libc::c_int != 0
+        || !p.is_null() as libc::c_int != 0) as libc::c_int != 0
+    {
+        found = 1 as libc::c_int;
+    }
+    while 0 != found && (steps > 3) as libc::c_int == 0 {
+        steps += 1;
+        found = (steps < a || !!(b == 0 || 0 == *p)) as libc::c_int;
+    }
+    if found == 0 {
+        return -1;
+    }
+    steps
+}
+
+fn count(a: libc::c_int, b: libc::c_int) -> libc::c_int {
+    // `flag` is marked, but it's also used as a number.
+    let mut flag: libc::c_int = (a == b) as libc::c_int;
+    if flag != 0 && (a != 0 || (b > 0) as libc::c_int != 0) {
+        flag += 1;
+    }
+    flag
+}
@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select bool 'crate; desc(pat && (name("found") || name("flag")));' \; \
+    simplify_bool_conditions \
+    -- old.rs $rustflags
@@ -0,0 +1,43 @@
+fn compute(n: i32) -> i32 {
+    n * 2
+}
+
+fn folded(n: i32) -> i32 {
+    println!("start");
+    let a: i32 = compute(n);
+    let mut b: i32 = a + 1;
+    b += 1;
+    let total: i32 = a + b;
+    total
+}
+
+fn branches(n: i32) -> i32 {
+    let mut y: i32 = 0;
+    println!("branches");
+    let x: i32;
+    if n > 0 {
+        x = 1;
+    } else if n < 0 {
+        x = -1;
+    } else {
+        x = 0;
+    }
+    if n > 10 {
+        y = 2;
+    }
+    x + y
+}
+
+fn nested(n: i32) -> i32 {
+    println!("nested");
+    let z: i32;
+    {
+        println!("inner");
+        z = n + 1;
+    }
+    z * 2
+}
+
+fn main() {
+    println!("{} {} {}", folded(3), branches(-4), nested(5));
+}
@@ -0,0 +1,46 @@
+fn compute(n: i32) -> i32 {
+    n * 2
+}
+
+fn folded(n: i32) -> i32 {
+    let mut a: i32 = 0;
+    let mut b: i32 = 0;
+    let mut total: i32 = 0;
+    println!("start");
+    a = compute(n);
+    b = a + 1;
+    b += 1;
+    total = a + b;
+    total
+}
+
+fn branches(n: i32) -> i32 {
+    let mut x: i32 = 0;
+    let mut y: i32 = 0;
+    println!("branches");
+    if n > 0 {
+        x = 1;
+    } else if n < 0 {
+        x = -1;
+    } else {
+        x = 0;
+    }
+    if n > 10 {
+        y = 2;
+    }
+    x + y
+}
+
+fn nested(n: i32) -> i32 {
+    let mut z: i32 = 0;
+    println!("nested");
+    {
+        println!("inner");
+        z = n + 1;
+    }
+    z * 2
+}
+
+fn main() {
+    println!("{} {} {}", folded(3), branches(-4), nested(5));
+}
@@ -0,0 +1,84 @@
+#![feature(register_tool)]
+#![register_tool(c2rust)]
+
+pub mod big {
+    use std::os::raw::c_int;
+
+    pub mod list_h {
+        use super::*;
+
+        #[c2rust::src_loc = "src/list.h:1:1"]
+        pub enum list_kind {
+            Stack,
+            Queue,
+        }
+
+        #[c2rust::src_loc = "src/list.h:3:1"]
+        pub struct list {
+            pub head: *mut crate::big::node_h::node,
+            pub(super) len: c_int,
+            pub kind: crate::big::list_h::list_kind,
+        }
+
+        #[c2rust::src_loc = "src/list.h:8:1"]
+        pub(super) unsafe fn list_push(
+            l: *mut crate::big::list_h::list,
+            n: *mut crate::big::node_h::node,
+        ) {
+            (*n).next = (*l).head;
+            (*n).owner = l;
+            (*l).head = n;
+            (*l).len += 1;
+        }
+    }
+
+    pub mod node_h {
+        use super::*;
+
+        #[c2rust::src_loc = "src/node.h:2:1"]
+        pub struct node {
+            pub(super) next: *mut crate::big::node_h::node,
+            pub owner: *mut crate::big::list_h::list,
+            pub(super) value: c_int,
+        }
+
+        #[c2rust::src_loc = "src/node.h:6:1"]
+        static mut NODE_COUNT: c_int = 0;
+
+        #[c2rust::src_loc = "src/node.h:8:1"]
+        pub(super) unsafe fn node_list_len(n: *const crate::big::node_h::node) -> c_int {
+            crate::big::node_h::NODE_COUNT += 1;
+            (*(*n).owner).len
+        }
+    }
+
+    mod util_c {
+        use super::*;
+
+        pub(super) fn helper(x: c_int) -> c_int {
+            x + 1
+        }
+    }
+
+    pub unsafe fn demo() -> c_int {
+        let mut l = crate::big::list_h::list {
+            head: 0 as *mut crate::big::node_h::node,
+            len: 0,
+            kind: crate::big::list_h::list_kind::Stack,
+        };
+        let mut n = crate::big::node_h::node {
+            next: 0 as *mut crate::big::node_h::node,
+            owner: 0 as *mut crate::big::list_h::list,
+            value: crate::big::util_c::helper(1),
+        };
+        crate::big::list_h::list_push(&mut l, &mut n);
+        crate::big::node_h::node_list_len(&n)
+    }
+}
+
+fn main() {
+    unsafe {
+        big::demo();
+    }
+    let _ = std::mem::size_of::<crate::big::node_h::node>();
+}
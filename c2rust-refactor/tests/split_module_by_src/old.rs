@@ -0,0 +1,69 @@
+#![feature(register_tool)]
+#![register_tool(c2rust)]
+
+pub mod big {
+    use std::os::raw::c_int;
+
+    #[c2rust::src_loc = "src/list.h:1:1"]
+    pub enum list_kind {
+        Stack,
+        Queue,
+    }
+
+    #[c2rust::src_loc = "src/list.h:3:1"]
+    pub struct list {
+        pub head: *mut node,
+        len: c_int,
+        pub kind: list_kind,
+    }
+
+    #[c2rust::src_loc = "src/node.h:2:1"]
+    pub struct node {
+        next: *mut node,
+        pub owner: *mut list,
+        value: c_int,
+    }
+
+    #[c2rust::src_loc = "src/list.h:8:1"]
+    unsafe fn list_push(l: *mut list, n: *mut node) {
+        (*n).next = (*l).head;
+        (*n).owner = l;
+        (*l).head = n;
+        (*l).len += 1;
+    }
+
+    #[c2rust::src_loc = "src/node.h:6:1"]
+    static mut NODE_COUNT: c_int = 0;
+
+    #[c2rust::src_loc = "src/node.h:8:1"]
+    unsafe fn node_list_len(n: *const node) -> c_int {
+        NODE_COUNT += 1;
+        (*(*n).owner).len
+    }
+
+    fn helper(x: c_int) -> c_int {
+        x + 1
+    }
+
+    pub unsafe fn demo() -> c_int {
+        let mut l = list {
+            head: 0 as *mut node,
+            len: 0,
+            kind: list_kind::Stack,
+        };
+        let mut n = node {
+            next: 0 as *mut node,
+            owner: 0 as *mut list,
+            value: helper(1),
+        };
+        list_push(&mut l, &mut n);
+        node_list_len(&n)
+    }
+}
+
+fn main() {
+    unsafe {
+        big::demo();
+    }
+    let _ = std::mem::size_of::<big::node>();
+}
@@ -0,0 +1,16 @@
+fn release(p: *mut i32) {
+    unsafe { drop(Box::from_raw(p)) }
+}
+
+fn audit(p: *mut i32) -> *mut i32 {
+    p
+}
+
+fn main() {
+    let a = Box::into_raw(Box::new(1));
+    let b = Box::into_raw(Box::new(2));
+    println!("{:?} {:?}", a, b);
+    release(a);
+    let c = b;
+    release(c);
+}
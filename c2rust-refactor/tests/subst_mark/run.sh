@@ -0,0 +1,13 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+# The first command only marks the arguments of `release`, and the second one
+# rewrites the marked expressions.
+$refactor \
+    rewrite_expr 'release($p:Expr)' 'release(mark!($p, freed))' \; \
+    rewrite_expr 'marked!($e:Expr, freed)' 'audit($e)' \; \
+    -- old.rs $rustflags
@@ -0,0 +1,29 @@
+use std::mem::size_of;
+
+type Word = u32;
+type Handle = *mut Widget;
+
+struct Widget {
+    a: Word,
+    b: Word,
+}
+
+fn clear(_p: *mut u8, _n: usize) {}
+
+fn make<T>(x: T) -> T {
+    x
+}
+
+fn main() {
+    let mut w = Widget { a: 1, b: 2 };
+    let p: *mut Widget = &mut w;
+    let h: Handle = p;
+    clear(p as *mut u8, size_of::<Widget>());
+    clear(h as *mut u8, size_of::<crate::Widget>());
+
+    let n: Word = 7;
+    let f = || 1;
+    let m = make::<Word>(n);
+    let g = make(f);
+    println!("{} {} {} {}", m, g(), w.a, w.b);
+}
@@ -0,0 +1,12 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr 'clear($p:Expr as *mut u8, 8)' \
+        'clear($p as *mut u8, size_of::<pointee_of!($p)>())' \; \
+    rewrite_expr 'make($e:Expr)' 'make::<ty_of!($e)>($e)' \; \
+    -- old.rs $rustflags
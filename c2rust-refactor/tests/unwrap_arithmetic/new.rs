@@ -0,0 +1,29 @@
+pub fn scale(x: u8, n: u16) -> u32 {
+    (x as u32) * n as u32 + 1
+}
+
+pub fn low_byte_sum(a: u32, b: u32) -> u32 {
+    (a & 0xff) + (b & 0xff)
+}
+
+pub fn next_index(i: u32, len: u32) -> u32 {
+    i.wrapping_add(1) % len
+}
+
+pub fn diff(a: i32, b: i32) -> i32 {
+    a - b
+}
+
+pub fn hash_str(s: &[u8]) -> u32 {
+    let mut h: u32 = 5381;
+    for &c in s {
+        h = (h << 5).wrapping_add(h).wrapping_add(c as u32);
+    }
+    h
+}
+
+pub fn update_crc(crc: u32, c: u8) -> u32 {
+    (crc >> 8).wrapping_add(c as u32)
+}
+
+fn main() {}
@@ -0,0 +1,41 @@
+#![feature(libc)]
+extern crate libc;
+
+mod sys {
+    extern "C" {
+        pub fn open(path: *const libc::c_char, oflag: libc::c_int, ...) -> libc::c_int;
+        pub fn close(fd: libc::c_int) -> libc::c_int;
+    }
+
+    pub unsafe fn open3(
+        path: *const libc::c_char,
+        oflag: libc::c_int,
+        vararg0: u32,
+    ) -> libc::c_int {
+        open(path, oflag, vararg0)
+    }
+
+    pub unsafe fn open2(path: *const libc::c_char, oflag: libc::c_int) -> libc::c_int {
+        open(path, oflag)
+    }
+}
+
+unsafe fn open_file(path: *const libc::c_char, create: bool) -> libc::c_int {
+    if create {
+        crate::sys::open3(path, 0o100 | 0o1, 0o644 as libc::c_uint)
+    } else {
+        crate::sys::open2(path, 0)
+    }
+}
+
+unsafe fn open_mode(path: *const libc::c_char, mode: libc::c_int) -> libc::c_int {
+    crate::sys::open3(path, 0o100, mode as u32)
+}
+
+fn main() {
+    unsafe {
+        let path = b"/tmp/wrap_variadic_calls\0".as_ptr() as *const libc::c_char;
+        sys::close(open_file(path, true));
+        sys::close(open_mode(path, 0o600));
+    }
+}
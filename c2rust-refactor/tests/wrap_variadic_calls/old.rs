@@ -0,0 +1,29 @@
+#![feature(libc)]
+extern crate libc;
+
+mod sys {
+    extern "C" {
+        pub fn open(path: *const libc::c_char, oflag: libc::c_int, ...) -> libc::c_int;
+        pub fn close(fd: libc::c_int) -> libc::c_int;
+    }
+}
+
+unsafe fn open_file(path: *const libc::c_char, create: bool) -> libc::c_int {
+    if create {
+        sys::open(path, 0o100 | 0o1, 0o644 as libc::c_uint)
+    } else {
+        sys::open(path, 0)
+    }
+}
+
+unsafe fn open_mode(path: *const libc::c_char, mode: libc::c_int) -> libc::c_int {
+    sys::open(path, 0o100, mode)
+}
+
+fn main() {
+    unsafe {
+        let path = b"/tmp/wrap_variadic_calls\0".as_ptr() as *const libc::c_char;
+        sys::close(open_file(path, true));
+        sys::close(open_mode(path, 0o600));
+    }
+}
@@ -0,0 +1,52 @@
+use std::ptr;
+
+#[repr(C)]
+pub struct uart_regs {
+    pub dr: u32,
+    pub sr: u32,
+    pub cr: u32,
+}
+
+static mut UART: uart_regs = uart_regs { dr: 0, sr: 0, cr: 0 };
+
+static mut LAST_STATUS: u32 = 0;
+
+pub unsafe fn uart_ready() -> bool {
+    Reg(&mut UART.sr).read() & 0x1 != 0
+}
+
+pub unsafe fn uart_enable() {
+    let cr = Reg(&mut UART.cr).read();
+    Reg(&mut UART.cr).write(cr | 0x4);
+}
+
+pub unsafe fn uart_disable() {
+    Reg(&mut UART.cr).write(Reg(&mut UART.cr).read() & !0x4);
+}
+
+pub unsafe fn uart_put(c: u8) {
+    while !uart_ready() {}
+    Reg(&mut UART.dr).write(c as u32);
+    LAST_STATUS = Reg(&mut UART.sr).read();
+}
+
+pub unsafe fn uart_status_ptr() -> *const u32 {
+    &UART.sr
+}
+
+fn main() {}
+pub struct Reg<T>(pub *mut T);
+impl<T> Clone for Reg<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for Reg<T> {}
+impl<T> Reg<T> {
+    pub unsafe fn read(self) -> T {
+        ::std::ptr::read_volatile(self.0)
+    }
+    pub unsafe fn write(self, val: T) {
+        ::std::ptr::write_volatile(self.0, val)
+    }
+}
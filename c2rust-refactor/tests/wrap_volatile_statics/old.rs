@@ -0,0 +1,37 @@
+use std::ptr;
+
+#[repr(C)]
+pub struct uart_regs {
+    pub dr: u32,
+    pub sr: u32,
+    pub cr: u32,
+}
+
+static mut UART: uart_regs = uart_regs { dr: 0, sr: 0, cr: 0 };
+
+static mut LAST_STATUS: u32 = 0;
+
+pub unsafe fn uart_ready() -> bool {
+    ptr::read_volatile(&UART.sr as *const u32) & 0x1 != 0
+}
+
+pub unsafe fn uart_enable() {
+    let cr = ptr::read_volatile(&UART.cr as *const u32);
+    ptr::write_volatile(&mut UART.cr as *mut u32, cr | 0x4);
+}
+
+pub unsafe fn uart_disable() {
+    UART.cr &= !0x4;
+}
+
+pub unsafe fn uart_put(c: u8) {
+    while !uart_ready() {}
+    UART.dr = c as u32;
+    LAST_STATUS = UART.sr;
+}
+
+pub unsafe fn uart_status_ptr() -> *const u32 {
+    &UART.sr
+}
+
+fn main() {}
@@ -9,14 +9,25 @@ extern crate shlex;
 use clap::{App, ArgMatches};
 use std::fs::File;
 use std::io::Read;
+use std::path::PathBuf;
 use std::process;
 use std::str::FromStr;
 
-use c2rust_refactor::{file_io, CargoTarget, Command, Cursor, Mark, Options, RustcArgSource};
+use c2rust_refactor::{
+    command, file_io, CargoTarget, Command, Cursor, Mark, Options, RustcArgSource,
+};
 
 fn main() {
     let yaml = load_yaml!("../refactor.yaml");
-    let args = App::from_yaml(yaml).get_matches();
+    let mut app = App::from_yaml(yaml);
+    let args = app.clone().get_matches();
+
+    // `--help` is our own option, so it can take a command name.
+    if args.is_present("help") && args.value_of("help").is_none() {
+        app.print_help().unwrap();
+        println!();
+        return;
+    }
 
     let opts = match parse_opts(&args) {
         Some(x) => x,
@@ -47,6 +58,32 @@ fn parse_opts(args: &ArgMatches) -> Option<Options> {
         None => vec![file_io::OutputMode::Print],
     };
 
+    // A dry run only prints the diff, whatever the other modes would have written.
+    let dry_run = args.is_present("dry-run");
+    if dry_run && args.occurrences_of("rewrite-mode") > 0 {
+        panic!("--dry-run can't be combined with --rewrite-mode");
+    }
+    let rewrite_modes = if dry_run {
+        vec![file_io::OutputMode::PrintDiff]
+    } else {
+        rewrite_modes
+    };
+    let diff_out = args.value_of("diff-out").map(PathBuf::from);
+    let json_out = args.value_of("json-out").map(PathBuf::from);
+    let pipelines = args.value_of("pipelines").map(PathBuf::from);
+    let list_pipelines = args.is_present("list-pipelines");
+    let help_command = args.value_of("help").map(String::from);
+    let dump_command_specs = args.is_present("dump-command-specs");
+    let undo_limit = match args.value_of("undo-limit").map(usize::from_str) {
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            info!("Bad undo limit: {:?}", args.value_of("undo-limit").unwrap());
+            return None;
+        }
+        None => command::DEFAULT_UNDO_LIMIT,
+    };
+    let info_only = list_pipelines || help_command.is_some() || dump_command_specs;
+
     // Parse cursors
     let cursor_strs = args.values_of_lossy("cursor").unwrap_or(vec![]);
     let mut cursors = Vec::with_capacity(cursor_strs.len());
@@ -135,6 +172,7 @@ fn parse_opts(args: &ArgMatches) -> Option<Options> {
     // Handle --cargo and rustc-args
     let rustc_args = match args.values_of_lossy("rustc-args") {
         Some(args) => RustcArgSource::CmdLine(args),
+        None if info_only => RustcArgSource::CmdLine(Vec::new()),
         None => {
             assert!(args.is_present("cargo"));
             let target = if let Some(bin) = args.value_of("bin") {
@@ -166,7 +204,7 @@ fn parse_opts(args: &ArgMatches) -> Option<Options> {
     };
     let transforms: Box<dyn Iterator<Item = String>> = match args.value_of("transforms-file") {
         Some(_) => Box::new(shlex::Shlex::new(&transforms_file)),
-        None => Box::new(args.values_of("transforms").unwrap().map(String::from)),
+        None => Box::new(args.values_of("transforms").into_iter().flatten().map(String::from)),
     };
     let mut commands = Vec::new();
     let mut cur_command = None;
@@ -193,6 +231,14 @@ fn parse_opts(args: &ArgMatches) -> Option<Options> {
 
     Some(Options {
         rewrite_modes,
+        dry_run,
+        diff_out,
+        json_out,
+        pipelines,
+        list_pipelines,
+        help_command,
+        dump_command_specs,
+        undo_limit,
         commands,
         rustc_args,
         cursors,